@@ -0,0 +1,296 @@
+//! File types - what kind of file a buffer holds, worked out from its name
+//! or a `#!` first line, and the buffer options those files get. Each file
+//! type starts with its own defaults (Makefiles indent with tabs, Python
+//! with four spaces), then the `autocmd FileType` lines of .virusrc, then a
+//! modeline in the file itself, each overriding the one before:
+//!
+//! ```text
+//! autocmd FileType python set expandtab shiftwidth=4
+//! au FileType make,go setlocal noexpandtab
+//! # vim: set sw=2 et:
+//! ```
+
+use crate::document_model::Document;
+use std::path::Path;
+
+/// The 'filetype' of a file, from its name
+pub fn detect(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    match name {
+        "Makefile" | "makefile" | "GNUmakefile" => return Some("make"),
+        "Dockerfile" => return Some("dockerfile"),
+        _ => {}
+    }
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyw" => "python",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "go" => "go",
+        "java" => "java",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "rb" => "ruby",
+        "sh" | "bash" => "sh",
+        "lua" => "lua",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" | "markdown" => "markdown",
+        "txt" => "text",
+        "mk" => "make",
+        _ => return None,
+    })
+}
+
+/// The 'filetype' of a buffer: from its file name, or else from a `#!`
+/// line naming the interpreter
+pub fn detect_buffer(doc: &Document) -> Option<&'static str> {
+    doc.filename
+        .as_deref()
+        .and_then(detect)
+        .or_else(|| doc.get_line(0).as_deref().and_then(detect_shebang))
+}
+
+/// The 'filetype' from a `#!/usr/bin/env python3` style first line
+fn detect_shebang(line: &str) -> Option<&'static str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    // python3.12 is python
+    Some(match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" => "python",
+        "sh" | "bash" | "dash" | "ksh" | "zsh" => "sh",
+        "ruby" => "ruby",
+        "node" | "nodejs" => "javascript",
+        "lua" => "lua",
+        "make" => "make",
+        _ => return None,
+    })
+}
+
+/// Buffer options each file type starts with, before .virusrc and modelines
+fn defaults(file_type: &str) -> &'static [&'static str] {
+    match file_type {
+        "make" | "go" => &["noexpandtab"],
+        "python" | "rust" | "c" | "cpp" | "java" => &["expandtab", "shiftwidth=4"],
+        "javascript" | "typescript" | "ruby" | "lua" | "html" | "css" | "json" | "yaml" | "toml" | "sh" => {
+            &["expandtab", "shiftwidth=2"]
+        }
+        _ => &[],
+    }
+}
+
+/// Lines at each end of a file looked at for a modeline, as vim's 'modelines'
+const MODELINE_LINES: usize = 5;
+
+/// The options of a modeline in `line`, in either of vim's forms:
+/// `vim: set sw=4 et:` (text may follow the closing colon) or
+/// `vi: sw=4 et` (options separated by spaces or colons)
+fn modeline_options(line: &str) -> Option<Vec<&str>> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|&(at, _)| line[..at].ends_with(char::is_whitespace) || at == 0 && *marker != "ex:")
+            .map(|(at, _)| at + marker.len())
+    })?;
+    let rest = line[start..].trim_start();
+    let options = match rest.strip_prefix("set ").or_else(|| rest.strip_prefix("se ")) {
+        Some(set) => set.split(':').next()?.split_whitespace().collect(),
+        None => rest.split([' ', '\t', ':']).filter(|option| !option.is_empty()).collect(),
+    };
+    Some(options)
+}
+
+/// Set the buffer options of a modeline in the first or last lines of
+/// `doc`. Options that aren't local to a buffer are skipped.
+pub fn apply_modeline(doc: &mut Document) {
+    let count = doc.line_count();
+    let ends = (0..count.min(MODELINE_LINES)).chain(count.saturating_sub(MODELINE_LINES).max(MODELINE_LINES)..count);
+    let Some(options) = ends.filter_map(|index| doc.get_line(index)).find_map(|line| {
+        modeline_options(&line).map(|options| options.into_iter().map(str::to_string).collect::<Vec<_>>())
+    }) else {
+        return;
+    };
+    for option in options {
+        // As in vim, a file can't choose the commands run on it
+        if !option.starts_with("formatprg=") && !option.starts_with("fp=") {
+            apply_local_setting(doc, &option);
+        }
+    }
+}
+
+/// An `autocmd FileType` line: options set on buffers of some file types
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTypeRule {
+    pub file_types: Vec<String>,
+    pub settings: Vec<String>, // `expandtab`, `sw=4`, ...
+}
+
+impl FileTypeRule {
+    /// Read `autocmd FileType python,ruby set[local] et sw=2`. A value
+    /// with spaces in it escapes them: `fp=black\ -q\ -`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        if !matches!(words.next(), Some("autocmd" | "au")) || words.next() != Some("FileType") {
+            return None;
+        }
+        let file_types = words.next()?.split(',').map(str::to_string).collect();
+        if !matches!(words.next(), Some("set" | "setlocal" | "setl")) {
+            return None;
+        }
+        let settings = split_settings(words);
+        (!settings.is_empty()).then_some(Self { file_types, settings })
+    }
+
+    pub fn matches(&self, file_type: &str) -> bool {
+        self.file_types.iter().any(|name| name == file_type || name == "*")
+    }
+
+    /// Set `doc`'s options: its file type's defaults, then the rules that
+    /// match the file type, then its modeline. Options that aren't local
+    /// to a buffer are ignored.
+    pub fn apply_all(rules: &[FileTypeRule], doc: &mut Document) {
+        if let Some(file_type) = detect_buffer(doc) {
+            for setting in defaults(file_type) {
+                apply_local_setting(doc, setting);
+            }
+            for rule in rules.iter().filter(|rule| rule.matches(file_type)) {
+                for setting in &rule.settings {
+                    apply_local_setting(doc, setting);
+                }
+            }
+        }
+        apply_modeline(doc);
+    }
+}
+
+/// The options of a `set` line, one per word. A value with spaces in it
+/// escapes them: `fp=black\ -q\ -`.
+pub fn split_settings<'a>(words: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut settings: Vec<String> = Vec::new();
+    let mut escaped = false;
+    for word in words {
+        match settings.last_mut() {
+            Some(setting) if escaped => {
+                setting.pop();
+                setting.push(' ');
+                setting.push_str(word);
+            }
+            _ => settings.push(word.to_string()),
+        }
+        escaped = word.ends_with('\\');
+    }
+    settings
+}
+
+/// Set one buffer option (`expandtab`, `sw=4`, ...). False if `setting`
+/// isn't a buffer option or its value is invalid.
+pub fn apply_local_setting(doc: &mut Document, setting: &str) -> bool {
+    let number = |value: &str| value.parse::<usize>().ok();
+    match setting.split_once('=') {
+        None => match setting {
+            "expandtab" | "et" => doc.set_expand_tab(true),
+            "noexpandtab" | "noet" => doc.set_expand_tab(false),
+            "shiftround" | "sr" => doc.shift_round = true,
+            "noshiftround" | "nosr" => doc.shift_round = false,
+            "autoindent" | "ai" => doc.auto_indent = true,
+            "noautoindent" | "noai" => doc.auto_indent = false,
+            "autopairs" => doc.auto_pairs = true,
+            "noautopairs" => doc.auto_pairs = false,
+            "pastewrap" => doc.wrap_paste = true,
+            "nopastewrap" => doc.wrap_paste = false,
+            "trimtrailing" => doc.trim_trailing = true,
+            "notrimtrailing" => doc.trim_trailing = false,
+            "formatonsave" => doc.format_on_save = true,
+            "noformatonsave" => doc.format_on_save = false,
+            _ => return false,
+        },
+        Some((name, value)) => match (name, number(value)) {
+            ("shiftwidth" | "sw", Some(width)) => doc.shift_width = width,
+            ("textwidth" | "tw", Some(width)) => doc.text_width = width,
+            ("formatprg" | "fp", _) => doc.format_program = (!value.is_empty()).then(|| value.to_string()),
+            _ => return false,
+        },
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(Path::new("src/main.rs")), Some("rust"));
+        assert_eq!(detect(Path::new("setup.PY")), Some("python"));
+        assert_eq!(detect(Path::new("dir/Makefile")), Some("make"));
+        assert_eq!(detect(Path::new("notes")), None);
+
+        let script = |text: &str| detect_buffer(&Document::from_string(text.to_string()));
+        assert_eq!(script("#!/usr/bin/env python3\nprint()"), Some("python"));
+        assert_eq!(script("#!/usr/bin/env -S python3.12 -u"), Some("python"));
+        assert_eq!(script("#!/bin/bash -e"), Some("sh"));
+        assert_eq!(script("#!/usr/bin/perl"), None);
+        assert_eq!(script("print()"), None);
+    }
+
+    #[test]
+    fn test_defaults_then_rules_then_modeline() {
+        let open = |name: &str, text: &str, rules: &[FileTypeRule]| {
+            let mut doc = Document::from_string(text.to_string());
+            doc.filename = Some(PathBuf::from(name));
+            FileTypeRule::apply_all(rules, &mut doc);
+            (doc.expand_tab, doc.shift_width)
+        };
+        assert_eq!(open("Makefile", "all:", &[]), (false, 0));
+        assert_eq!(open("main.py", "", &[]), (true, 4));
+        assert_eq!(open("app.js", "", &[]), (true, 2));
+        assert_eq!(open("notes.txt", "", &[]), (true, 0));
+
+        let rule = FileTypeRule::parse("autocmd FileType python set sw=8").unwrap();
+        assert_eq!(open("main.py", "", std::slice::from_ref(&rule)), (true, 8));
+        assert_eq!(open("main.py", "# vim: set sw=3 noet:", &[rule]), (false, 3));
+        let long = format!("# vi: sw=6\n{}x", "\n".repeat(20));
+        assert_eq!(open("notes.txt", &long, &[]), (true, 6));
+        let long = format!("{}# vim:noet:sw=5", "\n".repeat(20));
+        assert_eq!(open("notes.txt", &long, &[]), (false, 5));
+        let middle = format!("{}# vim: set sw=7:{}", "\n".repeat(10), "\n".repeat(10));
+        assert_eq!(open("notes.txt", &middle, &[]), (true, 0));
+        assert_eq!(open("notes.txt", "a davim: set sw=7:", &[]), (true, 0));
+    }
+
+    #[test]
+    fn test_rules_set_buffer_options() {
+        let rule = FileTypeRule::parse("autocmd FileType python,ruby set expandtab sw=4 tw=79 nu").unwrap();
+        assert_eq!(rule.file_types, ["python", "ruby"]);
+        assert_eq!(FileTypeRule::parse("au FileType make setlocal noet").unwrap().settings, ["noet"]);
+        assert_eq!(FileTypeRule::parse("autocmd BufRead *.py set et"), None);
+        assert_eq!(FileTypeRule::parse("autocmd FileType python"), None);
+
+        let mut doc = Document::new();
+        doc.expand_tab = false;
+        doc.filename = Some(PathBuf::from("script.py"));
+        FileTypeRule::apply_all(std::slice::from_ref(&rule), &mut doc);
+        assert!(doc.expand_tab);
+        assert_eq!((doc.shift_width, doc.text_width), (4, 79));
+
+        let mut other = Document::new();
+        other.filename = Some(PathBuf::from("README.md"));
+        FileTypeRule::apply_all(&[rule], &mut other);
+        assert_eq!(other.shift_width, 0);
+
+        // Spaces in a value are escaped; a modeline can't set a command
+        let rule = FileTypeRule::parse(r"au FileType python setl fp=black\ -q\ - formatonsave").unwrap();
+        assert_eq!(rule.settings, ["fp=black -q -", "formatonsave"]);
+        let mut doc = Document::from_string("# vim: set fp=rm\\ x:".to_string());
+        doc.filename = Some(PathBuf::from("tool.py"));
+        FileTypeRule::apply_all(&[rule], &mut doc);
+        assert_eq!((doc.format_program.as_deref(), doc.format_on_save), (Some("black -q -"), true));
+    }
+}
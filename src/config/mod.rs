@@ -1,9 +1,10 @@
-/// Configuration subsystem - Editor settings and preferences
-/// 
-/// This module handles loading and applying configuration from .virusrc files,
-/// providing centralized settings management for the entire application.
+//! Configuration subsystem - Editor settings and preferences
+//!
+//! This module handles loading and applying configuration from .virusrc files,
+//! providing centralized settings management for the entire application.
 
 pub mod rc;
+pub mod filetype;
 
 // Re-export public interface
 pub use rc::{RcConfig, RcLoader};
\ No newline at end of file
@@ -1,4 +1,6 @@
 // use crate::controller::Controller; // Old Controller no longer used
+use super::filetype::{FileTypeRule, split_settings};
+use crate::controller::key_map::KeyMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -9,7 +11,38 @@ pub struct RcConfig {
     pub expand_tab: bool,
     pub show_line_numbers: bool,
     pub show_whitespace: bool,
+    pub list_chars: String,
+    pub show_trailing: bool,
+    pub cursor_line: bool,
+    pub color_columns: String,
     pub line_ending: String,
+    pub text_width: usize,
+    pub wrap_paste: bool,
+    pub trim_trailing: bool,
+    pub hlsearch: bool,
+    pub incsearch: bool,
+    pub ignorecase: bool,
+    pub smartcase: bool,
+    pub shortmess: String, // S leaves the match count out of search messages
+    pub autoread: bool,
+    pub confirm: bool,
+    pub undo_levels: usize,
+    pub autosave: u64, // Seconds without edits before modified buffers are written; 0 is off
+    pub tick_time: u64, // Milliseconds between redraws while idle; 0 is off
+    pub show_command: bool,
+    pub timeout: bool,     // Commands typed in part are dropped after timeout_len
+    pub timeout_len: u64,  // Milliseconds keys wait for the rest of a mapping or command
+    pub synmaxcol: usize,
+    pub colorscheme: String,
+    pub shift_width: usize,
+    pub shift_round: bool,
+    pub auto_indent: bool,
+    pub auto_pairs: bool,
+    pub show_match: bool,
+    pub match_time: u64, // Tenths of a second 'showmatch' highlights a bracket
+    pub mappings: Vec<(String, String)>, // :map-family command and its arguments
+    pub file_type_rules: Vec<FileTypeRule>,
+    pub language_servers: Vec<(String, String)>, // Filetype and the command that starts its server
 }
 
 impl Default for RcConfig {
@@ -19,7 +52,38 @@ impl Default for RcConfig {
             expand_tab: false,
             show_line_numbers: false,
             show_whitespace: false,
+            list_chars: crate::view::list_chars::DEFAULT_LIST_CHARS.to_string(),
+            show_trailing: false,
+            cursor_line: false,
+            color_columns: String::new(),
             line_ending: "unix".to_string(),
+            text_width: 0,
+            wrap_paste: false,
+            trim_trailing: false,
+            hlsearch: true,
+            incsearch: true,
+            ignorecase: false,
+            smartcase: false,
+            shortmess: String::new(),
+            autoread: false,
+            confirm: false,
+            undo_levels: crate::document_model::undo::DEFAULT_UNDO_LEVELS,
+            autosave: 0,
+            tick_time: crate::controller::timers::DEFAULT_TICK.as_millis() as u64,
+            show_command: false,
+            timeout: false,
+            timeout_len: crate::controller::timers::DEFAULT_TIMEOUT_LEN.as_millis() as u64,
+            synmaxcol: crate::view::renderer::DEFAULT_MAX_HIGHLIGHT_COLUMN,
+            colorscheme: "default".to_string(),
+            shift_width: 0,
+            shift_round: false,
+            auto_indent: false,
+            auto_pairs: false,
+            show_match: false,
+            match_time: crate::controller::show_match::DEFAULT_MATCH_TIME,
+            mappings: Vec::new(),
+            file_type_rules: Vec::new(),
+            language_servers: Vec::new(),
         }
     }
 }
@@ -83,6 +147,14 @@ impl RcLoader {
 
     /// Parse a single configuration line
     fn parse_config_line(line: &str, config: &mut RcConfig) {
+        // Mappings are taken whole: their keys may include # and "
+        if let Some((command, argument)) = line.split_once(char::is_whitespace)
+            && KeyMap::is_map_command(command)
+        {
+            config.mappings.push((command.to_string(), argument.trim().to_string()));
+            return;
+        }
+
         // Remove inline comments
         let line = if let Some(pos) = line.find('#') {
             &line[..pos]
@@ -91,39 +163,23 @@ impl RcLoader {
         }
         .trim();
 
+        // Handle "autocmd FileType python set expandtab" (vim-style)
+        if let Some(rule) = FileTypeRule::parse(line) {
+            config.file_type_rules.push(rule);
+        }
+        // Handle "lsp rust rust-analyzer": the language server for a filetype
+        else if let Some((file_type, command)) = line.strip_prefix("lsp ").and_then(|rest| rest.trim().split_once(char::is_whitespace)) {
+            config.language_servers.push((file_type.to_string(), command.trim().to_string()));
+        }
+        // Handle "colorscheme name" (vim-style)
+        else if let Some(name) = line.strip_prefix("colorscheme ").or_else(|| line.strip_prefix("colo ")) {
+            config.colorscheme = name.trim().to_string();
+        }
         // Handle "set" commands (vim-style)
-        if let Some(stripped) = line.strip_prefix("set ") {
-            let setting = stripped.trim();
-
-            if setting == "nu" || setting == "number" {
-                config.show_line_numbers = true;
-            } else if setting == "nonu" || setting == "nonumber" {
-                config.show_line_numbers = false;
-            } else if setting == "expandtab" {
-                config.expand_tab = true;
-            } else if setting == "noexpandtab" {
-                config.expand_tab = false;
-            } else if setting == "list" {
-                config.show_whitespace = true;
-            } else if setting == "nolist" {
-                config.show_whitespace = false;
-            } else if setting.starts_with("tabstop=") {
-                if let Some(value) = setting.strip_prefix("tabstop=") {
-                    if let Ok(tab_stop) = value.parse::<usize>() {
-                        if tab_stop > 0 && tab_stop <= 16 {
-                            config.tab_stop = tab_stop;
-                        }
-                    }
-                }
-            } else if setting.starts_with("fileformat=") {
-                if let Some(value) = setting.strip_prefix("fileformat=") {
-                    match value {
-                        "unix" | "dos" | "mac" => {
-                            config.line_ending = value.to_string();
-                        }
-                        _ => {} // Invalid value, ignore
-                    }
-                }
+        else if let Some(stripped) = line.strip_prefix("set ") {
+            // Several options may share a line: set tabstop=4 shiftwidth=4 number
+            for setting in split_settings(stripped.split_whitespace()) {
+                Self::parse_set_option(&setting, config);
             }
         }
         // Handle direct key-value pairs
@@ -133,10 +189,10 @@ impl RcLoader {
 
             match key {
                 "tabstop" | "tab_stop" => {
-                    if let Ok(tab_stop) = value.parse::<usize>() {
-                        if tab_stop > 0 && tab_stop <= 16 {
-                            config.tab_stop = tab_stop;
-                        }
+                    if let Ok(tab_stop) = value.parse::<usize>()
+                        && tab_stop > 0 && tab_stop <= 16
+                    {
+                        config.tab_stop = tab_stop;
                     }
                 }
                 "expandtab" | "expand_tab" => {
@@ -148,6 +204,106 @@ impl RcLoader {
                 "whitespace" | "show_whitespace" | "list" => {
                     config.show_whitespace = value == "true" || value == "1" || value == "yes";
                 }
+                "shiftwidth" | "shift_width" => {
+                    if let Ok(shift_width) = value.parse::<usize>() {
+                        config.shift_width = shift_width;
+                    }
+                }
+                "shiftround" | "shift_round" => {
+                    config.shift_round = value == "true" || value == "1" || value == "yes";
+                }
+                "autoindent" | "auto_indent" => {
+                    config.auto_indent = value == "true" || value == "1" || value == "yes";
+                }
+                "autopairs" | "auto_pairs" => {
+                    config.auto_pairs = value == "true" || value == "1" || value == "yes";
+                }
+                "textwidth" | "text_width" => {
+                    if let Ok(text_width) = value.parse::<usize>() {
+                        config.text_width = text_width;
+                    }
+                }
+                "synmaxcol" => {
+                    if let Ok(synmaxcol) = value.parse::<usize>() {
+                        config.synmaxcol = synmaxcol;
+                    }
+                }
+                "pastewrap" | "wrap_paste" => {
+                    config.wrap_paste = value == "true" || value == "1" || value == "yes";
+                }
+                "listchars" | "list_chars" => {
+                    config.list_chars = value.to_string();
+                }
+                "cursorline" | "cursor_line" => {
+                    config.cursor_line = value == "true" || value == "1" || value == "yes";
+                }
+                "colorcolumn" | "color_column" => {
+                    config.color_columns = value.to_string();
+                }
+                "hltrailing" | "show_trailing" => {
+                    config.show_trailing = value == "true" || value == "1" || value == "yes";
+                }
+                "trimtrailing" | "trim_trailing" => {
+                    config.trim_trailing = value == "true" || value == "1" || value == "yes";
+                }
+                "hlsearch" => {
+                    config.hlsearch = value == "true" || value == "1" || value == "yes";
+                }
+                "incsearch" => {
+                    config.incsearch = value == "true" || value == "1" || value == "yes";
+                }
+                "ignorecase" => {
+                    config.ignorecase = value == "true" || value == "1" || value == "yes";
+                }
+                "smartcase" => {
+                    config.smartcase = value == "true" || value == "1" || value == "yes";
+                }
+                "shortmess" | "short_messages" => {
+                    config.shortmess = value.to_string();
+                }
+                "autoread" => {
+                    config.autoread = value == "true" || value == "1" || value == "yes";
+                }
+                "confirm" => {
+                    config.confirm = value == "true" || value == "1" || value == "yes";
+                }
+                "showmatch" | "show_match" => {
+                    config.show_match = value == "true" || value == "1" || value == "yes";
+                }
+                "matchtime" | "match_time" => {
+                    if let Ok(tenths) = value.parse::<u64>() {
+                        config.match_time = tenths;
+                    }
+                }
+                "undolevels" | "undo_levels" => {
+                    if let Ok(levels) = value.parse::<usize>() {
+                        config.undo_levels = levels;
+                    }
+                }
+                "autosave" => {
+                    if let Ok(seconds) = value.parse::<u64>() {
+                        config.autosave = seconds;
+                    }
+                }
+                "ticktime" | "tick_time" => {
+                    if let Ok(millis) = value.parse::<u64>() {
+                        config.tick_time = millis;
+                    }
+                }
+                "showcmd" | "show_command" => {
+                    config.show_command = value == "true" || value == "1" || value == "yes";
+                }
+                "timeout" => {
+                    config.timeout = value == "true" || value == "1" || value == "yes";
+                }
+                "timeoutlen" | "timeout_len" => {
+                    if let Ok(millis) = value.parse::<u64>() {
+                        config.timeout_len = millis;
+                    }
+                }
+                "colorscheme" | "color_scheme" => {
+                    config.colorscheme = value.to_string();
+                }
                 "fileformat" | "line_ending" => {
                     match value {
                         "unix" | "dos" | "mac" => {
@@ -161,30 +317,229 @@ impl RcLoader {
         }
     }
 
+    /// Parse one option of a "set" line
+    fn parse_set_option(setting: &str, config: &mut RcConfig) {
+        if setting == "nu" || setting == "number" {
+            config.show_line_numbers = true;
+        } else if setting == "nonu" || setting == "nonumber" {
+            config.show_line_numbers = false;
+        } else if setting == "expandtab" || setting == "et" {
+            config.expand_tab = true;
+        } else if setting == "noexpandtab" || setting == "noet" {
+            config.expand_tab = false;
+        } else if setting == "list" {
+            config.show_whitespace = true;
+        } else if setting == "nolist" {
+            config.show_whitespace = false;
+        } else if setting == "shiftround" || setting == "sr" {
+            config.shift_round = true;
+        } else if setting == "noshiftround" || setting == "nosr" {
+            config.shift_round = false;
+        } else if setting == "autoindent" || setting == "ai" {
+            config.auto_indent = true;
+        } else if setting == "noautoindent" || setting == "noai" {
+            config.auto_indent = false;
+        } else if setting == "autopairs" {
+            config.auto_pairs = true;
+        } else if setting == "noautopairs" {
+            config.auto_pairs = false;
+        } else if setting == "pastewrap" {
+            config.wrap_paste = true;
+        } else if setting == "nopastewrap" {
+            config.wrap_paste = false;
+        } else if let Some(value) = setting.strip_prefix("listchars=").or_else(|| setting.strip_prefix("lcs=")) {
+            config.list_chars = value.to_string();
+        } else if setting == "cursorline" || setting == "cul" {
+            config.cursor_line = true;
+        } else if setting == "nocursorline" || setting == "nocul" {
+            config.cursor_line = false;
+        } else if let Some(value) = setting.strip_prefix("colorcolumn=").or_else(|| setting.strip_prefix("cc=")) {
+            config.color_columns = value.to_string();
+        } else if setting == "hltrailing" {
+            config.show_trailing = true;
+        } else if setting == "nohltrailing" {
+            config.show_trailing = false;
+        } else if setting == "trimtrailing" {
+            config.trim_trailing = true;
+        } else if setting == "notrimtrailing" {
+            config.trim_trailing = false;
+        } else if setting == "hlsearch" || setting == "hls" {
+            config.hlsearch = true;
+        } else if setting == "nohlsearch" || setting == "nohls" {
+            config.hlsearch = false;
+        } else if setting == "incsearch" || setting == "is" {
+            config.incsearch = true;
+        } else if setting == "noincsearch" || setting == "nois" {
+            config.incsearch = false;
+        } else if setting == "ignorecase" || setting == "ic" {
+            config.ignorecase = true;
+        } else if setting == "noignorecase" || setting == "noic" {
+            config.ignorecase = false;
+        } else if setting == "smartcase" || setting == "scs" {
+            config.smartcase = true;
+        } else if setting == "nosmartcase" || setting == "noscs" {
+            config.smartcase = false;
+        } else if setting == "showmatch" || setting == "sm" {
+            config.show_match = true;
+        } else if setting == "noshowmatch" || setting == "nosm" {
+            config.show_match = false;
+        } else if let Some(value) = setting.strip_prefix("matchtime=").or_else(|| setting.strip_prefix("mat=")) {
+            if let Ok(tenths) = value.parse::<u64>() {
+                config.match_time = tenths;
+            }
+        } else if setting == "autoread" || setting == "ar" {
+            config.autoread = true;
+        } else if setting == "noautoread" || setting == "noar" {
+            config.autoread = false;
+        } else if setting == "confirm" || setting == "cf" {
+            config.confirm = true;
+        } else if setting == "noconfirm" || setting == "nocf" {
+            config.confirm = false;
+        } else if let Some(value) = setting.strip_prefix("undolevels=").or_else(|| setting.strip_prefix("ul=")) {
+            if let Ok(levels) = value.parse::<usize>() {
+                config.undo_levels = levels;
+            }
+        } else if setting == "autosave" {
+            config.autosave = crate::controller::session_controller::DEFAULT_AUTOSAVE_SECONDS;
+        } else if setting == "noautosave" {
+            config.autosave = 0;
+        } else if let Some(value) = setting.strip_prefix("autosave=") {
+            if let Ok(seconds) = value.parse::<u64>() {
+                config.autosave = seconds;
+            }
+        } else if let Some(value) = setting.strip_prefix("ticktime=") {
+            if let Ok(millis) = value.parse::<u64>() {
+                config.tick_time = millis;
+            }
+        } else if let Some(value) = setting.strip_prefix("shortmess=").or_else(|| setting.strip_prefix("shm=")) {
+            config.shortmess = value.to_string();
+        } else if setting == "showcmd" || setting == "sc" {
+            config.show_command = true;
+        } else if setting == "noshowcmd" || setting == "nosc" {
+            config.show_command = false;
+        } else if setting == "timeout" || setting == "to" {
+            config.timeout = true;
+        } else if setting == "notimeout" || setting == "noto" {
+            config.timeout = false;
+        } else if let Some(value) = setting.strip_prefix("timeoutlen=").or_else(|| setting.strip_prefix("tm=")) {
+            if let Ok(millis) = value.parse::<u64>() {
+                config.timeout_len = millis;
+            }
+        } else if let Some(value) = setting
+            .strip_prefix("textwidth=")
+            .or_else(|| setting.strip_prefix("tw="))
+        {
+            if let Ok(text_width) = value.parse::<usize>() {
+                config.text_width = text_width;
+            }
+        } else if let Some(value) = setting
+            .strip_prefix("synmaxcol=")
+            .or_else(|| setting.strip_prefix("smc="))
+        {
+            if let Ok(synmaxcol) = value.parse::<usize>() {
+                config.synmaxcol = synmaxcol;
+            }
+        } else if let Some(value) = setting
+            .strip_prefix("shiftwidth=")
+            .or_else(|| setting.strip_prefix("sw="))
+        {
+            if let Ok(shift_width) = value.parse::<usize>() {
+                config.shift_width = shift_width;
+            }
+        } else if setting.starts_with("tabstop=") || setting.starts_with("ts=") {
+            if let Some((_, value)) = setting.split_once('=')
+                && let Ok(tab_stop) = value.parse::<usize>()
+                && tab_stop > 0 && tab_stop <= 16
+            {
+                config.tab_stop = tab_stop;
+            }
+        } else if setting.starts_with("fileformat=")
+            && let Some(value) = setting.strip_prefix("fileformat=")
+        {
+            match value {
+                "unix" | "dos" | "mac" => {
+                    config.line_ending = value.to_string();
+                }
+                _ => {} // Invalid value, ignore
+            }
+        }
+    }
+
     /// Apply the configuration to the new modular architecture
     pub fn apply_config_to_shared_state(shared_state: &mut crate::controller::SharedEditorState, config: &RcConfig) {
         // Apply view settings
         shared_state.view.set_tab_stop(config.tab_stop);
         shared_state.view.set_line_numbers(config.show_line_numbers);
         shared_state.view.set_show_whitespace(config.show_whitespace);
+        shared_state.view.set_show_trailing(config.show_trailing);
+        match crate::view::ListChars::parse(&config.list_chars) {
+            Ok(list_chars) => shared_state.view.set_list_chars(list_chars),
+            Err(e) => shared_state.status_message = e,
+        }
+        shared_state.view.set_cursor_line(config.cursor_line);
+        match crate::view::renderer::parse_color_columns(&config.color_columns) {
+            Ok(columns) => shared_state.view.set_color_columns(columns),
+            Err(e) => shared_state.status_message = e,
+        }
+        shared_state.view.set_max_highlight_column(config.synmaxcol);
+        shared_state.search_state.hlsearch = config.hlsearch;
+        shared_state.search_state.incsearch = config.incsearch;
+        shared_state.search_state.ignore_case = config.ignorecase;
+        shared_state.search_state.smart_case = config.smartcase;
+        shared_state.search_state.shortmess = config.shortmess.clone();
+        shared_state.session_controller.autoread = config.autoread;
+        shared_state.session_controller.confirm = config.confirm;
+        shared_state.session_controller.undo_levels = config.undo_levels;
+        shared_state.session_controller.autosave =
+            (config.autosave > 0).then(|| std::time::Duration::from_secs(config.autosave));
+        shared_state.timers.set_tick(std::time::Duration::from_millis(config.tick_time));
+        shared_state.view.set_show_command(config.show_command);
+        shared_state.timers.timeout = config.timeout;
+        shared_state.timers.timeout_len = std::time::Duration::from_millis(config.timeout_len);
+        shared_state.show_match.enabled = config.show_match;
+        shared_state.show_match.match_time = config.match_time;
+        match crate::view::Theme::load(&config.colorscheme) {
+            Ok(theme) => shared_state.view.set_theme(theme),
+            Err(e) => shared_state.status_message = e,
+        }
 
         // Apply document settings
         shared_state.session_controller
             .current_document_mut()
             .set_expand_tab(config.expand_tab);
+        let doc = shared_state.session_controller.current_document_mut();
+        doc.text_width = config.text_width;
+        doc.wrap_paste = config.wrap_paste;
+        doc.trim_trailing = config.trim_trailing;
+        doc.shift_width = config.shift_width;
+        doc.shift_round = config.shift_round;
+        doc.auto_indent = config.auto_indent;
+        doc.auto_pairs = config.auto_pairs;
+
+        // The line ending setting is for new files; a file read from disk
+        // keeps the one it was written with
+        let doc = shared_state.session_controller.current_document_mut();
+        if !doc.filename.as_ref().is_some_and(|path| path.is_file())
+            && let Some(line_ending) = crate::document_model::LineEnding::from_name(&config.line_ending)
+        {
+            doc.line_ending = line_ending;
+        }
+
+        // Per-filetype options for the buffers already open and any opened later
+        let session = &mut shared_state.session_controller;
+        session.file_type_rules = config.file_type_rules.clone();
+        for doc in &mut session.buffers {
+            FileTypeRule::apply_all(&session.file_type_rules, doc);
+        }
 
-        // Apply line ending setting
-        match config.line_ending.as_str() {
-            "unix" => shared_state.session_controller
-                .current_document_mut()
-                .set_line_ending(crate::document_model::LineEnding::Unix),
-            "dos" => shared_state.session_controller
-                .current_document_mut()
-                .set_line_ending(crate::document_model::LineEnding::Windows),
-            "mac" => shared_state.session_controller
-                .current_document_mut()
-                .set_line_ending(crate::document_model::LineEnding::Mac),
-            _ => {} // Default to Unix
+        for (file_type, command) in &config.language_servers {
+            shared_state.lsp.set_server(file_type, command);
+        }
+
+        for (command, argument) in &config.mappings {
+            if let Some(Err(e)) = shared_state.key_map.execute(command, argument) {
+                shared_state.status_message = format!(".virusrc: {} {}: {}", command, argument, e);
+            }
         }
     }
 
@@ -197,13 +552,72 @@ impl RcLoader {
 # Display settings
 set nu                  # Show line numbers (or set nonu to disable)
 set list               # Show whitespace characters (or set nolist to disable)
+# set listchars=tab:»·,trail:·,nbsp:␣,eol:$   # ...drawn as these (also space:)
+# set hltrailing       # Highlight spaces and tabs at the ends of lines
+# set cursorline       # Highlight the line the cursor is on
+# set colorcolumn=80,100  # Mark these screen columns on every line
+
+# Colours: a built-in theme (default, dark, light, solarized, mono)
+# or one from ~/.virus/themes/<name>.toml
+# colorscheme dark
 
 # Tab settings
 set tabstop=4          # Set tab width to 4 spaces
 set expandtab          # Use spaces instead of tabs (or set noexpandtab)
+# set shiftwidth=2     # Columns >> and << move by (0 = tabstop)
+# set shiftround       # >> and << round the indent to a multiple of shiftwidth
+# set autoindent       # New lines start with the indent of the line before
+# set autopairs        # Typing ( [ { " ' ` adds the closing one
+# set ts=4 sw=4 et     # Several options on one line
+
+# Options for some kinds of file only (buffer options: expandtab,
+# shiftwidth, shiftround, autoindent, autopairs, textwidth, pastewrap,
+# trimtrailing), set over the file type's own defaults
+# autocmd FileType python set expandtab shiftwidth=4
+# autocmd FileType make,go set noexpandtab
+
+# Key mappings (map, nmap, vmap, imap; noremap, nnoremap, vnoremap,
+# inoremap don't map their right-hand side again)
+# nnoremap <C-s> :w<CR>
+# inoremap jk <Esc>
+# <leader> in the mappings after this stands for , (\ by default)
+# let mapleader = ","
+# nnoremap <leader>w :w<CR>
 
 # File format
-set fileformat=unix    # Line endings: unix, dos, or mac
+set fileformat=unix    # Line endings of new files: unix, dos, or mac
+
+# Wrapping
+# set textwidth=79     # Column for typing, gq, :Wrap and pastewrap (0 = off)
+# set pastewrap        # Wrap pasted text to textwidth
+# set trimtrailing     # Strip trailing whitespace when writing a file
+# set synmaxcol=3000   # No highlighting past this column on long lines (0 = no limit)
+
+# Search
+# set nohlsearch       # Don't highlight matches of the last search
+# set noincsearch      # Don't jump to matches while typing a search
+# set ignorecase       # Searches and :s ignore case...
+# set smartcase        # ...unless the pattern has a capital letter
+# set shortmess=S      # Don't show which match a search went to ([3/17])
+
+# Brackets
+# set showmatch        # Briefly highlight the bracket a typed ), ] or } closes
+# set matchtime=5      # ...for this many tenths of a second
+
+# Undo
+# set undolevels=1000  # Changes kept for undo; older ones are dropped
+
+# Files changed on disk
+# set autoread         # Reload unmodified buffers without asking
+# set autosave=30      # Write modified buffers after 30s without edits,
+#                      # on leaving a buffer and when the terminal loses focus
+# set ticktime=1000    # Redraw every second while idle (0 = only for input)
+# set confirm          # Ask to save (or overwrite) instead of failing on :q, :e, :w name
+
+# Keys
+# set showcmd          # Show a command typed in part ("a2d) on the status line
+# set timeout          # Drop a command typed in part when typing pauses...
+# set timeoutlen=1000  # ...for this many ms (also how long mappings wait)
 
 # Alternative key=value syntax:
 # tab_stop=4
@@ -211,6 +625,7 @@ set fileformat=unix    # Line endings: unix, dos, or mac
 # line_numbers=true
 # show_whitespace=false
 # line_ending=unix
+# colorscheme=dark
 "#
         .to_string()
     }
@@ -229,10 +644,51 @@ mod tests {
             set tabstop=8
             set list
             set fileformat=dos
+            set textwidth=72
+            set pastewrap
+            set hltrailing
+            set cul cc=80,100
+            set lcs=tab:>-,space:.
+            set trimtrailing
+            set nohls
+            set ic
+            set scs
+            set shm=S
+            set smc=500
+            set ar
+            set cf
+            set ul=50
+            set autosave=20
+            set ticktime=250
+            set sm mat=3
+            set sc to tm=500
+            colorscheme solarized
         "#;
 
         RcLoader::parse_config_content(content, &mut config);
 
+        assert_eq!(config.text_width, 72);
+        assert_eq!(config.synmaxcol, 500);
+        assert!(config.wrap_paste);
+        assert!(config.show_trailing && config.trim_trailing);
+        assert_eq!(config.list_chars, "tab:>-,space:.");
+        assert!(config.cursor_line);
+        assert_eq!(config.color_columns, "80,100");
+        assert!(!config.hlsearch);
+        assert!(config.incsearch);
+        assert!(config.ignorecase && config.smartcase);
+        assert_eq!(config.shortmess, "S");
+        assert!(config.autoread);
+        assert!(config.confirm);
+        assert_eq!(config.undo_levels, 50);
+        assert_eq!(config.autosave, 20);
+        assert_eq!(config.tick_time, 250);
+        assert!(config.show_match);
+        assert_eq!(config.match_time, 3);
+        assert!(config.show_command && config.timeout);
+        assert_eq!(config.timeout_len, 500);
+        assert_eq!(config.colorscheme, "solarized");
+
         assert!(config.show_line_numbers);
         assert!(config.expand_tab);
         assert_eq!(config.tab_stop, 8);
@@ -249,6 +705,7 @@ mod tests {
             line_numbers=yes
             show_whitespace=false
             line_ending=mac
+            colorscheme=light
         "#;
 
         RcLoader::parse_config_content(content, &mut config);
@@ -258,6 +715,7 @@ mod tests {
         assert_eq!(config.tab_stop, 2);
         assert!(!config.show_whitespace);
         assert_eq!(config.line_ending, "mac");
+        assert_eq!(config.colorscheme, "light");
     }
 
     #[test]
@@ -281,6 +739,37 @@ mod tests {
         assert!(!config.show_whitespace);
     }
 
+    #[test]
+    fn test_parse_mappings_filetypes_and_option_lists() {
+        let mut config = RcConfig::default();
+        let content = r#"
+            set ts=8 sw=2 number noet   # several at once
+            nnoremap <C-s> :w<CR>
+            map # :nohls<CR>
+            inoremap jk <Esc>
+            let mapleader = ","
+            autocmd FileType python set expandtab shiftwidth=4  # PEP 8
+            au FileType make setlocal noet
+            lsp rust rust-analyzer --log-file /tmp/ra.log
+        "#;
+
+        RcLoader::parse_config_content(content, &mut config);
+
+        assert_eq!((config.tab_stop, config.shift_width), (8, 2));
+        assert!(config.show_line_numbers);
+        assert!(!config.expand_tab);
+        assert_eq!(config.mappings, [
+            ("nnoremap".to_string(), "<C-s> :w<CR>".to_string()),
+            ("map".to_string(), "# :nohls<CR>".to_string()),
+            ("inoremap".to_string(), "jk <Esc>".to_string()),
+            ("let".to_string(), "mapleader = \",\"".to_string()),
+        ]);
+        assert_eq!(config.file_type_rules.len(), 2);
+        assert_eq!(config.file_type_rules[0].file_types, ["python"]);
+        assert_eq!(config.file_type_rules[0].settings, ["expandtab", "shiftwidth=4"]);
+        assert_eq!(config.language_servers, [("rust".to_string(), "rust-analyzer --log-file /tmp/ra.log".to_string())]);
+    }
+
     #[test]
     fn test_invalid_values_ignored() {
         let mut config = RcConfig::default();
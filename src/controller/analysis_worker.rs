@@ -0,0 +1,129 @@
+//! Background analysis - whole-buffer passes run on a worker thread while
+//! the user is idle, so keystrokes never wait on them
+//!
+//! Each job is tagged with the revision of the text it was given. Starting
+//! a job for a newer revision cancels the one in flight, and results for
+//! anything but the latest request are dropped.
+
+use crate::document_model::analysis;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+struct Job {
+    revision: u64,
+    text: String,
+    cancel: Arc<AtomicBool>,
+}
+
+/// What the worker found in one revision of a buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisResult {
+    pub revision: u64,
+    pub unmatched_brackets: Vec<(usize, usize)>,
+}
+
+pub struct AnalysisWorker {
+    jobs: Sender<Job>,
+    results: Receiver<AnalysisResult>,
+    pending: Option<(u64, Arc<AtomicBool>)>, // Revision being worked on and its cancel flag
+}
+
+impl Default for AnalysisWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalysisWorker {
+    /// Start the worker thread. It exits when the worker is dropped.
+    pub fn new() -> Self {
+        let (jobs, job_queue) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            for job in job_queue {
+                let Some(unmatched_brackets) = analysis::unmatched_brackets(&job.text, &job.cancel) else {
+                    continue;
+                };
+                let result = AnalysisResult { revision: job.revision, unmatched_brackets };
+                if result_sender.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { jobs, results, pending: None }
+    }
+
+    /// True while a job is waiting for its result
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The revision being worked on, if any
+    pub fn pending_revision(&self) -> Option<u64> {
+        self.pending.as_ref().map(|(revision, _)| *revision)
+    }
+
+    /// Analyse `text`, cancelling whatever was running
+    pub fn start(&mut self, revision: u64, text: String) {
+        self.cancel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = Job { revision, text, cancel: Arc::clone(&cancel) };
+        if self.jobs.send(job).is_ok() {
+            self.pending = Some((revision, cancel));
+        }
+    }
+
+    /// Stop the job in flight; its result will never be reported
+    pub fn cancel(&mut self) {
+        if let Some((_, cancel)) = self.pending.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The latest job's result if it has finished, without waiting
+    pub fn try_result(&mut self) -> Option<AnalysisResult> {
+        while let Ok(result) = self.results.try_recv() {
+            if self.pending_revision() == Some(result.revision) {
+                self.pending = None;
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(worker: &mut AnalysisWorker) -> Option<AnalysisResult> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(result) = worker.try_result() {
+                return Some(result);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn test_only_the_latest_revision_is_reported() {
+        let mut worker = AnalysisWorker::new();
+        worker.start(1, "(".repeat(100_000));
+        worker.start(2, "a)".to_string());
+        assert_eq!(worker.pending_revision(), Some(2));
+
+        let result = wait_for(&mut worker).unwrap();
+        assert_eq!(result, AnalysisResult { revision: 2, unmatched_brackets: vec![(0, 1)] });
+        assert!(!worker.is_busy());
+
+        worker.start(3, "[".to_string());
+        worker.cancel();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(worker.try_result(), None);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::ArgList;
+use std::path::PathBuf;
+
+/// Moving through the argument list - :args, :next, :prev, :first, :last
+/// and :argadd. The list is kept apart from the buffer list: closing a
+/// buffer or opening another file with :e doesn't change it.
+pub struct ArgCommands;
+
+impl ArgCommands {
+    /// Edit file `index` of the argument list, in the buffer that has it
+    /// or a new one
+    pub fn edit(shared: &mut SharedEditorState, index: usize) -> Result<(), String> {
+        let args = &mut shared.session_controller.args;
+        let count = args.files().len();
+        let path = args.select(index).cloned().ok_or("Argument list is empty")?;
+
+        let session = &mut shared.session_controller;
+        if session.current_document().filename.as_ref() != Some(&path) {
+            match session.switch_to_file(&path) {
+                Ok(()) => {}
+                // A file the list names may not have been written yet
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    session.open_files(vec![&path.to_string_lossy()]);
+                }
+                Err(e) => return Err(format!("Can't open {}: {}", path.display(), e)),
+            }
+            shared.view.reset_scroll();
+            shared.cached_unmatched_brackets = None;
+        }
+        shared.status_message = format!("\"{}\" (file {} of {})", path.display(), index + 1, count);
+        Ok(())
+    }
+
+    /// :next / :prev - edit the file `count` on or back in the list
+    pub fn step(shared: &mut SharedEditorState, count: usize, forward: bool) -> Result<(), String> {
+        let index = shared.session_controller.args.step(count, forward)?;
+        Self::edit(shared, index)
+    }
+
+    /// :args file... - make `files` the argument list and edit the first
+    pub fn set(shared: &mut SharedEditorState, files: &[String]) -> Result<(), String> {
+        shared.session_controller.args = ArgList::new(files.iter().map(PathBuf::from).collect());
+        Self::edit(shared, 0)
+    }
+
+    /// :argadd [file...] - add files after the current one in the list, or
+    /// without any, the current buffer's file
+    pub fn add(shared: &mut SharedEditorState, files: &[String]) -> Result<(), String> {
+        let files = if files.is_empty() {
+            vec![shared.session_controller.current_document().filename.clone().ok_or("No file name")?]
+        } else {
+            files.iter().map(PathBuf::from).collect()
+        };
+        shared.session_controller.args.add(files);
+        shared.status_message = shared.session_controller.args.describe();
+        Ok(())
+    }
+}
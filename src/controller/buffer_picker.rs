@@ -0,0 +1,48 @@
+use crate::controller::SessionController;
+use crate::controller::shared_state::SharedEditorState;
+use crate::view::ListPopup;
+use crossterm::event::KeyCode;
+
+/// The :ls buffer list, moved through with j/k and picked with Enter
+pub struct BufferPicker {
+    selected: usize,
+}
+
+impl BufferPicker {
+    pub const PROMPT: &str = "ls (j/k to move, Enter to switch, Esc to close)";
+
+    /// Start on the current buffer
+    pub fn new(session: &SessionController) -> Self {
+        Self { selected: session.current_buffer_index() }
+    }
+
+    /// Handle one key. Returns true while the list stays open.
+    pub fn handle_key(&mut self, code: KeyCode, shared: &mut SharedEditorState) -> bool {
+        let last = shared.session_controller.buffer_count() - 1;
+        match code {
+            KeyCode::Char('j') | KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Enter => {
+                shared.status_message = shared
+                    .session_controller
+                    .switch_to_buffer(self.selected.min(last) + 1)
+                    .unwrap_or_else(|msg| msg);
+                return false;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// The list as drawn over the bottom of the text area
+    pub fn popup(&self, session: &SessionController) -> ListPopup {
+        ListPopup {
+            title: format!(" Buffers ({})", session.buffer_count()),
+            items: session.buffer_rows(),
+            selected: self.selected,
+        }
+    }
+}
@@ -1,26 +1,390 @@
-use crate::controller::shared_state::{ModeController, ModeTransition, SharedEditorState};
+use crate::controller::arg_commands::ArgCommands;
+use crate::controller::buffer_picker::BufferPicker;
+use crate::controller::confirm_dialog::{ConfirmDialog, DialogAnswer, DialogKind};
+use crate::controller::directory_commands::DirectoryCommands;
+use crate::controller::disk_changes::ReloadPrompt;
+use crate::controller::shared_state::{ModeController, ModeTransition, NormalKeys, SharedEditorState};
 use crate::controller::command_types::Mode;
-use crossterm::event::{KeyEvent, KeyCode};
+use crate::controller::ex_commands::ExCommands;
+use crate::controller::help_commands::{HelpCommands, NOT_MODIFIABLE};
+use crate::controller::quickfix_commands::{QuickfixCommands, QuickfixPicker};
+use crate::controller::session_controller::DEFAULT_AUTOSAVE_SECONDS;
+use crate::controller::session_script::{DEFAULT_SESSION_FILE, SessionScript};
+use crate::controller::substitute::{SubstituteConfirm, SubstituteFlags, Substitution};
+use crate::controller::undo_commands::{UndoCommands, UndoDistance};
+use crate::controller::unsaved_buffers::{QuitAnswer, QuitPrompt, UnsavedBuffers};
+use crate::controller::window_commands::WindowCommands;
+use crate::controller::diff_commands::DiffCommands;
+use crate::controller::tag_commands::{TagCommands, TagPicker};
+use crate::controller::lsp_commands::LspCommands;
+use crate::config::filetype::split_settings;
+use crate::document_model::{FilePosition, RegisterType};
+use crate::document_model::history::{HistoryBrowser, HistoryKind};
+use crate::document_model::retab::Retab;
+use crate::document_model::{git, glob, shell};
+use crate::document_model::sort::LineSort;
+use crate::document_model::stats::BufferStats;
+use crate::view::renderer::parse_color_columns;
+use crate::view::theme::BUILTIN_THEMES;
+use crate::view::{ListChars, ListPopup, SplitDirection, Theme};
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub struct CommandController {
     pub command_buffer: String,
+    running_global: bool, // Set while :g executes its per-line command, to reject nesting
+    pending_register_insert: bool, // Ctrl-r pressed, waiting for a register name
+    history: HistoryBrowser,       // Up/Down through earlier command lines
+    confirm: Option<SubstituteConfirm>, // :s///c waiting for y/n/a/q/l
+    picker: Option<BufferPicker>,       // :ls list open
+    quickfix_picker: Option<QuickfixPicker>, // :copen list open
+    tag_picker: Option<TagPicker>,      // :tselect list open
+    reload: Option<ReloadPrompt>,       // Files changed on disk, waiting for l/k
+    quit_prompt: Option<QuitPrompt>,    // :q with other buffers unsaved, waiting for w/q/c
+    dialog: Option<ConfirmDialog>,      // 'confirm': :q, :e or :w name waiting for y/n/a/c
+    confirmed: bool,                    // Set while a confirmed command line runs, so it doesn't ask again
+    error: Option<String>,              // Set when a command fails, for --cmd runs
+}
+
+impl Default for CommandController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CommandController {
     pub fn new() -> Self {
         Self {
             command_buffer: String::new(),
+            running_global: false,
+            pending_register_insert: false,
+            history: HistoryBrowser::default(),
+            confirm: None,
+            picker: None,
+            quickfix_picker: None,
+            tag_picker: None,
+            reload: None,
+            quit_prompt: None,
+            dialog: None,
+            confirmed: false,
+            error: None,
+        }
+    }
+
+    /// @: - run the command line in the : register again, `count` times
+    pub fn repeat_last_command(shared: &mut SharedEditorState, count: usize) -> ModeTransition {
+        let command = shared
+            .register_manager
+            .get_register_content(Some(':'))
+            .map(|data| data.content.clone())
+            .unwrap_or_default();
+        if command.is_empty() {
+            shared.status_message = "No previous command line".to_string();
+            return ModeTransition::Stay;
+        }
+
+        let mut controller = Self::new();
+        for _ in 0..count.max(1) {
+            if controller.execute_command(&command, shared) {
+                return ModeTransition::Quit;
+            }
+        }
+        ModeTransition::Stay
+    }
+
+    /// & - repeat the last :s on the current line, without its flags
+    pub fn repeat_substitute(shared: &mut SharedEditorState) {
+        Self::new().execute_command("&", shared);
+    }
+
+    /// :stats - searches and substitutions per buffer and per file type this session
+    fn format_session_stats(shared: &SharedEditorState) -> String {
+        let session = &shared.session_controller;
+        let mut report = String::new();
+        let mut by_type: Vec<(String, BufferStats)> = Vec::new();
+        let mut total = BufferStats::default();
+
+        for (index, doc) in session.buffers.iter().enumerate() {
+            report.push_str(&format!(
+                "  {} {}: {}\n",
+                index + 1,
+                session.buffer_display_name(index),
+                doc.stats.summary()
+            ));
+
+            let file_type = doc
+                .filename
+                .as_ref()
+                .and_then(|path| path.extension())
+                .map_or("(none)".to_string(), |ext| format!(".{}", ext.to_string_lossy()));
+            match by_type.iter_mut().find(|(name, _)| *name == file_type) {
+                Some((_, stats)) => *stats += doc.stats,
+                None => by_type.push((file_type, doc.stats)),
+            }
+            total += doc.stats;
+        }
+
+        if by_type.len() > 1 {
+            report.push_str("By file type:\n");
+            for (file_type, stats) in &by_type {
+                report.push_str(&format!("  {}: {}\n", file_type, stats.summary()));
+            }
+        }
+        report.push_str(&format!("Total: {}", total.summary()));
+        report
+    }
+
+    /// Show a failed command's message, keeping it so a --cmd run can exit non-zero
+    fn report_error(&mut self, shared: &mut SharedEditorState, message: String) {
+        shared.status_message = message.clone();
+        self.error = Some(message);
+    }
+
+    /// Run one command line without the terminal (--cmd). Ok(true) means it quit.
+    pub fn execute_batch(&mut self, command: &str, shared: &mut SharedEditorState) -> Result<bool, String> {
+        self.error = None;
+        let quit = self.execute_command(command, shared);
+        self.picker = None;
+        self.quickfix_picker = None;
+        self.tag_picker = None;
+        if let Some(mut confirm) = self.confirm.take() {
+            confirm.cancel(shared);
+            return Err("Can't confirm substitutions without a terminal".to_string());
+        }
+        if let Some(mut reload) = self.reload.take() {
+            let message = reload.prompt.clone();
+            reload.cancel(shared);
+            return Err(message);
+        }
+        if let Some(quit_prompt) = self.quit_prompt.take() {
+            return Err(quit_prompt.prompt);
+        }
+        if let Some(dialog) = self.dialog.take() {
+            return Err(dialog.prompt);
+        }
+        match self.error.take() {
+            Some(message) => Err(message),
+            None => Ok(quit),
+        }
+    }
+
+    /// Ctrl-r {register} - insert a register's text into the command line
+    fn insert_register(&mut self, register: char, shared: &mut SharedEditorState) {
+        if let Some(data) = shared.register_manager.get_register_content(Some(register)) {
+            // A command line is a single line: drop the final newline of linewise text
+            let text = data.content.trim_end_matches('\n').replace('\n', " ");
+            self.command_buffer.push_str(&text);
         }
     }
     
+    /// Bracketed paste onto the command line, which holds a single line
+    pub fn paste(&mut self, text: &str) {
+        let asking = self.confirm.is_some() || self.reload.is_some() || self.quit_prompt.is_some() || self.dialog.is_some();
+        if !asking && self.picker.is_none() && self.quickfix_picker.is_none() && self.tag_picker.is_none() {
+            self.command_buffer.push_str(&text.trim_end_matches('\n').replace('\n', " "));
+        }
+    }
+
     pub fn get_command_buffer(&self) -> &str {
+        if let Some(confirm) = &self.confirm {
+            return &confirm.prompt;
+        }
+        if let Some(reload) = &self.reload {
+            return &reload.prompt;
+        }
+        if let Some(quit_prompt) = &self.quit_prompt {
+            return &quit_prompt.prompt;
+        }
+        if let Some(dialog) = &self.dialog {
+            return &dialog.prompt;
+        }
+        if self.picker.is_some() {
+            return BufferPicker::PROMPT;
+        }
+        if self.quickfix_picker.is_some() {
+            return QuickfixPicker::PROMPT;
+        }
+        if self.tag_picker.is_some() {
+            return TagPicker::PROMPT;
+        }
         &self.command_buffer
     }
+
+    /// Run a command line as Enter does. Stay means command mode stays open
+    /// for a question or a list.
+    pub fn run_line(&mut self, command: &str, shared: &mut SharedEditorState) -> ModeTransition {
+        let quit = self.execute_command(command, shared);
+        let asking = self.confirm.is_some() || self.reload.is_some() || self.quit_prompt.is_some() || self.dialog.is_some();
+        if asking || self.picker.is_some() || self.quickfix_picker.is_some() || self.tag_picker.is_some() {
+            ModeTransition::Stay // :s///c, :checktime, :q and 'confirm' ask first; :ls, :copen and :tselect wait for a pick
+        } else if quit {
+            ModeTransition::Quit
+        } else {
+            ModeTransition::ToMode(Mode::Normal)
+        }
+    }
+
+    /// Quit as the last window closes, unless other buffers have unsaved
+    /// changes: then ask about them first. True to quit now.
+    fn quit_last_window(&mut self, shared: &SharedEditorState) -> bool {
+        let mut unsaved = UnsavedBuffers::list(shared);
+        if self.confirmed {
+            // Answered n to 'confirm': the current buffer's changes go
+            unsaved.retain(|&index| index != shared.session_controller.current_buffer);
+        }
+        if unsaved.is_empty() {
+            return true;
+        }
+        self.quit_prompt = Some(QuitPrompt::new(shared, &unsaved));
+        false
+    }
+
+    /// Look for files changed on disk under open buffers. True when there
+    /// is a question to answer, which needs command mode.
+    pub fn check_files(&mut self, shared: &mut SharedEditorState) -> bool {
+        self.reload = ReloadPrompt::check(shared);
+        self.reload.is_some()
+    }
+
+    /// The :ls buffer list, the :copen quickfix list or the :tselect tag
+    /// list, while one is open
+    pub fn popup(&self, shared: &SharedEditorState) -> Option<ListPopup> {
+        if let Some(picker) = &self.quickfix_picker {
+            return Some(picker.popup(&shared.quickfix));
+        }
+        if let Some(picker) = &self.tag_picker {
+            return Some(picker.popup());
+        }
+        self.picker.as_ref().map(|picker| picker.popup(&shared.session_controller))
+    }
 }
 
 impl ModeController for CommandController {
     fn handle_key(&mut self, key_event: KeyEvent, shared: &mut SharedEditorState) -> ModeTransition {
+        if let Some(confirm) = &mut self.confirm {
+            let asking = match key_event.code {
+                KeyCode::Char(answer) => confirm.handle_key(answer, shared),
+                KeyCode::Esc => {
+                    confirm.cancel(shared);
+                    false
+                }
+                _ => true,
+            };
+            if asking {
+                return ModeTransition::Stay;
+            }
+            self.confirm = None;
+            return ModeTransition::ToMode(Mode::Normal);
+        }
+
+        if let Some(reload) = &mut self.reload {
+            let asking = match key_event.code {
+                KeyCode::Char(answer) => reload.handle_key(answer, shared),
+                KeyCode::Esc => {
+                    reload.cancel(shared);
+                    false
+                }
+                _ => true,
+            };
+            if asking {
+                return ModeTransition::Stay;
+            }
+            self.reload = None;
+            return ModeTransition::ToMode(Mode::Normal);
+        }
+
+        if let Some(quit_prompt) = &mut self.quit_prompt {
+            let answer = match key_event.code {
+                KeyCode::Char(answer) => quit_prompt.handle_key(answer, shared),
+                KeyCode::Esc => QuitAnswer::Cancel,
+                _ => QuitAnswer::Asking,
+            };
+            return match answer {
+                QuitAnswer::Asking => ModeTransition::Stay,
+                QuitAnswer::Quit => ModeTransition::Quit,
+                QuitAnswer::Cancel => {
+                    self.quit_prompt = None;
+                    ModeTransition::ToMode(Mode::Normal)
+                }
+            };
+        }
+
+        if let Some(dialog) = &self.dialog {
+            let answer = match key_event.code {
+                KeyCode::Char(answer) => dialog.handle_key(answer, shared),
+                KeyCode::Esc => DialogAnswer::Cancel,
+                _ => DialogAnswer::Asking,
+            };
+            return match (answer, self.dialog.take()) {
+                (DialogAnswer::Asking, dialog) => {
+                    self.dialog = dialog;
+                    ModeTransition::Stay
+                }
+                (DialogAnswer::Proceed, Some(dialog)) => {
+                    self.confirmed = true;
+                    let transition = self.run_line(&dialog.command_line, shared);
+                    self.confirmed = false;
+                    transition
+                }
+                _ => ModeTransition::ToMode(Mode::Normal),
+            };
+        }
+
+        if let Some(picker) = &mut self.picker {
+            if picker.handle_key(key_event.code, shared) {
+                return ModeTransition::Stay;
+            }
+            self.picker = None;
+            return ModeTransition::ToMode(Mode::Normal);
+        }
+
+        if let Some(picker) = &mut self.quickfix_picker {
+            if picker.handle_key(key_event.code, shared) {
+                return ModeTransition::Stay;
+            }
+            self.quickfix_picker = None;
+            return ModeTransition::ToMode(Mode::Normal);
+        }
+
+        if let Some(picker) = &mut self.tag_picker {
+            if picker.handle_key(key_event.code, shared) {
+                return ModeTransition::Stay;
+            }
+            self.tag_picker = None;
+            return ModeTransition::ToMode(Mode::Normal);
+        }
+
+        if self.pending_register_insert {
+            self.pending_register_insert = false;
+            if let KeyCode::Char(register) = key_event.code {
+                self.insert_register(register, shared);
+                return ModeTransition::Stay;
+            }
+        }
+
+        if !matches!(key_event.code, KeyCode::Up | KeyCode::Down) {
+            self.history.reset();
+        }
+
         match key_event.code {
+            KeyCode::Char('r') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_register_insert = true;
+                ModeTransition::Stay
+            }
+            KeyCode::Up => {
+                let entries = shared.history.entries(HistoryKind::Command);
+                if let Some(line) = self.history.older(entries, &self.command_buffer) {
+                    self.command_buffer = line;
+                }
+                ModeTransition::Stay
+            }
+            KeyCode::Down => {
+                if let Some(line) = self.history.newer(shared.history.entries(HistoryKind::Command)) {
+                    self.command_buffer = line;
+                }
+                ModeTransition::Stay
+            }
             KeyCode::Char(c) => {
                 self.command_buffer.push(c);
                 ModeTransition::Stay
@@ -32,18 +396,17 @@ impl ModeController for CommandController {
             KeyCode::Enter => {
                 // Execute the command
                 let command_str = self.command_buffer.clone();
-                let quit = self.execute_command(&command_str, shared);
-                self.command_buffer.clear();
-                
-                if quit {
-                    ModeTransition::Quit
-                } else {
-                    ModeTransition::ToMode(Mode::Normal)
+                if !command_str.trim().is_empty() {
+                    shared.register_manager.set_last_command(command_str.trim());
+                    shared.history.add(HistoryKind::Command, command_str.trim());
                 }
+                self.command_buffer.clear();
+                self.run_line(&command_str, shared)
             }
             KeyCode::Esc => {
                 // Cancel command mode
                 self.command_buffer.clear();
+                self.pending_register_insert = false;
                 ModeTransition::ToMode(Mode::Normal)
             }
             _ => ModeTransition::Stay,
@@ -52,6 +415,7 @@ impl ModeController for CommandController {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum Range {
     CurrentLine,                    // . (implicit)
     AllLines,                      // %
@@ -68,15 +432,73 @@ enum Range {
 #[derive(Debug)]
 struct ParsedCommand {
     range: Option<Range>,
-    command: String,
+    command: String,  // Full name for built-in commands (:s is "substitute")
     args: Vec<String>,
+    argument: String, // Everything after the command name, spaces kept
+}
+
+/// Commands whose argument names files (or is a shell command), where %
+/// and # are the current and alternate file names
+const FILE_NAME_COMMANDS: &[&str] = &[
+    "!", "args", "argadd", "badd", "diffsplit", "edit", "grep", "mkdir", "mksession", "next", "read", "source",
+    "split", "vsplit", "write", "wq", "xit",
+];
+
+/// Commands taking several files, where a wildcard stands for the files it matches
+const FILE_LIST_COMMANDS: &[&str] = &["args", "argadd", "badd", "edit", "next"];
+
+/// Commands that change the current buffer's text, which the help refuses
+/// (:! too, when it filters a range)
+const CHANGE_COMMANDS: &[&str] = &[
+    "&", "&&", "ascii", "change", "copy", "delete", "detab", "diffget", "diffput", "format", "move", "normalize",
+    "read", "read!", "recover", "retab", "retab!", "reverse", "sort", "sort!", "substitute", "trim", "uniq", "Wrap",
+];
+
+/// `text` with unescaped % and # replaced by the current and alternate
+/// file names, each followed by any of the modifiers :p (full path),
+/// :h (directory), :t (last part), :r (without extension) and :e (extension)
+fn expand_file_names(text: &str, current: Option<&str>, alternate: Option<&str>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+        let name = match ch {
+            '\\' if rest.starts_with(['%', '#']) => {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[1..];
+                continue;
+            }
+            '%' => current.ok_or("No file name to substitute for '%'")?,
+            '#' => alternate.ok_or("No alternate file name to substitute for '#'")?,
+            _ => {
+                expanded.push(ch);
+                continue;
+            }
+        };
+        let mut name = std::path::PathBuf::from(name);
+        while let Some(modifier) = rest.strip_prefix(':').and_then(|after| after.chars().next()).filter(|c| "phtre".contains(*c)) {
+            rest = &rest[2..];
+            name = match modifier {
+                'p' => std::path::absolute(&name).unwrap_or(name),
+                'h' => match name.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                    _ => std::path::PathBuf::from("."),
+                },
+                't' => name.file_name().map(std::path::PathBuf::from).unwrap_or(name),
+                'r' => name.with_extension(""),
+                _ => name.extension().map(std::path::PathBuf::from).unwrap_or_default(),
+            };
+        }
+        expanded.push_str(&name.to_string_lossy());
+    }
+    Ok(expanded)
 }
 
 #[derive(Debug)]
-struct SubstitutePattern {
-    old: String,
-    new: String,
-    global: bool,
+struct GlobalCommand {
+    invert: bool,    // :v / :g! - run on lines that do NOT match
+    pattern: String,
+    command: String, // Ex command run on each line; empty means print
 }
 
 impl CommandController {
@@ -88,26 +510,94 @@ impl CommandController {
         }
         
         // Parse command with range support
-        let parsed = self.parse_command_with_range(trimmed);
+        let mut parsed = self.parse_command_with_range(trimmed);
+
+        // % and # name the current and alternate files, and :e and :args
+        // take wildcards
+        shared.note_buffer_switch();
+        if let Err(message) = Self::expand_file_arguments(&mut parsed, shared) {
+            self.report_error(shared, message);
+            return false;
+        }
+
+        let command = parsed.command.as_str();
+        if (CHANGE_COMMANDS.contains(&command) || (command == "!" && parsed.range.is_some()))
+            && shared.session_controller.current_document().help
+        {
+            self.report_error(shared, NOT_MODIFIABLE.to_string());
+            return false;
+        }
+
+        // 'confirm' asks before :q or :e leave unsaved changes and before
+        // :w name writes over a file
+        if let Some((kind, path)) = self.needs_confirmation(&parsed, shared) {
+            self.dialog = Some(ConfirmDialog::new(shared, kind, trimmed.to_string(), path.as_deref()));
+            return false;
+        }
+        
+        // :g/pattern/cmd and :v/pattern/cmd run another command on each matching line
+        if let Some(global) = self.parse_global_command(&parsed) {
+            return self.execute_global_command(parsed.range.as_ref(), &global, shared);
+        }
+
+        // :normal keys are typed by the editor, on each line of the range
+        if matches!(parsed.command.as_str(), "normal" | "normal!") {
+            let lines = match &parsed.range {
+                Some(range) => {
+                    let (start, end) = self.resolve_range(range, shared);
+                    (start..=end).collect()
+                }
+                None => Vec::new(),
+            };
+            Self::queue_normal(&parsed.argument, lines, parsed.command == "normal", shared);
+            return false;
+        }
         
         // Handle commands that don't use ranges first
         if parsed.range.is_none() {
             // Handle buffer commands
-            if let Some(result) = self.execute_buffer_command(&parsed.command, shared) {
+            if let Some(result) = self.execute_buffer_command(&parsed, shared) {
+                return result;
+            }
+            
+            // Handle :grep and the quickfix list
+            if let Some(result) = self.execute_quickfix_command(&parsed, shared) {
                 return result;
             }
             
+            // Handle :args, :next, :prev and the rest of the argument list
+            if let Some(result) = self.execute_arg_command(&parsed, shared) {
+                return result;
+            }
+            
+            // Handle :tag, :tselect and :pop
+            if let Some(result) = self.execute_tag_command(&parsed, shared) {
+                return result;
+            }
+            
+            // Handle :lsp
+            if parsed.command == "lsp" {
+                if let Err(message) = LspCommands::configure(shared, &parsed.argument) {
+                    self.report_error(shared, message);
+                }
+                return false;
+            }
+
             // Handle file commands
             if let Some(result) = self.execute_file_command_parsed(&parsed, shared) {
                 return result;
             }
             
             // Handle setting commands
-            if parsed.command == "set" && !parsed.args.is_empty() {
-                let setting_command = format!("set {}", parsed.args.join(" "));
-                if let Some(result) = self.execute_setting_command(&setting_command, shared) {
-                    return result;
+            // Several options may share a line: set sw=2 sr
+            if matches!(parsed.command.as_str(), "set" | "setlocal") && !parsed.args.is_empty() {
+                for setting in split_settings(parsed.argument.split_whitespace()) {
+                    if self.execute_setting_command(&format!("set {}", setting), shared).is_none() {
+                        self.report_error(shared, format!("Unknown option: {}", setting));
+                        break;
+                    }
                 }
+                return false;
             }
             
             // Handle mark management commands
@@ -115,17 +605,15 @@ impl CommandController {
                 return result;
             }
             
-            // Handle utility commands
-            if let Some(result) = self.execute_utility_command(&parsed.command, shared) {
+            // Handle utility commands (:e and :badd need their filename arguments, :help its subject)
+            let utility_input = if matches!(parsed.command.as_str(), "edit" | "badd" | "help") {
+                format!("{} {}", parsed.command, parsed.argument)
+            } else {
+                parsed.command.clone()
+            };
+            if let Some(result) = self.execute_utility_command(utility_input.trim_end(), shared) {
                 return result;
             }
-            
-            // Handle substitute commands without range
-            if parsed.command.starts_with("s") && parsed.command.contains("/") {
-                if let Some(result) = self.execute_search_replace_command(trimmed, shared) {
-                    return result;
-                }
-            }
         }
         
         // Handle range-based commands
@@ -140,8 +628,8 @@ impl CommandController {
         }
         
         // Fallback to old string-based handling
-        let result = self.execute_misc_command(trimmed, shared);
-        result
+        
+        self.execute_misc_command(trimmed, shared)
     }
     
     fn parse_command_with_range(&self, input: &str) -> ParsedCommand {
@@ -153,6 +641,11 @@ impl CommandController {
         let mut in_range = true;
         while let Some(&ch) = chars.peek() {
             match ch {
+                // A mark ('a, '<) takes the character after the quote with it
+                '\'' if in_range => {
+                    range_str.push(chars.next().unwrap());
+                    range_str.extend(chars.next());
+                }
                 '0'..='9' | ',' | '%' | '$' | '.' | '+' | '-' | '\'' | '/' => {
                     if in_range {
                         range_str.push(chars.next().unwrap());
@@ -185,27 +678,78 @@ impl CommandController {
             self.parse_range(&range_str)
         };
         
-        // Split command and args
-        let (command, args) = if command_str.starts_with('s') && command_str.len() > 1 && !command_str.chars().nth(1).unwrap().is_whitespace() && command_str.chars().nth(1) == Some('/') {
-            // Handle substitute command: s/old/new/flags
-            ("s".to_string(), vec![command_str[1..].to_string()])
-        } else {
-            // Handle normal whitespace-separated commands
-            let parts: Vec<&str> = command_str.split_whitespace().collect();
-            let command = parts.first().unwrap_or(&"").to_string();
-            let args = parts.iter().skip(1).map(|s| s.to_string()).collect();
-            (command, args)
+        // Split off the command name, expanding abbreviations (:sub, :setl);
+        // :!cmd needs no space after the !
+        let (command, argument) = match ExCommands::split_name(&command_str) {
+            _ if command_str.starts_with('!') => ("!".to_string(), command_str[1..].trim()),
+            Some((command, rest)) => (command, rest.trim()),
+            None => match command_str.split_once(char::is_whitespace) {
+                Some((command, rest)) => (command.to_string(), rest.trim()),
+                None => (command_str.clone(), ""),
+            },
+        };
+        let args = match command.as_str() {
+            // s/old/new/flags - the pattern may contain spaces
+            "substitute" if !argument.is_empty() => vec![argument.to_string()],
+            _ => argument.split_whitespace().map(str::to_string).collect(),
         };
         
-        // Debug output for testing
-        if input.contains("/") || input.contains("newfile") {
-            println!("DEBUG: input='{}', final_command='{}', final_args={:?}", 
-                input, command, args);
-        }
-        
-        ParsedCommand { range, command, args }
+        ParsedCommand { range, command, args, argument: argument.to_string() }
     }
     
+    /// Put file names in for % and # and the files wildcards match, for the
+    /// commands that take file names; others keep their argument as typed
+    fn expand_file_arguments(parsed: &mut ParsedCommand, shared: &SharedEditorState) -> Result<(), String> {
+        let command = parsed.command.trim_end_matches('!');
+        let command = if command.is_empty() { "!" } else { command };
+        if !FILE_NAME_COMMANDS.contains(&command) {
+            return Ok(());
+        }
+        let session = &shared.session_controller;
+        let current = session.current_document().filename.as_ref().map(|path| path.display().to_string());
+        let alternate = session.alternate.as_ref().map(|path| path.display().to_string());
+        let mut argument = expand_file_names(&parsed.argument, current.as_deref(), alternate.as_deref())?;
+
+        if FILE_LIST_COMMANDS.contains(&command) {
+            let mut words = Vec::new();
+            for word in argument.split_whitespace() {
+                // +N places the cursor, it isn't a file
+                if glob::is_pattern(word) && !word.starts_with('+') {
+                    words.extend(glob::expand(word)?.iter().map(|path| path.display().to_string()));
+                } else {
+                    words.push(word.to_string());
+                }
+            }
+            argument = words.join(" ");
+        }
+        if argument != parsed.argument {
+            parsed.args = argument.split_whitespace().map(str::to_string).collect();
+            parsed.argument = argument;
+        }
+        Ok(())
+    }
+
+    /// With 'confirm', the question to ask before running `parsed`: :q
+    /// closing the last window and :e file leaving a modified buffer, and
+    /// :w name onto another existing file (with its path)
+    fn needs_confirmation(&self, parsed: &ParsedCommand, shared: &SharedEditorState) -> Option<(DialogKind, Option<PathBuf>)> {
+        let session = &shared.session_controller;
+        if !session.confirm || self.confirmed {
+            return None;
+        }
+        let doc = session.current_document();
+        match parsed.command.as_str() {
+            "quit" if doc.is_modified() && !shared.view.windows().is_split() => Some((DialogKind::SaveChanges, None)),
+            "edit" if doc.is_modified() && !parsed.argument.is_empty() => Some((DialogKind::SaveChanges, None)),
+            "write" if !parsed.argument.starts_with(['>', '!']) => {
+                let path = PathBuf::from(parsed.args.first()?);
+                let own_file = doc.filename.as_ref() == Some(&path);
+                (path.exists() && !own_file).then_some((DialogKind::Overwrite, Some(path)))
+            }
+            _ => None,
+        }
+    }
+
     fn parse_range(&self, range_str: &str) -> Option<Range> {
         if range_str.is_empty() {
             return None;
@@ -241,13 +785,13 @@ impl CommandController {
         if trimmed.starts_with('\'') && trimmed.len() == 2 {
             // Mark reference like 'a
             Some(Range::ToMark(trimmed.chars().nth(1)?))
-        } else if trimmed.starts_with('+') {
+        } else if let Some(stripped) = trimmed.strip_prefix('+') {
             // Relative forward like +5
-            let offset = trimmed[1..].parse::<isize>().ok()?;
+            let offset = stripped.parse::<isize>().ok()?;
             Some(Range::FromCurrent(offset))
-        } else if trimmed.starts_with('-') {
+        } else if let Some(stripped) = trimmed.strip_prefix('-') {
             // Relative backward like -3
-            let offset = trimmed[1..].parse::<isize>().ok()?;
+            let offset = stripped.parse::<isize>().ok()?;
             Some(Range::FromCurrent(-offset))
         } else if trimmed.starts_with('/') && trimmed.ends_with('/') && trimmed.len() > 2 {
             // Search pattern like /pattern/
@@ -262,31 +806,38 @@ impl CommandController {
     }
     
     fn execute_range_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
-        // For substitute commands, default to current line if no range specified
+        // Line commands default to the current line if no range specified
         let default_range;
         let range = if let Some(r) = parsed.range.as_ref() {
             r
-        } else if parsed.command == "s" {
+        } else if matches!(parsed.command.as_str(), "substitute" | "&" | "&&" | "delete" | "yank" | "copy" | "move" | "Wrap") {
             default_range = Range::CurrentLine;
             &default_range
+        } else if matches!(parsed.command.as_str(), "retab" | "retab!" | "detab" | "trim" | "format" | "sort" | "sort!" | "uniq" | "reverse") {
+            default_range = Range::AllLines;
+            &default_range
         } else {
             return None;
         };
         
         match parsed.command.as_str() {
-            "d" | "delete" => {
+            "delete" => {
                 self.execute_delete_range(range, shared);
                 Some(false)
             }
-            "y" | "yank" => {
+            "yank" => {
                 self.execute_yank_range(range, shared);
                 Some(false)
             }
-            "p" | "print" => {
+            "print" => {
                 self.execute_print_range(range, shared);
                 Some(false)
             }
-            "s" => {
+            "write" | "write!" => {
+                self.write_lines(Some(range), parsed, shared);
+                Some(false)
+            }
+            "substitute" => {
                 // Handle substitute with range
                 if !parsed.args.is_empty() {
                     let substitute_pattern = parsed.args.join(" ");
@@ -296,11 +847,31 @@ impl CommandController {
                     None
                 }
             }
-            "c" | "change" => {
+            "&" | "&&" => {
+                self.execute_repeat_substitute(range, parsed.command == "&&", shared);
+                Some(false)
+            }
+            "change" => {
                 self.execute_change_range(range, shared);
                 Some(false)
             }
-            "co" | "copy" => {
+            "Wrap" => {
+                // Optional width argument overrides 'textwidth'
+                let width = match parsed.args.first().map(|w| w.parse::<usize>()) {
+                    Some(Ok(width)) if width > 0 => width,
+                    Some(_) => {
+                        shared.status_message = "Invalid wrap width".to_string();
+                        return Some(false);
+                    }
+                    None => match shared.session_controller.current_document().text_width {
+                        0 => crate::document_model::reflow::DEFAULT_WRAP_WIDTH,
+                        text_width => text_width,
+                    },
+                };
+                self.execute_wrap_range(range, width, shared);
+                Some(false)
+            }
+            "copy" => {
                 if let Some(target) = parsed.args.first() {
                     if let Ok(target_line) = target.parse::<usize>() {
                         self.execute_copy_range(range, target_line, shared);
@@ -314,7 +885,7 @@ impl CommandController {
                     Some(false)
                 }
             }
-            "m" | "move" => {
+            "move" => {
                 if let Some(target) = parsed.args.first() {
                     if let Ok(target_line) = target.parse::<usize>() {
                         self.execute_move_range(range, target_line, shared);
@@ -328,11 +899,71 @@ impl CommandController {
                     Some(false)
                 }
             }
+            "retab" | "retab!" | "detab" => {
+                // Optional new tabstop: :retab 2
+                let new_tab_stop = match parsed.args.first().map(|ts| ts.parse::<usize>()) {
+                    Some(Ok(tab_stop)) if (1..=16).contains(&tab_stop) => tab_stop,
+                    Some(_) => {
+                        self.report_error(shared, "Tab width must be between 1 and 16".to_string());
+                        return Some(false);
+                    }
+                    None => shared.view.get_tab_stop(),
+                };
+                let retab = Retab {
+                    tab_stop: shared.view.get_tab_stop(),
+                    new_tab_stop,
+                    use_tabs: parsed.command != "detab",
+                    whole_line: parsed.command == "retab!",
+                };
+                self.execute_retab_range(range, &retab, shared);
+                Some(false)
+            }
+            "trim" => {
+                self.execute_trim_range(range, shared);
+                Some(false)
+            }
+            "format" => {
+                self.execute_format_range(range, shared);
+                Some(false)
+            }
+            "sort" | "sort!" | "uniq" => {
+                // Flags: u (unique), n (numeric), i (ignore case)
+                let sort = match LineSort::from_flags(&parsed.args.concat(), parsed.command == "sort!") {
+                    Ok(sort) => sort,
+                    Err(flag) => {
+                        self.report_error(shared, format!("Invalid argument: {flag}"));
+                        return Some(false);
+                    }
+                };
+                if parsed.command == "uniq" {
+                    let (before, after) = self.execute_reorder_range(range, |lines| sort.uniq(lines), shared);
+                    shared.status_message = match before - after {
+                        1 => "1 duplicate line removed".to_string(),
+                        removed => format!("{removed} duplicate lines removed"),
+                    };
+                } else {
+                    let (before, after) = self.execute_reorder_range(range, |lines| sort.sort(lines), shared);
+                    shared.status_message = match before - after {
+                        0 => format!("{before} lines sorted"),
+                        removed => format!("{before} lines sorted, {removed} duplicates removed"),
+                    };
+                }
+                Some(false)
+            }
+            "reverse" => {
+                let (count, _) = self.execute_reorder_range(range, |lines| lines.into_iter().rev().collect(), shared);
+                shared.status_message = format!("{count} lines reversed");
+                Some(false)
+            }
+            "!" => {
+                self.execute_filter_range(range, &parsed.argument, shared);
+                Some(false)
+            }
             "#" => {
                 self.execute_print_range_with_numbers(range, shared);
                 Some(false)
             }
-            "l" | "list" => {
+            "list" => {
                 self.execute_list_range(range, shared);
                 Some(false)
             }
@@ -373,34 +1004,13 @@ impl CommandController {
                 (target, target)
             }
             Range::ToMark(mark) => {
-                if let Some((line, _col)) = doc.get_local_mark(*mark) {
-                    (line, line)
-                } else if let Some(global_mark) = shared.mark_manager.get_global_mark(*mark) {
-                    let line = global_mark.line.min(last_line);
-                    (line, line)
-                } else {
-                    (current_line, current_line) // Mark not found, use current line
-                }
+                // Mark not found, use current line
+                let line = shared.mark_position(*mark).map_or(current_line, |(line, _)| line);
+                (line, line)
             }
             Range::MarkRange(start_mark, end_mark) => {
-                let start_line = if let Some((line, _)) = doc.get_local_mark(*start_mark) {
-                    line
-                } else if let Some(mark) = shared.mark_manager.get_global_mark(*start_mark) {
-                    mark.line
-                } else {
-                    current_line
-                };
-                
-                let end_line = if let Some((line, _)) = doc.get_local_mark(*end_mark) {
-                    line
-                } else if let Some(mark) = shared.mark_manager.get_global_mark(*end_mark) {
-                    mark.line
-                } else {
-                    current_line
-                };
-                
-                let start = start_line.min(last_line);
-                let end = end_line.min(last_line);
+                let start = shared.mark_position(*start_mark).map_or(current_line, |(line, _)| line);
+                let end = shared.mark_position(*end_mark).map_or(current_line, |(line, _)| line);
                 (start.min(end), start.max(end))
             }
             Range::SearchPattern(_pattern) => {
@@ -414,96 +1024,250 @@ impl CommandController {
         let (start_line, end_line) = self.resolve_range(range, shared);
         
         let doc = shared.session_controller.current_document_mut();
+        if start_line >= doc.line_count() {
+            shared.status_message = "No lines to delete".to_string();
+            return;
+        }
         let cursor_pos = (doc.cursor_line(), doc.cursor_column());
         doc.undo_manager_mut().start_group(cursor_pos);
-        
-        // Delete lines from end to start to maintain line numbers
-        for line_num in (start_line..=end_line).rev() {
-            if line_num < doc.line_count() {
-                doc.delete_line_at(line_num);
-            }
-        }
+        // As in vim, the cursor goes to the first non-blank of the line
+        // after the ones deleted (or the last line)
+        let deleted = doc.delete_lines_with_undo(start_line, end_line);
         
         let cursor_pos = (doc.cursor_line(), doc.cursor_column());
         doc.undo_manager_mut().end_group(cursor_pos);
         
+        shared.register_manager.store_delete(None, deleted, RegisterType::Line);
         let deleted_count = end_line.saturating_sub(start_line) + 1;
         shared.status_message = format!("{} lines deleted", deleted_count);
     }
     
-    fn execute_yank_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
+    fn execute_wrap_range(&mut self, range: &Range, width: usize, shared: &mut SharedEditorState) {
         let (start_line, end_line) = self.resolve_range(range, shared);
         
-        let doc = shared.session_controller.current_document();
-        let mut yanked_text = String::new();
+        let tab_stop = shared.view.get_tab_stop();
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
         
-        for line_num in start_line..=end_line {
-            if line_num < doc.line_count() {
-                if let Some(line) = doc.get_line(line_num) {
-                    yanked_text.push_str(&line);
-                    if line_num < end_line {
-                        yanked_text.push('\n');
-                    }
-                }
-            }
+        let added = doc.wrap_lines_with_undo(start_line, end_line, width, tab_stop);
+        if added > 0 {
+            doc.move_cursor_to(end_line + added, 0);
         }
         
-        if !yanked_text.is_empty() {
-            shared.register_manager.store_in_register(Some('"'), yanked_text, crate::document_model::RegisterType::Line);
-            let yanked_count = end_line.saturating_sub(start_line) + 1;
-            shared.status_message = format!("{} lines yanked", yanked_count);
-        }
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        
+        shared.status_message = if added > 0 {
+            format!("Wrapped to {} columns, {} more lines", width, added)
+        } else {
+            format!("No lines longer than {} columns", width)
+        };
     }
     
-    fn execute_print_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
+    fn execute_retab_range(&mut self, range: &Range, retab: &Retab, shared: &mut SharedEditorState) {
         let (start_line, end_line) = self.resolve_range(range, shared);
         
-        let doc = shared.session_controller.current_document();
-        let mut preview_content = Vec::new();
-        let mut line_count = 0;
-        
-        // Collect the lines to print
-        for line_num in start_line..=end_line {
-            if line_num < doc.line_count() {
-                if let Some(line) = doc.get_line(line_num) {
-                    preview_content.push(format!("{:4}: {}", line_num + 1, line));
-                    line_count += 1;
-                }
-            }
-        }
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let changed = doc.retab_lines_with_undo(start_line, end_line, retab);
+        let _ = doc.set_cursor(cursor_pos.0, cursor_pos.1);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
         
-        if line_count == 0 {
-            shared.status_message = "No lines to print".to_string();
-        } else {
-            // Create a preview buffer with the printed content
-            let preview_text = preview_content.join("\n");
-            let buffer_name = format!("[Print Range {}..{}]", start_line + 1, end_line + 1);
-            
-            match shared.session_controller.create_preview_buffer(buffer_name, preview_text) {
-                Ok(_) => {
-                    shared.status_message = format!("{} lines printed in preview buffer", line_count);
-                }
-                Err(e) => {
-                    shared.status_message = format!("Error creating preview: {}", e);
-                }
-            }
-        }
+        // Like vim, a new tabstop becomes the tabstop
+        shared.view.set_tab_stop(retab.new_tab_stop);
+        shared.status_message = match changed {
+            1 => "1 line retabbed".to_string(),
+            _ => format!("{} lines retabbed", changed),
+        };
     }
     
-    fn execute_print_range_with_numbers(&mut self, range: &Range, shared: &mut SharedEditorState) {
+    /// :[range]trim - strip trailing spaces and tabs, as one undo step
+    fn execute_trim_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
+        let (start_line, end_line) = self.resolve_range(range, shared);
+
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let changed = doc.trim_trailing_with_undo(start_line, end_line);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+
+        shared.status_message = match changed {
+            1 => "1 line trimmed".to_string(),
+            _ => format!("{} lines trimmed", changed),
+        };
+    }
+
+    /// :{range}format - pipe the lines through 'formatprg' and put back
+    /// the ones it changed, as one undo step
+    fn execute_format_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
+        let (start_line, end_line) = self.resolve_range(range, shared);
+
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let changed = doc.run_formatter_with_undo(start_line, end_line);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+
+        match changed {
+            Ok(0) => shared.status_message = "Already formatted".to_string(),
+            Ok(1) => shared.status_message = "1 line formatted".to_string(),
+            Ok(changed) => shared.status_message = format!("{} lines formatted", changed),
+            Err(message) => self.report_error(shared, message),
+        }
+    }
+
+    /// :{range}!cmd - pipe the lines through a shell command and replace
+    /// them with its output, as one undo step
+    fn execute_filter_range(&mut self, range: &Range, command_str: &str, shared: &mut SharedEditorState) {
+        if command_str.is_empty() {
+            self.report_error(shared, "No shell command given".to_string());
+            return;
+        }
+        let (start_line, end_line) = self.resolve_range(range, shared);
+        let doc = shared.session_controller.current_document();
+        let lines: Vec<String> = (start_line..=end_line).filter_map(|line| doc.get_line(line)).collect();
+        let input = format!("{}\n", lines.join("\n"));
+
+        let output = match shell::filter(command_str, input.into_bytes()) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(shared, format!("Failed to execute command: {}", e));
+                return;
+            }
+        };
+        // A failing command that printed nothing leaves the text alone
+        if !output.status.success() && output.stdout.is_empty() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            self.report_error(shared, format!("Command failed ({}): {}", output.status, stderr.trim()));
+            return;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let filtered: Vec<String> = match stdout.strip_suffix('\n') {
+            Some(text) => text.split('\n').map(str::to_string).collect(),
+            None if stdout.is_empty() => Vec::new(),
+            None => stdout.split('\n').map(str::to_string).collect(),
+        };
+        let filtered = if filtered.is_empty() && lines.len() >= doc.line_count() { vec![String::new()] } else { filtered };
+
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        doc.replace_lines_with_undo(start_line, lines.len(), &filtered);
+        let _ = doc.set_cursor(start_line.min(doc.line_count().saturating_sub(1)), 0);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+
+        shared.status_message = match lines.len() {
+            1 => "1 line filtered".to_string(),
+            count => format!("{count} lines filtered"),
+        };
+    }
+
+    /// Replace the lines in `range` with `reorder` applied to them, as one
+    /// undo step, and put the cursor on the first of them. Returns the line
+    /// counts before and after.
+    fn execute_reorder_range(
+        &mut self,
+        range: &Range,
+        reorder: impl FnOnce(Vec<String>) -> Vec<String>,
+        shared: &mut SharedEditorState,
+    ) -> (usize, usize) {
+        let (start_line, end_line) = self.resolve_range(range, shared);
+        
+        let doc = shared.session_controller.current_document_mut();
+        let lines: Vec<String> = (start_line..=end_line).filter_map(|line| doc.get_line(line)).collect();
+        let count = lines.len();
+        let reordered = reorder(lines);
+        
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        doc.replace_lines_with_undo(start_line, count, &reordered);
+        let _ = doc.set_cursor(start_line, 0);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        
+        (count, reordered.len())
+    }
+    
+    fn execute_yank_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
+        let (start_line, end_line) = self.resolve_range(range, shared);
+        
+        let doc = shared.session_controller.current_document();
+        let mut yanked_text = String::new();
+        
+        for line_num in start_line..=end_line {
+            if line_num < doc.line_count()
+                && let Some(line) = doc.get_line(line_num)
+            {
+                yanked_text.push_str(&line);
+                if line_num < end_line {
+                    yanked_text.push('\n');
+                }
+            }
+        }
+        
+        if !yanked_text.is_empty() {
+            shared.register_manager.store_yank(None, yanked_text, RegisterType::Line);
+            let yanked_count = end_line.saturating_sub(start_line) + 1;
+            shared.status_message = format!("{} lines yanked", yanked_count);
+        }
+    }
+    
+    fn execute_print_range(&mut self, range: &Range, shared: &mut SharedEditorState) {
         let (start_line, end_line) = self.resolve_range(range, shared);
         
         let doc = shared.session_controller.current_document();
         let mut preview_content = Vec::new();
         let mut line_count = 0;
         
-        // Collect the lines with line numbers
+        // Collect the lines to print
         for line_num in start_line..=end_line {
-            if line_num < doc.line_count() {
-                if let Some(line) = doc.get_line(line_num) {
-                    preview_content.push(format!("{:4}: {}", line_num + 1, line));
-                    line_count += 1;
+            if line_num < doc.line_count()
+                && let Some(line) = doc.get_line(line_num)
+            {
+                preview_content.push(format!("{:4}: {}", line_num + 1, line));
+                line_count += 1;
+            }
+        }
+        
+        if line_count == 0 {
+            shared.status_message = "No lines to print".to_string();
+        } else {
+            // Create a preview buffer with the printed content
+            let preview_text = preview_content.join("\n");
+            let buffer_name = format!("[Print Range {}..{}]", start_line + 1, end_line + 1);
+            
+            match shared.session_controller.create_preview_buffer(buffer_name, preview_text) {
+                Ok(_) => {
+                    shared.status_message = format!("{} lines printed in preview buffer", line_count);
                 }
+                Err(e) => {
+                    self.report_error(shared, format!("Error creating preview: {}", e));
+                }
+            }
+        }
+    }
+    
+    fn execute_print_range_with_numbers(&mut self, range: &Range, shared: &mut SharedEditorState) {
+        let (start_line, end_line) = self.resolve_range(range, shared);
+        
+        let doc = shared.session_controller.current_document();
+        let mut preview_content = Vec::new();
+        let mut line_count = 0;
+        
+        // Collect the lines with line numbers
+        for line_num in start_line..=end_line {
+            if line_num < doc.line_count()
+                && let Some(line) = doc.get_line(line_num)
+            {
+                preview_content.push(format!("{:4}: {}", line_num + 1, line));
+                line_count += 1;
             }
         }
         
@@ -519,7 +1283,7 @@ impl CommandController {
                     shared.status_message = format!("{} lines printed with numbers in preview buffer", line_count);
                 }
                 Err(e) => {
-                    shared.status_message = format!("Error creating preview: {}", e);
+                    self.report_error(shared, format!("Error creating preview: {}", e));
                 }
             }
         }
@@ -534,15 +1298,15 @@ impl CommandController {
         
         // Collect the lines with visible whitespace
         for line_num in start_line..=end_line {
-            if line_num < doc.line_count() {
-                if let Some(line) = doc.get_line(line_num) {
-                    // Show whitespace characters and line endings
-                    let visible_line = line
-                        .replace('\t', "^I")
-                        .replace(' ', "·");
-                    preview_content.push(format!("{:4}: {}$", line_num + 1, visible_line));
-                    line_count += 1;
-                }
+            if line_num < doc.line_count()
+                && let Some(line) = doc.get_line(line_num)
+            {
+                // Show whitespace characters and line endings
+                let visible_line = line
+                    .replace('\t', "^I")
+                    .replace(' ', "·");
+                preview_content.push(format!("{:4}: {}$", line_num + 1, visible_line));
+                line_count += 1;
             }
         }
         
@@ -558,7 +1322,7 @@ impl CommandController {
                     shared.status_message = format!("{} lines listed with whitespace in preview buffer", line_count);
                 }
                 Err(e) => {
-                    shared.status_message = format!("Error creating preview: {}", e);
+                    self.report_error(shared, format!("Error creating preview: {}", e));
                 }
             }
         }
@@ -577,26 +1341,35 @@ impl CommandController {
         let mut copied_lines = Vec::new();
         
         for line_num in start_line..=end_line {
-            if line_num < doc.line_count() {
-                if let Some(line) = doc.get_line(line_num) {
-                    copied_lines.push(line);
-                }
+            if line_num < doc.line_count()
+                && let Some(line) = doc.get_line(line_num)
+            {
+                copied_lines.push(line);
             }
         }
         
         let doc = shared.session_controller.current_document_mut();
         let target = target_line.min(doc.line_count());
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
         
         for (i, line) in copied_lines.iter().enumerate() {
-            doc.insert_line_at(target + i, line);
+            doc.insert_line_with_undo(target + i, line);
         }
         
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        
         let copied_count = copied_lines.len();
         shared.status_message = format!("{} lines copied", copied_count);
     }
     
     fn execute_move_range(&mut self, range: &Range, target_line: usize, shared: &mut SharedEditorState) {
-        // Move is copy + delete (but need to handle line number shifts)
+        // Move is copy + delete (but need to handle line number shifts), undone as one step
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().begin_batch(cursor_pos);
+        
         self.execute_copy_range(range, target_line, shared);
         
         // Adjust range if target is before start
@@ -610,116 +1383,294 @@ impl CommandController {
         
         self.execute_delete_range(&adjusted_range, shared);
         
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_batch(cursor_pos);
+        
         let moved_count = end_line.saturating_sub(start_line) + 1;
         shared.status_message = format!("{} lines moved", moved_count);
     }
     
     fn execute_substitute_range(&mut self, range: &Range, pattern: &str, shared: &mut SharedEditorState) {
+        match Substitution::parse(pattern) {
+            Ok(substitution) => self.run_substitution(range, &substitution, shared),
+            Err(e) => self.report_error(shared, e),
+        }
+    }
+
+    /// :& repeats the last substitution without its flags, :&& keeps them
+    fn execute_repeat_substitute(&mut self, range: &Range, keep_flags: bool, shared: &mut SharedEditorState) {
+        let Some(last) = shared.last_substitute.clone() else {
+            self.report_error(shared, "No previous substitute".to_string());
+            return;
+        };
+        let substitution = Substitution {
+            flags: if keep_flags { last.flags } else { SubstituteFlags::default() },
+            ..last
+        };
+        self.run_substitution(range, &substitution, shared);
+    }
+
+    fn run_substitution(&mut self, range: &Range, substitution: &Substitution, shared: &mut SharedEditorState) {
         let (start_line, end_line) = self.resolve_range(range, shared);
+        // Under :g a line without a match is normal, as if the e flag were given
+        let mut substitution = substitution.clone();
+        substitution.flags.no_error |= self.running_global;
+        // Over '<,'> only the selected text changes, not all of its lines
+        substitution.in_selection = matches!(range, Range::MarkRange('<', '>'));
+        match substitution.execute(start_line, end_line, shared) {
+            Ok(confirm) => self.confirm = confirm,
+            Err(e) => self.report_error(shared, e),
+        }
+    }
+    
+    /// Recognize :g/pat/cmd, :g!/pat/cmd, :v/pat/cmd (and :global/:vglobal).
+    /// Any non-alphanumeric character can delimit the pattern; \ escapes it.
+    fn parse_global_command(&self, parsed: &ParsedCommand) -> Option<GlobalCommand> {
+        let invert = match parsed.command.as_str() {
+            "global" => false,
+            "global!" | "vglobal" => true,
+            _ => return None,
+        };
         
-        // Debug output
-        println!("DEBUG substitute: pattern='{}', range={}..{}", pattern, start_line, end_line);
+        // The raw argument keeps any spaces in the pattern
+        let mut chars = parsed.argument.chars();
+        let delimiter = chars.next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || matches!(delimiter, '"' | '|' | '\\') {
+            return None;
+        }
         
-        // Parse substitute pattern: s/old/new/flags
-        if let Some(parsed) = self.parse_substitute_pattern(pattern) {
-            println!("DEBUG substitute: parsed old='{}', new='{}', global={}", parsed.old, parsed.new, parsed.global);
-            let doc = shared.session_controller.current_document_mut();
-            let mut replacements = 0;
-            
-            for line_num in start_line..=end_line {
-                if line_num < doc.line_count() {
-                    if let Some(line) = doc.get_line(line_num) {
-                        let new_line = if parsed.global {
-                            line.replace(&parsed.old, &parsed.new)
-                        } else {
-                            line.replacen(&parsed.old, &parsed.new, 1)
-                        };
-                        
-                        if line != new_line {
-                            doc.set_line(line_num, &new_line);
-                            replacements += 1;
-                        }
-                    }
+        let mut pattern = String::new();
+        let mut escaped = false;
+        for ch in chars.by_ref() {
+            if escaped {
+                if ch != delimiter {
+                    pattern.push('\\');
                 }
+                pattern.push(ch);
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == delimiter {
+                break;
+            } else {
+                pattern.push(ch);
             }
-            
-            shared.status_message = format!("{} substitutions made", replacements);
-        } else {
-            shared.status_message = "Invalid substitute pattern".to_string();
         }
+        
+        Some(GlobalCommand {
+            invert,
+            pattern,
+            command: chars.as_str().trim().to_string(),
+        })
     }
     
-    fn parse_substitute_pattern(&self, pattern: &str) -> Option<SubstitutePattern> {
-        if !pattern.starts_with('/') {
-            return None;
+    fn execute_global_command(&mut self, range: Option<&Range>, global: &GlobalCommand, shared: &mut SharedEditorState) -> bool {
+        if self.running_global {
+            shared.status_message = "Cannot do :global recursively".to_string();
+            return false;
+        }
+        if global.pattern.is_empty() {
+            shared.status_message = "Empty :global pattern".to_string();
+            return false;
         }
+        let regex = match regex::Regex::new(&global.pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                shared.status_message = format!("Invalid pattern: {}", e);
+                return false;
+            }
+        };
         
-        let parts: Vec<&str> = pattern[1..].split('/').collect();
-        if parts.len() < 2 {
-            return None;
+        // Default range is the whole file
+        let (start_line, end_line) = match range {
+            Some(range) => self.resolve_range(range, shared),
+            None => (0, shared.session_controller.current_document().line_count().saturating_sub(1)),
+        };
+        
+        // Pass 1: mark every target line before anything changes
+        let doc = shared.session_controller.current_document();
+        let mut targets: Vec<usize> = (start_line..=end_line)
+            .filter(|&line_num| {
+                doc.get_line(line_num)
+                    .is_some_and(|line| regex.is_match(&line) != global.invert)
+            })
+            .collect();
+        
+        if targets.is_empty() {
+            let message = if global.invert {
+                format!("Pattern found in every line: {}", global.pattern)
+            } else {
+                format!("Pattern not found: {}", global.pattern)
+            };
+            self.report_error(shared, message);
+            return false;
         }
         
-        let old = parts[0].to_string();
-        let new = parts[1].to_string();
-        let flags = parts.get(2).unwrap_or(&"").to_string();
-        let global = flags.contains('g');
+        if global.command.is_empty() {
+            let preview: Vec<String> = targets
+                .iter()
+                .filter_map(|&line_num| doc.get_line(line_num).map(|line| format!("{:4}: {}", line_num + 1, line)))
+                .collect();
+            let buffer_name = format!("[Global /{}/]", global.pattern);
+            shared.status_message = match shared.session_controller.create_preview_buffer(buffer_name, preview.join("\n")) {
+                Ok(_) => format!("{} matching lines printed in preview buffer", targets.len()),
+                Err(e) => format!("Error creating preview: {}", e),
+            };
+            return false;
+        }
+        
+        // :g/pattern/normal types its keys on each marked line once this command is done
+        let command = self.parse_command_with_range(&global.command);
+        if matches!(command.command.as_str(), "normal" | "normal!") && command.range.is_none() {
+            Self::queue_normal(&command.argument, targets, command.command == "normal", shared);
+            return false;
+        }
+
+        // Pass 2: run the command on each marked line as a single undo step
+        let doc = shared.session_controller.current_document_mut();
+        let lines_before = doc.line_count();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().begin_batch(cursor_pos);
+        self.running_global = true;
         
-        Some(SubstitutePattern { old, new, global })
+        let mut quit = false;
+        let mut processed = 0;
+        let mut index = 0;
+        while index < targets.len() {
+            let line_num = targets[index];
+            let doc = shared.session_controller.current_document_mut();
+            if line_num >= doc.line_count() {
+                break;
+            }
+            let _ = doc.set_cursor(line_num, 0);
+            let count_before = doc.line_count();
+            
+            quit = self.execute_command(&global.command, shared);
+            processed += 1;
+            if quit {
+                break;
+            }
+            
+            // Re-aim the remaining targets at the lines they originally marked
+            let delta = shared.session_controller.current_document().line_count() as isize - count_before as isize;
+            let remaining: Vec<usize> = targets[index + 1..]
+                .iter()
+                .filter_map(|&target| Self::shift_global_target(target, line_num, delta))
+                .collect();
+            targets.truncate(index + 1);
+            targets.extend(remaining);
+            index += 1;
+        }
+        
+        self.running_global = false;
+        let doc = shared.session_controller.current_document_mut();
+        if doc.cursor_line() >= doc.line_count() {
+            let _ = doc.set_cursor(doc.line_count().saturating_sub(1), 0);
+        }
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_batch(cursor_pos);
+        
+        let lines_after = doc.line_count();
+        if !quit {
+            shared.status_message = if lines_after < lines_before {
+                format!("{} fewer lines", lines_before - lines_after)
+            } else if lines_after > lines_before {
+                format!("{} more lines", lines_after - lines_before)
+            } else {
+                format!("{} lines processed", processed)
+            };
+        }
+        quit
+    }
+    
+    /// Leave :normal's keys for the editor to type on `lines`, through the
+    /// mappings if `remap`. Keys are written as in mappings (`<Esc>`,
+    /// `<CR>`, `<lt>` for <).
+    fn queue_normal(keys: &str, lines: Vec<usize>, remap: bool, shared: &mut SharedEditorState) {
+        let keys = crate::controller::key_map::parse_keys(keys);
+        if !keys.is_empty() {
+            shared.pending_normal = Some(NormalKeys { keys, lines, remap });
+        }
     }
 
-    fn execute_buffer_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
-        match trimmed {
+    /// Where a later :g target ends up after the command on `line_num` changed the line
+    /// count by `delta`. Inserted or deleted lines are assumed to start at `line_num`, as
+    /// they do for d, s, co, m and friends; targets inside a deleted block are dropped.
+    pub(crate) fn shift_global_target(target: usize, line_num: usize, delta: isize) -> Option<usize> {
+        if delta >= 0 {
+            Some(target + delta as usize)
+        } else {
+            let removed = delta.unsigned_abs();
+            if target < line_num + removed {
+                None
+            } else {
+                Some(target - removed)
+            }
+        }
+    }
+    
+    fn execute_buffer_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
+        match parsed.command.as_str() {
             "ls" | "buffers" => {
+                // The one-line list stays behind for when the picker closes
                 shared.status_message = shared.session_controller.list_buffers();
+                self.picker = Some(BufferPicker::new(&shared.session_controller));
                 Some(false)
             }
-            "bn" | "bnext" => {
+            "bnext" => {
                 shared.status_message = shared.session_controller.next_buffer();
                 Some(false)
             }
-            "bp" | "bprev" | "bprevious" => {
+            "bprevious" => {
                 shared.status_message = shared.session_controller.prev_buffer();
                 Some(false)
             }
-            "bd" | "bdelete" => {
+            "bdelete" => {
+                let closing = shared.session_controller.current_buffer_index();
                 match shared.session_controller.close_buffer(&mut shared.mark_manager) {
-                    Ok(msg) => shared.status_message = msg,
+                    Ok(msg) => {
+                        shared.status_message = msg;
+                        let buffer_count = shared.session_controller.buffer_count();
+                        shared.view.windows_mut().buffer_removed(closing, buffer_count);
+                    }
                     Err(msg) => shared.status_message = msg,
                 }
                 Some(false)
             }
-            "bd!" => {
+            "bdelete!" => {
+                let closing = shared.session_controller.current_buffer_index();
                 match shared.session_controller.force_close_buffer(&mut shared.mark_manager) {
-                    Ok(msg) => shared.status_message = msg,
+                    Ok(msg) => {
+                        shared.status_message = msg;
+                        let buffer_count = shared.session_controller.buffer_count();
+                        shared.view.windows_mut().buffer_removed(closing, buffer_count);
+                    }
                     Err(msg) => shared.status_message = msg,
                 }
                 Some(false)
             }
-            _ if trimmed.starts_with("b") => {
-                match trimmed[1..].parse::<usize>() {
-                    Ok(buffer_num) => {
-                        match shared.session_controller.switch_to_buffer(buffer_num) {
-                            Ok(msg) => shared.status_message = msg,
-                            Err(msg) => shared.status_message = msg,
-                        }
-                        Some(false)
-                    }
-                    Err(_) => {
-                        shared.status_message = "Invalid buffer number".to_string();
-                        Some(false)
-                    }
-                }
+            "buffer" if !parsed.args.is_empty() => {
+                // :b 2, :b2 or :b {partial name}
+                let arg = parsed.args.join(" ");
+                let session = &mut shared.session_controller;
+                let result = match arg.parse::<usize>() {
+                    Ok(buffer_num) => session.switch_to_buffer(buffer_num),
+                    Err(_) => session.find_buffer(&arg).and_then(|index| session.switch_to_buffer(index + 1)),
+                };
+                shared.status_message = result.unwrap_or_else(|msg| msg);
+                Some(false)
             }
-            _ if trimmed.starts_with("bf ") => {
+            "bf" if !parsed.args.is_empty() => {
                 // Switch to buffer by filename
-                let filename = &trimmed[3..];
-                let path = std::path::PathBuf::from(filename);
+                let filename = parsed.args.join(" ");
+                let path = std::path::PathBuf::from(&filename);
                 match shared.session_controller.switch_to_file(&path) {
                     Ok(_) => {
                         shared.status_message = format!("Switched to buffer: {}", filename);
                     }
                     Err(e) => {
-                        shared.status_message = format!("Error switching to file: {}", e);
+                        self.report_error(shared, format!("Error switching to file: {}", e));
                     }
                 }
                 Some(false)
@@ -728,21 +1679,152 @@ impl CommandController {
         }
     }
 
+    fn execute_arg_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
+        // Buffers keep their changes when another is shown, so ! changes nothing
+        let count = parsed.args.first().and_then(|arg| arg.parse::<usize>().ok());
+        let result = match parsed.command.trim_end_matches('!') {
+            "args" if parsed.args.is_empty() => {
+                let args = &shared.session_controller.args;
+                shared.status_message =
+                    if args.is_empty() { "Argument list is empty".to_string() } else { args.describe() };
+                Ok(())
+            }
+            // :args and :next with files start a new list
+            "args" | "next" if count.is_none() && !parsed.args.is_empty() => ArgCommands::set(shared, &parsed.args),
+            "next" => ArgCommands::step(shared, count.unwrap_or(1).max(1), true),
+            "previous" | "Next" => ArgCommands::step(shared, count.unwrap_or(1).max(1), false),
+            "first" | "rewind" => ArgCommands::edit(shared, 0),
+            "last" => ArgCommands::edit(shared, shared.session_controller.args.files().len().saturating_sub(1)),
+            "argadd" => ArgCommands::add(shared, &parsed.args),
+            _ => return None,
+        };
+        if let Err(message) = result {
+            self.report_error(shared, message);
+        }
+        Some(false)
+    }
+
+    fn execute_quickfix_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
+        let count = || parsed.args.first().and_then(|arg| arg.parse::<usize>().ok()).unwrap_or(1).max(1);
+        match parsed.command.as_str() {
+            "grep" => {
+                if let Err(message) = QuickfixCommands::grep(shared, parsed.argument.trim()) {
+                    self.report_error(shared, message);
+                }
+            }
+            "cnext" => QuickfixCommands::step(shared, count(), true),
+            "cprevious" => QuickfixCommands::step(shared, count(), false),
+            "cfirst" | "clast" | "cc" if shared.quickfix.is_empty() => {
+                self.report_error(shared, "Quickfix list is empty".to_string());
+            }
+            "cfirst" => QuickfixCommands::jump(shared, 0),
+            "clast" => QuickfixCommands::jump(shared, shared.quickfix.entries().len() - 1),
+            "cc" => {
+                // :cc on its own shows the current entry again
+                let index = match parsed.args.first() {
+                    Some(_) => count() - 1,
+                    None => shared.quickfix.current_index(),
+                };
+                QuickfixCommands::jump(shared, index);
+            }
+            "copen" => {
+                if shared.quickfix.is_empty() {
+                    self.report_error(shared, "Quickfix list is empty".to_string());
+                } else {
+                    self.quickfix_picker = Some(QuickfixPicker::new(&shared.quickfix));
+                }
+            }
+            _ => return None,
+        }
+        Some(false)
+    }
+
+    fn execute_tag_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
+        let count = || parsed.args.first().and_then(|arg| arg.parse::<usize>().ok()).unwrap_or(1).max(1);
+        match parsed.command.as_str() {
+            "tag" => match parsed.args.first() {
+                Some(name) => {
+                    if let Err(message) = TagCommands::jump(shared, name) {
+                        self.report_error(shared, message);
+                    }
+                }
+                None => self.report_error(shared, "Argument required".to_string()),
+            },
+            "tselect" => {
+                // Without a name, the last tag jumped to
+                let name = parsed.args.first().map(String::as_str).or(shared.tag_stack.last_name()).map(str::to_string);
+                match name.ok_or_else(|| "Argument required".to_string()).and_then(|name| TagCommands::lookup(shared, &name)) {
+                    Ok(tags) => self.tag_picker = Some(TagPicker::new(tags)),
+                    Err(message) => self.report_error(shared, message),
+                }
+            }
+            "pop" => {
+                if let Err(message) = TagCommands::pop(shared, count()) {
+                    self.report_error(shared, message);
+                }
+            }
+            _ => return None,
+        }
+        Some(false)
+    }
+
     fn execute_file_command_parsed(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
         match parsed.command.as_str() {
-            "q" | "quit" => {
-                // Check if file is modified
-                if shared.session_controller.current_document().is_modified() {
-                    shared.status_message = "No write since last change (add ! to override)".to_string();
+            "checktime" => {
+                shared.status_message.clear();
+                if !self.check_files(shared) && shared.status_message.is_empty() {
+                    shared.status_message = "No files changed on disk".to_string();
+                }
+                Some(false)
+            }
+            "recover" => {
+                let name = shared.session_controller.get_display_filename().to_string();
+                match shared.session_controller.current_document_mut().recover_from_swap() {
+                    Ok(()) => {
+                        shared.status_message = format!("\"{}\" recovered from its swap file; :w to keep it", name)
+                    }
+                    Err(e) => self.report_error(shared, format!("Can't recover \"{}\": {}", name, e)),
+                }
+                Some(false)
+            }
+            "quit" | "quit!" if shared.view.windows().is_split() => {
+                // With split windows, quitting closes just the current window
+                WindowCommands::close(shared);
+                Some(false)
+            }
+            "quit" => {
+                if shared.session_controller.current_document().is_modified() && !self.confirmed {
+                    self.report_error(shared, "No write since last change (add ! to override)".to_string());
                     Some(false)
                 } else {
-                    Some(true) // Quit
+                    Some(self.quit_last_window(shared))
                 }
             }
-            "q!" | "quit!" => {
+            "quit!" | "qall!" => {
                 Some(true) // Force quit
             }
-            "w" | "write" => {
+            "qall" => {
+                let unsaved = UnsavedBuffers::list(shared);
+                if unsaved.is_empty() {
+                    return Some(true);
+                }
+                let names = UnsavedBuffers::names(shared, &unsaved);
+                self.report_error(shared, format!("No write since last change for {} (add ! to override)", names));
+                Some(false)
+            }
+            "wqall" | "xall" => match UnsavedBuffers::write_all(shared) {
+                Ok(_) => Some(true),
+                Err(message) => {
+                    self.report_error(shared, message);
+                    Some(false)
+                }
+            },
+            // :w >> file and :w !cmd
+            "write" | "write!" if parsed.argument.starts_with(['>', '!']) => {
+                self.write_lines(None, parsed, shared);
+                Some(false)
+            }
+            "write" => {
                 if parsed.args.is_empty() {
                     // Save current file
                     match shared.session_controller.current_document_mut().save() {
@@ -752,7 +1834,7 @@ impl CommandController {
                             Some(false)
                         }
                         Err(e) => {
-                            shared.status_message = format!("Error saving file: {}", e);
+                            self.report_error(shared, format!("Error saving file: {}", e));
                             Some(false)
                         }
                     }
@@ -765,23 +1847,105 @@ impl CommandController {
                             Some(false)
                         }
                         Err(e) => {
-                            shared.status_message = format!("Error saving file: {}", e);
+                            self.report_error(shared, format!("Error saving file: {}", e));
                             Some(false)
                         }
                     }
                 }
             }
-            "wq" | "x" => {
-                // Save and quit
-                match shared.session_controller.current_document_mut().save() {
-                    Ok(_) => Some(true), // Quit after successful save
+            "write!" => {
+                // Write even if the buffer was opened read-only because another editor holds it
+                match shared.session_controller.current_document_mut().save_forced() {
+                    Ok(_) => {
+                        shared.status_message = format!("\"{}\" written",
+                            shared.session_controller.get_display_filename());
+                    }
+                    Err(e) => {
+                        self.report_error(shared, format!("Error saving file: {}", e));
+                    }
+                }
+                Some(false)
+            }
+            "wq" | "xit" => {
+                // Save and quit (or close the window when split); :x only
+                // writes when there are changes
+                let doc = shared.session_controller.current_document_mut();
+                let result = if parsed.command == "xit" && !doc.is_modified() { Ok(0) } else { doc.save() };
+                match result {
+                    Ok(_) if shared.view.windows().is_split() => {
+                        WindowCommands::close(shared);
+                        Some(false)
+                    }
+                    Ok(_) => Some(self.quit_last_window(shared)),
                     Err(e) => {
-                        shared.status_message = format!("Error saving file: {}", e);
+                        self.report_error(shared, format!("Error saving file: {}", e));
                         Some(false)
                     }
                 }
             }
-            "f" | "file" => {
+            "wall" => {
+                match UnsavedBuffers::write_all(shared) {
+                    Ok(0) => shared.status_message = "No buffers have unsaved changes".to_string(),
+                    Ok(1) => shared.status_message = "1 buffer written".to_string(),
+                    Ok(count) => shared.status_message = format!("{} buffers written", count),
+                    Err(message) => self.report_error(shared, message),
+                }
+                Some(false)
+            }
+            "split" | "vsplit" => {
+                let direction = if parsed.command.starts_with('v') {
+                    SplitDirection::Vertical
+                } else {
+                    SplitDirection::Horizontal
+                };
+                WindowCommands::split(shared, direction);
+                // An optional filename is opened in the new window
+                if let Some(filename) = parsed.args.first() {
+                    let path = std::path::PathBuf::from(filename);
+                    shared.status_message = match shared.session_controller.switch_to_file(&path) {
+                        Ok(()) => format!("\"{}\"", filename),
+                        Err(_) => shared.session_controller.open_file(filename),
+                    };
+                    shared.view.reset_scroll();
+                }
+                Some(false)
+            }
+            "close" => {
+                WindowCommands::close(shared);
+                Some(false)
+            }
+            "diffsplit" => {
+                match parsed.args.first() {
+                    Some(filename) => {
+                        if let Err(message) = DiffCommands::split(shared, filename) {
+                            self.report_error(shared, message);
+                        }
+                    }
+                    None => self.report_error(shared, "Argument required".to_string()),
+                }
+                Some(false)
+            }
+            "diffoff" => {
+                DiffCommands::off(shared);
+                Some(false)
+            }
+            "diffget" | "diffput" => {
+                if let Err(message) = DiffCommands::transfer(shared, parsed.command == "diffget") {
+                    self.report_error(shared, message);
+                }
+                Some(false)
+            }
+            "Gblame" => {
+                if let Err(message) = self.show_blame(shared) {
+                    self.report_error(shared, message);
+                }
+                Some(false)
+            }
+            "only" => {
+                WindowCommands::only(shared);
+                Some(false)
+            }
+            "file" => {
                 // Show file info
                 let doc = shared.session_controller.current_document();
                 let line_count = doc.line_count();
@@ -797,62 +1961,476 @@ impl CommandController {
                             total += 1; // Add 1 for newline character (except last line)
                         }
                     }
-                    total
-                };
-                
-                shared.status_message = format!("\"{}\" {} lines, {} characters{}", filename, line_count, char_count, modified);
+                    total
+                };
+                
+                shared.status_message = format!("\"{}\" {} lines, {} characters{}", filename, line_count, char_count, modified);
+                Some(false)
+            }
+            _ => None
+        }
+    }
+
+
+    fn execute_setting_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
+        match trimmed {
+            "set nu" | "set number" => {
+                shared.view.set_line_numbers(true);
+                shared.status_message = "Line numbers enabled".to_string();
+                Some(false)
+            }
+            "set nonu" | "set nonumber" => {
+                shared.view.set_line_numbers(false);
+                shared.status_message = "Line numbers disabled".to_string();
+                Some(false)
+            }
+            "set list" => {
+                shared.view.set_show_whitespace(true);
+                shared.status_message = "Whitespace characters shown".to_string();
+                Some(false)
+            }
+            "set nolist" => {
+                shared.view.set_show_whitespace(false);
+                shared.status_message = "Whitespace characters hidden".to_string();
+                Some(false)
+            }
+            "set lcs" | "set listchars" => {
+                shared.status_message = format!("listchars={}", shared.view.get_list_chars());
+                Some(false)
+            }
+            _ if trimmed.starts_with("set listchars=") || trimmed.starts_with("set lcs=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match ListChars::parse(value_part) {
+                    Ok(list_chars) => {
+                        shared.view.set_list_chars(list_chars);
+                        shared.status_message = format!("listchars={}", list_chars);
+                    }
+                    Err(e) => self.report_error(shared, e),
+                }
+                Some(false)
+            }
+            "set cul" | "set cursorline" => {
+                shared.view.set_cursor_line(true);
+                shared.status_message = "Cursor line highlighted".to_string();
+                Some(false)
+            }
+            "set nocul" | "set nocursorline" => {
+                shared.view.set_cursor_line(false);
+                shared.status_message = "Cursor line not highlighted".to_string();
+                Some(false)
+            }
+            "set cc" | "set colorcolumn" => {
+                let columns: Vec<String> = shared.view.get_color_columns().iter().map(usize::to_string).collect();
+                shared.status_message = format!("colorcolumn={}", columns.join(","));
+                Some(false)
+            }
+            _ if trimmed.starts_with("set colorcolumn=") || trimmed.starts_with("set cc=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match parse_color_columns(value_part) {
+                    Ok(columns) => {
+                        shared.view.set_color_columns(columns);
+                        shared.status_message = format!("colorcolumn={}", value_part);
+                    }
+                    Err(e) => self.report_error(shared, e),
+                }
+                Some(false)
+            }
+            "set hltrailing" => {
+                shared.view.set_show_trailing(true);
+                shared.status_message = "Trailing whitespace highlighted".to_string();
+                Some(false)
+            }
+            "set nohltrailing" => {
+                shared.view.set_show_trailing(false);
+                shared.status_message = "Trailing whitespace not highlighted".to_string();
+                Some(false)
+            }
+            "set et" | "set expandtab" => {
+                shared.session_controller.current_document_mut().set_expand_tab(true);
+                shared.status_message = "Tab key will insert spaces".to_string();
+                Some(false)
+            }
+            "set noet" | "set noexpandtab" => {
+                shared.session_controller.current_document_mut().set_expand_tab(false);
+                shared.status_message = "Tab key will insert tabs".to_string();
+                Some(false)
+            }
+            "set ff" | "set fileformat" => {
+                let doc = shared.session_controller.current_document();
+                shared.status_message = format!("fileformat={}", doc.line_ending.name());
+                Some(false)
+            }
+            _ if trimmed.starts_with("set ff=") || trimmed.starts_with("set fileformat=") => {
+                let value = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match crate::document_model::LineEnding::from_name(value) {
+                    Some(line_ending) => {
+                        shared.session_controller.current_document_mut().set_line_ending(line_ending);
+                        shared.status_message = match line_ending {
+                            crate::document_model::LineEnding::Unix => "Line endings set to Unix (LF)",
+                            crate::document_model::LineEnding::Windows => "Line endings set to DOS (CRLF)",
+                            crate::document_model::LineEnding::Mac => "Line endings set to Mac (CR)",
+                        }
+                        .to_string();
+                    }
+                    None => self.report_error(shared, format!("Invalid fileformat: {} (unix, dos or mac)", value)),
+                }
+                Some(false)
+            }
+            "set fenc" | "set fileencoding" => {
+                let doc = shared.session_controller.current_document();
+                let bom = if doc.bom { " (with BOM)" } else { "" };
+                shared.status_message = format!("fileencoding={}{}", doc.encoding.name(), bom);
+                Some(false)
+            }
+            _ if trimmed.starts_with("set fenc=") || trimmed.starts_with("set fileencoding=") => {
+                let value = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match crate::document_model::Encoding::from_name(value) {
+                    Some(encoding) => {
+                        shared.session_controller.current_document_mut().set_encoding(encoding);
+                        shared.status_message = format!("File will be written as {}", encoding.name());
+                    }
+                    None => self.report_error(
+                        shared,
+                        format!("Invalid fileencoding: {} (utf-8, utf-16le, utf-16be or latin1)", value),
+                    ),
+                }
+                Some(false)
+            }
+            "set eol" | "set endofline" => {
+                shared.session_controller.current_document_mut().set_end_of_line(true);
+                shared.status_message = "The last line will end with a line break".to_string();
+                Some(false)
+            }
+            "set noeol" | "set noendofline" => {
+                shared.session_controller.current_document_mut().set_end_of_line(false);
+                shared.status_message = "The last line will be written without a line break".to_string();
+                Some(false)
+            }
+            "set sr" | "set shiftround" => {
+                shared.session_controller.current_document_mut().shift_round = true;
+                shared.status_message = "> and < round the indent to a multiple of shiftwidth".to_string();
+                Some(false)
+            }
+            "set nosr" | "set noshiftround" => {
+                shared.session_controller.current_document_mut().shift_round = false;
+                shared.status_message = "> and < move the indent by shiftwidth".to_string();
+                Some(false)
+            }
+            "set ai" | "set autoindent" => {
+                shared.session_controller.current_document_mut().auto_indent = true;
+                shared.status_message = "New lines will keep the indent".to_string();
+                Some(false)
+            }
+            "set noai" | "set noautoindent" => {
+                shared.session_controller.current_document_mut().auto_indent = false;
+                shared.status_message = "New lines will start at the margin".to_string();
+                Some(false)
+            }
+            "set autopairs" => {
+                shared.session_controller.current_document_mut().auto_pairs = true;
+                shared.status_message = "Brackets and quotes will be closed as they are typed".to_string();
+                Some(false)
+            }
+            "set noautopairs" => {
+                shared.session_controller.current_document_mut().auto_pairs = false;
+                shared.status_message = "Brackets and quotes will not be closed".to_string();
+                Some(false)
+            }
+            "set pastewrap" => {
+                shared.session_controller.current_document_mut().wrap_paste = true;
+                shared.status_message = "Pasted text will be wrapped to textwidth".to_string();
+                Some(false)
+            }
+            "set nopastewrap" => {
+                shared.session_controller.current_document_mut().wrap_paste = false;
+                shared.status_message = "Pasted text will not be wrapped".to_string();
+                Some(false)
+            }
+            "set trimtrailing" => {
+                shared.session_controller.current_document_mut().trim_trailing = true;
+                shared.status_message = "Trailing whitespace will be stripped on write".to_string();
+                Some(false)
+            }
+            "set notrimtrailing" => {
+                shared.session_controller.current_document_mut().trim_trailing = false;
+                shared.status_message = "Trailing whitespace will be kept on write".to_string();
+                Some(false)
+            }
+            "set formatonsave" => {
+                shared.session_controller.current_document_mut().format_on_save = true;
+                shared.status_message = "The buffer will be formatted with formatprg on write".to_string();
+                Some(false)
+            }
+            "set noformatonsave" => {
+                shared.session_controller.current_document_mut().format_on_save = false;
+                shared.status_message = "The buffer won't be formatted on write".to_string();
+                Some(false)
+            }
+            "set hls" | "set hlsearch" => {
+                shared.search_state.hlsearch = true;
+                shared.search_state.highlight_suspended = false;
+                shared.status_message = "Search matches highlighted".to_string();
+                Some(false)
+            }
+            "set nohls" | "set nohlsearch" => {
+                shared.search_state.hlsearch = false;
+                shared.status_message = "Search matches not highlighted".to_string();
+                Some(false)
+            }
+            "set ar" | "set autoread" => {
+                shared.session_controller.autoread = true;
+                shared.status_message = "Unmodified buffers reload when their file changes".to_string();
+                Some(false)
+            }
+            "set noar" | "set noautoread" => {
+                shared.session_controller.autoread = false;
+                shared.status_message = "Changed files will be asked about".to_string();
+                Some(false)
+            }
+            "set cf" | "set confirm" => {
+                shared.session_controller.confirm = true;
+                shared.status_message = "Unsaved changes and existing files will be asked about".to_string();
+                Some(false)
+            }
+            "set nocf" | "set noconfirm" => {
+                shared.session_controller.confirm = false;
+                shared.status_message = "Unsaved changes and existing files stop a command".to_string();
+                Some(false)
+            }
+            "set autosave" => {
+                let seconds = DEFAULT_AUTOSAVE_SECONDS;
+                shared.session_controller.autosave = Some(Duration::from_secs(seconds));
+                shared.status_message = format!("Modified buffers save after {}s without edits", seconds);
+                Some(false)
+            }
+            "set noautosave" | "set autosave=0" => {
+                shared.session_controller.autosave = None;
+                shared.status_message = "Autosave disabled".to_string();
+                Some(false)
+            }
+            "set autosave?" => {
+                shared.status_message = match shared.session_controller.autosave {
+                    Some(delay) => format!("autosave={}", delay.as_secs()),
+                    None => "noautosave".to_string(),
+                };
+                Some(false)
+            }
+            _ if trimmed.starts_with("set autosave=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<u64>() {
+                    Ok(seconds) => {
+                        shared.session_controller.autosave = Some(Duration::from_secs(seconds));
+                        shared.status_message = format!("Modified buffers save after {}s without edits", seconds);
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid autosave: {}", value_part)),
+                }
+                Some(false)
+            }
+            "set ticktime" => {
+                let tick = shared.timers.tick().map_or(0, |tick| tick.as_millis());
+                shared.status_message = format!("ticktime={}", tick);
+                Some(false)
+            }
+            _ if trimmed.starts_with("set ticktime=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<u64>() {
+                    Ok(millis) => {
+                        shared.timers.set_tick(Duration::from_millis(millis));
+                        shared.status_message = if millis == 0 {
+                            "Screen redrawn only for input and timers".to_string()
+                        } else {
+                            format!("Screen redrawn every {}ms", millis)
+                        };
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid ticktime: {}", value_part)),
+                }
+                Some(false)
+            }
+            "set sc" | "set showcmd" => {
+                shared.view.set_show_command(true);
+                shared.status_message = "Commands typed in part are shown".to_string();
+                Some(false)
+            }
+            "set nosc" | "set noshowcmd" => {
+                shared.view.set_show_command(false);
+                shared.status_message = "Commands typed in part aren't shown".to_string();
+                Some(false)
+            }
+            "set to" | "set timeout" => {
+                shared.timers.timeout = true;
+                shared.status_message = format!("Commands typed in part are dropped after {}ms", shared.timers.timeout_len.as_millis());
+                Some(false)
+            }
+            "set noto" | "set notimeout" => {
+                shared.timers.timeout = false;
+                shared.status_message = "Commands typed in part wait for the next key".to_string();
+                Some(false)
+            }
+            "set tm" | "set timeoutlen" => {
+                shared.status_message = format!("timeoutlen={}", shared.timers.timeout_len.as_millis());
+                Some(false)
+            }
+            _ if trimmed.starts_with("set timeoutlen=") || trimmed.starts_with("set tm=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<u64>() {
+                    Ok(millis) => {
+                        shared.timers.timeout_len = Duration::from_millis(millis);
+                        shared.status_message = format!("timeoutlen={}", millis);
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid timeoutlen: {}", value_part)),
+                }
+                Some(false)
+            }
+            "set sm" | "set showmatch" => {
+                shared.show_match.enabled = true;
+                shared.status_message = "Typed closing brackets show the bracket they close".to_string();
+                Some(false)
+            }
+            "set nosm" | "set noshowmatch" => {
+                shared.show_match.enabled = false;
+                shared.status_message = "Typed closing brackets aren't matched".to_string();
+                Some(false)
+            }
+            "set ul" | "set undolevels" => {
+                shared.status_message = format!("undolevels={}", shared.session_controller.undo_levels);
+                Some(false)
+            }
+            _ if trimmed.starts_with("set undolevels=") || trimmed.starts_with("set ul=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<usize>() {
+                    Ok(levels) => {
+                        let session = &mut shared.session_controller;
+                        session.undo_levels = levels;
+                        for doc in &mut session.buffers {
+                            doc.undo_manager_mut().limit(levels);
+                        }
+                        shared.status_message = format!("undolevels={}", levels);
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid undolevels: {}", value_part)),
+                }
                 Some(false)
             }
-            _ => None
-        }
-    }
-
-
-    fn execute_setting_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
-        match trimmed {
-            "set nu" | "set number" => {
-                shared.view.set_line_numbers(true);
-                shared.status_message = "Line numbers enabled".to_string();
+            _ if trimmed.starts_with("set matchtime=") || trimmed.starts_with("set mat=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<u64>() {
+                    Ok(tenths) => {
+                        shared.show_match.match_time = tenths;
+                        shared.status_message = format!("matchtime={}", tenths);
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid matchtime: {}", value_part)),
+                }
                 Some(false)
             }
-            "set nonu" | "set nonumber" => {
-                shared.view.set_line_numbers(false);
-                shared.status_message = "Line numbers disabled".to_string();
+            "set is" | "set incsearch" => {
+                shared.search_state.incsearch = true;
+                shared.status_message = "Incremental search enabled".to_string();
                 Some(false)
             }
-            "set list" => {
-                shared.view.set_show_whitespace(true);
-                shared.status_message = "Whitespace characters shown".to_string();
+            "set nois" | "set noincsearch" => {
+                shared.search_state.incsearch = false;
+                shared.status_message = "Incremental search disabled".to_string();
                 Some(false)
             }
-            "set nolist" => {
-                shared.view.set_show_whitespace(false);
-                shared.status_message = "Whitespace characters hidden".to_string();
+            "set ic" | "set ignorecase" => {
+                shared.search_state.ignore_case = true;
+                shared.status_message = "Searches ignore case".to_string();
                 Some(false)
             }
-            "set et" | "set expandtab" => {
-                shared.session_controller.current_document_mut().set_expand_tab(true);
-                shared.status_message = "Tab key will insert spaces".to_string();
+            "set noic" | "set noignorecase" => {
+                shared.search_state.ignore_case = false;
+                shared.status_message = "Searches match case".to_string();
                 Some(false)
             }
-            "set noet" | "set noexpandtab" => {
-                shared.session_controller.current_document_mut().set_expand_tab(false);
-                shared.status_message = "Tab key will insert tabs".to_string();
+            "set scs" | "set smartcase" => {
+                shared.search_state.smart_case = true;
+                shared.status_message = "Patterns with a capital letter match case".to_string();
+                Some(false)
+            }
+            "set noscs" | "set nosmartcase" => {
+                shared.search_state.smart_case = false;
+                shared.status_message = "Capital letters don't change how patterns match".to_string();
+                Some(false)
+            }
+            _ if trimmed.starts_with("set shortmess=") || trimmed.starts_with("set shm=") => {
+                let flags = trimmed.split_once('=').map_or("", |(_, value)| value).trim();
+                shared.search_state.shortmess = flags.to_string();
+                shared.status_message = format!("shortmess={}", flags);
+                Some(false)
+            }
+            _ if trimmed.starts_with("set textwidth=") || trimmed.starts_with("set tw=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                if let Ok(text_width) = value_part.parse::<usize>() {
+                    shared.session_controller.current_document_mut().text_width = text_width;
+                    shared.status_message = if text_width == 0 {
+                        "Text width disabled".to_string()
+                    } else {
+                        format!("Text width set to {}", text_width)
+                    };
+                } else {
+                    shared.status_message = "Invalid text width value".to_string();
+                }
+                Some(false)
+            }
+            _ if trimmed.starts_with("set writeprg=") || trimmed.starts_with("set wp=") => {
+                // Everything after '=' is the shell command; empty turns the filter off
+                let program = trimmed.split_once('=').map_or("", |(_, value)| value).trim();
+                let doc = shared.session_controller.current_document_mut();
+                doc.write_program = (!program.is_empty()).then(|| program.to_string());
+                shared.status_message = match &doc.write_program {
+                    Some(program) => format!("Saving pipes through: {}", program),
+                    None => "Write filter disabled".to_string(),
+                };
+                Some(false)
+            }
+            _ if trimmed.starts_with("set formatprg=") || trimmed.starts_with("set fp=") => {
+                // Everything after '=' is the shell command; empty means none
+                let program = trimmed.split_once('=').map_or("", |(_, value)| value).trim();
+                let doc = shared.session_controller.current_document_mut();
+                doc.format_program = (!program.is_empty()).then(|| program.to_string());
+                shared.status_message = match &doc.format_program {
+                    Some(program) => format!(":format pipes through: {}", program),
+                    None => "No formatter".to_string(),
+                };
+                Some(false)
+            }
+            _ if trimmed.starts_with("set synmaxcol=") || trimmed.starts_with("set smc=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                if let Ok(column) = value_part.parse::<usize>() {
+                    shared.view.set_max_highlight_column(column);
+                    shared.status_message = if column == 0 {
+                        "Highlighting long lines in full".to_string()
+                    } else {
+                        format!("Highlighting stops at column {}", column)
+                    };
+                } else {
+                    shared.status_message = "Invalid synmaxcol value".to_string();
+                }
                 Some(false)
             }
-            "set ff=unix" => {
-                shared.session_controller.current_document_mut().set_line_ending(crate::document_model::LineEnding::Unix);
-                shared.status_message = "Line endings set to Unix (LF)".to_string();
+            "set sw" | "set shiftwidth" => {
+                let doc = shared.session_controller.current_document();
+                shared.status_message = format!("shiftwidth={}", doc.shift_width);
                 Some(false)
             }
-            "set ff=dos" => {
-                shared.session_controller.current_document_mut().set_line_ending(crate::document_model::LineEnding::Windows);
-                shared.status_message = "Line endings set to DOS (CRLF)".to_string();
+            _ if trimmed.starts_with("set sw=") || trimmed.starts_with("set shiftwidth=") => {
+                let value_part = trimmed.split_once('=').map_or("", |(_, value)| value);
+                match value_part.parse::<usize>() {
+                    Ok(width) => {
+                        shared.session_controller.current_document_mut().shift_width = width;
+                        shared.status_message = if width == 0 {
+                            "Shift width follows tabstop".to_string()
+                        } else {
+                            format!("Shift width set to {}", width)
+                        };
+                    }
+                    Err(_) => self.report_error(shared, format!("Invalid shiftwidth: {}", value_part)),
+                }
                 Some(false)
             }
-            "set ff=mac" => {
-                shared.session_controller.current_document_mut().set_line_ending(crate::document_model::LineEnding::Mac);
-                shared.status_message = "Line endings set to Mac (CR)".to_string();
+            "set ft" | "set filetype" => {
+                let doc = shared.session_controller.current_document();
+                let file_type = crate::config::filetype::detect_buffer(doc);
+                shared.status_message = format!("filetype={}", file_type.unwrap_or(""));
                 Some(false)
             }
             _ if trimmed.starts_with("set tabstop=") => {
@@ -875,9 +2453,26 @@ impl CommandController {
 
     fn execute_utility_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
         match trimmed {
-            "help" | "h" | "?" => {
-                shared.session_controller.add_help_buffer();
-                shared.status_message = "Help buffer opened".to_string();
+            _ if matches!(trimmed, "help" | "?") || trimmed.starts_with("help ") => {
+                let subject = trimmed.strip_prefix("help").unwrap_or_default().trim();
+                if let Err(message) = HelpCommands::open(shared, subject) {
+                    self.report_error(shared, message);
+                }
+                Some(false)
+            }
+            "stats" => {
+                // One line per buffer and file type: too many for the status line
+                let report = Self::format_session_stats(shared);
+                let buffers = shared.session_controller.buffer_count();
+                match shared.session_controller.create_preview_buffer("[Session Stats]".to_string(), report) {
+                    Ok(_) => shared.status_message = format!("Session stats for {} buffers in preview buffer", buffers),
+                    Err(e) => self.report_error(shared, format!("Error creating preview: {}", e)),
+                }
+                Some(false)
+            }
+            "nohlsearch" => {
+                // Hide highlights until the next search; 'hlsearch' stays set
+                shared.search_state.highlight_suspended = true;
                 Some(false)
             }
             "mkvirus" => {
@@ -887,33 +2482,17 @@ impl CommandController {
                         shared.status_message = "Sample .virusrc created in current directory".to_string();
                     }
                     Err(e) => {
-                        shared.status_message = format!("Error creating .virusrc: {}", e);
+                        self.report_error(shared, format!("Error creating .virusrc: {}", e));
                     }
                 }
                 Some(false)
             }
-            "detab" => {
-                let tab_width = shared.view.get_tab_stop();
-                let count = shared.session_controller.current_document_mut().tabs_to_spaces(tab_width);
-                shared.status_message = if count == 1 {
-                    "1 tab converted to spaces".to_string()
-                } else {
-                    format!("{} tabs converted to spaces", count)
-                };
-                Some(false)
-            }
-            "retab" => {
-                let tab_width = shared.view.get_tab_stop();
-                let count = shared.session_controller.current_document_mut().spaces_to_tabs(tab_width);
-                shared.status_message = if count == 1 {
-                    "1 space sequence converted to tab".to_string()
-                } else {
-                    format!("{} space sequences converted to tabs", count)
-                };
-                Some(false)
-            }
             "ascii" | "normalize" => {
-                let count = shared.session_controller.current_document_mut().ascii_normalize();
+                let doc = shared.session_controller.current_document_mut();
+                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+                doc.undo_manager_mut().start_group(cursor_pos);
+                let count = doc.ascii_normalize();
+                doc.undo_manager_mut().end_group(cursor_pos);
                 shared.status_message = if count == 0 {
                     "No Unicode characters found to normalize".to_string()
                 } else if count == 1 {
@@ -955,7 +2534,7 @@ impl CommandController {
                 shared.status_message = "Scroll position reset".to_string();
                 Some(false)
             }
-            "e" => {
+            "edit" => {
                 // Create new empty buffer
                 shared.status_message = shared.session_controller.create_new_buffer();
                 Some(false)
@@ -967,7 +2546,7 @@ impl CommandController {
             }
             _ if trimmed.starts_with("badd ") => {
                 // Add new buffers for specified files
-                let filenames_str = &trimmed[5..];
+                let filenames_str = &trimmed["badd ".len()..];
                 let filenames: Vec<&str> = filenames_str.split_whitespace().collect();
                 if !filenames.is_empty() {
                     shared.status_message = shared.session_controller.open_files(filenames);
@@ -976,10 +2555,17 @@ impl CommandController {
                 }
                 Some(false)
             }
-            _ if trimmed.starts_with("e ") => {
-                // Open/create file(s)
-                let filenames_str = &trimmed[2..];
-                let filenames: Vec<&str> = filenames_str.split_whitespace().collect();
+            _ if trimmed.starts_with("edit ") => {
+                // Open/create file(s); +N or file:line[:column] places the cursor
+                let words = trimmed["edit ".len()..].split_whitespace();
+                let (plus, mut filenames): (Vec<&str>, Vec<&str>) = words.partition(|word| word.starts_with('+'));
+                let mut position = plus.last().and_then(|word| FilePosition::parse_plus(word));
+                if filenames.len() == 1 {
+                    let (name, suffix) = FilePosition::split_name(filenames[0]);
+                    filenames[0] = name;
+                    position = position.or(suffix);
+                }
+                let opened = !filenames.is_empty();
                 if filenames.len() == 1 {
                     shared.status_message = shared.session_controller.open_file(filenames[0]);
                 } else if filenames.len() > 1 {
@@ -987,6 +2573,11 @@ impl CommandController {
                 } else {
                     shared.status_message = "No filename specified".to_string();
                 }
+                if let Some(position) = position
+                    && opened
+                {
+                    position.apply(shared.session_controller.current_document_mut());
+                }
                 Some(false)
             }
             _ => None
@@ -994,7 +2585,125 @@ impl CommandController {
     }
 
     fn execute_parsed_misc_command(&mut self, parsed: &ParsedCommand, shared: &mut SharedEditorState) -> Option<bool> {
+        if let Some(result) = shared.key_map.execute(&parsed.command, &parsed.argument) {
+            match result {
+                Ok(message) => shared.status_message = message,
+                Err(e) => self.report_error(shared, e),
+            }
+            return Some(false);
+        }
         match parsed.command.as_str() {
+            "earlier" | "later" => {
+                match UndoDistance::parse(parsed.args.first().map(String::as_str)) {
+                    Some(distance) if parsed.command.starts_with('e') => UndoCommands::earlier(shared, distance),
+                    Some(distance) => UndoCommands::later(shared, distance),
+                    None => {
+                        shared.status_message = format!("Invalid argument: {}", parsed.args.join(" "));
+                    }
+                }
+                Some(false)
+            }
+            "read" | "read!" => {
+                // :r file, :r !cmd (or :r!cmd); a line number reads in after that line
+                match parsed.argument.strip_prefix('!') {
+                    _ if parsed.command == "read!" => self.read_command_output(parsed.range.as_ref(), &parsed.argument, shared),
+                    Some(command_str) => self.read_command_output(parsed.range.as_ref(), command_str.trim(), shared),
+                    None if parsed.argument.is_empty() => {
+                        self.report_error(shared, "No filename specified".to_string());
+                    }
+                    None => self.read_file(parsed.range.as_ref(), &parsed.argument, shared),
+                }
+                Some(false)
+            }
+            "!" => {
+                // The editor runs it with the terminal handed over
+                if parsed.argument.is_empty() {
+                    self.report_error(shared, "No shell command given".to_string());
+                } else {
+                    shared.pending_shell_command = Some(parsed.argument.clone());
+                }
+                Some(false)
+            }
+            "stop" | "stop!" | "suspend" | "suspend!" => {
+                // The editor stops once it has handed the terminal back
+                shared.pending_suspend = true;
+                Some(false)
+            }
+            "undolist" => {
+                UndoCommands::undolist(shared);
+                Some(false)
+            }
+            "registers" | "display" => {
+                self.show_registers(&parsed.argument, shared);
+                Some(false)
+            }
+            "mksession" | "mksession!" => {
+                let filename = match parsed.argument.as_str() {
+                    "" => DEFAULT_SESSION_FILE,
+                    name => name,
+                };
+                if parsed.command == "mksession" && std::path::Path::new(filename).exists() {
+                    self.report_error(shared, format!("\"{}\" exists (add ! to override)", filename));
+                    return Some(false);
+                }
+                match std::fs::write(filename, SessionScript::generate(shared)) {
+                    Ok(()) => shared.status_message = format!("Session saved to \"{}\"", filename),
+                    Err(e) => self.report_error(shared, format!("Error writing session: {}", e)),
+                }
+                Some(false)
+            }
+            "colorscheme" => {
+                if parsed.argument.is_empty() {
+                    shared.status_message = format!(
+                        "{} (built in: {})",
+                        shared.view.theme().name,
+                        BUILTIN_THEMES.join(", ")
+                    );
+                    return Some(false);
+                }
+                match Theme::load(&parsed.argument) {
+                    Ok(theme) => shared.view.set_theme(theme),
+                    Err(e) => self.report_error(shared, e),
+                }
+                Some(false)
+            }
+            "source" => {
+                if parsed.argument.is_empty() {
+                    self.report_error(shared, "No filename specified".to_string());
+                    return Some(false);
+                }
+                Some(self.source_file(&parsed.argument, shared))
+            }
+            "wincmd" => {
+                let mut keys = parsed.argument.chars();
+                match (keys.next(), keys.next()) {
+                    (Some(key), None) => WindowCommands::execute_key(shared, key),
+                    _ => self.report_error(shared, "Argument required".to_string()),
+                }
+                Some(false)
+            }
+            "call" => {
+                // Only cursor(line, column), which sessions use to put the cursor back
+                match SessionScript::parse_cursor_call(&parsed.argument) {
+                    Some((line, column)) => {
+                        let doc = shared.session_controller.current_document_mut();
+                        doc.move_cursor_to(line.saturating_sub(1), column.saturating_sub(1));
+                    }
+                    None => self.report_error(shared, format!("Unknown function: {}", parsed.argument)),
+                }
+                Some(false)
+            }
+            "mkdir" => {
+                match parsed.args.first() {
+                    Some(name) => {
+                        if let Err(e) = DirectoryCommands::make_directory(name, shared) {
+                            self.report_error(shared, e);
+                        }
+                    }
+                    None => self.report_error(shared, "No directory name given".to_string()),
+                }
+                Some(false)
+            }
             "delmarks" => {
                 // Delete specific marks
                 let mut deleted_count = 0;
@@ -1024,7 +2733,7 @@ impl CommandController {
                 };
                 Some(false)
             }
-            "w" => {
+            "write" => {
                 if parsed.args.is_empty() {
                     // Save current file
                     match shared.session_controller.current_document_mut().save() {
@@ -1033,7 +2742,7 @@ impl CommandController {
                             shared.status_message = format!("\"{}\" {} bytes written", filename, byte_count);
                         }
                         Err(e) => {
-                            shared.status_message = format!("Error saving file: {}", e);
+                            self.report_error(shared, format!("Error saving file: {}", e));
                         }
                     }
                     Some(false)
@@ -1045,7 +2754,7 @@ impl CommandController {
                             shared.status_message = format!("\"{}\" {} bytes written", filename, byte_count);
                         }
                         Err(e) => {
-                            shared.status_message = format!("Error saving file: {}", e);
+                            self.report_error(shared, format!("Error saving file: {}", e));
                         }
                     }
                     Some(false)
@@ -1055,66 +2764,204 @@ impl CommandController {
         }
     }
 
-    fn execute_misc_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> bool {
-        // Handle shell command execution first
-        if let Some(result) = self.execute_shell_command(trimmed, shared) {
-            return result;
+    /// :source file - run each line of a file as an ex command. Blank lines
+    /// and `"` comments are skipped, and a failing line doesn't stop the
+    /// rest; the first failure is reported at the end. True if a line quit.
+    fn source_file(&mut self, filename: &str, shared: &mut SharedEditorState) -> bool {
+        let script = match std::fs::read_to_string(filename) {
+            Ok(script) => script,
+            Err(e) => {
+                self.report_error(shared, format!("Can't open \"{}\": {}", filename, e));
+                return false;
+            }
+        };
+
+        let session = &shared.session_controller;
+        let start_buffer = session.current_buffer_index();
+        let scratch = session.is_empty_scratch(start_buffer).then_some(start_buffer);
+
+        let mut first_error = None;
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            self.error = None;
+            let quit = self.execute_command(line.strip_prefix(':').unwrap_or(line), shared);
+            if let Some(message) = self.error.take() {
+                first_error.get_or_insert(format!("line {}: {}", index + 1, message));
+            }
+            if quit {
+                return true;
+            }
         }
 
-        // Handle file read operations (:r filename, :0r filename, :$r filename, etc.)
-        if trimmed.starts_with("r ") {
-            let filename = &trimmed[2..];
-            match shared.session_controller.current_document_mut().insert_file_at_cursor(filename.as_ref()) {
-                Ok(lines_added) => {
-                    shared.status_message = format!("\"{}\" {} lines inserted", filename, lines_added);
-                }
-                Err(e) => {
-                    shared.status_message = format!("Error reading file \"{}\": {}", filename, e);
-                }
+        // Once the script has opened files, the empty buffer the editor started with is clutter
+        if let Some(index) = scratch {
+            Self::drop_unused_scratch(index, shared);
+        }
+
+        match first_error {
+            Some(message) => self.report_error(shared, format!("Error in \"{}\" {}", filename, message)),
+            None => shared.status_message = format!("\"{}\" sourced", filename),
+        }
+        false
+    }
+
+    /// Remove the empty buffer `index` if it is still empty and no window shows it
+    fn drop_unused_scratch(index: usize, shared: &mut SharedEditorState) {
+        let session = &shared.session_controller;
+        let windows = shared.view.windows();
+        let shown = session.current_buffer_index() == index
+            || windows.windows().iter().any(|w| w.id != windows.active_id() && w.buffer_index == index);
+        if shown || !session.is_empty_scratch(index) {
+            return;
+        }
+        shared.session_controller.remove_buffer(index);
+        let buffer_count = shared.session_controller.buffer_count();
+        shared.view.windows_mut().buffer_removed(index, buffer_count);
+    }
+
+    /// Where :read puts what it reads: the line to insert after (0 for the
+    /// top, None for after the cursor line) and how to describe that
+    fn read_position(&self, range: Option<&Range>, shared: &SharedEditorState) -> (Option<usize>, String) {
+        match range {
+            None => (None, String::new()),
+            Some(Range::LineNumber(0)) => (Some(0), " at beginning".to_string()),
+            Some(Range::LastLine) => {
+                (Some(shared.session_controller.current_document().line_count()), " at end".to_string())
             }
-            return false;
-        } else if trimmed.starts_with("0r ") {
-            // Insert at beginning of file
-            let filename = &trimmed[3..];
-            match shared.session_controller.current_document_mut().insert_file_at_line(filename.as_ref(), 0) {
-                Ok(lines_added) => {
-                    shared.status_message = format!("\"{}\" {} lines inserted at beginning", filename, lines_added);
-                }
-                Err(e) => {
-                    shared.status_message = format!("Error reading file \"{}\": {}", filename, e);
-                }
+            Some(range) => {
+                let line = self.resolve_range(range, shared).1 + 1;
+                (Some(line), format!(" after line {}", line))
             }
-            return false;
-        } else if trimmed.starts_with("$r ") {
-            // Insert at end of file
-            let filename = &trimmed[3..];
-            let line_count = shared.session_controller.current_document().line_count();
-            match shared.session_controller.current_document_mut().insert_file_at_line(filename.as_ref(), line_count) {
-                Ok(lines_added) => {
-                    shared.status_message = format!("\"{}\" {} lines inserted at end", filename, lines_added);
-                }
-                Err(e) => {
-                    shared.status_message = format!("Error reading file \"{}\": {}", filename, e);
+        }
+    }
+
+    /// :[range]w [>>] file and :[range]w !cmd - write the lines (all of
+    /// them without a range) to another file, append them to a file (the
+    /// buffer's own without a name), or pipe them to a shell command
+    fn write_lines(&mut self, range: Option<&Range>, parsed: &ParsedCommand, shared: &mut SharedEditorState) {
+        let last = shared.session_controller.current_document().line_count().saturating_sub(1);
+        let (start, end) = match range {
+            Some(range) => self.resolve_range(range, shared),
+            None => (0, last),
+        };
+        let whole = start == 0 && end >= last;
+        let force = parsed.command == "write!";
+        let argument = parsed.argument.trim();
+
+        if let Some(command_str) = argument.strip_prefix('!') {
+            self.pipe_lines(start, end, command_str.trim(), shared);
+            return;
+        }
+        let (append, name) = match argument.strip_prefix(">>") {
+            Some(name) => (true, name.trim()),
+            None => (false, argument),
+        };
+        let doc = shared.session_controller.current_document_mut();
+        let path = match name {
+            "" => match &doc.filename {
+                Some(filename) => filename.clone(),
+                None => {
+                    self.report_error(shared, "No file name".to_string());
+                    return;
                 }
+            },
+            name => std::path::PathBuf::from(name),
+        };
+        let own_file = doc.filename.as_ref() == Some(&path);
+
+        if !append && own_file {
+            if !whole && !force {
+                self.report_error(shared, "Use ! to write partial buffer".to_string());
+                return;
             }
-            return false;
-        } else if let Some(pos) = trimmed.find("r ") {
-            // Handle :10r filename format
-            let line_part = &trimmed[..pos];
-            if let Ok(line_num) = line_part.parse::<usize>() {
-                let filename = &trimmed[pos + 2..];
-                match shared.session_controller.current_document_mut().insert_file_at_line(filename.as_ref(), line_num) {
-                    Ok(lines_added) => {
-                        shared.status_message = format!("\"{}\" {} lines inserted after line {}", filename, lines_added, line_num);
-                    }
-                    Err(e) => {
-                        shared.status_message = format!("Error reading file \"{}\": {}", filename, e);
-                    }
+            if whole {
+                // :%w is :w
+                let saved = if force { doc.save_forced() } else { doc.save() };
+                match saved {
+                    Ok(byte_count) => shared.status_message = format!("\"{}\" {} bytes written", path.display(), byte_count),
+                    Err(e) => self.report_error(shared, format!("Error saving file: {}", e)),
                 }
-                return false;
+                return;
             }
         }
-        
+        if append && !force && !path.exists() {
+            self.report_error(shared, format!("\"{}\" doesn't exist (add ! to create it)", path.display()));
+            return;
+        }
+        match doc.write_lines(start, end, &path, append) {
+            Ok(lines) => {
+                shared.status_message = format!(
+                    "\"{}\" {} line{} {}",
+                    path.display(),
+                    lines,
+                    if lines == 1 { "" } else { "s" },
+                    if append { "appended" } else { "written" }
+                );
+            }
+            Err(e) => self.report_error(shared, format!("Error writing \"{}\": {}", path.display(), e)),
+        }
+    }
+
+    /// :w !cmd - feed lines to a shell command's stdin and show what it
+    /// prints: on the status line if it is one line, in a preview buffer if more
+    fn pipe_lines(&mut self, start: usize, end: usize, command_str: &str, shared: &mut SharedEditorState) {
+        if command_str.is_empty() {
+            self.report_error(shared, "No shell command given".to_string());
+            return;
+        }
+        let input = match shared.session_controller.current_document().encoded_lines(start, end, false) {
+            Ok(input) => input,
+            Err(e) => {
+                self.report_error(shared, format!("Can't encode the lines: {}", e));
+                return;
+            }
+        };
+        let output = match shell::filter(command_str, input) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(shared, format!("Failed to execute command: {}", e));
+                return;
+            }
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            self.report_error(shared, format!("Command failed ({}): {}", output.status, stderr.trim()));
+            return;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stdout = stdout.trim_end_matches('\n');
+        shared.status_message = match stdout.lines().count() {
+            0 => "Command executed (no output)".to_string(),
+            1 => stdout.to_string(),
+            lines => match shared.session_controller.create_preview_buffer(format!("[!{}]", command_str), stdout.to_string()) {
+                Ok(()) => format!("{} lines of output in preview buffer", lines),
+                Err(e) => format!("Error creating preview: {}", e),
+            },
+        };
+    }
+
+    /// Insert a file at the cursor, or after the line a range names
+    fn read_file(&mut self, range: Option<&Range>, filename: &str, shared: &mut SharedEditorState) {
+        let (line, place) = self.read_position(range, shared);
+        let doc = shared.session_controller.current_document_mut();
+        let result = match line {
+            Some(line) => doc.insert_file_at_line(filename.as_ref(), line),
+            None => doc.insert_file_at_cursor(filename.as_ref()),
+        };
+        match result {
+            Ok(lines_added) => {
+                shared.status_message = format!("\"{}\" {} lines inserted{}", filename, lines_added, place);
+            }
+            Err(e) => {
+                self.report_error(shared, format!("Error reading file \"{}\": {}", filename, e));
+            }
+        }
+    }
+
+    fn execute_misc_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> bool {
         // Handle numeric line jumps like ":42"
         if let Ok(line_num) = trimmed.parse::<usize>() {
             if line_num > 0 {
@@ -1130,22 +2977,83 @@ impl CommandController {
             }
             false
         } else {
-            shared.status_message = format!("Unknown command: {}", trimmed);
+            self.report_error(shared, format!("Unknown command: {}", trimmed));
             false
         }
     }
 
-    fn execute_search_replace_command(&mut self, _trimmed: &str, _shared: &mut SharedEditorState) -> Option<bool> {
-        // TODO: Legacy substitute handling - now handled by range system
-        None
+    /// :registers [names] - what each register holds, in a preview buffer.
+    /// Line breaks show as ^J, so every register takes one line.
+    fn show_registers(&mut self, names: &str, shared: &mut SharedEditorState) {
+        let names = names.to_lowercase();
+        let registers: Vec<_> = shared
+            .register_manager
+            .list()
+            .into_iter()
+            .filter(|(name, _)| names.is_empty() || names.contains(*name))
+            .collect();
+        if registers.is_empty() {
+            shared.status_message = "No registers to show".to_string();
+            return;
+        }
+
+        let mut listing = vec!["Type Name Content".to_string()];
+        for (name, data) in &registers {
+            let (kind, content) = match data.register_type {
+                RegisterType::Character => ('c', data.content.clone()),
+                RegisterType::Line => ('l', format!("{}\n", data.content)),
+                RegisterType::Block => ('b', data.content.clone()),
+            };
+            listing.push(format!("  {}  \"{}   {}", kind, name, Self::caret_notation(&content)));
+        }
+        shared.status_message = match shared.session_controller.create_preview_buffer("[Registers]".to_string(), listing.join("\n")) {
+            Ok(_) => format!("{} registers listed in preview buffer", registers.len()),
+            Err(e) => format!("Error creating preview: {}", e),
+        };
+    }
+
+    /// :Gblame - who last changed each line, in a window to the left that
+    /// scrolls with the buffer's
+    fn show_blame(&mut self, shared: &mut SharedEditorState) -> Result<(), String> {
+        let doc = shared.session_controller.current_document();
+        let path = doc.filename.clone().ok_or("No file name")?;
+        let lines = git::blame(&path, doc.file_content().as_bytes())?;
+        let line = doc.cursor_line();
+
+        let source = shared.view.windows().active_id();
+        WindowCommands::split(shared, SplitDirection::Vertical);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        shared.session_controller.create_preview_buffer(format!("[Blame] {}", name), lines.join("\n"))?;
+        let blame = shared.session_controller.current_document_mut();
+        blame.read_only = true;
+        blame.move_cursor_to(line.min(blame.line_count().saturating_sub(1)), 0);
+
+        let windows = shared.view.windows_mut();
+        windows.active_mut().scroll_bind = true;
+        if let Some(window) = windows.get_mut(source) {
+            window.scroll_bind = true;
+        }
+        shared.status_message = format!("{} lines blamed", lines.len());
+        Ok(())
+    }
+
+    /// Control characters as ^J, ^I and so on
+    fn caret_notation(text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                '\u{7f}' => "^?".to_string(),
+                c if c.is_ascii_control() => format!("^{}", (c as u8 + b'@') as char),
+                c => c.to_string(),
+            })
+            .collect()
     }
 
     fn execute_mark_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
         match trimmed {
             "marks" => {
                 // List all marks
-                let local_marks = shared.session_controller.current_document().get_all_local_marks();
-                let marks_vec = shared.mark_manager.list_marks(local_marks);
+                let doc = shared.session_controller.current_document();
+                let marks_vec = shared.mark_manager.list_marks(doc.get_all_local_marks(), doc.undo_manager().last_change());
                 
                 // Format marks for display
                 let mut marks_display = "Marks:\n".to_string();
@@ -1161,7 +3069,7 @@ impl CommandController {
                 shared.status_message = marks_display;
                 Some(false)
             }
-            "jumps" | "ju" => {
+            "jumps" => {
                 // Show jump list
                 let (jump_list, current_pos) = shared.mark_manager.get_jump_list();
                 let mut jump_info = format!("Jump list (current position: {}):\n", current_pos);
@@ -1227,39 +3135,39 @@ impl CommandController {
         }
     }
 
-    fn execute_shell_command(&mut self, trimmed: &str, shared: &mut SharedEditorState) -> Option<bool> {
-        if trimmed.starts_with("r !") {
-            let command_str = &trimmed[3..];
-            match std::process::Command::new("sh")
-                .arg("-c")
-                .arg(command_str)
-                .output()
-            {
-                Ok(output) => {
-                    let output_str = String::from_utf8_lossy(&output.stdout);
-                    if !output_str.is_empty() {
-                        match shared.session_controller.current_document_mut().insert_text_at_cursor(&output_str) {
-                            Ok(lines_added) => {
-                                shared.status_message = format!("Command output: {} lines inserted", lines_added);
-                            }
-                            Err(e) => {
-                                shared.status_message = format!("Error inserting command output: {}", e);
-                            }
+    /// :r !cmd - insert the output of a shell command after the cursor
+    /// line, or after the line a range gives
+    fn read_command_output(&mut self, range: Option<&Range>, command_str: &str, shared: &mut SharedEditorState) {
+        let (line, place) = self.read_position(range, shared);
+        match std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command_str)
+            .output()
+        {
+            Ok(output) => {
+                let output_str = String::from_utf8_lossy(&output.stdout);
+                if !output_str.is_empty() {
+                    let doc = shared.session_controller.current_document_mut();
+                    let line = line.unwrap_or(doc.cursor_line() + 1);
+                    match doc.insert_text_at_line(output_str.strip_suffix('\n').unwrap_or(&output_str), line) {
+                        Ok(_) => {
+                            let lines_added = output_str.lines().count();
+                            shared.status_message = format!("Command output: {} lines inserted{}", lines_added, place);
+                        }
+                        Err(e) => {
+                            self.report_error(shared, format!("Error inserting command output: {}", e));
                         }
-                    } else if !output.stderr.is_empty() {
-                        let error_str = String::from_utf8_lossy(&output.stderr);
-                        shared.status_message = format!("Command error: {}", error_str.trim());
-                    } else {
-                        shared.status_message = "Command executed (no output)".to_string();
                     }
-                }
-                Err(e) => {
-                    shared.status_message = format!("Failed to execute command: {}", e);
+                } else if !output.stderr.is_empty() {
+                    let error_str = String::from_utf8_lossy(&output.stderr);
+                    shared.status_message = format!("Command error: {}", error_str.trim());
+                } else {
+                    shared.status_message = "Command executed (no output)".to_string();
                 }
             }
-            Some(false)
-        } else {
-            None
+            Err(e) => {
+                shared.status_message = format!("Failed to execute command: {}", e);
+            }
         }
     }
 
@@ -1270,7 +3178,11 @@ impl CommandController {
 mod tests {
     use super::*;
     use crate::controller::SessionController;
-    use crate::document_model::{Document, MarkManager, RegisterManager, SearchState};
+    use crate::controller::key_map::KeyMap;
+    use crate::controller::show_match::ShowMatch;
+    use crate::controller::timers::TimerQueue;
+    use crate::document_model::{Document, History, MarkManager, QuickfixList, RegisterManager, SearchState, TagStack};
+    use crate::controller::lsp_commands::LspManager;
     use crate::view::View;
     use crossterm::event::{KeyCode, KeyModifiers};
     use std::path::PathBuf;
@@ -1285,6 +3197,21 @@ mod tests {
             status_message: String::new(),
             show_all_unmatched: false,
             cached_unmatched_brackets: None,
+            block_insert: None,
+            pending_selection: None,
+            last_substitute: None,
+            pending_shell_command: None,
+            pending_ex_command: None,
+            pending_normal: None,
+            pending_suspend: false,
+            key_map: KeyMap::new(),
+            timers: TimerQueue::new(),
+            history: History::new(),
+            show_match: ShowMatch::default(),
+            diff: None,
+            tag_stack: TagStack::default(),
+            lsp: LspManager::default(),
+            quickfix: QuickfixList::default(),
         }
     }
     
@@ -1305,6 +3232,20 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_expand_file_names() {
+        let expand = |text| expand_file_names(text, Some("src/main.rs"), Some("README.md"));
+        assert_eq!(expand("% #"), Ok("src/main.rs README.md".to_string()));
+        assert_eq!(expand("%:r.o %:h %:t %:e #:r"), Ok("src/main.o src main.rs rs README".to_string()));
+        assert_eq!(expand(r"50\% \#1"), Ok("50% #1".to_string()));
+        assert_eq!(expand(r"a\b"), Ok(r"a\b".to_string()));
+        assert_eq!(
+            expand_file_names("#", Some("a"), None),
+            Err("No alternate file name to substitute for '#'".to_string())
+        );
+        assert_eq!(expand_file_names("%", None, None), Err("No file name to substitute for '%'".to_string()));
+    }
+
     #[test]
     fn test_new_controller() {
         let controller = CommandController::new();
@@ -1408,6 +3349,88 @@ mod tests {
         assert_eq!(result, ModeTransition::Quit);
     }
     
+    #[test]
+    fn test_quit_asks_about_other_unsaved_buffers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("b.txt");
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a");
+        let mut other = Document::from_string("b".to_string());
+        other.filename = Some(path.clone());
+        other.insert_text_at(0, 0, "x");
+        shared.session_controller.buffers.push(other);
+
+        // c cancels, leaving the editor open
+        type_command(&mut controller, "q", &mut shared);
+        assert_eq!(controller.handle_key(key_event(KeyCode::Enter), &mut shared), ModeTransition::Stay);
+        assert_eq!(
+            controller.get_command_buffer(),
+            "Unsaved changes in \"b.txt\": (w)rite all and quit, (q)uit anyway, (c)ancel?"
+        );
+        assert_eq!(controller.handle_key(key_event(KeyCode::Char('x')), &mut shared), ModeTransition::Stay);
+        assert_eq!(controller.handle_key(key_event(KeyCode::Char('c')), &mut shared), ModeTransition::ToMode(Mode::Normal));
+
+        // :qa names the buffers instead of asking; :qa! quits anyway
+        let message = controller.execute_batch("qa", &mut shared).unwrap_err();
+        assert_eq!(message, "No write since last change for \"b.txt\" (add ! to override)");
+        assert_eq!(controller.execute_batch("qa!", &mut shared), Ok(true));
+
+        // w writes them all, then quits
+        type_command(&mut controller, "q", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(controller.handle_key(key_event(KeyCode::Char('w')), &mut shared), ModeTransition::Quit);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xb");
+        assert!(UnsavedBuffers::list(&shared).is_empty());
+    }
+
+    #[test]
+    fn test_write_all_and_quit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a");
+        shared.session_controller.current_document_mut().insert_text_at(0, 0, "1");
+        let mut other = Document::new();
+        other.insert_text_at(0, 0, "2");
+        shared.session_controller.buffers.push(other);
+
+        // Every modified buffer needs a name
+        shared.session_controller.current_document_mut().filename = Some(dir.path().join("a.txt"));
+        assert_eq!(controller.execute_batch("wqa", &mut shared), Err("No file name for buffer 2".to_string()));
+        assert!(!shared.session_controller.current_document().is_modified());
+
+        shared.session_controller.buffers[1].filename = Some(dir.path().join("b.txt"));
+        assert_eq!(controller.execute_batch("xa", &mut shared), Ok(true));
+        assert_eq!(std::fs::read_to_string(dir.path().join("b.txt")).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_write_all_reports_each_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a");
+        assert_eq!(controller.execute_batch("wa", &mut shared), Ok(false));
+        assert_eq!(shared.status_message, "No buffers have unsaved changes");
+
+        for name in ["b.txt", "c.txt"] {
+            let mut doc = Document::new();
+            doc.filename = Some(dir.path().join(name));
+            doc.insert_text_at(0, 0, name);
+            shared.session_controller.buffers.push(doc);
+        }
+        shared.session_controller.current_document_mut().insert_text_at(0, 0, "x");
+        shared.session_controller.buffers[2].filename = Some(dir.path().join("missing/c.txt"));
+        let message = controller.execute_batch("wa", &mut shared).unwrap_err();
+        assert!(message.starts_with("No file name for buffer 1; Error saving \"c.txt\": "), "{}", message);
+        assert_eq!(std::fs::read_to_string(dir.path().join("b.txt")).unwrap(), "b.txt\n");
+
+        // :x on an unchanged buffer quits without writing it
+        shared.session_controller.buffers.truncate(2);
+        shared.session_controller.buffers[0].modified = false;
+        std::fs::remove_file(dir.path().join("b.txt")).unwrap();
+        assert_eq!(controller.execute_batch("x", &mut shared), Ok(true));
+        assert!(!dir.path().join("b.txt").exists());
+    }
+
     #[test]
     fn test_write_command() {
         let mut controller = CommandController::new();
@@ -1439,19 +3462,61 @@ mod tests {
         // Should attempt to save and quit (will fail save in test but return quit)
         assert_eq!(controller.command_buffer, "");
     }
-    
+    
+    #[test]
+    fn test_buffer_list_command() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+        
+        // Type "ls"
+        type_command(&mut controller, "ls", &mut shared);
+        let result = controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        
+        // The picker stays open in command mode
+        assert_eq!(result, ModeTransition::Stay);
+        assert_eq!(controller.get_command_buffer(), BufferPicker::PROMPT);
+        let popup = controller.popup(&shared).unwrap();
+        assert_eq!(popup.items, ["  1 %  [No Name]  1 line"]);
+
+        let result = controller.handle_key(key_event(KeyCode::Esc), &mut shared);
+        assert_eq!(result, ModeTransition::ToMode(Mode::Normal));
+        assert!(controller.popup(&shared).is_none());
+        // Test the actual buffer list format: "% 1: \"[No Name]\" "
+        assert!(shared.status_message.contains("[No Name]") || shared.status_message.contains("Buffer"));
+    }
+
     #[test]
-    fn test_buffer_list_command() {
+    fn test_buffer_picker_and_partial_name_switch() {
         let mut controller = CommandController::new();
         let mut shared = create_test_shared_state();
-        
-        // Type "ls"
+        for name in ["main.rs", "notes.md"] {
+            let mut doc = Document::new();
+            doc.filename = Some(std::path::PathBuf::from(name));
+            shared.session_controller.buffers.push(doc);
+        }
+
+        // j/k move through the list and Enter switches
         type_command(&mut controller, "ls", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        for code in [KeyCode::Char('j'), KeyCode::Char('j'), KeyCode::Char('j'), KeyCode::Char('k')] {
+            controller.handle_key(key_event(code), &mut shared);
+        }
+        assert_eq!(controller.popup(&shared).unwrap().selected, 1);
         let result = controller.handle_key(key_event(KeyCode::Enter), &mut shared);
-        
         assert_eq!(result, ModeTransition::ToMode(Mode::Normal));
-        // Test the actual buffer list format: "% 1: \"[No Name]\" "
-        assert!(shared.status_message.contains("[No Name]") || shared.status_message.contains("Buffer"));
+        assert_eq!(shared.session_controller.current_buffer, 1);
+
+        type_command(&mut controller, "b note", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.session_controller.current_buffer, 2);
+
+        type_command(&mut controller, "buffer 1", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.session_controller.current_buffer, 0);
+
+        type_command(&mut controller, "b xyz", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.status_message, "No matching buffer for xyz");
     }
     
     #[test]
@@ -1550,6 +3615,23 @@ mod tests {
         assert!(shared.status_message.is_empty() || !shared.status_message.contains("Error"));
     }
     
+    #[test]
+    fn test_colorscheme_command() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+
+        controller.execute_batch("colo solarized", &mut shared).unwrap();
+        assert_eq!(shared.view.theme(), &Theme::builtin("solarized").unwrap());
+        controller.execute_batch("colorscheme", &mut shared).unwrap();
+        assert!(shared.status_message.starts_with("solarized (built in: default, dark"));
+
+        assert_eq!(
+            controller.execute_batch("colorscheme no-such-theme", &mut shared),
+            Err("Cannot find color scheme 'no-such-theme'".to_string())
+        );
+        assert_eq!(shared.view.theme().name, "solarized");
+    }
+
     #[test]
     fn test_goto_line_command() {
         let mut controller = CommandController::new();
@@ -1579,6 +3661,27 @@ mod tests {
         assert!(cursor_line <= 2); // Should be at or before last line
     }
     
+    #[test]
+    fn test_stats_counts_substitutions_per_buffer() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("hello world\nhello there\nbye");
+        shared.session_controller.buffers[0].filename = Some(std::path::PathBuf::from("notes.txt"));
+        shared.session_controller.buffers.push(Document::from_string("hello".to_string()));
+
+        type_command(&mut controller, "%s/hello/hi/", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "stats", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+
+        assert_eq!(shared.status_message, "Session stats for 2 buffers in preview buffer");
+        let doc = shared.session_controller.current_document();
+        let report: Vec<String> = (0..doc.line_count()).filter_map(|line| doc.get_line(line)).collect();
+        assert_eq!(
+            report.join("\n"),
+            "  1 notes.txt: 0 searches, 1 :s, 2 lines changed\n  2 [No Name]: 0 searches, 0 :s, 0 lines changed\nBy file type:\n  .txt: 0 searches, 1 :s, 2 lines changed\n  (none): 0 searches, 0 :s, 0 lines changed\nTotal: 0 searches, 1 :s, 2 lines changed"
+        );
+    }
+
     #[test]
     fn test_substitute_command_current_line() {
         let mut controller = CommandController::new();
@@ -1593,6 +3696,109 @@ mod tests {
         assert_eq!(content, "hi world\nhello there");
     }
     
+    #[test]
+    fn test_abbreviated_command_names() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("hello world\nhello there");
+
+        controller.execute_command("sub/hello/hi/", &mut shared);
+        controller.execute_command("2substitute/hello/yo/", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "hi world\nyo there");
+
+        controller.execute_command("setl nu", &mut shared);
+        assert_eq!(shared.status_message, "Line numbers enabled");
+        controller.execute_command("se nonu", &mut shared);
+        assert_eq!(shared.status_message, "Line numbers disabled");
+
+        controller.execute_command("vsp", &mut shared);
+        assert!(shared.view.windows().is_split());
+        controller.execute_command("clo", &mut shared);
+        assert!(!shared.view.windows().is_split());
+
+        // Shorter than the shortest abbreviation
+        controller.execute_command("cl", &mut shared);
+        assert_eq!(shared.status_message, "Unknown command: cl");
+    }
+
+    #[test]
+    fn test_retab_range_and_new_tabstop() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("    a\t\"x\ty\"\n        b\n\tc");
+        shared.view.set_tab_stop(4);
+
+        // Only the indentation of lines 1-2 changes; the tab in the string stays
+        controller.execute_command("1,2retab", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "\ta\t\"x\ty\"\n\t\tb\n\tc");
+        assert_eq!(shared.status_message, "2 lines retabbed");
+
+        controller.execute_command("detab 2", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "    a\t\"x\ty\"\n        b\n    c");
+        assert_eq!(shared.view.get_tab_stop(), 2);
+
+        controller.execute_command("retab", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "\t\ta\t\"x\ty\"\n\t\t\t\tb\n\t\tc");
+
+        // One undo step for the whole command
+        UndoCommands::earlier(&mut shared, UndoDistance::Changes(1));
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "    a\t\"x\ty\"\n        b\n    c");
+
+        controller.execute_command("retab 0", &mut shared);
+        assert_eq!(shared.status_message, "Tab width must be between 1 and 16");
+    }
+
+    #[test]
+    fn test_sort_uniq_and_reverse_ranges() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("pear\n10 b\n9 a\npear\napple");
+
+        controller.execute_command("%sort u", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "10 b\n9 a\napple\npear");
+        assert_eq!(shared.status_message, "5 lines sorted, 1 duplicates removed");
+
+        controller.execute_command("1,2sor n", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "9 a\n10 b\napple\npear");
+
+        controller.execute_command("sort!", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "pear\napple\n9 a\n10 b");
+
+        controller.execute_command("2,3reverse", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "pear\n9 a\napple\n10 b");
+        assert_eq!(shared.status_message, "2 lines reversed");
+        assert_eq!(shared.session_controller.current_document().cursor_line(), 1);
+
+        // One undo step for the whole command
+        UndoCommands::earlier(&mut shared, UndoDistance::Changes(1));
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "pear\napple\n9 a\n10 b");
+
+        controller.execute_command("sort x", &mut shared);
+        assert_eq!(shared.status_message, "Invalid argument: x");
+    }
+
+    #[test]
+    fn test_uniq_removes_adjacent_repeats() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a\na\nA\nb\na");
+
+        controller.execute_command("uniq", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a\nA\nb\na");
+        assert_eq!(shared.status_message, "1 duplicate line removed");
+
+        controller.execute_command("uniq i", &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a\nb\na");
+    }
+
     #[test]
     fn test_substitute_command_global() {
         let mut controller = CommandController::new();
@@ -1607,6 +3813,297 @@ mod tests {
         assert_eq!(content, "hi world\nhi there");
     }
     
+    #[test]
+    fn test_substitute_joins_and_splits_lines() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a,\nb,\nc\nd");
+
+        // The match starting on the last line of the range may reach past it
+        type_command(&mut controller, "1,2s/,\\n/, /", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a, b, c\nd");
+        assert_eq!(shared.status_message, "2 substitutions made");
+
+        type_command(&mut controller, "s/, /\\r/g", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a\nb\nc\nd");
+
+        type_command(&mut controller, "%s/$/;/", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "%s/^b/B/", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a;\nB;\nc;\nd;");
+    }
+
+    #[test]
+    fn test_substitute_count_case_and_repeat_flags() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("Foo foo\nfoo\nbar");
+
+        type_command(&mut controller, "%s/foo/x/gn", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.status_message, "2 matches on 2 lines");
+
+        type_command(&mut controller, "%s/foo/x/gin", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.status_message, "3 matches on 2 lines");
+
+        // :& drops the flags (first match only), :&& keeps them
+        type_command(&mut controller, "s/foo/x/gi", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "2&", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "x x\nx\nbar");
+
+        let mut shared = create_test_shared_state_with_content("Foo foo");
+        type_command(&mut controller, "s/foo/x/", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        shared.last_substitute.as_mut().unwrap().flags.ignore_case = true;
+        type_command(&mut controller, "&&", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "x x");
+    }
+
+    #[test]
+    fn test_batch_commands_report_failures() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("foo\nbar");
+
+        assert_eq!(controller.execute_batch("%s/foo/x/", &mut shared), Ok(false));
+        assert_eq!(
+            controller.execute_batch("%s/foo/x/", &mut shared),
+            Err("Pattern not found: foo".to_string())
+        );
+        // The e flag, and :s run by :g on lines without a match, are not failures
+        assert_eq!(controller.execute_batch("%s/foo/x/e", &mut shared), Ok(false));
+        assert_eq!(controller.execute_batch("g/bar/s/zz/y/", &mut shared), Ok(false));
+        assert_eq!(controller.execute_batch("s/x/y/gc", &mut shared).map_err(|_| ()), Err(()));
+        assert_eq!(controller.execute_batch("frob", &mut shared), Err("Unknown command: frob".to_string()));
+        assert_eq!(controller.execute_batch("q!", &mut shared), Ok(true));
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "x\nbar");
+    }
+
+    #[test]
+    fn test_grep_fills_quickfix_list() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\nfoo here\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "foo\nbar foo\n").unwrap();
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+        let cursor = |shared: &SharedEditorState| {
+            let doc = shared.session_controller.current_document();
+            (doc.filename.as_ref().unwrap().file_name().unwrap().to_string_lossy().to_string(), doc.cursor_line(), doc.cursor_column())
+        };
+
+        let glob = format!("{}/*.txt", dir.path().display());
+        assert_eq!(controller.execute_batch(&format!("grep foo {}", glob), &mut shared), Ok(false));
+        assert_eq!(cursor(&shared), ("a.txt".to_string(), 1, 0));
+        assert_eq!(shared.status_message, "(1 of 3): foo here");
+
+        controller.execute_batch("cn 2", &mut shared).unwrap();
+        assert_eq!(cursor(&shared), ("b.txt".to_string(), 1, 4));
+        controller.execute_batch("cnext", &mut shared).unwrap();
+        assert_eq!(shared.status_message, "No more items");
+        controller.execute_batch("cp", &mut shared).unwrap();
+        assert_eq!(cursor(&shared), ("b.txt".to_string(), 0, 0));
+
+        // Without files, :grep searches the open buffers
+        assert_eq!(controller.execute_batch("grep -i HERE", &mut shared), Ok(false));
+        assert_eq!(cursor(&shared), ("a.txt".to_string(), 1, 4));
+        assert_eq!(controller.execute_batch("grep zzz", &mut shared), Err("No matches for zzz".to_string()));
+
+        // :copen lists the matches to pick from
+        controller.execute_batch(&format!("grep foo {}", glob), &mut shared).unwrap();
+        type_command(&mut controller, "copen", &mut shared);
+        assert_eq!(controller.handle_key(key_event(KeyCode::Enter), &mut shared), ModeTransition::Stay);
+        assert_eq!(controller.get_command_buffer(), QuickfixPicker::PROMPT);
+        let popup = controller.popup(&shared).unwrap();
+        assert_eq!(popup.items.len(), 3);
+        assert!(popup.items[2].ends_with("b.txt:2:5: bar foo"));
+        controller.handle_key(key_event(KeyCode::Char('G')), &mut shared);
+        let result = controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(result, ModeTransition::ToMode(Mode::Normal));
+        assert_eq!(cursor(&shared), ("b.txt".to_string(), 1, 4));
+        assert!(controller.popup(&shared).is_none());
+    }
+
+    #[test]
+    fn test_checktime_asks_or_autoreads() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+        for path in [&a, &b] {
+            std::fs::write(path, "old").unwrap();
+            shared.session_controller.buffers.push(Document::from_file(path.clone()).unwrap());
+        }
+        let change = |path: &std::path::Path| {
+            std::fs::write(path, "new").unwrap();
+            let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+            std::fs::File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+        };
+        let line = |shared: &SharedEditorState, index: usize| shared.session_controller.buffers[index].get_line(0);
+
+        type_command(&mut controller, "checktime", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.status_message, "No files changed on disk");
+
+        // Each changed file is asked about in turn
+        change(&a);
+        change(&b);
+        type_command(&mut controller, "checkt", &mut shared);
+        assert_eq!(controller.handle_key(key_event(KeyCode::Enter), &mut shared), ModeTransition::Stay);
+        assert!(controller.get_command_buffer().contains("a.txt\" changed on disk: (l)oad file"));
+        controller.handle_key(key_event(KeyCode::Char('l')), &mut shared);
+        assert!(controller.get_command_buffer().contains("b.txt"));
+        let result = controller.handle_key(key_event(KeyCode::Char('k')), &mut shared);
+        assert_eq!(result, ModeTransition::ToMode(Mode::Normal));
+        assert_eq!((line(&shared, 1), line(&shared, 2)), (Some("new".to_string()), Some("old".to_string())));
+        assert!(!controller.check_files(&mut shared));
+
+        // 'autoread' reloads unmodified buffers but still asks about modified ones
+        controller.execute_batch("set autoread", &mut shared).unwrap();
+        change(&a);
+        change(&b);
+        shared.session_controller.buffers[2].modified = true;
+        assert!(controller.check_files(&mut shared));
+        assert_eq!(line(&shared, 1), Some("new".to_string()));
+        assert!(shared.status_message.ends_with("a.txt\" reloaded"));
+        assert!(controller.get_command_buffer().contains("(buffer has unsaved changes)"));
+    }
+
+    #[test]
+    fn test_writeprg_filters_saved_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("hello\nworld");
+        shared.session_controller.current_document_mut().filename = Some(path.clone());
+
+        type_command(&mut controller, "set writeprg=tr\\ a-z\\ A-Z", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "w", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "HELLO\nWORLD");
+        // Only the file is filtered, not the buffer
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "hello\nworld");
+
+        // A failing filter leaves the file alone and shows its stderr
+        shared.session_controller.current_document_mut().modified = true;
+        let result = controller.execute_batch("set wp=echo\\ broken\\ >&2;\\ exit\\ 3", &mut shared)
+            .and_then(|_| controller.execute_batch("w", &mut shared));
+        let message = result.unwrap_err();
+        assert!(message.starts_with("Error saving file: writeprg `echo broken >&2; exit 3` failed"));
+        assert!(message.ends_with("file not written\nbroken"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "HELLO\nWORLD");
+        assert!(shared.session_controller.current_document().is_modified());
+
+        type_command(&mut controller, "set writeprg=", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.session_controller.current_document().write_program, None);
+    }
+
+    #[test]
+    fn test_format_replaces_only_changed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("keep\nfix me\nkeep too\nfix\nlast");
+        shared.session_controller.current_document_mut().filename = Some(path.clone());
+        assert_eq!(controller.execute_batch("format", &mut shared), Err("'formatprg' is not set".to_string()));
+
+        let doc = shared.session_controller.current_document_mut();
+        doc.set_local_mark('a', 2, 3).unwrap();
+        doc.set_cursor(4, 2).unwrap();
+        controller.execute_batch("set fp=sed\\ s/^fix/fixed/", &mut shared).unwrap();
+        controller.execute_batch("format", &mut shared).unwrap();
+        assert_eq!(buffer_text(&mut shared), "keep\nfixed me\nkeep too\nfixed\nlast");
+        assert_eq!(shared.status_message, "2 lines formatted");
+        let doc = shared.session_controller.current_document_mut();
+        assert_eq!((doc.get_local_mark('a'), doc.cursor_line(), doc.cursor_column()), (Some((2, 3)), 4, 2));
+        let group = doc.undo_manager_mut().undo().unwrap();
+        group.apply_reverse_to_document(doc);
+        assert_eq!(buffer_text(&mut shared), "keep\nfix me\nkeep too\nfix\nlast");
+
+        // Lines the formatter adds move the marks and the cursor below them
+        controller.execute_batch("set fp=(echo\\ new;\\ cat)", &mut shared).unwrap();
+        controller.execute_batch("2,3format", &mut shared).unwrap();
+        assert_eq!(buffer_text(&mut shared), "keep\nnew\nfix me\nkeep too\nfix\nlast");
+        let doc = shared.session_controller.current_document();
+        assert_eq!((doc.get_local_mark('a'), doc.cursor_line()), (Some((3, 3)), 5));
+
+        // 'formatonsave' formats the buffer before it is written
+        controller.execute_batch("set fp=tr\\ a-z\\ A-Z", &mut shared).unwrap();
+        controller.execute_batch("set formatonsave", &mut shared).unwrap();
+        controller.execute_batch("w", &mut shared).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "KEEP\nNEW\nFIX ME\nKEEP TOO\nFIX\nLAST");
+        assert_eq!(buffer_text(&mut shared), "KEEP\nNEW\nFIX ME\nKEEP TOO\nFIX\nLAST");
+
+        // A formatter that fails stops the write
+        shared.session_controller.current_document_mut().modified = true;
+        controller.execute_batch("set fp=echo\\ bad\\ syntax\\ >&2;\\ exit\\ 1", &mut shared).unwrap();
+        let message = controller.execute_batch("w", &mut shared).unwrap_err();
+        assert!(message.ends_with("failed (exit status: 1): bad syntax, file not written"), "{message}");
+        assert!(shared.session_controller.current_document().is_modified());
+    }
+
+    #[test]
+    fn test_set_fileformat_and_endofline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\n");
+        shared.session_controller.current_document_mut().filename = Some(path.clone());
+
+        controller.execute_batch("set fileformat=dos", &mut shared).unwrap();
+        controller.execute_batch("set ff", &mut shared).unwrap();
+        assert_eq!(shared.status_message, "fileformat=dos");
+        controller.execute_batch("w", &mut shared).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\r\nb\r\n");
+
+        controller.execute_batch("set ff=mac", &mut shared).unwrap();
+        controller.execute_batch("set noeol", &mut shared).unwrap();
+        controller.execute_batch("w", &mut shared).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\rb");
+
+        let result = controller.execute_batch("set ff=amiga", &mut shared);
+        assert_eq!(result.unwrap_err(), "Invalid fileformat: amiga (unix, dos or mac)");
+    }
+
+    #[test]
+    fn test_set_fileencoding_converts_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("café\n");
+        shared.session_controller.current_document_mut().filename = Some(path.clone());
+
+        controller.execute_batch("set fenc=latin1", &mut shared).unwrap();
+        controller.execute_batch("w", &mut shared).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xe9\n");
+
+        controller.execute_batch("set fileencoding=utf-16le", &mut shared).unwrap();
+        controller.execute_batch("set fenc", &mut shared).unwrap();
+        assert_eq!(shared.status_message, "fileencoding=utf-16le (with BOM)");
+        controller.execute_batch("w", &mut shared).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [&[0xFF, 0xFE][..], b"c\0a\0f\0\xe9\0\n\0"].concat());
+
+        // Text latin1 can't hold isn't written
+        shared.session_controller.current_document_mut().insert_text_at(0, 0, "✓");
+        controller.execute_batch("set fenc=latin1", &mut shared).unwrap();
+        let message = controller.execute_batch("w", &mut shared).unwrap_err();
+        assert!(message.ends_with("can't write '✓' in latin1 (line 1)"), "{}", message);
+        assert!(controller.execute_batch("set fenc=ebcdic", &mut shared).is_err());
+    }
+
     #[test]
     fn test_delete_lines_command() {
         let mut controller = CommandController::new();
@@ -1655,6 +4152,26 @@ mod tests {
         assert!(shared.status_message.contains("A line 1, col 1"));
     }
     
+    #[test]
+    fn test_registers_command() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+        shared.register_manager.store_in_register(Some('a'), "one\ttwo".to_string(), RegisterType::Character);
+        shared.register_manager.store_in_register(Some('b'), "line".to_string(), RegisterType::Line);
+
+        type_command(&mut controller, "reg ab", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+
+        assert_eq!(shared.status_message, "2 registers listed in preview buffer");
+        let doc = shared.session_controller.current_document();
+        assert_eq!(doc.get_line(1).unwrap(), "  c  \"a   one^Itwo");
+        assert_eq!(doc.get_line(2).unwrap(), "  l  \"b   line^J");
+
+        type_command(&mut controller, "display z", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(shared.status_message, "No registers to show");
+    }
+
     #[test]
     fn test_delmarks_command() {
         let mut controller = CommandController::new();
@@ -1743,4 +4260,147 @@ mod tests {
         assert_eq!(shared.session_controller.current_document().filename, 
                    Some(PathBuf::from("newfile.txt")));
     }
-}
\ No newline at end of file
+    
+    fn buffer_text(shared: &mut SharedEditorState) -> String {
+        shared.session_controller.current_document_mut().text_buffer_mut().get_text()
+    }
+    
+    #[test]
+    fn test_global_delete_is_one_undo_step() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("foo 1\nbar\nfoo 2\nfoo 3\nbaz");
+        
+        type_command(&mut controller, "g/foo/d", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "bar\nbaz");
+        assert_eq!(shared.status_message, "3 fewer lines");
+        
+        let doc = shared.session_controller.current_document_mut();
+        let group = doc.undo_manager_mut().undo().unwrap();
+        group.apply_reverse_to_document(doc);
+        assert_eq!(buffer_text(&mut shared), "foo 1\nbar\nfoo 2\nfoo 3\nbaz");
+    }
+    
+    #[test]
+    fn test_vglobal_and_substitute() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("keep a\ndrop a\nkeep b\ndrop b");
+        
+        type_command(&mut controller, "v/^keep/d", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "keep a\nkeep b");
+        
+        // Spaces in the pattern and a range limiting the search
+        type_command(&mut controller, "2g/keep b/s/keep/kept/", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "keep a\nkept b");
+    }
+    
+    #[test]
+    fn test_global_move_reverses_lines() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("1\n2\n3\n4");
+        
+        type_command(&mut controller, "g/^/m 0", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "4\n3\n2\n1");
+    }
+    
+    #[test]
+    fn test_global_rejects_nesting_and_bad_patterns() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb");
+        
+        type_command(&mut controller, "g/a/g/b/d", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "a\nb");
+        
+        type_command(&mut controller, "g/(/d", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert!(shared.status_message.starts_with("Invalid pattern"));
+    }
+    
+    #[test]
+    fn test_file_locked_elsewhere_opens_read_only() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state();
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("shared.txt");
+        std::fs::write(&file, "original").unwrap();
+        // Pretend vim has the file open
+        std::fs::write(dir.path().join(".shared.txt.swp"), "").unwrap();
+        
+        type_command(&mut controller, &format!("e {}", file.display()), &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert!(shared.session_controller.current_document().read_only);
+        assert!(shared.status_message.contains("read-only"));
+        
+        // Plain :w is refused, :w! writes anyway
+        shared.session_controller.current_document_mut().insert_char('x');
+        type_command(&mut controller, "w", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "original");
+        
+        type_command(&mut controller, "w!", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "xoriginal");
+        assert!(!shared.session_controller.current_document().read_only);
+    }
+    
+    #[test]
+    fn test_wrap_range_uses_textwidth_and_undoes_in_one_step() {
+        let mut controller = CommandController::new();
+        let original = "one two three four five\nshort\n  six seven eight nine";
+        let mut shared = create_test_shared_state_with_content(original);
+        
+        type_command(&mut controller, "set tw=10", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "%Wrap", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(
+            buffer_text(&mut shared),
+            "one two\nthree four\nfive\nshort\n  six\n  seven\n  eight\n  nine"
+        );
+        
+        let doc = shared.session_controller.current_document_mut();
+        let group = doc.undo_manager_mut().undo().unwrap();
+        group.apply_reverse_to_document(doc);
+        assert_eq!(buffer_text(&mut shared), original);
+        
+        // An explicit width wins over textwidth; without a range only the current line wraps
+        type_command(&mut controller, "Wrap 15", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        assert_eq!(buffer_text(&mut shared), "one two three\nfour five\nshort\n  six seven eight nine");
+    }
+    
+    #[test]
+    fn test_pastewrap_wraps_pasted_lines() {
+        let mut controller = CommandController::new();
+        let mut shared = create_test_shared_state_with_content("first");
+        shared.register_manager.store_in_register(
+            None,
+            "alpha beta gamma delta".to_string(),
+            crate::document_model::RegisterType::Line,
+        );
+        
+        type_command(&mut controller, "set textwidth=11", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        type_command(&mut controller, "set pastewrap", &mut shared);
+        controller.handle_key(key_event(KeyCode::Enter), &mut shared);
+        shared.session_controller.paste_text(
+            crate::controller::yank_paste::PasteType::After,
+            None,
+            1,
+            4,
+            &mut shared.register_manager,
+            &mut shared.status_message,
+        );
+        assert_eq!(buffer_text(&mut shared), "first\nalpha beta\ngamma delta");
+        
+        // The wrapped paste is a single undo step
+        let doc = shared.session_controller.current_document_mut();
+        let group = doc.undo_manager_mut().undo().unwrap();
+        group.apply_reverse_to_document(doc);
+        assert_eq!(buffer_text(&mut shared), "first");
+    }
+}
@@ -1,3 +1,5 @@
+use crate::controller::operator::{Motion, Operator};
+
 #[derive(Debug)]
 pub enum Command {
     // Basic movement
@@ -30,26 +32,34 @@ pub enum Command {
     MoveHalfPageDown,
 
     // Line jumping
-    #[allow(dead_code)] // Will be wired up in key handler
-    MoveToLine(usize),
+    MoveToLine(usize), // 1-based line number (5G, 5gg)
 
     // Screen positioning
     MoveToScreenTop,    // H
     MoveToScreenMiddle, // M
     MoveToScreenBottom, // L
 
+    // Vertical scrolling: the view moves, the cursor only to stay on screen
+    ScrollLinesDown, // Ctrl-E - count of lines
+    ScrollLinesUp,   // Ctrl-Y
+    ScrollCursorTop,    // zt - the cursor's line to the top of the window
+    ScrollCursorMiddle, // zz
+    ScrollCursorBottom, // zb
+
+    // Horizontal scrolling
+    ScrollRight(usize),   // zl - count of columns
+    ScrollLeft(usize),    // zh
+    ScrollHalfWidthRight, // zL
+    ScrollHalfWidthLeft,  // zH
+
     // Bracket matching
     MatchBracket, // %
 
-    // Character search
-    #[allow(dead_code)] // Will be wired up in key handler
-    FindChar(char),
-    #[allow(dead_code)] // Will be wired up in key handler
-    FindCharBackward(char),
-    #[allow(dead_code)] // Will be wired up in key handler
-    FindCharBefore(char),
-    #[allow(dead_code)] // Will be wired up in key handler
-    FindCharBeforeBackward(char),
+    // Character search - the target and count (3fx)
+    FindChar(char, usize),
+    FindCharBackward(char, usize),
+    FindCharBefore(char, usize),
+    FindCharBeforeBackward(char, usize),
     RepeatFind,
     RepeatFindReverse,
 
@@ -59,6 +69,22 @@ pub enum Command {
     JumpToMarkLine(char),
     JumpBackward,
     JumpForward,
+    ChangeListOlder(usize), // g; : back to where an earlier change happened
+    ChangeListNewer(usize), // g, : forward again
+
+    // Tags: Ctrl-] jumps to the definition of the word under the cursor,
+    // Ctrl-T goes back (count times)
+    JumpToTag,
+    PopTag,
+
+    // Ctrl-^: edit the alternate file, the one edited before this in the
+    // window; N Ctrl-^ edits buffer N
+    AlternateFile(Option<usize>),
+
+    // Language servers: gd goes to the definition of the symbol under the
+    // cursor, K shows what the server says about it
+    GotoDefinition,
+    Hover,
 
     // Insert modes
     EnterInsertMode,
@@ -68,95 +94,132 @@ pub enum Command {
     EnterInsertModeLineEnd,
     EnterInsertModeLineStart,
 
-    // Indentation commands
-    IndentLine,
-    IndentLines(usize), // count of lines
-    DedentLine,
-    DedentLines(usize), // count of lines
-
     // Search commands
     EnterSearchMode,
     EnterSearchBackwardMode,
     SearchNext,
     SearchPrevious,
-    SearchWordUnderCursor,         // *
-    SearchWordUnderCursorBackward, // #
+    SearchWordUnderCursor(bool),         // * as a whole word; g* (false) inside other words too
+    SearchWordUnderCursorBackward(bool), // # and g#
 
     // Other commands
     EnterCommandMode,
+    OpenHistory(char), // q: q/ q? - the history of that prompt in a buffer
     InsertChar(char),
     InsertNewline,
     InsertTab,
+    InsertDeleteWord,    // Ctrl-W
+    InsertDeleteToStart, // Ctrl-U: back to where the insert began
+    InsertShift(bool),   // Ctrl-T (true) indents the line, Ctrl-D dedents it
+    InsertRegister(char), // Ctrl-R {register}
+    InsertLiteral,        // Ctrl-V: the next key as it is, or a character by its code
+    InsertDigraph,        // Ctrl-K {char}{char}
     DeleteChar,
-    DeleteCharForward,
-    DeleteCharBackward,
-    DeleteLine,
-    DeleteLines(usize), // count of lines
-    DeleteToEndOfLine,
-    DeleteWord,
-    DeleteBigWord,
-    DeleteWordBackward,
-    DeleteBigWordBackward,
-    DeleteToEndOfWord,
-    DeleteToEndOfBigWord,
-    DeleteToStartOfLine,
-    DeleteToFirstNonWhitespace,
-    DeleteToEndOfFile,
-    DeleteToStartOfFile,
-    SubstituteChar,
-    SubstituteLine,
-    DeleteUntilChar(char),
-    DeleteUntilCharBackward(char),
-    DeleteFindChar(char),
-    DeleteFindCharBackward(char),
-
-    // Change commands (delete + enter insert mode)
-    ChangeLine,
-    ChangeLines(usize),
-    ChangeToEndOfLine,
-    ChangeWord,
-    ChangeBigWord,
-    ChangeWordBackward,
-    ChangeBigWordBackward,
-    ChangeToEndOfWord,
-    ChangeToEndOfBigWord,
-    ChangeToStartOfLine,
-    ChangeToFirstNonWhitespace,
-    ChangeToEndOfFile,
-    ChangeToStartOfFile,
-    ChangeUntilChar(char),
-    ChangeUntilCharBackward(char),
-    ChangeFindChar(char),
-    ChangeFindCharBackward(char),
-
-    // Yank and paste commands (simplified)
-    Yank(crate::controller::yank_paste::YankType, Option<char>),
-    Paste(crate::controller::yank_paste::PasteType, Option<char>),
+    DeleteCharForward(Option<char>, usize), // register, count of characters
+    DeleteCharBackward(Option<char>, usize),
+    SubstituteChar(Option<char>, usize),
+
+    // An operator over a motion (d3w, gUe, >G): count, register
+    Operate(Operator, Motion, usize, Option<char>),
+
+    // Surroundings: ys{motion}{char} adds them; cs{target}{char} changes
+    // and ds{target} (None) deletes them
+    Surround(Motion, usize, char),
+    ChangeSurround(char, Option<char>),
+
+    // Paste commands
+    Paste(crate::controller::yank_paste::PasteType, Option<char>, usize), // count of copies
+    YankPop(usize), // Ctrl-P after a paste: count of history entries to step back
 
     // Visual mode commands
     EnterVisualChar,
     EnterVisualLine,
     EnterVisualBlock,
     ExitVisualMode,
-    VisualDelete,
-    VisualIndent,
-    VisualDedent,
-    VisualYank,
+    VisualOperate(Operator), // An operator over the selection
+    Motion(Motion),          // Extend the selection
+    ToggleBlockComment,      // gb
+    VisualBlockInsert,       // I in block mode
+    VisualBlockAppend,       // A in block mode
+    VisualReplace(char),     // r{char}: every selected character becomes char
+    VisualJoin,              // J: join the selected lines
+    VisualPut(bool),         // p, or P (true) which keeps the register: paste over the selection
+    VisualSwapEnds(bool),    // o, or O (true) which keeps to the row in block mode
+    ReselectVisual,          // gv: select the buffer's last visual selection again
 
     ExitInsertMode,
     Redraw,
 
+    // Repeat the last command line (@:) count times
+    RepeatCommandLine(usize),
+
+    // ZZ writes if modified and quits (:x); ZQ quits without writing (:q!)
+    WriteQuit,
+    QuitWithoutWriting,
+
+    // Ctrl-Z stops the editor and goes back to the shell (:stop)
+    Suspend,
+
+    // Repeat the last :s on the current line (&)
+    RepeatSubstitute,
+
+    // Split windows (Ctrl-W followed by a key)
+    Window(char),
+
+    // Diff mode: ]c and [c move to the next or previous change (count);
+    // do takes the other side's version of a change, dp gives it this one
+    NextChange(usize),
+    PreviousChange(usize),
+    DiffObtain,
+    DiffPut,
+
     // Line operations
-    JoinLines,
+    JoinLines(bool), // J, or gJ (false) which adds no spaces and keeps indent
 
     // Case operations
     ToggleCase,
-    Lowercase,
-    Uppercase,
 
     // Undo/Redo commands
     Undo,
     Redo,
+    UndoTreeBackward(usize), // g- : previous state in time, across branches
+    UndoTreeForward(usize),  // g+ : next state in time
+}
+
+impl Command {
+    /// True for commands that change the text (or start typing it), which
+    /// the help buffer refuses
+    pub fn changes_text(&self) -> bool {
+        match self {
+            Command::Operate(operator, ..) | Command::VisualOperate(operator) => *operator != Operator::Yank,
+            Command::EnterInsertMode
+            | Command::EnterInsertModeAfter
+            | Command::EnterInsertModeNewLine
+            | Command::EnterInsertModeNewLineAbove
+            | Command::EnterInsertModeLineEnd
+            | Command::EnterInsertModeLineStart
+            | Command::DeleteChar
+            | Command::DeleteCharForward(..)
+            | Command::DeleteCharBackward(..)
+            | Command::SubstituteChar(..)
+            | Command::Surround(..)
+            | Command::ChangeSurround(..)
+            | Command::Paste(..)
+            | Command::YankPop(_)
+            | Command::ToggleBlockComment
+            | Command::VisualBlockInsert
+            | Command::VisualBlockAppend
+            | Command::VisualReplace(_)
+            | Command::VisualJoin
+            | Command::VisualPut(_)
+            | Command::RepeatSubstitute
+            | Command::DiffObtain
+            | Command::DiffPut
+            | Command::JoinLines(_)
+            | Command::ToggleCase => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
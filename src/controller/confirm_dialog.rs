@@ -0,0 +1,70 @@
+//! 'confirm' - instead of failing, :q with unsaved changes and :e leaving
+//! a modified buffer ask whether to save it, and :w name asks before
+//! writing over an existing file
+
+use crate::controller::shared_state::SharedEditorState;
+use crate::controller::unsaved_buffers::UnsavedBuffers;
+use std::path::Path;
+
+/// What the question is about
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogKind {
+    SaveChanges, // The current buffer is modified: (y)es, (n)o, (a)ll, (c)ancel
+    Overwrite,   // The file exists: (y)es, (n)o
+}
+
+/// What an answer to the question leads to
+#[derive(Debug, PartialEq)]
+pub enum DialogAnswer {
+    Asking,
+    Proceed, // Run the command line, without asking again
+    Cancel,
+}
+
+/// A question waiting for its answer, and the command line it stopped
+pub struct ConfirmDialog {
+    pub prompt: String,
+    pub command_line: String,
+    kind: DialogKind,
+}
+
+impl ConfirmDialog {
+    pub fn new(shared: &SharedEditorState, kind: DialogKind, command_line: String, path: Option<&Path>) -> Self {
+        let prompt = match (&kind, path) {
+            (DialogKind::Overwrite, Some(path)) => format!("Overwrite existing file \"{}\"? (y)es, (n)o", path.display()),
+            _ => format!(
+                "Save changes to \"{}\"? (y)es, (n)o, (a)ll, (c)ancel",
+                shared.session_controller.get_display_filename()
+            ),
+        };
+        Self { prompt, command_line, kind }
+    }
+
+    /// y saves the current buffer (or writes over the file) and a every
+    /// modified buffer before going on; n goes on without saving, except
+    /// that it leaves an existing file alone. A failed save cancels with
+    /// the error on the status line.
+    pub fn handle_key(&self, key: char, shared: &mut SharedEditorState) -> DialogAnswer {
+        match (&self.kind, key) {
+            (DialogKind::Overwrite, 'y') => DialogAnswer::Proceed,
+            (DialogKind::Overwrite, 'n' | 'c') => DialogAnswer::Cancel,
+            (DialogKind::SaveChanges, 'y') => match shared.session_controller.current_document_mut().save() {
+                Ok(_) => DialogAnswer::Proceed,
+                Err(e) => {
+                    shared.status_message = format!("Error saving file: {}", e);
+                    DialogAnswer::Cancel
+                }
+            },
+            (DialogKind::SaveChanges, 'a') => match UnsavedBuffers::write_all(shared) {
+                Ok(_) => DialogAnswer::Proceed,
+                Err(message) => {
+                    shared.status_message = message;
+                    DialogAnswer::Cancel
+                }
+            },
+            (DialogKind::SaveChanges, 'n') => DialogAnswer::Proceed,
+            (DialogKind::SaveChanges, 'c') => DialogAnswer::Cancel,
+            _ => DialogAnswer::Asking,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Crashes - a panic gives the terminal back before its message is printed,
+//! then the work not yet saved is kept: each modified buffer with a file is
+//! written beside it as `name.recovered`, and a crash log in the temp
+//! directory holds the message and the text of every modified buffer. Swap
+//! files from a panic are left where they are for `vi-rus -r`.
+
+use crate::document_model::Document;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+/// The message of the last panic on the main thread, kept for the crash log
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Put the panic hook in place. A panic on the main thread runs `restore`
+/// first, so the message lands on the shell's screen rather than the
+/// alternate screen the editor was drawing on.
+pub fn install_panic_hook(restore: fn() -> io::Result<()>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() == Some("main") {
+            let _ = restore();
+            if let Ok(mut last) = LAST_PANIC.lock() {
+                *last = Some(info.to_string());
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// What the panic hook last saw
+pub fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().ok().and_then(|last| last.clone())
+}
+
+/// What was kept after a crash
+#[derive(Debug, Default)]
+pub struct CrashReport {
+    pub log: Option<PathBuf>,
+    pub recovered: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>, // Buffers whose .recovered file couldn't be written
+}
+
+impl CrashReport {
+    /// Tell the shell where everything went. `swap_kept` is true when the
+    /// swap files outlive the editor, as they do after a panic.
+    pub fn print(&self, swap_kept: bool) {
+        match &self.log {
+            Some(log) => eprintln!("vi-rus: crash log written to {}", log.display()),
+            None => eprintln!("vi-rus: the crash log couldn't be written"),
+        }
+        for path in &self.recovered {
+            eprintln!("vi-rus: unsaved changes written to {}", path.display());
+        }
+        for (path, error) in &self.failed {
+            eprintln!("vi-rus: can't write {}: {}", path.display(), error);
+        }
+        if swap_kept {
+            eprintln!("vi-rus: swap files were kept; vi-rus -r lists them");
+        }
+    }
+}
+
+/// Where a buffer's file is saved after a crash
+pub fn recovered_path(filename: &Path) -> PathBuf {
+    let mut path = filename.as_os_str().to_owned();
+    path.push(".recovered");
+    PathBuf::from(path)
+}
+
+/// Write every modified buffer with a file to `name.recovered`, in the
+/// buffer's own encoding where it can be, and a crash log in `log_dir`
+/// with `message` and the text of each modified buffer
+pub fn save_unsaved_work(buffers: &[Document], message: &str, log_dir: &Path) -> CrashReport {
+    let mut report = CrashReport::default();
+    let mut log = format!("vi-rus crashed: {}\n", message);
+
+    for document in buffers.iter().filter(|document| document.is_modified() && document.directory.is_none()) {
+        let content = document.file_content();
+        let name = document.filename.as_ref().map_or_else(|| "[No Name]".to_string(), |f| f.display().to_string());
+        log.push_str(&format!("\n==== {} ====\n{}", name, content));
+        if !content.ends_with('\n') {
+            log.push('\n');
+        }
+
+        let Some(filename) = &document.filename else {
+            continue;
+        };
+        let path = recovered_path(filename);
+        let bytes = document.encoding.encode(&content, document.bom).unwrap_or_else(|_| content.clone().into_bytes());
+        match fs::write(&path, bytes) {
+            Ok(()) => report.recovered.push(path),
+            Err(e) => report.failed.push((path, e.to_string())),
+        }
+    }
+
+    let log_path = log_dir.join(format!("vi-rus-crash-{}.log", std::process::id()));
+    if fs::write(&log_path, log).is_ok() {
+        report.log = Some(log_path);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_unsaved_work() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("notes.txt");
+
+        let mut named = Document::new();
+        named.filename = Some(file.clone());
+        named.insert_char('x');
+        let mut unnamed = Document::new();
+        unnamed.insert_char('y');
+        let mut saved = Document::new();
+        saved.filename = Some(dir.path().join("saved.txt"));
+
+        let report = save_unsaved_work(&[named, unnamed, saved], "boom", dir.path());
+        assert_eq!(report.recovered, vec![dir.path().join("notes.txt.recovered")]);
+        assert!(report.failed.is_empty());
+        assert!(fs::read_to_string(&report.recovered[0]).unwrap().starts_with('x'));
+        assert!(!dir.path().join("saved.txt.recovered").exists());
+
+        let log = fs::read_to_string(report.log.unwrap()).unwrap();
+        assert!(log.starts_with("vi-rus crashed: boom\n"));
+        assert!(log.contains(&format!("==== {} ====\nx", file.display())));
+        assert!(log.contains("==== [No Name] ====\ny"));
+        assert!(!log.contains("saved.txt"));
+    }
+}
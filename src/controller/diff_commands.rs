@@ -0,0 +1,162 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::controller::window_commands::WindowCommands;
+use crate::document_model::diff::Diff;
+use crate::document_model::{DiffRow, Document};
+use crate::view::SplitDirection;
+
+/// Two buffers compared side by side, and their diff as of the revisions
+/// it was made from
+pub struct DiffMode {
+    pub buffers: [usize; 2], // Old, then new
+    pub diff: Diff,
+    revisions: [u64; 2],
+    buffer_count: usize, // Buffers open when the diff began; the indices are stale once this changes
+}
+
+impl DiffMode {
+    fn new(buffers: &[Document], old: usize, new: usize) -> Self {
+        let mut mode = Self { buffers: [old, new], diff: Diff::default(), revisions: [0; 2], buffer_count: buffers.len() };
+        mode.compare(buffers);
+        mode
+    }
+
+    fn compare(&mut self, buffers: &[Document]) {
+        let lines = |index: usize| -> Vec<String> {
+            let doc = &buffers[index];
+            (0..doc.line_count()).map(|line| doc.get_line(line).unwrap_or_default()).collect()
+        };
+        self.diff = Diff::new(&lines(self.buffers[0]), &lines(self.buffers[1]));
+        self.revisions = self.buffers.map(|index| buffers[index].revision());
+    }
+
+    /// The side buffer `index` is on, if it is one of the two
+    pub fn side(&self, index: usize) -> Option<usize> {
+        self.buffers.iter().position(|&buffer| buffer == index)
+    }
+
+    /// The rows a window showing buffer `index` draws
+    pub fn rows_for(&self, index: usize) -> Option<&[DiffRow]> {
+        self.side(index).map(|side| self.diff.rows(side))
+    }
+}
+
+/// Diff mode handlers - ]c and [c move between changes, do and dp copy a
+/// change from one side to the other
+pub struct DiffCommands;
+
+impl DiffCommands {
+    /// Compare two buffers: the old one in a new window on the left, the
+    /// new one in the current window on the right
+    pub fn start(shared: &mut SharedEditorState, old: usize, new: usize) {
+        shared.session_controller.current_buffer = new;
+        WindowCommands::split(shared, SplitDirection::Vertical);
+        shared.session_controller.current_buffer = old;
+        shared.view.reset_scroll();
+
+        let mode = DiffMode::new(&shared.session_controller.buffers, old, new);
+        shared.status_message = match mode.diff.hunks.len() {
+            0 => "No differences".to_string(),
+            1 => "1 change".to_string(),
+            count => format!("{} changes", count),
+        };
+        shared.diff = Some(mode);
+    }
+
+    /// :diffsplit {file} - compare the current buffer with a file
+    pub fn split(shared: &mut SharedEditorState, filename: &str) -> Result<(), String> {
+        let current = shared.session_controller.current_buffer_index();
+        shared.session_controller.open_file(filename);
+        let other = shared.session_controller.current_buffer_index();
+        shared.session_controller.current_buffer = current;
+        if other == current {
+            return Err(format!("\"{}\" is the current buffer", filename));
+        }
+        Self::start(shared, current, other);
+        Ok(())
+    }
+
+    /// :diffoff - leave diff mode, keeping the windows
+    pub fn off(shared: &mut SharedEditorState) {
+        shared.diff = None;
+    }
+
+    /// Compare again if either buffer has changed since the last time.
+    /// Diff mode ends when buffers are added or deleted.
+    pub fn refresh(shared: &mut SharedEditorState) {
+        let buffers = &shared.session_controller.buffers;
+        let Some(mode) = &mut shared.diff else {
+            return;
+        };
+        if mode.buffer_count != buffers.len() {
+            shared.diff = None;
+        } else if mode.buffers.map(|index| buffers[index].revision()) != mode.revisions {
+            mode.compare(buffers);
+        }
+    }
+
+    /// The diff and the current buffer's side of it
+    fn current_side(shared: &mut SharedEditorState) -> Result<(&DiffMode, usize), String> {
+        Self::refresh(shared);
+        let current = shared.session_controller.current_buffer_index();
+        let mode = shared.diff.as_ref().ok_or("Not in diff mode")?;
+        let side = mode.side(current).ok_or("Current buffer is not in diff mode")?;
+        Ok((mode, side))
+    }
+
+    /// ]c and [c - move to the start of the `count`th change on
+    pub fn jump(shared: &mut SharedEditorState, count: usize, forward: bool) -> Result<(), String> {
+        let line = shared.session_controller.current_document().cursor_line();
+        let (mode, side) = Self::current_side(shared)?;
+        let target = mode.diff.change_from(side, line, count, forward).ok_or("No more changes")?;
+        shared.session_controller.current_document_mut().move_cursor_to(target, 0);
+        Ok(())
+    }
+
+    /// do and dp - make the change at the cursor the same on both sides,
+    /// taking the other side's lines (`obtain`) or giving it these
+    pub fn transfer(shared: &mut SharedEditorState, obtain: bool) -> Result<(), String> {
+        let line = shared.session_controller.current_document().cursor_line();
+        let (mode, side) = Self::current_side(shared)?;
+        let hunk = mode.diff.hunk_at(side, line).ok_or("No change at the cursor")?.clone();
+        let (from, to) = if obtain { (1 - side, side) } else { (side, 1 - side) };
+        let (from_buffer, to_buffer) = (mode.buffers[from], mode.buffers[to]);
+
+        let buffers = &mut shared.session_controller.buffers;
+        if buffers[to_buffer].read_only {
+            return Err("Cannot make changes, buffer is read-only".to_string());
+        }
+        let lines: Vec<String> = hunk.side(from).clone().filter_map(|line| buffers[from_buffer].get_line(line)).collect();
+        let target = hunk.side(to);
+
+        let doc = &mut buffers[to_buffer];
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        doc.replace_lines_with_undo(target.start, target.len(), &lines);
+        if doc.cursor_line() >= doc.line_count() {
+            doc.move_cursor_to(doc.line_count().saturating_sub(1), 0);
+        }
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor);
+        Self::refresh(shared);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_side_and_rows() {
+        let buffers = [
+            Document::from_string("a\nb\nc".to_string()),
+            Document::from_string("a\nx\nc".to_string()),
+            Document::new(),
+        ];
+        let mode = DiffMode::new(&buffers, 0, 1);
+        assert_eq!((mode.side(0), mode.side(1), mode.side(2)), (Some(0), Some(1), None));
+        assert_eq!(mode.diff.hunks.len(), 1);
+        assert!(mode.rows_for(1).is_some_and(|rows| rows.len() == 3));
+        assert!(mode.rows_for(2).is_none());
+    }
+}
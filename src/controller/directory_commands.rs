@@ -0,0 +1,67 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::Document;
+use crossterm::event::KeyCode;
+use std::path::Path;
+
+/// What a key pressed in a directory listing asks of the editor
+#[derive(Debug, Clone, PartialEq)]
+pub enum DirectoryAction {
+    Handled,
+    Prompt(String), // Open the command line with this text typed in
+}
+
+/// Directory listing buffers - Enter opens, - goes up, d and % create
+pub struct DirectoryCommands;
+
+impl DirectoryCommands {
+    /// Handle a normal mode key when the current buffer is a directory
+    /// listing. Returns None for keys the listing leaves to normal mode.
+    pub fn handle_key(code: KeyCode, shared: &mut SharedEditorState) -> Option<DirectoryAction> {
+        let doc = shared.session_controller.current_document();
+        let listing = doc.directory.as_ref()?;
+        let (target, select) = match code {
+            KeyCode::Enter => (listing.entry_path(doc.cursor_line())?, None),
+            KeyCode::Char('-') => (listing.parent(), listing.path.file_name().map(|name| name.to_owned())),
+            KeyCode::Char('d') => return Some(DirectoryAction::Prompt("mkdir ".to_string())),
+            KeyCode::Char('%') => {
+                let prefix = listing.path.join("");
+                return Some(DirectoryAction::Prompt(format!("e {}", prefix.display())));
+            }
+            _ => return None,
+        };
+
+        shared.status_message = shared.session_controller.replace_current(target);
+        shared.cached_unmatched_brackets = None;
+        shared.view.reset_scroll();
+
+        // Going up leaves the cursor on the directory just left
+        if let Some(name) = select {
+            Self::select_entry(shared, &name.to_string_lossy());
+        }
+        Some(DirectoryAction::Handled)
+    }
+
+    /// :mkdir name - create a directory beside the listing (or in the working
+    /// directory elsewhere) and refresh the listing to show it
+    pub fn make_directory(name: &str, shared: &mut SharedEditorState) -> Result<(), String> {
+        let doc = shared.session_controller.current_document();
+        let base = doc.directory.as_ref().map_or(Path::new("."), |listing| listing.path.as_path());
+        let path = base.join(name);
+        std::fs::create_dir_all(&path).map_err(|e| format!("Can't create \"{}\": {}", name, e))?;
+        shared.status_message = format!("Created directory \"{}\"", path.display());
+
+        if let Some(listing) = &shared.session_controller.current_document().directory {
+            let refreshed = Document::from_directory(&listing.path).map_err(|e| e.to_string())?;
+            *shared.session_controller.current_document_mut() = refreshed;
+            Self::select_entry(shared, name.trim_end_matches('/'));
+        }
+        Ok(())
+    }
+
+    fn select_entry(shared: &mut SharedEditorState, name: &str) {
+        let doc = shared.session_controller.current_document_mut();
+        if let Some(line) = doc.directory.as_ref().and_then(|listing| listing.line_of(name)) {
+            doc.move_cursor_to(line, 0);
+        }
+    }
+}
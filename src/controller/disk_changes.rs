@@ -0,0 +1,86 @@
+//! Files changed on disk under open buffers - looked for by :checktime, when
+//! the terminal gets focus back and after :!cmd
+
+use crate::controller::shared_state::SharedEditorState;
+
+/// Asks about each buffer whose file changed: load the file or keep the buffer
+pub struct ReloadPrompt {
+    pending: Vec<usize>, // Buffers still to ask about, the one being asked first
+    pub prompt: String,
+}
+
+impl ReloadPrompt {
+    /// Look at every buffer. With 'autoread', unmodified buffers are reloaded
+    /// straight away; the rest are asked about. None when nothing is left to
+    /// ask. The status line names what was reloaded.
+    pub fn check(shared: &mut SharedEditorState) -> Option<Self> {
+        let session = &mut shared.session_controller;
+        let mut reloaded = Vec::new();
+        let mut pending = Vec::new();
+        for index in 0..session.buffers.len() {
+            let doc = &mut session.buffers[index];
+            if !doc.changed_on_disk() {
+                continue;
+            }
+            if session.autoread && !doc.is_modified() {
+                match doc.reload() {
+                    Ok(()) => reloaded.push(format!("\"{}\"", session.buffer_display_name(index))),
+                    Err(_) => pending.push(index),
+                }
+            } else {
+                pending.push(index);
+            }
+        }
+
+        if !reloaded.is_empty() {
+            shared.status_message = format!("{} reloaded", reloaded.join(", "));
+        }
+        let mut prompt = Self { pending, prompt: String::new() };
+        prompt.ask_next(shared).then_some(prompt)
+    }
+
+    /// Handle an answer: l loads the file, k keeps the buffer as it is.
+    /// Returns false once every buffer has been answered.
+    pub fn handle_key(&mut self, key: char, shared: &mut SharedEditorState) -> bool {
+        let index = self.pending[0];
+        let name = shared.session_controller.buffer_display_name(index).to_string();
+        let doc = &mut shared.session_controller.buffers[index];
+        match key {
+            'l' => {
+                shared.status_message = match doc.reload() {
+                    Ok(()) => format!("\"{}\" reloaded", name),
+                    Err(e) => format!("Can't reload \"{}\": {}", name, e),
+                };
+            }
+            'k' => {
+                doc.keep_over_disk_change();
+                shared.status_message = format!("\"{}\" kept; :w overwrites the file", name);
+            }
+            _ => return true,
+        }
+        self.pending.remove(0);
+        self.ask_next(shared)
+    }
+
+    /// Esc - keep every buffer still to be asked about
+    pub fn cancel(&mut self, shared: &mut SharedEditorState) {
+        for index in self.pending.drain(..) {
+            shared.session_controller.buffers[index].keep_over_disk_change();
+        }
+    }
+
+    /// Show the question for the next buffer. Returns false if there is none.
+    fn ask_next(&mut self, shared: &SharedEditorState) -> bool {
+        let Some(&index) = self.pending.first() else {
+            return false;
+        };
+        let session = &shared.session_controller;
+        let unsaved = if session.buffers[index].is_modified() { " (buffer has unsaved changes)" } else { "" };
+        self.prompt = format!(
+            "\"{}\" changed on disk{}: (l)oad file, (k)eep buffer?",
+            session.buffer_display_name(index),
+            unsaved
+        );
+        true
+    }
+}
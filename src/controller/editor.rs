@@ -1,19 +1,39 @@
-use crate::controller::shared_state::{ModeController, ModeTransition, SharedEditorState};
+use crate::controller::shared_state::{ModeController, ModeTransition, NormalKeys, SharedEditorState};
 use crate::controller::command_types::Mode;
 use crate::controller::insert::InsertController;
 use crate::controller::normal::NormalController;
 use crate::controller::visual::VisualController;
+use crate::controller::analysis_worker::AnalysisWorker;
+use crate::controller::lsp_commands::{LspCommands, LspManager};
 use crate::controller::command::CommandController;
+use crate::controller::diff_commands::DiffCommands;
+use crate::controller::directory_commands::{DirectoryAction, DirectoryCommands};
+use crate::controller::help_commands::HelpCommands;
+use crate::controller::search_commands::SearchCommands;
+use crate::controller::key_map::{KeyMap, MapMode, parse_keys};
+use crate::controller::show_match::ShowMatch;
+use crate::controller::timers::{Timer, TimerQueue};
 use crate::controller::SessionController;
-use crate::view::{View, RenderParams, DocumentViewModel, BracketHighlight};
-use crate::document_model::{MarkManager, RegisterManager, SearchState, SearchDirection};
+use crate::controller::crash;
+use crate::controller::event_source::{EventSource, TerminalEvents};
+use crate::view::{View, RenderParams, DocumentViewModel, BracketHighlight, WindowPane};
+use crate::view::renderer::Frame;
+use crate::document_model::history::{HistoryBrowser, HistoryKind};
+use crate::document_model::info_file::InfoFile;
+use crate::document_model::selection::Selection;
+use crate::document_model::{FilePosition, History, MarkManager, QuickfixList, RegisterManager, SearchState, SearchDirection, TagStack, LockChoice, LockConflict};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{
+        DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange, Event, KeyCode, KeyEvent,
+        KeyModifiers,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use std::io::stdout;
+use std::io::{Write, stdout};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 pub struct EditorController {
     shared_state: SharedEditorState,
@@ -27,6 +47,35 @@ pub struct EditorController {
     
     // Search mode state (handled directly like in original)
     command_buffer: String,
+    search_history: HistoryBrowser, // Up/Down through earlier searches
+    search_origin: Option<((usize, usize), (usize, usize))>, // Cursor and scroll when / or ? was pressed
+    search_region: Option<Selection>, // The selection / or ? was pressed in, which the search keeps to
+
+    // Whole-buffer passes run while idle
+    analysis: AnalysisWorker,
+    analysed_revision: Option<u64>, // Buffer revision the cached unmatched brackets came from
+
+    events: Box<dyn EventSource>, // The terminal unless set_event_source gives a script
+}
+
+/// How long input must pause before background analysis starts
+const ANALYSIS_IDLE_DELAY: Duration = Duration::from_millis(100);
+/// How often to check for a finished analysis while waiting for input
+const ANALYSIS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long input must pause before every pending edit goes to the swap files
+/// (vim's default 'updatetime')
+const SWAP_IDLE_DELAY: Duration = Duration::from_secs(4);
+/// How often files are looked at for changes on disk with 'autoread'
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often language servers are sent the buffers' changes and listened to
+const LSP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often the text read of files loading in the background is added to their buffers
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+impl Default for EditorController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EditorController {
@@ -41,6 +90,21 @@ impl EditorController {
                 status_message: String::new(),
                 show_all_unmatched: false,
                 cached_unmatched_brackets: None,
+                block_insert: None,
+                pending_selection: None,
+                last_substitute: None,
+                pending_shell_command: None,
+                pending_ex_command: None,
+                pending_normal: None,
+                pending_suspend: false,
+                key_map: KeyMap::new(),
+                timers: TimerQueue::new(),
+                history: History::new(),
+                show_match: ShowMatch::default(),
+                diff: None,
+                tag_stack: TagStack::default(),
+                lsp: LspManager::default(),
+                quickfix: QuickfixList::default(),
             },
             current_mode: Mode::Normal,
             insert_controller: InsertController::new(),
@@ -48,13 +112,24 @@ impl EditorController {
             visual_controller: VisualController::new(),
             command_controller: CommandController::new(),
             command_buffer: String::new(),
+            search_history: HistoryBrowser::default(),
+            search_origin: None,
+            search_region: None,
+            analysis: AnalysisWorker::new(),
+            analysed_revision: None,
+            events: Box::new(TerminalEvents),
         }
     }
     
-    pub fn new_with_files(filenames: Vec<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Open files, asking `resolve` how to handle files another editor is already editing
+    pub fn new_with_files<F>(filenames: Vec<PathBuf>, resolve: F) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&std::path::Path, &LockConflict) -> LockChoice,
+    {
         // Use SessionController's efficient new_with_files method
-        let session_controller = SessionController::new_with_files(filenames)?;
-        
+        let session_controller = SessionController::new_with_files(filenames, resolve)?;
+        let status_message = session_controller.current_document().load_warning().unwrap_or_else(|| "Files loaded".to_string());
+
         let controller = Self {
             shared_state: SharedEditorState {
                 session_controller,
@@ -62,9 +137,24 @@ impl EditorController {
                 mark_manager: MarkManager::new(),
                 register_manager: RegisterManager::new(),
                 search_state: SearchState::new(),
-                status_message: "Files loaded".to_string(),
+                status_message,
                 show_all_unmatched: false,
                 cached_unmatched_brackets: None,
+                block_insert: None,
+                pending_selection: None,
+                last_substitute: None,
+                pending_shell_command: None,
+                pending_ex_command: None,
+                pending_normal: None,
+                pending_suspend: false,
+                key_map: KeyMap::new(),
+                timers: TimerQueue::new(),
+                history: History::new(),
+                show_match: ShowMatch::default(),
+                diff: None,
+                tag_stack: TagStack::default(),
+                lsp: LspManager::default(),
+                quickfix: QuickfixList::default(),
             },
             current_mode: Mode::Normal,
             insert_controller: InsertController::new(),
@@ -72,105 +162,667 @@ impl EditorController {
             visual_controller: VisualController::new(),
             command_controller: CommandController::new(),
             command_buffer: String::new(),
+            search_history: HistoryBrowser::default(),
+            search_origin: None,
+            search_region: None,
+            analysis: AnalysisWorker::new(),
+            analysed_revision: None,
+            events: Box::new(TerminalEvents),
         };
         
         Ok(controller)
     }
     
     pub fn run(mut self) -> Result<(), Box<dyn std::error::Error>> {
-        enable_raw_mode()?;
-        execute!(stdout(), EnterAlternateScreen)?;
-        
-        let result = self.run_loop();
-        
-        disable_raw_mode()?;
-        execute!(stdout(), LeaveAlternateScreen)?;
+        Self::take_terminal()?;
+        crash::install_panic_hook(Self::release_terminal);
+
+        // A panic has already given the terminal back (the hook does that);
+        // what wasn't saved is kept before the panic carries on
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| self.run_loop())) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.save_after_crash(&crash::last_panic().unwrap_or_else(|| "panic".to_string()), true);
+                panic::resume_unwind(payload);
+            }
+        };
+
+        Self::release_terminal()?;
+        if let Err(e) = &result {
+            self.save_after_crash(&e.to_string(), false);
+        }
+
+        if let Some(path) = InfoFile::path()
+            && let Err(e) = self.save_info(&path)
+        {
+            eprintln!("vi-rus: can't write {}: {}", path.display(), e);
+        }
         
         result
     }
     
+    /// Run ex commands in order without the terminal (--cmd, -es, -S),
+    /// stopping at the first failure or quit. Ok(true) means one of them
+    /// quit; errors carry the failing command's index.
+    pub fn run_commands(&mut self, commands: &[String]) -> Result<bool, (usize, String)> {
+        for (index, command) in commands.iter().enumerate() {
+            let command = command.strip_prefix(':').unwrap_or(command);
+            match self.command_controller.execute_batch(command, &mut self.shared_state) {
+                Ok(true) => return Ok(true),
+                Ok(false) => match self.type_normal_keys() {
+                    Ok(true) => return Ok(true),
+                    Ok(false) => {}
+                    Err(e) => return Err((index, e.to_string())),
+                },
+                Err(message) => return Err((index, message)),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Run -c and +cmd commands before the first screen is drawn, as if
+    /// typed on the command line. A failure only leaves its message on the
+    /// status line. A command that asks something (:s///c, 'confirm') or
+    /// lists something to pick from (:ls) is left open for the user, and
+    /// the commands after it don't run. Returns true when one quit.
+    pub fn run_startup_commands(&mut self, commands: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        for command in commands {
+            let command = command.strip_prefix(':').unwrap_or(command);
+            match self.command_controller.run_line(command, &mut self.shared_state) {
+                ModeTransition::Stay => {
+                    self.transition_to_mode(Mode::Command);
+                    return Ok(false);
+                }
+                ModeTransition::ToMode(mode) => self.transition_to_mode(mode),
+                ModeTransition::Quit => return Ok(true),
+            }
+            if self.type_normal_keys()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     fn run_loop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // Files given on the command line may still be loading
+        self.schedule_state_timers(Instant::now());
         loop {
-            let buffer_info = format!(
-                "Buffer {}/{}: \"{}\"",
-                self.shared_state.session_controller.current_buffer_index() + 1,
-                self.shared_state.session_controller.buffer_count(),
-                self.get_display_filename()
-            );
-
-            // Refresh unmatched brackets cache if highlighting is enabled and needed
-            if self.shared_state.show_all_unmatched {
-                self.refresh_unmatched_cache_if_needed();
-            }
-
-            // Borrow fields separately to avoid borrowing conflicts
-            let doc = self.shared_state.session_controller.current_document();
-
-            // Create view model adapter
-            let view_model = DocumentViewModel::new(doc);
-
-            // Create bracket highlights
-            let bracket_highlights = BracketHighlight {
-                matching: doc.find_matching_bracket(),
-                unmatched_at_cursor: doc.is_unmatched_bracket(),
-                all_unmatched: if self.shared_state.show_all_unmatched {
-                    self.shared_state.cached_unmatched_brackets.clone().unwrap_or_default()
-                } else {
-                    Vec::new()
-                },
-            };
+            // Keys already waiting (a paste the terminal sends as keys) are
+            // handled before the screen is drawn again
+            if !self.events.poll(Duration::ZERO)? {
+                self.with_render_params(|view, view_model, params| view.render(view_model, params))?;
+            }
 
-            let command_buffer_str = self.get_command_buffer_for_mode();
-            let params = RenderParams {
-                mode: &self.current_mode,
-                command_buffer: &command_buffer_str,
-                status_message: &self.shared_state.status_message,
-                buffer_info: Some(&buffer_info),
-                visual_selection: self.visual_controller.visual_selection.as_ref(),
-                search_state: Some(&self.shared_state.search_state),
-                bracket_highlights: Some(&bracket_highlights),
-            };
-            self.shared_state.view.render(&view_model, &params)?;
-
-            match event::read()? {
-                Event::Key(key_event) => {
-                    // Handle special modes that need direct character input
-                    if self.current_mode == Mode::Search || self.current_mode == Mode::SearchBackward {
-                        if self.handle_search_mode_input(key_event)? {
-                            break; // Quit
-                        }
-                        continue;
+            // Run background analysis while waiting; redraw when it finishes first
+            if self.wait_for_input()? {
+                continue;
+            }
+            // Wait for input until the tick comes round or a timer is due
+            if let Some(wait) = self.shared_state.timers.wait(Instant::now())
+                && !self.events.poll(wait)?
+            {
+                if self.run_timers(Instant::now())? {
+                    break;
+                }
+                continue;
+            }
+            let event = self.events.read()?;
+            if self.handle_event(event)? {
+                break; // Quit
+            }
+            if let Some(command) = self.shared_state.pending_shell_command.take() {
+                self.run_shell_command(&command)?;
+            }
+            if std::mem::take(&mut self.shared_state.pending_suspend) {
+                self.suspend()?;
+            }
+            self.shared_state.session_controller.sync_swap_files(false);
+            self.schedule_timers(Instant::now());
+        }
+        
+        Ok(())
+    }
+
+    /// Set the timers for after an event: every pending edit goes to the
+    /// swap files once input pauses, along with the timers that depend on
+    /// the editor's state
+    fn schedule_timers(&mut self, now: Instant) {
+        self.shared_state.timers.schedule(Timer::SwapSync, now + SWAP_IDLE_DELAY);
+        self.schedule_state_timers(now);
+        // With 'timeout' a command typed in part is dropped once typing pauses
+        let command_pending = !self.pending_keys().is_empty();
+        let timers = &mut self.shared_state.timers;
+        if timers.timeout && command_pending {
+            timers.schedule(Timer::PendingCommand, now + timers.timeout_len);
+        }
+    }
+
+    /// The keys of a command typed in part in normal or visual mode
+    fn pending_keys(&self) -> String {
+        match self.current_mode {
+            Mode::Normal => self.normal_controller.pending_keys(),
+            Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => self.visual_controller.pending_keys(),
+            _ => String::new(),
+        }
+    }
+
+    /// Set or cancel the timers that follow the editor's state: the
+    /// mapping timeout while keys wait on a mapping, 'autosave' while a
+    /// buffer has edits for it, file checks with 'autoread', talking to
+    /// language servers while any are configured, and filling buffers
+    /// while their files load
+    fn schedule_state_timers(&mut self, now: Instant) {
+        let command_pending = self.shared_state.timers.timeout && !self.pending_keys().is_empty();
+        let timers = &mut self.shared_state.timers;
+        let session = &self.shared_state.session_controller;
+        if self.shared_state.key_map.is_waiting() {
+            if !timers.is_scheduled(Timer::MappingTimeout) {
+                timers.schedule(Timer::MappingTimeout, now + timers.timeout_len);
+            }
+        } else {
+            timers.cancel(Timer::MappingTimeout);
+        }
+        if !command_pending {
+            timers.cancel(Timer::PendingCommand);
+        }
+        match session.autosave_wait(now) {
+            Some(wait) => timers.schedule(Timer::Autosave, now + wait),
+            None => timers.cancel(Timer::Autosave),
+        }
+        if !session.autoread {
+            timers.cancel(Timer::CheckFiles);
+        } else if !timers.is_scheduled(Timer::CheckFiles) {
+            timers.schedule(Timer::CheckFiles, now + FILE_CHECK_INTERVAL);
+        }
+        if !self.shared_state.lsp.is_active() {
+            timers.cancel(Timer::LanguageServer);
+        } else if !timers.is_scheduled(Timer::LanguageServer) {
+            timers.schedule(Timer::LanguageServer, now + LSP_POLL_INTERVAL);
+        }
+        if !session.is_loading() {
+            timers.cancel(Timer::LoadFiles);
+        } else if !timers.is_scheduled(Timer::LoadFiles) {
+            timers.schedule(Timer::LoadFiles, now + LOAD_POLL_INTERVAL);
+        }
+    }
+
+    /// Run the timers due at `now`. Returns true when the editor should quit.
+    fn run_timers(&mut self, now: Instant) -> Result<bool, Box<dyn std::error::Error>> {
+        for timer in self.shared_state.timers.take_due(now) {
+            match timer {
+                Timer::SwapSync => self.shared_state.session_controller.sync_swap_files(true),
+                Timer::Autosave => {
+                    let saved = self.shared_state.session_controller.autosave(now, false);
+                    if !saved.is_empty() {
+                        self.shared_state.status_message = Self::autosave_message(saved);
                     }
-                    
-                    // Handle command mode
-                    if self.current_mode == Mode::Command {
-                        match self.command_controller.handle_key(key_event, &mut self.shared_state) {
-                            ModeTransition::Stay => continue,
-                            ModeTransition::ToMode(mode) => {
-                                self.current_mode = mode;
-                                continue;
-                            }
-                            ModeTransition::Quit => break,
-                        }
+                }
+                Timer::MappingTimeout => {
+                    // Keys that began a mapping stand for themselves once typing pauses
+                    self.shared_state.key_map.time_out();
+                    if self.handle_typeahead()? {
+                        return Ok(true);
                     }
-                    
-                    // Delegate to appropriate mode controller
-                    let transition = self.handle_key_in_current_mode(key_event);
-                    
-                    match transition {
-                        ModeTransition::Stay => {}
-                        ModeTransition::ToMode(new_mode) => {
-                            self.transition_to_mode(new_mode);
-                        }
-                        ModeTransition::Quit => break,
+                }
+                Timer::CheckFiles => self.check_files(),
+                Timer::ShowMatch => self.shared_state.show_match.clear(),
+                Timer::LanguageServer => {
+                    LspCommands::sync(&mut self.shared_state);
+                    LspCommands::poll(&mut self.shared_state);
+                }
+                Timer::LoadFiles => {
+                    if let Some(message) = self.shared_state.session_controller.poll_loading() {
+                        self.shared_state.status_message = message;
                     }
                 }
-                _ => {}
+                Timer::PendingCommand => {
+                    // The next key starts a command of its own
+                    self.normal_controller.cancel_pending();
+                    self.visual_controller.cancel_pending();
+                }
             }
         }
+        self.schedule_state_timers(now);
+        Ok(false)
+    }
+
+    /// The status line message for what 'autosave' did
+    fn autosave_message(results: Vec<Result<String, String>>) -> String {
+        let (written, failed): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+        match (written.as_slice(), failed.first()) {
+            (_, Some(Err(error))) => format!("Autosave failed: {}", error),
+            ([Ok(name)], _) => format!("\"{}\" autosaved", name),
+            (written, _) => format!("{} buffers autosaved", written.len()),
+        }
+    }
+
+    /// Raw mode on the alternate screen, with focus and paste reporting:
+    /// the terminal as the editor draws on it
+    fn take_terminal() -> std::io::Result<()> {
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, EnableFocusChange, EnableBracketedPaste)
+    }
+
+    /// Put the terminal back as the shell had it
+    fn release_terminal() -> std::io::Result<()> {
+        disable_raw_mode()?;
+        execute!(stdout(), DisableBracketedPaste, DisableFocusChange, LeaveAlternateScreen)
+    }
+
+    /// After a crash: bring the swap files up to date, then write the
+    /// .recovered files and the crash log and say where they are. A panic
+    /// leaves the swap files behind; an error returned from the loop doesn't.
+    fn save_after_crash(&mut self, message: &str, panicked: bool) {
+        let session = &mut self.shared_state.session_controller;
+        if !panicked && !session.buffers.iter().any(|document| document.is_modified()) {
+            return;
+        }
+        session.sync_swap_files(true);
+        crash::save_unsaved_work(&session.buffers, message, &std::env::temp_dir()).print(panicked);
+    }
+
+    /// Ctrl-Z and :stop - hand the terminal back and stop with SIGTSTP, as
+    /// job control would, then take it over again and redraw everything
+    /// when the shell continues the editor (fg sends SIGCONT)
+    #[cfg(unix)]
+    fn suspend(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // The swap files have every edit, should the editor never be continued
+        self.shared_state.session_controller.sync_swap_files(true);
+        Self::release_terminal()?;
+        // SAFETY: raise only sends a signal to this process; it returns once
+        // the process is continued
+        unsafe {
+            libc::raise(libc::SIGTSTP);
+        }
+        Self::take_terminal()?;
+        self.shared_state.view.force_redraw();
+        // Files may have been changed while the editor was stopped
+        self.check_files();
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn suspend(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.shared_state.status_message = "Suspending isn't supported on this system".to_string();
+        Ok(())
+    }
+
+    /// :!cmd - leave the editor screen so the command has the terminal to
+    /// itself, then wait for Enter before coming back
+    fn run_shell_command(&mut self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Self::release_terminal()?;
+
+        let status = std::process::Command::new("sh").arg("-c").arg(command).status();
+        match &status {
+            Ok(status) if !status.success() => println!("\nshell returned {}", status.code().unwrap_or(-1)),
+            Ok(_) => println!(),
+            Err(e) => println!("\nFailed to execute command: {}", e),
+        }
+        print!("Press ENTER to continue");
+        stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+
+        Self::take_terminal()?;
+        self.shared_state.view.force_redraw();
+        self.shared_state.status_message = match status {
+            Ok(status) if status.success() => format!(":!{}", command),
+            Ok(status) => format!(":!{} (exit {})", command, status.code().unwrap_or(-1)),
+            Err(e) => format!("Failed to execute command: {}", e),
+        };
+        // The command may have changed files that are open
+        self.check_files();
+        Ok(())
+    }
+
+    /// Ask about (or with 'autoread', reload) files changed on disk. Only
+    /// from normal mode, so typing and pending commands aren't cut short.
+    fn check_files(&mut self) {
+        if self.current_mode == Mode::Normal && self.command_controller.check_files(&mut self.shared_state) {
+            self.transition_to_mode(Mode::Command);
+        }
+    }
+    
+    /// Gather what the current frame shows and hand it to `draw` along with the view
+    fn with_render_params<R>(&mut self, draw: impl FnOnce(&mut View, &DocumentViewModel, &RenderParams) -> R) -> R {
+        // Diff windows line up with the text as it is now
+        DiffCommands::refresh(&mut self.shared_state);
+        // ...and search highlights follow edits
+        self.shared_state.search_state.sync(self.shared_state.session_controller.current_document());
+
+        let buffer_info = format!(
+            "Buffer {}/{}: \"{}\"{}",
+            self.shared_state.session_controller.current_buffer_index() + 1,
+            self.shared_state.session_controller.buffer_count(),
+            self.get_display_filename(),
+            if self.shared_state.session_controller.current_document().read_only { " [RO]" } else { "" }
+        );
+        let buffer_info = match self.shared_state.session_controller.current_document().load_progress() {
+            Some(percent) => format!("{} [Loading {}%]", buffer_info, percent),
+            None => buffer_info,
+        };
+
+        // Borrow fields separately to avoid borrowing conflicts
+        let doc = self.shared_state.session_controller.current_document();
+
+        // Create view model adapter
+        let view_model = DocumentViewModel::new(doc);
+
+        // Create bracket highlights, without scanning through huge lines
+        let max_line_len = self.shared_state.view.highlight_line_limit();
+        let bracket_highlights = BracketHighlight {
+            // A bracket 'showmatch' is flashing stands in for the one at the cursor
+            matching: self.shared_state.show_match.flash.or_else(|| doc.find_matching_bracket(max_line_len)),
+            unmatched_at_cursor: doc.is_unmatched_bracket(max_line_len),
+            all_unmatched: if self.shared_state.show_all_unmatched {
+                self.shared_state.cached_unmatched_brackets.clone().unwrap_or_default()
+            } else {
+                Vec::new()
+            },
+        };
+
+        // Each split window shows its own buffer and cursor; the active one uses the live document
+        let window_panes: Vec<WindowPane> = if self.shared_state.view.windows().is_split() {
+            let session = &self.shared_state.session_controller;
+            let active = self.shared_state.view.windows().active_id();
+            self.shared_state.view.windows().windows().into_iter().map(|window| {
+                let (buffer_index, view_model) = if window.id == active {
+                    (session.current_buffer_index(), DocumentViewModel::new(session.current_document()))
+                } else {
+                    let index = window.buffer_index.min(session.buffer_count() - 1);
+                    (index, DocumentViewModel::with_cursor(&session.buffers[index], window.cursor))
+                };
+                WindowPane {
+                    id: window.id,
+                    view_model: Box::new(view_model),
+                    name: session.buffer_display_name(buffer_index).to_string(),
+                    modified: session.buffers[buffer_index].is_modified(),
+                    diff: self.shared_state.diff.as_ref().and_then(|mode| mode.rows_for(buffer_index)),
+                }
+            }).collect()
+        } else {
+            Vec::new()
+        };
+
+        let command_buffer_str = self.get_command_buffer_for_mode();
+        let pending_keys = self.pending_keys();
+        let popup = self.command_controller.popup(&self.shared_state).or_else(|| self.shared_state.lsp.hover.clone());
+        let params = RenderParams {
+            mode: &self.current_mode,
+            command_buffer: &command_buffer_str,
+            status_message: &self.shared_state.status_message,
+            buffer_info: Some(&buffer_info),
+            visual_selection: self.visual_controller.visual_selection.as_ref(),
+            search_state: Some(&self.shared_state.search_state),
+            bracket_highlights: Some(&bracket_highlights),
+            window_panes: &window_panes,
+            popup: popup.as_ref(),
+            pending_keys: &pending_keys,
+        };
+        draw(&mut self.shared_state.view, &view_model, &params)
+    }
+
+    /// Process one terminal event. Returns true when the editor should quit.
+    fn handle_event(&mut self, event: Event) -> Result<bool, Box<dyn std::error::Error>> {
+        match event {
+            Event::Key(key_event) => {
+                let buffer = self.buffer_position();
+                // K's hover text stays up until the next key
+                self.shared_state.lsp.hover = None;
+                self.shared_state.key_map.type_key(key_event);
+                if self.handle_typeahead()? {
+                    return Ok(true);
+                }
+                self.shared_state.session_controller.track_changes(Instant::now());
+                // Leaving a buffer autosaves it
+                let (index, count) = buffer;
+                if self.buffer_position() != buffer
+                    && count == self.shared_state.session_controller.buffer_count()
+                    && let Some(result) = self.shared_state.session_controller.autosave_buffer(index, Instant::now())
+                {
+                    let message = Self::autosave_message(vec![result]);
+                    let status = &mut self.shared_state.status_message;
+                    *status = if status.is_empty() { message } else { format!("{} ({})", status, message) };
+                }
+            }
+            Event::Resize(width, height) => {
+                // Layout is recomputed from the new size on the next render
+                self.shared_state.view.handle_resize(width, height);
+            }
+            Event::Paste(text) => self.handle_paste(&text)?,
+            Event::FocusGained => self.check_files(),
+            Event::FocusLost => {
+                let saved = self.shared_state.session_controller.autosave(Instant::now(), true);
+                if !saved.is_empty() {
+                    self.shared_state.status_message = Self::autosave_message(saved);
+                }
+            }
+            _ => {}
+        }
         
+        Ok(false)
+    }
+
+    /// Bracketed paste: the terminal sends the pasted text as one event,
+    /// which goes into the buffer as one insert rather than a key at a time.
+    /// In normal mode it goes before the cursor as one undo step; the
+    /// command and search lines take it as a single line.
+    fn handle_paste(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Keys waiting on a mapping were typed first
+        if self.shared_state.key_map.is_waiting() {
+            self.shared_state.key_map.time_out();
+            self.handle_typeahead()?;
+        }
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.current_mode {
+            Mode::Insert => self.insert_controller.paste(&text, &mut self.shared_state),
+            Mode::Normal if !text.is_empty() && !HelpCommands::refuse_change(&mut self.shared_state) => {
+                let doc = self.shared_state.session_controller.current_document_mut();
+                let cursor = (doc.cursor_line(), doc.cursor_column());
+                doc.undo_manager_mut().start_group(cursor);
+                doc.insert_text_with_undo(&text);
+                doc.move_cursor_left(); // Onto the last character pasted
+                let cursor = (doc.cursor_line(), doc.cursor_column());
+                doc.undo_manager_mut().end_group(cursor);
+                self.shared_state.cached_unmatched_brackets = None;
+            }
+            Mode::Command => self.command_controller.paste(&text),
+            Mode::Search | Mode::SearchBackward => {
+                self.command_buffer.push_str(&text.trim_end_matches('\n').replace('\n', " "));
+                self.update_incremental_search();
+            }
+            _ => {}
+        }
         Ok(())
     }
+
+    /// The current buffer's index, and how many buffers there are
+    fn buffer_position(&self) -> (usize, usize) {
+        let session = &self.shared_state.session_controller;
+        (session.current_buffer_index(), session.buffer_count())
+    }
+
+    /// Handle the typed keys that are ready, after key mappings have had
+    /// their say. Returns true when the editor should quit.
+    fn handle_typeahead(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        while let Some(key_event) = self.shared_state.key_map.next_key(Self::map_mode(self.current_mode)) {
+            if self.handle_key(key_event)? {
+                return Ok(true);
+            }
+        }
+        if let Some(error) = self.shared_state.key_map.take_error() {
+            self.shared_state.status_message = error;
+        }
+        Ok(false)
+    }
+
+    /// The mappings that apply in `mode`; none on the command and search lines
+    fn map_mode(mode: Mode) -> Option<MapMode> {
+        match mode {
+            Mode::Normal => Some(MapMode::Normal),
+            Mode::Insert => Some(MapMode::Insert),
+            Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => Some(MapMode::Visual),
+            Mode::Command | Mode::Search | Mode::SearchBackward => None,
+        }
+    }
+
+    /// Process one key. Returns true when the editor should quit.
+    fn handle_key(&mut self, key_event: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        // The last key may have moved to another file, leaving an alternate
+        self.shared_state.note_buffer_switch();
+
+        // Handle special modes that need direct character input
+        if self.current_mode == Mode::Search || self.current_mode == Mode::SearchBackward {
+            if self.handle_search_mode_input(key_event)? {
+                return Ok(true); // Quit
+            }
+            return Ok(false);
+        }
+
+        // Handle command mode
+        if self.current_mode == Mode::Command {
+            match self.command_controller.handle_key(key_event, &mut self.shared_state) {
+                ModeTransition::Stay => return Ok(false),
+                ModeTransition::ToMode(mode) => {
+                    self.current_mode = mode;
+                    return self.type_normal_keys();
+                }
+                ModeTransition::Quit => return Ok(true),
+            }
+        }
+
+        // Enter in a q: / q/ window runs the line under the cursor
+        if self.current_mode == Mode::Normal
+            && key_event.code == KeyCode::Enter
+            && self.shared_state.session_controller.current_document().history_window.is_some()
+        {
+            return self.run_history_line();
+        }
+
+        // The help follows links with Ctrl-] and closes with q
+        if self.current_mode == Mode::Normal
+            && self.pending_keys().is_empty()
+            && HelpCommands::handle_key(key_event, &mut self.shared_state)
+        {
+            return Ok(false);
+        }
+
+        // Directory listings browse with Enter, -, d and %
+        if self.current_mode == Mode::Normal
+            && !key_event.modifiers.contains(KeyModifiers::CONTROL)
+            && let Some(action) = DirectoryCommands::handle_key(key_event.code, &mut self.shared_state)
+        {
+            if let DirectoryAction::Prompt(text) = action {
+                self.transition_to_mode(Mode::Command);
+                self.command_controller.command_buffer = text;
+            }
+            return Ok(false);
+        }
+
+        // Delegate to appropriate mode controller
+        let mut transition = self.handle_key_in_current_mode(key_event);
+        if let Some(command) = self.shared_state.pending_ex_command.take() {
+            transition = match self.command_controller.run_line(&command, &mut self.shared_state) {
+                // A question about unsaved buffers is answered in command mode
+                ModeTransition::Stay => ModeTransition::ToMode(Mode::Command),
+                other => other,
+            };
+        }
+
+        match transition {
+            ModeTransition::Stay => {}
+            ModeTransition::ToMode(new_mode) => {
+                self.transition_to_mode(new_mode);
+            }
+            ModeTransition::Quit => return Ok(true),
+        }
+
+        // @: may have run a :normal
+        self.type_normal_keys()
+    }
+
+    /// Type the keys a :normal command left, from the start of each of its
+    /// lines or once at the cursor, as one undo step. Mappings apply unless
+    /// it was :normal!. Returns true when the keys quit the editor.
+    fn type_normal_keys(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(NormalKeys { keys, mut lines, remap }) = self.shared_state.pending_normal.take() else {
+            return Ok(false);
+        };
+        let buffer = self.shared_state.session_controller.current_buffer_index();
+        let doc = self.shared_state.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().begin_batch(cursor);
+
+        let mut quit = false;
+        if lines.is_empty() {
+            quit = self.type_keys(&keys, remap)?;
+        }
+        let mut index = 0;
+        while index < lines.len() && !quit {
+            let line_num = lines[index];
+            let session = &mut self.shared_state.session_controller;
+            if session.current_buffer_index() != buffer || line_num >= session.current_document().line_count() {
+                break;
+            }
+            let doc = session.current_document_mut();
+            let _ = doc.set_cursor(line_num, 0);
+            let count_before = doc.line_count();
+
+            quit = self.type_keys(&keys, remap)?;
+
+            // As for :g, later lines keep their place when lines come and go
+            let delta = self.shared_state.session_controller.current_document().line_count() as isize - count_before as isize;
+            let remaining: Vec<usize> = lines[index + 1..]
+                .iter()
+                .filter_map(|&target| CommandController::shift_global_target(target, line_num, delta))
+                .collect();
+            lines.truncate(index + 1);
+            lines.extend(remaining);
+            index += 1;
+        }
+
+        if let Some(doc) = self.shared_state.session_controller.buffers.get_mut(buffer) {
+            let cursor = (doc.cursor_line(), doc.cursor_column());
+            doc.undo_manager_mut().end_batch(cursor);
+        }
+        self.shared_state.cached_unmatched_brackets = None;
+        Ok(quit)
+    }
+
+    /// Type `keys` in normal mode, through the mappings if `remap`, then
+    /// end whatever they left unfinished as Esc would: an insert, a
+    /// selection, a command line
+    fn type_keys(&mut self, keys: &[KeyEvent], remap: bool) -> Result<bool, Box<dyn std::error::Error>> {
+        if remap {
+            // Keys typed ahead wait until these are done; a mapping they
+            // leave unfinished gets what it has, as after a pause
+            let typeahead = self.shared_state.key_map.take_typeahead();
+            for &key in keys {
+                self.shared_state.key_map.type_key(key);
+            }
+            self.shared_state.key_map.time_out();
+            let quit = self.handle_typeahead();
+            self.shared_state.key_map.restore_typeahead(typeahead);
+            if quit? {
+                return Ok(true);
+            }
+        } else {
+            for &key in keys {
+                if self.handle_key(key)? {
+                    return Ok(true);
+                }
+            }
+        }
+        let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        for _ in 0..2 {
+            if self.current_mode != Mode::Normal && self.handle_key(escape)? {
+                return Ok(true);
+            }
+        }
+        self.current_mode = Mode::Normal;
+        self.normal_controller.cancel_pending();
+        Ok(false)
+    }
     
     fn handle_key_in_current_mode(&mut self, key_event: KeyEvent) -> ModeTransition {
         match self.current_mode {
@@ -201,23 +853,36 @@ impl EditorController {
         
         // Handle initialization for the new mode
         match new_mode {
-            Mode::VisualChar => {
-                let doc = self.shared_state.session_controller.current_document();
-                self.visual_controller.start_selection(new_mode, doc.cursor_line(), doc.cursor_column());
-            }
-            Mode::VisualLine => {
-                let doc = self.shared_state.session_controller.current_document();
-                self.visual_controller.start_selection(new_mode, doc.cursor_line(), doc.cursor_column());
-            }
-            Mode::VisualBlock => {
-                let doc = self.shared_state.session_controller.current_document();
-                self.visual_controller.start_selection(new_mode, doc.cursor_line(), doc.cursor_column());
+            Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => {
+                // gv starts with the selection it put back
+                if let Some(selection) = self.shared_state.pending_selection.take() {
+                    self.visual_controller.visual_selection = Some(selection);
+                } else {
+                    let doc = self.shared_state.session_controller.current_document();
+                    self.visual_controller.start_selection(new_mode, doc.cursor_line(), doc.cursor_column());
+                }
             }
             Mode::Command => {
                 self.command_controller.command_buffer.clear();
+                // From visual mode the command line starts with the selection's range
+                if matches!(self.current_mode, Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock) {
+                    self.command_controller.command_buffer.push_str("'<,'>");
+                }
             }
             Mode::Search | Mode::SearchBackward => {
                 self.command_buffer.clear();
+                let doc = self.shared_state.session_controller.current_document();
+                // From visual mode the search keeps to the selection just left
+                self.search_region = if matches!(self.current_mode, Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock) {
+                    doc.last_selection.clone()
+                } else {
+                    None
+                };
+                let view = &self.shared_state.view;
+                self.search_origin = Some((
+                    (doc.cursor_line(), doc.cursor_column()),
+                    (view.get_scroll_offset(), view.get_horizontal_scroll()),
+                ));
             }
             _ => {}
         }
@@ -225,51 +890,159 @@ impl EditorController {
         self.current_mode = new_mode;
     }
     
+    /// Close the history window and run the line the cursor was on at its
+    /// prompt, as if typed there
+    fn run_history_line(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let doc = self.shared_state.session_controller.current_document();
+        let line = doc.get_line(doc.cursor_line()).unwrap_or_default();
+        let Some(prompt) = self.shared_state.session_controller.close_history_window() else {
+            return Ok(false);
+        };
+        self.shared_state.cached_unmatched_brackets = None;
+        self.shared_state.view.reset_scroll();
+        self.shared_state.status_message.clear();
+        if line.trim().is_empty() {
+            return Ok(false);
+        }
+
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        match prompt {
+            ':' => {
+                self.transition_to_mode(Mode::Command);
+                self.command_controller.command_buffer = line;
+                self.handle_key(enter)
+            }
+            _ => {
+                self.transition_to_mode(if prompt == '/' { Mode::Search } else { Mode::SearchBackward });
+                self.command_buffer = line;
+                self.handle_search_mode_input(enter)
+            }
+        }
+    }
+
     fn handle_search_mode_input(&mut self, key_event: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        if !matches!(key_event.code, KeyCode::Up | KeyCode::Down) {
+            self.search_history.reset();
+        }
         match key_event.code {
             KeyCode::Char(c) => {
                 self.command_buffer.push(c);
+                self.update_incremental_search();
                 Ok(false)
             }
             KeyCode::Backspace => {
                 self.command_buffer.pop();
+                self.update_incremental_search();
+                Ok(false)
+            }
+            KeyCode::Up | KeyCode::Down => {
+                let entries = self.shared_state.history.entries(HistoryKind::Search);
+                let line = if key_event.code == KeyCode::Up {
+                    self.search_history.older(entries, &self.command_buffer)
+                } else {
+                    self.search_history.newer(entries)
+                };
+                if let Some(line) = line {
+                    self.command_buffer = line;
+                    self.update_incremental_search();
+                }
                 Ok(false)
             }
             KeyCode::Enter => {
-                // Execute the search
+                // Execute the search from where it started, not from the preview
+                self.restore_search_origin();
                 let pattern = self.command_buffer.clone();
+                self.shared_state.history.add(HistoryKind::Search, &pattern);
                 // Set the search pattern and direction
-                let direction = if self.current_mode == Mode::Search {
-                    SearchDirection::Forward
-                } else {
-                    SearchDirection::Backward
-                };
+                let direction = self.search_direction();
+                let forward = direction == SearchDirection::Forward;
                 
                 let doc = self.shared_state.session_controller.current_document();
-                if let Ok(_) = crate::controller::search_commands::SearchCommands::start_search(
+                let (line, col) = (doc.cursor_line(), doc.cursor_column());
+                if SearchCommands::start_search(
                     &mut self.shared_state.search_state,
                     doc,
                     pattern,
-                    direction
-                ) {
+                    direction,
+                    self.search_region.take(),
+                ).is_ok() {
                     // Find first match and move cursor there
-                    if let Some(search_match) = self.shared_state.search_state.find_next_match(0, 0) {
-                        let doc = self.shared_state.session_controller.current_document_mut();
-                        doc.move_cursor_to(search_match.line, search_match.start_col);
+                    let search_state = &mut self.shared_state.search_state;
+                    let found = if forward {
+                        search_state.find_next_match(line, col)
+                    } else {
+                        search_state.find_prev_match(line, col)
+                    };
+                    let target = found.map(|m| (m.line, m.start_col));
+                    let doc = self.shared_state.session_controller.current_document_mut();
+                    doc.stats.record_search();
+                    if let Some((line, col)) = target {
+                        doc.move_cursor_to(line, col);
+                        self.shared_state.status_message = SearchCommands::found_message(&self.shared_state.search_state, forward);
                     }
                 }
-                self.command_buffer.clear();
-                self.current_mode = Mode::Normal;
+                self.finish_search();
                 Ok(false)
             }
             KeyCode::Esc => {
-                self.command_buffer.clear();
-                self.current_mode = Mode::Normal;
+                self.restore_search_origin();
+                self.finish_search();
                 Ok(false)
             }
             _ => Ok(false),
         }
     }
+
+    fn search_direction(&self) -> SearchDirection {
+        if self.current_mode == Mode::Search {
+            SearchDirection::Forward
+        } else {
+            SearchDirection::Backward
+        }
+    }
+
+    /// 'incsearch': move to the first match of the pattern typed so far,
+    /// or back to where the search started when nothing matches
+    fn update_incremental_search(&mut self) {
+        if !self.shared_state.search_state.incsearch {
+            return;
+        }
+        let Some((origin, _)) = self.search_origin else {
+            return;
+        };
+        let direction = self.search_direction();
+        let doc = self.shared_state.session_controller.current_document();
+        let target = self
+            .shared_state
+            .search_state
+            .preview(&self.command_buffer, &direction, doc, origin, self.search_region.as_ref())
+            .map(|m| (m.line, m.start_col));
+
+        match target {
+            Some((line, col)) => {
+                let doc = self.shared_state.session_controller.current_document_mut();
+                doc.move_cursor_to(line, col);
+            }
+            None => self.restore_search_origin(),
+        }
+    }
+
+    /// Put the cursor and viewport back where they were before the search began
+    fn restore_search_origin(&mut self) {
+        if let Some(((line, col), (scroll, horizontal_scroll))) = self.search_origin {
+            let doc = self.shared_state.session_controller.current_document_mut();
+            doc.move_cursor_to(line, col);
+            self.shared_state.view.set_scroll_position(scroll, horizontal_scroll);
+        }
+    }
+
+    fn finish_search(&mut self) {
+        self.shared_state.search_state.clear_preview();
+        self.search_origin = None;
+        self.search_region = None;
+        self.command_buffer.clear();
+        self.current_mode = Mode::Normal;
+    }
     
     fn get_command_buffer_for_mode(&self) -> String {
         match self.current_mode {
@@ -283,23 +1056,578 @@ impl EditorController {
         self.shared_state.session_controller.get_display_filename().to_string()
     }
     
-    fn refresh_unmatched_cache_if_needed(&mut self) {
-        // Only refresh cache if show_all_unmatched is enabled and cache is empty
-        if self.shared_state.show_all_unmatched && self.shared_state.cached_unmatched_brackets.is_none() {
-            let unmatched = self.shared_state.session_controller
-                .current_document()
-                .find_all_unmatched_brackets();
-            self.shared_state.cached_unmatched_brackets = Some(unmatched);
-        }
-        
-        // Clear cache if show_all_unmatched is disabled
-        if !self.shared_state.show_all_unmatched && self.shared_state.cached_unmatched_brackets.is_some() {
+    /// Wait until a terminal event is ready, using the pause to find all
+    /// unmatched brackets on the worker thread. Returns true when a result
+    /// came in first and the screen should be redrawn with it.
+    fn wait_for_input(&mut self) -> std::io::Result<bool> {
+        if !self.shared_state.show_all_unmatched {
+            self.analysis.cancel();
             self.shared_state.cached_unmatched_brackets = None;
+            return Ok(false);
+        }
+
+        let doc = self.shared_state.session_controller.current_document();
+        let revision = doc.revision();
+        if self.shared_state.cached_unmatched_brackets.is_some() && self.analysed_revision == Some(revision) {
+            return Ok(false);
+        }
+        if self.analysis.pending_revision() != Some(revision) {
+            // The text changed: drop the old job, and start over once typing pauses
+            self.analysis.cancel();
+            if self.events.poll(ANALYSIS_IDLE_DELAY)? {
+                return Ok(false);
+            }
+            self.analysis.start(revision, doc.buffer_text());
+        }
+
+        while self.analysis.is_busy() {
+            if self.events.poll(ANALYSIS_POLL_INTERVAL)? {
+                return Ok(false);
+            }
+            if let Some(result) = self.analysis.try_result() {
+                self.analysed_revision = Some(result.revision);
+                self.shared_state.cached_unmatched_brackets = Some(result.unmatched_brackets);
+                return Ok(true);
+            }
         }
+        Ok(false)
     }
     
     /// Apply RC configuration to this editor controller
     pub fn apply_config(&mut self, config: &crate::config::RcConfig) {
         crate::config::RcLoader::apply_config_to_shared_state(&mut self.shared_state, config);
     }
-}
\ No newline at end of file
+
+    /// Bring back marks, registers, history and the last search from the info file.
+    /// A missing file is normal; one that can't be used is noted on the status line.
+    pub fn load_info(&mut self, path: &std::path::Path) {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return;
+        };
+        let info = match InfoFile::parse(&text) {
+            Ok(info) => info,
+            Err(e) => {
+                self.shared_state.status_message = format!("{} not loaded: {}", path.display(), e);
+                return;
+            }
+        };
+        let shared = &mut self.shared_state;
+        info.restore(&mut shared.mark_manager, &mut shared.register_manager, &mut shared.history);
+        if let Some((pattern, direction)) = info.search {
+            let search_state = &mut shared.search_state;
+            if search_state.set_pattern(pattern, direction).is_ok() {
+                let _ = search_state.search_document(shared.session_controller.current_document());
+                // n finds the old pattern, but its matches aren't lit up until searched again
+                search_state.highlight_suspended = true;
+            }
+        }
+    }
+
+    /// Make the text piped in (virus -) the first buffer
+    pub fn open_stdin(&mut self, bytes: &[u8]) {
+        self.shared_state.session_controller.open_stdin(bytes);
+        let lines = bytes.iter().filter(|&&b| b == b'\n').count() + usize::from(bytes.last().is_some_and(|&b| b != b'\n'));
+        self.shared_state.status_message = format!("Read {} lines from stdin", lines);
+    }
+
+    /// Put the cursor of buffer `index` at `position` (+N and file:line on
+    /// the command line)
+    pub fn go_to_position(&mut self, index: usize, position: FilePosition) {
+        if let Some(doc) = self.shared_state.session_controller.buffers.get_mut(index) {
+            position.apply(doc);
+        }
+    }
+
+    /// Compare buffers `old` and `new` side by side (virus -d)
+    pub fn diff_buffers(&mut self, old: usize, new: usize) {
+        let last = self.shared_state.session_controller.buffer_count().saturating_sub(1);
+        DiffCommands::start(&mut self.shared_state, old.min(last), new.min(last));
+    }
+
+    /// Write marks, registers, history and the last search to the info file
+    pub fn save_info(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let shared = &self.shared_state;
+        let info = InfoFile::capture(&shared.mark_manager, &shared.register_manager, &shared.search_state, &shared.history);
+        std::fs::write(path, info.to_text())
+    }
+}
+
+/// Driving the editor without a terminal: embedders and the test harness
+/// in `crate::test_support` feed it events and read back what it would draw
+impl EditorController {
+    /// Handle one event as if read from the terminal. Returns true on quit.
+    /// Shell commands and suspending are left to `run`.
+    pub fn feed_event(&mut self, event: Event) -> Result<bool, Box<dyn std::error::Error>> {
+        self.handle_event(event)
+    }
+
+    /// Read input from `events` instead of the terminal
+    pub fn set_event_source(&mut self, events: Box<dyn EventSource>) {
+        self.events = events;
+    }
+
+    /// The event loop `run` goes through, without taking over the terminal:
+    /// with a scripted event source and the view drawing to memory, a whole
+    /// session runs headless. Ends when the editor quits.
+    pub fn run_events(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.run_loop()
+    }
+
+    /// Type keys in vim's notation (`ihello<Esc>`, `<C-w>v`), stopping at
+    /// the key that quits. Returns true if one did.
+    pub fn feed_keys(&mut self, script: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        for key in parse_keys(script) {
+            if self.handle_event(Event::Key(key))? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Let `idle` pass without input: the timers set by the events so far
+    /// run if they come due. Returns true when the editor should quit.
+    pub fn idle_for(&mut self, idle: Duration) -> Result<bool, Box<dyn std::error::Error>> {
+        let now = Instant::now();
+        self.schedule_timers(now);
+        self.run_timers(now + idle)
+    }
+
+    /// The frame render() would draw now
+    pub fn frame(&mut self) -> Frame {
+        self.with_render_params(|view, view_model, params| view.compose_frame(view_model, params))
+    }
+
+    /// Draw the screen to the view's output (see `View::set_output`)
+    pub fn render(&mut self) -> std::io::Result<()> {
+        self.with_render_params(|view, view_model, params| view.render(view_model, params))
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.current_mode
+    }
+
+    pub fn shared_state(&self) -> &SharedEditorState {
+        &self.shared_state
+    }
+
+    pub fn shared_state_mut(&mut self) -> &mut SharedEditorState {
+        &mut self.shared_state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::Document;
+
+    fn editor_with_lines(lines: usize, size: (u16, u16)) -> EditorController {
+        let mut editor = EditorController::new();
+        let content: Vec<String> = (0..lines).map(|i| format!("line {}", i)).collect();
+        editor.shared_state.session_controller.buffers[0] = Document::from_string(content.join("\n"));
+        editor.shared_state.view.set_terminal_size(Some(size));
+        editor
+    }
+
+    fn press(editor: &mut EditorController, keys: &str) {
+        for c in keys.chars() {
+            let event = Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            assert!(!editor.handle_event(event).unwrap());
+        }
+    }
+
+    /// Lay out a frame the way render() does, without touching the terminal,
+    /// and check the cursor landed inside the text area
+    fn layout_frame(editor: &mut EditorController) -> (usize, usize) {
+        let doc = editor.shared_state.session_controller.current_document();
+        let view_model = DocumentViewModel::new(doc);
+        let view = &mut editor.shared_state.view;
+        let layout = view.update_layout(&view_model, true);
+        let (row, _) = view.cursor_screen_position(&view_model, &layout);
+        if layout.max_lines > 0 {
+            assert!(row >= layout.start_line && row < layout.start_line + layout.max_lines);
+        }
+        (row, layout.max_lines)
+    }
+
+    fn cursor_line(editor: &EditorController) -> usize {
+        editor.shared_state.session_controller.current_document().cursor_line()
+    }
+
+    #[test]
+    fn test_resize_event_updates_view_size() {
+        let mut editor = editor_with_lines(10, (80, 24));
+        assert!(!editor.handle_event(Event::Resize(100, 30)).unwrap());
+        assert_eq!(editor.shared_state.view.terminal_size(), (100, 30));
+        assert_eq!(editor.shared_state.view.get_visible_lines_count(), 28);
+    }
+
+    #[test]
+    fn test_resize_storm_during_visual_selection() {
+        let mut editor = editor_with_lines(300, (80, 24));
+        press(&mut editor, "100G");
+        layout_frame(&mut editor);
+        press(&mut editor, "V");
+        assert_eq!(editor.current_mode, Mode::VisualLine);
+
+        let sizes = [(80, 5), (20, 2), (200, 60), (1, 1), (0, 0), (80, 3), (120, 40), (80, 24)];
+        for (i, &(w, h)) in sizes.iter().cycle().take(40).enumerate() {
+            editor.handle_event(Event::Resize(w, h)).unwrap();
+            press(&mut editor, if i % 3 == 0 { "k" } else { "j" });
+            layout_frame(&mut editor);
+        }
+
+        // Selection is still anchored where it started and follows the cursor
+        let selection = editor.visual_controller.visual_selection.clone().unwrap();
+        assert_eq!(editor.current_mode, Mode::VisualLine);
+        assert_eq!(selection.start_line, 99);
+        assert_eq!(selection.end_line, cursor_line(&editor));
+        assert!(editor.shared_state.view.get_scroll_offset() <= cursor_line(&editor));
+    }
+
+    #[test]
+    fn test_screen_motions_use_resized_height() {
+        let mut editor = editor_with_lines(300, (80, 50));
+        layout_frame(&mut editor);
+
+        editor.handle_event(Event::Resize(80, 12)).unwrap();
+        layout_frame(&mut editor);
+        press(&mut editor, "L");
+        assert_eq!(cursor_line(&editor), 9);
+
+        // Cursor at the bottom row stays put across a shrink then grow
+        editor.handle_event(Event::Resize(80, 6)).unwrap();
+        let (row, max_lines) = layout_frame(&mut editor);
+        assert_eq!(row, max_lines);
+        editor.handle_event(Event::Resize(80, 40)).unwrap();
+        layout_frame(&mut editor);
+        press(&mut editor, "H");
+        assert_eq!(cursor_line(&editor), editor.shared_state.view.get_scroll_offset());
+    }
+
+    fn command(editor: &mut EditorController, command: &str) {
+        press(editor, ":");
+        press(editor, command);
+        editor.handle_event(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))).unwrap();
+    }
+
+    fn ctrl_w(editor: &mut EditorController, key: char) {
+        let event = Event::Key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert!(!editor.handle_event(event).unwrap());
+        press(editor, &key.to_string());
+    }
+
+    #[test]
+    fn test_split_windows_keep_independent_cursors() {
+        let mut editor = editor_with_lines(100, (80, 24));
+        press(&mut editor, "10G");
+        command(&mut editor, "split");
+        assert_eq!(editor.shared_state.view.windows().count(), 2);
+
+        // Move in the new (top) window, then go down to the original one
+        press(&mut editor, "50G");
+        ctrl_w(&mut editor, 'j');
+        assert_eq!(cursor_line(&editor), 9);
+        layout_frame(&mut editor);
+        assert_eq!(editor.shared_state.view.get_visible_lines_count(), 10); // Half the text area, less a status line
+
+        ctrl_w(&mut editor, 'k');
+        assert_eq!(cursor_line(&editor), 49);
+
+        // :q closes only the current window
+        command(&mut editor, "q");
+        assert_eq!(editor.shared_state.view.windows().count(), 1);
+        assert_eq!(cursor_line(&editor), 9);
+    }
+
+    #[test]
+    fn test_vsplit_with_other_buffer() {
+        let mut editor = editor_with_lines(5, (80, 24));
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("other.txt");
+        std::fs::write(&file, "other").unwrap();
+
+        command(&mut editor, &format!("vsplit {}", file.display()));
+        assert_eq!(editor.shared_state.session_controller.get_display_filename(), "other.txt");
+
+        ctrl_w(&mut editor, 'w');
+        assert_eq!(editor.shared_state.session_controller.current_buffer_index(), 0);
+        ctrl_w(&mut editor, 'h'); // The new window is on the left
+        assert_eq!(editor.shared_state.session_controller.get_display_filename(), "other.txt");
+
+        ctrl_w(&mut editor, 'o');
+        assert!(!editor.shared_state.view.windows().is_split());
+    }
+
+    fn key(editor: &mut EditorController, code: KeyCode) {
+        assert!(!editor.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))).unwrap());
+    }
+
+    #[test]
+    fn test_incremental_search_previews_and_restores() {
+        let mut editor = editor_with_lines(300, (80, 24));
+        press(&mut editor, "5G/line 42");
+        assert_eq!(cursor_line(&editor), 42);
+        let search = &editor.shared_state.search_state;
+        assert_eq!(search.highlighted_matches().len(), 1);
+        assert!(search.pattern.is_empty()); // Nothing committed yet
+
+        // The viewport follows the preview
+        layout_frame(&mut editor);
+        assert!(editor.shared_state.view.get_scroll_offset() > 0);
+
+        // No match puts the cursor back; Esc cancels
+        press(&mut editor, "x");
+        assert_eq!(cursor_line(&editor), 4);
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(cursor_line(&editor), 42);
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(cursor_line(&editor), 4);
+        assert_eq!(editor.shared_state.view.get_scroll_offset(), 0);
+        assert!(editor.shared_state.search_state.highlighted_matches().is_empty());
+    }
+
+    #[test]
+    fn test_search_starts_from_cursor_and_noh_clears_highlight() {
+        let mut editor = editor_with_lines(30, (80, 24));
+        press(&mut editor, "15G?line 1");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(cursor_line(&editor), 13); // "line 13", searching backward from line 14
+
+        let matches = editor.shared_state.search_state.highlighted_matches().len();
+        assert_eq!(matches, 11);
+        command(&mut editor, "noh");
+        assert!(editor.shared_state.search_state.highlighted_matches().is_empty());
+
+        press(&mut editor, "n");
+        assert_eq!(editor.shared_state.search_state.highlighted_matches().len(), matches);
+    }
+
+    #[test]
+    fn test_visual_gb_toggles_block_comment() {
+        let mut editor = EditorController::new();
+        let mut doc = Document::from_string("fn main() {\n    a(); // keep\n    b();\n}".to_string());
+        doc.filename = Some(PathBuf::from("main.rs"));
+        editor.shared_state.session_controller.buffers[0] = doc;
+        let text = |editor: &EditorController| {
+            let doc = editor.shared_state.session_controller.current_document();
+            (0..doc.line_count()).filter_map(|i| doc.get_line(i)).collect::<Vec<_>>()
+        };
+        let original = text(&editor);
+
+        press(&mut editor, "jVjgb");
+        assert_eq!(editor.current_mode, Mode::Normal);
+        assert_eq!(text(&editor)[1..3], ["    /* a(); // keep", "    b(); */"]);
+
+        press(&mut editor, "Vjgb");
+        assert_eq!(text(&editor), original);
+
+        // Each toggle undoes in one step
+        press(&mut editor, "u");
+        assert_eq!(text(&editor)[1], "    /* a(); // keep");
+        press(&mut editor, "u");
+        assert_eq!(text(&editor), original);
+    }
+
+    #[test]
+    fn test_repeat_last_command_line() {
+        let mut editor = editor_with_lines(10, (80, 24));
+        let line_count = |editor: &EditorController| editor.shared_state.session_controller.current_document().line_count();
+
+        press(&mut editor, "@:");
+        assert_eq!(editor.shared_state.status_message, "No previous command line");
+
+        command(&mut editor, "d");
+        assert_eq!(line_count(&editor), 9);
+        press(&mut editor, "2@:");
+        assert_eq!(line_count(&editor), 7);
+        press(&mut editor, "@@");
+        assert_eq!(line_count(&editor), 6);
+
+        // Ctrl-r : brings the last command back for editing
+        press(&mut editor, ":");
+        let ctrl_r = Event::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        editor.handle_event(ctrl_r).unwrap();
+        press(&mut editor, ":");
+        assert_eq!(editor.command_controller.command_buffer, "d");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(line_count(&editor), 5);
+        assert_eq!(editor.shared_state.session_controller.current_document().get_line(0).unwrap(), "line 5");
+    }
+
+    #[test]
+    fn test_undo_tree_keeps_undone_branch() {
+        let mut editor = editor_with_lines(4, (80, 24));
+        let text = |editor: &EditorController| {
+            let doc = editor.shared_state.session_controller.current_document();
+            (0..doc.line_count()).filter_map(|i| doc.get_line(i)).collect::<Vec<_>>().join("|")
+        };
+
+        command(&mut editor, "d");
+        command(&mut editor, "d"); // States 1 and 2
+        press(&mut editor, "u");
+        command(&mut editor, "s/line/LINE/"); // State 3 branches off state 1
+        assert_eq!(text(&editor), "LINE 1|line 2|line 3");
+
+        // g- walks back in time, into the branch plain undo lost
+        press(&mut editor, "g-");
+        assert_eq!(text(&editor), "line 2|line 3");
+        assert_eq!(editor.shared_state.status_message, "Undo state 2 of 3");
+        press(&mut editor, "2g-");
+        assert_eq!(text(&editor), "line 0|line 1|line 2|line 3");
+        press(&mut editor, "g-");
+        assert_eq!(editor.shared_state.status_message, "Already at oldest change");
+
+        command(&mut editor, "later 10s");
+        assert_eq!(text(&editor), "LINE 1|line 2|line 3");
+        command(&mut editor, "earlier 2");
+        assert_eq!(text(&editor), "line 1|line 2|line 3");
+
+        command(&mut editor, "undolist");
+        assert_eq!(editor.shared_state.status_message, "2 undo branches listed in preview buffer");
+        let rows = buffer_lines(&editor);
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].starts_with("     2       2"));
+        assert!(rows[2].starts_with("     3       2"));
+    }
+
+    fn editor_with_text(text: &str) -> EditorController {
+        let mut editor = EditorController::new();
+        editor.shared_state.session_controller.buffers[0] = Document::from_string(text.to_string());
+        editor
+    }
+
+    fn buffer_lines(editor: &EditorController) -> Vec<String> {
+        let doc = editor.shared_state.session_controller.current_document();
+        (0..doc.line_count()).filter_map(|i| doc.get_line(i)).collect()
+    }
+
+    fn ctrl_v(editor: &mut EditorController) {
+        let event = Event::Key(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        assert!(!editor.handle_event(event).unwrap());
+    }
+
+    #[test]
+    fn test_block_dollar_append_is_ragged() {
+        let mut editor = editor_with_text("ab\nabcdef\nabcd");
+        ctrl_v(&mut editor);
+        press(&mut editor, "jj$A;");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(buffer_lines(&editor), ["ab;", "abcdef;", "abcd;"]);
+
+        // The whole append undoes in one step
+        press(&mut editor, "u");
+        assert_eq!(buffer_lines(&editor), ["ab", "abcdef", "abcd"]);
+    }
+
+    #[test]
+    fn test_substitute_limited_to_last_block_selection() {
+        let mut editor = editor_with_text("1 1 1\n1 1 1\n1 1 1");
+        press(&mut editor, "w");
+        ctrl_v(&mut editor);
+        press(&mut editor, "j");
+        key(&mut editor, KeyCode::Esc);
+
+        press(&mut editor, ":%s/\\%V1/X/g");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(buffer_lines(&editor), ["1 X 1", "1 X 1", "1 1 1"]);
+    }
+
+    #[test]
+    fn test_substitute_confirm_loop() {
+        let mut editor = editor_with_text("a a\na a\na");
+        press(&mut editor, ":%s/a/b/gc");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.current_mode, Mode::Command);
+        assert_eq!(editor.command_controller.get_command_buffer(), "replace with b (y/n/a/q/l)?");
+        let preview = editor.shared_state.search_state.highlighted_matches().to_vec();
+        assert_eq!(preview.iter().map(|m| (m.line, m.start_col)).collect::<Vec<_>>(), [(0, 0)]);
+
+        // y, n, then the cursor should sit on the third candidate
+        press(&mut editor, "yn");
+        assert_eq!(cursor_line(&editor), 1);
+        press(&mut editor, "a");
+        assert_eq!(editor.current_mode, Mode::Normal);
+        assert_eq!(buffer_lines(&editor), ["b a", "b b", "b"]);
+        assert!(editor.shared_state.search_state.highlighted_matches().is_empty());
+
+        // The whole run undoes in one step
+        press(&mut editor, "u");
+        assert_eq!(buffer_lines(&editor), ["a a", "a a", "a"]);
+
+        // l replaces one more then stops; & repeats on the current line
+        press(&mut editor, ":%s/a/b/c");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "nl");
+        assert_eq!(buffer_lines(&editor), ["a a", "b a", "a"]);
+        press(&mut editor, "G&");
+        assert_eq!(buffer_lines(&editor), ["a a", "b a", "b"]);
+    }
+
+    #[test]
+    fn test_block_append_pads_and_insert_skips_short_rows() {
+        let mut editor = editor_with_text("abc\na\nabc");
+        press(&mut editor, "l");
+        ctrl_v(&mut editor);
+        press(&mut editor, "jjA|");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(buffer_lines(&editor), ["ab|c", "a |", "ab|c"]);
+
+        // $ then a sideways move is a plain block again
+        let mut editor = editor_with_text("abcd\nab\nabcd");
+        press(&mut editor, "ll");
+        ctrl_v(&mut editor);
+        press(&mut editor, "jj$hI#");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(buffer_lines(&editor), ["ab#cd", "ab", "ab#cd"]);
+    }
+
+    #[test]
+    fn test_block_insert_repeats_edited_text_and_undoes_once() {
+        let mut editor = editor_with_text("one\ntwo\nsix");
+        ctrl_v(&mut editor);
+        press(&mut editor, "jjI//x");
+        key(&mut editor, KeyCode::Backspace);
+        press(&mut editor, " ");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(buffer_lines(&editor), ["// one", "// two", "// six"]);
+
+        press(&mut editor, "u");
+        assert_eq!(buffer_lines(&editor), ["one", "two", "six"]);
+    }
+
+    #[test]
+    fn test_browse_directory_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        let mut editor = EditorController::new_with_files(vec![dir.path().to_path_buf()], |_, _| LockChoice::Proceed).unwrap();
+        assert_eq!(buffer_lines(&editor), ["../", "sub/", "a.txt"]);
+
+        // d prompts for a directory name and refreshes the listing
+        press(&mut editor, "d");
+        assert_eq!(editor.current_mode, Mode::Command);
+        assert_eq!(editor.command_controller.command_buffer, "mkdir ");
+        press(&mut editor, "new");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(buffer_lines(&editor), ["../", "new/", "sub/", "a.txt"]);
+        assert!(dir.path().join("new").is_dir());
+
+        // Enter descends, - comes back up to the same entry
+        press(&mut editor, "gg2j");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(buffer_lines(&editor), ["../"]);
+        press(&mut editor, "-");
+        assert_eq!(cursor_line(&editor), 2);
+
+        press(&mut editor, "j");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(buffer_lines(&editor), ["hello"]);
+        assert_eq!(editor.shared_state.session_controller.buffer_count(), 1);
+
+        // % starts :e in the listed directory
+        let mut editor = EditorController::new_with_files(vec![dir.path().to_path_buf()], |_, _| LockChoice::Proceed).unwrap();
+        press(&mut editor, "%");
+        let expected = format!("e {}", dir.path().canonicalize().unwrap().join("").display());
+        assert_eq!(editor.command_controller.command_buffer, expected);
+    }
+}
@@ -0,0 +1,61 @@
+//! Where the editor's input comes from - the terminal, or a script of
+//! events for tests and embedders, so the whole event loop runs the same
+//! either way
+
+use crossterm::event::{self, Event};
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+pub trait EventSource {
+    /// Wait up to `timeout` for an event; true once one can be read
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool>;
+
+    /// The next event, waiting for it if need be
+    fn read(&mut self) -> io::Result<Event>;
+}
+
+/// Events from the terminal, through crossterm
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        event::read()
+    }
+}
+
+/// Events given up front, arriving as if typed one at a time: a poll that
+/// doesn't wait finds nothing, so the screen is drawn before each event,
+/// and any wait at all brings the next one. Past the last event reads
+/// fail with UnexpectedEof, so a loop that never quits still ends.
+#[derive(Debug, Default)]
+pub struct ScriptedEvents {
+    events: VecDeque<Event>,
+}
+
+impl ScriptedEvents {
+    pub fn new(events: impl IntoIterator<Item = Event>) -> Self {
+        Self { events: events.into_iter().collect() }
+    }
+
+    /// Keys in vim's notation, as key mappings take them
+    pub fn keys(script: &str) -> Self {
+        Self::new(crate::controller::key_map::parse_keys(script).into_iter().map(Event::Key))
+    }
+}
+
+impl EventSource for ScriptedEvents {
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        Ok(!timeout.is_zero())
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        self.events
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the event script ran out before the editor quit"))
+    }
+}
@@ -0,0 +1,214 @@
+//! Ex command names - the table every `:` command is looked up in
+//!
+//! As in vim, a command may be typed as any prefix of its full name that is
+//! at least as long as its shortest abbreviation (`:s`, `:sub`, `:se`,
+//! `:setl`, `:vs`). Entries come in priority order, so the first entry that
+//! accepts a prefix wins: `:s` is `:substitute`, not `:set` or `:split`.
+
+/// Full command name and the length of its shortest abbreviation
+const COMMANDS: &[(&str, usize)] = &[
+    ("buffer", 1),
+    ("badd", 3),
+    ("bdelete", 2),
+    ("bf", 2),
+    ("bnext", 2),
+    ("bprevious", 2),
+    ("brackets", 8),
+    ("buffers", 7),
+    ("change", 1),
+    ("call", 3),
+    ("cc", 2),
+    ("cfirst", 4),
+    ("checkbrackets", 13),
+    ("checktime", 6),
+    ("clast", 3),
+    ("close", 3),
+    ("colorscheme", 4),
+    ("cnext", 2),
+    ("copy", 2),
+    ("copen", 4),
+    ("cprevious", 2),
+    ("delete", 1),
+    ("delmarks", 4),
+    ("detab", 5),
+    ("diffget", 5),
+    ("diffoff", 5),
+    ("diffput", 6),
+    ("diffsplit", 5),
+    ("display", 2),
+    ("edit", 1),
+    ("earlier", 2),
+    ("file", 1),
+    ("first", 3),
+    ("format", 4),
+    ("global", 1),
+    ("grep", 2),
+    ("help", 1),
+    ("imap", 2),
+    ("inoremap", 3),
+    ("iunmap", 2),
+    ("jumps", 2),
+    ("list", 1),
+    ("later", 3),
+    ("last", 2),
+    ("let", 3),
+    ("ls", 2),
+    ("lsp", 3),
+    ("map", 3),
+    ("move", 1),
+    ("marks", 5),
+    ("mkdir", 5),
+    ("mksession", 3),
+    ("mkvirus", 7),
+    ("next", 1),
+    ("normal", 4),
+    ("normalize", 9),
+    ("nohlsearch", 3),
+    ("nmap", 2),
+    ("nnoremap", 2),
+    ("noremap", 2),
+    ("nunmap", 3),
+    ("only", 2),
+    ("print", 1),
+    ("previous", 4),
+    ("pop", 2),
+    ("quit", 1),
+    ("qall", 2),
+    ("read", 1),
+    ("recover", 3),
+    ("redraw", 4),
+    ("registers", 3),
+    ("resetscroll", 11),
+    ("retab", 3),
+    ("reverse", 3),
+    ("rewind", 3),
+    ("substitute", 1),
+    ("set", 2),
+    ("setlocal", 4),
+    ("sort", 3),
+    ("source", 2),
+    ("scroll", 6),
+    ("split", 2),
+    ("stats", 5),
+    ("stop", 2),
+    ("suspend", 3),
+    ("tag", 2),
+    ("trim", 3),
+    ("tselect", 2),
+    ("undolist", 5),
+    ("uniq", 3),
+    ("unmap", 3),
+    ("vglobal", 1),
+    ("vsplit", 2),
+    ("vmap", 2),
+    ("vnoremap", 2),
+    ("vunmap", 2),
+    ("wincmd", 4),
+    ("write", 1),
+    ("wall", 2),
+    ("wq", 2),
+    ("wqall", 3),
+    ("xit", 1),
+    ("xall", 2),
+    ("xmap", 2),
+    ("xnoremap", 2),
+    ("xunmap", 2),
+    ("yank", 1),
+    ("argadd", 4),
+    ("args", 2),
+    ("ascii", 5),
+    ("Wrap", 4),
+    ("Gblame", 2),
+    ("Next", 1),
+];
+
+pub struct ExCommands;
+
+impl ExCommands {
+    /// Full name of the command `name` abbreviates, if any
+    pub fn resolve(name: &str) -> Option<&'static str> {
+        COMMANDS
+            .iter()
+            .find(|(full, min)| name.len() >= *min && full.starts_with(name))
+            .map(|(full, _)| *full)
+    }
+
+    /// Split a command line into the full name of its command and whatever
+    /// follows the name: `"setl ts=4"` gives `("setlocal", " ts=4")` and
+    /// `"s/a/b/"` gives `("substitute", "/a/b/")`. A `!` right after the
+    /// name stays with it (`"q!"` is `"quit!"`). None if the line doesn't
+    /// start with a known command name.
+    pub fn split_name(line: &str) -> Option<(String, &str)> {
+        let name_len = line.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(line.len());
+        let full = Self::resolve(&line[..name_len])?;
+        Some(match line[name_len..].strip_prefix('!') {
+            Some(rest) => (format!("{full}!"), rest),
+            None => (full.to_string(), &line[name_len..]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abbreviations_resolve_by_priority() {
+        assert_eq!(ExCommands::resolve("s"), Some("substitute"));
+        assert_eq!(ExCommands::resolve("su"), Some("substitute"));
+        assert_eq!(ExCommands::resolve("se"), Some("set"));
+        assert_eq!(ExCommands::resolve("set"), Some("set"));
+        assert_eq!(ExCommands::resolve("setl"), Some("setlocal"));
+        assert_eq!(ExCommands::resolve("sp"), Some("split"));
+        assert_eq!(ExCommands::resolve("vsp"), Some("vsplit"));
+        assert_eq!(ExCommands::resolve("bprev"), Some("bprevious"));
+        assert_eq!(ExCommands::resolve("x"), Some("xit"));
+        assert_eq!(ExCommands::resolve("ea"), Some("earlier"));
+        assert_eq!(ExCommands::resolve("reg"), Some("registers"));
+        assert_eq!(ExCommands::resolve("di"), Some("display"));
+        assert_eq!(ExCommands::resolve("n"), Some("next"));
+        assert_eq!(ExCommands::resolve("N"), Some("Next"));
+        assert_eq!(ExCommands::resolve("pr"), Some("print"));
+        assert_eq!(ExCommands::resolve("prev"), Some("previous"));
+        assert_eq!(ExCommands::resolve("la"), Some("last"));
+        assert_eq!(ExCommands::resolve("ar"), Some("args"));
+        assert_eq!(ExCommands::resolve("arga"), Some("argadd"));
+        assert_eq!(ExCommands::resolve("co"), Some("copy"));
+        assert_eq!(ExCommands::resolve("cope"), Some("copen"));
+        assert_eq!(ExCommands::resolve("cn"), Some("cnext"));
+        assert_eq!(ExCommands::resolve("cp"), Some("cprevious"));
+        assert_eq!(ExCommands::resolve("gr"), Some("grep"));
+        assert_eq!(ExCommands::resolve("rec"), Some("recover"));
+        assert_eq!(ExCommands::resolve("qa"), Some("qall"));
+        assert_eq!(ExCommands::resolve("wqa"), Some("wqall"));
+        assert_eq!(ExCommands::resolve("xa"), Some("xall"));
+        assert_eq!(ExCommands::resolve("wa"), Some("wall"));
+        assert_eq!(ExCommands::resolve("colo"), Some("colorscheme"));
+        assert_eq!(ExCommands::resolve("no"), Some("noremap"));
+        assert_eq!(ExCommands::resolve("noh"), Some("nohlsearch"));
+        assert_eq!(ExCommands::resolve("nn"), Some("nnoremap"));
+        assert_eq!(ExCommands::resolve("ino"), Some("inoremap"));
+        assert_eq!(ExCommands::resolve("unm"), Some("unmap"));
+        assert_eq!(ExCommands::resolve("norm"), Some("normal"));
+        assert_eq!(ExCommands::resolve("normalize"), Some("normalize"));
+    }
+
+    #[test]
+    fn test_short_or_unknown_names_do_not_resolve() {
+        assert_eq!(ExCommands::resolve("clo"), Some("close"));
+        assert_eq!(ExCommands::resolve("cl"), None);
+        assert_eq!(ExCommands::resolve("undo"), None);
+        assert_eq!(ExCommands::resolve("substitutes"), None);
+        assert_eq!(ExCommands::resolve(""), None);
+    }
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(ExCommands::split_name("s/a/b/g"), Some(("substitute".to_string(), "/a/b/g")));
+        assert_eq!(ExCommands::split_name("setl ts=4"), Some(("setlocal".to_string(), " ts=4")));
+        assert_eq!(ExCommands::split_name("q!"), Some(("quit!".to_string(), "")));
+        assert_eq!(ExCommands::split_name("b3"), Some(("buffer".to_string(), "3")));
+        assert_eq!(ExCommands::split_name("&&"), None);
+        assert_eq!(ExCommands::split_name("clear marks"), None);
+    }
+}
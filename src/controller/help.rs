@@ -1,342 +1,970 @@
+//! The help buffer, made from topics. Each topic is a section headed by its
+//! title and its tag (*registers*); |tag| in the text links to it.
+
 use crate::document_model::Document;
 
+/// One section of the help, which :help and |links| find by its tag
+pub struct HelpTopic {
+    pub tag: &'static str,
+    pub title: &'static str,
+    pub lines: &'static [&'static str],
+}
+
+/// Width the topic headings right-align their tags to
+const HELP_WIDTH: usize = 78;
+
+pub const HELP_TOPICS: &[HelpTopic] = &[
+    HelpTopic {
+        tag: "movement",
+        title: "MOVEMENT (Normal Mode)",
+        lines: &[
+            "  h, ← - Move left",
+            "  j, ↓ - Move down",
+            "  k, ↑ - Move up",
+            "  l, → - Move right",
+        ],
+    },
+    HelpTopic {
+        tag: "word-movement",
+        title: "WORD MOVEMENT",
+        lines: &[
+            "  w - Next word start",
+            "  b - Previous word start",
+            "  e - Next word end",
+            "  W - Next WORD start (space-separated)",
+            "  B - Previous WORD start",
+            "  E - Next WORD end",
+        ],
+    },
+    HelpTopic {
+        tag: "line-movement",
+        title: "LINE MOVEMENT",
+        lines: &[
+            "  0 - Start of line",
+            "  $ - End of line",
+            "  ^ - First non-whitespace character",
+            "  + - Down to first non-whitespace of next line",
+            "  - - Up to first non-whitespace of previous line",
+            "  Enter - Down to first non-whitespace of next line",
+        ],
+    },
+    HelpTopic {
+        tag: "indentation",
+        title: "INDENTATION",
+        lines: &[
+            "  >> - Indent current line",
+            "  << - Dedent current line",
+            "  3>> - Indent 3 lines starting from current",
+            "  5<< - Dedent 5 lines starting from current",
+        ],
+    },
+    HelpTopic {
+        tag: "document-movement",
+        title: "DOCUMENT MOVEMENT",
+        lines: &[
+            "  gg - Start of document",
+            "  G - End of document",
+            "  5G, 5gg - Go to line 5",
+            "  :15 - Go to line 15",
+            "  Ctrl+f, Page Down - Page down",
+            "  Ctrl+b, Page Up - Page up",
+            "  Ctrl+d - Scroll half a page down, cursor too (use Alt+j on macOS if Ctrl+d splits terminal)",
+            "  Ctrl+u - Scroll half a page up, cursor too (5 Ctrl+d / Ctrl+u: 5 lines)",
+            "  Ctrl+e, Ctrl+y - Scroll the view a line down/up; the cursor stays unless it leaves the screen",
+            "  zt, zz, zb - Scroll the cursor's line to the top, middle or bottom of the window",
+            "  zl, zh - Scroll the view right/left (3zl for 3 columns)",
+            "  zL, zH - Scroll the view right/left half a screen width",
+        ],
+    },
+    HelpTopic {
+        tag: "character-search",
+        title: "CHARACTER SEARCH",
+        lines: &[
+            "  f{char} - Find character forward",
+            "  F{char} - Find character backward",
+            "  t{char} - Move to before character forward",
+            "  T{char} - Move to before character backward",
+            "  ; - Repeat last find",
+            "  , - Repeat last find (reverse)",
+        ],
+    },
+    HelpTopic {
+        tag: "marks-jumps",
+        title: "MARKS & JUMPS",
+        lines: &[
+            "  m{a-z} - Set local mark (a-z)",
+            "  m{A-Z} - Set global mark (A-Z, across files)",
+            "  '{a-z,A-Z} - Jump to mark line (switches files for A-Z)",
+            "  `{a-z,A-Z} - Jump to exact mark position (switches files)",
+            "  '' - Jump to last jump position",
+            "  '. - Jump to last change position (in this buffer)",
+            "  '^ - Jump to last insert position",
+            "  d'a, y'a, c'a - Delete, yank or change lines to mark a",
+            "  d`a, y`a, c`a - Delete, yank or change up to mark a",
+            "  v'a, V`a - Extend a visual selection to mark a",
+            "  Ctrl+o - Jump backward in jump list (switches files)",
+            "  Ctrl+i - Jump forward in jump list (switches files)",
+            "  g; / g, - Go to an older / newer change position (3g; goes back three)",
+            "  :marks - List all marks",
+            "  :jumps, :ju - Show jump list history",
+            "  :clear marks - Clear all user marks (a-z, A-Z)",
+            "  :clear jumps - Clear jump list history",
+            "  :clear all - Clear marks and jumps",
+            "  :clear - Clear/redraw screen",
+        ],
+    },
+    HelpTopic {
+        tag: "search",
+        title: "SEARCH & REPLACE",
+        lines: &[
+            "  /{pattern} - Search forward for pattern (regex)",
+            "  ?{pattern} - Search backward for pattern (regex)",
+            "  n - Next search result (same direction)",
+            "  N - Previous search result (opposite direction)",
+            "  A search shows which match the cursor is on: /foo/  [3/17]",
+            "  * / # - Search forward / backward for the word under the cursor, as a whole word",
+            "  g* / g# - The same, finding it inside other words too",
+            "  \\<word\\> in a pattern - Match only at the start / end of a word",
+            "  \\%V in a pattern - Match only inside the buffer's last visual selection (gv)",
+            "  :noh, :nohlsearch - Clear match highlighting until the next search",
+            "  :set hlsearch / nohlsearch - Highlight all matches of the last search",
+            "  :set incsearch / noincsearch - Jump to matches while typing the pattern",
+            "  :set ignorecase / noignorecase (ic) - Searches, * # and :s ignore case",
+            "  :set smartcase (scs) - ...unless the pattern has a capital letter",
+            "  :set shortmess=S (shm) - Leave the [3/17] match count out",
+            "  \\c / \\C in a pattern - Ignore / match case for this pattern only",
+            "  :s/old/new/ - Replace first match on current line only",
+            "  :s/old/new/g - Replace all matches on current line only",
+            "  :s/old/new/i - Case-insensitive replace on current line only",
+            "  :s/old/new/e - No error if the pattern isn't found",
+            "  :%s/old/new/ - Replace first match on ALL lines (% = entire document)",
+            "  :%s/old/new/g - Replace all matches on ALL lines (entire document)",
+            "  :%s/old/new/gi - Global case-insensitive replace (entire document)",
+            "  :%s/old/new/gc - Confirm each match: y yes, n no, a all, q quit, l last",
+            "  :%s/old/new/gn - Count matches without replacing",
+            "  & / :& - Repeat last :s on current line without flags; :&& keeps flags",
+            "  :2,5s/old/new/g - Replace in lines 2-5",
+            "  :'a,'bs/old/new/g - Replace between marks a and b",
+            "  Patterns are regular expressions: ^ and $ anchor to line start/end, \\n matches a line break",
+            "  :s/(\\w+) (\\w+)/\\2 \\1/ - Groups: \\1-\\9 or $1-$9 in the new text, & or \\0 for the whole match",
+            "  :s/x/\\u&/ - \\u \\l change the case of the next character, \\U \\L of the rest up to \\E",
+            "  :s#/usr#/opt# - Any punctuation can stand in for /; a backslash puts it in the text",
+            "  :%s/,\\n/, / - Join lines ending in a comma; \\r in new text splits a line",
+            "  :%s/\\%Vold/new/g - Replace only inside the last visual selection (e.g. one block column)",
+            "  :'<,'>s/old/new/g - From visual mode: replace only in the selected text",
+            "  :stats - Searches and substitutions per buffer and file type this session, in a preview buffer",
+            "  Search results are highlighted in yellow",
+            "  While typing a search, the match to jump to is magenta; Esc returns",
+            "  Brackets under cursor are highlighted in cyan",
+            "  Unmatched brackets are highlighted in red",
+            "  Examples:",
+            "    /test - Find 'test' forward (highlighted in yellow)",
+            "    ?hello - Find 'hello' backward (highlighted in yellow)",
+            "    :s/foo/bar/ - Replace first 'foo' with 'bar'",
+            "    :%s/\\d+/NUMBER/g - Replace all numbers with 'NUMBER'",
+        ],
+    },
+    HelpTopic {
+        tag: "grep-quickfix",
+        title: "GREP & QUICKFIX",
+        lines: &[
+            "  :grep [-i] pattern [files] - Search files with grep (globs work) into the quickfix list",
+            "  :grep pattern - Without files, search the files of the open buffers",
+            "  :cnext, :cn [n] / :cprevious, :cp [n] - Go to the next / previous match",
+            "  :cfirst / :clast / :cc [n] - Go to the first, last or nth match",
+            "  :copen, :cope - Pick a match from the list (j/k, Enter)",
+        ],
+    },
+    HelpTopic {
+        tag: "tags",
+        title: "TAGS",
+        lines: &[
+            "  Uses the nearest tags file (ctags) at or above the file's directory, else ./tags",
+            "  Ctrl+] - Jump to the definition of the identifier under the cursor",
+            "  Ctrl+t / :pop, :po [n] - Go back to where the last (nth) tag jump started",
+            "  :tag, :ta name - Jump to the first definition of name",
+            "  :tselect, :ts [name] - Pick among the definitions of name, or the last tag (j/k, Enter)",
+        ],
+    },
+    HelpTopic {
+        tag: "language-servers",
+        title: "LANGUAGE SERVERS",
+        lines: &[
+            "  :lsp filetype command - Serve files of filetype with command (.virusrc: lsp rust rust-analyzer)",
+            "  :lsp - List the language servers",
+            "  Errors (E>) and warnings (W>) show in the sign column and are underlined",
+            "  gd - Go to the definition of the symbol under the cursor (without a server: its first use)",
+            "  K - Show the diagnostics on the line and the server's hover text for the symbol",
+        ],
+    },
+    HelpTopic {
+        tag: "range-operations",
+        title: "RANGE OPERATIONS",
+        lines: &[
+            "  Range prefixes can be used with most line-based commands:",
+            "  % - All lines (entire document)",
+            "  $ - Last line",
+            "  . - Current line (default if no range specified)",
+            "  5 - Line 5",
+            "  2,8 - Lines 2 through 8",
+            "  2,$ - Lines 2 through end of file",
+            "  .,$ - Current line through end of file",
+            "  'a,'b - From mark a to mark b",
+            "  +3 - 3 lines forward from current",
+            "  -2 - 2 lines backward from current",
+        ],
+    },
+    HelpTopic {
+        tag: "range-delete-commands",
+        title: "RANGE DELETE COMMANDS",
+        lines: &[
+            "  :2,5d - Delete lines 2-5",
+            "  :%d - Delete entire document",
+            "  :.,+3d - Delete current line and 3 lines below",
+            "  :'a,'bd - Delete from mark a to mark b",
+        ],
+    },
+    HelpTopic {
+        tag: "range-yank-commands",
+        title: "RANGE YANK COMMANDS",
+        lines: &[
+            "  :2,5y - Yank (copy) lines 2-5",
+            "  :%y - Yank entire document",
+            "  :'a,'by - Yank from mark a to mark b",
+        ],
+    },
+    HelpTopic {
+        tag: "range-copy-move-commands",
+        title: "RANGE COPY/MOVE COMMANDS",
+        lines: &[
+            "  :2,5co 10 - Copy lines 2-5 after line 10",
+            "  :2,5m 10 - Move lines 2-5 after line 10",
+            "  :%co 0 - Copy entire document to beginning",
+        ],
+    },
+    HelpTopic {
+        tag: "range-print-commands",
+        title: "RANGE PRINT COMMANDS",
+        lines: &[
+            "  :2,5p - Print lines 2-5 in new preview buffer",
+            "  :2,5# - Print lines 2-5 with line numbers in preview buffer",
+            "  :2,5l - List lines 2-5 (show whitespace) in preview buffer",
+            "  :%p - Print entire document in preview buffer",
+            "  Preview buffers can be closed with :bd",
+        ],
+    },
+    HelpTopic {
+        tag: "global-commands",
+        title: "GLOBAL COMMANDS",
+        lines: &[
+            "  :g/pattern/cmd - Run ex command on every line matching pattern",
+            "  :v/pattern/cmd - Run ex command on every line NOT matching (also :g!)",
+            "  :g/TODO/d - Delete all lines containing TODO",
+            "  :g/^/m 0 - Reverse the file",
+            "  :10,20g/x/s/a/b/ - Limit to lines 10-20",
+            "  :g/pattern/ - Print matching lines in preview buffer",
+            "  The whole :g operation is undone with a single u",
+            "",
+            ":NORMAL:",
+            "  :normal {keys}, :norm - Type keys in normal mode at the cursor, as if typed",
+            "  :%norm Ax - With a range, type them from the start of each line (:'<,'>norm for a selection)",
+            "  :g/TODO/norm dd - Type them on every matching line",
+            "  Keys are written as in :map (<Esc>, <CR>, <C-w>, <lt>); mappings apply",
+            "  :normal! {keys} - The same, without mappings",
+            "  An unfinished insert or command ends as Esc would; one u undoes it all",
+        ],
+    },
+    HelpTopic {
+        tag: "operators",
+        title: "OPERATORS",
+        lines: &[
+            "  d c y - Delete, change, yank",
+            "  gu gU g~ - Lowercase, uppercase, toggle case",
+            "  > < - Indent, dedent the lines covered",
+            "  gq - Refill the lines covered to textwidth, keeping indent and // # > leaders",
+            "  Each takes any motion: w b e W B E 0 $ ^ h l j k G gg % f F t T 'a `a",
+            "  Doubled, an operator works on lines: dd, yy, >>, guu, gUU, g~~, gqq",
+            "  Counts before and after multiply: 2d3w deletes 6 words",
+        ],
+    },
+    HelpTopic {
+        tag: "surround",
+        title: "SURROUND",
+        lines: &[
+            "  ys{motion}{c} - Surround the text covered with c: ysw) makes (word)",
+            "  yss{c} - Surround the line's text",
+            "  cs{t}{c} - Change the t pair around the cursor to c: cs\"' turns \"x\" into 'x'",
+            "  ds{t} - Delete the t pair around the cursor",
+            "  ( [ { add a space inside the pair and take it away again; ) ] } b B r a don't",
+            "  ~ - Toggle the case of the character under the cursor",
+            "  J - Join the line with the next one; 3J joins three lines",
+            "  gJ - Join without adding a space or removing the next line's indent",
+        ],
+    },
+    HelpTopic {
+        tag: "delete-operations",
+        title: "DELETE OPERATIONS",
+        lines: &[
+            "  x - Delete character forward (at cursor)",
+            "  X - Delete character backward",
+            "  s - Substitute character (delete and enter insert mode)",
+            "  S - Substitute line (clear line and enter insert mode)",
+            "  D - Delete to end of line",
+            "  dd - Delete entire line",
+            "  Backspace - Delete character backward (insert mode)",
+        ],
+    },
+    HelpTopic {
+        tag: "motion-based-delete",
+        title: "MOTION-BASED DELETE",
+        lines: &[
+            "  dw - Delete word forward",
+            "  dW - Delete WORD forward (space-separated)",
+            "  db - Delete word backward",
+            "  dB - Delete WORD backward",
+            "  de - Delete to end of word",
+            "  dE - Delete to end of WORD",
+            "  d0 - Delete to beginning of line",
+            "  d$ - Delete to end of line (same as D)",
+            "  d^ - Delete to first non-whitespace character",
+            "  dgg - Delete to beginning of file",
+            "  dG - Delete to end of file",
+        ],
+    },
+    HelpTopic {
+        tag: "character-based-delete",
+        title: "CHARACTER-BASED DELETE",
+        lines: &[
+            "  dt{char} - Delete until (but not including) character",
+            "  dT{char} - Delete backward until character",
+            "  df{char} - Delete including character forward",
+            "  dF{char} - Delete including character backward",
+        ],
+    },
+    HelpTopic {
+        tag: "numbered-delete-commands",
+        title: "NUMBERED DELETE COMMANDS",
+        lines: &[
+            "  3dd - Delete 3 lines starting from current",
+            "  5dw - Delete 5 words forward",
+            "  2db - Delete 2 words backward",
+            "  d3w - Delete 3 words forward (count after operator)",
+            "  d2j / d2k - Delete current line and 2 lines below / above",
+            "  d2f{char} - Delete through 2nd occurrence of character",
+            "  4>> - Indent 4 lines (example of numbered commands)",
+        ],
+    },
+    HelpTopic {
+        tag: "change-operations",
+        title: "CHANGE OPERATIONS (delete + enter insert mode)",
+        lines: &[
+            "  cc - Change entire line",
+            "  C - Change to end of line",
+            "  cw - Change word forward",
+            "  cW - Change WORD forward (space-separated)",
+            "  cb - Change word backward",
+            "  cB - Change WORD backward",
+            "  ce - Change to end of word",
+            "  cE - Change to end of WORD",
+            "  c0 - Change to beginning of line",
+            "  c$ - Change to end of line (same as C)",
+            "  c^ - Change to first non-whitespace character",
+            "  cgg - Change to beginning of file",
+            "  cG - Change to end of file",
+        ],
+    },
+    HelpTopic {
+        tag: "character-based-change",
+        title: "CHARACTER-BASED CHANGE",
+        lines: &[
+            "  ct{char} - Change until (but not including) character",
+            "  cT{char} - Change backward until character",
+            "  cf{char} - Change including character forward",
+            "  cF{char} - Change including character backward",
+        ],
+    },
+    HelpTopic {
+        tag: "numbered-change-commands",
+        title: "NUMBERED CHANGE COMMANDS",
+        lines: &[
+            "  3cc - Change 3 lines starting from current",
+            "  5cw - Change 5 words forward",
+            "  2cb - Change 2 words backward",
+            "  c2j / c2k - Change current line and 2 lines below / above",
+        ],
+    },
+    HelpTopic {
+        tag: "yank",
+        title: "YANK (COPY) OPERATIONS",
+        lines: &[
+            "  yy - Yank (copy) current line",
+            "  3yy - Yank 3 lines starting from current",
+            "  y4j / y4k - Yank current line and 4 lines below / above",
+            "  yw - Yank word forward",
+            "  yW - Yank WORD forward (space-separated)",
+            "  yb - Yank word backward",
+            "  yB - Yank WORD backward",
+            "  ye - Yank to end of word",
+            "  yE - Yank to end of WORD",
+            "  y0 - Yank to start of line",
+            "  y$ - Yank to end of line",
+            "  y^ - Yank to first non-whitespace character",
+            "  yG - Yank to end of file",
+            "  ygg - Yank to start of file",
+            "  yt{char} - Yank until (but not including) character",
+            "  yT{char} - Yank backward until character",
+            "  yf{char} - Yank including character forward",
+            "  yF{char} - Yank including character backward",
+        ],
+    },
+    HelpTopic {
+        tag: "paste-operations",
+        title: "PASTE OPERATIONS",
+        lines: &[
+            "  p - Paste after cursor/line",
+            "  P - Paste before cursor/line",
+            "  Lines paste below/above the line, text after/at the cursor, a block as a column",
+            "    (at the same screen column on every row, padding short lines with spaces)",
+            "  3p - Paste 3 copies after cursor/line (a block's rows repeat side by side)",
+            "  Ctrl+p - Right after p/P, swap the paste for the previous yank or delete",
+        ],
+    },
+    HelpTopic {
+        tag: "registers",
+        title: "NAMED REGISTERS",
+        lines: &[
+            "  \"ayy - Yank current line to register 'a'",
+            "  \"ayw - Yank word to register 'a'",
+            "  \"ap - Paste from register 'a' after cursor",
+            "  \"aP - Paste from register 'a' before cursor",
+            "  Registers a-z: replace content, A-Z: append to content (\"Ayy adds a line to a)",
+            "  :registers, :reg, :di [names] - List what the registers hold (:reg a1 for just a and 1)",
+            "  Register 0: the last yank; 1-9: line deletes, newest first (\"2p pastes the one before)",
+            "  Register '-': the last delete within a line (x, dw, ...)",
+            "  Register ':' holds the last command line (read-only)",
+            "  Registers '%' and '#': the current and alternate file names (read-only)",
+            "  Registers '+' and '*': system clipboard (\"+yy copies, \"+p pastes)",
+            "  @: - Repeat the last command line (3@: repeats it 3 times, @@ again)",
+            "  Ctrl+r {reg} - In command mode, insert a register (Ctrl+r : for the last command)",
+        ],
+    },
+    HelpTopic {
+        tag: "history",
+        title: "HISTORY",
+        lines: &[
+            "  Up / Down - At the : or / prompt, step through earlier lines starting with what's typed",
+            "  q: - Open the command line history in a buffer; Enter runs the line under the cursor",
+            "  q/ q? - Open the search history the same way; Enter searches forward / backward",
+            "  The last 100 command lines and searches are kept in ~/.virusinfo",
+            "  Examples:",
+            "    \"ayy - Copy line to register 'a'",
+            "    \"byW - Copy WORD to register 'b'",
+            "    \"ap - Paste register 'a' content",
+        ],
+    },
+    HelpTopic {
+        tag: "visual-mode",
+        title: "VISUAL MODE",
+        lines: &[
+            "  v - Enter visual character mode",
+            "  V - Enter visual line mode",
+            "  Ctrl+v - Enter visual block mode",
+            "  gv - Select the buffer's last visual selection again (in visual mode: swap with it)",
+            "  Esc - Exit visual mode",
+            "  o - Go to the other end of the selection",
+            "  / or ? - Search inside the selection (n and N keep to it until the next search)",
+            "  O - In block mode, go to the other corner of the row (elsewhere as o)",
+            "  d, x - Delete selected text",
+            "  y - Yank (copy) selected text",
+            "  c, s - Change selected text (on every row in block mode)",
+            "  > - Indent selected lines (3> indents them three steps)",
+            "  < - Dedent selected lines (3< dedents them three steps)",
+            "  u, U, ~ - Lowercase, uppercase, toggle case of the selection",
+            "  r{char} - Replace every selected character with {char}",
+            "  J - Join the selected lines",
+            "  p - Put the register over the selection, which goes to the register",
+            "  P - Put the register over the selection, keeping the register",
+            "  \"{register} - Use that register for the next d, y, c or p",
+            "  gq - Refill the selected lines to textwidth",
+            "  I / A - In block mode, insert before / append after the block on every row",
+            "  $ - In block mode, extend each row to its own end ($A appends to ragged lines)",
+            "  gb - Toggle a block comment (/* */ or <!-- -->, by file type)",
+            "  All movement keys work in visual mode",
+        ],
+    },
+    HelpTopic {
+        tag: "insert-modes",
+        title: "INSERT MODES",
+        lines: &[
+            "  i - Insert before cursor",
+            "  a - Insert after cursor",
+            "  o - Open new line below",
+            "  O - Open new line above",
+            "  A - Insert at end of line",
+            "  I - Insert at start of line",
+            "  Esc - Return to normal mode",
+        ],
+    },
+    HelpTopic {
+        tag: "keys-in-insert-mode",
+        title: "KEYS IN INSERT MODE (each part of the insert's undo step)",
+        lines: &[
+            "  Ctrl-W - Delete the word before the cursor",
+            "  Ctrl-U - Delete what this insert typed on the line, then back to the indent",
+            "  Ctrl-T / Ctrl-D - Indent / dedent the line by shiftwidth",
+            "  Ctrl-R {register} - Insert the register's text",
+            "  Ctrl-V {key} - Insert the key as it is: a real tab with expandtab, ^A for Ctrl-A",
+            "  Ctrl-V u1234 / U00012345 / x41 / o101 / 065 - Insert a character by its code",
+            "  Ctrl-K {char}{char} - Insert a digraph: e' is é, a: is ä, n? is ñ, Eu is €",
+            "  :set showmatch (sm) - A typed ), ] or } briefly highlights the bracket it closes",
+            "    (its line goes on the status line when that bracket is off the screen)",
+            "  :set matchtime=N (mat) - ...for N tenths of a second (default 5)",
+        ],
+    },
+    HelpTopic {
+        tag: "file-operations",
+        title: "FILE OPERATIONS",
+        lines: &[
+            "  :w - Save current file",
+            "  :w filename - Save as filename",
+            "  :10,20w file, :'<,'>w file - Write just those lines to another file",
+            "  :w >> file - Append the buffer (or a range) to a file; :w! >> creates it",
+            "  :w !cmd - Pipe the buffer (or a range) to a shell command and show its output",
+            "  :wq - Save and quit",
+            "  :x, :xit - Save if modified, then quit",
+            "  :wa, :wall - Save every modified buffer",
+            "  ZZ / ZQ - Same as :x / :q!",
+            "  :q - Quit (if no changes; asks about other unsaved buffers)",
+            "  :q! - Force quit without saving",
+            "  :qa, :qall - Quit if no buffer has unsaved changes",
+            "  :qa! - Quit, discarding changes in every buffer",
+            "  :wqa, :xa - Write every modified buffer and quit",
+            "  :f - Show file information",
+            "  :w! - Save a read-only [RO] buffer, taking over its lock",
+            "  :checktime - Look for files changed on disk: (l)oad the file or (k)eep the buffer",
+            "    (also checked when the terminal regains focus and after :!cmd)",
+            "  :set autoread / noautoread - Reload unmodified buffers without asking",
+            "  :set autosave[=N] - Write modified buffers after N seconds without edits",
+            "    (30 if N is left out), on leaving a buffer and when the terminal loses",
+            "    focus; :set noautosave turns it off",
+            "  :set ticktime=N - Redraw every N ms while idle (0 = only for input and timers)",
+            "  :set confirm (cf) - Ask instead of failing: :q and :e with unsaved changes",
+            "    offer (y)es, (n)o, (a)ll or (c)ancel; :w name asks before overwriting a file",
+            "  :set showcmd (sc) - Show a command typed in part (\"a2d) at the right of the status line",
+            "  :set timeout (to) - Drop a command typed in part once typing pauses for timeoutlen",
+            "  :set timeoutlen=N (tm) - ...N ms (default 1000); also how long mappings wait",
+        ],
+    },
+    HelpTopic {
+        tag: "file-locking",
+        title: "FILE LOCKING",
+        lines: &[
+            "  Open files are guarded by a .name.virus-lock file next to them",
+            "  If another vi-rus (or vim, via .name.swp) has the file open, you",
+            "  are asked to open read-only, edit anyway, or abort",
+            "  Files opened with :e while locked elsewhere open read-only",
+        ],
+    },
+    HelpTopic {
+        tag: "swap-files-recovery",
+        title: "SWAP FILES & RECOVERY",
+        lines: &[
+            "  Unsaved edits are journaled to a .name.virus-swap file next to the file",
+            "    (every 200 changes and whenever typing pauses; removed on :w or quit)",
+            "  Opening a file whose swap file a crashed vi-rus left offers (R)ecover",
+            "  :recover, :rec - Restore the current buffer from its swap file",
+            "  virus -r file - Open file, recovering it from its swap file",
+            "  virus -r - List the swap files in the current directory",
+        ],
+    },
+    HelpTopic {
+        tag: "windows",
+        title: "WINDOWS",
+        lines: &[
+            "  :split, :sp [file] - Split window horizontally",
+            "  :vsplit, :vs [file] - Split window vertically",
+            "  :close - Close current window",
+            "  :only - Close all other windows",
+            "  :q - Close current window when split (quits with one window)",
+            "  Ctrl+w s / Ctrl+w v - Split horizontally / vertically",
+            "  Ctrl+w w / Ctrl+w W - Next / previous window",
+            "  Ctrl+w h/j/k/l - Move to window left/below/above/right",
+            "  Ctrl+w c / Ctrl+w o - Close window / close other windows",
+            "  :wincmd {c} - Run the Ctrl+w command {c}",
+        ],
+    },
+    HelpTopic {
+        tag: "diff-mode",
+        title: "DIFF MODE",
+        lines: &[
+            "  virus -d old new - Compare two files side by side",
+            "  :diffsplit, :diffs file - Compare the current buffer with file",
+            "  ]c / [c - Next / previous change",
+            "  do / dp - Take the other side's change / give it this side's",
+            "  :diffget / :diffput - Same as do / dp",
+            "  :diffoff - Leave diff mode",
+        ],
+    },
+    HelpTopic {
+        tag: "git",
+        title: "GIT",
+        lines: &[
+            "  Sign column - + added, ~ modified, _ lines removed below, since the file was staged",
+            "  :Gblame, :Gb - Commit, date and author of each line, in a window scrolling with this one",
+        ],
+    },
+    HelpTopic {
+        tag: "sessions",
+        title: "SESSIONS",
+        lines: &[
+            "  :mksession [file] - Save buffers, windows, cursors and settings (default Session.vim)",
+            "  :mksession! [file] - Same, overwriting an existing file",
+            "  :source file - Run each line of file as a : command",
+            "  virus -S [file] - Start with a saved session",
+        ],
+    },
+    HelpTopic {
+        tag: "buffer-operations",
+        title: "BUFFER OPERATIONS",
+        lines: &[
+            "  :e - Create new empty buffer",
+            "  :e filename - Edit/open new file",
+            "  :e file1 file2 - Open multiple files as buffers",
+            "  :e *.md, :e src/**/*.rs - Open every file a wildcard matches (** = any depth);",
+            "    :args and :badd take them too",
+            "  % / # in a file or shell command - The current / alternate (edited before) file,",
+            "    with :p :h :t :r :e for full path, directory, name, root, extension (\\% = %)",
+            "  Ctrl-^ (Ctrl-6) - Edit the alternate file, the one this window edited before;",
+            "    N Ctrl-^ edits buffer N",
+            "  :e file +N, :e file:line[:col] - Open file with the cursor on line N (+ alone: last line)",
+            "  virus +N file, virus file:line[:col] - Start with the cursor there",
+            "  cmd | virus - - Edit what is piped in as an unnamed buffer",
+            "  virus -c cmd file, virus +cmd file - Run an ex command once the file is loaded",
+            "  virus -es +cmd... file - Run the commands without the terminal, then exit",
+            "  :badd - Add new empty buffer",
+            "  :badd file1 file2 - Add multiple files to buffer list",
+            "  :ls - Buffer list (% = current, # = alternate, + = modified); j/k, Enter to switch",
+            "  :b1, :b2, :b3 - Switch to buffer 1, 2, 3",
+            "  :b name - Switch by partial name (main, mrs -> main.rs)",
+            "  :bf filename - Switch to buffer by filename",
+            "  :bn - Next buffer",
+            "  :bp - Previous buffer",
+            "  :bd - Close current buffer",
+            "  :bd! - Force close buffer (discard unsaved changes)",
+        ],
+    },
+    HelpTopic {
+        tag: "args",
+        title: "ARGUMENT LIST",
+        lines: &[
+            "  The files named on the command line, kept apart from the buffer list",
+            "  :args - Show the list, the current file in [brackets]",
+            "  :args file1 file2, :next file1 file2 - Make a new list and edit its first file",
+            "  :n, :next [N] - Edit the next (Nth next) file in the list",
+            "  :N, :prev [N] - Edit the previous (Nth previous) file",
+            "  :first, :rewind, :last - Edit the first / last file",
+            "  :argadd [file...] - Add files (or this buffer's) after the current file",
+        ],
+    },
+    HelpTopic {
+        tag: "directory",
+        title: "DIRECTORY LISTINGS (vi-rus dir/, :e .)",
+        lines: &[
+            "  Enter - Open the file or directory under the cursor",
+            "  - - Go up to the parent directory",
+            "  d - Create a directory (:mkdir name)",
+            "  % - Start a new file in this directory",
+        ],
+    },
+    HelpTopic {
+        tag: "read-operations",
+        title: "READ OPERATIONS",
+        lines: &[
+            "  :r filename - Insert file at cursor",
+            "  :[line]r !command - Insert command output after the line",
+            "  :{range}!command - Filter lines through a command (:'<,'>!sort)",
+            "  :!command - Run a command in the terminal, Enter to return",
+            "  Ctrl+z, :stop, :suspend - Go back to the shell; fg brings the editor back",
+            "  :0r filename - Insert at beginning",
+            "  :$r filename - Insert at end",
+            "  :10r filename - Insert after line 10",
+        ],
+    },
+    HelpTopic {
+        tag: "tabs-spaces",
+        title: "TABS & SPACES",
+        lines: &[
+            "  :set tabstop=4 - Set tab width to 4 spaces",
+            "  :set et - Tab key inserts spaces",
+            "  :set noet - Tab key inserts tabs",
+            "  :set sw=N, :set shiftwidth=N - Indent step of > and < (0 = tabstop)",
+            "  :set sr, :set shiftround - > and < round the indent to a multiple of shiftwidth",
+            "  :set sw=2 sr - Set several options at once",
+            "  :set ai, :set autoindent - New lines keep the indent of the line before",
+            "  :set autopairs - Typing ( [ { \" ' ` adds the closing one; Backspace removes an empty pair",
+            "  :set ft - Show the file type worked out from the file name or a #! line",
+            "  File types start with their own indentation (make, go: tabs; python, rust: 4 spaces)",
+            "  A modeline in a file's first or last 5 lines overrides it: # vim: set sw=2 et:",
+            "  :set list - Show whitespace characters",
+            "  :set nolist - Hide whitespace characters",
+            "  :set listchars=tab:»·,trail:·,nbsp:␣,eol:$ (lcs) - What list shows for tabs, trailing",
+            "    spaces, non-breaking spaces and line ends (space:c marks every space)",
+            "  :set hltrailing - Highlight spaces and tabs at the ends of lines",
+            "  :set cursorline (cul) - Highlight the line the cursor is on",
+            "  :set colorcolumn=80,100 (cc) - Mark these screen columns on every line (empty for none)",
+            "  :set synmaxcol=N - Highlight only the first N columns of long lines",
+            "  :[range]detab [N] - Turn indentation into spaces",
+            "  :[range]retab [N] - Turn indentation into tabs (N sets a new tabstop)",
+            "  :[range]retab! [N] - Retab all whitespace, not just indentation",
+            "  :[range]trim - Strip trailing whitespace (whole file by default)",
+            "  :set trimtrailing - Strip trailing whitespace whenever the file is written",
+            "  :[range]sort[!] [u][n][i] - Sort lines (! reverse, u unique, n numeric, i ignore case)",
+            "  :[range]uniq [i] - Remove repeated adjacent lines",
+            "  :[range]reverse - Reverse the order of lines",
+            "  : in visual mode - Run a command on the selected lines (:'<,'>)",
+            "  :ascii - Normalize Unicode characters to ASCII equivalents",
+            "  :normalize - Same as :ascii",
+            "  :brackets - Check for unmatched brackets",
+            "  :checkbrackets - Same as :brackets",
+            "  :colorscheme name - Switch colours: default, dark, light, solarized, mono,",
+            "    or ~/.virus/themes/name.toml (:colo alone shows the current one)",
+            "  :redraw - Force screen refresh",
+            "  :scroll - Show scroll information",
+            "  :resetscroll - Reset scroll position",
+        ],
+    },
+    HelpTopic {
+        tag: "edit-operations",
+        title: "EDIT OPERATIONS",
+        lines: &[
+            "  :paste - Paste from clipboard",
+            "  Terminal paste goes in as one change (before the cursor in normal mode)",
+            "  :set ff=unix - Set Unix line endings (:set fileformat=, written on save)",
+            "  :set ff=dos - Set Windows line endings",
+            "  :set ff=mac - Set Mac line endings",
+            "  :set ff - Show the buffer's line endings",
+            "  :set eol / noeol - End the last line with a line break on save, or not",
+            "  :set fenc=latin1 - Write the file as utf-8, utf-16le, utf-16be or latin1",
+            "  :set fenc - Show the encoding the file was read in (BOM and UTF-16 detected;",
+            "    other non-UTF-8 files read as latin1, binary files open read-only)",
+            "  UTF-8 files of 16MB or more open at once and load in the background, shown",
+            "    as [Loading N%] in the status line; edits and :w wait for the rest",
+            "    (with Unix line endings they are mapped into memory rather than copied)",
+            "  :set nu - Show line numbers",
+            "  :set nonu - Hide line numbers",
+            "  :set textwidth=N, :set tw=N - Wrap column for typing, gq, :Wrap and pastewrap (0 = off)",
+            "  :set pastewrap - Wrap pasted text to textwidth (nopastewrap to disable)",
+            "  :[range]Wrap [width] - Hard-wrap long lines to textwidth (79 if unset)",
+            "  :set writeprg=cmd, :set wp=cmd - Save this buffer through cmd's output (empty = off)",
+            "  :set formatprg=cmd, :set fp=cmd - Formatter for :format, e.g. rustfmt or black\\ -q\\ -",
+            "  :[range]format, :form - Format lines with formatprg (whole file by default); only",
+            "    the lines it changes are replaced, so the cursor and marks stay put",
+            "  :set formatonsave - Format the whole buffer with formatprg on every write",
+        ],
+    },
+    HelpTopic {
+        tag: "mappings",
+        title: "KEY MAPPINGS",
+        lines: &[
+            "  :nmap lhs rhs - Typing lhs in normal mode types rhs (mappings in rhs apply)",
+            "  :nnoremap lhs rhs - Same, but rhs is typed as is",
+            "  :vmap, :vnoremap, :imap, :inoremap - The same for visual and insert mode",
+            "  :map, :noremap - Map in normal and visual mode",
+            "  :nunmap lhs, :vunmap, :iunmap, :unmap - Remove a mapping",
+            "  :map, :nmap, ... without arguments - List mappings (* = not remapped)",
+            "  Keys are written <CR>, <Esc>, <Tab>, <Space>, <lt>, <C-x>, <F5>, ...",
+            "  A key that starts a longer mapping waits timeoutlen (a second) for the rest",
+            "  <leader> in a mapping is \\ or what :let mapleader = \",\" set before it",
+            "  :let mapleader - Show the leader",
+        ],
+    },
+    HelpTopic {
+        tag: "rc-configuration",
+        title: "RC CONFIGURATION",
+        lines: &[
+            "  vi-rus loads settings from .virusrc file",
+            "  Search order: current directory, then ~/.virusrc",
+            "  :mkvirus - Generate sample .virusrc in current directory",
+            "  ~/.virusinfo keeps marks A-Z, registers a-z, the last search, the",
+            "  last : command and the history between sessions; it is rewritten on exit",
+        ],
+    },
+    HelpTopic {
+        tag: "rc-file-format",
+        title: "RC FILE FORMAT",
+        lines: &[
+            "  # Comment lines start with # or \"",
+            "  set nu                # Show line numbers",
+            "  set nonu              # Hide line numbers",
+            "  set list              # Show whitespace",
+            "  set nolist            # Hide whitespace",
+            "  set lcs=tab:>-,eol:$  # What list shows for whitespace",
+            "  set expandtab         # Use spaces for tabs",
+            "  set noexpandtab       # Use tab characters",
+            "  set tabstop=4         # Set tab width",
+            "  set fileformat=unix   # Line endings of new files (unix/dos/mac)",
+            "  set textwidth=79      # Wrap column (0 = off)",
+            "  set pastewrap         # Wrap pasted text to textwidth",
+            "  set hltrailing        # Highlight trailing whitespace",
+            "  set cursorline        # Highlight the cursor's line",
+            "  set colorcolumn=80    # Mark column 80",
+            "  set trimtrailing      # Strip trailing whitespace on write",
+            "  set nohlsearch        # Don't highlight search matches",
+            "  set noincsearch       # Don't search while typing",
+            "  set smartcase         # Match case when a pattern has a capital letter",
+            "  set showmatch         # Flash the bracket a typed ), ] or } closes",
+            "  set autoread          # Reload unmodified buffers changed on disk",
+            "  set autosave=30       # Write modified buffers after 30s without edits",
+            "  set ticktime=1000     # Redraw every second while idle",
+            "  set confirm           # Ask to save instead of failing on :q and :e",
+            "  set showcmd timeout   # Show commands typed in part; drop them when typing pauses",
+            "  set synmaxcol=3000    # Stop highlighting past this column (0 = no limit)",
+            "  colorscheme dark      # Colour theme, built in or from ~/.virus/themes",
+            "  set ts=4 sw=4 et nu   # Several options on one line",
+            "  nnoremap <C-s> :w<CR> # Key mappings, as the :map commands",
+            "  autocmd FileType python set et sw=4  # Buffer options for a file type",
+            "  au FileType make,go setlocal noet    # (et, sw=, tw=, pastewrap, trimtrailing)",
+            "  au FileType python set fp=black\\ -q\\ - formatonsave  # Format on write",
+            "  lsp rust rust-analyzer               # Language server for a file type",
+        ],
+    },
+    HelpTopic {
+        tag: "rc-alternative-syntax",
+        title: "RC ALTERNATIVE SYNTAX",
+        lines: &[
+            "  tab_stop=4            # Tab width",
+            "  expand_tab=true       # Use spaces",
+            "  line_numbers=yes      # Show line numbers",
+            "  show_whitespace=false # Hide whitespace",
+            "  line_ending=unix      # Line endings",
+            "  colorscheme=dark      # Colour theme",
+        ],
+    },
+    HelpTopic {
+        tag: "undo-redo",
+        title: "UNDO & REDO",
+        lines: &[
+            "  u - Undo last change",
+            "  Ctrl+r - Redo last undone change",
+            "  Undo history is a tree: a change after undo starts a new branch",
+            "  g- / g+ - Go to the previous / next state in time, across branches",
+            "  :earlier N, :later N - Move N states back / forward in time",
+            "  :earlier 10s, :later 2m - Move by time (s, m, h, d)",
+            "  :undolist - List the tip of each undo branch in a preview buffer",
+            "  :set undolevels=N (ul) - Keep N changes for undo, dropping the oldest (1000)",
+        ],
+    },
+    HelpTopic {
+        tag: "help",
+        title: "HELP & MISC",
+        lines: &[
+            "  :help, :h - Open this help; :help subject goes to a topic, key or command",
+            "  Ctrl-] - In the help, follow the |link| (or look up the word) under the cursor",
+            "  q - Close the help",
+            "  :redraw - Force screen redraw",
+            "  :unmatched - Toggle highlighting of all unmatched brackets",
+            "  Ctrl+l - Force screen redraw",
+        ],
+    },
+];
+
 pub fn create_help_document() -> Document {
-    let help_lines = vec![
+    let mut help_lines = vec![
         "VI-RUS EDITOR HELP".to_string(),
         "==================".to_string(),
         "".to_string(),
-        "MOVEMENT (Normal Mode):".to_string(),
-        "  h, ← - Move left".to_string(),
-        "  j, ↓ - Move down".to_string(),
-        "  k, ↑ - Move up".to_string(),
-        "  l, → - Move right".to_string(),
-        "".to_string(),
-        "WORD MOVEMENT:".to_string(),
-        "  w - Next word start".to_string(),
-        "  b - Previous word start".to_string(),
-        "  e - Next word end".to_string(),
-        "  W - Next WORD start (space-separated)".to_string(),
-        "  B - Previous WORD start".to_string(),
-        "  E - Next WORD end".to_string(),
-        "".to_string(),
-        "LINE MOVEMENT:".to_string(),
-        "  0 - Start of line".to_string(),
-        "  $ - End of line".to_string(),
-        "  ^ - First non-whitespace character".to_string(),
-        "  + - Down to first non-whitespace of next line".to_string(),
-        "  - - Up to first non-whitespace of previous line".to_string(),
-        "  Enter - Down to first non-whitespace of next line".to_string(),
-        "".to_string(),
-        "INDENTATION:".to_string(),
-        "  >> - Indent current line".to_string(),
-        "  << - Dedent current line".to_string(),
-        "  3>> - Indent 3 lines starting from current".to_string(),
-        "  5<< - Dedent 5 lines starting from current".to_string(),
-        "".to_string(),
-        "DOCUMENT MOVEMENT:".to_string(),
-        "  gg - Start of document".to_string(),
-        "  G - End of document".to_string(),
-        "  :15 - Go to line 15".to_string(),
-        "  Ctrl+f, Page Down - Page down".to_string(),
-        "  Ctrl+b, Page Up - Page up".to_string(),
-        "  Ctrl+d - Half page down (use Alt+j on macOS if Ctrl+d splits terminal)".to_string(),
-        "  Ctrl+u - Half page up".to_string(),
-        "".to_string(),
-        "CHARACTER SEARCH:".to_string(),
-        "  f{char} - Find character forward".to_string(),
-        "  F{char} - Find character backward".to_string(),
-        "  t{char} - Move to before character forward".to_string(),
-        "  T{char} - Move to before character backward".to_string(),
-        "  ; - Repeat last find".to_string(),
-        "  , - Repeat last find (reverse)".to_string(),
-        "".to_string(),
-        "MARKS & JUMPS:".to_string(),
-        "  m{a-z} - Set local mark (a-z)".to_string(),
-        "  m{A-Z} - Set global mark (A-Z, across files)".to_string(),
-        "  '{a-z,A-Z} - Jump to mark line (switches files for A-Z)".to_string(),
-        "  `{a-z,A-Z} - Jump to exact mark position (switches files)".to_string(),
-        "  '' - Jump to last jump position".to_string(),
-        "  '. - Jump to last change position".to_string(),
-        "  '^ - Jump to last insert position".to_string(),
-        "  Ctrl+o - Jump backward in jump list (switches files)".to_string(),
-        "  Ctrl+i - Jump forward in jump list (switches files)".to_string(),
-        "  :marks - List all marks".to_string(),
-        "  :jumps, :ju - Show jump list history".to_string(),
-        "  :clear marks - Clear all user marks (a-z, A-Z)".to_string(),
-        "  :clear jumps - Clear jump list history".to_string(),
-        "  :clear all - Clear marks and jumps".to_string(),
-        "  :clear - Clear/redraw screen".to_string(),
-        "".to_string(),
-        "SEARCH & REPLACE:".to_string(),
-        "  /{pattern} - Search forward for pattern (regex)".to_string(),
-        "  ?{pattern} - Search backward for pattern (regex)".to_string(),
-        "  n - Next search result (same direction)".to_string(),
-        "  N - Previous search result (opposite direction)".to_string(),
-        "  :s/old/new/ - Replace first match on current line only".to_string(),
-        "  :s/old/new/g - Replace all matches on current line only".to_string(),
-        "  :s/old/new/i - Case-insensitive replace on current line only".to_string(),
-        "  :%s/old/new/ - Replace first match on ALL lines (% = entire document)".to_string(),
-        "  :%s/old/new/g - Replace all matches on ALL lines (entire document)".to_string(),
-        "  :%s/old/new/gi - Global case-insensitive replace (entire document)".to_string(),
-        "  :2,5s/old/new/g - Replace in lines 2-5".to_string(),
-        "  :'a,'bs/old/new/g - Replace between marks a and b".to_string(),
-        "  Search results are highlighted in yellow".to_string(),
-        "  Brackets under cursor are highlighted in cyan".to_string(),
-        "  Unmatched brackets are highlighted in red".to_string(),
-        "  Examples:".to_string(),
-        "    /test - Find 'test' forward (highlighted in yellow)".to_string(),
-        "    ?hello - Find 'hello' backward (highlighted in yellow)".to_string(),
-        "    :s/foo/bar/ - Replace first 'foo' with 'bar'".to_string(),
-        "    :%s/\\d+/NUMBER/g - Replace all numbers with 'NUMBER'".to_string(),
-        "".to_string(),
-        "RANGE OPERATIONS:".to_string(),
-        "  Range prefixes can be used with most line-based commands:".to_string(),
-        "  % - All lines (entire document)".to_string(),
-        "  $ - Last line".to_string(),
-        "  . - Current line (default if no range specified)".to_string(),
-        "  5 - Line 5".to_string(),
-        "  2,8 - Lines 2 through 8".to_string(),
-        "  2,$ - Lines 2 through end of file".to_string(),
-        "  .,$ - Current line through end of file".to_string(),
-        "  'a,'b - From mark a to mark b".to_string(),
-        "  +3 - 3 lines forward from current".to_string(),
-        "  -2 - 2 lines backward from current".to_string(),
-        "".to_string(),
-        "RANGE DELETE COMMANDS:".to_string(),
-        "  :2,5d - Delete lines 2-5".to_string(),
-        "  :%d - Delete entire document".to_string(),
-        "  :.,+3d - Delete current line and 3 lines below".to_string(),
-        "  :'a,'bd - Delete from mark a to mark b".to_string(),
-        "".to_string(),
-        "RANGE YANK COMMANDS:".to_string(),
-        "  :2,5y - Yank (copy) lines 2-5".to_string(),
-        "  :%y - Yank entire document".to_string(),
-        "  :'a,'by - Yank from mark a to mark b".to_string(),
-        "".to_string(),
-        "RANGE COPY/MOVE COMMANDS:".to_string(),
-        "  :2,5co 10 - Copy lines 2-5 after line 10".to_string(),
-        "  :2,5m 10 - Move lines 2-5 after line 10".to_string(),
-        "  :%co 0 - Copy entire document to beginning".to_string(),
-        "".to_string(),
-        "RANGE PRINT COMMANDS:".to_string(),
-        "  :2,5p - Print lines 2-5 in new preview buffer".to_string(),
-        "  :2,5# - Print lines 2-5 with line numbers in preview buffer".to_string(),
-        "  :2,5l - List lines 2-5 (show whitespace) in preview buffer".to_string(),
-        "  :%p - Print entire document in preview buffer".to_string(),
-        "  Preview buffers can be closed with :bd".to_string(),
-        "".to_string(),
-        "DELETE OPERATIONS:".to_string(),
-        "  x - Delete character forward (at cursor)".to_string(),
-        "  X - Delete character backward".to_string(),
-        "  s - Substitute character (delete and enter insert mode)".to_string(),
-        "  S - Substitute line (clear line and enter insert mode)".to_string(),
-        "  D - Delete to end of line".to_string(),
-        "  dd - Delete entire line".to_string(),
-        "  Backspace - Delete character backward (insert mode)".to_string(),
-        "".to_string(),
-        "MOTION-BASED DELETE:".to_string(),
-        "  dw - Delete word forward".to_string(),
-        "  dW - Delete WORD forward (space-separated)".to_string(),
-        "  db - Delete word backward".to_string(),
-        "  dB - Delete WORD backward".to_string(),
-        "  de - Delete to end of word".to_string(),
-        "  dE - Delete to end of WORD".to_string(),
-        "  d0 - Delete to beginning of line".to_string(),
-        "  d$ - Delete to end of line (same as D)".to_string(),
-        "  d^ - Delete to first non-whitespace character".to_string(),
-        "  dgg - Delete to beginning of file".to_string(),
-        "  dG - Delete to end of file".to_string(),
-        "".to_string(),
-        "CHARACTER-BASED DELETE:".to_string(),
-        "  dt{char} - Delete until (but not including) character".to_string(),
-        "  dT{char} - Delete backward until character".to_string(),
-        "  df{char} - Delete including character forward".to_string(),
-        "  dF{char} - Delete including character backward".to_string(),
-        "".to_string(),
-        "NUMBERED DELETE COMMANDS:".to_string(),
-        "  3dd - Delete 3 lines starting from current".to_string(),
-        "  5dw - Delete 5 words forward".to_string(),
-        "  2db - Delete 2 words backward".to_string(),
-        "  4>> - Indent 4 lines (example of numbered commands)".to_string(),
-        "".to_string(),
-        "CHANGE OPERATIONS (delete + enter insert mode):".to_string(),
-        "  cc - Change entire line".to_string(),
-        "  C - Change to end of line".to_string(),
-        "  cw - Change word forward".to_string(),
-        "  cW - Change WORD forward (space-separated)".to_string(),
-        "  cb - Change word backward".to_string(),
-        "  cB - Change WORD backward".to_string(),
-        "  ce - Change to end of word".to_string(),
-        "  cE - Change to end of WORD".to_string(),
-        "  c0 - Change to beginning of line".to_string(),
-        "  c$ - Change to end of line (same as C)".to_string(),
-        "  c^ - Change to first non-whitespace character".to_string(),
-        "  cgg - Change to beginning of file".to_string(),
-        "  cG - Change to end of file".to_string(),
-        "".to_string(),
-        "CHARACTER-BASED CHANGE:".to_string(),
-        "  ct{char} - Change until (but not including) character".to_string(),
-        "  cT{char} - Change backward until character".to_string(),
-        "  cf{char} - Change including character forward".to_string(),
-        "  cF{char} - Change including character backward".to_string(),
-        "".to_string(),
-        "NUMBERED CHANGE COMMANDS:".to_string(),
-        "  3cc - Change 3 lines starting from current".to_string(),
-        "  5cw - Change 5 words forward".to_string(),
-        "  2cb - Change 2 words backward".to_string(),
-        "".to_string(),
-        "YANK (COPY) OPERATIONS:".to_string(),
-        "  yy - Yank (copy) current line".to_string(),
-        "  3yy - Yank 3 lines starting from current".to_string(),
-        "  yw - Yank word forward".to_string(),
-        "  yW - Yank WORD forward (space-separated)".to_string(),
-        "  yb - Yank word backward".to_string(),
-        "  yB - Yank WORD backward".to_string(),
-        "  ye - Yank to end of word".to_string(),
-        "  yE - Yank to end of WORD".to_string(),
-        "  y0 - Yank to start of line".to_string(),
-        "  y$ - Yank to end of line".to_string(),
-        "  y^ - Yank to first non-whitespace character".to_string(),
-        "  yG - Yank to end of file".to_string(),
-        "  ygg - Yank to start of file".to_string(),
-        "  yt{char} - Yank until (but not including) character".to_string(),
-        "  yT{char} - Yank backward until character".to_string(),
-        "  yf{char} - Yank including character forward".to_string(),
-        "  yF{char} - Yank including character backward".to_string(),
-        "".to_string(),
-        "PASTE OPERATIONS:".to_string(),
-        "  p - Paste after cursor/line".to_string(),
-        "  P - Paste before cursor/line".to_string(),
-        "".to_string(),
-        "NAMED REGISTERS:".to_string(),
-        "  \"ayy - Yank current line to register 'a'".to_string(),
-        "  \"ayw - Yank word to register 'a'".to_string(),
-        "  \"ap - Paste from register 'a' after cursor".to_string(),
-        "  \"aP - Paste from register 'a' before cursor".to_string(),
-        "  Registers a-z: replace content, A-Z: append to content".to_string(),
-        "  Numbered registers 0-9: automatic delete history".to_string(),
-        "  Examples:".to_string(),
-        "    \"ayy - Copy line to register 'a'".to_string(),
-        "    \"byW - Copy WORD to register 'b'".to_string(),
-        "    \"ap - Paste register 'a' content".to_string(),
-        "".to_string(),
-        "VISUAL MODE:".to_string(),
-        "  v - Enter visual character mode".to_string(),
-        "  V - Enter visual line mode".to_string(),
-        "  Ctrl+v - Enter visual block mode".to_string(),
-        "  Esc - Exit visual mode".to_string(),
-        "  d, x - Delete selected text".to_string(),
-        "  y - Yank (copy) selected text".to_string(),
-        "  > - Indent selected lines".to_string(),
-        "  < - Dedent selected lines".to_string(),
-        "  All movement keys work in visual mode".to_string(),
-        "".to_string(),
-        "INSERT MODES:".to_string(),
-        "  i - Insert before cursor".to_string(),
-        "  a - Insert after cursor".to_string(),
-        "  o - Open new line below".to_string(),
-        "  O - Open new line above".to_string(),
-        "  A - Insert at end of line".to_string(),
-        "  I - Insert at start of line".to_string(),
-        "  Esc - Return to normal mode".to_string(),
-        "".to_string(),
-        "FILE OPERATIONS:".to_string(),
-        "  :w - Save current file".to_string(),
-        "  :w filename - Save as filename".to_string(),
-        "  :wq - Save and quit".to_string(),
-        "  :q - Quit (if no changes)".to_string(),
-        "  :q! - Force quit without saving".to_string(),
-        "  :f - Show file information".to_string(),
-        "".to_string(),
-        "BUFFER OPERATIONS:".to_string(),
-        "  :e - Create new empty buffer".to_string(),
-        "  :e filename - Edit/open new file".to_string(),
-        "  :e file1 file2 - Open multiple files as buffers".to_string(),
-        "  :badd - Add new empty buffer".to_string(),
-        "  :badd file1 file2 - Add multiple files to buffer list".to_string(),
-        "  :ls - List all open buffers (% = current, + = modified)".to_string(),
-        "  :b1, :b2, :b3 - Switch to buffer 1, 2, 3".to_string(),
-        "  :bf filename - Switch to buffer by filename".to_string(),
-        "  :bn - Next buffer".to_string(),
-        "  :bp - Previous buffer".to_string(),
-        "  :bd - Close current buffer".to_string(),
-        "  :bd! - Force close buffer (discard unsaved changes)".to_string(),
-        "".to_string(),
-        "READ OPERATIONS:".to_string(),
-        "  :r filename - Insert file at cursor".to_string(),
-        "  :r !command - Insert command output".to_string(),
-        "  :0r filename - Insert at beginning".to_string(),
-        "  :$r filename - Insert at end".to_string(),
-        "  :10r filename - Insert after line 10".to_string(),
-        "".to_string(),
-        "TABS & SPACES:".to_string(),
-        "  :set tabstop=4 - Set tab width to 4 spaces".to_string(),
-        "  :set et - Tab key inserts spaces".to_string(),
-        "  :set noet - Tab key inserts tabs".to_string(),
-        "  :set list - Show whitespace characters".to_string(),
-        "  :set nolist - Hide whitespace characters".to_string(),
-        "  :detab - Convert all tabs to spaces".to_string(),
-        "  :retab - Convert all spaces to tabs".to_string(),
-        "  :ascii - Normalize Unicode characters to ASCII equivalents".to_string(),
-        "  :normalize - Same as :ascii".to_string(),
-        "  :brackets - Check for unmatched brackets".to_string(),
-        "  :checkbrackets - Same as :brackets".to_string(),
-        "  :redraw - Force screen refresh".to_string(),
-        "  :scroll - Show scroll information".to_string(),
-        "  :resetscroll - Reset scroll position".to_string(),
+        "Move onto a |link| and press Ctrl-] to follow it, Ctrl-o to go back.".to_string(),
+        "/ searches the help, q closes it, and :help subject opens it at a topic,".to_string(),
+        "key or command (:help registers, :help :s, :help dd).".to_string(),
         "".to_string(),
-        "EDIT OPERATIONS:".to_string(),
-        "  :paste - Paste from clipboard".to_string(),
-        "  :set ff=unix - Set Unix line endings".to_string(),
-        "  :set ff=dos - Set Windows line endings".to_string(),
-        "  :set ff=mac - Set Mac line endings".to_string(),
-        "  :set nu - Show line numbers".to_string(),
-        "  :set nonu - Hide line numbers".to_string(),
-        "".to_string(),
-        "RC CONFIGURATION:".to_string(),
-        "  vi-rus loads settings from .virusrc file".to_string(),
-        "  Search order: current directory, then ~/.virusrc".to_string(),
-        "  :mkvirus - Generate sample .virusrc in current directory".to_string(),
-        "".to_string(),
-        "RC FILE FORMAT:".to_string(),
-        "  # Comment lines start with # or \"".to_string(),
-        "  set nu                # Show line numbers".to_string(),
-        "  set nonu              # Hide line numbers".to_string(),
-        "  set list              # Show whitespace".to_string(),
-        "  set nolist            # Hide whitespace".to_string(),
-        "  set expandtab         # Use spaces for tabs".to_string(),
-        "  set noexpandtab       # Use tab characters".to_string(),
-        "  set tabstop=4         # Set tab width".to_string(),
-        "  set fileformat=unix   # Line endings (unix/dos/mac)".to_string(),
-        "".to_string(),
-        "RC ALTERNATIVE SYNTAX:".to_string(),
-        "  tab_stop=4            # Tab width".to_string(),
-        "  expand_tab=true       # Use spaces".to_string(),
-        "  line_numbers=yes      # Show line numbers".to_string(),
-        "  show_whitespace=false # Hide whitespace".to_string(),
-        "  line_ending=unix      # Line endings".to_string(),
-        "".to_string(),
-        "UNDO & REDO:".to_string(),
-        "  u - Undo last change".to_string(),
-        "  Ctrl+r - Redo last undone change".to_string(),
-        "".to_string(),
-        "HELP & MISC:".to_string(),
-        "  :help, :h - Show this help".to_string(),
-        "  :redraw - Force screen redraw".to_string(),
-        "  :unmatched - Toggle highlighting of all unmatched brackets".to_string(),
-        "  Ctrl+l - Force screen redraw".to_string(),
-        "".to_string(),
-        "Press :bd to close this help buffer".to_string(),
-        "".to_string(),
-        "😊".to_string(),
+        "TOPICS:".to_string(),
     ];
 
-    // Create a document with the help content
-    
-    let help_content = help_lines.join("\n");
-    
-    let help_doc = Document::from_string(help_content);
-    help_doc
+    // The contents: a link to each topic, as many to a line as fit
+    let mut row = String::from(" ");
+    for topic in HELP_TOPICS {
+        let link = format!(" |{}|", topic.tag);
+        if row.len() + link.len() > HELP_WIDTH {
+            help_lines.push(std::mem::replace(&mut row, String::from(" ")));
+        }
+        row.push_str(&link);
+    }
+    help_lines.push(row);
+
+    for topic in HELP_TOPICS {
+        let heading = format!("{}:", topic.title);
+        let tag = format!("*{}*", topic.tag);
+        let width = HELP_WIDTH.saturating_sub(heading.chars().count() + 1);
+        help_lines.push(String::new());
+        help_lines.push(format!("{heading} {tag:>width$}"));
+        help_lines.extend(topic.lines.iter().map(|line| line.to_string()));
+    }
+
+    let mut doc = Document::from_string(help_lines.join("\n"));
+    doc.help = true;
+    doc.read_only = true; // Edits are refused too, see HelpCommands::refuse_change
+    doc
+}
+
+/// The tag a topic heading line ends with ("MARKS & JUMPS:   *marks-jumps*")
+fn heading_tag(line: &str) -> Option<&str> {
+    let (_, tag) = line.strip_suffix('*')?.rsplit_once(" *")?;
+    (!tag.is_empty() && !tag.contains(char::is_whitespace)).then_some(tag)
+}
+
+/// The line of the help that :help `subject` goes to. Tried in turn: the
+/// topic tagged `subject`, the entry for a key or command written that way
+/// (":s" finds "  :s/old/new/ - ..."), a topic whose tag has it in, and
+/// then any line mentioning it.
+pub fn find_subject(doc: &Document, subject: &str) -> Option<usize> {
+    let lines: Vec<String> = (0..doc.line_count()).map(|i| doc.get_line(i).unwrap_or_default()).collect();
+    let lower = subject.to_lowercase();
+    let first_topic = lines.iter().position(|line| heading_tag(line).is_some()).unwrap_or(0);
+
+    let is_entry = |line: &String| {
+        line.starts_with("  ")
+            && line.trim_start().strip_prefix(subject).is_some_and(|rest| {
+                rest.chars().next().is_none_or(|c| matches!(c, ' ' | ',' | '/' | '#' | '[' | '{' | '!' | '<'))
+            })
+    };
+    lines
+        .iter()
+        .position(|line| heading_tag(line).is_some_and(|tag| tag == lower))
+        .or_else(|| lines.iter().position(is_entry))
+        .or_else(|| lines.iter().position(|line| heading_tag(line).is_some_and(|tag| tag.contains(&lower))))
+        .or_else(|| {
+            let found = lines[first_topic..].iter().position(|line| line.to_lowercase().contains(&lower));
+            found.map(|i| first_topic + i)
+        })
+}
+
+/// What Ctrl-] follows at byte `column` of `line`: the |link| there, or
+/// else the word, without the stars of a *tag*
+pub fn subject_at(line: &str, column: usize) -> Option<&str> {
+    let mut start = 0;
+    while let Some(open) = line[start..].find('|').map(|i| start + i) {
+        let Some(close) = line[open + 1..].find('|').map(|i| open + 1 + i) else {
+            break;
+        };
+        let link = &line[open + 1..close];
+        if link.is_empty() || link.contains(char::is_whitespace) {
+            start = open + 1;
+            continue;
+        }
+        if (open..=close).contains(&column) {
+            return Some(link);
+        }
+        start = close + 1;
+    }
+
+    let mut column = column.min(line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    let begin = line[..column].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let end = line[column..].find(char::is_whitespace).map_or(line.len(), |i| column + i);
+    let word = line[begin..end].trim_matches(|c| c == '*' || c == ',');
+    (!word.is_empty()).then_some(word)
 }
 
 #[cfg(test)]
@@ -409,18 +1037,51 @@ mod tests {
         // Test specific lines are correct
         assert_eq!(help_doc.get_line(1).unwrap_or_default(), "==================");
         assert_eq!(help_doc.get_line(2).unwrap_or_default(), "");
-        assert_eq!(help_doc.get_line(3).unwrap_or_default(), "MOVEMENT (Normal Mode):");
-        assert_eq!(help_doc.get_line(4).unwrap_or_default(), "  h, ← - Move left");
-        assert_eq!(help_doc.get_line(5).unwrap_or_default(), "  j, ↓ - Move down");
+        let movement = find_subject(&help_doc, "movement").unwrap();
+        assert!(help_doc.get_line(movement).unwrap_or_default().starts_with("MOVEMENT (Normal Mode): "));
+        assert!(help_doc.get_line(movement).unwrap_or_default().ends_with(" *movement*"));
+        assert_eq!(help_doc.get_line(movement + 1).unwrap_or_default(), "  h, ← - Move left");
+        assert_eq!(help_doc.get_line(movement + 2).unwrap_or_default(), "  j, ↓ - Move down");
         
         // Verify content is accessible overall
         let content = help_doc.get_piece_table_content();
         assert!(content.contains("VI-RUS EDITOR HELP"));
         assert!(content.contains("MOVEMENT (Normal Mode)"));
-        assert!(content.contains("Press :bd to close this help buffer"));
+        assert!(content.contains("|movement|"));
+        assert!(content.contains("q closes it"));
+        assert!(help_doc.help && help_doc.read_only);
         
         println!("✅ Help document creation working correctly");
         println!("   Help has {} lines", help_doc.line_count());
         println!("   Lines 0-5 are correctly formatted");
     }
+
+    #[test]
+    fn test_find_subject() {
+        let help_doc = create_help_document();
+        let line = |subject| find_subject(&help_doc, subject).map(|i| help_doc.get_line(i).unwrap_or_default());
+
+        assert!(line("registers").unwrap().starts_with("NAMED REGISTERS: "));
+        assert!(line("Registers").unwrap().ends_with("*registers*"));
+        assert!(line(":s").unwrap().starts_with("  :s/old/new/ - "));
+        assert!(line("dd").unwrap().starts_with("  dd - "));
+        // A tag with the word in, then any line mentioning it
+        assert!(line("quickfix").unwrap().ends_with("*grep-quickfix*"));
+        assert_eq!(line("in red").unwrap(), "  Unmatched brackets are highlighted in red");
+        assert_eq!(line("no-such-thing"), None);
+    }
+
+    #[test]
+    fn test_subject_at() {
+        let line = "  see |registers| and |marks-jumps|, or *tags*";
+        assert_eq!(subject_at(line, 6), Some("registers"));
+        assert_eq!(subject_at(line, 12), Some("registers"));
+        assert_eq!(subject_at(line, 16), Some("registers"));
+        assert_eq!(subject_at(line, 25), Some("marks-jumps"));
+        assert_eq!(subject_at(line, 3), Some("see"));
+        assert_eq!(subject_at(line, 43), Some("tags"));
+        assert_eq!(subject_at(line, 1), None);
+        // A lone bar isn't a link
+        assert_eq!(subject_at("  :g/a/d | :g/b/d", 9), Some("|"));
+    }
 }
@@ -0,0 +1,106 @@
+use crate::controller::help;
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::Document;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Refusing an edit to the help buffer
+pub const NOT_MODIFIABLE: &str = "Cannot make changes, the help buffer is read-only";
+
+/// The :help buffer - Ctrl-] follows a link, q closes
+pub struct HelpCommands;
+
+impl HelpCommands {
+    /// :help [subject] - switch to the help buffer, at the topic or entry
+    /// for `subject` if one is given
+    pub fn open(shared: &mut SharedEditorState, subject: &str) -> Result<(), String> {
+        if !subject.is_empty() {
+            let line = help::find_subject(&help::create_help_document(), subject)
+                .ok_or_else(|| format!("Sorry, no help for {}", subject))?;
+            Self::enter(shared);
+            Self::go_to_line(shared, line);
+            shared.status_message = format!("Help: {}", subject);
+        } else {
+            Self::enter(shared);
+            shared.status_message = "Help buffer opened".to_string();
+        }
+        Ok(())
+    }
+
+    /// Handle a normal mode key when the current buffer is the help.
+    /// Returns false for keys the help leaves to normal mode.
+    pub fn handle_key(key_event: KeyEvent, shared: &mut SharedEditorState) -> bool {
+        if !shared.session_controller.current_document().help {
+            return false;
+        }
+        match (key_event.code, key_event.modifiers.contains(KeyModifiers::CONTROL)) {
+            (KeyCode::Char(']'), true) => {
+                if let Err(message) = Self::follow_link(shared) {
+                    shared.status_message = message;
+                }
+            }
+            (KeyCode::Char('q'), false) => Self::close(shared),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The help can't be changed, like a buffer with vim's 'nomodifiable'.
+    /// True, with the error on the status line, when the current buffer is
+    /// the help.
+    pub fn refuse_change(shared: &mut SharedEditorState) -> bool {
+        if !shared.session_controller.current_document().help {
+            return false;
+        }
+        shared.status_message = NOT_MODIFIABLE.to_string();
+        true
+    }
+
+    /// Ctrl-] - go to the topic the link (or word) under the cursor names,
+    /// leaving where the cursor was on the jump list for Ctrl-o
+    fn follow_link(shared: &mut SharedEditorState) -> Result<(), String> {
+        let doc = shared.session_controller.current_document();
+        let (line, column) = (doc.cursor_line(), doc.cursor_column());
+        let text = doc.get_line(line).unwrap_or_default();
+        let subject = help::subject_at(&text, column).ok_or("No help topic under cursor")?;
+        let target = help::find_subject(doc, subject).ok_or_else(|| format!("Sorry, no help for {}", subject))?;
+
+        shared.mark_manager.add_to_jump_list(line, column, None);
+        Self::go_to_line(shared, target);
+        Ok(())
+    }
+
+    /// q - close the help, going back to the buffer before it
+    fn close(shared: &mut SharedEditorState) {
+        let closing = shared.session_controller.current_buffer_index();
+        match shared.session_controller.force_close_buffer(&mut shared.mark_manager) {
+            Ok(_) => {
+                let buffer_count = shared.session_controller.buffer_count();
+                shared.view.windows_mut().buffer_removed(closing, buffer_count);
+                shared.status_message = format!("\"{}\"", shared.session_controller.get_display_filename());
+            }
+            // The help was all there was open
+            Err(_) => {
+                *shared.session_controller.current_document_mut() = Document::new();
+                shared.status_message.clear();
+            }
+        }
+        shared.cached_unmatched_brackets = None;
+    }
+
+    fn enter(shared: &mut SharedEditorState) {
+        if !shared.session_controller.current_document().help {
+            shared.session_controller.open_help_buffer();
+            shared.cached_unmatched_brackets = None;
+            shared.view.reset_scroll();
+        }
+    }
+
+    /// Put `line` at the top of the screen with the cursor on it
+    fn go_to_line(shared: &mut SharedEditorState, line: usize) {
+        let doc = shared.session_controller.current_document_mut();
+        let line_count = doc.line_count();
+        let _ = doc.set_cursor(line, 0);
+        let top = shared.view.get_scroll_offset();
+        shared.view.scroll_vertically(line as isize - top as isize, line_count);
+    }
+}
@@ -1,32 +1,151 @@
 use crate::controller::shared_state::{ModeController, ModeTransition, SharedEditorState};
 use crate::controller::command_types::{Mode, Command};
 use crate::controller::key_handler::KeyHandler;
-use crossterm::event::{KeyEvent};
+use crate::controller::show_match::ShowMatch;
+use crate::document_model::digraph;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 pub struct InsertController {
-    // Insert mode specific state can go here if needed
+    pending_key: Option<char>, // Ctrl-R waiting for its register
+    pending_input: Option<PendingInput>,
+}
+
+/// A character being typed with Ctrl-V or Ctrl-K
+#[derive(Debug, Clone, PartialEq)]
+enum PendingInput {
+    Literal, // Ctrl-V: the next key as it is
+    Code { prefix: Option<char>, radix: u32, max_digits: usize, digits: String }, // Ctrl-V u, U, x, o or a digit
+    Digraph(Option<char>), // Ctrl-K, and the first character once typed
+}
+
+/// What a key did to a pending character
+#[derive(Debug, PartialEq)]
+enum InputStep {
+    More(PendingInput),        // Still waiting for keys
+    Done(Option<char>),        // The key finished it: the character to insert, if any
+    Interrupted(Option<char>), // The key isn't part of it: insert the character, then handle the key
+}
+
+impl PendingInput {
+    fn code(prefix: Option<char>, radix: u32, max_digits: usize, digits: &str) -> InputStep {
+        InputStep::More(PendingInput::Code { prefix, radix, max_digits, digits: digits.to_string() })
+    }
+
+    fn feed(self, key: &KeyEvent) -> InputStep {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match self {
+            PendingInput::Literal => match key.code {
+                KeyCode::Char(c) if control => InputStep::Done(Some(control_char(c))),
+                KeyCode::Char('u') => Self::code(Some('u'), 16, 4, ""),
+                KeyCode::Char('U') => Self::code(Some('U'), 16, 8, ""),
+                KeyCode::Char(c @ ('x' | 'X')) => Self::code(Some(c), 16, 2, ""),
+                KeyCode::Char(c @ ('o' | 'O')) => Self::code(Some(c), 8, 3, ""),
+                KeyCode::Char(c) if c.is_ascii_digit() => Self::code(None, 10, 3, &c.to_string()),
+                KeyCode::Char(c) => InputStep::Done(Some(c)),
+                KeyCode::Tab => InputStep::Done(Some('\t')),
+                KeyCode::Enter => InputStep::Done(Some('\r')),
+                KeyCode::Esc => InputStep::Done(Some('\u{1b}')),
+                KeyCode::Backspace => InputStep::Done(Some('\u{8}')),
+                _ => InputStep::Done(None),
+            },
+            PendingInput::Code { prefix, radix, max_digits, mut digits } => match key.code {
+                KeyCode::Char(c) if !control && c.is_digit(radix) => {
+                    digits.push(c);
+                    if digits.len() == max_digits {
+                        InputStep::Done(code_char(radix, &digits))
+                    } else {
+                        InputStep::More(PendingInput::Code { prefix, radix, max_digits, digits })
+                    }
+                }
+                // No digits after the u: it was the letter itself
+                _ if digits.is_empty() => InputStep::Interrupted(prefix),
+                _ => InputStep::Interrupted(code_char(radix, &digits)),
+            },
+            PendingInput::Digraph(first) => match (first, key.code) {
+                (None, KeyCode::Char(c)) => InputStep::More(PendingInput::Digraph(Some(c))),
+                // A pair that isn't a digraph types its second character
+                (Some(first), KeyCode::Char(second)) => InputStep::Done(Some(digraph::lookup(first, second).unwrap_or(second))),
+                _ => InputStep::Done(None),
+            },
+        }
+    }
+}
+
+/// The control character Ctrl-`c` types: ^A for Ctrl-A, ^? (DEL) for Ctrl-?
+fn control_char(c: char) -> char {
+    match c.to_ascii_uppercase() {
+        upper @ '@'..='_' => char::from(upper as u8 & 0x1f),
+        '?' => '\u{7f}',
+        _ => c,
+    }
+}
+
+fn code_char(radix: u32, digits: &str) -> Option<char> {
+    u32::from_str_radix(digits, radix).ok().and_then(char::from_u32)
+}
+
+impl Default for InsertController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InsertController {
     pub fn new() -> Self {
-        Self {}
+        Self { pending_key: None, pending_input: None }
+    }
+
+    /// Bracketed paste: the text goes in with one edit, part of this
+    /// insert's undo step
+    pub fn paste(&mut self, text: &str, shared: &mut SharedEditorState) {
+        shared.session_controller.current_document_mut().insert_text_with_undo(text);
+        shared.cached_unmatched_brackets = None;
     }
 }
 
 impl ModeController for InsertController {
     fn handle_key(&mut self, key_event: KeyEvent, shared: &mut SharedEditorState) -> ModeTransition {
+        // Any key ends a 'showmatch' highlight
+        shared.show_match.clear();
+
+        // The keys after Ctrl-V or Ctrl-K
+        if let Some(pending) = self.pending_input.take() {
+            let (c, handled) = match pending.feed(&key_event) {
+                InputStep::More(pending) => {
+                    self.pending_input = Some(pending);
+                    return ModeTransition::Stay;
+                }
+                InputStep::Done(c) => (c, true),
+                InputStep::Interrupted(c) => (c, false),
+            };
+            if let Some(c) = c {
+                shared.session_controller.current_document_mut().insert_char(c);
+                shared.cached_unmatched_brackets = None;
+            }
+            if handled {
+                return ModeTransition::Stay;
+            }
+        }
+
         // Parse the key event using the existing key handler
         let command = KeyHandler::parse_key_with_state(
             &Mode::Insert,
             &key_event,
-            &mut None, // pending_key not used in insert mode
+            &mut self.pending_key,
             &mut None, // number_prefix not used in insert mode
             &mut None, // pending_register not used in insert mode
+            &mut None, // Nor are operators
         );
         
         if let Some(command) = command {
             match command {
                 Command::ExitInsertMode => {
+                    // Repeat a block insert/append on the block's other rows
+                    if let Some(block_insert) = shared.block_insert.take() {
+                        block_insert.finish(shared.session_controller.current_document_mut());
+                        shared.cached_unmatched_brackets = None;
+                    }
+
                     // End undo group when leaving insert mode
                     let cursor_pos = {
                         let doc = shared.session_controller.current_document();
@@ -47,22 +166,25 @@ impl ModeController for InsertController {
                     return ModeTransition::ToMode(Mode::Normal);
                 }
                 Command::InsertChar(c) => {
-                    shared.session_controller.current_document_mut().insert_char(c);
+                    let doc = shared.session_controller.current_document_mut();
+                    if doc.auto_pairs {
+                        doc.insert_char_paired(c);
+                    } else {
+                        doc.insert_char(c);
+                    }
+                    // 'textwidth': a word typed past the margin starts a new line
+                    if !c.is_whitespace() {
+                        let width = doc.text_width;
+                        doc.auto_wrap(width, shared.view.get_tab_stop());
+                    }
                     // Invalidate bracket cache on modification
                     shared.cached_unmatched_brackets = None;
-                    // Mark change position
-                    let doc = shared.session_controller.current_document();
-                    shared.mark_manager
-                        .set_last_change(doc.cursor_line(), doc.cursor_column());
+                    ShowMatch::bracket_typed(shared, c);
                 }
                 Command::InsertNewline => {
                     shared.session_controller.current_document_mut().insert_newline();
                     // Invalidate bracket cache on modification
                     shared.cached_unmatched_brackets = None;
-                    // Mark change position
-                    let doc = shared.session_controller.current_document();
-                    shared.mark_manager
-                        .set_last_change(doc.cursor_line(), doc.cursor_column());
                 }
                 Command::InsertTab => {
                     let tab_width = shared.view.get_tab_stop();
@@ -70,8 +192,61 @@ impl ModeController for InsertController {
                     // Invalidate bracket cache on modification
                     shared.cached_unmatched_brackets = None;
                 }
+                Command::InsertDeleteWord => {
+                    let doc = shared.session_controller.current_document_mut();
+                    if doc.cursor_column() == 0 {
+                        doc.delete_char(); // Join to the line above
+                    } else {
+                        let start = doc.word_start_before_cursor();
+                        doc.delete_back_to(start);
+                    }
+                    shared.cached_unmatched_brackets = None;
+                }
+                Command::InsertDeleteToStart => {
+                    let doc = shared.session_controller.current_document_mut();
+                    let (line, column) = (doc.cursor_line(), doc.cursor_column());
+                    let indent = {
+                        let text = doc.get_line(line).unwrap_or_default();
+                        text.len() - text.trim_start().len()
+                    };
+                    // What this insert typed on the line, then the text
+                    // back to the indent, then the indent
+                    let start = match doc.undo_manager().group_start() {
+                        Some((start_line, start)) if start_line == line && start < column => start,
+                        _ if column > indent => indent,
+                        _ => 0,
+                    };
+                    if column == 0 {
+                        doc.delete_char();
+                    } else {
+                        doc.delete_back_to(start);
+                    }
+                    shared.cached_unmatched_brackets = None;
+                }
+                Command::InsertShift(indent) => {
+                    let tab_stop = shared.view.get_tab_stop();
+                    let doc = shared.session_controller.current_document_mut();
+                    doc.shift_cursor_line_with_undo(if indent { 1 } else { -1 }, tab_stop);
+                }
+                Command::InsertRegister(name) => {
+                    let text = shared.register_manager.get_register_content(Some(name)).map(|data| data.content.clone());
+                    match text {
+                        Some(text) if !text.is_empty() => {
+                            shared.session_controller.current_document_mut().insert_text_with_undo(&text);
+                            shared.cached_unmatched_brackets = None;
+                        }
+                        _ => shared.status_message = format!("Register {name} is empty"),
+                    }
+                }
+                Command::InsertLiteral => self.pending_input = Some(PendingInput::Literal),
+                Command::InsertDigraph => self.pending_input = Some(PendingInput::Digraph(None)),
                 Command::DeleteChar => {
-                    shared.session_controller.current_document_mut().delete_char();
+                    let doc = shared.session_controller.current_document_mut();
+                    if doc.auto_pairs {
+                        doc.delete_char_paired();
+                    } else {
+                        doc.delete_char();
+                    }
                     // Invalidate bracket cache on modification
                     shared.cached_unmatched_brackets = None;
                 }
@@ -103,7 +278,10 @@ impl ModeController for InsertController {
 mod tests {
     use super::*;
     use crate::controller::SessionController;
-    use crate::document_model::{Document, MarkManager, RegisterManager, SearchState};
+    use crate::controller::key_map::KeyMap;
+    use crate::controller::timers::TimerQueue;
+    use crate::document_model::{Document, History, MarkManager, QuickfixList, RegisterManager, SearchState, TagStack};
+    use crate::controller::lsp_commands::LspManager;
     use crate::view::View;
     use crossterm::event::{KeyCode, KeyModifiers};
     
@@ -117,6 +295,21 @@ mod tests {
             status_message: String::new(),
             show_all_unmatched: false,
             cached_unmatched_brackets: None,
+            block_insert: None,
+            pending_selection: None,
+            last_substitute: None,
+            pending_shell_command: None,
+            pending_ex_command: None,
+            pending_normal: None,
+            pending_suspend: false,
+            key_map: KeyMap::new(),
+            timers: TimerQueue::new(),
+            history: History::new(),
+            show_match: ShowMatch::default(),
+            diff: None,
+            tag_stack: TagStack::default(),
+            lsp: LspManager::default(),
+            quickfix: QuickfixList::default(),
         }
     }
     
@@ -377,14 +570,14 @@ mod tests {
         // Insert text
         controller.handle_key(key_event(KeyCode::Char('a')), &mut shared);
         
-        // The mark manager sets the last change position
-        // We verify the operation doesn't panic
-        
         // Insert more text at different position
         controller.handle_key(key_event(KeyCode::Enter), &mut shared);
         controller.handle_key(key_event(KeyCode::Char('b')), &mut shared);
         
-        // The mark should be updated internally
+        // Leaving insert mode records the change for the '. mark
+        assert_eq!(shared.mark_position('.'), None);
+        controller.handle_key(key_event(KeyCode::Esc), &mut shared);
+        assert_eq!(shared.mark_position('.'), Some((1, 0)));
     }
     
     #[test]
@@ -480,4 +673,38 @@ mod tests {
         assert_eq!(result, ModeTransition::Stay);
         // The unhandled command message may or may not be set depending on key parsing
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_literal_codes_and_digraphs() {
+        let mut controller = InsertController::new();
+        let mut shared = create_test_shared_state();
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        // A real tab with expandtab on, Ctrl-A raw, then codes in each base
+        controller.handle_key(ctrl('v'), &mut shared);
+        controller.handle_key(key_event(KeyCode::Tab), &mut shared);
+        controller.handle_key(ctrl('v'), &mut shared);
+        controller.handle_key(ctrl('a'), &mut shared);
+        for keys in ["u00e9", "x41", "o102", "067", "U0001F600"] {
+            controller.handle_key(ctrl('v'), &mut shared);
+            for c in keys.chars() {
+                controller.handle_key(key_event(KeyCode::Char(c)), &mut shared);
+            }
+        }
+        // Fewer digits end at the next key, which is typed too
+        controller.handle_key(ctrl('v'), &mut shared);
+        for c in "u3b1!".chars() {
+            controller.handle_key(key_event(KeyCode::Char(c)), &mut shared);
+        }
+        // A digraph, and a pair that isn't one
+        for pair in ["e'", "qz"] {
+            controller.handle_key(ctrl('k'), &mut shared);
+            for c in pair.chars() {
+                controller.handle_key(key_event(KeyCode::Char(c)), &mut shared);
+            }
+        }
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "\t\u{1}éABC😀α!éz");
+    }
+}
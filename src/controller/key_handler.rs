@@ -1,16 +1,120 @@
 use crate::controller::command_types::{Command, Mode};
-use crate::controller::yank_paste::{YankType, PasteType};
+use crate::controller::operator::{Motion, Operator};
+use crate::controller::yank_paste::PasteType;
 use crossterm::event::{KeyCode, KeyModifiers};
 
 pub struct KeyHandler;
 
+/// An operator typed and waiting for its motion (the d of d3w)
+#[derive(Debug, Clone, Copy)]
+pub struct PendingOperator {
+    operator: Operator,
+    count: Option<usize>,  // Count typed before the operator (the 2 of 2d3w)
+    prefix: Option<char>, // First key of a two-key motion (f, t, ', g...)
+    surround: Option<SurroundStep>, // Set once the operator turns out to be ys, cs or ds
+}
+
+/// How far a surround command has got
+#[derive(Debug, Clone, Copy)]
+enum SurroundStep {
+    Delete,                // ds: the target next
+    Change,                // cs: the target next
+    ChangeTo(char),        // cs{target}: the replacement next
+    AddMotion,             // ys: a motion next (yss for the line)
+    Add(Motion, usize),    // ys{motion}: the delimiter next
+}
+
 impl KeyHandler {
+    /// What 'showcmd' shows of a command typed in part, as it was typed:
+    /// the register, the counts, the operator and the first key of a
+    /// two-key command ("a2d3 for "a2d3w so far)
+    pub fn pending_keys(
+        pending_key: Option<char>,
+        number_prefix: Option<usize>,
+        pending_register: Option<char>,
+        pending_operator: Option<&PendingOperator>,
+    ) -> String {
+        let mut keys = String::new();
+        if let Some(register) = pending_register {
+            keys.push('"');
+            keys.push(register);
+        }
+        if let Some(pending) = pending_operator {
+            if let Some(count) = pending.count {
+                keys.push_str(&count.to_string());
+            }
+            keys.push_str(match pending.surround {
+                Some(SurroundStep::Delete) => "ds",
+                Some(SurroundStep::Change | SurroundStep::ChangeTo(_)) => "cs",
+                Some(SurroundStep::AddMotion | SurroundStep::Add(..)) => "ys",
+                None => pending.operator.keys(),
+            });
+            if let Some(SurroundStep::ChangeTo(target)) = pending.surround {
+                keys.push(target);
+            }
+        }
+        if let Some(count) = number_prefix {
+            keys.push_str(&count.to_string());
+        }
+        if let Some(prefix) = pending_operator.and_then(|pending| pending.prefix).or(pending_key) {
+            match prefix {
+                '\u{1}' => keys.push('@'), // Stands for @ while the register is typed
+                c if c.is_control() => keys.push_str(&format!("^{}", (c as u8 + b'@') as char)),
+                c => keys.push(c),
+            }
+        }
+        keys
+    }
+
+    /// Marks an operator can move to: a-z, A-Z and the special '' `` '. '^
+    fn is_mark_name(c: char) -> bool {
+        c.is_ascii_alphabetic() || matches!(c, '\'' | '`' | '.' | '^')
+    }
+
+    /// The key that repeats an operator to make it linewise (dd, >>, g~~)
+    fn doubled_key(operator: Operator) -> char {
+        match operator {
+            Operator::Delete => 'd',
+            Operator::Change => 'c',
+            Operator::Yank => 'y',
+            Operator::Lowercase => 'u',
+            Operator::Uppercase => 'U',
+            Operator::ToggleCase => '~',
+            Operator::Indent => '>',
+            Operator::Dedent => '<',
+            Operator::Format => 'q',
+        }
+    }
+
+    /// Motions that are a single key, the same after an operator as in visual mode
+    fn motion_key(c: char) -> Option<Motion> {
+        Some(match c {
+            'h' => Motion::Left,
+            'l' => Motion::Right,
+            'k' => Motion::Up,
+            'j' => Motion::Down,
+            'w' => Motion::WordForward,
+            'b' => Motion::WordBackward,
+            'e' => Motion::WordEnd,
+            'W' => Motion::BigWordForward,
+            'B' => Motion::BigWordBackward,
+            'E' => Motion::BigWordEnd,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            '^' => Motion::FirstNonWhitespace,
+            'G' => Motion::DocumentEnd,
+            '%' => Motion::MatchBracket,
+            _ => return None,
+        })
+    }
+
     pub fn parse_key_with_state(
         mode: &Mode,
         key_event: &crossterm::event::KeyEvent,
         pending_key: &mut Option<char>,
         number_prefix: &mut Option<usize>,
         pending_register: &mut Option<char>,
+        pending_operator: &mut Option<PendingOperator>,
     ) -> Option<Command> {
         let key = key_event.code;
         let modifiers = key_event.modifiers;
@@ -22,12 +126,13 @@ impl KeyHandler {
                 pending_key,
                 number_prefix,
                 pending_register,
+                pending_operator,
             ),
-            Mode::Insert => Self::parse_insert_mode_key(key),
+            Mode::Insert => Self::parse_insert_mode_key(key, modifiers, pending_key),
             Mode::Command => Self::parse_command_mode_key(key),
             Mode::Search | Mode::SearchBackward => None, // Search mode input is handled directly in controller
             Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => {
-                Self::parse_visual_mode_key(key, modifiers)
+                Self::parse_visual_mode_key(key, modifiers, pending_key, number_prefix, pending_register)
             }
         }
     }
@@ -47,11 +152,18 @@ impl KeyHandler {
             KeyCode::Char('u') if modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(Command::MoveHalfPageUp)
             }
+            KeyCode::Char('e') if modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Command::ScrollLinesDown)
+            }
+            KeyCode::Char('y') if modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Command::ScrollLinesUp)
+            }
             // Alternative binding for half page down (in case Ctrl+D is intercepted by terminal)
             KeyCode::Char('j') if modifiers.contains(KeyModifiers::ALT) => {
                 Some(Command::MoveHalfPageDown)
             }
             KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => Some(Command::Redo),
+            KeyCode::Char('z') if modifiers.contains(KeyModifiers::CONTROL) => Some(Command::Suspend),
             KeyCode::Char('l') if modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(Command::Redraw)
             }
@@ -64,6 +176,13 @@ impl KeyHandler {
             KeyCode::Char('i') if modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(Command::JumpForward)
             }
+            // Terminals send Ctrl-] as the byte crossterm reads as Ctrl-5
+            KeyCode::Char(']' | '5') if modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Command::JumpToTag)
+            }
+            KeyCode::Char('t') if modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Command::PopTag)
+            }
 
             // Insert modes
             KeyCode::Char('i') => Some(Command::EnterInsertMode),
@@ -114,40 +233,16 @@ impl KeyHandler {
             KeyCode::Char(';') => Some(Command::RepeatFind),
             KeyCode::Char(',') => Some(Command::RepeatFindReverse),
 
-            // Delete commands
-            KeyCode::Char('x') => Some(Command::DeleteCharForward),
-            KeyCode::Char('X') => Some(Command::DeleteCharBackward),
-            KeyCode::Char('D') => Some(Command::DeleteToEndOfLine),
-            KeyCode::Char('s') => Some(Command::SubstituteChar),
-            KeyCode::Char('S') => Some(Command::SubstituteLine),
-            KeyCode::Char('J') => Some(Command::JoinLines),
+            // Line and case commands
+            KeyCode::Char('J') => Some(Command::JoinLines(true)),
             KeyCode::Char('~') => Some(Command::ToggleCase),
-
-            // Change commands
-            KeyCode::Char('c') => None, // Handled in stateful parser for cc, cw, etc.
-            KeyCode::Char('C') => Some(Command::ChangeToEndOfLine),
+            KeyCode::Char('&') => Some(Command::RepeatSubstitute),
+            KeyCode::Char('K') => Some(Command::Hover),
 
             // Visual mode
             KeyCode::Char('v') => Some(Command::EnterVisualChar),
             KeyCode::Char('V') => Some(Command::EnterVisualLine),
 
-            // Yank (copy) commands
-            KeyCode::Char('y') => None, // Handled in stateful parser for yy, yw, etc.
-
-            // Paste commands
-            KeyCode::Char('p') => Some(Command::Paste(
-                PasteType::After,
-                None,
-            )),
-            KeyCode::Char('P') => Some(Command::Paste(
-                PasteType::Before,
-                None,
-            )),
-
-            // Indentation (handled in new stateful parser)
-            KeyCode::Char('>') => None,
-            KeyCode::Char('<') => None,
-
             // Command mode
             KeyCode::Char(':') => Some(Command::EnterCommandMode),
 
@@ -156,8 +251,8 @@ impl KeyHandler {
             KeyCode::Char('?') => Some(Command::EnterSearchBackwardMode),
             KeyCode::Char('n') => Some(Command::SearchNext),
             KeyCode::Char('N') => Some(Command::SearchPrevious),
-            KeyCode::Char('*') => Some(Command::SearchWordUnderCursor),
-            KeyCode::Char('#') => Some(Command::SearchWordUnderCursorBackward),
+            KeyCode::Char('*') => Some(Command::SearchWordUnderCursor(true)),
+            KeyCode::Char('#') => Some(Command::SearchWordUnderCursorBackward(true)),
             KeyCode::Char('%') => Some(Command::MatchBracket),
 
             // Undo/Redo
@@ -167,7 +262,33 @@ impl KeyHandler {
         }
     }
 
-    fn parse_insert_mode_key(key: KeyCode) -> Option<Command> {
+    fn parse_insert_mode_key(key: KeyCode, modifiers: KeyModifiers, pending_key: &mut Option<char>) -> Option<Command> {
+        // The register after Ctrl-R
+        if pending_key.take().is_some() {
+            return match key {
+                KeyCode::Char(c) if c.is_ascii_alphanumeric() || matches!(c, '"' | '-' | ':' | '+' | '*') => {
+                    Some(Command::InsertRegister(c))
+                }
+                _ => None,
+            };
+        }
+
+        if modifiers == KeyModifiers::CONTROL {
+            return match key {
+                KeyCode::Char('w') => Some(Command::InsertDeleteWord),
+                KeyCode::Char('u') => Some(Command::InsertDeleteToStart),
+                KeyCode::Char('t') => Some(Command::InsertShift(true)),
+                KeyCode::Char('d') => Some(Command::InsertShift(false)),
+                KeyCode::Char('r') => {
+                    *pending_key = Some('\u{12}'); // ^R: wait for the register name
+                    None
+                }
+                KeyCode::Char('v') => Some(Command::InsertLiteral),
+                KeyCode::Char('k') => Some(Command::InsertDigraph),
+                _ => None,
+            };
+        }
+
         match key {
             KeyCode::Esc => Some(Command::ExitInsertMode),
             KeyCode::Enter => Some(Command::InsertNewline),
@@ -183,20 +304,146 @@ impl KeyHandler {
         }
     }
 
+    /// The key after an operator: a count, the first key of a two-key
+    /// motion, or the motion that completes the command
+    fn parse_operator_motion(
+        key: KeyCode,
+        pending_operator: &mut Option<PendingOperator>,
+        number_prefix: &mut Option<usize>,
+        pending_register: &mut Option<char>,
+    ) -> Option<Command> {
+        let pending = pending_operator.as_mut()?;
+        let c = match key {
+            KeyCode::Char(c) => c,
+            KeyCode::Left if pending.prefix.is_none() => 'h',
+            KeyCode::Right if pending.prefix.is_none() => 'l',
+            KeyCode::Up if pending.prefix.is_none() => 'k',
+            KeyCode::Down if pending.prefix.is_none() => 'j',
+            _ => {
+                // Esc or another key that can't be part of a motion cancels the operator
+                *pending_operator = None;
+                *number_prefix = None;
+                *pending_register = None;
+                return None;
+            }
+        };
+
+        // do and dp obtain and put diff changes; they are not d with a motion
+        if pending.operator == Operator::Delete
+            && pending.prefix.is_none()
+            && pending.surround.is_none()
+            && matches!(c, 'o' | 'p')
+        {
+            *pending_operator = None;
+            *number_prefix = None;
+            *pending_register = None;
+            return Some(if c == 'o' { Command::DiffObtain } else { Command::DiffPut });
+        }
+
+        // ds, cs and ys: the s, then targets and delimiters
+        match pending.surround {
+            None if c == 's' && pending.prefix.is_none() && number_prefix.is_none() => {
+                pending.surround = match pending.operator {
+                    Operator::Delete => Some(SurroundStep::Delete),
+                    Operator::Change => Some(SurroundStep::Change),
+                    Operator::Yank => Some(SurroundStep::AddMotion),
+                    _ => None,
+                };
+                if pending.surround.is_some() {
+                    return None;
+                }
+            }
+            Some(SurroundStep::Change) => {
+                pending.surround = Some(SurroundStep::ChangeTo(c));
+                return None;
+            }
+            Some(step @ (SurroundStep::Delete | SurroundStep::ChangeTo(_) | SurroundStep::Add(..))) => {
+                *pending_operator = None;
+                *pending_register = None;
+                return Some(match step {
+                    SurroundStep::Add(motion, count) => Command::Surround(motion, count, c),
+                    SurroundStep::ChangeTo(target) => Command::ChangeSurround(target, Some(c)),
+                    _ => Command::ChangeSurround(c, None),
+                });
+            }
+            _ => {}
+        }
+
+        // A count between the operator and the motion multiplies the first (2d3w is d6w)
+        if pending.prefix.is_none() && c.is_ascii_digit() && (c != '0' || number_prefix.is_some()) {
+            if let Some(digit) = c.to_digit(10) {
+                *number_prefix = Some(number_prefix.unwrap_or(0) * 10 + digit as usize);
+            }
+            return None;
+        }
+        let count = match (pending.count, number_prefix.take()) {
+            (Some(before), Some(after)) => Some(before * after),
+            (before, after) => before.or(after),
+        };
+
+        let operator = pending.operator;
+        let adding_surround = matches!(pending.surround, Some(SurroundStep::AddMotion));
+        let doubled = if adding_surround { 's' } else { Self::doubled_key(operator) };
+        let motion = match pending.prefix.take() {
+            Some('f') => Some(Motion::FindChar(c)),
+            Some('F') => Some(Motion::FindCharBackward(c)),
+            Some('t') => Some(Motion::UntilChar(c)),
+            Some('T') => Some(Motion::UntilCharBackward(c)),
+            Some('\'') if Self::is_mark_name(c) => Some(Motion::MarkLine(c)),
+            Some('`') if Self::is_mark_name(c) => Some(Motion::Mark(c)),
+            Some('g') if c == 'g' => Some(count.map_or(Motion::DocumentStart, Motion::ToLine)),
+            // gugu and gUgU work as well as guu and gUU
+            Some('g') if c == doubled && !matches!(doubled, 'd' | 'c' | 'y' | '>' | '<') => Some(Motion::Line),
+            Some(_) => None,
+            None if matches!(c, 'f' | 'F' | 't' | 'T' | '\'' | '`' | 'g') => {
+                pending.prefix = Some(c);
+                pending.count = count;
+                return None; // Wait for the rest of the motion
+            }
+            None if c == doubled => Some(Motion::Line),
+            None if c == 'G' => Some(count.map_or(Motion::DocumentEnd, Motion::ToLine)),
+            None => Self::motion_key(c),
+        };
+
+        if adding_surround && let Some(motion) = motion {
+            pending.surround = Some(SurroundStep::Add(motion, count.unwrap_or(1)));
+            return None; // Wait for the delimiter
+        }
+        *pending_operator = None;
+        match motion {
+            Some(motion) => Some(Command::Operate(operator, motion, count.unwrap_or(1), pending_register.take())),
+            None => {
+                // Not a motion: the operator is cancelled
+                *pending_register = None;
+                None
+            }
+        }
+    }
+
     fn parse_normal_mode_with_state(
         key: KeyCode,
         modifiers: KeyModifiers,
         pending_key: &mut Option<char>,
         number_prefix: &mut Option<usize>,
         pending_register: &mut Option<char>,
+        pending_operator: &mut Option<PendingOperator>,
     ) -> Option<Command> {
+        if pending_operator.is_some() {
+            return Self::parse_operator_motion(key, pending_operator, number_prefix, pending_register);
+        }
+
         match key {
             // Handle '0' specially - if no number prefix exists, it's MoveLineStart
-            KeyCode::Char('0') if number_prefix.is_none() => {
+            KeyCode::Char('0') if number_prefix.is_none() && pending_key.is_none() => {
                 Some(Command::MoveLineStart)
             }
             // Handle numbers for prefixes
-            KeyCode::Char(c) if c.is_ascii_digit() => {
+            KeyCode::Char(c)
+                if c.is_ascii_digit()
+                    && !modifiers.contains(KeyModifiers::CONTROL)
+                    && (c != '0' || number_prefix.is_some())
+                    && pending_key.is_none() =>
+            {
                 if let Some(digit) = c.to_digit(10) {
                     *number_prefix = Some(number_prefix.unwrap_or(0) * 10 + digit as usize);
                 }
@@ -206,245 +453,30 @@ impl KeyHandler {
             // Handle pending multi-key sequences
             KeyCode::Char(c) if pending_key.is_some() => {
                 let pending = pending_key.take().expect("pending_key was just checked to be Some");
-                let count = number_prefix.take().unwrap_or(1);
 
-                // Handle register sequences first
-                if pending == '"' && (c.is_ascii_alphabetic() || c.is_ascii_digit()) {
+                // Handle register sequences first so a count typed before "a survives
+                if pending == '"' && (c.is_ascii_alphabetic() || c.is_ascii_digit() || matches!(c, ':' | '+' | '*' | '-' | '%' | '#')) {
                     *pending_register = Some(c);
                     return None; // Wait for the actual command (y, d, p, etc.)
                 }
 
-                match (pending, c) {
-                    ('>', '>') => Some(if count == 1 {
-                        Command::IndentLine
-                    } else {
-                        Command::IndentLines(count)
-                    }),
-                    ('<', '<') => Some(if count == 1 {
-                        Command::DedentLine
-                    } else {
-                        Command::DedentLines(count)
-                    }),
-                    ('d', 'd') => Some(if count == 1 {
-                        Command::DeleteLine
-                    } else {
-                        Command::DeleteLines(count)
-                    }),
-                    ('d', 'w') => Some(Command::DeleteWord),
-                    ('d', 'W') => Some(Command::DeleteBigWord),
-                    ('d', 'b') => Some(Command::DeleteWordBackward),
-                    ('d', 'B') => Some(Command::DeleteBigWordBackward),
-                    ('d', 'e') => Some(Command::DeleteToEndOfWord),
-                    ('d', 'E') => Some(Command::DeleteToEndOfBigWord),
-                    ('d', '0') => Some(Command::DeleteToStartOfLine),
-                    ('d', '$') => Some(Command::DeleteToEndOfLine),
-                    ('d', '^') => Some(Command::DeleteToFirstNonWhitespace),
-                    ('d', 'G') => Some(Command::DeleteToEndOfFile),
-                    ('d', 'g') => Some(Command::DeleteToStartOfFile), // dgg -> delete to start
-                    ('d', 't') => {
-                        // For dt{char} - wait for target character
-                        *pending_key = Some('~'); // Use '~' to indicate delete-until-char mode
-                        None
-                    }
-                    ('d', 'T') => {
-                        // For dT{char} - wait for target character
-                        *pending_key = Some('@'); // Use '@' to indicate delete-until-char-backward mode
-                        None
-                    }
-                    ('d', 'f') => {
-                        // For df{char} - wait for target character
-                        *pending_key = Some('#'); // Use '#' to indicate delete-find-char mode
-                        None
-                    }
-                    ('d', 'F') => {
-                        // For dF{char} - wait for target character
-                        *pending_key = Some('%'); // Use '%' to indicate delete-find-char-backward mode
-                        None
-                    }
-                    ('~', target_char) => Some(Command::DeleteUntilChar(target_char)),
-                    ('@', target_char) => Some(Command::DeleteUntilCharBackward(target_char)),
-                    ('#', target_char) => Some(Command::DeleteFindChar(target_char)),
-                    ('%', target_char) => Some(Command::DeleteFindCharBackward(target_char)),
-
-                    // Yank (copy) commands
-                    ('y', 'y') => {
-                        let register = pending_register.take();
-                        Some(if count == 1 {
-                            Command::Yank(YankType::Line, register)
-                        } else {
-                            Command::Yank(
-                                YankType::Lines(count),
-                                register,
-                            )
-                        })
-                    }
-                    ('y', 'w') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::Word,
-                            register,
-                        ))
-                    }
-                    ('y', 'W') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::BigWord,
-                            register,
-                        ))
-                    }
-                    ('y', 'b') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::WordBackward,
-                            register,
-                        ))
-                    }
-                    ('y', 'B') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::BigWordBackward,
-                            register,
-                        ))
-                    }
-                    ('y', 'e') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToEndOfWord,
-                            register,
-                        ))
-                    }
-                    ('y', 'E') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToEndOfBigWord,
-                            register,
-                        ))
-                    }
-                    ('y', '0') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToStartOfLine,
-                            register,
-                        ))
-                    }
-                    ('y', '$') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToEndOfLine,
-                            register,
-                        ))
-                    }
-                    ('y', '^') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToFirstNonWhitespace,
-                            register,
-                        ))
-                    }
-                    ('y', 'G') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToEndOfFile,
-                            register,
-                        ))
-                    }
-                    ('y', 'g') => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::ToStartOfFile,
-                            register,
-                        ))
-                    } // ygg -> yank to start
-                    ('y', 't') => {
-                        // For yt{char} - wait for target character
-                        *pending_key = Some('&'); // Use '&' to indicate yank-until-char mode
-                        None
-                    }
-                    ('y', 'T') => {
-                        // For yT{char} - wait for target character
-                        *pending_key = Some('*'); // Use '*' to indicate yank-until-char-backward mode
-                        None
-                    }
-                    ('y', 'f') => {
-                        // For yf{char} - wait for target character
-                        *pending_key = Some('('); // Use '(' to indicate yank-find-char mode
-                        None
-                    }
-                    ('y', 'F') => {
-                        // For yF{char} - wait for target character
-                        *pending_key = Some(')'); // Use ')' to indicate yank-find-char-backward mode
-                        None
-                    }
-                    ('&', target_char) => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::UntilChar(target_char),
-                            register,
-                        ))
-                    }
-                    ('*', target_char) => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::UntilCharBackward(target_char),
-                            register,
-                        ))
-                    }
-                    ('(', target_char) => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::FindChar(target_char),
-                            register,
-                        ))
-                    }
-                    (')', target_char) => {
-                        let register = pending_register.take();
-                        Some(Command::Yank(
-                            YankType::FindCharBackward(target_char),
-                            register,
-                        ))
-                    }
+                // gu, gU and g~ are operators waiting for a motion
+                if pending == 'g'
+                    && let Some(operator) = match c {
+                        'u' => Some(Operator::Lowercase),
+                        'U' => Some(Operator::Uppercase),
+                        '~' => Some(Operator::ToggleCase),
+                        'q' => Some(Operator::Format),
+                        _ => None,
+                    }
+                {
+                    *pending_operator = Some(PendingOperator { operator, count: number_prefix.take(), prefix: None, surround: None });
+                    return None;
+                }
 
-                    // Change (delete + insert mode) commands
-                    ('c', 'c') => Some(if count == 1 {
-                        Command::ChangeLine
-                    } else {
-                        Command::ChangeLines(count)
-                    }),
-                    ('c', 'w') => Some(Command::ChangeWord),
-                    ('c', 'W') => Some(Command::ChangeBigWord),
-                    ('c', 'b') => Some(Command::ChangeWordBackward),
-                    ('c', 'B') => Some(Command::ChangeBigWordBackward),
-                    ('c', 'e') => Some(Command::ChangeToEndOfWord),
-                    ('c', 'E') => Some(Command::ChangeToEndOfBigWord),
-                    ('c', '0') => Some(Command::ChangeToStartOfLine),
-                    ('c', '$') => Some(Command::ChangeToEndOfLine),
-                    ('c', '^') => Some(Command::ChangeToFirstNonWhitespace),
-                    ('c', 'G') => Some(Command::ChangeToEndOfFile),
-                    ('c', 'g') => Some(Command::ChangeToStartOfFile), // cgg -> change to start
-                    ('c', 't') => {
-                        // For ct{char} - wait for target character
-                        *pending_key = Some('!'); // Use '!' to indicate change-until-char mode
-                        None
-                    }
-                    ('c', 'T') => {
-                        // For cT{char} - wait for target character
-                        *pending_key = Some('?'); // Use '?' to indicate change-until-char-backward mode
-                        None
-                    }
-                    ('c', 'f') => {
-                        // For cf{char} - wait for target character
-                        *pending_key = Some('['); // Use '[' to indicate change-find-char mode
-                        None
-                    }
-                    ('c', 'F') => {
-                        // For cF{char} - wait for target character
-                        *pending_key = Some(']'); // Use ']' to indicate change-find-char-backward mode
-                        None
-                    }
-                    ('!', target_char) => Some(Command::ChangeUntilChar(target_char)),
-                    ('?', target_char) => Some(Command::ChangeUntilCharBackward(target_char)),
-                    ('[', target_char) => Some(Command::ChangeFindChar(target_char)),
-                    (']', target_char) => Some(Command::ChangeFindCharBackward(target_char)),
+                let count = number_prefix.take().unwrap_or(1);
+
+                match (pending, c) {
                     ('m', mark_char) if mark_char.is_ascii_alphabetic() => {
                         Some(Command::SetMark(mark_char))
                     }
@@ -464,16 +496,56 @@ impl KeyHandler {
                     {
                         Some(Command::JumpToMark(mark_char))
                     }
-                    // Handle 'g' commands: gg for goto line 1, gu for lowercase, gU for uppercase
-                    ('g', 'g') => Some(Command::MoveDocumentStart),
-                    ('g', 'u') => Some(Command::Lowercase),
-                    ('g', 'U') => Some(Command::Uppercase),
+                    // Handle 'g' commands: gg goes to line 1 (or the count's line)
+                    ('g', 'g') => Some(if count > 1 {
+                        Command::MoveToLine(count)
+                    } else {
+                        Command::MoveDocumentStart
+                    }),
+                    ('g', ';') => Some(Command::ChangeListOlder(count)),
+                    ('g', ',') => Some(Command::ChangeListNewer(count)),
+                    ('g', '-') => Some(Command::UndoTreeBackward(count)),
+                    ('g', '+') => Some(Command::UndoTreeForward(count)),
+                    ('g', 'v') => Some(Command::ReselectVisual),
+                    ('g', '*') => Some(Command::SearchWordUnderCursor(false)),
+                    ('g', '#') => Some(Command::SearchWordUnderCursorBackward(false)),
+                    ('g', 'd') => Some(Command::GotoDefinition),
+                    ('g', 'J') => {
+                        *number_prefix = Some(count); // 3gJ joins three lines, as 3J does
+                        Some(Command::JoinLines(false))
+                    }
+
+                    // 'z' commands scroll the view: zt, zz, zb put the cursor's line at
+                    // the top, middle or bottom, the others scroll sideways
+                    ('z', 't') => Some(Command::ScrollCursorTop),
+                    ('z', 'z') => Some(Command::ScrollCursorMiddle),
+                    ('z', 'b') => Some(Command::ScrollCursorBottom),
+                    ('z', 'l') => Some(Command::ScrollRight(count)),
+                    ('z', 'h') => Some(Command::ScrollLeft(count)),
+                    ('z', 'L') => Some(Command::ScrollHalfWidthRight),
+                    ('z', 'H') => Some(Command::ScrollHalfWidthLeft),
+
+                    // q: q/ q? open the command line or search history
+                    ('q', prompt @ (':' | '/' | '?')) => Some(Command::OpenHistory(prompt)),
+
+                    // ]c and [c move between the changes of diff mode
+                    (']', 'c') => Some(Command::NextChange(count)),
+                    ('[', 'c') => Some(Command::PreviousChange(count)),
+
+                    ('Z', 'Z') => Some(Command::WriteQuit),
+                    ('Z', 'Q') => Some(Command::QuitWithoutWriting),
+
+                    // Ctrl-W {key} window commands (Ctrl-W Ctrl-W works like Ctrl-W w)
+                    ('\u{17}', window_key) => Some(Command::Window(window_key)),
+
+                    // @: repeats the last command line; @@ repeats it again
+                    ('\u{1}', ':' | '@') => Some(Command::RepeatCommandLine(count)),
                     
                     // Handle character search commands
-                    ('f', target_char) => Some(Command::FindChar(target_char)),
-                    ('F', target_char) => Some(Command::FindCharBackward(target_char)),
-                    ('t', target_char) => Some(Command::FindCharBefore(target_char)),
-                    ('T', target_char) => Some(Command::FindCharBeforeBackward(target_char)),
+                    ('f', target_char) => Some(Command::FindChar(target_char, count)),
+                    ('F', target_char) => Some(Command::FindCharBackward(target_char, count)),
+                    ('t', target_char) => Some(Command::FindCharBefore(target_char, count)),
+                    ('T', target_char) => Some(Command::FindCharBeforeBackward(target_char, count)),
                     _ => {
                         // Invalid sequence, clear state
                         *pending_key = None;
@@ -484,25 +556,20 @@ impl KeyHandler {
             }
 
             // Start multi-key sequences (only for unmodified keys)
-            KeyCode::Char('>') if modifiers.is_empty() => {
-                *pending_key = Some('>');
-                None // Wait for second >
-            }
-            KeyCode::Char('<') if modifiers.is_empty() => {
-                *pending_key = Some('<');
-                None // Wait for second <
+            KeyCode::Char('w') if modifiers.contains(KeyModifiers::CONTROL) => {
+                *pending_key = Some('\u{17}'); // Use the ^W character to indicate window-command mode
+                None // Wait for the window command key
             }
-            KeyCode::Char('d') if modifiers.is_empty() => {
-                *pending_key = Some('d');
-                None // Wait for second key (d, w, W, etc.)
-            }
-            KeyCode::Char('y') if modifiers.is_empty() => {
-                *pending_key = Some('y');
-                None // Wait for second key (y, w, W, etc.)
-            }
-            KeyCode::Char('c') if modifiers.is_empty() => {
-                *pending_key = Some('c');
-                None // Wait for second key (c, w, W, etc.)
+            KeyCode::Char(c @ ('d' | 'c' | 'y' | '>' | '<')) if modifiers.is_empty() => {
+                let operator = match c {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    'y' => Operator::Yank,
+                    '>' => Operator::Indent,
+                    _ => Operator::Dedent,
+                };
+                *pending_operator = Some(PendingOperator { operator, count: number_prefix.take(), prefix: None, surround: None });
+                None // Wait for the motion
             }
             KeyCode::Char('m') if modifiers.is_empty() => {
                 *pending_key = Some('m');
@@ -516,13 +583,33 @@ impl KeyHandler {
                 *pending_key = Some('`');
                 None // Wait for mark character (exact position jump)
             }
+            KeyCode::Char('@') if modifiers.is_empty() => {
+                *pending_key = Some('\u{1}'); // Use ^A for execute-register mode
+                None // Wait for register name
+            }
             KeyCode::Char('"') if modifiers.is_empty() => {
                 *pending_key = Some('"');
-                None // Wait for register name (a-z, A-Z, 0-9)
+                None // Wait for register name (a-z, A-Z, 0-9, :)
             }
             KeyCode::Char('g') if modifiers.is_empty() => {
                 *pending_key = Some('g');
-                None // Wait for second key (g, u, U)
+                None // Wait for second key (g, u, U, ~)
+            }
+            KeyCode::Char('z') if modifiers.is_empty() => {
+                *pending_key = Some('z');
+                None // Wait for second key (l, h, L, H)
+            }
+            KeyCode::Char('q') if modifiers.is_empty() => {
+                *pending_key = Some('q');
+                None // Wait for :, / or ?
+            }
+            KeyCode::Char('Z') => {
+                *pending_key = Some('Z');
+                None // Wait for second key (Z, Q)
+            }
+            KeyCode::Char(c @ (']' | '[')) if modifiers.is_empty() => {
+                *pending_key = Some(c);
+                None // Wait for c
             }
             KeyCode::Char('f') if modifiers.is_empty() => {
                 *pending_key = Some('f');
@@ -550,21 +637,49 @@ impl KeyHandler {
                 *pending_key = None;
                 // Don't consume number_prefix here - let the controller handle it
 
-                // Handle register-aware commands
+                // Handle register-aware and count-consuming commands
                 match (key, register_char) {
-                    (KeyCode::Char('p'), Some(reg)) => Some(Command::Paste(
+                    (KeyCode::Char('p'), _) if modifiers.contains(KeyModifiers::CONTROL) => {
+                        Some(Command::YankPop(number_prefix.take().unwrap_or(1)))
+                    }
+                    (KeyCode::Char('p'), reg) => Some(Command::Paste(
                         PasteType::After,
-                        Some(reg),
+                        reg,
+                        number_prefix.take().unwrap_or(1),
                     )),
-                    (KeyCode::Char('P'), Some(reg)) => Some(Command::Paste(
+                    (KeyCode::Char('P'), reg) => Some(Command::Paste(
                         PasteType::Before,
-                        Some(reg),
+                        reg,
+                        number_prefix.take().unwrap_or(1),
+                    )),
+                    (KeyCode::Char('x'), reg) => Some(Command::DeleteCharForward(
+                        reg,
+                        number_prefix.take().unwrap_or(1),
+                    )),
+                    (KeyCode::Char('X'), reg) => Some(Command::DeleteCharBackward(
+                        reg,
+                        number_prefix.take().unwrap_or(1),
                     )),
-                    (KeyCode::Char('y'), Some(_reg)) => {
-                        // Store the register for the yank command sequence
-                        *pending_register = register_char;
-                        *pending_key = Some('y');
-                        None // Wait for second key (y, w, W, etc.)
+                    (KeyCode::Char('s'), reg) => Some(Command::SubstituteChar(
+                        reg,
+                        number_prefix.take().unwrap_or(1),
+                    )),
+                    // Ctrl-^ comes through as Ctrl-6 from most terminals
+                    (KeyCode::Char('^' | '6'), _) if modifiers.contains(KeyModifiers::CONTROL) => {
+                        Some(Command::AlternateFile(number_prefix.take()))
+                    }
+                    (KeyCode::Char('G'), _) if number_prefix.is_some() => {
+                        Some(Command::MoveToLine(number_prefix.take().unwrap_or(1)))
+                    }
+                    // D, C and S are short for d$, c$ and cc
+                    (KeyCode::Char('D'), reg) => {
+                        Some(Command::Operate(Operator::Delete, Motion::LineEnd, number_prefix.take().unwrap_or(1), reg))
+                    }
+                    (KeyCode::Char('C'), reg) => {
+                        Some(Command::Operate(Operator::Change, Motion::LineEnd, number_prefix.take().unwrap_or(1), reg))
+                    }
+                    (KeyCode::Char('S'), reg) => {
+                        Some(Command::Operate(Operator::Change, Motion::Line, number_prefix.take().unwrap_or(1), reg))
                     }
                     _ => {
                         // Use existing parsing but apply count if relevant
@@ -584,43 +699,95 @@ impl KeyHandler {
         }
     }
 
-    fn parse_visual_mode_key(key: KeyCode, modifiers: KeyModifiers) -> Option<Command> {
+    fn parse_visual_mode_key(
+        key: KeyCode,
+        modifiers: KeyModifiers,
+        pending_key: &mut Option<char>,
+        number_prefix: &mut Option<usize>,
+        pending_register: &mut Option<char>,
+    ) -> Option<Command> {
+        // Second key of a g command, a mark jump, r or a register name
+        if let Some(pending) = pending_key.take() {
+            return match (pending, key) {
+                ('"', KeyCode::Char(c)) if c.is_ascii_alphanumeric() || matches!(c, ':' | '+' | '*' | '-') => {
+                    *pending_register = Some(c);
+                    None
+                }
+                ('r', KeyCode::Char(c)) => Some(Command::VisualReplace(c)),
+                ('g', KeyCode::Char('g')) => Some(Command::Motion(Motion::DocumentStart)),
+                ('g', KeyCode::Char('b')) => Some(Command::ToggleBlockComment),
+                ('g', KeyCode::Char('u')) => Some(Command::VisualOperate(Operator::Lowercase)),
+                ('g', KeyCode::Char('U')) => Some(Command::VisualOperate(Operator::Uppercase)),
+                ('g', KeyCode::Char('~')) => Some(Command::VisualOperate(Operator::ToggleCase)),
+                ('g', KeyCode::Char('q')) => Some(Command::VisualOperate(Operator::Format)),
+                ('g', KeyCode::Char('v')) => Some(Command::ReselectVisual),
+                ('\'', KeyCode::Char(c)) if Self::is_mark_name(c) => Some(Command::JumpToMarkLine(c)),
+                ('`', KeyCode::Char(c)) if Self::is_mark_name(c) => Some(Command::JumpToMark(c)),
+                _ => None,
+            };
+        }
+
         match key {
+            // A count for the next motion or shift (0 alone is a motion)
+            KeyCode::Char(c) if c.is_ascii_digit() && (c != '0' || number_prefix.is_some()) => {
+                if let Some(digit) = c.to_digit(10) {
+                    *number_prefix = Some(number_prefix.unwrap_or(0) * 10 + digit as usize);
+                }
+                None
+            }
+
             // Exit visual mode
             KeyCode::Esc => Some(Command::ExitVisualMode),
 
-            // Visual mode operations
-            KeyCode::Char('d') => Some(Command::VisualDelete),
-            KeyCode::Char('x') => Some(Command::VisualDelete),
-            KeyCode::Char('y') => Some(Command::VisualYank),
-            KeyCode::Char('>') => Some(Command::VisualIndent),
-            KeyCode::Char('<') => Some(Command::VisualDedent),
-
-            // Movement in visual mode (same as normal mode)
-            KeyCode::Char('h') | KeyCode::Left => Some(Command::MoveLeft),
-            KeyCode::Char('j') | KeyCode::Down => Some(Command::MoveDown),
-            KeyCode::Char('k') | KeyCode::Up => Some(Command::MoveUp),
-            KeyCode::Char('l') | KeyCode::Right => Some(Command::MoveRight),
+            // Ex command over the selected lines (:'<,'>)
+            KeyCode::Char(':') => Some(Command::EnterCommandMode),
 
-            // Word movement
-            KeyCode::Char('w') => Some(Command::MoveWordForward),
-            KeyCode::Char('b') => Some(Command::MoveWordBackward),
-            KeyCode::Char('e') => Some(Command::MoveWordEnd),
-            KeyCode::Char('W') => Some(Command::MoveBigWordForward),
-            KeyCode::Char('B') => Some(Command::MoveBigWordBackward),
-            KeyCode::Char('E') => Some(Command::MoveBigWordEnd),
+            // Search inside the selection
+            KeyCode::Char('/') => Some(Command::EnterSearchMode),
+            KeyCode::Char('?') => Some(Command::EnterSearchBackwardMode),
 
-            // Line movement
-            KeyCode::Char('0') => Some(Command::MoveLineStart),
-            KeyCode::Char('$') => Some(Command::MoveLineEnd),
-            KeyCode::Char('^') => Some(Command::MoveFirstNonWhitespace),
+            // Operators act on the selection
+            KeyCode::Char('d') | KeyCode::Char('x') => Some(Command::VisualOperate(Operator::Delete)),
+            KeyCode::Char('y') => Some(Command::VisualOperate(Operator::Yank)),
+            KeyCode::Char('c') | KeyCode::Char('s') => Some(Command::VisualOperate(Operator::Change)),
+            KeyCode::Char('>') => Some(Command::VisualOperate(Operator::Indent)),
+            KeyCode::Char('<') => Some(Command::VisualOperate(Operator::Dedent)),
+            KeyCode::Char('~') => Some(Command::VisualOperate(Operator::ToggleCase)),
+            KeyCode::Char('u') if !modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Command::VisualOperate(Operator::Lowercase))
+            }
+            KeyCode::Char('U') => Some(Command::VisualOperate(Operator::Uppercase)),
+            KeyCode::Char('J') => Some(Command::VisualJoin),
+            KeyCode::Char('p') if !modifiers.contains(KeyModifiers::CONTROL) => Some(Command::VisualPut(false)),
+            KeyCode::Char('P') => Some(Command::VisualPut(true)),
+            KeyCode::Char('I') => Some(Command::VisualBlockInsert),
+            KeyCode::Char('A') => Some(Command::VisualBlockAppend),
+            KeyCode::Char('o') => Some(Command::VisualSwapEnds(false)),
+            KeyCode::Char('O') => Some(Command::VisualSwapEnds(true)),
+
+            // Motions extend the selection, the same motions an operator takes
+            KeyCode::Left => Some(Command::Motion(Motion::Left)),
+            KeyCode::Down => Some(Command::Motion(Motion::Down)),
+            KeyCode::Up => Some(Command::Motion(Motion::Up)),
+            KeyCode::Right => Some(Command::Motion(Motion::Right)),
+            KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) && let Some(motion) = Self::motion_key(c) => {
+                Some(Command::Motion(motion))
+            }
             KeyCode::Char('+') => Some(Command::MoveDownToFirstNonWhitespace),
             KeyCode::Char('-') => Some(Command::MoveUpToFirstNonWhitespace),
             KeyCode::Enter => Some(Command::MoveDownToFirstNonWhitespace),
 
-            // Document movement (only if not already handled above)
-            KeyCode::Char('g') => Some(Command::MoveDocumentStart),
-            KeyCode::Char('G') => Some(Command::MoveDocumentEnd),
+            // g commands (gg, gb, gu, gU, g~, gv)
+            KeyCode::Char('g') => {
+                *pending_key = Some('g');
+                None
+            }
+
+            // Extend the selection to a mark ('a, `a), replace (r), pick a register (")
+            KeyCode::Char(c @ ('\'' | '`' | 'r' | '"')) if !modifiers.contains(KeyModifiers::CONTROL) => {
+                *pending_key = Some(c);
+                None
+            }
 
             // Screen positioning
             KeyCode::Char('H') => Some(Command::MoveToScreenTop),
@@ -636,11 +803,8 @@ impl KeyHandler {
             }
 
             // Word search
-            KeyCode::Char('*') => Some(Command::SearchWordUnderCursor),
-            KeyCode::Char('#') => Some(Command::SearchWordUnderCursorBackward),
-
-            // Bracket matching
-            KeyCode::Char('%') => Some(Command::MatchBracket),
+            KeyCode::Char('*') => Some(Command::SearchWordUnderCursor(true)),
+            KeyCode::Char('#') => Some(Command::SearchWordUnderCursorBackward(true)),
 
             // Switch visual modes (Ctrl+v must come first to avoid conflict)
             KeyCode::Char('v') if modifiers.contains(KeyModifiers::CONTROL) => {
@@ -0,0 +1,574 @@
+//! Key mappings - :map, :noremap and the rest, from the command line or .virusrc
+//!
+//! Keys go through a typeahead queue before a mode controller sees them. A
+//! key that starts the left-hand side of a mapping waits until the keys after
+//! it either complete the mapping or can't (or until typing pauses), and a
+//! completed left-hand side is replaced in the queue by the right-hand side.
+//! Keys from a :map right-hand side are looked up again, except where they
+//! repeat the left-hand side it starts with; keys from :noremap are not.
+//! Each mode's mappings form a tree of their left-hand sides, so the keys
+//! waiting are looked up by walking down it a key at a time.
+//!
+//! Keys are written in vim's notation: ordinary characters stand for
+//! themselves and `<Esc>`, `<CR>`, `<BS>`, `<Tab>`, `<Del>`, `<Up>`, `<Down>`,
+//! `<Left>`, `<Right>`, `<Home>`, `<End>`, `<PageUp>`, `<PageDown>`,
+//! `<Space>`, `<C-x>` (Ctrl with any character) and `<lt>` (a literal '<')
+//! name the rest. A '<' that doesn't start one of these is itself. In a
+//! mapping, `<leader>` stands for the keys `:let mapleader` last set
+//! (backslash until then), as they were when the mapping was made.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+/// Expansions allowed for one typed key before a mapping counts as recursive
+const MAX_EXPANSIONS: usize = 1000;
+
+/// What `<leader>` stands for until :let mapleader changes it
+const DEFAULT_LEADER: &str = "\\";
+
+/// The modes a mapping can apply in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapMode {
+    Normal,
+    Visual,
+    Insert,
+}
+
+impl MapMode {
+    /// The letter :map listings show for the mode
+    fn letter(self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Visual => 'v',
+            MapMode::Insert => 'i',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Mapping {
+    lhs: Vec<KeyEvent>,
+    rhs: Vec<KeyEvent>,
+    recursive: bool,  // :map rather than :noremap
+    lhs_text: String, // As written, for listings
+    rhs_text: String,
+}
+
+/// The mappings of one mode, as a tree of their left-hand sides: each key
+/// steps down a branch, and a node holds the mapping whose left-hand side
+/// ends there
+#[derive(Debug, Default)]
+struct MapTree {
+    mapping: Option<Mapping>,
+    branches: Vec<(KeyEvent, MapTree)>,
+}
+
+impl MapTree {
+    fn branch(&self, key: &KeyEvent) -> Option<&MapTree> {
+        self.branches.iter().find(|(k, _)| k == key).map(|(_, node)| node)
+    }
+
+    /// The node `keys` lead to, if any mapping starts with them
+    fn get(&self, keys: &[KeyEvent]) -> Option<&MapTree> {
+        keys.iter().try_fold(self, |node, key| node.branch(key))
+    }
+
+    /// Add `mapping`, replacing any with the same left-hand side
+    fn insert(&mut self, mapping: Mapping) {
+        let mut node = self;
+        for key in &mapping.lhs {
+            let index = match node.branches.iter().position(|(k, _)| k == key) {
+                Some(index) => index,
+                None => {
+                    node.branches.push((*key, MapTree::default()));
+                    node.branches.len() - 1
+                }
+            };
+            node = &mut node.branches[index].1;
+        }
+        node.mapping = Some(mapping);
+    }
+
+    /// Remove the mapping for `lhs` and any branch it leaves empty. False
+    /// if there was none.
+    fn remove(&mut self, lhs: &[KeyEvent]) -> bool {
+        let Some((key, rest)) = lhs.split_first() else {
+            return self.mapping.take().is_some();
+        };
+        let Some(index) = self.branches.iter().position(|(k, _)| k == key) else {
+            return false;
+        };
+        let removed = self.branches[index].1.remove(rest);
+        let branch = &self.branches[index].1;
+        if branch.mapping.is_none() && branch.branches.is_empty() {
+            self.branches.remove(index);
+        }
+        removed
+    }
+
+    /// The mapping with the longest left-hand side that `keys` start with
+    fn longest_match(&self, keys: &[KeyEvent]) -> Option<&Mapping> {
+        let mut node = self;
+        let mut found = None;
+        for key in keys {
+            let Some(next) = node.branch(key) else { break };
+            node = next;
+            found = node.mapping.as_ref().or(found);
+        }
+        found
+    }
+
+    /// Every mapping at or below this node
+    fn mappings(&self) -> Vec<&Mapping> {
+        let mut all: Vec<&Mapping> = self.mapping.iter().collect();
+        for (_, branch) in &self.branches {
+            all.extend(branch.mappings());
+        }
+        all
+    }
+}
+
+/// What a :map-family command does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MapAction {
+    Map { recursive: bool },
+    Unmap,
+}
+
+/// What the keys waiting for a mapping came to
+enum Settled {
+    Wait,
+    Expanded,
+    Key(KeyEvent),
+}
+
+/// Keys set aside while :normal types its own
+#[derive(Debug, Default)]
+pub struct Typeahead {
+    keys: VecDeque<(KeyEvent, bool)>,
+    pending: Vec<KeyEvent>,
+}
+
+#[derive(Debug, Default)]
+pub struct KeyMap {
+    normal: MapTree,
+    visual: MapTree,
+    insert: MapTree,
+    leader: Option<String>,                // :let mapleader, as written
+    typeahead: VecDeque<(KeyEvent, bool)>, // Keys still to handle, and whether mappings apply to them
+    pending: Vec<KeyEvent>,                // Keys that may be the start of a mapping
+    timed_out: bool,                       // Typing paused: stop waiting for the rest of a mapping
+    expansions: usize,
+    error: Option<String>,
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Modes and action of a :map-family command, None for other commands
+    fn command_kind(command: &str) -> Option<(&'static [MapMode], MapAction)> {
+        const NORMAL_VISUAL: &[MapMode] = &[MapMode::Normal, MapMode::Visual];
+        let (map, noremap) = (MapAction::Map { recursive: true }, MapAction::Map { recursive: false });
+        Some(match command {
+            "map" => (NORMAL_VISUAL, map),
+            "noremap" => (NORMAL_VISUAL, noremap),
+            "unmap" => (NORMAL_VISUAL, MapAction::Unmap),
+            "nmap" => (&[MapMode::Normal], map),
+            "nnoremap" => (&[MapMode::Normal], noremap),
+            "nunmap" => (&[MapMode::Normal], MapAction::Unmap),
+            "vmap" | "xmap" => (&[MapMode::Visual], map),
+            "vnoremap" | "xnoremap" => (&[MapMode::Visual], noremap),
+            "vunmap" | "xunmap" => (&[MapMode::Visual], MapAction::Unmap),
+            "imap" => (&[MapMode::Insert], map),
+            "inoremap" => (&[MapMode::Insert], noremap),
+            "iunmap" => (&[MapMode::Insert], MapAction::Unmap),
+            _ => return None,
+        })
+    }
+
+    /// Whether `command` is one for the key map: a :map-family command or
+    /// :let (which sets mapleader)
+    pub fn is_map_command(command: &str) -> bool {
+        command == "let" || Self::command_kind(command).is_some()
+    }
+
+    fn tree(&self, mode: MapMode) -> &MapTree {
+        match mode {
+            MapMode::Normal => &self.normal,
+            MapMode::Visual => &self.visual,
+            MapMode::Insert => &self.insert,
+        }
+    }
+
+    fn tree_mut(&mut self, mode: MapMode) -> &mut MapTree {
+        match mode {
+            MapMode::Normal => &mut self.normal,
+            MapMode::Visual => &mut self.visual,
+            MapMode::Insert => &mut self.insert,
+        }
+    }
+
+    /// Run a :map-family command: `map lhs rhs` adds a mapping, `map lhs`
+    /// and `map` list them, `unmap lhs` removes one. Ok holds the message
+    /// for the status line; None if `command` isn't one of these.
+    pub fn execute(&mut self, command: &str, argument: &str) -> Option<Result<String, String>> {
+        if command == "let" {
+            return Some(self.let_leader(argument));
+        }
+        let (modes, action) = Self::command_kind(command)?;
+        let argument = argument.trim();
+        let (lhs_text, rhs_text) = match argument.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim_start()),
+            None => (argument, ""),
+        };
+        let lhs = self.parse_mapping_keys(lhs_text);
+
+        Some(match action {
+            MapAction::Unmap if lhs.is_empty() => Err("Argument required".to_string()),
+            MapAction::Unmap => {
+                let mut removed = false;
+                for &mode in modes {
+                    removed |= self.tree_mut(mode).remove(&lhs);
+                }
+                if removed { Ok(String::new()) } else { Err("No such mapping".to_string()) }
+            }
+            MapAction::Map { .. } if rhs_text.is_empty() => Ok(self.list(modes, &lhs)),
+            MapAction::Map { recursive } => {
+                let mapping = Mapping {
+                    lhs,
+                    rhs: self.parse_mapping_keys(rhs_text),
+                    recursive,
+                    lhs_text: lhs_text.to_string(),
+                    rhs_text: rhs_text.to_string(),
+                };
+                for &mode in modes {
+                    self.tree_mut(mode).insert(mapping.clone());
+                }
+                Ok(String::new())
+            }
+        })
+    }
+
+    /// :let mapleader = "," - what `<leader>` stands for in the mappings
+    /// made after it; `:let mapleader` shows it. Strings are written as in
+    /// vim: "\<Space>" names a key between double quotes, and single quotes
+    /// take the text as it is.
+    fn let_leader(&mut self, argument: &str) -> Result<String, String> {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (argument.trim(), None),
+        };
+        if name != "mapleader" {
+            return Err(format!("Undefined variable: {}", name));
+        }
+        let Some(value) = value else {
+            return Ok(format!("mapleader {}", self.leader.as_deref().unwrap_or(DEFAULT_LEADER)));
+        };
+        let leader = if let Some(text) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            text.replace("\\<", "<").replace("\\\\", "\\")
+        } else if let Some(text) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            text.replace("''", "'").replace('<', "<lt>")
+        } else {
+            return Err(format!("Invalid expression: {}", value));
+        };
+        self.leader = Some(leader);
+        Ok(String::new())
+    }
+
+    /// Keys of a mapping's left- or right-hand side, with `<leader>` replaced
+    fn parse_mapping_keys(&self, text: &str) -> Vec<KeyEvent> {
+        let leader = parse_keys(self.leader.as_deref().unwrap_or(DEFAULT_LEADER));
+        let lower = text.to_ascii_lowercase();
+        let mut keys = Vec::new();
+        let mut start = 0;
+        while let Some(found) = lower[start..].find("<leader>").map(|i| start + i) {
+            keys.extend(parse_keys(&text[start..found]));
+            keys.extend(leader.iter().copied());
+            start = found + "<leader>".len();
+        }
+        keys.extend(parse_keys(&text[start..]));
+        keys
+    }
+
+    /// Mappings in `modes` whose left-hand side starts with `prefix`, one per line
+    fn list(&self, modes: &[MapMode], prefix: &[KeyEvent]) -> String {
+        let mut rows = Vec::new();
+        for &mode in modes {
+            let mappings = self.tree(mode).get(prefix).map(MapTree::mappings).unwrap_or_default();
+            rows.extend(mappings.into_iter().map(|m| {
+                format!("{}  {:<10} {}{}", mode.letter(), m.lhs_text, if m.recursive { " " } else { "*" }, m.rhs_text)
+            }));
+        }
+        if rows.is_empty() { "No mapping found".to_string() } else { rows.join("\n") }
+    }
+
+    /// A key read from the terminal
+    pub fn type_key(&mut self, key: KeyEvent) {
+        self.typeahead.push_back((key, true));
+        self.timed_out = false;
+        self.expansions = 0;
+    }
+
+    /// Set the keys not yet handled aside, leaving the queue empty
+    pub fn take_typeahead(&mut self) -> Typeahead {
+        Typeahead { keys: std::mem::take(&mut self.typeahead), pending: std::mem::take(&mut self.pending) }
+    }
+
+    /// Put keys set aside by take_typeahead back, after anything left
+    pub fn restore_typeahead(&mut self, typeahead: Typeahead) {
+        self.typeahead.extend(typeahead.keys);
+        self.pending.extend(typeahead.pending);
+        self.timed_out = false;
+    }
+
+    /// True while typed keys wait to see whether they complete a mapping
+    pub fn is_waiting(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Typing paused: the keys waiting for a mapping get what they have so far
+    pub fn time_out(&mut self) {
+        self.timed_out = true;
+    }
+
+    /// Why the last keys were dropped, once
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// The next key for the controller of `mode` (None where mappings don't
+    /// apply), or None while there is none or keys wait for a mapping
+    pub fn next_key(&mut self, mode: Option<MapMode>) -> Option<KeyEvent> {
+        loop {
+            if self.expansions > MAX_EXPANSIONS {
+                self.typeahead.clear();
+                self.pending.clear();
+                self.error = Some("Recursive mapping".to_string());
+                return None;
+            }
+            let done = match self.typeahead.front() {
+                _ if self.pending.is_empty() => {
+                    let (key, remap) = self.typeahead.pop_front()?;
+                    if !remap || mode.is_none() {
+                        return Some(key);
+                    }
+                    self.pending.push(key);
+                    false
+                }
+                Some(&(key, true)) => {
+                    self.typeahead.pop_front();
+                    self.pending.push(key);
+                    false
+                }
+                // A key that can't be mapped, or a pause, ends the wait
+                Some(_) => true,
+                None if self.timed_out => true,
+                None => return None,
+            };
+            match self.settle(mode, done) {
+                Settled::Wait | Settled::Expanded => {}
+                Settled::Key(key) => return Some(key),
+            }
+        }
+    }
+
+    /// Work out what the pending keys are. Unless `done`, keys that could
+    /// still grow into a longer mapping wait for more.
+    fn settle(&mut self, mode: Option<MapMode>, done: bool) -> Settled {
+        let tree = mode.map(|mode| self.tree(mode));
+        if !done && tree.and_then(|tree| tree.get(&self.pending)).is_some_and(|node| !node.branches.is_empty()) {
+            return Settled::Wait;
+        }
+
+        let longest = tree.and_then(|tree| tree.longest_match(&self.pending)).cloned();
+        let mut pending = std::mem::take(&mut self.pending);
+        let (used, result) = match &longest {
+            Some(mapping) => (mapping.lhs.len(), Settled::Expanded),
+            None => (1, Settled::Key(pending[0])),
+        };
+        // Keys after the mapping are looked at again, after its expansion
+        for key in pending.split_off(used).into_iter().rev() {
+            self.typeahead.push_front((key, true));
+        }
+        if let Some(mapping) = longest {
+            // A right-hand side that starts with its own left-hand side
+            // doesn't map it again, there or later on (:map x xl, :map j jj)
+            let lhs = &mapping.lhs;
+            let mut own = vec![false; mapping.rhs.len()];
+            if mapping.rhs.starts_with(lhs) {
+                for start in 0..=mapping.rhs.len() - lhs.len() {
+                    if mapping.rhs[start..].starts_with(lhs) {
+                        own[start..start + lhs.len()].fill(true);
+                    }
+                }
+            }
+            for (index, &key) in mapping.rhs.iter().enumerate().rev() {
+                self.typeahead.push_front((key, mapping.recursive && !own[index]));
+            }
+            self.expansions += 1;
+        }
+        result
+    }
+}
+
+/// Turn keys in vim's notation into key events
+pub fn parse_keys(script: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = script;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<'
+            && let Some(end) = rest.find('>')
+            && let Some(key) = named_key(&rest[1..end])
+        {
+            keys.push(key);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        keys.push(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        rest = &rest[ch.len_utf8()..];
+    }
+    keys
+}
+
+/// The key for a name written between angle brackets
+fn named_key(name: &str) -> Option<KeyEvent> {
+    let plain = |code| Some(KeyEvent::new(code, KeyModifiers::NONE));
+    let lower = name.to_ascii_lowercase();
+    if let Some(ch) = lower.strip_prefix("c-") {
+        let mut chars = ch.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(ch), None) => Some(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL)),
+            _ => None,
+        };
+    }
+    match lower.as_str() {
+        "esc" => plain(KeyCode::Esc),
+        "cr" | "enter" | "return" => plain(KeyCode::Enter),
+        "bs" => plain(KeyCode::Backspace),
+        "tab" => plain(KeyCode::Tab),
+        "del" => plain(KeyCode::Delete),
+        "up" => plain(KeyCode::Up),
+        "down" => plain(KeyCode::Down),
+        "left" => plain(KeyCode::Left),
+        "right" => plain(KeyCode::Right),
+        "home" => plain(KeyCode::Home),
+        "end" => plain(KeyCode::End),
+        "pageup" => plain(KeyCode::PageUp),
+        "pagedown" => plain(KeyCode::PageDown),
+        "space" => plain(KeyCode::Char(' ')),
+        "lt" => plain(KeyCode::Char('<')),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `keys` and collect what comes out for `mode`
+    fn run(map: &mut KeyMap, mode: MapMode, keys: &str) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        for key in parse_keys(keys) {
+            map.type_key(key);
+            while let Some(key) = map.next_key(Some(mode)) {
+                out.push(key);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_keys_names_and_literals() {
+        let keys = parse_keys("a<Esc><C-r><lt>x<nope>");
+        let codes: Vec<(KeyCode, KeyModifiers)> = keys.iter().map(|key| (key.code, key.modifiers)).collect();
+        assert_eq!(codes[..4], [
+            (KeyCode::Char('a'), KeyModifiers::NONE),
+            (KeyCode::Esc, KeyModifiers::NONE),
+            (KeyCode::Char('r'), KeyModifiers::CONTROL),
+            (KeyCode::Char('<'), KeyModifiers::NONE),
+        ]);
+        let typed: String = keys[4..].iter().map(|key| match key.code {
+            KeyCode::Char(ch) => ch,
+            _ => '?',
+        }).collect();
+        assert_eq!(typed, "x<nope>");
+    }
+
+    #[test]
+    fn test_mappings_expand() {
+        let mut map = KeyMap::new();
+        map.execute("nnoremap", "<C-s> :w<CR>").unwrap().unwrap();
+        map.execute("nmap", "Q dd").unwrap().unwrap();
+        map.execute("nmap", "dd x").unwrap().unwrap();
+        map.execute("nnoremap", "X dd").unwrap().unwrap();
+        assert_eq!(map.execute("imap", "Q"), Some(Ok("No mapping found".to_string())));
+
+        assert_eq!(run(&mut map, MapMode::Normal, "<C-s>"), parse_keys(":w<CR>"));
+        assert_eq!(run(&mut map, MapMode::Normal, "Q"), parse_keys("x"), ":nmap maps its keys again");
+        assert_eq!(run(&mut map, MapMode::Normal, "X"), parse_keys("dd"), ":noremap doesn't");
+        assert_eq!(run(&mut map, MapMode::Insert, "Q"), parse_keys("Q"), "other modes are untouched");
+
+        assert_eq!(map.execute("nunmap", "Q"), Some(Ok(String::new())));
+        assert_eq!(map.execute("nunmap", "Q"), Some(Err("No such mapping".to_string())));
+        assert_eq!(map.execute("set", "nu"), None);
+    }
+
+    #[test]
+    fn test_prefix_waits_for_the_rest() {
+        let mut map = KeyMap::new();
+        map.execute("inoremap", "jk <Esc>").unwrap().unwrap();
+
+        assert_eq!(run(&mut map, MapMode::Insert, "ajk"), parse_keys("a<Esc>"));
+        assert_eq!(run(&mut map, MapMode::Insert, "j"), []);
+        assert!(map.is_waiting());
+        assert_eq!(run(&mut map, MapMode::Insert, "x"), parse_keys("jx"));
+
+        // After a pause the j stands for itself
+        assert_eq!(run(&mut map, MapMode::Insert, "j"), []);
+        map.time_out();
+        assert_eq!(map.next_key(Some(MapMode::Insert)), parse_keys("j").pop());
+        assert!(!map.is_waiting());
+    }
+
+    #[test]
+    fn test_leader_and_longer_mappings() {
+        let mut map = KeyMap::new();
+        map.execute("nnoremap", "<leader>w :w<CR>").unwrap().unwrap();
+        assert_eq!(map.execute("let", "mapleader = \"\\<Space>\""), Some(Ok(String::new())));
+        map.execute("nnoremap", "<Leader>d dd").unwrap().unwrap();
+        map.execute("nnoremap", "<leader>dw dw").unwrap().unwrap();
+
+        // <leader> is what it was when the mapping was made
+        assert_eq!(run(&mut map, MapMode::Normal, "\\w"), parse_keys(":w<CR>"));
+        assert_eq!(run(&mut map, MapMode::Normal, "<Space>dw"), parse_keys("dw"));
+        assert_eq!(run(&mut map, MapMode::Normal, "<Space>d"), []);
+        assert_eq!(run(&mut map, MapMode::Normal, "j"), parse_keys("ddj"));
+        assert_eq!(map.execute("let", "mapleader"), Some(Ok("mapleader <Space>".to_string())));
+
+        // Removing the shorter mapping leaves the longer one
+        assert_eq!(map.execute("nunmap", "<leader>d"), Some(Ok(String::new())));
+        assert_eq!(run(&mut map, MapMode::Normal, "<Space>dx"), parse_keys(" dx"));
+        assert_eq!(map.execute("nmap", "<Space>"), Some(Ok("n  <leader>dw *dw".to_string())));
+
+        assert_eq!(map.execute("let", "mapleader = ','"), Some(Ok(String::new())));
+        assert_eq!(map.execute("let", "mapleader = ,"), Some(Err("Invalid expression: ,".to_string())));
+        assert_eq!(map.execute("let", "x = 1"), Some(Err("Undefined variable: x".to_string())));
+    }
+
+    #[test]
+    fn test_recursive_mapping_is_stopped() {
+        let mut map = KeyMap::new();
+        map.execute("nmap", "a b").unwrap().unwrap();
+        map.execute("nmap", "b a").unwrap().unwrap();
+        map.execute("nmap", "x xl").unwrap().unwrap();
+        map.execute("nmap", "j jj").unwrap().unwrap();
+
+        assert_eq!(run(&mut map, MapMode::Normal, "a"), []);
+        assert_eq!(map.take_error(), Some("Recursive mapping".to_string()));
+        assert_eq!(run(&mut map, MapMode::Normal, "x"), parse_keys("xl"), "a rhs starting with its lhs isn't mapped again");
+        assert_eq!(run(&mut map, MapMode::Normal, "j"), parse_keys("jj"), "nor is the lhs later in it");
+        assert_eq!(map.take_error(), None);
+    }
+}
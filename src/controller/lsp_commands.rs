@@ -0,0 +1,299 @@
+use crate::config::filetype;
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::{Diagnostic, Document};
+use crate::lsp::{LanguageServer, LspEvent, Position, PositionEncoding};
+use crate::view::ListPopup;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The language servers the editor runs: the command that starts the
+/// server for each filetype, the servers started so far, and what each
+/// open file's server was last sent
+#[derive(Default)]
+pub struct LspManager {
+    commands: Vec<(String, String)>,          // Filetype and the command serving it, from `lsp` lines and :lsp
+    servers: HashMap<String, LanguageServer>, // By filetype, started when a buffer of it is first synced
+    failed: Vec<String>,                      // Filetypes whose server couldn't be started, not tried again
+    synced: HashMap<PathBuf, SyncedFile>,     // By absolute path
+    pub hover: Option<ListPopup>,             // K's answer, shown until the next key
+}
+
+/// What a server knows of one file
+struct SyncedFile {
+    file_type: String,
+    version: u64,  // The protocol's version, one more for each change sent
+    revision: u64, // The buffer revision that was sent
+}
+
+impl LspManager {
+    /// Serve `file_type` with `command`, stopping the server running for it
+    pub fn set_server(&mut self, file_type: &str, command: &str) {
+        self.commands.retain(|(existing, _)| existing != file_type);
+        self.commands.push((file_type.to_string(), command.to_string()));
+        self.servers.remove(file_type);
+        self.failed.retain(|failed| failed != file_type);
+        self.synced.retain(|_, file| file.file_type != file_type);
+    }
+
+    pub fn servers(&self) -> &[(String, String)] {
+        &self.commands
+    }
+
+    /// True while any filetype has a server, so the event loop keeps
+    /// polling for what they say
+    pub fn is_active(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
+    fn command_for(&self, file_type: &str) -> Option<&str> {
+        self.commands.iter().find(|(existing, _)| existing == file_type).map(|(_, command)| command.as_str())
+    }
+
+    /// The running server for `doc`, with the path it knows the file by
+    fn server_for(&mut self, doc: &Document) -> Option<(&mut LanguageServer, PathBuf)> {
+        let path = absolute(doc.filename.as_deref()?);
+        let file = self.synced.get(&path)?;
+        let server = self.servers.get_mut(&file.file_type).filter(|server| server.is_running())?;
+        Some((server, path))
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The protocol's name for a filetype, where it differs
+fn language_id(file_type: &str) -> &str {
+    match file_type {
+        "sh" => "shellscript",
+        other => other,
+    }
+}
+
+/// Talking to language servers - keeping them up to date with the buffers,
+/// and gd and K, which ask them about the code
+pub struct LspCommands;
+
+impl LspCommands {
+    /// Tell the servers about buffers they haven't seen, text that changed
+    /// since they were last told, and buffers that were closed. Servers
+    /// are started here when a buffer first needs one, and hear of a file
+    /// loading in the background once all of it has been read.
+    pub fn sync(shared: &mut SharedEditorState) {
+        let lsp = &mut shared.lsp;
+        let mut open = Vec::new();
+        for doc in &shared.session_controller.buffers {
+            let (Some(path), Some(file_type)) = (doc.filename.as_deref(), filetype::detect_buffer(doc)) else {
+                continue;
+            };
+            if doc.is_loading() {
+                continue;
+            }
+            let Some(command) = lsp.command_for(file_type).map(str::to_string) else {
+                continue;
+            };
+            if !lsp.servers.contains_key(file_type) && !lsp.failed.iter().any(|failed| failed == file_type) {
+                match LanguageServer::spawn(&command, Path::new(".")) {
+                    Ok(server) => {
+                        lsp.servers.insert(file_type.to_string(), server);
+                    }
+                    Err(e) => {
+                        lsp.failed.push(file_type.to_string());
+                        shared.status_message = format!("Can't start language server {}: {}", command, e);
+                    }
+                }
+            }
+            let Some(server) = lsp.servers.get_mut(file_type).filter(|server| server.is_running()) else {
+                continue;
+            };
+            let path = absolute(path);
+            let revision = doc.revision();
+            match lsp.synced.get_mut(&path) {
+                None => {
+                    server.did_open(&path, language_id(file_type), 1, &doc.buffer_text());
+                    lsp.synced.insert(path.clone(), SyncedFile { file_type: file_type.to_string(), version: 1, revision });
+                }
+                Some(file) if file.revision != revision => {
+                    file.version += 1;
+                    file.revision = revision;
+                    server.did_change(&path, file.version, &doc.buffer_text());
+                }
+                Some(_) => {}
+            }
+            open.push(path);
+        }
+
+        let closed: Vec<PathBuf> = lsp.synced.keys().filter(|path| !open.contains(path)).cloned().collect();
+        for path in closed {
+            if let Some(file) = lsp.synced.remove(&path)
+                && let Some(server) = lsp.servers.get_mut(&file.file_type)
+            {
+                server.did_close(&path);
+            }
+        }
+    }
+
+    /// Act on everything the servers have said
+    pub fn poll(shared: &mut SharedEditorState) {
+        let mut events = Vec::new();
+        for (file_type, server) in &mut shared.lsp.servers {
+            events.extend(server.poll().into_iter().map(|event| (file_type.clone(), server.encoding(), event)));
+        }
+        for (file_type, encoding, event) in events {
+            Self::handle_event(shared, &file_type, encoding, event);
+        }
+    }
+
+    pub fn handle_event(shared: &mut SharedEditorState, file_type: &str, encoding: PositionEncoding, event: LspEvent) {
+        match event {
+            LspEvent::Diagnostics { path, diagnostics } => {
+                for doc in &mut shared.session_controller.buffers {
+                    if doc.filename.as_deref().map(absolute).as_ref() != Some(&path) {
+                        continue;
+                    }
+                    let column = |position: Position| {
+                        let line = doc.get_line(position.line).unwrap_or_default();
+                        (position.line, encoding.byte_column(&line, position.character))
+                    };
+                    let converted: Vec<Diagnostic> = diagnostics
+                        .iter()
+                        .map(|diagnostic| Diagnostic {
+                            start: column(diagnostic.start),
+                            end: column(diagnostic.end),
+                            severity: diagnostic.severity,
+                            message: diagnostic.message.clone(),
+                        })
+                        .collect();
+                    doc.diagnostics = converted;
+                }
+            }
+            LspEvent::Definition(locations) => {
+                let Some(location) = locations.first() else {
+                    shared.status_message = "No definition found".to_string();
+                    return;
+                };
+                // A file under the working directory by the name it was opened with
+                let cwd = std::env::current_dir().unwrap_or_default();
+                let path = location.path.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or_else(|_| location.path.clone());
+                if let Err(message) = Self::go(shared, &path, location.position, encoding) {
+                    shared.status_message = message;
+                } else if locations.len() > 1 {
+                    shared.status_message = format!("definition 1 of {}", locations.len());
+                }
+            }
+            LspEvent::Hover(text) => {
+                let doc = shared.session_controller.current_document();
+                let mut items: Vec<String> = Self::diagnostics_on_cursor_line(doc)
+                    .map(|diagnostic| format!("{} {}", diagnostic.severity.sign(), diagnostic.message))
+                    .collect();
+                items.extend(text.lines().map(str::to_string));
+                if items.is_empty() {
+                    shared.status_message = "No information under cursor".to_string();
+                } else {
+                    let selected = items.len();
+                    shared.lsp.hover = Some(ListPopup { title: " Hover".to_string(), items, selected });
+                }
+            }
+            LspEvent::Message(text) => shared.status_message = text,
+            LspEvent::Exited => {
+                shared.status_message = format!("Language server for {} exited", file_type);
+                shared.lsp.synced.retain(|_, file| file.file_type != file_type);
+            }
+        }
+    }
+
+    fn diagnostics_on_cursor_line(doc: &Document) -> impl Iterator<Item = &Diagnostic> {
+        let line = doc.cursor_line();
+        let mut on_line: Vec<&Diagnostic> = doc.diagnostics.iter().filter(move |diagnostic| diagnostic.starts_on(line)).collect();
+        on_line.sort_by_key(|diagnostic| diagnostic.severity);
+        on_line.into_iter()
+    }
+
+    /// Where the cursor is, as the buffer's server counts it
+    fn cursor_position(doc: &Document, encoding: PositionEncoding) -> Position {
+        let line = doc.get_line(doc.cursor_line()).unwrap_or_default();
+        Position { line: doc.cursor_line(), character: encoding.character(&line, doc.cursor_column()) }
+    }
+
+    /// gd - ask the buffer's server where the symbol under the cursor is
+    /// defined. Without a server, go to the first use of the word in the
+    /// buffer, which is usually where it is declared.
+    pub fn definition(shared: &mut SharedEditorState) -> Result<(), String> {
+        Self::sync(shared);
+        let doc = shared.session_controller.current_document();
+        if let Some((server, path)) = shared.lsp.server_for(doc) {
+            let position = Self::cursor_position(doc, server.encoding());
+            server.definition(&path, position);
+            return Ok(());
+        }
+
+        let word = doc.get_word_under_cursor().ok_or("No identifier under cursor")?;
+        let pattern = regex::Regex::new(&format!(r"\b{}\b", regex::escape(&word))).map_err(|e| e.to_string())?;
+        let found = (0..doc.line_count())
+            .find_map(|line| pattern.find(&doc.get_line(line)?).map(|found| (line, found.start())))
+            .ok_or_else(|| format!("Pattern not found: {}", word))?;
+        let (line, column) = (doc.cursor_line(), doc.cursor_column());
+        shared.mark_manager.add_to_jump_list(line, column, doc.filename.clone());
+        let _ = shared.session_controller.current_document_mut().set_cursor(found.0, found.1);
+        Ok(())
+    }
+
+    /// K - show what the buffer's server says about the symbol under the
+    /// cursor, after the diagnostics on the cursor line
+    pub fn hover(shared: &mut SharedEditorState) -> Result<(), String> {
+        Self::sync(shared);
+        let doc = shared.session_controller.current_document();
+        match shared.lsp.server_for(doc) {
+            Some((server, path)) => {
+                let position = Self::cursor_position(doc, server.encoding());
+                server.hover(&path, position);
+                Ok(())
+            }
+            None if Self::diagnostics_on_cursor_line(doc).next().is_some() => {
+                Self::handle_event(shared, "", PositionEncoding::Utf8, LspEvent::Hover(String::new()));
+                Ok(())
+            }
+            None => Err("No language server for this buffer".to_string()),
+        }
+    }
+
+    /// Go to `position` in `path`, leaving where the cursor was on the
+    /// jump list
+    fn go(shared: &mut SharedEditorState, path: &Path, position: Position, encoding: PositionEncoding) -> Result<(), String> {
+        let doc = shared.session_controller.current_document();
+        let (line, column, filename) = (doc.cursor_line(), doc.cursor_column(), doc.filename.clone());
+        if filename.as_deref() != Some(path) {
+            shared
+                .session_controller
+                .switch_to_file(&path.to_path_buf())
+                .map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+            shared.view.reset_scroll();
+        }
+        shared.mark_manager.add_to_jump_list(line, column, filename);
+
+        let doc = shared.session_controller.current_document_mut();
+        let line = position.line.min(doc.line_count().saturating_sub(1));
+        let text = doc.get_line(line).unwrap_or_default();
+        let _ = doc.set_cursor(line, encoding.byte_column(&text, position.character));
+        Ok(())
+    }
+
+    /// :lsp - list the servers, or with a filetype and a command, serve
+    /// that filetype with it
+    pub fn configure(shared: &mut SharedEditorState, args: &str) -> Result<(), String> {
+        let args = args.trim();
+        if args.is_empty() {
+            let servers = shared.lsp.servers();
+            shared.status_message = if servers.is_empty() {
+                "No language servers".to_string()
+            } else {
+                servers.iter().map(|(file_type, command)| format!("{}: {}", file_type, command)).collect::<Vec<_>>().join(" | ")
+            };
+            return Ok(());
+        }
+        let (file_type, command) = args.split_once(char::is_whitespace).ok_or("Usage: :lsp {filetype} {command}")?;
+        shared.lsp.set_server(file_type, command.trim());
+        Self::sync(shared);
+        Ok(())
+    }
+}
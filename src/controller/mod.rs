@@ -1,21 +1,44 @@
-/// Controller subsystem - Handles all user input and command execution
-/// 
-/// This module contains all the mode-specific controllers and command processors,
-/// providing a clean separation between user interaction logic and data/view layers.
+//! Controller subsystem - Handles all user input and command execution
+//!
+//! This module contains all the mode-specific controllers and command processors,
+//! providing a clean separation between user interaction logic and data/view layers.
 
 pub mod editor;
 pub mod normal;
 pub mod insert;
 pub mod visual;
 pub mod command;
+pub mod ex_commands;
 pub mod shared_state;
 pub mod help;
+pub mod help_commands;
 pub mod command_types;
 pub mod key_handler;
+pub mod key_map;
+pub mod operator;
 pub mod visual_mode;
 pub mod yank_paste;
 pub mod search_commands;
+pub mod substitute;
+pub mod buffer_picker;
+pub mod quickfix_commands;
+pub mod arg_commands;
+pub mod diff_commands;
+pub mod tag_commands;
+pub mod lsp_commands;
+pub mod directory_commands;
+pub mod disk_changes;
+pub mod unsaved_buffers;
+pub mod confirm_dialog;
+pub mod analysis_worker;
+pub mod timers;
+pub mod window_commands;
+pub mod undo_commands;
 pub mod session_controller;
+pub mod session_script;
+pub mod show_match;
+pub mod crash;
+pub mod event_source;
 
 // Re-export public interface
 pub use editor::EditorController;
@@ -1,6 +1,16 @@
 use crate::controller::shared_state::{ModeController, ModeTransition, SharedEditorState};
 use crate::controller::command_types::{Mode, Command};
-use crate::controller::key_handler::KeyHandler;
+use crate::controller::help_commands::HelpCommands;
+use crate::controller::diff_commands::DiffCommands;
+use crate::controller::tag_commands::TagCommands;
+use crate::controller::lsp_commands::LspCommands;
+use crate::controller::key_handler::{KeyHandler, PendingOperator};
+use crate::controller::operator::{Operators, Surround};
+use crate::controller::undo_commands::{UndoCommands, UndoDistance};
+use crate::controller::visual::VisualController;
+use crate::controller::yank_paste::{PasteType, YankPasteHandler};
+use crate::document_model::{Document, HistoryKind, RegisterType};
+use crate::view::DocumentViewModel;
 use crossterm::event::KeyEvent;
 
 // Helper macros to reduce boilerplate
@@ -27,6 +37,22 @@ pub struct NormalController {
     pub pending_key: Option<char>,
     pub number_prefix: Option<usize>,
     pub pending_register: Option<char>,
+    pending_operator: Option<PendingOperator>, // d, c, y... waiting for a motion
+    last_paste: Option<LastPaste>, // The paste Ctrl-P may still replace
+}
+
+/// A paste that was the last command, so Ctrl-P can swap in older text
+struct LastPaste {
+    paste_type: PasteType,
+    count: usize,
+    history_index: Option<usize>, // Which history entry was pasted
+    undo_state: usize,            // Undo state right after the paste
+}
+
+impl Default for NormalController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NormalController {
@@ -38,8 +64,23 @@ impl NormalController {
             pending_key: None,
             number_prefix: None,
             pending_register: None,
+            pending_operator: None,
+            last_paste: None,
         }
     }
+
+    /// The keys of a command typed in part, for 'showcmd'
+    pub fn pending_keys(&self) -> String {
+        KeyHandler::pending_keys(self.pending_key, self.number_prefix, self.pending_register, self.pending_operator.as_ref())
+    }
+
+    /// Forget a count, register or operator typed but not yet used
+    pub fn cancel_pending(&mut self) {
+        self.pending_key = None;
+        self.number_prefix = None;
+        self.pending_register = None;
+        self.pending_operator = None;
+    }
 }
 
 impl ModeController for NormalController {
@@ -51,10 +92,20 @@ impl ModeController for NormalController {
             &mut self.pending_key,
             &mut self.number_prefix,
             &mut self.pending_register,
+            &mut self.pending_operator,
         );
         
         
         if let Some(command) = command {
+            if command.changes_text() && HelpCommands::refuse_change(shared) {
+                return ModeTransition::Stay;
+            }
+
+            // Ctrl-P only cycles a paste while nothing else has happened since
+            if !matches!(command, Command::Paste(_, _, _) | Command::YankPop(_)) {
+                self.last_paste = None;
+            }
+
             // Take the number prefix (count) before executing the command
             let count = self.number_prefix.take().unwrap_or(1);
             
@@ -79,21 +130,17 @@ impl ModeController for NormalController {
                     return ModeTransition::ToMode(Mode::Insert);
                 }
                 Command::EnterInsertModeNewLine => {
-                    shared.session_controller.current_document_mut().open_line_below();
-                    let doc = shared.session_controller.current_document();
+                    let doc = shared.session_controller.current_document_mut();
                     let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                    shared.session_controller.current_document_mut()
-                        .undo_manager_mut()
-                        .start_group(cursor_pos);
+                    doc.undo_manager_mut().start_group(cursor_pos);
+                    doc.open_line_below();
                     return ModeTransition::ToMode(Mode::Insert);
                 }
                 Command::EnterInsertModeNewLineAbove => {
-                    shared.session_controller.current_document_mut().open_line_above();
-                    let doc = shared.session_controller.current_document();
+                    let doc = shared.session_controller.current_document_mut();
                     let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                    shared.session_controller.current_document_mut()
-                        .undo_manager_mut()
-                        .start_group(cursor_pos);
+                    doc.undo_manager_mut().start_group(cursor_pos);
+                    doc.open_line_above();
                     return ModeTransition::ToMode(Mode::Insert);
                 }
                 Command::EnterInsertModeLineEnd => {
@@ -175,10 +222,10 @@ impl NormalController {
             | Command::MoveToScreenMiddle
             | Command::MoveToScreenBottom
             | Command::MatchBracket
-            | Command::FindChar(_)
-            | Command::FindCharBackward(_)
-            | Command::FindCharBefore(_)
-            | Command::FindCharBeforeBackward(_)
+            | Command::FindChar(..)
+            | Command::FindCharBackward(..)
+            | Command::FindCharBefore(..)
+            | Command::FindCharBeforeBackward(..)
             | Command::RepeatFind
             | Command::RepeatFindReverse => {
                 self.execute_movement_command(command, count, shared);
@@ -186,258 +233,72 @@ impl NormalController {
 
             // Edit commands
             Command::DeleteChar
-            | Command::DeleteCharForward
-            | Command::DeleteCharBackward
-            | Command::DeleteLine
-            | Command::DeleteLines(_)
-            | Command::DeleteToEndOfLine
-            | Command::DeleteWord
-            | Command::DeleteBigWord
-            | Command::DeleteWordBackward
-            | Command::DeleteBigWordBackward
-            | Command::DeleteToEndOfWord
-            | Command::DeleteToEndOfBigWord
-            | Command::DeleteToStartOfLine
-            | Command::DeleteToFirstNonWhitespace
-            | Command::DeleteToEndOfFile
-            | Command::DeleteToStartOfFile
-            | Command::DeleteUntilChar(_)
-            | Command::DeleteUntilCharBackward(_)
-            | Command::DeleteFindChar(_)
-            | Command::DeleteFindCharBackward(_) => {
+            | Command::DeleteCharForward(_, _)
+            | Command::DeleteCharBackward(_, _) => {
                 self.execute_edit_command(command, shared);
             }
 
-            // Substitute commands that enter insert mode  
-            Command::SubstituteChar => {
-                shared.session_controller.current_document_mut().substitute_char();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::SubstituteLine => {
-                shared.session_controller.current_document_mut().substitute_line();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
+            // Operators over motions (dw, c$, gUe, >j)
+            Command::Operate(operator, motion, count, register) => {
+                return Operators::operate(shared, operator, motion, count, register);
             }
+            Command::Surround(motion, count, target) => Surround::add(shared, motion, count, target),
+            Command::ChangeSurround(target, replacement) => Surround::change(shared, target, replacement),
 
-            // Change commands (delete + enter insert mode)
-            Command::ChangeLine => {
-                let _deleted = shared.session_controller.current_document_mut().change_line();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeLines(count) => {
-                let mut deleted_lines = Vec::new();
-                for _ in 0..count {
-                    let is_empty = shared.session_controller.current_document().line_count() == 0;
-                    if !is_empty {
-                        deleted_lines.push(shared.session_controller.current_document_mut().change_line());
-                        // Adjust cursor if we're at the end
-                        let line_count = shared.session_controller.current_document().line_count();
-                        if shared.session_controller.current_document().cursor_line() >= line_count {
-                            let current_column = shared.session_controller.current_document().cursor_column();
-                            let _ = shared.session_controller.current_document_mut().set_cursor(line_count.saturating_sub(1), current_column);
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToEndOfLine => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_end_of_line();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeWord => {
-                let _deleted = shared.session_controller.current_document_mut().change_word_forward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeBigWord => {
-                let _deleted = shared.session_controller.current_document_mut().change_big_word_forward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeWordBackward => {
-                let _deleted = shared.session_controller.current_document_mut().change_word_backward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeBigWordBackward => {
-                let _deleted = shared.session_controller.current_document_mut().change_big_word_backward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToEndOfWord => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_end_of_word();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToEndOfBigWord => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_end_of_big_word();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToStartOfLine => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_start_of_line();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToFirstNonWhitespace => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_first_non_whitespace();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToEndOfFile => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_end_of_file();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeToStartOfFile => {
-                let _deleted = shared.session_controller.current_document_mut().change_to_start_of_file();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeUntilChar(target) => {
-                let _deleted = shared.session_controller.current_document_mut().change_until_char(target);
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeUntilCharBackward(target) => {
-                let _deleted = shared.session_controller.current_document_mut().change_until_char_backward(target);
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeFindChar(target) => {
-                let _deleted = shared.session_controller.current_document_mut().change_find_char(target);
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                return ModeTransition::ToMode(Mode::Insert);
-            }
-            Command::ChangeFindCharBackward(target) => {
-                let _deleted = shared.session_controller.current_document_mut().change_find_char_backward(target);
-                let doc = shared.session_controller.current_document();
+            // Substitute commands that enter insert mode  
+            Command::SubstituteChar(register, count) => {
+                let doc = doc_mut!(shared);
                 let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
+                doc.undo_manager_mut().start_group(cursor_pos);
+                let deleted = doc.substitute_chars(count);
+                Self::store_deleted(shared, register, deleted);
                 return ModeTransition::ToMode(Mode::Insert);
             }
-
             // Mark commands
             Command::SetMark(_)
             | Command::JumpToMark(_)
             | Command::JumpToMarkLine(_)
             | Command::JumpBackward
-            | Command::JumpForward => {
+            | Command::JumpForward
+            | Command::ChangeListOlder(_)
+            | Command::ChangeListNewer(_) => {
                 self.execute_mark_command(command, shared);
             }
 
             // Search commands
             Command::SearchNext
             | Command::SearchPrevious
-            | Command::SearchWordUnderCursor
-            | Command::SearchWordUnderCursorBackward => {
+            | Command::SearchWordUnderCursor(_)
+            | Command::SearchWordUnderCursorBackward(_) => {
                 self.execute_search_command(command, shared);
             }
 
             // Yank and paste commands
-            Command::Yank(yank_type, register) => {
-                shared.session_controller.yank_text(yank_type, register, &mut shared.register_manager, &mut shared.status_message);
-            }
-            Command::Paste(paste_type, register) => {
-                shared.session_controller.paste_text(paste_type, register, &mut shared.register_manager, &mut shared.status_message);
-            }
-
-            // Indentation commands
-            Command::IndentLine
-            | Command::IndentLines(_)
-            | Command::DedentLine
-            | Command::DedentLines(_) => {
-                self.execute_indentation_command(command, shared);
+            Command::Paste(paste_type, register, count) => {
+                let state_before = shared.session_controller.current_document().undo_manager().current_state();
+                let tab_stop = shared.view.get_tab_stop();
+                shared.session_controller.paste_text(paste_type.clone(), register, count, tab_stop, &mut shared.register_manager, &mut shared.status_message);
+                let undo_state = shared.session_controller.current_document().undo_manager().current_state();
+                self.last_paste = (undo_state != state_before).then(|| {
+                    let pasted = shared.register_manager.get_register_content(register).map(|data| data.content.clone());
+                    LastPaste {
+                        paste_type,
+                        count,
+                        history_index: pasted.and_then(|text| shared.register_manager.history_position(&text)),
+                        undo_state,
+                    }
+                });
             }
+            Command::YankPop(count) => self.yank_pop(count, shared),
 
             // Line operations
-            Command::JoinLines => {
-                self.execute_join_lines_command(shared);
+            Command::JoinLines(spaces) => {
+                self.execute_join_lines_command(count, spaces, shared);
             }
 
             // Case operations
-            Command::ToggleCase | Command::Lowercase | Command::Uppercase => {
-                self.execute_case_command(command, shared);
+            Command::ToggleCase => {
+                self.execute_toggle_case_command(shared);
             }
 
             // Undo/Redo commands
@@ -451,6 +312,113 @@ impl NormalController {
                 shared.status_message.clear();
             }
 
+            Command::ScrollLinesDown => Self::scroll_vertically(shared, count as isize),
+            Command::ScrollLinesUp => Self::scroll_vertically(shared, -(count as isize)),
+            Command::ScrollCursorTop | Command::ScrollCursorMiddle | Command::ScrollCursorBottom => {
+                let line = shared.session_controller.current_document().cursor_line();
+                let visible = shared.view.get_visible_lines_count().max(1);
+                let top = match command {
+                    Command::ScrollCursorTop => line,
+                    Command::ScrollCursorMiddle => line.saturating_sub(visible / 2),
+                    _ => (line + 1).saturating_sub(visible),
+                };
+                let lines = top as isize - shared.view.get_scroll_offset() as isize;
+                Self::scroll_vertically(shared, lines);
+            }
+            Command::ScrollRight(columns) => Self::scroll_horizontally(shared, columns as isize),
+            Command::ScrollLeft(columns) => Self::scroll_horizontally(shared, -(columns as isize)),
+            Command::ScrollHalfWidthRight | Command::ScrollHalfWidthLeft => {
+                let view_model = DocumentViewModel::new(shared.session_controller.current_document());
+                let half = (shared.view.text_columns(&view_model) / 2).max(1) as isize;
+                let columns = if matches!(command, Command::ScrollHalfWidthRight) { half } else { -half };
+                Self::scroll_horizontally(shared, columns);
+            }
+
+            Command::Window(key) => {
+                crate::controller::window_commands::WindowCommands::execute_key(shared, key);
+            }
+            Command::NextChange(count) | Command::PreviousChange(count) => {
+                let forward = matches!(command, Command::NextChange(_));
+                if let Err(message) = DiffCommands::jump(shared, count, forward) {
+                    shared.status_message = message;
+                }
+            }
+            Command::JumpToTag => {
+                if let Err(message) = TagCommands::jump_to_word(shared) {
+                    shared.status_message = message;
+                }
+            }
+            Command::PopTag => {
+                if let Err(message) = TagCommands::pop(shared, count) {
+                    shared.status_message = message;
+                }
+            }
+            Command::AlternateFile(buffer) => {
+                let switched = match buffer {
+                    Some(buffer) => shared.session_controller.switch_to_buffer(buffer),
+                    None => shared.session_controller.switch_to_alternate(),
+                };
+                match switched {
+                    Ok(message) => {
+                        shared.status_message = message;
+                        shared.view.reset_scroll();
+                        shared.cached_unmatched_brackets = None;
+                    }
+                    Err(message) => shared.status_message = message,
+                }
+            }
+            Command::GotoDefinition => {
+                if let Err(message) = LspCommands::definition(shared) {
+                    shared.status_message = message;
+                }
+            }
+            Command::Hover => {
+                if let Err(message) = LspCommands::hover(shared) {
+                    shared.status_message = message;
+                }
+            }
+            Command::DiffObtain | Command::DiffPut => {
+                if let Err(message) = DiffCommands::transfer(shared, matches!(command, Command::DiffObtain)) {
+                    shared.status_message = message;
+                }
+            }
+
+            Command::UndoTreeBackward(count) => {
+                UndoCommands::earlier(shared, UndoDistance::Changes(count));
+            }
+            Command::UndoTreeForward(count) => {
+                UndoCommands::later(shared, UndoDistance::Changes(count));
+            }
+
+            Command::RepeatCommandLine(count) => {
+                return crate::controller::command::CommandController::repeat_last_command(shared, count);
+            }
+
+            Command::ReselectVisual => {
+                let Some(selection) = shared.session_controller.current_document().last_selection.clone() else {
+                    shared.status_message = "No previous visual selection".to_string();
+                    return ModeTransition::Stay;
+                };
+                return VisualController::reselect(shared, selection);
+            }
+
+            Command::OpenHistory(prompt) => {
+                let entries = shared.history.entries(HistoryKind::of(prompt)).to_vec();
+                shared.session_controller.open_history_window(prompt, &entries);
+                shared.cached_unmatched_brackets = None;
+                shared.view.reset_scroll();
+                shared.status_message = "Enter runs the line under the cursor".to_string();
+            }
+
+            Command::RepeatSubstitute => {
+                crate::controller::command::CommandController::repeat_substitute(shared);
+            }
+
+            // Run by the editor's command controller, which can ask about unsaved buffers
+            Command::WriteQuit => shared.pending_ex_command = Some("xit".to_string()),
+            Command::Suspend => shared.pending_suspend = true,
+            Command::QuitWithoutWriting => shared.pending_ex_command = Some("quit!".to_string()),
+
             _ => {
                 shared.status_message = format!("Unhandled normal mode command: {:?}", command);
             }
@@ -496,42 +464,50 @@ impl NormalController {
             }
             Command::MovePageUp => repeat_command!(doc, move_page_up, count),
             Command::MovePageDown => repeat_command!(doc, move_page_down, count),
-            Command::MoveHalfPageUp => repeat_command!(doc, move_half_page_up, count),
-            Command::MoveHalfPageDown => repeat_command!(doc, move_half_page_down, count),
+            Command::MoveHalfPageUp | Command::MoveHalfPageDown => {
+                // A count scrolls that many lines rather than half the window
+                let lines = if count > 1 { count } else { (shared.view.get_visible_lines_count() / 2).max(1) };
+                let down = matches!(command, Command::MoveHalfPageDown);
+                Self::scroll_half_page(shared, if down { lines as isize } else { -(lines as isize) });
+            }
 
             // Line jumping
-            Command::MoveToLine(line) => doc.move_to_line(line),
+            Command::MoveToLine(line) => {
+                let current_doc = shared.session_controller.current_document();
+                shared.mark_manager.add_to_jump_list(current_doc.cursor_line(), current_doc.cursor_column(), current_doc.filename.clone());
+                shared.session_controller.current_document_mut().move_to_line(line);
+            }
 
             // Character search
-            Command::FindChar(c) => {
+            Command::FindChar(c, times) => {
                 self.last_find_char = Some(c);
                 self.last_find_forward = true;
                 self.last_find_before = false;
-                for _ in 0..count {
+                for _ in 0..times {
                     doc.find_char(c, true, false);
                 }
             }
-            Command::FindCharBackward(c) => {
+            Command::FindCharBackward(c, times) => {
                 self.last_find_char = Some(c);
                 self.last_find_forward = false;
                 self.last_find_before = false;
-                for _ in 0..count {
+                for _ in 0..times {
                     doc.find_char(c, false, false);
                 }
             }
-            Command::FindCharBefore(c) => {
+            Command::FindCharBefore(c, times) => {
                 self.last_find_char = Some(c);
                 self.last_find_forward = true;
                 self.last_find_before = true;
-                for _ in 0..count {
+                for _ in 0..times {
                     shared.session_controller.current_document_mut().find_char(c, true, true);
                 }
             }
-            Command::FindCharBeforeBackward(c) => {
+            Command::FindCharBeforeBackward(c, times) => {
                 self.last_find_char = Some(c);
                 self.last_find_forward = false;
                 self.last_find_before = true;
-                for _ in 0..count {
+                for _ in 0..times {
                     shared.session_controller.current_document_mut().find_char(c, false, true);
                 }
             }
@@ -552,7 +528,7 @@ impl NormalController {
 
             // Bracket matching
             Command::MatchBracket => {
-                if let Some((target_line, target_column)) = shared.session_controller.current_document().find_matching_bracket() {
+                if let Some((target_line, target_column)) = shared.session_controller.current_document().find_matching_bracket(usize::MAX) {
                     let _ = shared.session_controller.current_document_mut().set_cursor(target_line, target_column);
                     shared.status_message = "Bracket matched".to_string();
                 } else {
@@ -609,71 +585,109 @@ impl NormalController {
                     .undo_manager_mut()
                     .end_group(cursor_pos);
             }
-            Command::DeleteCharForward => {
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                shared.session_controller.current_document_mut().delete_char_forward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .end_group(cursor_pos);
-            }
-            Command::DeleteCharBackward => {
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .start_group(cursor_pos);
-                shared.session_controller.current_document_mut().delete_char_backward();
-                let doc = shared.session_controller.current_document();
-                let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                shared.session_controller.current_document_mut()
-                    .undo_manager_mut()
-                    .end_group(cursor_pos);
+            Command::DeleteCharForward(register, count) => {
+                Self::delete_chars(shared, register, count, Document::delete_chars_forward);
             }
-            Command::DeleteLine => {
-                shared.session_controller.current_document_mut().delete_line();
+            Command::DeleteCharBackward(register, count) => {
+                Self::delete_chars(shared, register, count, Document::delete_chars_backward);
             }
-            Command::DeleteLines(count) => {
-                for _ in 0..count {
-                    let line_count = shared.session_controller.current_document().line_count();
-                    if line_count > 1 {
-                        shared.session_controller.current_document_mut().delete_line();
-                        // Adjust cursor if we deleted the last line
-                        let new_line_count = shared.session_controller.current_document().line_count();
-                        if shared.session_controller.current_document().cursor_line() >= new_line_count {
-                            let current_column = shared.session_controller.current_document().cursor_column();
-                            let _ = shared.session_controller.current_document_mut().set_cursor(new_line_count.saturating_sub(1), current_column);
-                        }
-                    } else {
-                        break;
-                    }
-                }
-            }
-            Command::DeleteToEndOfLine => doc_mut!(shared).delete_to_end_of_line(),
-            Command::DeleteWord => doc_mut!(shared).delete_word_forward(),
-            Command::DeleteBigWord => doc_mut!(shared).delete_big_word_forward(),
-            Command::DeleteWordBackward => doc_mut!(shared).delete_word_backward(),
-            Command::DeleteBigWordBackward => doc_mut!(shared).delete_big_word_backward(),
-            Command::DeleteToEndOfWord => doc_mut!(shared).delete_to_end_of_word(),
-            Command::DeleteToEndOfBigWord => doc_mut!(shared).delete_to_end_of_big_word(),
-            Command::DeleteToStartOfLine => doc_mut!(shared).delete_to_start_of_line(),
-            Command::DeleteToFirstNonWhitespace => doc_mut!(shared).delete_to_first_non_whitespace(),
-            Command::DeleteToEndOfFile => doc_mut!(shared).delete_to_end_of_file(),
-            Command::DeleteToStartOfFile => doc_mut!(shared).delete_to_start_of_file(),
-            Command::DeleteUntilChar(target) => doc_mut!(shared).delete_until_char(target),
-            Command::DeleteUntilCharBackward(target) => doc_mut!(shared).delete_until_char_backward(target),
-            Command::DeleteFindChar(target) => doc_mut!(shared).delete_find_char(target),
-            Command::DeleteFindCharBackward(target) => doc_mut!(shared).delete_find_char_backward(target),
-
             _ => {} // Should not reach here
         }
     }
 
+    /// x and X: delete count characters as one undo step, keeping them for paste
+    fn delete_chars(
+        shared: &mut SharedEditorState,
+        register: Option<char>,
+        count: usize,
+        delete: fn(&mut Document, usize) -> String,
+    ) {
+        let doc = doc_mut!(shared);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let deleted = delete(doc, count);
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        Self::store_deleted(shared, register, deleted);
+    }
+
+    /// Put characterwise deleted text in the given register, or the unnamed
+    /// and small delete registers
+    fn store_deleted(shared: &mut SharedEditorState, register: Option<char>, deleted: String) {
+        if !deleted.is_empty() {
+            shared.register_manager.store_delete(register, deleted, RegisterType::Character);
+        }
+    }
+
+    /// zl/zh: scroll the view sideways, dragging the cursor along when it
+    /// would leave the screen
+    /// Ctrl-E and Ctrl-Y: scroll the view `lines` rows (negative: up),
+    /// moving the cursor only as far as it takes to stay on screen
+    fn scroll_vertically(shared: &mut SharedEditorState, lines: isize) {
+        let doc = shared.session_controller.current_document();
+        let (line, column, line_count) = (doc.cursor_line(), doc.cursor_column(), doc.line_count());
+        let top = shared.view.scroll_vertically(lines, line_count);
+        let bottom = top + shared.view.get_visible_lines_count().max(1) - 1;
+        let target = line.clamp(top, bottom.max(top));
+        if target != line {
+            let _ = doc_mut!(shared).set_cursor(target, column);
+        }
+    }
+
+    /// Ctrl-D and Ctrl-U: scroll the view and the cursor `lines` rows
+    /// together. Near the ends of the buffer, where the view can't scroll
+    /// that far, the cursor still moves the whole way.
+    fn scroll_half_page(shared: &mut SharedEditorState, lines: isize) {
+        let doc = shared.session_controller.current_document();
+        let (line, column, line_count) = (doc.cursor_line(), doc.cursor_column(), doc.line_count());
+        let last = line_count.saturating_sub(1);
+        if (lines > 0 && line == last) || (lines < 0 && line == 0) {
+            return;
+        }
+        // Scroll down no further than it takes to show the last line
+        let visible = shared.view.get_visible_lines_count().max(1);
+        let top = shared.view.get_scroll_offset();
+        let max_top = line_count.saturating_sub(visible).max(top);
+        let scroll = if lines > 0 { lines.min((max_top - top) as isize) } else { lines };
+        shared.view.scroll_vertically(scroll, line_count);
+        let _ = doc_mut!(shared).set_cursor(line.saturating_add_signed(lines).min(last), column);
+    }
+
+    fn scroll_horizontally(shared: &mut SharedEditorState, columns: isize) {
+        let doc = shared.session_controller.current_document();
+        let (line, column) = (doc.cursor_line(), doc.cursor_column());
+        let text = doc.get_line(line).unwrap_or_default();
+        let column = shared.view.scroll_horizontally(&text, column, columns, &DocumentViewModel::new(doc));
+        let _ = doc_mut!(shared).set_cursor(line, column);
+    }
+
+    /// Ctrl-P after p or P: replace the pasted text with the next older
+    /// entry of the yank history, wrapping round to the newest. The swap is
+    /// a single undo step, so u afterwards removes the paste altogether.
+    fn yank_pop(&mut self, count: usize, shared: &mut SharedEditorState) {
+        let tab_stop = shared.view.get_tab_stop();
+        let doc = doc_mut!(shared);
+        let Some(last) = self.last_paste.as_mut().filter(|last| last.undo_state == doc.undo_manager().current_state()) else {
+            self.last_paste = None;
+            shared.status_message = "Ctrl-P only works straight after p or P".to_string();
+            return;
+        };
+        let history_len = shared.register_manager.history_len();
+        let next = last.history_index.map_or(count - 1, |index| index + count) % history_len.max(1);
+        let Some(entry) = shared.register_manager.history_entry(next).cloned() else {
+            shared.status_message = "Yank history is empty".to_string();
+            return;
+        };
+
+        if let Some(group) = doc.undo_manager_mut().undo() {
+            group.apply_reverse_to_document(doc);
+        }
+        YankPasteHandler::paste_data(doc, &entry, &last.paste_type, last.count, tab_stop);
+        last.history_index = Some(next);
+        last.undo_state = doc.undo_manager().current_state();
+        shared.status_message = format!("Yank history {} of {history_len}", next + 1);
+    }
+
     fn execute_mark_command(&mut self, command: Command, shared: &mut SharedEditorState) {
         match command {
             Command::SetMark(mark_char) => {
@@ -693,9 +707,11 @@ impl NormalController {
                 let current_filename = doc.filename.clone();
                 shared.mark_manager.add_to_jump_list(doc.cursor_line(), doc.cursor_column(), current_filename);
                 
-                if mark_char.is_ascii_lowercase() {
-                    if let Some((line, column)) = shared.session_controller.current_document().get_local_mark(mark_char) {
+                if mark_char.is_ascii_lowercase() || mark_char == '.' {
+                    if let Some((line, column)) = shared.mark_position(mark_char) {
                         let _ = shared.session_controller.current_document_mut().set_cursor(line, column);
+                    } else if mark_char == '.' {
+                        shared.status_message = "Mark not set".to_string();
                     }
                 } else if let Some(mark) = shared.mark_manager.get_global_mark(mark_char).cloned() {
                     let _ = shared.session_controller.current_document_mut().set_cursor(mark.line, mark.column);
@@ -707,8 +723,8 @@ impl NormalController {
                 let current_filename = doc.filename.clone();
                 shared.mark_manager.add_to_jump_list(doc.cursor_line(), doc.cursor_column(), current_filename);
                 
-                if mark_char.is_ascii_lowercase() {
-                    if let Some((line, _)) = shared.session_controller.current_document().get_local_mark(mark_char) {
+                if mark_char.is_ascii_lowercase() || mark_char == '.' {
+                    if let Some((line, _)) = shared.mark_position(mark_char) {
                         let current_column = shared.session_controller.current_document().cursor_column();
                         let _ = shared.session_controller.current_document_mut().set_cursor(line, current_column);
                         shared.session_controller.current_document_mut().move_first_non_whitespace();
@@ -737,6 +753,17 @@ impl NormalController {
                     let _ = shared.session_controller.current_document_mut().set_cursor(entry.line, entry.column);
                 }
             }
+            Command::ChangeListOlder(count) | Command::ChangeListNewer(count) => {
+                let older = matches!(command, Command::ChangeListOlder(_));
+                let doc = shared.session_controller.current_document_mut();
+                match doc.undo_manager_mut().step_change_list(count, older) {
+                    Ok((line, column)) => {
+                        let line = line.min(doc.line_count().saturating_sub(1));
+                        let _ = doc.set_cursor(line, column.min(doc.get_line_length(line)));
+                    }
+                    Err(message) => shared.status_message = message,
+                }
+            }
             _ => {}
         }
     }
@@ -744,7 +771,7 @@ impl NormalController {
     fn execute_search_command(&mut self, command: Command, shared: &mut SharedEditorState) {
         // Add current position to jump list for major search movements
         match command {
-            Command::SearchWordUnderCursor | Command::SearchWordUnderCursorBackward => {
+            Command::SearchWordUnderCursor(_) | Command::SearchWordUnderCursorBackward(_) => {
                 let doc = shared.session_controller.current_document();
                 let current_filename = doc.filename.clone();
                 shared.mark_manager.add_to_jump_list(doc.cursor_line(), doc.cursor_column(), current_filename);
@@ -753,46 +780,57 @@ impl NormalController {
         }
         
         match command {
-            Command::SearchNext => crate::controller::search_commands::SearchCommands::next(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message),
-            Command::SearchPrevious => crate::controller::search_commands::SearchCommands::previous(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message),
-            Command::SearchWordUnderCursor => crate::controller::search_commands::SearchCommands::search_word_forward(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message),
-            Command::SearchWordUnderCursorBackward => crate::controller::search_commands::SearchCommands::search_word_backward(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message),
+            Command::SearchNext | Command::SearchPrevious => {
+                // Matches found before an edit may have moved
+                shared.search_state.sync(shared.session_controller.current_document());
+                let doc = shared.session_controller.current_document_mut();
+                if matches!(command, Command::SearchNext) {
+                    crate::controller::search_commands::SearchCommands::next(&mut shared.search_state, doc, &mut shared.status_message)
+                } else {
+                    crate::controller::search_commands::SearchCommands::previous(&mut shared.search_state, doc, &mut shared.status_message)
+                }
+            }
+            Command::SearchWordUnderCursor(whole) => crate::controller::search_commands::SearchCommands::search_word_forward(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message, whole),
+            Command::SearchWordUnderCursorBackward(whole) => crate::controller::search_commands::SearchCommands::search_word_backward(&mut shared.search_state, shared.session_controller.current_document_mut(), &mut shared.status_message, whole),
             _ => {}
         }
+        // * and # patterns go in the search history, as if typed after /
+        if matches!(command, Command::SearchWordUnderCursor(_) | Command::SearchWordUnderCursorBackward(_)) {
+            shared.history.add(HistoryKind::Search, &shared.search_state.pattern);
+        }
     }
 
-    fn execute_indentation_command(&mut self, command: Command, shared: &mut SharedEditorState) {
-        shared.session_controller.execute_indent_command(command, &mut shared.status_message);
-    }
-
-    fn execute_join_lines_command(&mut self, shared: &mut SharedEditorState) {
+    /// J and gJ: join `count` lines (at least two) as one undo step,
+    /// leaving the cursor where the first two met
+    fn execute_join_lines_command(&mut self, count: usize, spaces: bool, shared: &mut SharedEditorState) {
         let doc = doc_mut!(shared);
-        if doc.join_lines() {
-            shared.status_message = "Lines joined".to_string();
-        } else {
-            shared.status_message = "Cannot join: at last line".to_string();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let mut first_join = None;
+        for _ in 1..count.max(2) {
+            if !doc.join_lines(spaces) {
+                break;
+            }
+            first_join.get_or_insert(doc.cursor_column());
+        }
+        if let Some(column) = first_join {
+            let _ = doc.set_cursor(doc.cursor_line(), column);
         }
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        shared.status_message = if first_join.is_some() {
+            "Lines joined".to_string()
+        } else {
+            "Cannot join: at last line".to_string()
+        };
     }
 
-    fn execute_case_command(&mut self, command: Command, shared: &mut SharedEditorState) {
+    fn execute_toggle_case_command(&mut self, shared: &mut SharedEditorState) {
         let doc = doc_mut!(shared);
-        match command {
-            Command::ToggleCase => {
-                if doc.toggle_case_char() {
-                    shared.status_message = "Case toggled".to_string();
-                } else {
-                    shared.status_message = "No character to toggle".to_string();
-                }
-            }
-            Command::Lowercase => {
-                doc.lowercase_line();
-                shared.status_message = "Line converted to lowercase".to_string();
-            }
-            Command::Uppercase => {
-                doc.uppercase_line();
-                shared.status_message = "Line converted to uppercase".to_string();
-            }
-            _ => {}
+        if doc.toggle_case_char() {
+            shared.status_message = "Case toggled".to_string();
+        } else {
+            shared.status_message = "No character to toggle".to_string();
         }
     }
 
@@ -839,7 +877,11 @@ impl NormalController {
 mod tests {
     use super::*;
     use crate::controller::SessionController;
-    use crate::document_model::{Document, MarkManager, RegisterManager, SearchState};
+    use crate::controller::key_map::KeyMap;
+    use crate::controller::show_match::ShowMatch;
+    use crate::controller::timers::TimerQueue;
+    use crate::document_model::{Document, History, MarkManager, QuickfixList, RegisterManager, SearchState, TagStack};
+    use crate::controller::lsp_commands::LspManager;
     use crate::view::View;
     use crossterm::event::{KeyCode, KeyModifiers};
     
@@ -853,6 +895,21 @@ mod tests {
             status_message: String::new(),
             show_all_unmatched: false,
             cached_unmatched_brackets: None,
+            block_insert: None,
+            pending_selection: None,
+            last_substitute: None,
+            pending_shell_command: None,
+            pending_ex_command: None,
+            pending_normal: None,
+            pending_suspend: false,
+            key_map: KeyMap::new(),
+            timers: TimerQueue::new(),
+            history: History::new(),
+            show_match: ShowMatch::default(),
+            diff: None,
+            tag_stack: TagStack::default(),
+            lsp: LspManager::default(),
+            quickfix: QuickfixList::default(),
         }
     }
     
@@ -1113,10 +1170,241 @@ mod tests {
         assert_eq!(result, ModeTransition::Stay);
         assert!(controller.number_prefix.is_none()); // Verify prefix is cleared
         let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
-        // The actual behavior seems to delete only 1 char, let's verify
-        assert_eq!(content, "ello world");
+        assert_eq!(content, "lo world");
     }
     
+    #[test]
+    fn test_counted_char_deletes_fill_registers() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("hello world");
+
+        press_keys(&mut controller, &mut shared, "$h5X");
+        let doc = shared.session_controller.current_document();
+        assert_eq!(doc.get_line(0).unwrap(), "hellod");
+        assert_eq!(doc.cursor_column(), 5);
+        assert_eq!(shared.register_manager.get_register_content(None).unwrap().content, " worl");
+        assert_eq!(shared.register_manager.get_register_content(Some('-')).unwrap().content, " worl");
+
+        // A count past the end of the line stops there
+        press_keys(&mut controller, &mut shared, "0\"a9x");
+        assert_eq!(shared.session_controller.current_document().get_line(0).unwrap(), "");
+        assert_eq!(shared.register_manager.get_register_content(Some('a')).unwrap().content, "hellod");
+        assert_eq!(shared.register_manager.get_register_content(Some('-')).unwrap().content, " worl");
+
+        press_keys(&mut controller, &mut shared, "u");
+        assert_eq!(shared.session_controller.current_document().get_line(0).unwrap(), "hellod");
+    }
+
+    #[test]
+    fn test_counted_substitute() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("hello world");
+
+        controller.handle_key(key_event(KeyCode::Char('4')), &mut shared);
+        let result = controller.handle_key(key_event(KeyCode::Char('s')), &mut shared);
+
+        assert_eq!(result, ModeTransition::ToMode(Mode::Insert));
+        assert_eq!(shared.session_controller.current_document().get_line(0).unwrap(), "o world");
+        assert_eq!(shared.register_manager.get_register_content(None).unwrap().content, "hell");
+    }
+
+    fn press_keys(controller: &mut NormalController, shared: &mut SharedEditorState, keys: &str) {
+        for c in keys.chars() {
+            controller.handle_key(key_event(KeyCode::Char(c)), shared);
+        }
+    }
+
+    #[test]
+    fn test_count_between_operator_and_motion() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("one two three four");
+
+        press_keys(&mut controller, &mut shared, "d3w");
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "four");
+    }
+
+    #[test]
+    fn test_count_delete_lines() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\nc\nd\ne");
+
+        // 2dd removes two lines, d1j removes two more, leaving one
+        press_keys(&mut controller, &mut shared, "2ddd1j");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "e");
+
+        // Deleting more lines than exist clears the last line
+        press_keys(&mut controller, &mut shared, "5dd");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "");
+    }
+
+    #[test]
+    fn test_count_delete_lines_above() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\nc\nd");
+        shared.session_controller.current_document_mut().set_cursor(2, 0).unwrap();
+
+        press_keys(&mut controller, &mut shared, "d2k");
+
+        let doc = shared.session_controller.current_document();
+        assert_eq!(doc.cursor_line(), 0);
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "d");
+    }
+
+    #[test]
+    fn test_count_change_lines() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\nc\nd");
+
+        press_keys(&mut controller, &mut shared, "2c");
+        let result = controller.handle_key(key_event(KeyCode::Char('c')), &mut shared);
+
+        assert_eq!(result, ModeTransition::ToMode(Mode::Insert));
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "\nc\nd");
+    }
+
+    #[test]
+    fn test_count_yank_lines_and_paste() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\nc");
+
+        // y1j yanks two lines, 2p pastes them twice below the cursor
+        press_keys(&mut controller, &mut shared, "y1j2p");
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a\na\nb\na\nb\nb\nc");
+    }
+
+    #[test]
+    fn test_count_paste_characters() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("ab cd");
+
+        press_keys(&mut controller, &mut shared, "yw2P");
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "ab ab ab cd");
+    }
+
+    #[test]
+    fn test_count_goto_line() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("1\n2\n3\n4\n5\n6");
+
+        press_keys(&mut controller, &mut shared, "5G");
+        assert_eq!(shared.session_controller.current_document().cursor_line(), 4);
+
+        press_keys(&mut controller, &mut shared, "2gg");
+        assert_eq!(shared.session_controller.current_document().cursor_line(), 1);
+
+        // Out-of-range counts clamp to the last line
+        press_keys(&mut controller, &mut shared, "99G");
+        assert_eq!(shared.session_controller.current_document().cursor_line(), 5);
+    }
+
+    #[test]
+    fn test_zero_after_operator_and_digit_targets() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("abc 123 x3y3z");
+        shared.session_controller.current_document_mut().set_cursor(0, 4).unwrap();
+
+        // d0 deletes to line start rather than starting a count
+        press_keys(&mut controller, &mut shared, "d0");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "123 x3y3z");
+        assert!(controller.number_prefix.is_none());
+
+        // Digits are valid targets for f/t and their operator forms
+        press_keys(&mut controller, &mut shared, "f3");
+        assert_eq!(shared.session_controller.current_document().cursor_column(), 2);
+        press_keys(&mut controller, &mut shared, "d2t3");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "123z");
+    }
+
+    #[test]
+    fn test_count_find_char_is_used_up() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a-b-c-d-e");
+
+        // The count goes to the search, leaving none for x
+        press_keys(&mut controller, &mut shared, "3f-x");
+
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "a-b-cd-e");
+    }
+
+    #[test]
+    fn test_count_before_register() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("a\nb\nc");
+
+        press_keys(&mut controller, &mut shared, "2\"ayy");
+
+        let content = shared.register_manager.get_register_content(Some('a')).map(|r| r.content.clone());
+        assert_eq!(content.as_deref(), Some("a\nb"));
+    }
+
+    #[test]
+    fn test_every_operator_takes_every_motion() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("one two three\nfour five");
+
+        press_keys(&mut controller, &mut shared, "gUw");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "ONE two three\nfour five");
+
+        press_keys(&mut controller, &mut shared, "wg~e");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "ONE TWO three\nfour five");
+
+        // Counts on both sides multiply: 2gu2w lowercases four words
+        press_keys(&mut controller, &mut shared, "0g~~2gu2w");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "one two three\nfour five");
+
+        press_keys(&mut controller, &mut shared, ">j");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "    one two three\n    four five");
+        press_keys(&mut controller, &mut shared, "u");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "one two three\nfour five");
+    }
+
+    #[test]
+    fn test_word_operators_stay_on_their_line() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("one two\nthree");
+        shared.session_controller.current_document_mut().set_cursor(0, 4).unwrap();
+
+        // dw on the last word of a line leaves the line break alone
+        press_keys(&mut controller, &mut shared, "dw");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, "one \nthree");
+
+        // cw changes to the end of the word, keeping the space after it
+        press_keys(&mut controller, &mut shared, "0cw");
+        let content = shared.session_controller.current_document_mut().text_buffer_mut().get_text();
+        assert_eq!(content, " \nthree");
+    }
+
+    #[test]
+    fn test_cancelled_operator() {
+        let mut controller = NormalController::new();
+        let mut shared = create_test_shared_state_with_content("abc");
+
+        // A key that isn't a motion drops the operator; the next key is a command again
+        press_keys(&mut controller, &mut shared, "dzl");
+        let doc = shared.session_controller.current_document();
+        assert_eq!(doc.get_line(0).unwrap(), "abc");
+        assert_eq!(doc.cursor_column(), 1);
+    }
+
     #[test]
     fn test_goto_line_gg() {
         let mut controller = NormalController::new();
@@ -1282,8 +1570,9 @@ mod tests {
         let result = controller.handle_key(key_event, &mut shared);
         
         assert_eq!(result, ModeTransition::Stay);
-        // Half page down should move 10 lines (as defined in movement.rs)
-        assert_eq!(shared.session_controller.current_document().cursor_line(), 10);
+        // Half page down moves half the window's lines
+        let half = shared.view.get_visible_lines_count() / 2;
+        assert_eq!(shared.session_controller.current_document().cursor_line(), half);
         
         // Press Ctrl+D again
         controller.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL), &mut shared);
@@ -1307,8 +1596,9 @@ mod tests {
         let result = controller.handle_key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL), &mut shared);
         
         assert_eq!(result, ModeTransition::Stay);
-        // Half page up should move 10 lines up (as defined in movement.rs)
-        assert_eq!(shared.session_controller.current_document().cursor_line(), 9);
+        // Half page up moves half the window's lines up
+        let half = shared.view.get_visible_lines_count() / 2;
+        assert_eq!(shared.session_controller.current_document().cursor_line(), 19 - half);
         
         // Press Ctrl+U again
         controller.handle_key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL), &mut shared);
@@ -1331,8 +1621,9 @@ mod tests {
         let result = controller.handle_key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::ALT), &mut shared);
         
         assert_eq!(result, ModeTransition::Stay);
-        // Should move 10 lines down (same as Ctrl+D)
-        assert_eq!(shared.session_controller.current_document().cursor_line(), 10);
+        // Should move half the window down (same as Ctrl+D)
+        let half = shared.view.get_visible_lines_count() / 2;
+        assert_eq!(shared.session_controller.current_document().cursor_line(), half);
     }
     
     #[test]
@@ -0,0 +1,636 @@
+//! Operators and the motions they act over
+//!
+//! An operator command is an operator, a motion and a count (d3w, gUe, >G).
+//! The motion is turned into a span of text, characterwise or linewise as the
+//! motion dictates, and the operator works on that span, so every motion
+//! works with every operator. Visual mode hands its selection to the same
+//! operators as a span of its own.
+
+use crate::controller::command_types::Mode;
+use crate::controller::shared_state::{ModeTransition, SharedEditorState};
+use crate::controller::yank_paste::{PasteType, YankPasteHandler};
+use crate::document_model::text_buffer::{grapheme_at_display_column, grapheme_start, next_grapheme, prev_grapheme};
+use crate::document_model::selection::LINE_END;
+use crate::document_model::registers::RegisterData;
+use crate::document_model::{Document, RegisterType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,     // d
+    Change,     // c
+    Yank,       // y
+    Lowercase,  // gu
+    Uppercase,  // gU
+    ToggleCase, // g~
+    Indent,     // >
+    Dedent,     // <
+    Format,     // gq
+}
+
+impl Operator {
+    /// The keys that type the operator
+    pub fn keys(self) -> &'static str {
+        match self {
+            Operator::Delete => "d",
+            Operator::Change => "c",
+            Operator::Yank => "y",
+            Operator::Lowercase => "gu",
+            Operator::Uppercase => "gU",
+            Operator::ToggleCase => "g~",
+            Operator::Indent => ">",
+            Operator::Dedent => "<",
+            Operator::Format => "gq",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Line, // The operator doubled (dd, yy, gUU): count lines from the cursor
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    BigWordForward,
+    BigWordBackward,
+    BigWordEnd,
+    LineStart,
+    LineEnd,
+    FirstNonWhitespace,
+    DocumentStart,
+    DocumentEnd,
+    ToLine(usize), // 1-based line number (d5G, y2gg)
+    MatchBracket,
+    FindChar(char),
+    FindCharBackward(char),
+    UntilChar(char),
+    UntilCharBackward(char),
+    Mark(char),     // `a
+    MarkLine(char), // 'a
+}
+
+/// How an operator treats the text between the cursor and a motion's target
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reach {
+    Exclusive, // Up to the target
+    Inclusive, // Up to and including the character at the target
+    Linewise,  // Every line from the cursor line to the target line
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    Characters, // From start up to, not including, end
+    Lines,      // Lines start.0..=end.0
+    Block(usize), // Screen columns start.1..=end.1 of lines start.0..=end.0, laid out with this tab stop
+}
+
+/// The text an operator works on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub kind: SpanKind,
+}
+
+impl Motion {
+    /// Move the cursor as the motion does on its own. Marks are left to
+    /// the caller, which knows where they are.
+    pub fn move_cursor(self, doc: &mut Document, count: usize) {
+        match self {
+            Motion::Line | Motion::Mark(_) | Motion::MarkLine(_) => {}
+            Motion::Left => (0..count).for_each(|_| { doc.move_cursor_left(); }),
+            Motion::Right => (0..count).for_each(|_| { doc.move_cursor_right(); }),
+            Motion::Up => (0..count).for_each(|_| { doc.move_cursor_up(); }),
+            Motion::Down => (0..count).for_each(|_| { doc.move_cursor_down(); }),
+            Motion::WordForward => (0..count).for_each(|_| doc.move_word_forward()),
+            Motion::WordBackward => (0..count).for_each(|_| doc.move_word_backward()),
+            Motion::WordEnd => (0..count).for_each(|_| doc.move_word_end()),
+            Motion::BigWordForward => (0..count).for_each(|_| doc.move_big_word_forward()),
+            Motion::BigWordBackward => (0..count).for_each(|_| doc.move_big_word_backward()),
+            Motion::BigWordEnd => (0..count).for_each(|_| doc.move_big_word_end()),
+            Motion::LineStart => doc.move_line_start(),
+            Motion::LineEnd => {
+                // 3$ is the end of the line two below
+                (1..count).for_each(|_| { doc.move_cursor_down(); });
+                doc.move_line_end();
+            }
+            Motion::FirstNonWhitespace => doc.move_first_non_whitespace(),
+            Motion::DocumentStart => doc.move_document_start(),
+            Motion::DocumentEnd => doc.move_document_end(),
+            Motion::ToLine(line) => doc.move_to_line(line),
+            Motion::MatchBracket => {
+                if let Some((line, column)) = doc.find_matching_bracket(usize::MAX) {
+                    let _ = doc.set_cursor(line, column);
+                }
+            }
+            Motion::FindChar(c) => (0..count).for_each(|_| doc.find_char(c, true, false)),
+            Motion::FindCharBackward(c) => (0..count).for_each(|_| doc.find_char(c, false, false)),
+            Motion::UntilChar(c) => (0..count).for_each(|_| doc.find_char(c, true, true)),
+            Motion::UntilCharBackward(c) => (0..count).for_each(|_| doc.find_char(c, false, true)),
+        }
+    }
+
+    fn reach(self) -> Reach {
+        match self {
+            Motion::Line
+            | Motion::Up
+            | Motion::Down
+            | Motion::DocumentStart
+            | Motion::DocumentEnd
+            | Motion::ToLine(_)
+            | Motion::MarkLine(_) => Reach::Linewise,
+            Motion::WordEnd | Motion::BigWordEnd | Motion::LineEnd | Motion::MatchBracket | Motion::FindChar(_) => {
+                Reach::Inclusive
+            }
+            _ => Reach::Exclusive,
+        }
+    }
+
+    /// Where the motion ends for an operator, or None if it can't be made
+    /// (no such character on the line, an unset mark, no bracket for %)
+    fn target(self, shared: &mut SharedEditorState, count: usize) -> Option<(usize, usize)> {
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        match self {
+            Motion::Line => Some(((cursor.0 + count - 1).min(doc.line_count().saturating_sub(1)), cursor.1)),
+            // % needs a bracket to match, k and j a line to go to
+            Motion::MatchBracket => doc.find_matching_bracket(usize::MAX),
+            Motion::Up if cursor.0 == 0 => None,
+            Motion::Down if cursor.0 + 1 >= doc.line_count() => None,
+            // Unlike the cursor motions, these fail when the character isn't there
+            Motion::FindChar(c) | Motion::UntilChar(c) => {
+                doc.find_char_column(c, true, count).map(|column| (cursor.0, column))
+            }
+            Motion::FindCharBackward(c) => doc.find_char_column(c, false, count).map(|column| (cursor.0, column)),
+            Motion::UntilCharBackward(c) => {
+                doc.find_char_column(c, false, count).map(|column| (cursor.0, column + c.len_utf8()))
+            }
+            Motion::Mark(c) | Motion::MarkLine(c) => {
+                let position = shared.mark_position(c);
+                if position.is_none() {
+                    shared.status_message = "Mark not set".to_string();
+                }
+                position
+            }
+            _ => {
+                self.move_cursor(doc, count);
+                let target = (doc.cursor_line(), doc.cursor_column());
+                let _ = doc.set_cursor(cursor.0, cursor.1);
+                Some(target)
+            }
+        }
+    }
+
+    /// The span `operator` works on when given this motion
+    pub fn span(self, operator: Operator, shared: &mut SharedEditorState, count: usize) -> Option<Span> {
+        let doc = shared.session_controller.current_document();
+        let line = doc.get_line(doc.cursor_line()).unwrap_or_default();
+        let cursor = (doc.cursor_line(), grapheme_start(&line, doc.cursor_column()));
+        let big = matches!(self, Motion::BigWordForward);
+
+        // cw on a word changes to its end and leaves the space after it, as ce would
+        if operator == Operator::Change
+            && matches!(self, Motion::WordForward | Motion::BigWordForward)
+            && line[cursor.1..].starts_with(|c: char| !c.is_whitespace())
+        {
+            let end_motion = if big { Motion::BigWordEnd } else { Motion::WordEnd };
+            let end = if is_word_end(&line, cursor.1, big) {
+                if count == 1 { cursor } else { end_motion.target(shared, count - 1)? }
+            } else {
+                end_motion.target(shared, count)?
+            };
+            return Some(Self::inclusive_span(shared.session_controller.current_document(), cursor, end));
+        }
+
+        let target = self.target(shared, count)?;
+        let doc = shared.session_controller.current_document();
+        let (start, end) = if target < cursor { (target, cursor) } else { (cursor, target) };
+        Some(match self.reach() {
+            Reach::Linewise => Span { start: (start.0, 0), end: (end.0, 0), kind: SpanKind::Lines },
+            Reach::Inclusive => Self::inclusive_span(doc, start, end),
+            Reach::Exclusive if matches!(self, Motion::WordForward | Motion::BigWordForward) => {
+                Span { start, end: Self::word_forward_end(doc, cursor, end, big), kind: SpanKind::Characters }
+            }
+            Reach::Exclusive => Span { start, end, kind: SpanKind::Characters },
+        })
+    }
+
+    fn inclusive_span(doc: &Document, start: (usize, usize), end: (usize, usize)) -> Span {
+        let end_line = doc.get_line(end.0).unwrap_or_default();
+        Span { start, end: (end.0, next_grapheme(&end_line, end.1)), kind: SpanKind::Characters }
+    }
+
+    /// Where dw stops: a word at the end of a line is taken without the line
+    /// break after it, and the last word of the file is taken whole
+    fn word_forward_end(doc: &Document, cursor: (usize, usize), target: (usize, usize), big: bool) -> (usize, usize) {
+        let target_line = doc.get_line(target.0).unwrap_or_default();
+        let first_non_blank = target_line.find(|c: char| !c.is_whitespace()).unwrap_or(target_line.len());
+        if target.0 > cursor.0 && target.1 <= first_non_blank {
+            let previous = target.0 - 1;
+            return (previous, doc.get_line_length(previous));
+        }
+        // At the end of the file w stops on the last character rather than past it
+        let last_line = target.0 + 1 == doc.line_count();
+        let on_last_char = target.1 == prev_grapheme(&target_line, target_line.len());
+        if last_line && on_last_char && (target == cursor || !is_word_start(&target_line, target.1, big)) {
+            return (target.0, target_line.len());
+        }
+        target
+    }
+}
+
+impl Span {
+    /// Per line, the byte columns covered: (line, start, end)
+    fn pieces(&self, doc: &Document) -> Vec<(usize, usize, usize)> {
+        let last = self.end.0.min(doc.line_count().saturating_sub(1));
+        (self.start.0..=last)
+            .map(|line_num| {
+                let line = doc.get_line(line_num).unwrap_or_default();
+                let (from, to) = match self.kind {
+                    SpanKind::Lines => (0, line.len()),
+                    SpanKind::Characters => (
+                        if line_num == self.start.0 { grapheme_start(&line, self.start.1) } else { 0 },
+                        if line_num == self.end.0 { grapheme_start(&line, self.end.1) } else { line.len() },
+                    ),
+                    // Whole characters: a tab or wide character the block's
+                    // edge runs through is taken too
+                    SpanKind::Block(tab_stop) => {
+                        let (from, _) = grapheme_at_display_column(&line, self.start.1, tab_stop);
+                        let to = if self.end.1 == LINE_END {
+                            line.len()
+                        } else {
+                            let (last, _) = grapheme_at_display_column(&line, self.end.1, tab_stop);
+                            next_grapheme(&line, last)
+                        };
+                        (from, to.max(from))
+                    }
+                };
+                (line_num, from, to)
+            })
+            .collect()
+    }
+
+    pub fn text(&self, doc: &Document) -> String {
+        if self.kind == SpanKind::Characters {
+            return doc.get_text_range(self.start.0, self.start.1, self.end.0, self.end.1);
+        }
+        self.pieces(doc)
+            .into_iter()
+            .map(|(line_num, from, to)| doc.get_line(line_num).unwrap_or_default()[from..to].to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn register_type(&self) -> RegisterType {
+        match self.kind {
+            SpanKind::Characters => RegisterType::Character,
+            SpanKind::Lines => RegisterType::Line,
+            SpanKind::Block(_) => RegisterType::Block,
+        }
+    }
+
+    /// The byte column the span starts at on its first line
+    fn start_column(&self, doc: &Document) -> usize {
+        match self.kind {
+            SpanKind::Block(_) => self.pieces(doc).first().map_or(0, |&(_, from, _)| from),
+            SpanKind::Characters | SpanKind::Lines => self.start.1,
+        }
+    }
+
+    /// Replace the covered text of every line with `map` of it, recording undo
+    fn map_text(&self, doc: &mut Document, map: impl Fn(&str) -> String) {
+        for (line_num, from, to) in self.pieces(doc) {
+            let line = doc.get_line(line_num).unwrap_or_default();
+            let new_line = format!("{}{}{}", &line[..from], map(&line[from..to]), &line[to..]);
+            doc.replace_line_with_undo(line_num, &new_line);
+        }
+    }
+}
+
+/// Whether the character at `column` is the last of its word
+fn is_word_end(line: &str, column: usize, big: bool) -> bool {
+    let mut chars = line.get(column..).unwrap_or_default().chars();
+    match (chars.next(), chars.next()) {
+        (Some(current), Some(next)) => next.is_whitespace() || (!big && is_word_char(current) != is_word_char(next)),
+        _ => true,
+    }
+}
+
+/// Whether the character at `column` begins a word
+fn is_word_start(line: &str, column: usize, big: bool) -> bool {
+    let current = line.get(column..).and_then(|rest| rest.chars().next());
+    let previous = line.get(..column).and_then(|before| before.chars().next_back());
+    match (previous, current) {
+        (None, _) => true,
+        (Some(previous), Some(current)) => {
+            previous.is_whitespace() || (!big && is_word_char(previous) != is_word_char(current))
+        }
+        (Some(_), None) => false,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub struct Operators;
+
+impl Operators {
+    /// Run `operator` over `motion` from the cursor
+    pub fn operate(
+        shared: &mut SharedEditorState,
+        operator: Operator,
+        motion: Motion,
+        count: usize,
+        register: Option<char>,
+    ) -> ModeTransition {
+        match motion.span(operator, shared, count) {
+            Some(span) => Self::apply(shared, operator, span, register),
+            None => ModeTransition::Stay,
+        }
+    }
+
+    /// Run `operator` over `span`. A change leaves its undo group open so
+    /// the text typed next joins it.
+    pub fn apply(shared: &mut SharedEditorState, operator: Operator, span: Span, register: Option<char>) -> ModeTransition {
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+
+        if operator == Operator::Yank {
+            let text = span.text(doc);
+            // The cursor goes to the start of what was yanked
+            let column = if span.kind == SpanKind::Lines { cursor.1 } else { span.start_column(doc) };
+            let _ = doc.set_cursor(span.start.0, column);
+            YankPasteHandler::store_yank(text, span.register_type(), register, &mut shared.register_manager, &mut shared.status_message);
+            return ModeTransition::Stay;
+        }
+
+        if matches!(operator, Operator::Indent | Operator::Dedent) {
+            return Self::shift(shared, operator, span, 1);
+        }
+
+        doc.undo_manager_mut().start_group(cursor);
+        match operator {
+            Operator::Delete | Operator::Change => {
+                let deleted = match (span.kind, operator) {
+                    (SpanKind::Lines, Operator::Delete) => doc.delete_lines_with_undo(span.start.0, span.end.0),
+                    (SpanKind::Lines, _) => {
+                        let deleted = span.text(doc);
+                        doc.replace_lines_with_undo(span.start.0, span.end.0 - span.start.0 + 1, &[String::new()]);
+                        let _ = doc.set_cursor(span.start.0, 0);
+                        deleted
+                    }
+                    (SpanKind::Characters, _) => doc.delete_span_with_undo(span.start, span.end),
+                    (SpanKind::Block(_), _) => {
+                        let deleted = span.text(doc);
+                        span.map_text(doc, |_| String::new());
+                        let _ = doc.set_cursor(span.start.0, span.start_column(doc));
+                        deleted
+                    }
+                };
+                if span.kind == SpanKind::Lines || !deleted.is_empty() {
+                    shared.register_manager.store_delete(register, deleted, span.register_type());
+                }
+                if operator == Operator::Change {
+                    return ModeTransition::ToMode(Mode::Insert);
+                }
+                // Normal mode keeps the cursor on a character
+                let line = doc.get_line(doc.cursor_line()).unwrap_or_default();
+                if doc.cursor_column() >= line.len() {
+                    let _ = doc.set_cursor(doc.cursor_line(), prev_grapheme(&line, line.len()));
+                }
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                span.map_text(doc, |text| match operator {
+                    Operator::Lowercase => text.to_lowercase(),
+                    Operator::Uppercase => text.to_uppercase(),
+                    _ => text.chars().map(toggle_case).collect(),
+                });
+                let column = if span.kind == SpanKind::Lines { cursor.1 } else { span.start_column(doc) };
+                let _ = doc.set_cursor(span.start.0, column);
+            }
+            Operator::Format => {
+                let width = match doc.text_width {
+                    0 => crate::document_model::reflow::DEFAULT_WRAP_WIDTH,
+                    text_width => text_width,
+                };
+                let lines = doc.format_lines_with_undo(span.start.0, span.end.0, width, shared.view.get_tab_stop());
+                // The cursor goes to the last line formatted
+                let _ = doc.set_cursor(span.start.0 + lines.saturating_sub(1), 0);
+                doc.move_first_non_whitespace();
+                shared.status_message = if lines == 1 { "1 line formatted".to_string() } else { format!("{lines} lines formatted") };
+            }
+            Operator::Yank | Operator::Indent | Operator::Dedent => {}
+        }
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor);
+        ModeTransition::Stay
+    }
+
+    /// Shift the lines of `span` by `levels` steps of 'shiftwidth': right
+    /// for Indent, left for Dedent. The cursor goes to the first line's text.
+    pub fn shift(shared: &mut SharedEditorState, operator: Operator, span: Span, levels: usize) -> ModeTransition {
+        let tab_stop = shared.view.get_tab_stop();
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        let levels = if operator == Operator::Indent { levels as isize } else { -(levels as isize) };
+        doc.shift_lines_with_undo(span.start.0, span.end.0, levels, tab_stop);
+
+        let lines = span.end.0 - span.start.0 + 1;
+        let verb = if operator == Operator::Indent { "indented" } else { "dedented" };
+        shared.status_message = if lines == 1 { format!("Line {verb}") } else { format!("{lines} lines {verb}") };
+        let _ = doc.set_cursor(span.start.0, 0);
+        doc.move_first_non_whitespace();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor);
+        ModeTransition::Stay
+    }
+}
+
+/// What visual mode does with a selection besides the operators: r, J,
+/// and p / P, each as one undo step
+pub struct SelectionEdits;
+
+impl SelectionEdits {
+    /// r{char}: every character of the span becomes `c`
+    pub fn replace(shared: &mut SharedEditorState, span: Span, c: char) {
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        span.map_text(doc, |text| text.chars().map(|_| c).collect());
+        let column = if span.kind == SpanKind::Lines { 0 } else { span.start_column(doc) };
+        let _ = doc.set_cursor(span.start.0, column);
+        doc.undo_manager_mut().end_group((span.start.0, column));
+    }
+
+    /// J: join the span's lines, or its line and the next
+    pub fn join(shared: &mut SharedEditorState, span: Span) {
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        let _ = doc.set_cursor(span.start.0, 0);
+        for _ in 0..(span.end.0 - span.start.0).max(1) {
+            if !doc.join_lines(true) {
+                shared.status_message = "Cannot join: at last line".to_string();
+                break;
+            }
+        }
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor);
+    }
+
+    /// p and P: `count` copies of the register's text take the span's
+    /// place. Selected lines are replaced by lines whatever the register
+    /// holds; whole lines put over text go on lines of their own, and a
+    /// single line of text put over a block goes on every row. p leaves
+    /// the replaced text in the registers as a delete does; P (`keep`)
+    /// leaves them as they were.
+    pub fn put(shared: &mut SharedEditorState, span: Span, register: Option<char>, keep: bool, count: usize) {
+        let Some(data) = shared.register_manager.get_register_content(register).cloned() else {
+            shared.status_message = "Register empty".to_string();
+            return;
+        };
+        let tab_stop = shared.view.get_tab_stop();
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().begin_batch(cursor);
+        let replaced = span.text(doc);
+
+        if span.kind == SpanKind::Lines {
+            let lines = RegisterData::new(data.content, RegisterType::Line);
+            let emptied = span.end.0 - span.start.0 + 1 >= doc.line_count();
+            doc.delete_lines_with_undo(span.start.0, span.end.0);
+            if emptied {
+                // Nothing left but the empty line the delete leaves: put after it, then drop it
+                YankPasteHandler::paste_data(doc, &lines, &PasteType::After, count, tab_stop);
+                doc.delete_lines_with_undo(0, 0);
+            } else if span.start.0 < doc.line_count() {
+                let _ = doc.set_cursor(span.start.0, 0);
+                YankPasteHandler::paste_data(doc, &lines, &PasteType::Before, count, tab_stop);
+            } else {
+                let _ = doc.set_cursor(doc.line_count() - 1, 0);
+                YankPasteHandler::paste_data(doc, &lines, &PasteType::After, count, tab_stop);
+            }
+        } else {
+            let column = span.start_column(doc);
+            if matches!(span.kind, SpanKind::Block(_)) {
+                span.map_text(doc, |_| String::new());
+            } else {
+                doc.delete_span_with_undo(span.start, span.end);
+            }
+            let _ = doc.set_cursor(span.start.0, column);
+            match data.register_type {
+                RegisterType::Line if span.kind == SpanKind::Characters => {
+                    let line = doc.get_line(span.start.0).unwrap_or_default();
+                    let (before, after) = line.split_at(column.min(line.len()));
+                    let mut lines = vec![before.to_string()];
+                    for _ in 0..count {
+                        lines.extend(data.content.split('\n').map(str::to_string));
+                    }
+                    lines.push(after.to_string());
+                    doc.replace_lines_with_undo(span.start.0, 1, &lines);
+                    let _ = doc.set_cursor(span.start.0 + 1, 0);
+                    doc.move_first_non_whitespace();
+                }
+                RegisterType::Character if matches!(span.kind, SpanKind::Block(_)) && !data.content.contains('\n') => {
+                    let rows = vec![data.content.repeat(count); span.end.0 - span.start.0 + 1];
+                    let block = RegisterData::new(rows.join("\n"), RegisterType::Block);
+                    YankPasteHandler::paste_data(doc, &block, &PasteType::Before, 1, tab_stop);
+                }
+                _ => YankPasteHandler::paste_data(doc, &data, &PasteType::Before, count, tab_stop),
+            }
+        }
+
+        if !keep {
+            shared.register_manager.store_delete(None, replaced, span.register_type());
+        }
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_batch(cursor);
+    }
+}
+
+/// Surround commands: ys adds delimiters around a motion's text, cs and ds
+/// change and delete those around the cursor
+pub struct Surround;
+
+impl Surround {
+    /// ys{motion}{target}: put the delimiters for `target` around the text
+    /// the motion covers; for whole lines (yss), around their text without
+    /// the indent
+    pub fn add(shared: &mut SharedEditorState, motion: Motion, count: usize, target: char) {
+        let Some(span) = motion.span(Operator::Yank, shared, count) else {
+            return;
+        };
+        let doc = shared.session_controller.current_document_mut();
+        let (start, end) = match span.kind {
+            SpanKind::Lines => {
+                let first = doc.get_line(span.start.0).unwrap_or_default();
+                let last = doc.get_line(span.end.0).unwrap_or_default();
+                ((span.start.0, first.len() - first.trim_start().len()), (span.end.0, last.trim_end().len()))
+            }
+            _ => {
+                // Not the blanks a motion like w takes after the text
+                let line = doc.get_line(span.end.0).unwrap_or_default();
+                let from = if span.start.0 == span.end.0 { span.start.1 } else { 0 };
+                let end = from + line[from..span.end.1.min(line.len())].trim_end().len();
+                (span.start, (span.end.0, end))
+            }
+        };
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        doc.surround_with_undo(start, end, target);
+        doc.undo_manager_mut().end_group(start);
+    }
+
+    /// cs{target}{replacement} and ds{target} (no replacement)
+    pub fn change(shared: &mut SharedEditorState, target: char, replacement: Option<char>) {
+        let doc = shared.session_controller.current_document_mut();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor);
+        let changed = doc.change_surround_with_undo(target, replacement);
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor);
+        if !changed {
+            shared.status_message = format!("No {target} pair around the cursor");
+        }
+    }
+}
+
+fn toggle_case(c: char) -> String {
+    if c.is_uppercase() {
+        c.to_lowercase().collect()
+    } else {
+        c.to_uppercase().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_boundaries() {
+        assert!(is_word_end("abc def", 2, false));
+        assert!(!is_word_end("abc def", 1, false));
+        assert!(is_word_end("a.b", 0, false));
+        assert!(!is_word_end("a.b", 0, true));
+        assert!(is_word_start("abc def", 4, false));
+        assert!(!is_word_start("abc def", 5, false));
+        assert!(is_word_start("a.b", 1, false));
+        assert!(!is_word_start("a.b", 1, true));
+    }
+
+    #[test]
+    fn test_span_pieces_snap_to_characters() {
+        let doc = Document::from_string("éé\naé".to_string());
+        let span = Span { start: (0, 1), end: (1, 2), kind: SpanKind::Characters };
+        assert_eq!(span.pieces(&doc), [(0, 0, 4), (1, 0, 1)]);
+        let span = Span { start: (0, 1), end: (0, 3), kind: SpanKind::Characters };
+        assert_eq!(span.pieces(&doc), [(0, 0, 2)]);
+    }
+}
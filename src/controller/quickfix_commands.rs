@@ -0,0 +1,175 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::QuickfixList;
+use crate::view::ListPopup;
+use crossterm::event::KeyCode;
+use regex::Regex;
+
+/// :grep and moving through the quickfix list - :cnext, :cprevious, :cfirst,
+/// :clast and :cc
+pub struct QuickfixCommands;
+
+impl QuickfixCommands {
+    /// :grep [options] pattern [files] - run grep through the shell (so globs
+    /// expand) and fill the quickfix list from what it prints, then go to the
+    /// first match. Without files it searches the files of the open buffers,
+    /// as saved on disk.
+    pub fn grep(shared: &mut SharedEditorState, argument: &str) -> Result<(), String> {
+        let words = Self::shell_words(argument);
+        let ignore_case = words.iter().any(|word| word == "-i");
+        let mut operands = words.iter().filter(|word| !word.starts_with('-'));
+        let pattern = operands.next().ok_or("No pattern given")?;
+
+        let mut command = format!("grep -n -H {}", argument);
+        if operands.next().is_none() {
+            let files: Vec<String> = shared
+                .session_controller
+                .buffers
+                .iter()
+                .filter_map(|doc| doc.filename.as_ref())
+                .filter(|path| path.is_file())
+                .map(|path| Self::quote(&path.to_string_lossy()))
+                .collect();
+            if files.is_empty() {
+                return Err("No files to search".to_string());
+            }
+            command = format!("{} {}", command, files.join(" "));
+        }
+
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .output()
+            .map_err(|e| format!("Failed to run grep: {}", e))?;
+        // grep exits 1 when nothing matched and 2 on errors
+        if output.status.code() == Some(2) && output.stdout.is_empty() {
+            return Err(format!("grep: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        let case = if ignore_case { "(?i)" } else { "" };
+        let regex = Regex::new(&format!("{}{}", case, pattern)).ok();
+        shared.quickfix = QuickfixList::from_grep_output(&String::from_utf8_lossy(&output.stdout), regex.as_ref());
+        if shared.quickfix.is_empty() {
+            return Err(format!("No matches for {}", pattern));
+        }
+        Self::jump(shared, 0);
+        Ok(())
+    }
+
+    /// Go to entry `index`, opening its file if no buffer has it
+    pub fn jump(shared: &mut SharedEditorState, index: usize) {
+        let count = shared.quickfix.entries().len();
+        let Some(entry) = shared.quickfix.select(index).cloned() else {
+            shared.status_message = format!("No item {} in the quickfix list", index + 1);
+            return;
+        };
+
+        let doc = shared.session_controller.current_document();
+        let (line, column, filename) = (doc.cursor_line(), doc.cursor_column(), doc.filename.clone());
+        shared.mark_manager.add_to_jump_list(line, column, filename.clone());
+        if filename.as_ref() != Some(&entry.path) {
+            if let Err(e) = shared.session_controller.switch_to_file(&entry.path) {
+                shared.status_message = format!("Can't open {}: {}", entry.path.display(), e);
+                return;
+            }
+            shared.view.reset_scroll();
+        }
+
+        let doc = shared.session_controller.current_document_mut();
+        let line = entry.line.min(doc.line_count().saturating_sub(1));
+        let _ = doc.set_cursor(line, entry.column.min(doc.get_line_length(line)));
+        shared.status_message = format!("({} of {}): {}", index + 1, count, entry.text.trim());
+    }
+
+    /// :cnext / :cprevious - `count` entries on or back
+    pub fn step(shared: &mut SharedEditorState, count: usize, forward: bool) {
+        match shared.quickfix.step(count, forward) {
+            Ok(index) => Self::jump(shared, index),
+            Err(message) => shared.status_message = message,
+        }
+    }
+
+    /// Split a command line argument into words as the shell would, for
+    /// telling the pattern from the files
+    fn shell_words(argument: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut chars = argument.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                ' ' | '\t' => words.extend(word.take()),
+                '\'' | '"' => {
+                    let quoted = word.get_or_insert_with(String::new);
+                    for inner in chars.by_ref() {
+                        if inner == ch {
+                            break;
+                        }
+                        quoted.push(inner);
+                    }
+                }
+                '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+                _ => word.get_or_insert_with(String::new).push(ch),
+            }
+        }
+        words.extend(word);
+        words
+    }
+
+    /// Single-quote `text` for sh
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+/// The :copen quickfix list, moved through with j/k and picked with Enter
+pub struct QuickfixPicker {
+    selected: usize,
+}
+
+impl QuickfixPicker {
+    pub const PROMPT: &str = "copen (j/k to move, Enter to jump, Esc to close)";
+
+    /// Start on the current entry
+    pub fn new(list: &QuickfixList) -> Self {
+        Self { selected: list.current_index() }
+    }
+
+    /// Handle one key. Returns true while the list stays open.
+    pub fn handle_key(&mut self, code: KeyCode, shared: &mut SharedEditorState) -> bool {
+        let last = shared.quickfix.entries().len().saturating_sub(1);
+        match code {
+            KeyCode::Char('j') | KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Enter => {
+                QuickfixCommands::jump(shared, self.selected.min(last));
+                return false;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// The list as drawn over the bottom of the text area
+    pub fn popup(&self, list: &QuickfixList) -> ListPopup {
+        ListPopup {
+            title: format!(" Quickfix ({})", list.entries().len()),
+            items: list.rows(),
+            selected: self.selected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_words() {
+        assert_eq!(QuickfixCommands::shell_words("-i foo *.rs"), vec!["-i", "foo", "*.rs"]);
+        assert_eq!(QuickfixCommands::shell_words("'two words' \"a\\b\" c\\ d"), vec!["two words", "a\\b", "c d"]);
+        assert_eq!(QuickfixCommands::shell_words("''"), vec![""]);
+        assert_eq!(QuickfixCommands::quote("it's"), "'it'\\''s'");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::document_model::{Document, SearchState, SearchDirection, SearchError};
+use crate::document_model::selection::Selection;
 
 /// Search command handlers - controller logic for search operations
 pub struct SearchCommands;
@@ -6,22 +7,13 @@ pub struct SearchCommands;
 impl SearchCommands {
     /// Execute next search (n command)
     pub fn next(search_state: &mut SearchState, document: &mut Document, status_message: &mut String) {
+        document.stats.record_search();
         let line = document.cursor_line();
         let col = document.cursor_column();
 
         if let Some(search_match) = search_state.repeat_last_search(line, col) {
             document.move_cursor_to(search_match.line, search_match.start_col);
-            
-            // Show match count if available
-            if let (Some(current), matches) = (search_state.current_match_index(), search_state.matches.len()) {
-                if matches > 0 {
-                    *status_message = format!("/{}/  [{}/{}]", search_state.pattern, current + 1, matches);
-                } else {
-                    *status_message = format!("/{}/", search_state.pattern);
-                }
-            } else {
-                *status_message = format!("/{}/", search_state.pattern);
-            }
+            *status_message = Self::found_message(search_state, true);
         } else {
             *status_message = if search_state.pattern.is_empty() {
                 "No previous search".to_string()
@@ -33,22 +25,13 @@ impl SearchCommands {
 
     /// Execute previous search (N command)
     pub fn previous(search_state: &mut SearchState, document: &mut Document, status_message: &mut String) {
+        document.stats.record_search();
         let line = document.cursor_line();
         let col = document.cursor_column();
 
         if let Some(search_match) = search_state.repeat_last_search_reverse(line, col) {
             document.move_cursor_to(search_match.line, search_match.start_col);
-            
-            // Show match count if available
-            if let (Some(current), matches) = (search_state.current_match_index(), search_state.matches.len()) {
-                if matches > 0 {
-                    *status_message = format!("?{}?  [{}/{}]", search_state.pattern, current + 1, matches);
-                } else {
-                    *status_message = format!("?{}?", search_state.pattern);
-                }
-            } else {
-                *status_message = format!("?{}?", search_state.pattern);
-            }
+            *status_message = Self::found_message(search_state, false);
         } else {
             *status_message = if search_state.pattern.is_empty() {
                 "No previous search".to_string()
@@ -58,10 +41,27 @@ impl SearchCommands {
         }
     }
 
-    /// Search for word under cursor forward (* command)
-    pub fn search_word_forward(search_state: &mut SearchState, document: &mut Document, status_message: &mut String) {
+    /// "/pattern/  [3/17]" once n, N, / or ? has gone to a match
+    pub fn found_message(search_state: &SearchState, forward: bool) -> String {
+        let delimiter = if forward { '/' } else { '?' };
+        let pattern = format!("{delimiter}{}{delimiter}", search_state.pattern);
+        match search_state.match_count() {
+            Some(count) => format!("{}  {}", pattern, count),
+            None => pattern,
+        }
+    }
+
+    /// The pattern * and # search for: the word, or with `whole` (not g* or
+    /// g#) the word standing on its own
+    fn word_pattern(word: &str, whole: bool) -> String {
+        if whole { format!("\\<{word}\\>") } else { word.to_string() }
+    }
+
+    /// Search for word under cursor forward (* and g* commands)
+    pub fn search_word_forward(search_state: &mut SearchState, document: &mut Document, status_message: &mut String, whole: bool) {
         if let Some(word) = document.get_word_under_cursor() {
-            if let Err(e) = search_state.set_pattern(word.clone(), SearchDirection::Forward) {
+            search_state.region = None;
+            if let Err(e) = search_state.set_pattern(Self::word_pattern(&word, whole), SearchDirection::Forward) {
                 *status_message = format!("Search error: {}", e);
                 return;
             }
@@ -77,10 +77,11 @@ impl SearchCommands {
         }
     }
 
-    /// Search for word under cursor backward (# command)
-    pub fn search_word_backward(search_state: &mut SearchState, document: &mut Document, status_message: &mut String) {
+    /// Search for word under cursor backward (# and g# commands)
+    pub fn search_word_backward(search_state: &mut SearchState, document: &mut Document, status_message: &mut String, whole: bool) {
         if let Some(word) = document.get_word_under_cursor() {
-            if let Err(e) = search_state.set_pattern(word.clone(), SearchDirection::Backward) {
+            search_state.region = None;
+            if let Err(e) = search_state.set_pattern(Self::word_pattern(&word, whole), SearchDirection::Backward) {
                 *status_message = format!("Search error: {}", e);
                 return;
             }
@@ -90,19 +91,26 @@ impl SearchCommands {
                 return;
             }
             
+            // From the start of the word under the cursor, so # finds the one before it
+            let (line, column) = (document.cursor_line(), document.cursor_column());
+            if let Some(current) = search_state.matches.iter().find(|m| m.contains(line, column)) {
+                document.move_cursor_to(current.line, current.start_col);
+            }
             Self::previous(search_state, document, status_message);
         } else {
             *status_message = "No word under cursor".to_string();
         }
     }
 
-    /// Initialize search with pattern
+    /// Initialize search with pattern, limited to `region` when searching a selection
     pub fn start_search(
         search_state: &mut SearchState,
         document: &Document,
         pattern: String,
         direction: SearchDirection,
+        region: Option<Selection>,
     ) -> Result<(), SearchError> {
+        search_state.region = region;
         search_state.set_pattern(pattern, direction)?;
         search_state.search_document(document)?;
         Ok(())
@@ -1,8 +1,31 @@
-use crate::document_model::Document;
+use crate::config::filetype::FileTypeRule;
+use crate::document_model::{ArgList, Document, LockChoice, LockConflict};
+use crate::document_model::history::HistoryWindow;
+use crate::document_model::undo::DEFAULT_UNDO_LEVELS;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Seconds without edits before 'autosave' writes a buffer, when set
+/// without a value
+pub const DEFAULT_AUTOSAVE_SECONDS: u64 = 30;
 
 pub struct SessionController {
     pub buffers: Vec<Document>,
     pub current_buffer: usize,
+    pub autoread: bool, // 'autoread': reload unmodified buffers whose file changed on disk
+    pub confirm: bool,  // 'confirm': ask instead of failing when :q, :e or :w would lose work
+    pub undo_levels: usize, // 'undolevels': changes each buffer keeps for undo
+    pub file_type_rules: Vec<FileTypeRule>, // `autocmd FileType` lines from .virusrc
+    pub autosave: Option<Duration>, // 'autosave': write modified buffers after this long without edits
+    pub args: ArgList,              // The files from the command line, for :next and :prev
+    pub alternate: Option<PathBuf>, // The file the active window edited before this one, for # and Ctrl-^
+    last_file: Option<PathBuf>,     // The current buffer's file when last looked at
+}
+
+impl Default for SessionController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SessionController {
@@ -10,30 +33,166 @@ impl SessionController {
         Self {
             buffers: vec![Document::new()],
             current_buffer: 0,
+            autoread: false,
+            confirm: false,
+            undo_levels: DEFAULT_UNDO_LEVELS,
+            file_type_rules: Vec::new(),
+            autosave: None,
+            args: ArgList::default(),
+            alternate: None,
+            last_file: None,
+        }
+    }
+
+    /// Save every modified buffer, carrying on past ones that fail: a result
+    /// per buffer, with the error for each that wasn't written
+    pub fn write_all(&mut self) -> Vec<(usize, Result<(), String>)> {
+        let unsaved: Vec<usize> = (0..self.buffers.len()).filter(|&index| self.buffers[index].is_modified()).collect();
+        unsaved.into_iter().map(|index| (index, self.write_buffer(index))).collect()
+    }
+
+    fn write_buffer(&mut self, index: usize) -> Result<(), String> {
+        let name = self.buffer_display_name(index).to_string();
+        let doc = &mut self.buffers[index];
+        if doc.filename.is_none() {
+            Err(format!("No file name for buffer {}", index + 1))
+        } else {
+            doc.save().map(|_| ()).map_err(|e| format!("Error saving \"{}\": {}", name, e))
+        }
+    }
+
+    /// Note when each buffer was last edited, for 'autosave', and drop
+    /// the undo states past 'undolevels'
+    pub fn track_changes(&mut self, now: Instant) {
+        for doc in &mut self.buffers {
+            doc.track_changes(now);
+            doc.undo_manager_mut().limit(self.undo_levels);
+        }
+    }
+
+    /// How long until 'autosave' writes the next buffer; None when it is
+    /// off or no buffer has edits to write
+    pub fn autosave_wait(&self, now: Instant) -> Option<Duration> {
+        let delay = self.autosave?;
+        self.buffers.iter().filter_map(|doc| doc.autosave_wait(now, delay)).min()
+    }
+
+    /// With 'autosave' on, write the buffers whose last edit is at least the
+    /// autosave delay old, or with `all` every buffer with edits to write
+    /// (the editor is losing focus). A buffer that fails isn't tried again
+    /// until its next edit. Returns the display name of each buffer written,
+    /// or the error.
+    pub fn autosave(&mut self, now: Instant, all: bool) -> Vec<Result<String, String>> {
+        let Some(delay) = self.autosave else {
+            return Vec::new();
+        };
+        let due: Vec<usize> = (0..self.buffers.len())
+            .filter(|&index| {
+                self.buffers[index]
+                    .autosave_wait(now, delay)
+                    .is_some_and(|wait| all || wait.is_zero())
+            })
+            .collect();
+        due.into_iter().map(|index| self.autosave_write(index)).collect()
+    }
+
+    /// With 'autosave' on, write buffer `index` if it has edits to write,
+    /// however recent (the editor is leaving the buffer)
+    pub fn autosave_buffer(&mut self, index: usize, now: Instant) -> Option<Result<String, String>> {
+        let delay = self.autosave?;
+        self.buffers.get(index)?.autosave_wait(now, delay)?;
+        Some(self.autosave_write(index))
+    }
+
+    fn autosave_write(&mut self, index: usize) -> Result<String, String> {
+        let result = self.write_buffer(index).map(|()| self.buffer_display_name(index).to_string());
+        if result.is_err() {
+            self.buffers[index].forget_changes();
         }
+        result
     }
 
-    pub fn new_with_files(filenames: Vec<std::path::PathBuf>) -> Result<Self, std::io::Error> {
+    /// Bring the swap files of modified buffers up to date; `force` writes
+    /// every pending edit (the editor does this when keys stop coming)
+    pub fn sync_swap_files(&mut self, force: bool) {
+        for doc in &mut self.buffers {
+            doc.sync_swap_file(force);
+        }
+    }
+
+    /// True while any buffer's file is still being read in the background
+    pub fn is_loading(&self) -> bool {
+        self.buffers.iter().any(Document::is_loading)
+    }
+
+    /// Add what has been read of files loading in the background to their
+    /// buffers. Returns the status line message for a load that ended.
+    pub fn poll_loading(&mut self) -> Option<String> {
+        let mut message = None;
+        for index in 0..self.buffers.len() {
+            let ended = match self.buffers[index].poll_loading(false) {
+                None => continue,
+                Some(Ok(())) => self.buffers[index].load_warning().unwrap_or_else(|| {
+                    format!("\"{}\" {} lines", self.buffer_display_name(index), self.buffers[index].line_count())
+                }),
+                Some(Err(e)) => format!("Error reading \"{}\": {}; opened read-only", self.buffer_display_name(index), e),
+            };
+            message = Some(ended);
+        }
+        message
+    }
+
+    /// Open files, asking `resolve` what to do with any file another editor already holds
+    pub fn new_with_files<F>(
+        filenames: Vec<std::path::PathBuf>,
+        mut resolve: F,
+    ) -> Result<Self, std::io::Error>
+    where
+        F: FnMut(&std::path::Path, &LockConflict) -> LockChoice,
+    {
         if filenames.is_empty() {
             return Ok(Self::new());
         }
 
+        let args = ArgList::new(filenames.clone());
         let mut buffers = Vec::new();
         for filename in filenames {
-            match Document::from_file(filename.clone()) {
-                Ok(doc) => buffers.push(doc),
+            let mut doc = match Document::from_file(filename.clone()) {
+                Ok(doc) => doc,
                 Err(_) => {
                     // Create new file if it doesn't exist
                     let mut new_doc = Document::new();
-                    new_doc.filename = Some(filename);
-                    buffers.push(new_doc);
+                    new_doc.filename = Some(filename.clone());
+                    new_doc
+                }
+            };
+            if let Err(conflict) = doc.acquire_lock() {
+                match resolve(&filename, &conflict) {
+                    LockChoice::ReadOnly => doc.read_only = true,
+                    LockChoice::Proceed => doc.force_lock(),
+                    LockChoice::Recover => doc.recover_from_swap()?,
+                    LockChoice::Abort => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::AlreadyExists,
+                            format!("\"{}\": {}", filename.display(), conflict),
+                        ));
+                    }
                 }
             }
+            buffers.push(doc);
         }
 
         Ok(Self {
             buffers,
             current_buffer: 0,
+            autoread: false,
+            confirm: false,
+            undo_levels: DEFAULT_UNDO_LEVELS,
+            file_type_rules: Vec::new(),
+            autosave: None,
+            args,
+            alternate: None,
+            last_file: None,
         })
     }
 
@@ -46,7 +205,15 @@ impl SessionController {
     }
 
     pub fn get_display_filename(&self) -> &str {
-        self.current_document()
+        self.buffer_display_name(self.current_buffer)
+    }
+
+    /// File name shown for a buffer ("[No Name]" if it has none)
+    pub fn buffer_display_name(&self, index: usize) -> &str {
+        if self.buffers[index].help {
+            return "[Help]";
+        }
+        self.buffers[index]
             .filename
             .as_ref()
             .and_then(|p| p.file_name())
@@ -54,21 +221,120 @@ impl SessionController {
             .unwrap_or("[No Name]")
     }
 
+    /// virus - : the text piped in, as an unnamed buffer ahead of any files
+    /// named with it. It counts as modified, so quitting doesn't lose it.
+    pub fn open_stdin(&mut self, bytes: &[u8]) {
+        let mut doc = Document::from_bytes(bytes);
+        doc.modified = true;
+        FileTypeRule::apply_all(&self.file_type_rules, &mut doc);
+        // It takes the place of the empty buffer there is when no files are named
+        if let [only] = self.buffers.as_slice()
+            && only.filename.is_none()
+            && !only.is_modified()
+        {
+            self.buffers.clear();
+        }
+        self.buffers.insert(0, doc);
+        self.current_buffer = 0;
+    }
+
+    /// :e file - switch to the file's buffer if it is open, otherwise load it
+    /// into a new buffer
     pub fn open_file(&mut self, filename: &str) -> String {
         let path = std::path::PathBuf::from(filename);
-        match Document::from_file(path.clone()) {
-            Ok(doc) => {
-                self.buffers.push(doc);
-                self.current_buffer = self.buffers.len() - 1;
-                format!("\"{filename}\" opened")
-            }
+        if let Some(index) = self.buffer_with_file(&path) {
+            self.current_buffer = index;
+            return format!("\"{filename}\"");
+        }
+        let (mut doc, message) = match Document::from_file(path.clone()) {
+            Ok(doc) => (doc, format!("\"{filename}\" opened")),
             Err(_) => {
                 // Create new file if it doesn't exist
                 let mut new_doc = Document::new();
                 new_doc.filename = Some(path);
-                self.buffers.push(new_doc);
-                self.current_buffer = self.buffers.len() - 1;
-                format!("\"{filename}\" [New File]")
+                (new_doc, format!("\"{filename}\" [New File]"))
+            }
+        };
+        FileTypeRule::apply_all(&self.file_type_rules, &mut doc);
+        self.buffers.push(doc);
+        self.current_buffer = self.buffers.len() - 1;
+        match Self::lock_or_read_only(self.current_document_mut()) {
+            Some(warning) => format!("{message} | {warning}"),
+            None => message,
+        }
+    }
+
+    /// Open `path` in place of the current buffer, as when browsing out of a
+    /// directory listing. A file already open elsewhere is switched to instead.
+    pub fn replace_current(&mut self, path: std::path::PathBuf) -> String {
+        if let Some(index) = self.buffers.iter().position(|doc| doc.filename.as_ref() == Some(&path)) {
+            self.current_buffer = index;
+            return format!("\"{}\"", path.display());
+        }
+
+        let mut doc = match Document::from_file(path.clone()) {
+            Ok(doc) => doc,
+            Err(_) => {
+                let mut new_doc = Document::new();
+                new_doc.filename = Some(path.clone());
+                new_doc
+            }
+        };
+        let warning = Self::lock_or_read_only(&mut doc);
+        FileTypeRule::apply_all(&self.file_type_rules, &mut doc);
+        let message = if doc.directory.is_some() {
+            format!("\"{}\" {} entries", path.display(), doc.line_count() - 1)
+        } else {
+            format!("\"{}\"", path.display())
+        };
+        self.buffers[self.current_buffer] = doc;
+        match warning {
+            Some(warning) => format!("{message} | {warning}"),
+            None => message,
+        }
+    }
+
+    /// Buffer holding `path`, comparing resolved paths so `a.txt` matches `./a.txt`
+    fn buffer_with_file(&self, path: &std::path::Path) -> Option<usize> {
+        let resolved = std::fs::canonicalize(path).ok();
+        self.buffers.iter().position(|doc| match &doc.filename {
+            Some(filename) if filename == path => true,
+            Some(filename) => resolved.is_some() && std::fs::canonicalize(filename).ok() == resolved,
+            None => false,
+        })
+    }
+
+    /// True for an unnamed, unmodified, empty buffer like the one the editor starts with
+    pub fn is_empty_scratch(&self, index: usize) -> bool {
+        self.buffers.get(index).is_some_and(|doc| {
+            doc.filename.is_none() && !doc.is_modified() && doc.line_count() == 1 && doc.get_line_length(0) == 0
+        })
+    }
+
+    /// Drop a buffer other than the current one, keeping `current_buffer` on the same document
+    pub fn remove_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        self.buffers.remove(index);
+        if self.current_buffer > index {
+            self.current_buffer -= 1;
+        }
+    }
+
+    /// Take the edit lock on a freshly opened document. If another editor holds
+    /// the file, open it read-only and return a warning for the status line;
+    /// a binary file (already read-only) gets one too.
+    fn lock_or_read_only(doc: &mut Document) -> Option<String> {
+        match doc.acquire_lock() {
+            Ok(()) => doc.load_warning(),
+            Err(conflict @ LockConflict::Recoverable { .. }) => {
+                doc.read_only = true;
+                Some(format!("W: {conflict}; opened read-only (:recover to restore it, :w! to write anyway)"))
+            }
+            Err(conflict) => {
+                doc.read_only = true;
+                Some(format!("W: {conflict}; opened read-only (:w! to write anyway)"))
             }
         }
     }
@@ -80,22 +346,28 @@ impl SessionController {
 
         let mut opened_files = Vec::new();
         let mut new_files = Vec::new();
+        let mut read_only_files = Vec::new();
 
         for filename in filenames {
             let path = std::path::PathBuf::from(filename);
-            match Document::from_file(path.clone()) {
+            let mut doc = match Document::from_file(path.clone()) {
                 Ok(doc) => {
-                    self.buffers.push(doc);
                     opened_files.push(filename);
+                    doc
                 }
                 Err(_) => {
                     // Create new file if it doesn't exist
                     let mut new_doc = Document::new();
                     new_doc.filename = Some(path);
-                    self.buffers.push(new_doc);
                     new_files.push(filename);
+                    new_doc
                 }
+            };
+            if Self::lock_or_read_only(&mut doc).is_some() {
+                read_only_files.push(filename);
             }
+            FileTypeRule::apply_all(&self.file_type_rules, &mut doc);
+            self.buffers.push(doc);
         }
 
         // Switch to the first newly opened buffer
@@ -114,13 +386,19 @@ impl SessionController {
             }
             message.push_str(&format!("New files: {}", new_files.join(", ")));
         }
+        if !read_only_files.is_empty() {
+            message.push_str(&format!(
+                " | W: edited elsewhere, opened read-only: {}",
+                read_only_files.join(", ")
+            ));
+        }
         message
     }
 
     pub fn list_buffers(&self) -> String {
         let mut buffer_list = String::new();
         for (i, buffer) in self.buffers.iter().enumerate() {
-            let indicator = if i == self.current_buffer { "%" } else { " " };
+            let indicator = self.buffer_indicator(i);
             let filename = buffer
                 .filename
                 .as_ref()
@@ -143,6 +421,61 @@ impl SessionController {
         buffer_list
     }
 
+    /// One row per buffer for the :ls picker: number, % for the current
+    /// buffer or # for the alternate, + if modified, name and line count
+    pub fn buffer_rows(&self) -> Vec<String> {
+        let name_width = (0..self.buffers.len())
+            .map(|index| self.buffer_display_name(index).chars().count())
+            .max()
+            .unwrap_or(0);
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                let lines = buffer.line_count();
+                format!(
+                    "{:>3} {}{} {:<width$}  {} line{}",
+                    index + 1,
+                    self.buffer_indicator(index),
+                    if buffer.is_modified() { '+' } else { ' ' },
+                    self.buffer_display_name(index),
+                    lines,
+                    if lines == 1 { "" } else { "s" },
+                    width = name_width
+                )
+            })
+            .collect()
+    }
+
+    /// Index of the buffer for :b {name}. An exact file name wins, then the
+    /// only name containing `query`, then the only name containing its
+    /// characters in order (so "mrs" finds main.rs). Case is ignored.
+    pub fn find_buffer(&self, query: &str) -> Result<usize, String> {
+        let wanted = query.to_lowercase();
+        let names: Vec<String> = (0..self.buffers.len())
+            .map(|index| self.buffer_display_name(index).to_lowercase())
+            .collect();
+        let is_subsequence = |name: &str| {
+            let mut chars = name.chars();
+            wanted.chars().all(|c| chars.any(|n| n == c))
+        };
+        let matchers: [&dyn Fn(&str) -> bool; 3] = [
+            &|name| name == wanted,
+            &|name| name.contains(&wanted),
+            &is_subsequence,
+        ];
+
+        for matches in matchers {
+            let found: Vec<usize> = (0..names.len()).filter(|&index| matches(&names[index])).collect();
+            match found[..] {
+                [] => continue,
+                [index] => return Ok(index),
+                _ => return Err(format!("More than one match for {query}")),
+            }
+        }
+        Err(format!("No matching buffer for {query}"))
+    }
+
     pub fn next_buffer(&mut self) -> String {
         if self.buffers.len() > 1 {
             self.current_buffer = (self.current_buffer + 1) % self.buffers.len();
@@ -223,10 +556,58 @@ impl SessionController {
         }
     }
 
-    pub fn add_help_buffer(&mut self) {
-        let help_doc = crate::controller::help::create_help_document();
-        self.buffers.push(help_doc);
-        self.current_buffer = self.buffers.len() - 1;
+    /// Make the file last edited the alternate one if the current buffer
+    /// now holds another file. Called before each key and command, so
+    /// whatever moved to another buffer leaves its file as the alternate.
+    pub fn note_buffer_switch(&mut self) {
+        let current = self.current_document().filename.clone();
+        if current.is_some() && current != self.last_file {
+            self.alternate = std::mem::replace(&mut self.last_file, current).or(self.alternate.take());
+        }
+    }
+
+    /// Make `alternate` the alternate file, as when focus moves to the
+    /// window it belongs to
+    pub fn set_alternate(&mut self, alternate: Option<PathBuf>) {
+        self.alternate = alternate;
+        self.last_file = self.current_document().filename.clone();
+    }
+
+    /// The buffer holding the alternate file, if one does
+    pub fn alternate_buffer(&self) -> Option<usize> {
+        self.buffer_with_file(self.alternate.as_deref()?)
+    }
+
+    /// The mark :ls gives buffer `index`: % for the current buffer, # for
+    /// the alternate
+    fn buffer_indicator(&self, index: usize) -> char {
+        if index == self.current_buffer {
+            '%'
+        } else if self.alternate_buffer() == Some(index) {
+            '#'
+        } else {
+            ' '
+        }
+    }
+
+    /// Ctrl-^ - edit the alternate file, in the buffer that has it or a new one
+    pub fn switch_to_alternate(&mut self) -> Result<String, String> {
+        self.note_buffer_switch();
+        let path = self.alternate.clone().ok_or("No alternate file")?;
+        let message = self.open_file(&path.to_string_lossy());
+        self.note_buffer_switch();
+        Ok(message)
+    }
+
+    /// Switch to the help buffer, opening it if it isn't open already
+    pub fn open_help_buffer(&mut self) {
+        match self.buffers.iter().position(|doc| doc.help) {
+            Some(index) => self.current_buffer = index,
+            None => {
+                self.buffers.push(crate::controller::help::create_help_document());
+                self.current_buffer = self.buffers.len() - 1;
+            }
+        }
     }
 
     pub fn create_new_buffer(&mut self) -> String {
@@ -251,17 +632,19 @@ impl SessionController {
     ) -> Result<(), std::io::Error> {
         // First, check if the file is already open in a buffer
         for (i, buffer) in self.buffers.iter().enumerate() {
-            if let Some(ref buffer_filename) = buffer.filename {
-                if buffer_filename == target_filename {
-                    self.current_buffer = i;
-                    return Ok(());
-                }
+            if let Some(ref buffer_filename) = buffer.filename
+                && buffer_filename == target_filename
+            {
+                self.current_buffer = i;
+                return Ok(());
             }
         }
 
         // File not found in existing buffers, try to open it
         match Document::from_file(target_filename.clone()) {
-            Ok(doc) => {
+            Ok(mut doc) => {
+                Self::lock_or_read_only(&mut doc);
+                FileTypeRule::apply_all(&self.file_type_rules, &mut doc);
                 self.buffers.push(doc);
                 self.current_buffer = self.buffers.len() - 1;
                 Ok(())
@@ -270,40 +653,34 @@ impl SessionController {
         }
     }
 
-    pub fn yank_text(&mut self, yank_type: crate::controller::yank_paste::YankType, register: Option<char>, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
-        crate::controller::yank_paste::YankPasteHandler::execute_yank_simple(self.current_document(), yank_type, register, register_manager, status_message);
+    pub fn paste_text(&mut self, paste_type: crate::controller::yank_paste::PasteType, register: Option<char>, count: usize, tab_stop: usize, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
+        crate::controller::yank_paste::YankPasteHandler::execute_paste_simple(self.current_document_mut(), paste_type, register, count, tab_stop, register_manager, status_message);
     }
 
-    pub fn paste_text(&mut self, paste_type: crate::controller::yank_paste::PasteType, register: Option<char>, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
-        crate::controller::yank_paste::YankPasteHandler::execute_paste_simple(self.current_document_mut(), paste_type, register, register_manager, status_message);
+    /// q: / q/ / q? - open `entries` in a history window, one per line with
+    /// the cursor on an empty line after them, as a line to type afresh
+    pub fn open_history_window(&mut self, prompt: char, entries: &[String]) {
+        let text: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+        let mut doc = Document::from_string(text);
+        doc.filename = Some(std::path::PathBuf::from(if prompt == ':' { "[Command Line]" } else { "[Search History]" }));
+        doc.history_window = Some(HistoryWindow { prompt, origin: self.current_buffer });
+        doc.move_cursor_to(entries.len(), 0);
+        self.buffers.push(doc);
+        self.current_buffer = self.buffers.len() - 1;
     }
 
-    pub fn execute_indent_command(&mut self, command: crate::controller::command_types::Command, status_message: &mut String) {
-        use crate::controller::command_types::Command;
-        let tab_width = 4; // Could be configurable
-        let use_spaces = true; // Could be configurable
-        
-        match command {
-            Command::IndentLine => {
-                self.current_document_mut().indent_line(tab_width, use_spaces);
-                *status_message = "Line indented".to_string();
-            }
-            Command::IndentLines(count) => {
-                let current_line = self.current_document().cursor_line();
-                self.current_document_mut().indent_lines(current_line, count, tab_width, use_spaces);
-                *status_message = format!("{} lines indented", count);
-            }
-            Command::DedentLine => {
-                self.current_document_mut().dedent_line(tab_width);
-                *status_message = "Line dedented".to_string();
-            }
-            Command::DedentLines(count) => {
-                let current_line = self.current_document().cursor_line();
-                self.current_document_mut().dedent_lines(current_line, count, tab_width);
-                *status_message = format!("{} lines dedented", count);
-            }
-            _ => {}
+    /// Close the history window that is the current buffer, going back to
+    /// the buffer it was opened from. Returns its prompt, or None when the
+    /// current buffer isn't a history window.
+    pub fn close_history_window(&mut self) -> Option<char> {
+        let window = self.current_document().history_window?;
+        self.buffers.remove(self.current_buffer);
+        if self.buffers.is_empty() {
+            self.buffers.push(Document::new()); // The buffer it came from was closed
         }
+        let origin = if window.origin > self.current_buffer { window.origin - 1 } else { window.origin };
+        self.current_buffer = origin.min(self.buffers.len() - 1);
+        Some(window.prompt)
     }
 
     /// Create a preview buffer with the given name and content
@@ -324,3 +701,98 @@ impl SessionController {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::LineEnding;
+
+    fn session_with(names: &[&str]) -> SessionController {
+        let mut session = SessionController::new();
+        session.buffers = names
+            .iter()
+            .map(|name| {
+                let mut doc = Document::from_string("one\ntwo".to_string());
+                doc.filename = Some(std::path::PathBuf::from(name));
+                doc
+            })
+            .collect();
+        session
+    }
+
+    #[test]
+    fn test_find_buffer_by_partial_name() {
+        let session = session_with(&["src/main.rs", "src/mode.rs", "README.md", "main"]);
+        assert_eq!(session.find_buffer("main"), Ok(3)); // Exact beats substring
+        assert_eq!(session.find_buffer("MAIN.rs"), Ok(0));
+        assert_eq!(session.find_buffer("read"), Ok(2));
+        assert_eq!(session.find_buffer("mde"), Ok(1)); // Letters in order
+        assert_eq!(session.find_buffer(".rs"), Err("More than one match for .rs".to_string()));
+        assert_eq!(session.find_buffer("zz"), Err("No matching buffer for zz".to_string()));
+    }
+
+    #[test]
+    fn test_buffer_rows_show_flags_and_line_counts() {
+        let mut session = session_with(&["a.txt", "longer.txt"]);
+        session.buffers[1].modified = true;
+        assert_eq!(session.buffer_rows(), ["  1 %  a.txt       2 lines", "  2  + longer.txt  2 lines"]);
+    }
+
+    #[test]
+    fn test_alternate_follows_buffer_switches() {
+        let mut session = session_with(&["a.txt", "b.txt", "c.txt"]);
+        session.note_buffer_switch();
+        assert_eq!(session.switch_to_alternate(), Err("No alternate file".to_string()));
+
+        session.current_buffer = 2;
+        session.note_buffer_switch();
+        assert_eq!(session.alternate, Some(PathBuf::from("a.txt")));
+        assert_eq!(session.buffer_rows()[0], "  1 #  a.txt  2 lines");
+        assert_eq!(session.list_buffers(), "# 1: \"a.txt\"  |   2: \"b.txt\"  | % 3: \"c.txt\" ");
+
+        // Back and forth
+        session.switch_to_alternate().unwrap();
+        assert_eq!((session.current_buffer, session.alternate_buffer()), (0, Some(2)));
+        session.switch_to_alternate().unwrap();
+        assert_eq!((session.current_buffer, session.alternate_buffer()), (2, Some(0)));
+    }
+
+    #[test]
+    fn test_stdin_buffer_comes_first() {
+        let mut session = SessionController::new();
+        session.open_stdin(b"diff\r\n+added\r\n");
+        assert_eq!(session.buffer_count(), 1); // In place of the empty buffer
+        let doc = session.current_document();
+        assert_eq!((doc.get_line(1).as_deref(), doc.line_ending, doc.is_modified()), (Some("+added"), LineEnding::Windows, true));
+
+        let mut session = session_with(&["a.txt"]);
+        session.open_stdin(b"piped");
+        assert_eq!(session.buffer_rows(), ["  1 %+ [No Name]  1 line", "  2    a.txt      2 lines"]);
+    }
+
+    #[test]
+    fn test_autosave_writes_buffers_idle_long_enough() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let mut session = SessionController::new();
+        session.buffers = vec![Document::from_file(path.clone()).unwrap(), Document::from_string("scratch".to_string())];
+        let start = Instant::now();
+        session.track_changes(start);
+
+        session.buffers[0].insert_char('x');
+        session.buffers[1].insert_char('x'); // No file name, so never autosaved
+        session.track_changes(start);
+        assert_eq!(session.autosave_wait(start), None); // Off
+        assert!(session.autosave(start, true).is_empty());
+
+        session.autosave = Some(Duration::from_secs(30));
+        assert_eq!(session.autosave_wait(start + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+        assert!(session.autosave(start + Duration::from_secs(10), false).is_empty());
+        let name = session.buffer_display_name(0).to_string();
+        assert_eq!(session.autosave(start + Duration::from_secs(30), false), [Ok(name)]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xone\n");
+        assert!(!session.buffers[0].is_modified());
+        assert_eq!(session.autosave_wait(start + Duration::from_secs(30)), None);
+    }
+}
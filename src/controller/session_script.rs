@@ -0,0 +1,141 @@
+//! Session files - :mksession writes the editor's state out as ex commands,
+//! and :source (or `virus -S`) runs them to bring that state back
+//!
+//! A session holds the global options, each buffer that has a file with its
+//! local options, the window layout, and the buffer and cursor position of
+//! every window. Buffers without a file (new buffers, help) are left out, so a
+//! window showing one keeps whatever buffer it had when the session loads.
+
+use crate::controller::shared_state::SharedEditorState;
+use crate::view::{LayoutStep, SplitDirection};
+use std::path::Path;
+
+/// File :mksession writes and `-S` reads when no name is given
+pub const DEFAULT_SESSION_FILE: &str = "Session.vim";
+
+pub struct SessionScript;
+
+impl SessionScript {
+    /// The commands that restore the current session
+    pub fn generate(shared: &SharedEditorState) -> String {
+        let mut lines = vec!["\" vi-rus session - load with :source or virus -S".to_string()];
+
+        let view = &shared.view;
+        lines.push(format!("set tabstop={}", view.get_tab_stop()));
+        lines.push(Self::flag("set", "number", view.get_line_numbers()));
+        lines.push(Self::flag("set", "list", view.get_show_whitespace()));
+        lines.push(format!("set listchars={}", view.get_list_chars()));
+        lines.push(Self::flag("set", "hltrailing", view.get_show_trailing()));
+        lines.push(Self::flag("set", "cursorline", view.get_cursor_line()));
+        let columns: Vec<String> = view.get_color_columns().iter().map(usize::to_string).collect();
+        lines.push(format!("set colorcolumn={}", columns.join(",")));
+        lines.push(Self::flag("set", "hlsearch", shared.search_state.hlsearch));
+        lines.push(Self::flag("set", "incsearch", shared.search_state.incsearch));
+        lines.push(Self::flag("set", "ignorecase", shared.search_state.ignore_case));
+        lines.push(Self::flag("set", "smartcase", shared.search_state.smart_case));
+        lines.push(format!("set shortmess={}", shared.search_state.shortmess));
+        lines.push(Self::flag("set", "showmatch", shared.show_match.enabled));
+        lines.push(format!("set matchtime={}", shared.show_match.match_time));
+        lines.push(Self::flag("set", "autoread", shared.session_controller.autoread));
+        lines.push(Self::flag("set", "confirm", shared.session_controller.confirm));
+        lines.push(format!("set undolevels={}", shared.session_controller.undo_levels));
+        let autosave = shared.session_controller.autosave.map_or(0, |delay| delay.as_secs());
+        lines.push(format!("set autosave={}", autosave));
+        lines.push(format!("set ticktime={}", shared.timers.tick().map_or(0, |tick| tick.as_millis())));
+        lines.push(Self::flag("set", "showcmd", view.get_show_command()));
+        lines.push(Self::flag("set", "timeout", shared.timers.timeout));
+        lines.push(format!("set timeoutlen={}", shared.timers.timeout_len.as_millis()));
+        lines.push(format!("set synmaxcol={}", view.get_max_highlight_column()));
+        lines.push(format!("colorscheme {}", view.theme().name));
+
+        for doc in &shared.session_controller.buffers {
+            let Some(path) = doc.filename.as_deref() else {
+                continue;
+            };
+            lines.push(format!("edit {}", Self::path_text(path)));
+            lines.push(Self::flag("setlocal", "expandtab", doc.expand_tab));
+            lines.push(format!("setlocal textwidth={}", doc.text_width));
+            lines.push(format!("setlocal shiftwidth={}", doc.shift_width));
+            lines.push(Self::flag("setlocal", "shiftround", doc.shift_round));
+            lines.push(Self::flag("setlocal", "autoindent", doc.auto_indent));
+            lines.push(Self::flag("setlocal", "autopairs", doc.auto_pairs));
+            lines.push(Self::flag("setlocal", "pastewrap", doc.wrap_paste));
+            lines.push(Self::flag("setlocal", "trimtrailing", doc.trim_trailing));
+            if let Some(program) = &doc.write_program {
+                lines.push(format!("setlocal writeprg={}", program));
+            }
+            if let Some(program) = &doc.format_program {
+                lines.push(format!("setlocal formatprg={}", program));
+            }
+            lines.push(Self::flag("setlocal", "formatonsave", doc.format_on_save));
+        }
+
+        // Rebuild the layout from one window, then fill the windows in screen order
+        let windows = view.windows();
+        lines.push("only".to_string());
+        for step in windows.layout_steps() {
+            lines.push(match step {
+                LayoutStep::Split(SplitDirection::Horizontal) => "split".to_string(),
+                LayoutStep::Split(SplitDirection::Vertical) => "vsplit".to_string(),
+                LayoutStep::Next => "wincmd w".to_string(),
+            });
+        }
+        let live_window = windows.active_id();
+        for window in windows.windows() {
+            lines.push("wincmd w".to_string());
+            // The active window's buffer and cursor live in the session controller
+            let (buffer_index, cursor) = if window.id == live_window {
+                let doc = shared.session_controller.current_document();
+                (shared.session_controller.current_buffer_index(), (doc.cursor_line(), doc.cursor_column()))
+            } else {
+                (window.buffer_index, window.cursor)
+            };
+            if let Some(path) = shared.session_controller.buffers.get(buffer_index).and_then(|doc| doc.filename.as_deref()) {
+                lines.push(format!("edit {}", Self::path_text(path)));
+                lines.push(format!("call cursor({}, {})", cursor.0 + 1, cursor.1 + 1));
+            }
+        }
+        // After the last window, step round to the one that was active
+        for _ in 0..=windows.active_position() {
+            lines.push("wincmd w".to_string());
+        }
+
+        let mut script = lines.join("\n");
+        script.push('\n');
+        script
+    }
+
+    /// `set name` or `set noname` (or the same with :setlocal)
+    fn flag(command: &str, name: &str, on: bool) -> String {
+        if on { format!("{} {}", command, name) } else { format!("{} no{}", command, name) }
+    }
+
+    /// An absolute path where one can be found, so the session loads from any directory
+    fn path_text(path: &Path) -> String {
+        std::fs::canonicalize(path)
+            .or_else(|_| std::env::current_dir().map(|dir| dir.join(path)))
+            .unwrap_or_else(|_| path.to_path_buf())
+            .display()
+            .to_string()
+    }
+
+    /// `call cursor(line, column)` arguments: 1-based line and byte column
+    pub fn parse_cursor_call(argument: &str) -> Option<(usize, usize)> {
+        let inner = argument.trim().strip_prefix("cursor(")?.strip_suffix(')')?;
+        let (line, column) = inner.split_once(',')?;
+        Some((line.trim().parse().ok()?, column.trim().parse().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cursor_call() {
+        assert_eq!(SessionScript::parse_cursor_call("cursor(12, 5)"), Some((12, 5)));
+        assert_eq!(SessionScript::parse_cursor_call(" cursor(1,1) "), Some((1, 1)));
+        assert_eq!(SessionScript::parse_cursor_call("cursor(1)"), None);
+        assert_eq!(SessionScript::parse_cursor_call("search(1, 2)"), None);
+    }
+}
@@ -1,5 +1,12 @@
 use crate::controller::SessionController;
-use crate::document_model::{MarkManager, RegisterManager, SearchState};
+use crate::controller::diff_commands::DiffMode;
+use crate::controller::key_map::KeyMap;
+use crate::controller::lsp_commands::LspManager;
+use crate::controller::substitute::Substitution;
+use crate::controller::show_match::ShowMatch;
+use crate::controller::timers::TimerQueue;
+use crate::controller::visual_mode::{BlockInsert, Selection};
+use crate::document_model::{History, MarkManager, QuickfixList, RegisterManager, SearchState, TagStack};
 use crate::view::View;
 use crossterm::event::KeyEvent;
 
@@ -13,6 +20,30 @@ pub struct SharedEditorState {
     pub status_message: String,
     pub show_all_unmatched: bool,
     pub cached_unmatched_brackets: Option<Vec<(usize, usize)>>,
+    pub block_insert: Option<BlockInsert>, // Block I/A waiting for insert mode to end
+    pub pending_selection: Option<Selection>,     // gv: the selection visual mode starts with
+    pub last_substitute: Option<Substitution>,    // Repeated by & and :&&
+    pub pending_shell_command: Option<String>,    // :!cmd, run once the editor hands over the terminal
+    pub pending_ex_command: Option<String>,       // ZZ / ZQ: a : command for the command controller to run
+    pub pending_normal: Option<NormalKeys>,       // :normal keys, typed by the editor once the command line is done
+    pub pending_suspend: bool,                    // Ctrl-Z / :stop, done once the editor can hand over the terminal
+    pub quickfix: QuickfixList,                   // Matches from the last :grep
+    pub key_map: KeyMap,                          // :map mappings and the keys waiting on them
+    pub timers: TimerQueue,                       // Work the event loop does when its time comes
+    pub history: History,                         // Command lines and searches, for Up/Down, q: and the info file
+    pub show_match: ShowMatch,                    // 'showmatch' and the bracket it highlights
+    pub diff: Option<DiffMode>,                   // The two buffers diff mode compares
+    pub tag_stack: TagStack,                      // Where Ctrl-] and :tag jumped from, for Ctrl-T
+    pub lsp: LspManager,                          // Language servers and what they were sent
+}
+
+/// Keys :normal leaves for the editor to type: the command line can't type
+/// them itself, as the modes that take them belong to the editor
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalKeys {
+    pub keys: Vec<KeyEvent>,
+    pub lines: Vec<usize>, // Typed from the start of each line; empty for once at the cursor
+    pub remap: bool,       // :normal rather than :normal!
 }
 
 /// Result of handling a key event in a mode controller
@@ -26,4 +57,37 @@ pub enum ModeTransition {
 /// Trait that all mode controllers must implement
 pub trait ModeController {
     fn handle_key(&mut self, key_event: KeyEvent, shared: &mut SharedEditorState) -> ModeTransition;
-}
\ No newline at end of file
+}
+impl SharedEditorState {
+    /// Catch up with a move to another buffer: the file left becomes the
+    /// alternate, and the % and # registers name the files as they are now
+    pub fn note_buffer_switch(&mut self) {
+        self.session_controller.note_buffer_switch();
+        let current = self.session_controller.current_document().filename.as_deref();
+        self.register_manager.set_file_names(current, self.session_controller.alternate.as_deref());
+    }
+
+    /// Where a mark points in the current document, as used by jumps,
+    /// operators and ranges: a-z from the document, A-Z only when set in
+    /// this file, '< and '> for the last visual selection, and the special
+    /// '' `` '. '^ marks. Clamped to the text as it is now.
+    pub fn mark_position(&self, mark_char: char) -> Option<(usize, usize)> {
+        let doc = self.session_controller.current_document();
+        let (line, column) = if mark_char.is_ascii_lowercase() {
+            doc.get_local_mark(mark_char)?
+        } else if mark_char == '.' {
+            doc.undo_manager().last_change()?
+        } else if let '<' | '>' = mark_char {
+            let (start_line, start_column, end_line, end_column) = doc.last_selection.as_ref()?.get_ordered_bounds();
+            if mark_char == '<' { (start_line, start_column) } else { (end_line, end_column) }
+        } else {
+            let mark = self.mark_manager.get_global_mark(mark_char)?;
+            if mark_char.is_ascii_uppercase() && mark.filename != doc.filename {
+                return None;
+            }
+            (mark.line, mark.column)
+        };
+        let line = line.min(doc.line_count().saturating_sub(1));
+        Some((line, column.min(doc.get_line_length(line))))
+    }
+}
@@ -0,0 +1,60 @@
+//! 'showmatch' - a closing bracket typed in insert mode briefly highlights
+//! the bracket it closes, for 'matchtime' tenths of a second or until the
+//! next key. When that bracket is above the top of the screen, its line is
+//! shown on the status line instead.
+
+use crate::controller::shared_state::SharedEditorState;
+use crate::controller::timers::Timer;
+use std::time::{Duration, Instant};
+
+/// Default 'matchtime', in tenths of a second, as in vim
+pub const DEFAULT_MATCH_TIME: u64 = 5;
+
+#[derive(Debug)]
+pub struct ShowMatch {
+    pub enabled: bool,
+    pub match_time: u64,               // Tenths of a second the highlight lasts
+    pub flash: Option<(usize, usize)>, // The bracket highlighted now: line and byte column
+}
+
+impl Default for ShowMatch {
+    fn default() -> Self {
+        Self { enabled: false, match_time: DEFAULT_MATCH_TIME, flash: None }
+    }
+}
+
+impl ShowMatch {
+    /// `c` was just typed before the cursor: if it closes a bracket, show
+    /// the one it closes until the ShowMatch timer ends the highlight
+    pub fn bracket_typed(shared: &mut SharedEditorState, c: char) {
+        if !shared.show_match.enabled || !matches!(c, ')' | ']' | '}') {
+            return;
+        }
+        let doc = shared.session_controller.current_document();
+        let (line, column) = (doc.cursor_line(), doc.cursor_column());
+        let Some(text) = doc.get_line(line) else {
+            return;
+        };
+        // The cursor may have stepped over a closing bracket autopairs typed
+        let Some(typed) = text[..column.min(text.len())].char_indices().next_back().map(|(index, _)| index) else {
+            return;
+        };
+        let Some(open) = doc.find_matching_bracket_at(line, typed, shared.view.highlight_line_limit()) else {
+            return;
+        };
+
+        if open.0 < shared.view.get_scroll_offset() {
+            let open_line = doc.get_line(open.0).unwrap_or_default();
+            shared.status_message = format!("{}: {}", open.0 + 1, open_line.trim());
+        } else {
+            let time = Duration::from_millis(shared.show_match.match_time * 100);
+            shared.show_match.flash = Some(open);
+            shared.timers.schedule(Timer::ShowMatch, Instant::now() + time);
+        }
+    }
+
+    /// End the highlight, as the timer or the next key does
+    pub fn clear(&mut self) {
+        self.flash = None;
+    }
+}
@@ -0,0 +1,642 @@
+//! :s/old/new/flags - flag parsing, the batch run, the c-flag confirm loop and & repeats
+//!
+//! The pattern is a regular expression, as for :g, with `^` and `$`
+//! anchoring to each line and `\%V` limiting it to the last selection. Any
+//! punctuation can stand in for `/` (`:s#/usr#/opt#`), and a backslash
+//! makes it part of the text. In the replacement `&` and `\0` are the whole
+//! match, `\1`-`\9` and `$1`-`$9` its groups, `\u` / `\l` change the case
+//! of the next character and `\U` / `\L` of everything up to `\E` or `\e`;
+//! `\r` splits the line.
+
+use crate::controller::shared_state::SharedEditorState;
+use crate::controller::visual_mode::Selection;
+use crate::document_model::{Document, SearchState};
+use crate::document_model::search_state::{matches_case, uses_visual_area, LineOffsets, SearchMatch};
+use regex::{Captures, Regex};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SubstituteFlags {
+    pub global: bool,      // g - every match on a line, not just the first
+    pub ignore_case: bool, // i - case-insensitive pattern, whatever 'ignorecase' says
+    pub match_case: bool,  // I - case-sensitive pattern, likewise
+    pub confirm: bool,     // c - ask before each replacement
+    pub count_only: bool,  // n - report the number of matches, change nothing
+    pub no_error: bool,    // e - a pattern that isn't found is not an error
+}
+
+impl SubstituteFlags {
+    pub fn parse(flags: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for flag in flags.chars() {
+            match flag {
+                'g' => parsed.global = true,
+                'i' => (parsed.ignore_case, parsed.match_case) = (true, false),
+                'I' => (parsed.ignore_case, parsed.match_case) = (false, true),
+                'c' => parsed.confirm = true,
+                'n' => parsed.count_only = true,
+                'e' => parsed.no_error = true,
+                _ => return Err(format!("Unknown substitute flag: {}", flag)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A parsed :s command, kept in shared state so & and :&& can repeat it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: SubstituteFlags,
+    pub in_selection: bool, // :'<,'>s - only matches inside the last visual selection, as with \%V
+}
+
+impl Substitution {
+    /// Parse "/old/new/flags", or the same with another delimiter
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || "Invalid substitute pattern".to_string();
+        let delimiter = text.chars().next().ok_or_else(invalid)?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || matches!(delimiter, '\\' | '"' | '|') {
+            return Err(invalid());
+        }
+        let parts = split_on_delimiter(&text[delimiter.len_utf8()..], delimiter);
+        if parts.len() < 2 {
+            return Err(invalid());
+        }
+
+        // An escaped delimiter in the pattern is just that character
+        let escaped = format!("\\{delimiter}");
+        let pattern = if regex::escape(&delimiter.to_string()).len() > 1 {
+            parts[0].clone()
+        } else {
+            parts[0].replace(&escaped, &delimiter.to_string())
+        };
+        Ok(Self {
+            pattern,
+            replacement: parts[1].clone(),
+            flags: SubstituteFlags::parse(parts.get(2).map_or("", String::as_str))?,
+            in_selection: false,
+        })
+    }
+
+    /// The pattern's regex. The i and I flags decide its case before
+    /// 'ignorecase' and 'smartcase' do; \c and \C in it before either.
+    fn regex(&self, search: &SearchState) -> Result<Regex, String> {
+        let (ignore_case, smart_case) = match (self.flags.ignore_case, self.flags.match_case) {
+            (true, _) => (true, false),
+            (_, true) => (false, false),
+            _ => (search.ignore_case, search.smart_case),
+        };
+        let case = if matches_case(&self.pattern, ignore_case, smart_case) { "(?m)" } else { "(?mi)" };
+        Regex::new(&format!("{case}{}", without_vim_atoms(&self.pattern)))
+            .map_err(|e| format!("Invalid substitute pattern: {e}"))
+    }
+
+    /// The last visual selection when the pattern holds \%V or the range is '<,'>
+    fn visual_area(&self, shared: &SharedEditorState) -> Result<Option<Selection>, String> {
+        if !self.in_selection && !uses_visual_area(&self.pattern) {
+            return Ok(None);
+        }
+        shared
+            .session_controller
+            .current_document()
+            .last_selection
+            .clone()
+            .map(Some)
+            .ok_or_else(|| "No previous visual selection".to_string())
+    }
+
+    /// Run over lines start_line..=end_line. With the c flag this only sets up
+    /// the confirm loop, which the caller drives key by key.
+    pub fn execute(
+        &self,
+        start_line: usize,
+        end_line: usize,
+        shared: &mut SharedEditorState,
+    ) -> Result<Option<SubstituteConfirm>, String> {
+        let regex = self.regex(&shared.search_state)?;
+        let replacement = Replacement::parse(&self.replacement);
+        let visual_area = self.visual_area(shared)?;
+        shared.last_substitute = Some(self.clone());
+
+        let doc = shared.session_controller.current_document();
+        let (matches, lines) = self.count_matches(&regex, &replacement, doc, start_line, end_line, visual_area.as_ref());
+        if matches == 0 && !self.flags.no_error {
+            return Err(format!("Pattern not found: {}", self.pattern));
+        }
+
+        if self.flags.count_only {
+            shared.status_message = format!(
+                "{} match{} on {} line{}",
+                matches,
+                if matches == 1 { "" } else { "es" },
+                lines,
+                if lines == 1 { "" } else { "s" }
+            );
+            return Ok(None);
+        }
+
+        if self.flags.confirm {
+            let mut confirm = SubstituteConfirm {
+                regex,
+                replacement,
+                global: self.flags.global,
+                visual_area,
+                end_line,
+                from: None,
+                prev_end: None,
+                last_line: None,
+                current: None,
+                lines_changed: 0,
+                prompt: format!("replace with {} (y/n/a/q/l)?", self.replacement),
+            };
+            let doc = shared.session_controller.current_document_mut();
+            let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+            doc.undo_manager_mut().start_group(cursor_pos);
+            confirm.from = Some(
+                LineOffsets::new(&doc.buffer_text())
+                    .line_start(start_line)
+                    .unwrap_or(usize::MAX),
+            );
+            return Ok(confirm.advance(shared).then_some(confirm));
+        }
+
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().start_group(cursor_pos);
+        let lines_changed = self.replace_all(&regex, &replacement, doc, start_line, end_line, visual_area.as_ref());
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        doc.stats.record_substitute(lines_changed);
+
+        shared.status_message = format!("{} substitutions made", lines_changed);
+        Ok(None)
+    }
+
+    /// Matches to act on, as byte offsets into `text` with the text each is
+    /// replaced by: those starting in the line range, only the first per line
+    /// without g, and inside the visual area
+    fn matches_in_range(
+        &self,
+        regex: &Regex,
+        replacement: &Replacement,
+        text: &str,
+        start_line: usize,
+        end_line: usize,
+        visual_area: Option<&Selection>,
+    ) -> Vec<(usize, usize, usize, String)> {
+        let offsets = LineOffsets::new(text);
+        let first_offset = offsets.line_start(start_line).unwrap_or(text.len());
+        let mut found = Vec::new();
+        let mut last_line = None;
+
+        for captures in regex.captures_iter(text) {
+            let mat = captures.get(0).expect("group 0 is the whole match");
+            if mat.start() < first_offset {
+                continue;
+            }
+            let (line, column) = offsets.position(mat.start());
+            if line > end_line {
+                break;
+            }
+            if last_line == Some(line) && !self.flags.global {
+                continue;
+            }
+            if let Some(selection) = visual_area
+                && !selection.contains_span((line, column), offsets.position(mat.end()))
+            {
+                continue;
+            }
+            found.push((line, mat.start(), mat.end(), replacement.expand(&captures)));
+            last_line = Some(line);
+        }
+        found
+    }
+
+    fn count_matches(
+        &self,
+        regex: &Regex,
+        replacement: &Replacement,
+        doc: &Document,
+        start_line: usize,
+        end_line: usize,
+        visual_area: Option<&Selection>,
+    ) -> (usize, usize) {
+        let text = doc.buffer_text();
+        let found = self.matches_in_range(regex, replacement, &text, start_line, end_line, visual_area);
+        let mut lines: Vec<usize> = found.iter().map(|(line, ..)| *line).collect();
+        lines.dedup();
+        (found.len(), lines.len())
+    }
+
+    /// Replace every match at once, leaving the cursor on the first
+    /// non-blank of the last line substituted, as vim does. Returns the
+    /// number of lines changed.
+    fn replace_all(
+        &self,
+        regex: &Regex,
+        replacement: &Replacement,
+        doc: &mut Document,
+        start_line: usize,
+        end_line: usize,
+        visual_area: Option<&Selection>,
+    ) -> usize {
+        // Match against the whole buffer so a pattern containing \n can join
+        // lines, even past the end of the range
+        let text = doc.buffer_text();
+        let offsets = LineOffsets::new(&text);
+        let found = self.matches_in_range(regex, replacement, &text, start_line, end_line, visual_area);
+        let Some(&(_, _, last_end, _)) = found.last() else {
+            return 0;
+        };
+
+        let first_offset = offsets.line_start(start_line).unwrap_or(text.len());
+        let mut new_text = String::new();
+        let mut copied = first_offset;
+        let mut last_start = 0;
+        for (_, start, end, replaced) in &found {
+            new_text.push_str(&text[copied..*start]);
+            last_start = new_text.len();
+            new_text.push_str(replaced);
+            copied = *end;
+        }
+
+        let last_touched = offsets.position(last_end).0;
+        new_text.push_str(&text[copied..offsets.line_end(last_touched, text.len())]);
+        let new_lines: Vec<String> = new_text.split('\n').map(str::to_string).collect();
+        doc.replace_lines_with_undo(start_line, last_touched - start_line + 1, &new_lines);
+        doc.move_cursor_to(start_line + new_text[..last_start].matches('\n').count(), 0);
+        doc.move_first_non_whitespace();
+
+        let mut lines: Vec<usize> = found.iter().map(|(line, ..)| *line).collect();
+        lines.dedup();
+        lines.len()
+    }
+}
+
+/// The :s///c confirm loop: each candidate is highlighted and the cursor
+/// moved to it while the user answers y/n/a/q/l
+pub struct SubstituteConfirm {
+    regex: Regex,
+    replacement: Replacement,
+    global: bool,
+    visual_area: Option<Selection>,
+    end_line: usize,                        // Last line of the range, adjusted as lines are added or joined
+    from: Option<usize>,                    // Byte offset to search on from
+    prev_end: Option<usize>,                // End of the last match, so an empty match there is skipped
+    last_line: Option<usize>,               // Line of the last match, for the first-match-per-line rule
+    current: Option<(usize, usize, usize)>, // Line and byte range of the match being asked about
+    lines_changed: usize,
+    pub prompt: String,
+}
+
+impl SubstituteConfirm {
+    /// Handle an answer. Returns false once the loop is over.
+    pub fn handle_key(&mut self, key: char, shared: &mut SharedEditorState) -> bool {
+        match key {
+            'y' => {
+                self.replace_current(shared);
+                self.advance(shared)
+            }
+            'n' => {
+                self.skip_current();
+                self.advance(shared)
+            }
+            'a' => {
+                while self.current.is_some() {
+                    self.replace_current(shared);
+                    self.find_next(shared.session_controller.current_document());
+                }
+                self.finish(shared);
+                false
+            }
+            'l' => {
+                self.replace_current(shared);
+                self.finish(shared);
+                false
+            }
+            'q' => {
+                self.finish(shared);
+                false
+            }
+            _ => true, // Keep asking
+        }
+    }
+
+    /// Esc - stop asking, keeping the replacements made so far
+    pub fn cancel(&mut self, shared: &mut SharedEditorState) {
+        self.finish(shared);
+    }
+
+    /// Move to the next candidate and show it. Returns false (having
+    /// finished) when there are no more.
+    fn advance(&mut self, shared: &mut SharedEditorState) -> bool {
+        let doc = shared.session_controller.current_document_mut();
+        let Some((_, start, end)) = self.find_next(doc) else {
+            self.finish(shared);
+            return false;
+        };
+
+        let text = doc.buffer_text();
+        let offsets = LineOffsets::new(&text);
+        let (line, start_col) = offsets.position(start);
+        let (end_line, end_col) = offsets.position(end);
+        doc.move_cursor_to(line, start_col);
+
+        let search = &mut shared.search_state;
+        search.preview_matches = Some(vec![SearchMatch {
+            line,
+            start_col,
+            end_line,
+            end_col,
+            text: text[start..end].to_string(),
+        }]);
+        search.preview_current = Some(0);
+        shared.status_message.clear();
+        true
+    }
+
+    /// Find the next candidate from `from`, setting `current`
+    fn find_next(&mut self, doc: &Document) -> Option<(usize, usize, usize)> {
+        self.current = None;
+        let text = doc.buffer_text();
+        let offsets = LineOffsets::new(&text);
+        let mut from = self.from?;
+
+        while from <= text.len() {
+            let mat = self.regex.find_at(&text, from)?;
+            let (line, column) = offsets.position(mat.start());
+            if line > self.end_line {
+                return None;
+            }
+
+            let repeats_empty = mat.start() == mat.end() && Some(mat.start()) == self.prev_end;
+            let same_line = self.last_line == Some(line) && !self.global;
+            let outside = self
+                .visual_area
+                .as_ref()
+                .is_some_and(|selection| !selection.contains_span((line, column), offsets.position(mat.end())));
+            if repeats_empty || same_line || outside {
+                // Step past this match; skip to the next line when only the first per line counts
+                from = if same_line {
+                    offsets.line_end(line, text.len()) + 1
+                } else {
+                    next_char_boundary(&text, mat.start())
+                };
+                continue;
+            }
+
+            self.current = Some((line, mat.start(), mat.end()));
+            return self.current;
+        }
+        None
+    }
+
+    fn skip_current(&mut self) {
+        if let Some((line, _, end)) = self.current.take() {
+            self.last_line = Some(line);
+            self.from = Some(end);
+            self.prev_end = Some(end);
+        }
+    }
+
+    fn replace_current(&mut self, shared: &mut SharedEditorState) {
+        let Some((line, start, end)) = self.current.take() else {
+            return;
+        };
+        let doc = shared.session_controller.current_document_mut();
+        let text = doc.buffer_text();
+        let offsets = LineOffsets::new(&text);
+        let last_touched = offsets.position(end).0;
+
+        let line_start = offsets.line_start(line).unwrap_or(0);
+        let replaced = match self.regex.captures_at(&text, start) {
+            Some(captures) => self.replacement.expand(&captures),
+            None => String::new(),
+        };
+        let new_text = format!(
+            "{}{}{}",
+            &text[line_start..start],
+            replaced,
+            &text[end..offsets.line_end(last_touched, text.len())]
+        );
+        let new_lines: Vec<String> = new_text.split('\n').map(str::to_string).collect();
+        let old_count = last_touched - line + 1;
+        doc.replace_lines_with_undo(line, old_count, &new_lines);
+        doc.move_cursor_to(line, 0);
+        doc.move_first_non_whitespace();
+
+        self.end_line = (self.end_line + new_lines.len()).saturating_sub(old_count);
+        if self.last_line != Some(line) {
+            self.lines_changed += 1;
+        }
+        self.last_line = Some(line);
+        let resume = start + replaced.len();
+        self.from = Some(resume);
+        self.prev_end = Some(resume);
+    }
+
+    fn finish(&mut self, shared: &mut SharedEditorState) {
+        shared.search_state.clear_preview();
+        let doc = shared.session_controller.current_document_mut();
+        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+        doc.undo_manager_mut().end_group(cursor_pos);
+        doc.stats.record_substitute(self.lines_changed);
+        shared.status_message = format!("{} substitutions made", self.lines_changed);
+    }
+}
+
+/// Split the text after the first delimiter into pattern, replacement and
+/// flags at the delimiters a backslash doesn't escape
+fn split_on_delimiter(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == delimiter && parts.len() < 3 {
+            parts.push(String::new());
+            continue;
+        }
+        let part = parts.last_mut().expect("parts starts with one");
+        part.push(ch);
+        if ch == '\\' {
+            part.extend(chars.next());
+        }
+    }
+    parts
+}
+
+/// The pattern without the \%V the visual area stands for and the \c or
+/// \C that set its case, which a regex doesn't know
+fn without_vim_atoms(pattern: &str) -> String {
+    let mut kept = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            kept.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('%') if chars.peek() == Some(&'V') => {
+                chars.next();
+            }
+            Some('c' | 'C') => {}
+            Some(escaped) => {
+                kept.push(ch);
+                kept.push(escaped);
+            }
+            None => kept.push(ch),
+        }
+    }
+    kept
+}
+
+/// The replacement half of :s, read once and filled in for each match
+#[derive(Debug, Clone, PartialEq)]
+struct Replacement(Vec<ReplacementPart>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum ReplacementPart {
+    Text(String),
+    Group(usize), // 0 for the whole match
+    Case(CaseChange),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaseChange {
+    UpperNext, // \u
+    LowerNext, // \l
+    Upper,     // \U
+    Lower,     // \L
+    End,       // \E or \e
+}
+
+impl Replacement {
+    fn parse(text: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(ch) = chars.next() {
+            let part = match ch {
+                '&' => ReplacementPart::Group(0),
+                '$' if chars.peek().is_some_and(|next| next.is_ascii_digit()) => {
+                    ReplacementPart::Group(chars.next().and_then(|digit| digit.to_digit(10)).unwrap_or(0) as usize)
+                }
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => ReplacementPart::Group(digit.to_digit(10).unwrap_or(0) as usize),
+                    Some('u') => ReplacementPart::Case(CaseChange::UpperNext),
+                    Some('l') => ReplacementPart::Case(CaseChange::LowerNext),
+                    Some('U') => ReplacementPart::Case(CaseChange::Upper),
+                    Some('L') => ReplacementPart::Case(CaseChange::Lower),
+                    Some('E' | 'e') => ReplacementPart::Case(CaseChange::End),
+                    Some('r' | 'n') => {
+                        literal.push('\n');
+                        continue;
+                    }
+                    Some('t') => {
+                        literal.push('\t');
+                        continue;
+                    }
+                    Some(other) => {
+                        literal.push(other); // \&, \\, \/ ...
+                        continue;
+                    }
+                    None => {
+                        literal.push('\\');
+                        continue;
+                    }
+                },
+                _ => {
+                    literal.push(ch);
+                    continue;
+                }
+            };
+            if !literal.is_empty() {
+                parts.push(ReplacementPart::Text(std::mem::take(&mut literal)));
+            }
+            parts.push(part);
+        }
+        if !literal.is_empty() {
+            parts.push(ReplacementPart::Text(literal));
+        }
+        Self(parts)
+    }
+
+    /// The text that replaces the match `captures` holds
+    fn expand(&self, captures: &Captures) -> String {
+        let mut expanded = String::new();
+        let mut next = None;
+        let mut run = None;
+        for part in &self.0 {
+            let text = match part {
+                ReplacementPart::Text(text) => text.as_str(),
+                ReplacementPart::Group(group) => captures.get(*group).map_or("", |m| m.as_str()),
+                ReplacementPart::Case(change @ (CaseChange::UpperNext | CaseChange::LowerNext)) => {
+                    next = Some(*change);
+                    continue;
+                }
+                ReplacementPart::Case(CaseChange::End) => {
+                    run = None;
+                    continue;
+                }
+                ReplacementPart::Case(change) => {
+                    run = Some(*change);
+                    continue;
+                }
+            };
+            for ch in text.chars() {
+                match next.take().or(run) {
+                    Some(CaseChange::UpperNext | CaseChange::Upper) => expanded.extend(ch.to_uppercase()),
+                    Some(CaseChange::LowerNext | CaseChange::Lower) => expanded.extend(ch.to_lowercase()),
+                    _ => expanded.push(ch),
+                }
+            }
+        }
+        expanded
+    }
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    text[index..].chars().next().map_or(index + 1, |ch| index + ch.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flags() {
+        let substitution = Substitution::parse("/a/b/gic").unwrap();
+        assert_eq!((substitution.pattern.as_str(), substitution.replacement.as_str()), ("a", "b"));
+        assert_eq!(
+            substitution.flags,
+            SubstituteFlags { global: true, ignore_case: true, confirm: true, ..Default::default() }
+        );
+        assert!(Substitution::parse("/a/b/e").unwrap().flags.no_error);
+        assert_eq!(Substitution::parse("/a/b").unwrap().flags, SubstituteFlags::default());
+        assert_eq!(Substitution::parse("/a/b/gx"), Err("Unknown substitute flag: x".to_string()));
+        assert!(Substitution::parse("a/b/").is_err());
+    }
+
+    #[test]
+    fn test_parse_other_delimiters() {
+        let substitution = Substitution::parse("#/usr/lib#/opt\\#1#g").unwrap();
+        assert_eq!((substitution.pattern.as_str(), substitution.replacement.as_str()), ("/usr/lib", "/opt\\#1"));
+        assert!(substitution.flags.global);
+        let substitution = Substitution::parse("/a\\/b/c").unwrap();
+        assert_eq!(substitution.pattern, "a/b");
+        assert_eq!(without_vim_atoms("\\%Vx\\\\%V\\c"), "x\\\\%V");
+    }
+
+    #[test]
+    fn test_replacement_groups_and_case() {
+        let regex = Regex::new("(\\w+) (\\w+)").unwrap();
+        let captures = regex.captures("hello world").unwrap();
+        let expand = |text: &str| Replacement::parse(text).expand(&captures);
+        assert_eq!(expand("\\2 \\1"), "world hello");
+        assert_eq!(expand("$2-$1 [&] \\0"), "world-hello [hello world] hello world");
+        assert_eq!(expand("\\u\\1 \\U\\2\\E!"), "Hello WORLD!");
+        assert_eq!(expand("\\L\\uAB\\eC"), "AbC");
+        assert_eq!(expand("a\\&b\\rc\\\\"), "a&b\nc\\");
+        assert_eq!(expand("$x $"), "$x $");
+    }
+}
@@ -0,0 +1,142 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::document_model::tags::{TagAddress, TagStackEntry};
+use crate::document_model::{Tag, TagFile};
+use crate::view::ListPopup;
+use crossterm::event::KeyCode;
+
+/// Jumping to definitions from a tags file - Ctrl-] and :tag go to one,
+/// :tselect picks among several, Ctrl-T and :pop come back
+pub struct TagCommands;
+
+impl TagCommands {
+    /// Every definition of `name` in the tags file for the current buffer
+    pub fn lookup(shared: &SharedEditorState, name: &str) -> Result<Vec<Tag>, String> {
+        let current = shared.session_controller.current_document().filename.as_deref();
+        let path = TagFile::find(current).ok_or("No tags file")?;
+        let file = TagFile::load(&path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+        let tags = file.lookup(name);
+        if tags.is_empty() {
+            return Err(format!("tag not found: {}", name));
+        }
+        Ok(tags)
+    }
+
+    /// Ctrl-] and :tag {name} - go to the first definition of `name`
+    pub fn jump(shared: &mut SharedEditorState, name: &str) -> Result<(), String> {
+        let tags = Self::lookup(shared, name)?;
+        Self::go(shared, &tags[0])?;
+        if tags.len() > 1 {
+            shared.status_message = format!("tag 1 of {}, :tselect to choose", tags.len());
+        }
+        Ok(())
+    }
+
+    /// Ctrl-] - the identifier under the cursor
+    pub fn jump_to_word(shared: &mut SharedEditorState) -> Result<(), String> {
+        let word = shared.session_controller.current_document().get_word_under_cursor();
+        Self::jump(shared, &word.ok_or("No identifier under cursor")?)
+    }
+
+    /// Go to `tag`, leaving where the cursor was on the tag stack and the
+    /// jump list
+    pub fn go(shared: &mut SharedEditorState, tag: &Tag) -> Result<(), String> {
+        let doc = shared.session_controller.current_document();
+        let (line, column, filename) = (doc.cursor_line(), doc.cursor_column(), doc.filename.clone());
+        if filename.as_ref() != Some(&tag.path) {
+            shared
+                .session_controller
+                .switch_to_file(&tag.path)
+                .map_err(|e| format!("Can't open {}: {}", tag.path.display(), e))?;
+            shared.view.reset_scroll();
+        }
+        shared.mark_manager.add_to_jump_list(line, column, filename.clone());
+        shared.tag_stack.push(TagStackEntry { name: tag.name.clone(), path: filename, line, column });
+
+        let doc = shared.session_controller.current_document_mut();
+        match tag.line_in(doc) {
+            Some(line) => {
+                let _ = doc.set_cursor(line, 0);
+                doc.move_first_non_whitespace();
+                shared.status_message = format!("\"{}\" {}", tag.path.display(), tag.name);
+            }
+            None => {
+                let _ = doc.set_cursor(0, 0);
+                shared.status_message = format!("Couldn't find tag {} in {}", tag.name, tag.path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Ctrl-T and :pop - back to where the `count`th newest tag jump left
+    pub fn pop(shared: &mut SharedEditorState, count: usize) -> Result<(), String> {
+        let entry = shared.tag_stack.pop(count)?;
+        if let Some(path) = &entry.path
+            && shared.session_controller.current_document().filename.as_ref() != Some(path)
+        {
+            shared
+                .session_controller
+                .switch_to_file(path)
+                .map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+            shared.view.reset_scroll();
+        }
+        let doc = shared.session_controller.current_document_mut();
+        let line = entry.line.min(doc.line_count().saturating_sub(1));
+        let _ = doc.set_cursor(line, entry.column.min(doc.get_line_length(line)));
+        shared.status_message.clear();
+        Ok(())
+    }
+}
+
+/// The :tselect list of a tag's definitions, moved through with j/k and
+/// picked with Enter
+pub struct TagPicker {
+    tags: Vec<Tag>,
+    selected: usize,
+}
+
+impl TagPicker {
+    pub const PROMPT: &str = "tselect (j/k to move, Enter to jump, Esc to close)";
+
+    pub fn new(tags: Vec<Tag>) -> Self {
+        Self { tags, selected: 0 }
+    }
+
+    /// Handle one key. Returns true while the list stays open.
+    pub fn handle_key(&mut self, code: KeyCode, shared: &mut SharedEditorState) -> bool {
+        let last = self.tags.len().saturating_sub(1);
+        match code {
+            KeyCode::Char('j') | KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Char(digit @ '1'..='9') => self.selected = (digit as usize - '1' as usize).min(last),
+            KeyCode::Enter => {
+                if let Err(message) = TagCommands::go(shared, &self.tags[self.selected.min(last)]) {
+                    shared.status_message = message;
+                }
+                return false;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// The definitions as drawn over the bottom of the text area: number,
+    /// kind, file and the line the tag searches for
+    pub fn popup(&self) -> ListPopup {
+        let items = self
+            .tags
+            .iter()
+            .enumerate()
+            .map(|(index, tag)| {
+                let place = match &tag.address {
+                    TagAddress::Line(line) => format!("line {}", line + 1),
+                    TagAddress::Pattern(text) => text.trim().to_string(),
+                };
+                format!("{:>2} {:<2} {}  {}", index + 1, tag.kind, tag.path.display(), place)
+            })
+            .collect();
+        ListPopup { title: format!(" Tag {} ({})", self.tags[0].name, self.tags.len()), items, selected: self.selected }
+    }
+}
@@ -0,0 +1,121 @@
+//! Timers - work the event loop does when its time comes rather than in
+//! answer to an event: writing the swap files once typing pauses,
+//! 'autosave', giving up on keys that may start a mapping, looking for
+//! files changed on disk, ending a 'showmatch' highlight, talking to
+//! language servers, adding what has been read of files loading in the
+//! background, dropping a command typed in part with 'timeout'
+//!
+//! The loop waits for input no longer than until the next timer is due or
+//! the tick comes round, whichever is sooner, then runs the timers that are
+//! due and draws the screen again. Each kind of timer is pending at most
+//! once: scheduling it again moves it.
+
+use std::time::{Duration, Instant};
+
+/// Something to do at a set time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    SwapSync,       // Input paused: write every pending edit to the swap files
+    Autosave,       // A buffer's last edit is old enough for 'autosave'
+    MappingTimeout, // Keys that may start a mapping stand for themselves
+    CheckFiles,     // 'autoread': look for files changed on disk
+    ShowMatch,      // A bracket 'showmatch' highlighted has been shown long enough
+    LanguageServer, // Send language servers the buffers' changes and act on what they said
+    LoadFiles,      // Add the text read of big files loading in the background to their buffers
+    PendingCommand, // 'timeout': a count, register or operator typed in part is dropped
+}
+
+/// How often the screen is drawn while nothing happens, unless set with
+/// :set ticktime
+pub const DEFAULT_TICK: Duration = Duration::from_secs(1);
+
+/// How long keys wait for the rest of a mapping, or with 'timeout' of a
+/// command (vim's default 'timeoutlen')
+pub const DEFAULT_TIMEOUT_LEN: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub struct TimerQueue {
+    pending: Vec<(Timer, Instant)>,
+    tick: Option<Duration>, // None: wait for input or a timer, however long
+    pub timeout_len: Duration, // 'timeoutlen'
+    pub timeout: bool,         // 'timeout': commands typed in part time out as mappings do
+}
+
+impl Default for TimerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self { pending: Vec::new(), tick: Some(DEFAULT_TICK), timeout_len: DEFAULT_TIMEOUT_LEN, timeout: false }
+    }
+
+    pub fn tick(&self) -> Option<Duration> {
+        self.tick
+    }
+
+    /// Set the tick; zero turns it off
+    pub fn set_tick(&mut self, tick: Duration) {
+        self.tick = (!tick.is_zero()).then_some(tick);
+    }
+
+    /// Run `timer` at `at`, in place of any time it already had
+    pub fn schedule(&mut self, timer: Timer, at: Instant) {
+        self.cancel(timer);
+        self.pending.push((timer, at));
+    }
+
+    pub fn cancel(&mut self, timer: Timer) {
+        self.pending.retain(|&(pending, _)| pending != timer);
+    }
+
+    pub fn is_scheduled(&self, timer: Timer) -> bool {
+        self.pending.iter().any(|&(pending, _)| pending == timer)
+    }
+
+    /// How long the event loop may wait for input: until the next timer is
+    /// due or the tick, whichever is sooner. None when neither is set.
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        let next_timer = self.pending.iter().map(|&(_, at)| at.saturating_duration_since(now)).min();
+        next_timer.into_iter().chain(self.tick).min()
+    }
+
+    /// Take the timers that are due, soonest first
+    pub fn take_due(&mut self, now: Instant) -> Vec<Timer> {
+        let (mut due, pending): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|&(_, at)| at <= now);
+        self.pending = pending;
+        due.sort_by_key(|&(_, at)| at);
+        due.into_iter().map(|(timer, _)| timer).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_and_take_due() {
+        let now = Instant::now();
+        let mut timers = TimerQueue::new();
+        assert_eq!(timers.wait(now), Some(DEFAULT_TICK));
+        timers.set_tick(Duration::ZERO);
+        assert_eq!(timers.wait(now), None);
+
+        timers.schedule(Timer::SwapSync, now + Duration::from_secs(4));
+        timers.schedule(Timer::Autosave, now + Duration::from_secs(2));
+        timers.schedule(Timer::SwapSync, now + Duration::from_secs(3)); // Moves it
+        assert_eq!(timers.wait(now), Some(Duration::from_secs(2)));
+        timers.set_tick(Duration::from_millis(500));
+        assert_eq!(timers.wait(now), Some(Duration::from_millis(500)));
+
+        assert!(timers.take_due(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(timers.take_due(now + Duration::from_secs(5)), [Timer::Autosave, Timer::SwapSync]);
+        assert!(!timers.is_scheduled(Timer::SwapSync));
+
+        timers.schedule(Timer::CheckFiles, now);
+        timers.cancel(Timer::CheckFiles);
+        assert!(timers.take_due(now + Duration::from_secs(10)).is_empty());
+    }
+}
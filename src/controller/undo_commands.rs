@@ -0,0 +1,121 @@
+use crate::controller::shared_state::SharedEditorState;
+use std::time::Duration;
+
+/// How far :earlier / :later move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UndoDistance {
+    Changes(usize),
+    Time(Duration),
+}
+
+impl UndoDistance {
+    /// Parse "3", "10s", "5m", "2h" or "1d". No argument means one change.
+    pub fn parse(arg: Option<&str>) -> Option<Self> {
+        let Some(arg) = arg.map(str::trim).filter(|a| !a.is_empty()) else {
+            return Some(UndoDistance::Changes(1));
+        };
+        let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+        let amount: u64 = arg[..split].parse().ok()?;
+        let seconds = match &arg[split..] {
+            "" => return Some(UndoDistance::Changes(amount as usize)),
+            "s" => amount,
+            "m" => amount * 60,
+            "h" => amount * 60 * 60,
+            "d" => amount * 24 * 60 * 60,
+            _ => return None,
+        };
+        Some(UndoDistance::Time(Duration::from_secs(seconds)))
+    }
+}
+
+/// Undo tree navigation - g-, g+, :earlier, :later and :undolist
+pub struct UndoCommands;
+
+impl UndoCommands {
+    /// Move the current buffer to undo state `target`
+    pub fn goto_state(shared: &mut SharedEditorState, target: usize) {
+        let doc = shared.session_controller.current_document_mut();
+        let before = doc.undo_manager().current_state();
+        doc.goto_undo_state(target);
+        let undo = doc.undo_manager();
+        let (state, last) = (undo.current_state(), undo.last_state());
+
+        shared.status_message = if state == before {
+            if target <= state {
+                "Already at oldest change".to_string()
+            } else {
+                "Already at newest change".to_string()
+            }
+        } else {
+            format!("Undo state {} of {}", state, last)
+        };
+    }
+
+    /// g- / :earlier - step back through states in the order they were made
+    pub fn earlier(shared: &mut SharedEditorState, distance: UndoDistance) {
+        let undo = shared.session_controller.current_document().undo_manager();
+        let target = match distance {
+            UndoDistance::Changes(count) => undo.current_state().saturating_sub(count),
+            UndoDistance::Time(age) => undo.state_before(age),
+        };
+        Self::goto_state(shared, target);
+    }
+
+    /// g+ / :later - step forward through states in the order they were made
+    pub fn later(shared: &mut SharedEditorState, distance: UndoDistance) {
+        let undo = shared.session_controller.current_document().undo_manager();
+        let target = match distance {
+            UndoDistance::Changes(count) => undo.current_state() + count,
+            UndoDistance::Time(age) => undo.state_after(age),
+        };
+        Self::goto_state(shared, target);
+    }
+
+    /// :undolist - the tip of every undo branch, in a preview buffer
+    pub fn undolist(shared: &mut SharedEditorState) {
+        let leaves = shared.session_controller.current_document().undo_manager().leaves();
+        if leaves.is_empty() {
+            shared.status_message = "Nothing to undo".to_string();
+            return;
+        }
+
+        let mut list = "number changes  when".to_string();
+        for leaf in &leaves {
+            list.push_str(&format!(
+                "\n{:>6} {:>7}  {}",
+                leaf.state,
+                leaf.changes,
+                Self::format_age(leaf.age)
+            ));
+        }
+        let count = leaves.len();
+        shared.status_message = match shared.session_controller.create_preview_buffer("[Undo List]".to_string(), list) {
+            Ok(_) => format!("{} undo branches listed in preview buffer", count),
+            Err(e) => format!("Error creating preview: {}", e),
+        };
+    }
+
+    fn format_age(age: Duration) -> String {
+        let seconds = age.as_secs();
+        match seconds {
+            0..=59 => format!("{} second{} ago", seconds, if seconds == 1 { "" } else { "s" }),
+            60..=3599 => format!("{} min ago", seconds / 60),
+            _ => format!("{} h ago", seconds / 3600),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_distance() {
+        assert_eq!(UndoDistance::parse(None), Some(UndoDistance::Changes(1)));
+        assert_eq!(UndoDistance::parse(Some("4")), Some(UndoDistance::Changes(4)));
+        assert_eq!(UndoDistance::parse(Some("10s")), Some(UndoDistance::Time(Duration::from_secs(10))));
+        assert_eq!(UndoDistance::parse(Some("2m")), Some(UndoDistance::Time(Duration::from_secs(120))));
+        assert_eq!(UndoDistance::parse(Some("3f")), None);
+        assert_eq!(UndoDistance::parse(Some("s")), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Quitting with unsaved changes - :qa, :wqa / :xa, and the question asked
+//! when :q closes the last window while other buffers are modified
+
+use crate::controller::shared_state::SharedEditorState;
+
+pub struct UnsavedBuffers;
+
+impl UnsavedBuffers {
+    /// Indexes of the modified buffers. A q: history window's edits are
+    /// never written, so they don't count.
+    pub fn list(shared: &SharedEditorState) -> Vec<usize> {
+        let session = &shared.session_controller;
+        (0..session.buffers.len())
+            .filter(|&index| session.buffers[index].is_modified() && session.buffers[index].history_window.is_none())
+            .collect()
+    }
+
+    /// `"a.txt", "b.txt"` for the buffers at `indexes`
+    pub fn names(shared: &SharedEditorState, indexes: &[usize]) -> String {
+        let session = &shared.session_controller;
+        indexes
+            .iter()
+            .map(|&index| format!("\"{}\"", session.buffer_display_name(index)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Save every modified buffer (:wa, :wqa, :xa). Returns how many were
+    /// written, or the errors of those that couldn't be, one per buffer.
+    pub fn write_all(shared: &mut SharedEditorState) -> Result<usize, String> {
+        let results = shared.session_controller.write_all();
+        let errors: Vec<String> = results.iter().filter_map(|(_, result)| result.clone().err()).collect();
+        if errors.is_empty() { Ok(results.len()) } else { Err(errors.join("; ")) }
+    }
+}
+
+/// What an answer to the quit question leads to
+#[derive(Debug, PartialEq)]
+pub enum QuitAnswer {
+    Asking,
+    Quit,
+    Cancel,
+}
+
+/// Asks what to do with modified buffers when :q closes the last window
+pub struct QuitPrompt {
+    pub prompt: String,
+}
+
+impl QuitPrompt {
+    pub fn new(shared: &SharedEditorState, unsaved: &[usize]) -> Self {
+        let prompt = format!(
+            "Unsaved changes in {}: (w)rite all and quit, (q)uit anyway, (c)ancel?",
+            UnsavedBuffers::names(shared, unsaved)
+        );
+        Self { prompt }
+    }
+
+    /// w writes every modified buffer and quits, q quits without writing,
+    /// c cancels. A failed write cancels with the error on the status line.
+    pub fn handle_key(&mut self, key: char, shared: &mut SharedEditorState) -> QuitAnswer {
+        match key {
+            'w' => match UnsavedBuffers::write_all(shared) {
+                Ok(_) => QuitAnswer::Quit,
+                Err(message) => {
+                    shared.status_message = message;
+                    QuitAnswer::Cancel
+                }
+            },
+            'q' => QuitAnswer::Quit,
+            'c' => QuitAnswer::Cancel,
+            _ => QuitAnswer::Asking,
+        }
+    }
+}
@@ -1,17 +1,32 @@
 use crate::controller::shared_state::{ModeController, ModeTransition, SharedEditorState};
 use crate::controller::command_types::{Mode, Command};
+use crate::controller::help_commands::HelpCommands;
 use crate::controller::key_handler::KeyHandler;
+use crate::controller::operator::{Motion, Operator, Operators, SelectionEdits, Span};
 use crate::controller::visual_mode::{Selection, VisualMode, VisualModeHandler};
+use crate::document_model::text_buffer::grapheme_start;
 use crossterm::event::{KeyEvent};
 
 pub struct VisualController {
     pub visual_selection: Option<Selection>,
+    pending_key: Option<char>, // First key of a g command or mark jump
+    number_prefix: Option<usize>, // Count typed before a motion or > and <
+    pending_register: Option<char>, // "x typed before an operator or p
+}
+
+impl Default for VisualController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VisualController {
     pub fn new() -> Self {
         Self {
             visual_selection: None,
+            pending_key: None,
+            number_prefix: None,
+            pending_register: None,
         }
     }
     
@@ -24,6 +39,55 @@ impl VisualController {
         };
         self.visual_selection = Some(Selection::new(line, column, visual_mode));
     }
+
+    /// The text the selection covers now
+    fn selection_span(&self, shared: &SharedEditorState) -> Option<Span> {
+        let selection = self.visual_selection.as_ref()?;
+        Some(selection.span(shared.session_controller.current_document(), shared.view.get_tab_stop()))
+    }
+
+    /// gv: select `selection` again, within the document as it is now. The
+    /// editor starts visual mode with it in place of a new selection.
+    pub fn reselect(shared: &mut SharedEditorState, mut selection: Selection) -> ModeTransition {
+        let doc = shared.session_controller.current_document_mut();
+        let last_line = doc.line_count().saturating_sub(1);
+        // The text may have changed since: each end goes onto a character
+        let snap = |line: usize, column: usize| {
+            let text = doc.get_line(line).unwrap_or_default();
+            grapheme_start(&text, column.min(text.len()))
+        };
+        selection.start_line = selection.start_line.min(last_line);
+        selection.end_line = selection.end_line.min(last_line);
+        selection.start_column = snap(selection.start_line, selection.start_column);
+        selection.end_column = snap(selection.end_line, selection.end_column);
+        let _ = doc.set_cursor(selection.end_line, selection.end_column);
+        let mode = match selection.mode {
+            VisualMode::Char => Mode::VisualChar,
+            VisualMode::Line => Mode::VisualLine,
+            VisualMode::Block => Mode::VisualBlock,
+        };
+        shared.pending_selection = Some(selection);
+        ModeTransition::ToMode(mode)
+    }
+
+    /// The keys of a command typed in part, for 'showcmd'
+    pub fn pending_keys(&self) -> String {
+        KeyHandler::pending_keys(self.pending_key, self.number_prefix, self.pending_register, None)
+    }
+
+    /// Forget a count, register or key typed but not yet used
+    pub fn cancel_pending(&mut self) {
+        self.pending_key = None;
+        self.number_prefix = None;
+        self.pending_register = None;
+    }
+
+    /// Leave visual mode, remembering the selection for gv, '< '> and \%V
+    fn end_selection(&mut self, shared: &mut SharedEditorState) {
+        if let Some(selection) = self.visual_selection.take() {
+            shared.session_controller.current_document_mut().last_selection = Some(selection);
+        }
+    }
 }
 
 impl ModeController for VisualController {
@@ -32,83 +96,169 @@ impl ModeController for VisualController {
         let command = KeyHandler::parse_key_with_state(
             &Mode::VisualChar, // Visual modes use same key parsing
             &key_event,
-            &mut None, // pending_key not used much in visual mode
-            &mut None, // number_prefix could be used but simplified for now
-            &mut None, // pending_register not used much in visual mode
+            &mut self.pending_key,
+            &mut self.number_prefix,
+            &mut self.pending_register,
+            &mut None, // Operators act on the selection straight away
         );
         
         if let Some(command) = command {
+            let count = self.number_prefix.take().unwrap_or(1);
+            if command.changes_text() && HelpCommands::refuse_change(shared) {
+                self.end_selection(shared);
+                return ModeTransition::ToMode(Mode::Normal);
+            }
             match command {
                 Command::ExitVisualMode => {
-                    self.visual_selection = None;
+                    self.end_selection(shared);
                     return ModeTransition::ToMode(Mode::Normal);
                 }
                 
-                // Mode transitions from visual mode
-                Command::EnterInsertMode => {
-                    // Delete selection and enter insert mode
-                    if let Some(selection) = &self.visual_selection {
-                        VisualModeHandler::delete_selection(selection, shared.session_controller.current_document_mut());
+                Command::EnterCommandMode => {
+                    self.end_selection(shared);
+                    return ModeTransition::ToMode(Mode::Command);
+                }
+
+                // / and ? search inside the selection
+                Command::EnterSearchMode | Command::EnterSearchBackwardMode => {
+                    self.end_selection(shared);
+                    let forward = matches!(command, Command::EnterSearchMode);
+                    return ModeTransition::ToMode(if forward { Mode::Search } else { Mode::SearchBackward });
+                }
+
+                // Operators act on the selection and leave visual mode
+                Command::VisualOperate(operator) => {
+                    let Some(selection) = self.visual_selection.clone() else {
+                        return ModeTransition::ToMode(Mode::Normal);
+                    };
+                    let span = selection.span(shared.session_controller.current_document(), shared.view.get_tab_stop());
+                    let transition = match operator {
+                        // 3> shifts the lines three times as far
+                        Operator::Indent | Operator::Dedent => Operators::shift(shared, operator, span, count),
+                        _ => Operators::apply(shared, operator, span, self.pending_register.take()),
+                    };
+                    // c on a block types into every row, as I does
+                    if operator == Operator::Change && selection.mode == VisualMode::Block {
+                        let doc = shared.session_controller.current_document_mut();
+                        shared.block_insert = Some(VisualModeHandler::start_block_insert(&selection, doc, false));
                     }
-                    self.visual_selection = None;
-                    let doc = shared.session_controller.current_document();
-                    let cursor_pos = (doc.cursor_line(), doc.cursor_column());
-                    shared.session_controller.current_document_mut()
-                        .undo_manager_mut()
-                        .start_group(cursor_pos);
-                    return ModeTransition::ToMode(Mode::Insert);
+                    self.end_selection(shared);
+                    return match transition {
+                        ModeTransition::Stay => ModeTransition::ToMode(Mode::Normal),
+                        transition => transition,
+                    };
                 }
-                
-                // Visual mode operations
-                Command::Yank(_, _) => {
-                    if let Some(selection) = &self.visual_selection {
-                        let selected_text = VisualModeHandler::get_selected_text(selection, shared.session_controller.current_document());
-                        let register_type = match selection.mode {
-                            VisualMode::Line => crate::document_model::RegisterType::Line,
-                            VisualMode::Char => crate::document_model::RegisterType::Character,
-                            VisualMode::Block => crate::document_model::RegisterType::Block,
-                        };
-                        shared.register_manager.store_in_register(None, selected_text, register_type);
+
+                // r, J, p and P act on the selection and leave visual mode
+                Command::VisualReplace(c) => {
+                    if let Some(span) = self.selection_span(shared) {
+                        SelectionEdits::replace(shared, span, c);
                     }
-                    self.visual_selection = None;
+                    self.end_selection(shared);
                     return ModeTransition::ToMode(Mode::Normal);
                 }
-                
-                Command::DeleteChar => {
-                    if let Some(selection) = &self.visual_selection {
-                        VisualModeHandler::delete_selection(selection, shared.session_controller.current_document_mut());
+                Command::VisualJoin => {
+                    if let Some(span) = self.selection_span(shared) {
+                        SelectionEdits::join(shared, span);
                     }
-                    self.visual_selection = None;
+                    self.end_selection(shared);
                     return ModeTransition::ToMode(Mode::Normal);
                 }
-                
-                Command::IndentLine => {
-                    if let Some(selection) = &self.visual_selection {
-                        VisualModeHandler::indent_selection(selection, shared.session_controller.current_document_mut(), shared.view.get_tab_stop(), true); // Default to spaces
+                Command::VisualPut(keep) => {
+                    if let Some(span) = self.selection_span(shared) {
+                        SelectionEdits::put(shared, span, self.pending_register.take(), keep, count);
                     }
-                    // Stay in visual mode after indenting
+                    self.end_selection(shared);
+                    return ModeTransition::ToMode(Mode::Normal);
                 }
-                
-                Command::DedentLine => {
+
+                // o and O move the cursor to the other end, the selection staying put
+                Command::VisualSwapEnds(corner) => {
+                    if let Some(selection) = &mut self.visual_selection {
+                        let (line, column) = if corner { selection.swap_corners() } else { selection.swap_ends() };
+                        let _ = shared.session_controller.current_document_mut().set_cursor(line, column);
+                    }
+                }
+
+                // gv swaps the selection for the one before it
+                Command::ReselectVisual => {
+                    let doc = shared.session_controller.current_document_mut();
+                    let Some(last) = doc.last_selection.take() else {
+                        shared.status_message = "No previous visual selection".to_string();
+                        return ModeTransition::Stay;
+                    };
+                    doc.last_selection = self.visual_selection.take();
+                    return Self::reselect(shared, last);
+                }
+
+                Command::VisualBlockInsert | Command::VisualBlockAppend => {
+                    let Some(selection) = self.visual_selection.as_ref().filter(|s| s.mode == VisualMode::Block) else {
+                        shared.status_message = "I and A need a block selection (Ctrl-v)".to_string();
+                        return ModeTransition::Stay;
+                    };
+                    let doc = shared.session_controller.current_document_mut();
+                    let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+                    doc.undo_manager_mut().start_group(cursor_pos);
+                    let append = matches!(command, Command::VisualBlockAppend);
+                    shared.block_insert = Some(VisualModeHandler::start_block_insert(selection, doc, append));
+                    self.end_selection(shared);
+                    return ModeTransition::ToMode(Mode::Insert);
+                }
+
+                Command::ToggleBlockComment => {
                     if let Some(selection) = &self.visual_selection {
-                        VisualModeHandler::dedent_selection(selection, shared.session_controller.current_document_mut(), shared.view.get_tab_stop());
+                        let doc = shared.session_controller.current_document_mut();
+                        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+                        doc.undo_manager_mut().start_group(cursor_pos);
+                        let result = VisualModeHandler::toggle_block_comment(selection, doc);
+                        let cursor_pos = (doc.cursor_line(), doc.cursor_column());
+                        doc.undo_manager_mut().end_group(cursor_pos);
+
+                        shared.status_message = match result {
+                            Ok(true) => "Block comment added".to_string(),
+                            Ok(false) => "Block comment removed".to_string(),
+                            Err(e) => e.to_string(),
+                        };
                     }
-                    // Stay in visual mode after dedenting
+                    self.end_selection(shared);
+                    return ModeTransition::ToMode(Mode::Normal);
                 }
                 
-                // Movement commands - update selection
-                Command::MoveUp | Command::MoveDown | Command::MoveLeft | Command::MoveRight |
-                Command::MoveWordForward | Command::MoveWordBackward | Command::MoveLineStart | Command::MoveLineEnd => {
-                    // Execute the movement command on the document
-                    self.execute_movement_command(command, shared);
-                    
-                    // Update selection end position
+                // Motions move the cursor and the selection's end with it
+                Command::Motion(motion) => {
+                    // $ makes a block ragged-right until the next sideways move
+                    let to_line_end = match motion {
+                        Motion::LineEnd => Some(true),
+                        Motion::Up | Motion::Down => None,
+                        _ => Some(false),
+                    };
+
+                    let doc = shared.session_controller.current_document_mut();
+                    motion.move_cursor(doc, count);
                     if let Some(selection) = &mut self.visual_selection {
-                        let doc = shared.session_controller.current_document();
                         selection.update_end(doc.cursor_line(), doc.cursor_column());
+                        if let Some(to_line_end) = to_line_end {
+                            selection.to_line_end = to_line_end && selection.mode == VisualMode::Block;
+                        }
                     }
                 }
-                
+
+                // 'a and `a extend the selection to a mark
+                Command::JumpToMark(mark_char) | Command::JumpToMarkLine(mark_char) => {
+                    let Some((line, column)) = shared.mark_position(mark_char) else {
+                        shared.status_message = "Mark not set".to_string();
+                        return ModeTransition::Stay;
+                    };
+                    let doc = shared.session_controller.current_document_mut();
+                    let _ = doc.set_cursor(line, column);
+                    if matches!(command, Command::JumpToMarkLine(_)) {
+                        doc.move_first_non_whitespace();
+                    }
+                    if let Some(selection) = &mut self.visual_selection {
+                        selection.update_end(doc.cursor_line(), doc.cursor_column());
+                    }
+                }
+
                 _ => {
                     // Unhandled command in visual mode
                     shared.status_message = format!("Unhandled command in visual mode: {:?}", command);
@@ -120,19 +270,3 @@ impl ModeController for VisualController {
     }
 }
 
-impl VisualController {
-    fn execute_movement_command(&self, command: Command, shared: &mut SharedEditorState) {
-        match command {
-            Command::MoveUp => { let _ = shared.session_controller.current_document_mut().move_cursor_up(); },
-            Command::MoveDown => { let _ = shared.session_controller.current_document_mut().move_cursor_down(); },
-            Command::MoveLeft => { let _ = shared.session_controller.current_document_mut().move_cursor_left(); },
-            Command::MoveRight => { let _ = shared.session_controller.current_document_mut().move_cursor_right(); },
-            Command::MoveWordForward => shared.session_controller.current_document_mut().move_word_forward(),
-            Command::MoveWordBackward => shared.session_controller.current_document_mut().move_word_backward(),
-            Command::MoveLineStart => shared.session_controller.current_document_mut().move_line_start(),
-            Command::MoveLineEnd => shared.session_controller.current_document_mut().move_line_end(),
-            // Add more movement commands as needed
-            _ => {}
-        }
-    }
-}
\ No newline at end of file
@@ -1,119 +1,93 @@
+use crate::controller::operator::{Span, SpanKind};
 use crate::document_model::Document;
+use crate::document_model::comment::{self, CommentError};
+use crate::document_model::text_buffer::{cells_at, display_column, grapheme_start, next_grapheme};
+
+pub use crate::document_model::selection::{LINE_END, Selection, VisualMode};
 
 // Helper function to get line count efficiently
 fn get_line_count(document: &Document) -> usize {
     document.line_count()
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum VisualMode {
-    Char,
-    Line,
-    Block,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub struct Selection {
-    pub start_line: usize,
-    pub start_column: usize,
-    pub end_line: usize,
-    pub end_column: usize,
-    pub mode: VisualMode,
-}
-
 impl Selection {
-    pub fn new(line: usize, column: usize, mode: VisualMode) -> Self {
-        Self {
-            start_line: line,
-            start_column: column,
-            end_line: line,
-            end_column: column,
-            mode,
-        }
-    }
-
-    pub fn update_end(&mut self, line: usize, column: usize) {
-        self.end_line = line;
-        self.end_column = column;
-    }
-
-    pub fn get_ordered_bounds(&self) -> (usize, usize, usize, usize) {
-        let (start_line, start_col, end_line, end_col) = if self.start_line < self.end_line
-            || (self.start_line == self.end_line && self.start_column <= self.end_column)
-        {
-            (
-                self.start_line,
-                self.start_column,
-                self.end_line,
-                self.end_column,
-            )
-        } else {
-            (
-                self.end_line,
-                self.end_column,
-                self.start_line,
-                self.start_column,
-            )
-        };
-
+    /// The selection as the span an operator works on. A characterwise
+    /// selection takes the character under its end, or the line break
+    /// when it ends past the last character. A block covers the screen
+    /// columns between its corners, laid out with `tab_stop`.
+    pub fn span(&self, document: &Document, tab_stop: usize) -> Span {
+        let (start_line, start_col, end_line, end_col) = self.get_ordered_bounds();
         match self.mode {
-            VisualMode::Line => {
-                // Line mode always selects entire lines
-                (start_line, 0, end_line, usize::MAX)
+            VisualMode::Line => Span { start: (start_line, 0), end: (end_line, 0), kind: SpanKind::Lines },
+            VisualMode::Block => {
+                // The screen columns each corner's character is drawn over
+                let cells = |line: usize, column: usize| {
+                    let text = document.get_line(line).unwrap_or_default();
+                    let column = grapheme_start(&text, column);
+                    let at = display_column(&text, column, tab_stop);
+                    let grapheme = &text[column..next_grapheme(&text, column)];
+                    (at, at + cells_at(grapheme, at, tab_stop) - 1)
+                };
+                let (start_at, start_last) = cells(self.start_line, self.start_column);
+                let (end_at, end_last) = cells(self.end_line, self.end_column);
+                let right = if self.to_line_end { LINE_END } else { start_last.max(end_last) };
+                Span { start: (start_line, start_at.min(end_at)), end: (end_line, right), kind: SpanKind::Block(tab_stop) }
             }
             VisualMode::Char => {
-                // Character mode uses exact positions
-                (start_line, start_col, end_line, end_col)
-            }
-            VisualMode::Block => {
-                // Block mode maintains column boundaries
-                let left_col = start_col.min(end_col);
-                let right_col = start_col.max(end_col);
-                (start_line, left_col, end_line, right_col)
+                let line = document.get_line(end_line).unwrap_or_default();
+                let end = if end_col >= line.len() && end_line + 1 < document.line_count() {
+                    (end_line + 1, 0)
+                } else {
+                    (end_line, next_grapheme(&line, end_col.min(line.len())))
+                };
+                Span { start: (start_line, start_col), end, kind: SpanKind::Characters }
             }
         }
     }
+}
 
-    pub fn is_line_in_selection(&self, line: usize) -> bool {
-        let (start_line, _, end_line, _) = self.get_ordered_bounds();
-        line >= start_line && line <= end_line
-    }
+/// A pending block insert or append (Ctrl-v ... I / A). Text typed on the
+/// first line is repeated on the other lines when insert mode ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockInsert {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub column: usize,      // Insert column on every row (unused with to_line_end)
+    pub append: bool,       // A pads short rows out to the column; I skips them
+    pub to_line_end: bool,  // $ A: append at each row's own line end
+    first_line_len: usize,  // First row's length when typing started
+    line_count: usize,
+}
 
-    #[allow(dead_code)] // Will be used for visual selection highlighting
-    pub fn get_selected_range_for_line(
-        &self,
-        line: usize,
-        line_len: usize,
-    ) -> Option<(usize, usize)> {
-        if !self.is_line_in_selection(line) {
-            return None;
+impl BlockInsert {
+    /// Copy what was typed on the first row to the rest of the block
+    pub fn finish(&self, document: &mut Document) {
+        // Like vim, only a single-line insert on the first row is repeated
+        if document.line_count() != self.line_count || document.cursor_line() != self.start_line {
+            return;
         }
+        let first = document.get_line(self.start_line).unwrap_or_default();
+        let inserted_len = first.len().saturating_sub(self.first_line_len);
+        let column = if self.to_line_end { self.first_line_len } else { self.column };
+        let Some(text) = first.get(column..column + inserted_len).filter(|t| !t.is_empty()) else {
+            return;
+        };
 
-        let (start_line, start_col, end_line, end_col) = self.get_ordered_bounds();
-
-        match self.mode {
-            VisualMode::Line => {
-                // Entire line is selected
-                Some((0, line_len))
-            }
-            VisualMode::Char => {
-                let left = if line == start_line { start_col } else { 0 };
-                let right = if line == end_line {
-                    end_col.min(line_len)
-                } else {
-                    line_len
-                };
-                Some((left, right))
-            }
-            VisualMode::Block => {
-                // Block selection uses column boundaries
-                let left = start_col.min(line_len);
-                let right = end_col.min(line_len);
-                if left <= right {
-                    Some((left, right))
-                } else {
-                    None
+        for line_idx in (self.start_line + 1)..=self.end_line.min(get_line_count(document) - 1) {
+            let mut line = document.get_line(line_idx).unwrap_or_default();
+            let at = if self.to_line_end {
+                line.len()
+            } else if !self.append && self.column > 0 && line.len() <= self.column {
+                continue; // Row ends before the block: nothing to insert into
+            } else {
+                if line.len() < self.column {
+                    line.push_str(&" ".repeat(self.column - line.len()));
                 }
+                self.column
+            };
+            if line.is_char_boundary(at) {
+                line.insert_str(at, text);
+                document.replace_line_with_undo(line_idx, &line);
             }
         }
     }
@@ -122,248 +96,78 @@ impl Selection {
 pub struct VisualModeHandler;
 
 impl VisualModeHandler {
-    #[allow(dead_code)] // Will be used for copy/paste operations
-    pub fn get_selected_text(selection: &Selection, document: &Document) -> String {
-        let mut result = String::new();
-        let (start_line, start_col, end_line, end_col) = selection.get_ordered_bounds();
-
-        match selection.mode {
-            VisualMode::Char => {
-                if start_line == end_line {
-                    // Single line selection
-                    let line = document.get_line(start_line).unwrap_or_default();
-                    let end = end_col.min(line.len());
-                    if start_col < line.len() && start_col < end {
-                        result.push_str(&line[start_col..end]);
-                    }
-                } else {
-                    // Multi-line selection
-                    // First line
-                    let first_line = document.get_line(start_line).unwrap_or_default();
-                    if start_col < first_line.len() {
-                        result.push_str(&first_line[start_col..]);
-                    }
-                    result.push('\n');
-
-                    // Middle lines
-                    for line_idx in (start_line + 1)..end_line {
-                        if line_idx < get_line_count(document) {
-                            result.push_str(&document.get_line(line_idx).unwrap_or_default());
-                            result.push('\n');
-                        }
-                    }
+    /// Block I / A: put the cursor where typing starts on the first row,
+    /// padding that row with spaces if an append column lies past its end
+    pub fn start_block_insert(selection: &Selection, document: &mut Document, append: bool) -> BlockInsert {
+        let (start_line, left_col, end_line, right_col) = selection.get_ordered_bounds();
+        let first = document.get_line(start_line).unwrap_or_default();
+
+        let column = if !append {
+            left_col
+        } else if right_col == LINE_END {
+            first.len()
+        } else {
+            // Just after the block's last column
+            let width = first.get(right_col..).and_then(|rest| rest.chars().next()).map_or(1, char::len_utf8);
+            right_col + width
+        };
 
-                    // Last line
-                    if end_line < get_line_count(document) {
-                        let last_line = &document.get_line(end_line).unwrap_or_default();
-                        let end = end_col.min(last_line.len());
-                        result.push_str(&last_line[..end]);
-                    }
-                }
-            }
-            VisualMode::Line => {
-                // Select entire lines
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        result.push_str(&document.get_line(line_idx).unwrap_or_default());
-                        if line_idx < end_line {
-                            result.push('\n');
-                        }
-                    }
-                }
-            }
-            VisualMode::Block => {
-                // Block selection - rectangular region
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        let line = &document.get_line(line_idx).unwrap_or_default();
-                        let left = start_col.min(line.len());
-                        let right = end_col.min(line.len());
-                        if left < right {
-                            result.push_str(&line[left..right]);
-                        }
-                        if line_idx < end_line {
-                            result.push('\n');
-                        }
-                    }
-                }
-            }
+        if column > first.len() {
+            let padded = format!("{}{}", first, " ".repeat(column - first.len()));
+            document.replace_line_with_undo(start_line, &padded);
         }
-
-        result
-    }
-
-    pub fn delete_selection(selection: &Selection, document: &mut Document) {
-        let (start_line, start_col, end_line, end_col) = selection.get_ordered_bounds();
-
-        match selection.mode {
-            VisualMode::Char => {
-                if start_line == end_line {
-                    // Single line deletion
-                    let line = &mut document.get_line(start_line).unwrap_or_default();
-                    let end = end_col.min(line.len());
-                    if start_col < line.len() && start_col < end {
-                        line.drain(start_col..end);
-                    }
-                } else {
-                    // Multi-line deletion
-                    // Get the remaining parts of first and last lines
-                    let first_line_start = if start_line < get_line_count(document) {
-                        document.get_line(start_line).unwrap_or_default()
-                            [..start_col.min(document.get_line(start_line).unwrap_or_default().len())]
-                            .to_string()
-                    } else {
-                        String::new()
-                    };
-
-                    let last_line_end = if end_line < get_line_count(document) {
-                        let last_line = &document.get_line(end_line).unwrap_or_default();
-                        let end_pos = end_col.min(last_line.len());
-                        last_line[end_pos..].to_string()
-                    } else {
-                        String::new()
-                    };
-
-                    // Remove all lines in the selection
-                    for _ in start_line..=end_line.min(get_line_count(document) - 1) {
-                        if start_line < get_line_count(document) {
-                            document.delete_line_at(start_line);
-                        }
-                    }
-
-                    // Insert the combined line
-                    let combined_line = first_line_start + &last_line_end;
-                    if start_line <= get_line_count(document) {
-                        document.set_line(start_line, &combined_line);
-                    } else {
-                        document.insert_line_at(document.line_count(), &combined_line);
-                    }
-                }
-            }
-            VisualMode::Line => {
-                // Delete entire lines
-                for _ in start_line..=end_line.min(get_line_count(document) - 1) {
-                    if start_line < get_line_count(document) {
-                        document.delete_line_at(start_line);
-                    }
-                }
-
-                // Ensure we have at least one line
-                if document.is_empty() {
-                    document.insert_line_at(0, "");
-                }
-            }
-            VisualMode::Block => {
-                // Block deletion - remove rectangular region from each line
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        let line = &mut document.get_line(line_idx).unwrap_or_default();
-                        let left = start_col.min(line.len());
-                        let right = end_col.min(line.len());
-                        if left < right {
-                            line.drain(left..right);
-                        }
-                    }
-                }
-            }
+        let first_line_len = document.get_line_length(start_line);
+        document.move_cursor_to(start_line, column.min(first_line_len));
+
+        BlockInsert {
+            start_line,
+            end_line,
+            column,
+            append,
+            to_line_end: append && right_col == LINE_END,
+            first_line_len,
+            line_count: get_line_count(document),
         }
-
-        // Update cursor position
-        let safe_line = start_line.min(get_line_count(document) - 1);
-        let safe_col = start_col.min(document.get_line(safe_line).unwrap_or_default().len());
-        document.move_cursor_to(safe_line, safe_col);
-        document.modified = true;
     }
 
-    pub fn indent_selection(
+    /// Toggle one block comment around the selection (gb). Line and block
+    /// selections cover whole lines; character selections the exact text.
+    pub fn toggle_block_comment(
         selection: &Selection,
         document: &mut Document,
-        tab_width: usize,
-        use_spaces: bool,
-    ) {
-        let (start_line, _, end_line, _) = selection.get_ordered_bounds();
-        let indent = if use_spaces {
-            " ".repeat(tab_width)
-        } else {
-            "\t".to_string()
-        };
+    ) -> Result<bool, CommentError> {
+        let (start_line, start_col, end_line, end_col) = selection.get_ordered_bounds();
+        let end_line = end_line.min(get_line_count(document).saturating_sub(1));
 
-        match selection.mode {
-            VisualMode::Line | VisualMode::Char => {
-                // Indent entire lines
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        document.get_line(line_idx).unwrap_or_default().insert_str(0, &indent);
-                    }
-                }
+        let (start_col, end_col) = match selection.mode {
+            VisualMode::Char => {
+                // The selection includes the character under its end
+                let last = document.get_line(end_line).unwrap_or_default();
+                let end_len = last.get(end_col..).and_then(|rest| rest.chars().next()).map_or(0, char::len_utf8);
+                (start_col, end_col + end_len)
             }
-            VisualMode::Block => {
-                // Block indent - insert at the left column of the block
-                let (_, start_col, _, _) = selection.get_ordered_bounds();
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        let line = &mut document.get_line(line_idx).unwrap_or_default();
-                        if start_col <= line.len() {
-                            line.insert_str(start_col, &indent);
-                        }
-                    }
-                }
+            VisualMode::Line | VisualMode::Block => {
+                let lines: Vec<String> = (start_line..=end_line).filter_map(|line| document.get_line(line)).collect();
+                comment::line_range(&lines)
             }
-        }
+        };
 
-        document.modified = true;
+        let commented = document.toggle_block_comment_with_undo((start_line, start_col), (end_line, end_col))?;
+        document.move_cursor_to(start_line, start_col);
+        Ok(commented)
     }
+}
 
-    pub fn dedent_selection(selection: &Selection, document: &mut Document, tab_width: usize) {
-        let (start_line, _, end_line, _) = selection.get_ordered_bounds();
-
-        match selection.mode {
-            VisualMode::Line | VisualMode::Char => {
-                // Dedent entire lines
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        let line = &mut document.get_line(line_idx).unwrap_or_default();
-
-                        // Try to remove a tab first
-                        if line.starts_with('\t') {
-                            line.remove(0);
-                        } else {
-                            // Try to remove spaces up to tab_width
-                            let mut removed = 0;
-                            while removed < tab_width && line.starts_with(' ') {
-                                line.remove(0);
-                                removed += 1;
-                            }
-                        }
-                    }
-                }
-            }
-            VisualMode::Block => {
-                // Block dedent - remove from the left column of the block
-                let (_, start_col, _, _) = selection.get_ordered_bounds();
-                for line_idx in start_line..=end_line {
-                    if line_idx < get_line_count(document) {
-                        let line = &mut document.get_line(line_idx).unwrap_or_default();
-                        if start_col < line.len() {
-                            if line.chars().nth(start_col) == Some('\t') {
-                                line.remove(start_col);
-                            } else {
-                                // Try to remove spaces
-                                let mut removed = 0;
-                                while removed < tab_width
-                                    && start_col < line.len()
-                                    && line.chars().nth(start_col) == Some(' ')
-                                {
-                                    line.remove(start_col);
-                                    removed += 1;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        document.modified = true;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_span_with_a_corner_inside_a_character() {
+        let document = Document::from_string("aé\naé".to_string());
+        let mut selection = Selection::new(0, 2, VisualMode::Block); // Inside é
+        selection.update_end(1, 2);
+        let span = selection.span(&document, 8);
+        assert_eq!((span.start, span.end), ((0, 1), (1, 1)));
     }
 }
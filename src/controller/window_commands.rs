@@ -0,0 +1,104 @@
+use crate::controller::shared_state::SharedEditorState;
+use crate::view::SplitDirection;
+use crate::view::window::WindowId;
+
+/// Split window handlers - keep each window's buffer, cursor and scroll
+/// position in step with the live document and view as focus moves
+pub struct WindowCommands;
+
+impl WindowCommands {
+    /// Copy the live buffer, cursor, scroll position and alternate file
+    /// into the active window
+    pub fn save_active(shared: &mut SharedEditorState) {
+        shared.note_buffer_switch();
+        let alternate = shared.session_controller.alternate.clone();
+        let doc = shared.session_controller.current_document();
+        let cursor = (doc.cursor_line(), doc.cursor_column());
+        let buffer_index = shared.session_controller.current_buffer_index();
+        let (scroll_offset, horizontal_scroll) = (shared.view.get_scroll_offset(), shared.view.get_horizontal_scroll());
+
+        let window = shared.view.windows_mut().active_mut();
+        window.buffer_index = buffer_index;
+        window.cursor = cursor;
+        window.scroll_offset = scroll_offset;
+        window.horizontal_scroll = horizontal_scroll;
+        window.alternate = alternate;
+    }
+
+    /// Make the active window's saved state live again
+    fn restore_active(shared: &mut SharedEditorState) {
+        let window = shared.view.windows().active().clone();
+        let last_buffer = shared.session_controller.buffer_count().saturating_sub(1);
+        shared.session_controller.current_buffer = window.buffer_index.min(last_buffer);
+        shared
+            .session_controller
+            .current_document_mut()
+            .move_cursor_to(window.cursor.0, window.cursor.1);
+        shared.view.set_scroll_position(window.scroll_offset, window.horizontal_scroll);
+        shared.session_controller.set_alternate(window.alternate);
+    }
+
+    /// Move focus to another window
+    pub fn focus(shared: &mut SharedEditorState, id: WindowId) {
+        if id == shared.view.windows().active_id() {
+            return;
+        }
+        Self::save_active(shared);
+        shared.view.windows_mut().set_active(id);
+        Self::restore_active(shared);
+    }
+
+    /// :split / :vsplit - the new window shows the current buffer at the same position
+    pub fn split(shared: &mut SharedEditorState, direction: SplitDirection) {
+        Self::save_active(shared);
+        shared.view.windows_mut().split(direction);
+        shared.status_message = format!("{} windows", shared.view.windows().count());
+    }
+
+    /// :close - close the active window. Returns false if it is the last one.
+    pub fn close(shared: &mut SharedEditorState) -> bool {
+        let active = shared.view.windows().active_id();
+        if !shared.view.windows_mut().close(active) {
+            shared.status_message = "Cannot close last window".to_string();
+            return false;
+        }
+        Self::restore_active(shared);
+        shared.status_message = format!("{} windows", shared.view.windows().count());
+        true
+    }
+
+    /// :only - close every other window
+    pub fn only(shared: &mut SharedEditorState) {
+        let closed = shared.view.windows_mut().only();
+        shared.status_message = if closed == 0 {
+            "Already only one window".to_string()
+        } else {
+            format!("{} windows closed", closed)
+        };
+    }
+
+    /// Ctrl-W {c} window commands
+    pub fn execute_key(shared: &mut SharedEditorState, key: char) {
+        match key {
+            's' | 'S' => Self::split(shared, SplitDirection::Horizontal),
+            'v' => Self::split(shared, SplitDirection::Vertical),
+            'c' | 'q' => {
+                Self::close(shared);
+            }
+            'o' => Self::only(shared),
+            'w' | 'W' | 'p' => {
+                let next = shared.view.windows().cycle(key == 'w');
+                Self::focus(shared, next);
+            }
+            'h' | 'j' | 'k' | 'l' => {
+                let area = shared.view.window_area();
+                if let Some(id) = shared.view.windows().neighbor(area, key) {
+                    Self::focus(shared, id);
+                }
+            }
+            _ => {
+                shared.status_message = format!("Unknown window command: Ctrl-W {}", key);
+            }
+        }
+    }
+}
@@ -4,31 +4,11 @@ use crate::document_model::Document;
 fn get_line_count(document: &Document) -> usize {
     document.line_count()
 }
-use crate::document_model::RegisterType;
+use crate::document_model::{RegisterType, registers::RegisterData};
+use crate::document_model::text_buffer::{cells_at, display_column, grapheme_at_display_column, next_grapheme};
 
 pub struct YankPasteHandler;
 
-#[derive(Debug, Clone)]
-pub enum YankType {
-    Line,
-    Lines(usize),
-    Word,
-    BigWord,
-    WordBackward,
-    BigWordBackward,
-    ToEndOfWord,
-    ToEndOfBigWord,
-    ToStartOfLine,
-    ToEndOfLine,
-    ToFirstNonWhitespace,
-    ToEndOfFile,
-    ToStartOfFile,
-    UntilChar(char),
-    UntilCharBackward(char),
-    FindChar(char),
-    FindCharBackward(char),
-}
-
 #[derive(Debug, Clone)]
 pub enum PasteType {
     After,
@@ -37,111 +17,169 @@ pub enum PasteType {
 
 impl YankPasteHandler {
 
-    fn get_yank_content(yank_type: &YankType, document: &Document) -> (String, RegisterType) {
-        match yank_type {
-            YankType::Line => (document.yank_line(), RegisterType::Line),
-            YankType::Lines(count) => {
-                let mut lines = Vec::new();
-                for i in 0..*count {
-                    let line_idx = document.cursor_line() + i;
-                    if line_idx < get_line_count(document) {
-                        lines.push(document.get_line(line_idx).unwrap_or_default());
-                    } else {
-                        break;
-                    }
-                }
-                (lines.join("\n"), RegisterType::Line)
-            }
-            YankType::Word => (document.yank_word_forward(), RegisterType::Character),
-            YankType::BigWord => (document.yank_big_word_forward(), RegisterType::Character),
-            YankType::WordBackward => (document.yank_word_backward(), RegisterType::Character),
-            YankType::BigWordBackward => {
-                (document.yank_big_word_backward(), RegisterType::Character)
-            }
-            YankType::ToEndOfWord => (document.yank_to_end_of_word(), RegisterType::Character),
-            YankType::ToEndOfBigWord => {
-                (document.yank_to_end_of_big_word(), RegisterType::Character)
-            }
-            YankType::ToStartOfLine => (document.yank_to_start_of_line(), RegisterType::Character),
-            YankType::ToEndOfLine => (document.yank_to_end_of_line(), RegisterType::Character),
-            YankType::ToFirstNonWhitespace => (
-                document.yank_to_first_non_whitespace(),
-                RegisterType::Character,
-            ),
-            YankType::ToEndOfFile => (document.yank_to_end_of_file(), RegisterType::Block),
-            YankType::ToStartOfFile => (document.yank_to_start_of_file(), RegisterType::Block),
-            YankType::UntilChar(target) => {
-                (document.yank_until_char(*target), RegisterType::Character)
-            }
-            YankType::UntilCharBackward(target) => (
-                document.yank_until_char_backward(*target),
-                RegisterType::Character,
-            ),
-            YankType::FindChar(target) => {
-                (document.yank_find_char(*target), RegisterType::Character)
-            }
-            YankType::FindCharBackward(target) => (
-                document.yank_find_char_backward(*target),
-                RegisterType::Character,
-            ),
-        }
-    }
-
+    /// Paste `count` copies of `content` as one undo step: whole lines go
+    /// below or above the cursor's line wherever the cursor is, text within
+    /// lines goes after or at the cursor, and a block goes in as a column,
+    /// lined up on the screen with tabs laid out by `tab_stop`
     fn paste_content(
         document: &mut Document,
         content: &str,
         register_type: &RegisterType,
         paste_type: &PasteType,
+        count: usize,
+        tab_stop: usize,
     ) {
+        let cursor_pos = (document.cursor_line(), document.cursor_column());
+        document.undo_manager_mut().start_group(cursor_pos);
+
         match register_type {
+            RegisterType::Block => Self::paste_block_wise(document, content, paste_type, count, tab_stop),
             RegisterType::Line => {
-                Self::paste_line_wise(document, content, paste_type);
+                let content = vec![content; count].join("\n");
+                if document.wrap_paste && document.text_width > 0 {
+                    Self::paste_wrapped(document, &content, register_type, paste_type, tab_stop);
+                } else {
+                    Self::paste_line_wise(document, &content, paste_type);
+                }
             }
-            RegisterType::Character | RegisterType::Block => {
-                Self::paste_character_wise(document, content, paste_type);
+            RegisterType::Character => {
+                let content = content.repeat(count);
+                if document.wrap_paste && document.text_width > 0 {
+                    Self::paste_wrapped(document, &content, register_type, paste_type, tab_stop);
+                } else {
+                    Self::paste_character_wise(document, &content, paste_type);
+                }
             }
         }
         document.modified = true;
+
+        let cursor_pos = (document.cursor_line(), document.cursor_column());
+        document.undo_manager_mut().end_group(cursor_pos);
     }
 
     fn paste_line_wise(document: &mut Document, content: &str, paste_type: &PasteType) {
-        let lines: Vec<&str> = content.lines().collect();
+        let lines: Vec<&str> = content.split('\n').collect();
         let insert_line = match paste_type {
             PasteType::After => document.cursor_line() + 1,
             PasteType::Before => document.cursor_line(),
         };
 
         for (i, line) in lines.iter().enumerate() {
-            document.insert_line_at(insert_line + i, line);
+            document.insert_line_with_undo(insert_line + i, line);
         }
 
-        // Move cursor to first line of pasted content
+        // Move cursor to the first non-blank of the first pasted line
         document.move_cursor_to(insert_line, 0);
+        document.move_first_non_whitespace();
+    }
+
+    /// Paste a block's rows into successive lines at the cursor's screen
+    /// column, each row `count` times over. Short lines are padded out to
+    /// the column and lines are added past the end; a tab the column falls
+    /// inside is split into spaces. Rows are padded to the block's width
+    /// where text follows them, so it stays in line.
+    fn paste_block_wise(document: &mut Document, content: &str, paste_type: &PasteType, count: usize, tab_stop: usize) {
+        let first_line = document.cursor_line();
+        let line = document.get_line(first_line).unwrap_or_default();
+        let mut insert_col = document.cursor_column().min(line.len());
+        if matches!(paste_type, PasteType::After) && insert_col < line.len() {
+            insert_col = next_grapheme(&line, insert_col);
+        }
+        let column = display_column(&line, insert_col, tab_stop);
+
+        let rows: Vec<&str> = content.split('\n').collect();
+        let row_width = |row: &str| display_column(row, row.len(), tab_stop);
+        let width = rows.iter().map(|row| row_width(row)).max().unwrap_or(0);
+        for (i, row) in rows.iter().enumerate() {
+            let line_num = first_line + i;
+            if line_num >= get_line_count(document) {
+                document.insert_line_with_undo(line_num, "");
+            }
+            let mut text = document.get_line(line_num).unwrap_or_default();
+            let (mut at, drawn_at) = grapheme_at_display_column(&text, column, tab_stop);
+            if drawn_at < column && at == text.len() {
+                text.extend(std::iter::repeat_n(' ', column - drawn_at));
+                at = text.len();
+            } else if drawn_at < column && text[at..].starts_with('\t') {
+                let spaces = cells_at("\t", drawn_at, tab_stop);
+                text.replace_range(at..at + 1, &" ".repeat(spaces));
+                at += column - drawn_at;
+            } else if drawn_at < column {
+                at = next_grapheme(&text, at); // After a wide character
+            }
+            let (before, after) = text.split_at(at);
+
+            let padded = format!("{row}{}", " ".repeat(width - row_width(row)));
+            let mut piece = padded.repeat(count);
+            if after.is_empty() {
+                piece.truncate(piece.trim_end_matches(' ').len());
+            }
+            document.replace_line_with_undo(line_num, &format!("{before}{piece}{after}"));
+        }
+
+        // The cursor ends at the top left of the pasted block
+        let line = document.get_line(first_line).unwrap_or_default();
+        let (at, _) = grapheme_at_display_column(&line, column, tab_stop);
+        document.move_cursor_to(first_line, at);
     }
 
     fn paste_character_wise(document: &mut Document, content: &str, paste_type: &PasteType) {
-        if document.cursor_line() < get_line_count(document) {
-            let line_length = document.get_line_length(document.cursor_line());
-            let mut insert_col = document.cursor_column();
-
-            match paste_type {
-                PasteType::After => {
-                    if insert_col < line_length {
-                        insert_col += 1; // Move cursor after current character
-                    }
-                }
-                PasteType::Before => {
-                    // Insert at current position
+        let line_num = document.cursor_line();
+        let Some(line) = document.get_line(line_num) else {
+            return;
+        };
+        let mut insert_col = document.cursor_column().min(line.len());
+        if matches!(paste_type, PasteType::After) && insert_col < line.len() {
+            insert_col = next_grapheme(&line, insert_col); // After the character under the cursor
+        }
+
+        let combined = format!("{}{}{}", &line[..insert_col], content, &line[insert_col..]);
+        let lines: Vec<String> = combined.split('\n').map(str::to_string).collect();
+        document.expand_line_with_undo(line_num, &lines);
+
+        // The cursor ends on the last pasted character, or at the start of a multi-line paste
+        if lines.len() == 1 && !content.is_empty() {
+            document.move_cursor_to(line_num, insert_col + content.len() - 1);
+        } else {
+            document.move_cursor_to(line_num, insert_col);
+        }
+    }
+
+    /// Paste with 'pastewrap' on: the pasted lines are hard-wrapped to
+    /// 'textwidth'
+    fn paste_wrapped(
+        document: &mut Document,
+        content: &str,
+        register_type: &RegisterType,
+        paste_type: &PasteType,
+        tab_stop: usize,
+    ) {
+        let width = document.text_width;
+        match register_type {
+            RegisterType::Line => {
+                let insert_line = match paste_type {
+                    PasteType::After => (document.cursor_line() + 1).min(get_line_count(document)),
+                    PasteType::Before => document.cursor_line(),
+                };
+                let lines: Vec<&str> = content.lines().collect();
+                for (i, line) in crate::document_model::reflow::wrap_lines(&lines, width, tab_stop).iter().enumerate() {
+                    document.insert_line_with_undo(insert_line + i, line);
                 }
+                document.move_cursor_to(insert_line, 0);
             }
+            RegisterType::Character | RegisterType::Block => {
+                let line_num = document.cursor_line();
+                let line = document.get_line(line_num).unwrap_or_default();
+                let mut insert_col = document.cursor_column().min(line.len());
+                if matches!(paste_type, PasteType::After) && insert_col < line.len() {
+                    insert_col += line[insert_col..].chars().next().map_or(1, |c| c.len_utf8());
+                }
 
-            if insert_col <= line_length {
-                use crate::document_model::Position;
-                let pos = Position::new(document.cursor_line(), insert_col);
-                document.text_buffer_mut().insert(pos, content);
-                let new_col = insert_col + content.len() - 1;
-                document.move_cursor_to(document.cursor_line(), new_col);
-                document.modified = true;
+                let combined = format!("{}{}{}", &line[..insert_col], content, &line[insert_col..]);
+                let lines: Vec<&str> = combined.split('\n').collect();
+                let wrapped = crate::document_model::reflow::wrap_lines(&lines, width, tab_stop);
+                document.expand_line_with_undo(line_num, &wrapped);
+                document.move_cursor_to(line_num + wrapped.len() - 1, 0);
             }
         }
     }
@@ -165,20 +203,23 @@ impl YankPasteHandler {
     }
 
 
-    pub fn execute_yank_simple(document: &crate::document_model::Document, yank_type: YankType, register: Option<char>, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
-        let (text, register_type) = Self::get_yank_content(&yank_type, document);
-        register_manager.store_in_register(register, text.clone(), register_type);
+    /// Store yanked text in a register and report what was yanked
+    pub fn store_yank(text: String, register_type: RegisterType, register: Option<char>, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
         Self::show_yank_feedback(status_message, &text, register);
+        register_manager.store_yank(register, text, register_type);
     }
 
-    pub fn execute_paste_simple(document: &mut crate::document_model::Document, paste_type: PasteType, register: Option<char>, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
+    pub fn execute_paste_simple(document: &mut crate::document_model::Document, paste_type: PasteType, register: Option<char>, count: usize, tab_stop: usize, register_manager: &mut crate::document_model::RegisterManager, status_message: &mut String) {
         if let Some(register_data) = register_manager.get_register_content(register) {
-            let content = register_data.content.clone();
-            let register_type = register_data.register_type.clone();
-            Self::paste_content(document, &content, &register_type, &paste_type);
+            Self::paste_data(document, register_data, &paste_type, count, tab_stop);
             *status_message = "Text pasted".to_string();
         } else {
             *status_message = "Register empty".to_string();
         }
     }
+
+    /// Paste `count` copies of register content as one undo step
+    pub fn paste_data(document: &mut Document, data: &RegisterData, paste_type: &PasteType, count: usize, tab_stop: usize) {
+        Self::paste_content(document, &data.content, &data.register_type, paste_type, count.max(1), tab_stop);
+    }
 }
@@ -0,0 +1,48 @@
+//! Whole-buffer analyses that may run on a background thread
+//!
+//! Each pass works on a snapshot of the text and checks `cancel` between
+//! lines, returning None once it is set so a stale run stops early.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Brackets with no partner, as (line, byte column) sorted by position.
+/// Each kind of bracket is matched on its own.
+pub fn unmatched_brackets(text: &str, cancel: &AtomicBool) -> Option<Vec<(usize, usize)>> {
+    const PAIRS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+    let mut open: [Vec<(usize, usize)>; 4] = Default::default();
+    let mut unmatched = Vec::new();
+
+    for (line_idx, line) in text.split('\n').enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        for (col_idx, ch) in line.char_indices() {
+            if let Some(kind) = PAIRS.iter().position(|&(opening, _)| opening == ch) {
+                open[kind].push((line_idx, col_idx));
+            } else if let Some(kind) = PAIRS.iter().position(|&(_, closing)| closing == ch)
+                && open[kind].pop().is_none()
+            {
+                unmatched.push((line_idx, col_idx));
+            }
+        }
+    }
+
+    unmatched.extend(open.into_iter().flatten());
+    unmatched.sort();
+    Some(unmatched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unmatched_brackets_and_cancel() {
+        let never = AtomicBool::new(false);
+        assert_eq!(unmatched_brackets("(a]\n{b}\n)(", &never), Some(vec![(0, 2), (2, 1)]));
+        assert_eq!(unmatched_brackets("([)]", &never), Some(vec![]));
+
+        let cancelled = AtomicBool::new(true);
+        assert_eq!(unmatched_brackets("(", &cancelled), None);
+    }
+}
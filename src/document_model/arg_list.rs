@@ -0,0 +1,105 @@
+//! The argument list - the files named on the command line (or by :args),
+//! stepped through with :next and :prev whatever else the buffer list holds
+
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct ArgList {
+    files: Vec<PathBuf>,
+    current: usize, // File last edited from the list
+}
+
+impl ArgList {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self { files, current: 0 }
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Make file `index` current (:first, :last)
+    pub fn select(&mut self, index: usize) -> Option<&PathBuf> {
+        let file = self.files.get(index)?;
+        self.current = index;
+        Some(file)
+    }
+
+    /// Move `count` files forward (:next) or back (:prev). Unlike the
+    /// quickfix list it doesn't stop at the end: going past it is an error.
+    pub fn step(&mut self, count: usize, forward: bool) -> Result<usize, String> {
+        if self.files.is_empty() {
+            return Err("Argument list is empty".to_string());
+        }
+        let index = if forward {
+            Some(self.current + count).filter(|&index| index < self.files.len())
+        } else {
+            self.current.checked_sub(count)
+        };
+        let index = index.ok_or(if forward { "Cannot go beyond last file" } else { "Cannot go before first file" })?;
+        self.current = index;
+        Ok(index)
+    }
+
+    /// :argadd - put `files` after the current file
+    pub fn add(&mut self, files: Vec<PathBuf>) {
+        let at = if self.files.is_empty() { 0 } else { self.current + 1 };
+        self.files.splice(at..at, files);
+    }
+
+    /// The files as :args shows them, the current one in brackets
+    pub fn describe(&self) -> String {
+        let names: Vec<String> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| {
+                let name = file.display().to_string();
+                if index == self.current { format!("[{}]", name) } else { name }
+            })
+            .collect();
+        names.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(names: &[&str]) -> ArgList {
+        ArgList::new(names.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn test_step_stops_at_the_ends() {
+        assert_eq!(ArgList::default().step(1, true), Err("Argument list is empty".to_string()));
+
+        let mut args = list(&["a", "b", "c"]);
+        assert_eq!(args.step(1, false), Err("Cannot go before first file".to_string()));
+        assert_eq!(args.step(2, true), Ok(2));
+        assert_eq!(args.step(1, true), Err("Cannot go beyond last file".to_string()));
+        assert_eq!(args.current_index(), 2);
+        assert_eq!(args.step(3, false), Err("Cannot go before first file".to_string()));
+        assert_eq!(args.step(1, false), Ok(1));
+        assert_eq!(args.describe(), "a [b] c");
+    }
+
+    #[test]
+    fn test_add_goes_after_the_current_file() {
+        let mut args = ArgList::default();
+        args.add(vec![PathBuf::from("a"), PathBuf::from("d")]);
+        assert_eq!(args.describe(), "[a] d");
+        args.add(vec![PathBuf::from("b"), PathBuf::from("c")]);
+        assert_eq!(args.describe(), "[a] b c d");
+        assert_eq!(args.select(3), Some(&PathBuf::from("d")));
+        assert_eq!(args.select(4), None);
+    }
+}
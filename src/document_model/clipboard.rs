@@ -0,0 +1,53 @@
+//! System clipboard access for the + and * registers
+//!
+//! Both registers share the one system clipboard. Without the `clipboard`
+//! feature, or when no clipboard is reachable (no display, headless SSH),
+//! the backend reports itself unavailable and the registers fall back to
+//! holding their text inside the editor.
+
+pub trait ClipboardBackend {
+    /// Current clipboard text, or None if the clipboard can't be read
+    fn get_text(&mut self) -> Option<String>;
+
+    /// Replace the clipboard text. Returns false if the clipboard can't be written.
+    fn set_text(&mut self, text: &str) -> bool;
+}
+
+/// The OS clipboard, opened on first use
+#[derive(Default)]
+pub struct SystemClipboard {
+    #[cfg(feature = "clipboard")]
+    clipboard: Option<arboard::Clipboard>,
+}
+
+#[cfg(feature = "clipboard")]
+impl SystemClipboard {
+    fn open(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.clipboard.is_none() {
+            self.clipboard = arboard::Clipboard::new().ok();
+        }
+        self.clipboard.as_mut()
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl ClipboardBackend for SystemClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        self.open()?.get_text().ok()
+    }
+
+    fn set_text(&mut self, text: &str) -> bool {
+        self.open().is_some_and(|clipboard| clipboard.set_text(text).is_ok())
+    }
+}
+
+#[cfg(not(feature = "clipboard"))]
+impl ClipboardBackend for SystemClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        None
+    }
+
+    fn set_text(&mut self, _text: &str) -> bool {
+        false
+    }
+}
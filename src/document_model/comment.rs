@@ -0,0 +1,190 @@
+//! Block comments - wrap a range of text in one block comment, or unwrap it
+//!
+//! Markers go just inside the range (after the first line's indentation and
+//! before the last line's trailing whitespace for whole lines), padded with
+//! one space, so unwrapping restores the text exactly. Line comments inside
+//! the range are ordinary text here and are left untouched.
+
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockCommentStyle {
+    pub open: &'static str,
+    pub close: &'static str,
+}
+
+const C_STYLE: BlockCommentStyle = BlockCommentStyle { open: "/*", close: "*/" };
+const MARKUP_STYLE: BlockCommentStyle = BlockCommentStyle { open: "<!--", close: "-->" };
+
+impl BlockCommentStyle {
+    /// Block comment style for a file, chosen by extension
+    pub fn for_file(filename: Option<&Path>) -> Option<Self> {
+        let extension = filename?.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" | "rs" | "go" | "java" | "js" | "mjs"
+            | "jsx" | "ts" | "tsx" | "cs" | "swift" | "kt" | "kts" | "scala" | "php" | "css"
+            | "scss" | "less" | "sql" | "dart" => Some(C_STYLE),
+            "html" | "htm" | "xhtml" | "xml" | "svg" | "md" | "markdown" | "vue" => Some(MARKUP_STYLE),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommentError {
+    /// The range already contains a closing marker, which would end the new comment early
+    NestedBlock(&'static str),
+    EmptyRange,
+    UnknownFileType,
+}
+
+impl fmt::Display for CommentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommentError::NestedBlock(close) => {
+                write!(f, "Selection already contains {}; block comments can't nest", close)
+            }
+            CommentError::EmptyRange => write!(f, "Nothing to comment"),
+            CommentError::UnknownFileType => write!(f, "No block comment style for this file type"),
+        }
+    }
+}
+
+/// Byte range of whole lines to comment: first non-blank of the first line
+/// to the last non-blank of the last line
+pub fn line_range(lines: &[String]) -> (usize, usize) {
+    let start = lines
+        .first()
+        .map_or(0, |line| line.len() - line.trim_start().len());
+    let end = lines.last().map_or(0, |line| line.trim_end().len());
+    (start, end)
+}
+
+/// Toggle a block comment around `lines`, from byte `start` in the first line
+/// to byte `end` (exclusive) in the last. Returns the new lines and whether
+/// the text is now commented.
+pub fn toggle_block_comment(
+    lines: &[String],
+    start: usize,
+    end: usize,
+    style: BlockCommentStyle,
+) -> Result<(Vec<String>, bool), CommentError> {
+    let Some(last) = lines.len().checked_sub(1) else {
+        return Err(CommentError::EmptyRange);
+    };
+    let start = floor_char_boundary(&lines[0], start);
+    let end = floor_char_boundary(&lines[last], end);
+    if last == 0 && start >= end {
+        return Err(CommentError::EmptyRange);
+    }
+
+    let mut result = lines.to_vec();
+    let first_text = &lines[0][start..];
+    let last_text = &lines[last][..end];
+    let spans_markers = if last == 0 {
+        end - start >= style.open.len() + style.close.len()
+    } else {
+        true
+    };
+
+    if spans_markers && first_text.starts_with(style.open) && last_text.ends_with(style.close) {
+        // Unwrap: drop the markers and the single space of padding beside each.
+        // The close marker goes first so `start` stays valid on a one-line range.
+        let mut close_start = end - style.close.len();
+        if lines[last][..close_start].ends_with(' ') && (last > 0 || close_start > start + style.open.len()) {
+            close_start -= 1;
+        }
+        result[last].replace_range(close_start..end, "");
+
+        let mut open_end = start + style.open.len();
+        if result[0][open_end..].starts_with(' ') && (last > 0 || open_end < close_start) {
+            open_end += 1;
+        }
+        result[0].replace_range(start..open_end, "");
+        return Ok((result, false));
+    }
+
+    // Wrap: refuse ranges holding a close marker, which would end the comment early
+    let contains_close = if last == 0 {
+        lines[0][start..end].contains(style.close)
+    } else {
+        first_text.contains(style.close)
+            || lines[1..last].iter().any(|line| line.contains(style.close))
+            || last_text.contains(style.close)
+    };
+    if contains_close {
+        return Err(CommentError::NestedBlock(style.close));
+    }
+
+    result[last].insert_str(end, &format!(" {}", style.close));
+    result[0].insert_str(start, &format!("{} ", style.open));
+    Ok((result, true))
+}
+
+fn floor_char_boundary(line: &str, index: usize) -> usize {
+    let mut index = index.min(line.len());
+    while !line.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_style_by_extension() {
+        assert_eq!(BlockCommentStyle::for_file(Some(Path::new("main.RS"))), Some(C_STYLE));
+        assert_eq!(BlockCommentStyle::for_file(Some(Path::new("index.html"))), Some(MARKUP_STYLE));
+        assert_eq!(BlockCommentStyle::for_file(Some(Path::new("script.py"))), None);
+        assert_eq!(BlockCommentStyle::for_file(None), None);
+    }
+
+    #[test]
+    fn test_lines_round_trip_with_nested_line_comments() {
+        let original = lines(&["    foo();", "    // note", "    bar(); // trailing  "]);
+        let (start, end) = line_range(&original);
+
+        let (commented, is_commented) = toggle_block_comment(&original, start, end, C_STYLE).unwrap();
+        assert!(is_commented);
+        assert_eq!(commented, lines(&["    /* foo();", "    // note", "    bar(); // trailing */  "]));
+
+        let (start, end) = line_range(&commented);
+        let (restored, is_commented) = toggle_block_comment(&commented, start, end, C_STYLE).unwrap();
+        assert!(!is_commented);
+        assert_eq!(restored, original);
+    }
+
+    #[test]
+    fn test_characterwise_range_in_markup() {
+        let original = lines(&["<p>hello world</p>"]);
+        let (commented, _) = toggle_block_comment(&original, 3, 14, MARKUP_STYLE).unwrap();
+        assert_eq!(commented, lines(&["<p><!-- hello world --></p>"]));
+
+        let (restored, _) = toggle_block_comment(&commented, 3, 23, MARKUP_STYLE).unwrap();
+        assert_eq!(restored, original);
+    }
+
+    #[test]
+    fn test_unwraps_unpadded_markers() {
+        let (restored, is_commented) = toggle_block_comment(&lines(&["/*x*/"]), 0, 5, C_STYLE).unwrap();
+        assert!(!is_commented);
+        assert_eq!(restored, lines(&["x"]));
+    }
+
+    #[test]
+    fn test_refuses_to_nest_block_comments() {
+        let text = lines(&["a();", "/* old */", "b();"]);
+        let (start, end) = line_range(&text);
+        assert_eq!(
+            toggle_block_comment(&text, start, end, C_STYLE),
+            Err(CommentError::NestedBlock("*/"))
+        );
+    }
+}
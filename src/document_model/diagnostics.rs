@@ -0,0 +1,64 @@
+//! Diagnostics - the errors and warnings a language server reports for a
+//! buffer, which the sign column marks and the text underlines
+
+/// How serious a diagnostic is, in the language server protocol's order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// From the protocol's number; servers that leave it out mean an error
+    pub fn from_lsp(number: Option<u64>) -> Self {
+        match number {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+
+    /// The sign column's text, two cells wide
+    pub fn sign(self) -> &'static str {
+        match self {
+            Severity::Error => "E>",
+            Severity::Warning => "W>",
+            Severity::Information => "I>",
+            Severity::Hint => "H>",
+        }
+    }
+}
+
+/// One diagnostic: the text it is about, from `start` up to `end`, as
+/// (line, byte column) pairs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// True when the byte at `column` of `line` is underlined. A diagnostic
+    /// that covers no text underlines the character it points at.
+    pub fn covers(&self, line: usize, column: usize) -> bool {
+        if self.start == self.end {
+            return (line, column) == self.start;
+        }
+        (line, column) >= self.start && (line, column) < self.end
+    }
+
+    /// True when the diagnostic starts on `line`
+    pub fn starts_on(&self, line: usize) -> bool {
+        self.start.0 == line
+    }
+}
+
+/// The most serious diagnostic starting on `line`
+pub fn worst_on(diagnostics: &[Diagnostic], line: usize) -> Option<&Diagnostic> {
+    diagnostics.iter().filter(|diagnostic| diagnostic.starts_on(line)).min_by_key(|diagnostic| diagnostic.severity)
+}
@@ -0,0 +1,242 @@
+//! Line diff for diff mode - Myers' algorithm over whole lines, giving the
+//! hunks where two texts differ, and the rows that line the texts up side
+//! by side, with filler rows where one side has lines the other doesn't.
+//!
+//! Sides are numbered 0 for the old text and 1 for the new.
+
+use std::ops::Range;
+
+/// Past this many lines added and removed between the common start and end
+/// of the texts, the rest is shown as one change: the search keeps a copy
+/// of its frontier per step, so its memory grows with the square of this
+const MAX_EDITS: usize = 1000;
+
+/// Lines that differ: `old` in the old text stand where `new` are in the
+/// new one. One of the two is empty when lines were only added or removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+impl Hunk {
+    /// The lines of one side
+    pub fn side(&self, side: usize) -> &Range<usize> {
+        if side == 0 { &self.old } else { &self.new }
+    }
+
+    /// True when `line` of `side` belongs to this hunk: inside its lines,
+    /// or next to where the other side's lines are missing
+    fn touches(&self, side: usize, line: usize) -> bool {
+        let range = self.side(side);
+        range.contains(&line) || (range.is_empty() && (range.start == line || range.start == line + 1))
+    }
+}
+
+/// How a row of the side-by-side view is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,    // Both sides have the line
+    Changed, // Both sides have a line here, but not the same one
+    Added,   // Only this side has the line
+    Filler,  // Only the other side has a line here
+}
+
+/// One row of one side: the line shown there, None for a filler row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffRow {
+    pub line: Option<usize>,
+    pub kind: DiffKind,
+}
+
+/// Where two texts differ, and how they line up
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diff {
+    pub hunks: Vec<Hunk>,
+    rows: [Vec<DiffRow>; 2], // As many rows on each side
+}
+
+impl Diff {
+    pub fn new<T: PartialEq>(old: &[T], new: &[T]) -> Self {
+        let hunks = diff_lines(old, new);
+        let rows = align(&hunks, old.len());
+        Self { hunks, rows }
+    }
+
+    /// The rows of one side
+    pub fn rows(&self, side: usize) -> &[DiffRow] {
+        &self.rows[side.min(1)]
+    }
+
+    /// The hunk the cursor on `line` of `side` is in, as do and dp take it
+    pub fn hunk_at(&self, side: usize, line: usize) -> Option<&Hunk> {
+        self.hunks.iter().find(|hunk| hunk.touches(side, line))
+    }
+
+    /// The line `count` changes on from `line` of `side`, going forward
+    /// or back, as ]c and [c move; None when there are no more
+    pub fn change_from(&self, side: usize, line: usize, count: usize, forward: bool) -> Option<usize> {
+        // A hunk past the last line, where lines were added to the other side, starts on it
+        let last = self.rows(side).iter().rev().find_map(|row| row.line).unwrap_or(0);
+        let starts = self.hunks.iter().map(|hunk| hunk.side(side).start.min(last));
+        if forward {
+            starts.filter(|&start| start > line).nth(count.max(1) - 1)
+        } else {
+            starts.filter(|&start| start < line).rev().nth(count.max(1) - 1)
+        }
+    }
+}
+
+/// The hunks where `new` differs from `old`, in order
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    // Lines both texts start and end with are the same whatever lies between
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut hunks = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    let ends = (old_middle.len(), new_middle.len());
+    for (same_x, same_y) in common_lines(old_middle, new_middle).into_iter().chain(std::iter::once(ends)) {
+        if same_x > x || same_y > y {
+            hunks.push(Hunk { old: prefix + x..prefix + same_x, new: prefix + y..prefix + same_y });
+        }
+        (x, y) = (same_x + 1, same_y + 1);
+    }
+    hunks
+}
+
+/// The pairs of lines a shortest edit from `a` to `b` keeps, in order.
+/// Empty when the texts share nothing or differ in more than MAX_EDITS lines.
+fn common_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_edits = (a.len() + b.len()).min(MAX_EDITS) as isize;
+    // v[k] is how far along `a` the furthest path on diagonal k = x - y got
+    let offset = max_edits + 1;
+    let index = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    let mut found = false;
+    'search: for d in 0..=max_edits {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return Vec::new();
+    }
+
+    // Walk back from the end, collecting the diagonal steps
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) { k + 1 } else { k - 1 };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// The rows of each side, with the lines of each hunk side by side and
+/// filler below the shorter side
+fn align(hunks: &[Hunk], old_len: usize) -> [Vec<DiffRow>; 2] {
+    let mut rows: [Vec<DiffRow>; 2] = [Vec::new(), Vec::new()];
+    let same = |rows: &mut [Vec<DiffRow>; 2], old: Range<usize>, new_start: usize| {
+        for (offset, line) in old.enumerate() {
+            rows[0].push(DiffRow { line: Some(line), kind: DiffKind::Same });
+            rows[1].push(DiffRow { line: Some(new_start + offset), kind: DiffKind::Same });
+        }
+    };
+
+    let (mut old_line, mut new_line) = (0, 0);
+    for hunk in hunks {
+        same(&mut rows, old_line..hunk.old.start, new_line);
+        let (old_count, new_count) = (hunk.old.len(), hunk.new.len());
+        for offset in 0..old_count.max(new_count) {
+            for (side, range, other_count) in [(0, &hunk.old, new_count), (1, &hunk.new, old_count)] {
+                let row = if offset >= range.len() {
+                    DiffRow { line: None, kind: DiffKind::Filler }
+                } else if offset < other_count {
+                    DiffRow { line: Some(range.start + offset), kind: DiffKind::Changed }
+                } else {
+                    DiffRow { line: Some(range.start + offset), kind: DiffKind::Added }
+                };
+                rows[side].push(row);
+            }
+        }
+        (old_line, new_line) = (hunk.old.end, hunk.new.end);
+    }
+    same(&mut rows, old_line..old_len, new_line);
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hunks_and_rows() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f", "g"];
+        let diff = Diff::new(&old, &new);
+        assert_eq!(
+            diff.hunks,
+            vec![Hunk { old: 1..2, new: 1..2 }, Hunk { old: 3..4, new: 3..3 }, Hunk { old: 5..5, new: 4..6 }]
+        );
+
+        let lines = |side| diff.rows(side).iter().map(|row| (row.line, row.kind)).collect::<Vec<_>>();
+        use DiffKind::*;
+        assert_eq!(
+            lines(0),
+            vec![
+                (Some(0), Same),
+                (Some(1), Changed),
+                (Some(2), Same),
+                (Some(3), Added),
+                (Some(4), Same),
+                (None, Filler),
+                (None, Filler),
+            ]
+        );
+        assert_eq!(lines(1)[3..], [(None, Filler), (Some(3), Same), (Some(4), Added), (Some(5), Added)]);
+
+        assert_eq!(diff.change_from(0, 0, 1, true), Some(1));
+        assert_eq!(diff.change_from(0, 0, 3, true), Some(4));
+        assert_eq!(diff.change_from(1, 5, 2, false), Some(3));
+        assert_eq!(diff.change_from(1, 5, 3, false), Some(1));
+        assert_eq!(diff.change_from(0, 4, 1, true), None);
+
+        assert_eq!(diff.hunk_at(1, 2), Some(&Hunk { old: 3..4, new: 3..3 }));
+        assert_eq!(diff.hunk_at(0, 4), Some(&Hunk { old: 5..5, new: 4..6 }));
+        assert_eq!(diff.hunk_at(0, 0), None);
+
+        assert!(diff_lines(&old, &old).is_empty());
+        assert_eq!(diff_lines(&["a"], &["b", "c"]), vec![Hunk { old: 0..1, new: 0..2 }]);
+        assert_eq!(diff_lines::<&str>(&[], &["b"]), vec![Hunk { old: 0..0, new: 0..1 }]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Digraphs - the pairs of characters Ctrl-K in insert mode turns into one
+//! character, after RFC 1345 as vim has them
+//!
+//! The first character is the letter and the second says what is done to
+//! it: `e'` is é, `a:` is ä, `n?` is ñ, `c,` is ç, `o/` is ø. A pair not
+//! in the table is looked up the other way round too, so `'e` is é as well.
+
+/// Accents as the second character of a digraph, with the letters they go on
+const ACCENTED: &[(char, &str, &str)] = &[
+    ('\'', "AEIOUYaeiouy", "ÁÉÍÓÚÝáéíóúý"),
+    ('!', "AEIOUaeiou", "ÀÈÌÒÙàèìòù"),
+    ('>', "AEIOUaeiou", "ÂÊÎÔÛâêîôû"),
+    (':', "AEIOUaeiouy", "ÄËÏÖÜäëïöüÿ"),
+    ('?', "ANOano", "ÃÑÕãñõ"),
+    (',', "Cc", "Çç"),
+    ('/', "Oo", "Øø"),
+    ('a', "Aa", "Åå"),
+];
+
+/// The rest: ligatures, currency, punctuation, fractions and Greek
+const OTHERS: &[(&str, char)] = &[
+    ("AE", 'Æ'), ("ae", 'æ'), ("ss", 'ß'), ("OE", 'Œ'), ("oe", 'œ'),
+    ("Eu", '€'), ("Pd", '£'), ("Ye", '¥'), ("Ct", '¢'), ("Co", '©'), ("Rg", '®'),
+    ("SE", '§'), ("PI", '¶'), ("DG", '°'), ("+-", '±'), ("*X", '×'), ("-:", '÷'),
+    ("<<", '«'), (">>", '»'), ("!I", '¡'), ("?I", '¿'), ("My", 'µ'), ("NS", '\u{a0}'),
+    ("12", '½'), ("14", '¼'), ("34", '¾'), ("1S", '¹'), ("2S", '²'), ("3S", '³'),
+    ("-N", '–'), ("-M", '—'), ("'6", '‘'), ("'9", '’'), ("\"6", '“'), ("\"9", '”'),
+    (".P", '⋅'), ("->", '→'), ("<-", '←'), ("OK", '✓'), ("XX", '✗'),
+    ("a*", 'α'), ("b*", 'β'), ("g*", 'γ'), ("d*", 'δ'), ("e*", 'ε'), ("l*", 'λ'),
+    ("m*", 'μ'), ("p*", 'π'), ("s*", 'σ'), ("t*", 'τ'), ("f*", 'φ'), ("w*", 'ω'),
+    ("D*", 'Δ'), ("S*", 'Σ'), ("W*", 'Ω'),
+];
+
+/// The character the digraph `first` `second` stands for
+pub fn lookup(first: char, second: char) -> Option<char> {
+    find(first, second).or_else(|| find(second, first))
+}
+
+fn find(first: char, second: char) -> Option<char> {
+    let accented = ACCENTED.iter().find(|(accent, _, _)| *accent == second).and_then(|(_, letters, results)| {
+        let index = letters.chars().position(|letter| letter == first)?;
+        results.chars().nth(index)
+    });
+    accented.or_else(|| {
+        OTHERS.iter().find(|(pair, _)| pair.chars().eq([first, second])).map(|&(_, result)| result)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup('e', '\''), Some('é'));
+        assert_eq!(lookup('\'', 'e'), Some('é'));
+        assert_eq!(lookup('U', ':'), Some('Ü'));
+        assert_eq!(lookup('n', '?'), Some('ñ'));
+        assert_eq!(lookup('E', 'u'), Some('€'));
+        assert_eq!(lookup('p', '*'), Some('π'));
+        assert_eq!(lookup('q', '\''), None);
+        for (_, letters, results) in ACCENTED {
+            assert_eq!(letters.chars().count(), results.chars().count());
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Directory listings shown as buffers (netrw-style browsing)
+//!
+//! Opening a directory gives a read-only buffer with one line per entry:
+//! "../" first, then subdirectories (with a trailing '/') and files, each
+//! group sorted by name.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryListing {
+    pub path: PathBuf,    // Absolute path of the directory
+    entries: Vec<String>, // Entry names in display order, directories ending in '/'
+}
+
+impl DirectoryListing {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let path = path.canonicalize()?;
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                dirs.push(format!("{name}/"));
+            } else {
+                files.push(name);
+            }
+        }
+        dirs.sort();
+        files.sort();
+        dirs.extend(files);
+        Ok(Self { path, entries: dirs })
+    }
+
+    /// The buffer text, one entry per line
+    pub fn text(&self) -> String {
+        std::iter::once("../")
+            .chain(self.entries.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Path of the entry on `line` (line 0 is the parent directory)
+    pub fn entry_path(&self, line: usize) -> Option<PathBuf> {
+        match line {
+            0 => Some(self.parent()),
+            _ => self.entries.get(line - 1).map(|name| self.path.join(name.trim_end_matches('/'))),
+        }
+    }
+
+    /// The parent directory, or the directory itself at the root
+    pub fn parent(&self) -> PathBuf {
+        self.path.parent().unwrap_or(&self.path).to_path_buf()
+    }
+
+    /// Line showing the entry called `name`, if listed
+    pub fn line_of(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.trim_end_matches('/') == name)
+            .map(|index| index + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_puts_directories_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("zeta")).unwrap();
+        fs::write(dir.path().join("alpha.txt"), "").unwrap();
+        fs::write(dir.path().join("beta.rs"), "").unwrap();
+
+        let listing = DirectoryListing::read(dir.path()).unwrap();
+        assert_eq!(listing.text(), "../\nzeta/\nalpha.txt\nbeta.rs");
+        assert_eq!(listing.entry_path(1), Some(listing.path.join("zeta")));
+        assert_eq!(listing.entry_path(0), Some(listing.parent()));
+        assert_eq!(listing.entry_path(4), None);
+        assert_eq!(listing.line_of("beta.rs"), Some(3));
+    }
+}
@@ -1,8 +1,19 @@
 use super::undo::UndoManager;
-use super::text_buffer::{TextBuffer, Position, Range};
+use super::text_buffer::{LineChange, TextBuffer, Position, Range, grapheme_start, next_grapheme, prev_grapheme};
+use super::directory::DirectoryListing;
+use super::history::HistoryWindow;
+use super::selection::Selection;
+use super::file_lock::{FileLock, LockConflict};
+use super::swap_file::SwapFile;
+use super::encoding::Encoding;
+use super::git::GitSigns;
+use super::diagnostics::Diagnostic;
+use super::loader::{BACKGROUND_LOAD_SIZE, FileLoader, LOAD_CHUNK_SIZE, LoadEnd, LoadStart};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineEnding {
@@ -30,6 +41,40 @@ impl LineEnding {
             LineEnding::Unix
         }
     }
+
+    /// The characters written between lines
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Windows => "\r\n",
+            LineEnding::Mac => "\r",
+        }
+    }
+
+    /// The 'fileformat' name: unix, dos or mac
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "unix",
+            LineEnding::Windows => "dos",
+            LineEnding::Mac => "mac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Unix),
+            "dos" => Some(LineEnding::Windows),
+            "mac" => Some(LineEnding::Mac),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a bracket search that won't walk through overlong lines
+enum BracketScan {
+    Found((usize, usize)),
+    Unmatched,
+    GaveUp, // Reached a line longer than the limit
 }
 
 #[derive(Clone)]
@@ -41,15 +86,54 @@ pub struct Document {
     // File metadata - PUBLIC: direct access allowed for now
     pub filename: Option<PathBuf>,
     pub modified: bool,
+    pub read_only: bool, // Set when another editor holds the file; :w needs !
     
     // Format settings - PUBLIC: direct access allowed for now
     pub line_ending: LineEnding,
+    pub end_of_line: bool, // 'endofline': end the last line with a line break when saving
+    pub encoding: Encoding, // 'fileencoding': how the file's bytes are read and written
+    pub bom: bool,          // Write a byte order mark before the text
+    pub binary: bool,       // Read with undecodable bytes replaced; opened read-only
     pub expand_tab: bool,
+    pub shift_width: usize, // 'shiftwidth': columns >> and << move by (0 = the tabstop)
+    pub shift_round: bool,  // 'shiftround': >> and << round the indent to a multiple of shift_width
+    pub auto_indent: bool,  // 'autoindent': a new line starts with the indent of the one before
+    pub auto_pairs: bool,   // 'autopairs': typing a bracket or quote adds its closing one
+    pub text_width: usize, // 'textwidth': hard-wrap column for typing, gq, :Wrap and wrapped paste (0 = off)
+    pub wrap_paste: bool,  // 'pastewrap': wrap pasted text to text_width
+    pub trim_trailing: bool, // 'trimtrailing': strip trailing whitespace from every line on save
+    pub write_program: Option<String>, // 'writeprg': shell command the text is piped through on save
+    pub format_program: Option<String>, // 'formatprg': shell command :format pipes lines through
+    pub format_on_save: bool,           // 'formatonsave': format the whole buffer with format_program before each write
+    pub stats: super::stats::BufferStats, // Searches and substitutions this session, for :stats
+    pub directory: Option<DirectoryListing>, // Set for a directory listing buffer
+    pub help: bool,                          // Set for the :help buffer
+    pub history_window: Option<HistoryWindow>, // Set for a q: / q/ history buffer
+    pub last_selection: Option<Selection>,     // The last visual selection, for gv, '< '> and \%V
+    pub git_signs: GitSigns,                   // The sign column: lines changed since the file was staged
+    pub diagnostics: Vec<Diagnostic>,          // Errors and warnings from the buffer's language server
     
     // Internal data structures - MODULE PRIVATE: controlled access only
     local_marks: HashMap<char, (usize, usize)>, // Local marks (a-z) for this buffer
     pub(super) undo_manager: UndoManager,
     pub(super) text_buffer: TextBuffer, // Piece table backend - single source of truth
+    file_lock: Option<Arc<FileLock>>, // Advisory lock on filename, released when the last clone drops
+    disk_modified: Option<SystemTime>, // The file's modification time when last read or written
+    swap_file: Option<Arc<SwapFile>>, // Journal of unsaved edits, kept while modified and locked
+    swap_ops: usize, // Edits appended to the swap file since its snapshot
+    seen_revision: u64, // Text revision when edits were last looked for, for 'autosave'
+    changed_at: Option<Instant>, // When an unsaved edit was last seen; 'autosave' writes once this is old enough
+}
+
+/// Pending edits are written to the swap file once there are this many
+const SWAP_SYNC_OPS: usize = 200;
+/// A swap file holding this many edits is started again from a fresh snapshot
+const SWAP_COMPACT_OPS: usize = 2000;
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Document {
@@ -61,53 +145,212 @@ impl Document {
             cursor_column: 0,
             filename: None,
             modified: false,
+            read_only: false,
             line_ending: LineEnding::system_default(),
+            end_of_line: true,
+            encoding: Encoding::Utf8,
+            bom: false,
+            binary: false,
             expand_tab: true, // Default to spaces
+            shift_width: 0,
+            shift_round: false,
+            auto_indent: false,
+            auto_pairs: false,
+            text_width: 0,
+            wrap_paste: false,
+            trim_trailing: false,
+            write_program: None,
+            format_program: None,
+            format_on_save: false,
+            stats: super::stats::BufferStats::default(),
+            directory: None,
+            help: false,
+            history_window: None,
+            last_selection: None,
+            git_signs: GitSigns::new(),
+            diagnostics: Vec::new(),
             local_marks: HashMap::new(),
             undo_manager: UndoManager::new(),
             text_buffer,
+            file_lock: None,
+            disk_modified: None,
+            swap_file: None,
+            swap_ops: 0,
+            seen_revision: 0,
+            changed_at: None,
         }
     }
     
     /// Create a new document from string content
     pub fn from_string(content: String) -> Self {
+        let end_of_line = Self::ends_with_line_break(&content);
         Self {
             cursor_line: 0,
             cursor_column: 0,
             filename: None,
             modified: false,
+            read_only: false,
             line_ending: LineEnding::Unix,
+            end_of_line,
+            encoding: Encoding::Utf8,
+            bom: false,
+            binary: false,
             expand_tab: true,
+            shift_width: 0,
+            shift_round: false,
+            auto_indent: false,
+            auto_pairs: false,
+            text_width: 0,
+            wrap_paste: false,
+            trim_trailing: false,
+            write_program: None,
+            format_program: None,
+            format_on_save: false,
+            stats: super::stats::BufferStats::default(),
+            directory: None,
+            help: false,
+            history_window: None,
+            last_selection: None,
+            git_signs: GitSigns::new(),
+            diagnostics: Vec::new(),
             local_marks: HashMap::new(),
             undo_manager: UndoManager::new(),
             text_buffer: TextBuffer::from_string(content),
+            file_lock: None,
+            disk_modified: None,
+            swap_file: None,
+            swap_ops: 0,
+            seen_revision: 0,
+            changed_at: None,
         }
     }
 
     pub fn from_file(filename: PathBuf) -> Result<Self, std::io::Error> {
-        let content = fs::read_to_string(&filename)?;
+        if filename.is_dir() {
+            return Self::from_directory(&filename);
+        }
+        let chunk_size = (fs::metadata(&filename)?.len() >= BACKGROUND_LOAD_SIZE).then_some(LOAD_CHUNK_SIZE);
+        Self::from_file_in_chunks(filename, chunk_size)
+    }
+
+    /// A document of `filename`. With a chunk size, only that much is read
+    /// now, and the rest in the background: see `poll_loading`.
+    pub fn from_file_in_chunks(filename: PathBuf, chunk_size: Option<usize>) -> Result<Self, std::io::Error> {
+        let started = match chunk_size {
+            Some(chunk_size) => FileLoader::start(&filename, chunk_size)?,
+            None => None,
+        };
+        let mut doc = match started {
+            Some((LoadStart::Text(start), loader)) => {
+                let mut doc = Self::from_bytes(&start);
+                doc.text_buffer.load_in_background(loader);
+                doc
+            }
+            #[cfg(feature = "mmap")]
+            Some((LoadStart::Mapped(file, len), loader)) => {
+                let mut doc = Self::from_bytes(b"");
+                doc.text_buffer = TextBuffer::from_mapped(file, len);
+                doc.text_buffer.load_in_background(loader);
+                doc
+            }
+            None => Self::from_bytes(&fs::read(&filename)?),
+        };
+        doc.disk_modified = Self::modified_time(&filename);
+        doc.git_signs = super::git::signs(&filename);
+        doc.filename = Some(filename);
+        Ok(doc)
+    }
+
+    /// An unnamed document of `bytes`, decoded as a file would be (virus -
+    /// reads stdin with this)
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let decoded = Encoding::decode(bytes);
+        let content = decoded.text;
         let line_ending = LineEnding::detect(&content);
+        let end_of_line = Self::ends_with_line_break(&content);
         
         let mut text_buffer = TextBuffer::from_string(content);
         text_buffer.set_line_ending(line_ending);
 
-        Ok(Self {
+        Self {
             cursor_line: 0,
             cursor_column: 0,
-            filename: Some(filename),
+            filename: None,
             modified: false,
+            read_only: decoded.binary,
             line_ending,
+            end_of_line,
+            encoding: decoded.encoding,
+            bom: decoded.bom,
+            binary: decoded.binary,
             expand_tab: true, // Default to spaces
+            shift_width: 0,
+            shift_round: false,
+            auto_indent: false,
+            auto_pairs: false,
+            text_width: 0,
+            wrap_paste: false,
+            trim_trailing: false,
+            write_program: None,
+            format_program: None,
+            format_on_save: false,
+            stats: super::stats::BufferStats::default(),
+            directory: None,
+            help: false,
+            history_window: None,
+            last_selection: None,
+            git_signs: GitSigns::new(),
+            diagnostics: Vec::new(),
             local_marks: HashMap::new(),
             undo_manager: UndoManager::new(),
             text_buffer,
-        })
+            file_lock: None,
+            disk_modified: None,
+            swap_file: None,
+            swap_ops: 0,
+            seen_revision: 0,
+            changed_at: None,
+        }
+    }
+
+    /// A read-only buffer listing the entries of `path`
+    pub fn from_directory(path: &std::path::Path) -> Result<Self, std::io::Error> {
+        let listing = DirectoryListing::read(path)?;
+        let mut doc = Self::from_string(listing.text());
+        doc.filename = Some(listing.path.clone());
+        doc.read_only = true;
+        doc.directory = Some(listing);
+        Ok(doc)
+    }
+
+    /// Whether a file's last line ends with a line break; an empty file
+    /// counts as one that does, so lines added to it get one
+    fn ends_with_line_break(content: &str) -> bool {
+        content.is_empty() || content.ends_with(['\n', '\r'])
     }
 
     pub fn is_modified(&self) -> bool {
         self.modified
     }
 
+    /// Take the edit lock on this document's file. If another editor holds it,
+    /// the conflict is returned and no lock is taken.
+    pub fn acquire_lock(&mut self) -> Result<(), LockConflict> {
+        if let Some(ref filename) = self.filename
+            && self.directory.is_none()
+        {
+            self.file_lock = FileLock::acquire(filename)?.map(Arc::new);
+        }
+        Ok(())
+    }
+
+    /// Take the edit lock even if another editor holds the file
+    pub fn force_lock(&mut self) {
+        if let Some(ref filename) = self.filename {
+            self.file_lock = FileLock::force_acquire(filename).map(Arc::new);
+        }
+    }
+
 
     
     
@@ -134,8 +377,8 @@ impl Document {
             return Err(format!("Line {} out of bounds (max: {})", line, line_count - 1));
         }
         
-        let line_length = self.get_line_length(line);
-        let safe_column = column.min(line_length);
+        let line_text = self.get_line(line).unwrap_or_default();
+        let safe_column = grapheme_start(&line_text, column.min(line_text.len()));
         
         self.cursor_line = line;
         self.cursor_column = safe_column;
@@ -146,8 +389,8 @@ impl Document {
     pub fn move_cursor_to(&mut self, line: usize, column: usize) {
         let line_count = self.line_count();
         let safe_line = if line_count == 0 { 0 } else { line.min(line_count - 1) };
-        let line_length = self.get_line_length(safe_line);
-        let safe_column = column.min(line_length);
+        let line_text = self.get_line(safe_line).unwrap_or_default();
+        let safe_column = grapheme_start(&line_text, column.min(line_text.len()));
         
         self.cursor_line = safe_line;
         self.cursor_column = safe_column;
@@ -161,12 +404,6 @@ impl Document {
         self.set_cursor(line, current_column)
     }
     
-    /// Set only the cursor column (keeping current line, with clamping)
-    pub fn set_cursor_column_only(&mut self, column: usize) -> Result<(), String> {
-        let current_line = self.cursor_line();
-        self.set_cursor(current_line, column)
-    }
-    
     /// Reset cursor column to 0 (start of line)
     pub fn reset_cursor_column(&mut self) {
         let current_line = self.cursor_line();
@@ -205,7 +442,8 @@ impl Document {
     /// Safe cursor movement - returns true if movement was successful
     pub fn move_cursor_left(&mut self) -> bool {
         if self.cursor_column() > 0 {
-            let _ = self.set_cursor_column_only(self.cursor_column() - 1);
+            let line = self.get_line(self.cursor_line()).unwrap_or_default();
+            self.cursor_column = prev_grapheme(&line, self.cursor_column());
             true
         } else {
             false
@@ -214,24 +452,19 @@ impl Document {
     
     /// Safe cursor movement - returns true if movement was successful
     pub fn move_cursor_right(&mut self) -> bool {
-        let current_line_length = self.get_line_length(self.cursor_line());
-        if self.cursor_column() < current_line_length {
-            let _ = self.set_cursor_column_only(self.cursor_column() + 1);
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        if self.cursor_column() < line.len() {
+            self.cursor_column = next_grapheme(&line, self.cursor_column());
             true
         } else {
             false
         }
     }
     
-    /// Clamp cursor column to current line bounds (used after line content changes)
+    /// Clamp the cursor to the text and snap its column to the start of a
+    /// character (used after line content changes or a move to another line)
     pub fn clamp_cursor_column_to_current_line(&mut self) {
-        let current_line = self.cursor_line();
-        let current_column = self.cursor_column();
-        let line_length = self.get_line_length(current_line);
-        
-        if current_column > line_length {
-            let _ = self.set_cursor(current_line, line_length);
-        }
+        self.move_cursor_to(self.cursor_line(), self.cursor_column());
     }
 
     
@@ -243,8 +476,26 @@ impl Document {
     pub fn undo_manager_mut(&mut self) -> &mut UndoManager {
         &mut self.undo_manager
     }
+
+    pub fn undo_manager(&self) -> &UndoManager {
+        &self.undo_manager
+    }
+
+    /// Put the text in undo state `target` (0 = original), undoing and redoing
+    /// across branches of the undo tree. Returns the number of changes applied.
+    pub fn goto_undo_state(&mut self, target: usize) -> usize {
+        let steps = self.undo_manager.travel_to(target);
+        for step in &steps {
+            match step {
+                super::undo::UndoStep::Undo(group) => group.apply_reverse_to_document(self),
+                super::undo::UndoStep::Redo(group) => group.apply_to_document(self),
+            }
+        }
+        steps.len()
+    }
     
     /// Get mutable reference to text buffer  
+    #[cfg(test)]
     pub fn text_buffer_mut(&mut self) -> &mut TextBuffer {
         &mut self.text_buffer
     }
@@ -252,20 +503,32 @@ impl Document {
 
     // Get line count from piece table
     pub fn line_count(&self) -> usize {
-        let mut text_buffer = self.text_buffer.clone();
-        text_buffer.line_count()
+        self.text_buffer.line_count()
     }
 
     // Get a specific line from piece table
     pub fn get_line(&self, line_num: usize) -> Option<String> {
-        let mut text_buffer = self.text_buffer.clone();
-        text_buffer.get_line(line_num)
+        self.text_buffer.get_line(line_num)
+    }
+
+    /// Changes whenever the text does, so results computed from one version can be checked
+    pub fn revision(&self) -> u64 {
+        self.text_buffer.revision()
+    }
+
+    /// The lines each edit since `revision` touched; None when unknown
+    pub fn changes_since(&self, revision: u64) -> Option<Vec<LineChange>> {
+        self.text_buffer.changes_since(revision)
+    }
+
+    /// The whole buffer with lines joined by "\n", for searches that span lines
+    pub fn buffer_text(&self) -> String {
+        self.text_buffer.get_text_normalized()
     }
 
     // Get line length from piece table
     pub fn get_line_length(&self, line_num: usize) -> usize {
-        let mut text_buffer = self.text_buffer.clone();
-        text_buffer.line_length(line_num)
+        self.text_buffer.line_length(line_num)
     }
     
     // Replace an entire line
@@ -286,9 +549,340 @@ impl Document {
     }
     
     
-    // Check if document is empty
-    pub fn is_empty(&self) -> bool {
-        self.line_count() == 0 || (self.line_count() == 1 && self.get_line_length(0) == 0)
+    /// Replace a line and record the change for undo (used by ex commands)
+    pub fn replace_line_with_undo(&mut self, line_num: usize, new_content: &str) {
+        if let Some(original) = self.get_line(line_num)
+            && original != new_content
+        {
+            self.record_line_replace_undo(line_num, &original, new_content);
+            self.set_line(line_num, new_content);
+        }
+    }
+
+    /// Insert a line and record it for undo (used by ex commands)
+    pub fn insert_line_with_undo(&mut self, line_num: usize, text: &str) {
+        let line_num = line_num.min(self.line_count());
+        self.undo_manager.add_action(super::undo::UndoAction::InsertLine {
+            line: line_num,
+            text: text.to_string(),
+        });
+        self.insert_line_at(line_num, text);
+    }
+
+    /// Delete a line and record it for undo (used by ex commands)
+    pub fn delete_line_with_undo(&mut self, line_num: usize) -> String {
+        if line_num >= self.line_count() {
+            return String::new();
+        }
+        let text = self.delete_line_at(line_num);
+        self.undo_manager.add_action(super::undo::UndoAction::DeleteLine {
+            line: line_num,
+            text: text.clone(),
+        });
+        text
+    }
+
+    /// Replace a line with several lines, recording undo (used by wrapping)
+    pub fn expand_line_with_undo(&mut self, line_num: usize, lines: &[String]) {
+        let Some((first, rest)) = lines.split_first() else {
+            return;
+        };
+        self.replace_line_with_undo(line_num, first);
+        for (i, line) in rest.iter().enumerate() {
+            self.insert_line_with_undo(line_num + 1 + i, line);
+        }
+    }
+
+    /// Replace `count` lines from `start` with `lines`, recording undo
+    pub fn replace_lines_with_undo(&mut self, start: usize, count: usize, lines: &[String]) {
+        for (offset, line) in lines.iter().enumerate() {
+            if offset < count {
+                self.replace_line_with_undo(start + offset, line);
+            } else {
+                self.insert_line_with_undo(start + offset, line);
+            }
+        }
+        for _ in lines.len()..count {
+            self.delete_line_with_undo(start + lines.len());
+        }
+    }
+
+    /// Delete the text from `start` up to, but not including, `end`,
+    /// recording undo, and leave the cursor at `start`. Columns inside a
+    /// character are taken as its start. Returns the text.
+    pub fn delete_span_with_undo(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let first = self.get_line(start.0).unwrap_or_default();
+        let last = self.get_line(end.0).unwrap_or_default();
+        let start = (start.0, grapheme_start(&first, start.1));
+        let end = (end.0, grapheme_start(&last, end.1));
+        let deleted = self.get_text_range(start.0, start.1, end.0, end.1);
+        let joined = format!("{}{}", &first[..start.1], &last[end.1..]);
+        self.replace_lines_with_undo(start.0, end.0 - start.0 + 1, &[joined]);
+        let _ = self.set_cursor(start.0, start.1);
+        deleted
+    }
+
+    /// Delete lines start..=end, recording undo, and leave the cursor on the
+    /// first non-blank of the line that moves up. Deleting every line leaves
+    /// one empty line. Returns the deleted lines.
+    pub fn delete_lines_with_undo(&mut self, start: usize, end: usize) -> String {
+        let end = end.min(self.line_count().saturating_sub(1));
+        let deleted: Vec<String> = (start..=end).filter_map(|line| self.get_line(line)).collect();
+        let replacement = if end - start + 1 >= self.line_count() { vec![String::new()] } else { Vec::new() };
+        self.replace_lines_with_undo(start, end - start + 1, &replacement);
+        let _ = self.set_cursor(start.min(self.line_count().saturating_sub(1)), 0);
+        self.move_first_non_whitespace();
+        deleted.join("\n")
+    }
+
+    /// Hard-wrap lines start..=end to `width` columns, recording undo.
+    /// Returns the number of lines added.
+    pub fn wrap_lines_with_undo(&mut self, start: usize, end: usize, width: usize, tab_stop: usize) -> usize {
+        let mut added = 0;
+        // Work bottom-up so earlier line numbers stay valid
+        for line_num in (start..=end.min(self.line_count().saturating_sub(1))).rev() {
+            let Some(line) = self.get_line(line_num) else {
+                continue;
+            };
+            let wrapped = super::reflow::wrap_line(&line, width, tab_stop);
+            if wrapped.len() > 1 {
+                added += wrapped.len() - 1;
+                self.expand_line_with_undo(line_num, &wrapped);
+            }
+        }
+        added
+    }
+
+    /// Refill the paragraphs of lines start..=end to `width` columns for
+    /// gq, recording undo. Returns the number of lines they now take.
+    pub fn format_lines_with_undo(&mut self, start: usize, end: usize, width: usize, tab_stop: usize) -> usize {
+        let end = end.min(self.line_count().saturating_sub(1));
+        let lines: Vec<String> = (start..=end).filter_map(|line| self.get_line(line)).collect();
+        let formatted = super::reflow::format_lines(&lines, width, tab_stop);
+        if formatted != lines {
+            self.replace_lines_with_undo(start, lines.len(), &formatted);
+        }
+        formatted.len()
+    }
+
+    /// The delimiters `target` names around the cursor (see surround.rs)
+    pub fn surrounding(&self, target: char) -> Option<super::surround::Surrounding> {
+        let first = self.cursor_line().saturating_sub(super::surround::SEARCH_LINES);
+        let last = (self.cursor_line() + super::surround::SEARCH_LINES).min(self.line_count().saturating_sub(1));
+        let lines: Vec<String> = (first..=last).filter_map(|line| self.get_line(line)).collect();
+        super::surround::find(&lines, first, (self.cursor_line(), self.cursor_column()), target)
+    }
+
+    /// Replace the delimiters `target` names around the cursor with those
+    /// for `replacement`, or remove them when it is None, recording undo.
+    /// The cursor goes to where the opening one was. False if there are
+    /// none around the cursor.
+    pub fn change_surround_with_undo(&mut self, target: char, replacement: Option<char>) -> bool {
+        let Some(found) = self.surrounding(target) else {
+            return false;
+        };
+        let (open, close) = replacement.map(super::surround::delimiters).unwrap_or_default();
+        // The close first, so the open's columns still hold on a shared line
+        for ((line, start, end), text) in [(found.close, close), (found.open, open)] {
+            let mut content = self.get_line(line).unwrap_or_default();
+            content.replace_range(start..end, &text);
+            self.replace_line_with_undo(line, &content);
+        }
+        let _ = self.set_cursor(found.open.0, found.open.1);
+        true
+    }
+
+    /// Put the delimiters for `target` around the text from `start` to
+    /// `end` (exclusive), recording undo. The cursor goes to the opening one.
+    pub fn surround_with_undo(&mut self, start: (usize, usize), end: (usize, usize), target: char) {
+        let (open, close) = super::surround::delimiters(target);
+        for ((line, column), text) in [(end, close), (start, open)] {
+            let mut content = self.get_line(line).unwrap_or_default();
+            content.insert_str(column.min(content.len()), &text);
+            self.replace_line_with_undo(line, &content);
+        }
+        let _ = self.set_cursor(start.0, start.1);
+    }
+
+    /// Rewrite the whitespace of lines start..=end for :retab, recording undo.
+    /// Returns the number of lines changed.
+    pub fn retab_lines_with_undo(&mut self, start: usize, end: usize, retab: &super::retab::Retab) -> usize {
+        let mut changed = 0;
+        for line_num in start..=end.min(self.line_count().saturating_sub(1)) {
+            let Some(line) = self.get_line(line_num) else {
+                continue;
+            };
+            let new_line = retab.apply(&line);
+            if new_line != line {
+                self.replace_line_with_undo(line_num, &new_line);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Strip the spaces and tabs from the ends of lines start..=end,
+    /// recording undo. Returns how many lines changed.
+    pub fn trim_trailing_with_undo(&mut self, start: usize, end: usize) -> usize {
+        let mut changed = 0;
+        for line_num in start..=end.min(self.line_count().saturating_sub(1)) {
+            let Some(line) = self.get_line(line_num) else {
+                continue;
+            };
+            let trimmed = line.trim_end_matches([' ', '\t']);
+            if trimmed.len() != line.len() {
+                self.replace_line_with_undo(line_num, trimmed);
+                changed += 1;
+            }
+        }
+        let column = self.cursor_column.min(self.get_line_length(self.cursor_line));
+        self.cursor_column = column;
+        changed
+    }
+
+    /// Make lines `start..start + count` read as `lines`, recording undo.
+    /// Only the lines that differ are replaced, so the cursor and the marks
+    /// on the others stay where they were. Returns how many lines changed.
+    pub fn replace_lines_minimally_with_undo(&mut self, start: usize, count: usize, lines: &[String]) -> usize {
+        let old: Vec<String> = (start..start + count).filter_map(|line| self.get_line(line)).collect();
+        let hunks = super::diff::diff_lines(&old, lines);
+
+        // Where the cursor's line ends up: moved by the hunks above it, or
+        // kept at the same offset into the hunk it is in
+        let cursor = self.cursor_line;
+        let mut cursor_line = cursor;
+        if cursor >= start {
+            let offset = cursor - start;
+            let mut shift = 0isize;
+            for hunk in &hunks {
+                if hunk.old.end <= offset {
+                    shift += hunk.new.len() as isize - hunk.old.len() as isize;
+                } else if hunk.old.start <= offset {
+                    let into = (offset - hunk.old.start).min(hunk.new.len().saturating_sub(1));
+                    shift = (hunk.new.start + into) as isize - offset as isize;
+                    break;
+                }
+            }
+            cursor_line = (cursor as isize + shift).max(0) as usize;
+        }
+
+        // From the bottom up, so each hunk's lines are still where the diff found them
+        for hunk in hunks.iter().rev() {
+            self.replace_lines_with_undo(start + hunk.old.start, hunk.old.len(), &lines[hunk.new.clone()]);
+        }
+        let line = cursor_line.min(self.line_count().saturating_sub(1));
+        self.cursor_line = line;
+        self.cursor_column = self.cursor_column.min(self.get_line_length(line));
+        hunks.iter().map(|hunk| hunk.old.len().max(hunk.new.len())).sum()
+    }
+
+    /// Pipe lines start..=end through 'formatprg' and put back what it
+    /// prints, changing only the lines that differ, recording undo. Returns
+    /// how many lines changed. A failing formatter leaves the text alone.
+    pub fn run_formatter_with_undo(&mut self, start: usize, end: usize) -> Result<usize, String> {
+        let program = self.format_program.clone().ok_or("'formatprg' is not set")?;
+        let end = end.min(self.line_count().saturating_sub(1));
+        let lines: Vec<String> = (start..=end).filter_map(|line| self.get_line(line)).collect();
+        let input = format!("{}\n", lines.join("\n"));
+        let output = super::shell::filter(&program, input.into_bytes())
+            .map_err(|e| format!("Can't run formatprg `{}`: {}", program, e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let first = stderr.lines().find(|line| !line.trim().is_empty()).unwrap_or_default();
+            return Err(format!("formatprg `{}` failed ({}): {}", program, output.status, first.trim()));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut formatted: Vec<String> = stdout.strip_suffix('\n').unwrap_or(&stdout).split('\n').map(str::to_string).collect();
+        if stdout.is_empty() {
+            formatted.clear();
+        }
+        if formatted.is_empty() && lines.len() >= self.line_count() {
+            formatted.push(String::new());
+        }
+        Ok(self.replace_lines_minimally_with_undo(start, lines.len(), &formatted))
+    }
+
+    /// Shift lines start..=end right by `levels` steps of 'shiftwidth'
+    /// (left if negative), recording undo. With 'shiftround' the indent
+    /// lands on a multiple of the step. The new indent uses tabs unless
+    /// 'expandtab' is set. Blank lines are left alone.
+    pub fn shift_lines_with_undo(&mut self, start: usize, end: usize, levels: isize, tab_stop: usize) {
+        let step = self.shift_columns(tab_stop).max(1);
+        for line_num in start..=end.min(self.line_count().saturating_sub(1)) {
+            let Some(line) = self.get_line(line_num).filter(|line| !line.is_empty()) else {
+                continue;
+            };
+            let body = line.trim_start_matches([' ', '\t']);
+            let width = Self::indent_width(&line[..line.len() - body.len()], tab_stop);
+            let new_width = if self.shift_round {
+                let level = if levels > 0 { width / step } else { width.div_ceil(step) };
+                level.saturating_add_signed(levels) * step
+            } else {
+                width.saturating_add_signed(levels * step as isize)
+            };
+            let shifted = format!("{}{body}", self.make_indent(new_width, tab_stop));
+            if shifted != line {
+                self.replace_line_with_undo(line_num, &shifted);
+            }
+        }
+    }
+
+    /// Ctrl-T and Ctrl-D in insert mode: shift the cursor line as >> and <<
+    /// do, an empty one too, keeping the cursor on the same text
+    pub fn shift_cursor_line_with_undo(&mut self, levels: isize, tab_stop: usize) {
+        let line = self.cursor_line();
+        let before = self.get_line_length(line);
+        if before == 0 && levels > 0 {
+            let indent = self.make_indent(self.shift_columns(tab_stop) * levels as usize, tab_stop);
+            self.replace_line_with_undo(line, &indent);
+        } else {
+            self.shift_lines_with_undo(line, line, levels, tab_stop);
+        }
+        let column = (self.cursor_column() + self.get_line_length(line)).saturating_sub(before);
+        let _ = self.set_cursor(line, column);
+    }
+
+    /// Columns taken by `indent` (spaces and tabs)
+    fn indent_width(indent: &str, tab_stop: usize) -> usize {
+        let tab_stop = tab_stop.max(1);
+        indent.chars().fold(0, |column, ch| if ch == '\t' { (column / tab_stop + 1) * tab_stop } else { column + 1 })
+    }
+
+    /// Indent `width` columns wide: spaces with 'expandtab', otherwise as
+    /// many tabs as fit and spaces for the rest
+    fn make_indent(&self, width: usize, tab_stop: usize) -> String {
+        if self.expand_tab || tab_stop == 0 {
+            " ".repeat(width)
+        } else {
+            format!("{}{}", "\t".repeat(width / tab_stop), " ".repeat(width % tab_stop))
+        }
+    }
+
+    /// The indent of `line`, for a line opened below or above it with 'autoindent'
+    fn auto_indent_of(&self, line: usize) -> String {
+        if !self.auto_indent {
+            return String::new();
+        }
+        let text = self.get_line(line).unwrap_or_default();
+        let body = text.trim_start_matches([' ', '\t']);
+        text[..text.len() - body.len()].to_string()
+    }
+
+    /// Toggle a block comment from `start` to `end` (exclusive), recording undo.
+    /// Returns true if the text is now commented.
+    pub fn toggle_block_comment_with_undo(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Result<bool, super::comment::CommentError> {
+        let style = super::comment::BlockCommentStyle::for_file(self.filename.as_deref())
+            .ok_or(super::comment::CommentError::UnknownFileType)?;
+        let lines: Vec<String> = (start.0..=end.0).filter_map(|line| self.get_line(line)).collect();
+        let (new_lines, commented) = super::comment::toggle_block_comment(&lines, start.1, end.1, style)?;
+        for (offset, line) in new_lines.iter().enumerate() {
+            self.replace_line_with_undo(start.0 + offset, line);
+        }
+        Ok(commented)
     }
 
     // Insert text at position using piece table
@@ -297,6 +891,40 @@ impl Document {
         let pos = Position::new(line, column);
         self.text_buffer.insert(pos, text);
         self.modified = true;
+        for _ in text.matches('\n') {
+            self.update_marks_line_inserted(line + 1);
+        }
+    }
+
+    /// Insert `text` at the cursor with one edit and one undo action, and
+    /// leave the cursor after it. Line breaks in `text` split the line.
+    pub fn insert_text_with_undo(&mut self, text: &str) {
+        let (line, column) = (self.cursor_line(), self.cursor_column());
+        self.record_insert_undo(line, column, text);
+        self.text_buffer.insert(Position::new(line, column), text);
+        self.modified = true;
+        (self.cursor_line, self.cursor_column) = Self::end_of_text(line, column, text);
+    }
+
+    /// Where `text` ends when it starts at `line`, `column`
+    fn end_of_text(line: usize, column: usize, text: &str) -> (usize, usize) {
+        match text.rsplit_once('\n') {
+            Some((before, last)) => (line + before.matches('\n').count() + 1, last.len()),
+            None => (line, column + text.len()),
+        }
+    }
+
+    /// Delete `text` where it starts at `line`, `column`; it may run over
+    /// several lines. Used to undo inserts.
+    pub(super) fn delete_text_spanning(&mut self, line: usize, column: usize, text: &str) {
+        let (end_line, end_column) = Self::end_of_text(line, column, text);
+        let end_line = end_line.min(self.line_count().saturating_sub(1));
+        let end_column = end_column.min(self.get_line_length(end_line));
+        self.text_buffer.delete(Range::new(Position::new(line, column), Position::new(end_line, end_column)));
+        self.modified = true;
+        for _ in line..end_line {
+            self.update_marks_line_deleted(line + 1);
+        }
     }
 
     // Delete text at position using piece table
@@ -305,7 +933,7 @@ impl Document {
         let start_pos = Position::new(line, column);
         let end_pos = Position::new(line, column + length);
         let range = Range::new(start_pos, end_pos);
-        let deleted_text = self.text_buffer.get_text_range(range.clone());
+        let deleted_text = self.text_buffer.get_text_range(range);
         self.text_buffer.delete(range);
         self.modified = true;
         deleted_text
@@ -362,48 +990,6 @@ impl Document {
         line_content
     }
 
-    // Split a line at given position using piece table
-    pub fn split_line_at(&mut self, line_num: usize, column: usize, insert_text: &str) {
-        use super::text_buffer::Position;
-        if line_num >= self.line_count() {
-            return;
-        }
-
-        let line_length = self.get_line_length(line_num);
-        if column <= line_length {
-            let pos = Position::new(line_num, column);
-            self.text_buffer.insert(pos, &format!("\n{}", insert_text));
-            self.modified = true;
-            
-            // Update marks: new line created at line_num + 1
-            self.update_marks_line_inserted(line_num + 1);
-        }
-    }
-
-    // Join two lines with separator using piece table
-    pub fn join_lines_at(&mut self, line_num: usize, separator: &str) {
-        use super::text_buffer::{Position, Range};
-        if line_num >= self.line_count() - 1 {
-            return;
-        }
-
-        // Replace the newline between the lines with the separator
-        let first_line_length = self.get_line_length(line_num);
-        let start_pos = Position::new(line_num, first_line_length);
-        let end_pos = Position::new(line_num + 1, 0);
-        let range = Range::new(start_pos, end_pos);
-        
-        self.text_buffer.delete(range.clone());
-        self.text_buffer.insert(start_pos, separator);
-        self.modified = true;
-        
-        // Update marks: line line_num + 1 was joined (removed)
-        self.update_marks_line_deleted(line_num + 1);
-    }
-
-
-
-
     #[cfg(test)]
     pub fn get_piece_table_content(&mut self) -> String {
         self.text_buffer.get_text()
@@ -412,7 +998,38 @@ impl Document {
 
 
 
+    /// Writing waits for the rest of a file still loading, and fails if
+    /// it couldn't all be read
+    fn finish_loading(&mut self) -> Result<(), std::io::Error> {
+        match self.poll_loading(true) {
+            Some(Err(e)) => Err(std::io::Error::other(format!("Error reading the file: {}, file not written", e))),
+            _ => Ok(()),
+        }
+    }
+
     pub fn save(&mut self) -> Result<usize, std::io::Error> {
+        self.finish_loading()?;
+        if self.directory.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::IsADirectory, "Can't write a directory listing"));
+        }
+        if self.read_only {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "'readonly' is set (add ! to override)",
+            ));
+        }
+        self.save_forced()
+    }
+
+    /// Save even if the buffer is read-only, taking over the file's edit lock
+    pub fn save_forced(&mut self) -> Result<usize, std::io::Error> {
+        if self.directory.is_some() {
+            return self.save();
+        }
+        if self.read_only {
+            self.read_only = false;
+            self.force_lock();
+        }
         if let Some(ref filename) = self.filename {
             self.save_as(filename.clone())
         } else {
@@ -423,60 +1040,400 @@ impl Document {
         }
     }
 
+    /// The text as it goes to disk: lines separated by the 'fileformat' line
+    /// ending, the last one followed by one too when 'endofline' is set.
+    /// The buffer keeps a final line break as an empty last line, which
+    /// 'endofline' stands in for.
+    pub fn file_content(&self) -> String {
+        let text = self.text_buffer.get_text_normalized();
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        if text.is_empty() && self.line_count() <= 1 {
+            return String::new();
+        }
+        let mut content = text.replace('\n', self.line_ending.as_str());
+        if self.end_of_line {
+            content.push_str(self.line_ending.as_str());
+        }
+        content
+    }
+
+    /// Lines `start..=end` as they go to disk: the whole buffer as
+    /// file_content() has it, or each line with its line break - the last
+    /// line of the buffer only with 'endofline'
+    pub fn lines_content(&self, start: usize, end: usize) -> String {
+        let mut last = self.line_count().saturating_sub(1);
+        // An empty last line is the final line break
+        if last > 0 && self.get_line_length(last) == 0 {
+            last -= 1;
+        }
+        if start == 0 && end >= last {
+            return self.file_content();
+        }
+        let ending = self.line_ending.as_str();
+        let mut content = String::new();
+        for line in start..=end.min(last) {
+            content.push_str(&self.get_line(line).unwrap_or_default());
+            if line < last || self.end_of_line {
+                content.push_str(ending);
+            }
+        }
+        content
+    }
+
+    /// The lines `start..=end` in the buffer's encoding, with a byte order
+    /// mark only if `bom` and the buffer has one
+    pub fn encoded_lines(&self, start: usize, end: usize, bom: bool) -> Result<Vec<u8>, std::io::Error> {
+        self.encoding
+            .encode(&self.lines_content(start, end), self.bom && bom)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// :{range}w file, :w >> file - write or append lines to `path`. The
+    /// buffer keeps its own file name and stays modified. Returns how many
+    /// lines went out.
+    pub fn write_lines(&mut self, start: usize, end: usize, path: &std::path::Path, append: bool) -> Result<usize, std::io::Error> {
+        self.finish_loading()?;
+        let whole = start == 0 && end + 1 >= self.line_count();
+        let content = self.encoded_lines(start, end, whole && !append)?;
+        if !append {
+            // Truncating the file the text is mapped from would pull it out from under the buffer
+            self.text_buffer.unmap_from(path);
+        }
+        let mut file = fs::OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)?;
+        std::io::Write::write_all(&mut file, &content)?;
+        let text = self.lines_content(start, end);
+        let ending = self.line_ending.as_str();
+        Ok(text.matches(ending).count() + usize::from(!text.is_empty() && !text.ends_with(ending)))
+    }
+
     pub fn save_as(&mut self, filename: PathBuf) -> Result<usize, std::io::Error> {
-        let content = self.text_buffer.get_text();
+        self.finish_loading()?;
+        // 'formatonsave': formatting is an undo step of its own, and a
+        // formatter that fails stops the write, as 'writeprg' does
+        if self.format_on_save && self.format_program.is_some() {
+            let cursor = (self.cursor_line, self.cursor_column);
+            self.undo_manager.start_group(cursor);
+            let formatted = self.run_formatter_with_undo(0, self.line_count().saturating_sub(1));
+            self.undo_manager.end_group((self.cursor_line, self.cursor_column));
+            formatted.map_err(|e| std::io::Error::other(format!("{}, file not written", e)))?;
+        }
+        // 'trimtrailing': the trim is an undo step of its own
+        if self.trim_trailing {
+            let cursor = (self.cursor_line, self.cursor_column);
+            self.undo_manager.start_group(cursor);
+            self.trim_trailing_with_undo(0, self.line_count().saturating_sub(1));
+            self.undo_manager.end_group((self.cursor_line, self.cursor_column));
+        }
+        let content = self
+            .encoding
+            .encode(&self.file_content(), self.bom)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let content = match &self.write_program {
+            Some(program) => Self::run_write_program(program, content)?,
+            None => content,
+        };
         let byte_count = content.len();
+        self.text_buffer.unmap_from(&filename);
         fs::write(&filename, &content)?;
+        self.disk_modified = Self::modified_time(&filename);
+        self.git_signs = super::git::signs(&filename);
         self.filename = Some(filename);
         self.modified = false;
+        self.changed_at = None;
+        self.drop_swap_file();
         Ok(byte_count)
     }
 
-    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
-        if self.line_ending != line_ending {
-            self.line_ending = line_ending;
-            self.modified = true;
+    fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// True when the file was written by something else since this buffer
+    /// read or saved it. A file that has gone away doesn't count.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.filename, self.disk_modified) {
+            (Some(filename), Some(known)) if self.directory.is_none() => {
+                Self::modified_time(filename).is_some_and(|now| now != known)
+            }
+            _ => false,
         }
     }
 
-    pub fn insert_file_at_cursor(
-        &mut self,
-        filename: &std::path::Path,
-    ) -> Result<usize, std::io::Error> {
-        let content = fs::read_to_string(filename)?;
-        self.insert_text_at_cursor(&content)
+    /// Keep this buffer's text over the changed file, and stop asking about it
+    /// until the file changes again
+    pub fn keep_over_disk_change(&mut self) {
+        if let Some(filename) = &self.filename {
+            self.disk_modified = Self::modified_time(filename);
+        }
     }
 
-    pub fn insert_file_at_line(
-        &mut self,
-        filename: &std::path::Path,
-        line_num: usize,
-    ) -> Result<usize, std::io::Error> {
-        let content = fs::read_to_string(filename)?;
-        self.insert_text_at_line(&content, line_num)
+    /// Read the file again, replacing the text as one change that u undoes.
+    /// Lines that are the same are left alone, so marks on them stay.
+    pub fn reload(&mut self) -> Result<(), std::io::Error> {
+        let filename = self.filename.clone().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "No filename specified")
+        })?;
+        let decoded = Encoding::decode(&fs::read(&filename)?);
+        let content = decoded.text;
+        (self.encoding, self.bom, self.binary) = (decoded.encoding, decoded.bom, decoded.binary);
+        self.disk_modified = Self::modified_time(&filename);
+        self.line_ending = LineEnding::detect(&content);
+        self.end_of_line = Self::ends_with_line_break(&content);
+        let lines = TextBuffer::from_string(content).get_lines();
+        self.replace_all_lines(&lines);
+        self.modified = false;
+        self.git_signs = super::git::signs(&filename);
+        Ok(())
     }
 
-    pub fn insert_text_at_cursor(&mut self, text: &str) -> Result<usize, std::io::Error> {
-        self.insert_text_at_line(text, self.cursor_line() + 1)
+    /// Replace the text with `lines` as one change that u undoes, leaving
+    /// lines that are the same alone so marks on them stay
+    fn replace_all_lines(&mut self, lines: &[String]) {
+        let cursor = (self.cursor_line, self.cursor_column);
+        self.undo_manager.start_group(cursor);
+        let old_count = self.line_count();
+        for (index, line) in lines.iter().enumerate().take(old_count) {
+            if self.get_line(index).as_deref() != Some(line.as_str()) {
+                self.replace_line_with_undo(index, line);
+            }
+        }
+        if lines.len() > old_count {
+            for (index, line) in lines.iter().enumerate().skip(old_count) {
+                self.insert_line_with_undo(index, line);
+            }
+        } else {
+            for _ in lines.len()..old_count {
+                self.delete_line_with_undo(lines.len());
+            }
+        }
+        let line = cursor.0.min(self.line_count().saturating_sub(1));
+        let _ = self.set_cursor(line, cursor.1.min(self.get_line_length(line)));
+        self.undo_manager.end_group((self.cursor_line, self.cursor_column));
     }
 
+    /// Note the time of any edit made since the last call
+    pub fn track_changes(&mut self, now: Instant) {
+        let revision = self.revision();
+        if revision != self.seen_revision {
+            self.seen_revision = revision;
+            self.changed_at = Some(now);
+        }
+    }
 
-    pub fn insert_text_at_line(
-        &mut self,
-        text: &str,
-        line_num: usize,
-    ) -> Result<usize, std::io::Error> {
-        if text.is_empty() {
-            return Ok(0);
+    /// How long until 'autosave' should write this buffer, when it has
+    /// unsaved edits it could write: zero once `delay` has passed since the
+    /// last one
+    pub fn autosave_wait(&self, now: Instant, delay: Duration) -> Option<Duration> {
+        if !self.modified || self.read_only || self.filename.is_none() || self.directory.is_some() {
+            return None;
         }
+        let changed_at = self.changed_at?;
+        Some(delay.saturating_sub(now.saturating_duration_since(changed_at)))
+    }
 
-        let byte_count = text.len();
+    /// Stop waiting to autosave until the next edit (after a failed write)
+    pub fn forget_changes(&mut self) {
+        self.changed_at = None;
+    }
 
-        // Insert after the specified line (0-based internally, but line_num is 1-based from user)
-        use super::text_buffer::Position;
-        let insert_pos = if line_num == 0 {
-            // Insert at beginning of document
-            Position::new(0, 0)
+    /// Keep the swap file up to date. A modified buffer whose file lock is
+    /// held gets a swap file with a snapshot of its text; after that, edits
+    /// are appended once SWAP_SYNC_OPS of them are waiting, or straight away
+    /// with `force`. Saving removes it.
+    pub fn sync_swap_file(&mut self, force: bool) {
+        if !self.modified || self.file_lock.is_none() || self.directory.is_some() {
+            self.drop_swap_file();
+            return;
+        }
+        let Some(filename) = self.filename.clone() else {
+            return;
+        };
+
+        let pending = self.text_buffer.pending_journal_len();
+        let flush = force || pending >= SWAP_SYNC_OPS;
+        let snapshot = self.swap_file.is_none()
+            || !self.text_buffer.is_journaling()
+            || (flush && self.swap_ops + pending >= SWAP_COMPACT_OPS);
+        if snapshot {
+            match SwapFile::create(&filename, &self.text_buffer.get_text_normalized(), self.line_ending) {
+                Ok(swap_file) => {
+                    self.swap_file = Some(Arc::new(swap_file));
+                    self.swap_ops = 0;
+                    self.text_buffer.start_journal();
+                }
+                Err(_) => self.drop_swap_file(),
+            }
+        } else if flush && pending > 0 {
+            let ops = self.text_buffer.take_journal();
+            let written = self.swap_file.as_ref().is_some_and(|swap_file| swap_file.append(&ops).is_ok());
+            if written {
+                self.swap_ops += ops.len();
+            } else {
+                // Start over from a snapshot next time
+                self.text_buffer.stop_journal();
+            }
+        }
+    }
+
+    fn drop_swap_file(&mut self) {
+        self.swap_file = None;
+        self.swap_ops = 0;
+        self.text_buffer.stop_journal();
+    }
+
+    /// Restore the text a crashed vi-rus left in this file's swap file, as
+    /// one change that u undoes. The buffer takes over the file's lock and
+    /// swap file and is left modified until written.
+    pub fn recover_from_swap(&mut self) -> Result<(), std::io::Error> {
+        let filename = self.filename.clone().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "No filename specified")
+        })?;
+        let swap_path = SwapFile::path_for(&filename);
+        match SwapFile::owner(&filename) {
+            None if !swap_path.exists() => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("No swap file \"{}\"", swap_path.display()),
+                ));
+            }
+            Some(pid) if pid == std::process::id() => {
+                return Err(std::io::Error::other("The swap file is this editor's own"));
+            }
+            Some(pid) if FileLock::process_alive(pid) => {
+                return Err(std::io::Error::other(format!("The swap file belongs to a running vi-rus (PID {})", pid)));
+            }
+            _ => {}
+        }
+        let recovered = SwapFile::recover(&filename)?;
+
+        let lines = TextBuffer::from_string(recovered.text).get_lines();
+        self.replace_all_lines(&lines);
+        self.line_ending = recovered.line_ending;
+        self.text_buffer.set_line_ending(recovered.line_ending);
+        self.modified = true;
+        self.read_only = false;
+        self.force_lock();
+        self.sync_swap_file(true);
+        Ok(())
+    }
+
+    /// Pipe `content` through 'writeprg' and return what it prints. A failing
+    /// command aborts the save with its stderr in the error.
+    fn run_write_program(program: &str, content: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        let output = super::shell::filter(program, content)?;
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("writeprg `{}` failed ({}), file not written", program, output.status);
+        if !stderr.trim().is_empty() {
+            message.push('\n');
+            message.push_str(stderr.trim_end());
+        }
+        Err(std::io::Error::other(message))
+    }
+
+    /// :set fileformat - every line break is written the new way on save
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.text_buffer.set_line_ending(line_ending);
+            self.modified = true;
+        }
+    }
+
+    /// :set fileencoding - the text is written in `encoding` on save. UTF-16
+    /// gets a byte order mark, so it reads back the same way.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if self.encoding != encoding {
+            self.encoding = encoding;
+            self.bom = matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be);
+            self.modified = true;
+        }
+    }
+
+    /// Status line warning for a file that didn't decode as text
+    pub fn load_warning(&self) -> Option<String> {
+        self.binary.then(|| "W: not a text file, undecodable bytes replaced; opened read-only (:w! to write anyway)".to_string())
+    }
+
+    /// True while the rest of the file is still being read
+    pub fn is_loading(&self) -> bool {
+        self.text_buffer.load_progress().is_some()
+    }
+
+    /// How much of a file still loading has been read, as a percentage
+    pub fn load_progress(&self) -> Option<u8> {
+        self.text_buffer.load_progress()
+    }
+
+    /// Add the text read of a file loading in the background since the
+    /// last call; with `wait`, wait for the rest of it. Once the load has
+    /// ended, returns Ok, or Err with why the file couldn't all be read:
+    /// the buffer is made read-only then, so :w can't cut the file short.
+    pub fn poll_loading(&mut self, wait: bool) -> Option<Result<(), String>> {
+        match self.text_buffer.take_loaded(wait)? {
+            LoadEnd::Complete { replaced, end_of_line } => {
+                self.end_of_line = end_of_line;
+                if replaced {
+                    self.binary = true;
+                    self.read_only = true;
+                }
+                Some(Ok(()))
+            }
+            LoadEnd::Failed(e) => {
+                self.read_only = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// :set endofline / noendofline
+    pub fn set_end_of_line(&mut self, end_of_line: bool) {
+        if self.end_of_line != end_of_line {
+            self.end_of_line = end_of_line;
+            self.modified = true;
+        }
+    }
+
+    pub fn insert_file_at_cursor(
+        &mut self,
+        filename: &std::path::Path,
+    ) -> Result<usize, std::io::Error> {
+        let content = Encoding::decode(&fs::read(filename)?).text;
+        self.insert_text_at_cursor(&content)
+    }
+
+    pub fn insert_file_at_line(
+        &mut self,
+        filename: &std::path::Path,
+        line_num: usize,
+    ) -> Result<usize, std::io::Error> {
+        let content = Encoding::decode(&fs::read(filename)?).text;
+        self.insert_text_at_line(&content, line_num)
+    }
+
+    pub fn insert_text_at_cursor(&mut self, text: &str) -> Result<usize, std::io::Error> {
+        self.insert_text_at_line(text, self.cursor_line() + 1)
+    }
+
+
+    pub fn insert_text_at_line(
+        &mut self,
+        text: &str,
+        line_num: usize,
+    ) -> Result<usize, std::io::Error> {
+        if text.is_empty() {
+            return Ok(0);
+        }
+
+        let byte_count = text.len();
+
+        // Insert after the specified line (0-based internally, but line_num is 1-based from user)
+        use super::text_buffer::Position;
+        let insert_pos = if line_num == 0 {
+            // Insert at beginning of document
+            Position::new(0, 0)
         } else {
             let target_line = (line_num - 1).min(self.line_count().saturating_sub(1));
             let line_length = self.get_line_length(target_line);
@@ -492,6 +1449,7 @@ impl Document {
             text.to_string()
         };
 
+        self.record_insert_undo(insert_pos.line, insert_pos.column, &text_to_insert);
         self.text_buffer.insert(insert_pos, &text_to_insert);
         self.modified = true;
         Ok(byte_count)
@@ -504,27 +1462,90 @@ impl Document {
         let pos = Position::new(self.cursor_line(), self.cursor_column());
         self.text_buffer.insert(pos, &c.to_string());
         
-        self.cursor_column += 1;
+        self.cursor_column += c.len_utf8();
         self.modified = true;
     }
 
-    pub fn insert_newline(&mut self) {
-        let current_line = self.get_line(self.cursor_line()).unwrap_or_default();
-        let new_line = if self.cursor_column() < current_line.len() {
-            current_line[self.cursor_column()..].to_string()
-        } else {
-            String::new()
+    /// Break the cursor line at the last blank within `width` columns once
+    /// the text before the cursor runs past it, as typing with 'textwidth'
+    /// does. The new line starts with the indent and comment leader of the
+    /// one broken. Tabs are measured with `tab_stop`. Returns true if the
+    /// line was broken.
+    pub fn auto_wrap(&mut self, width: usize, tab_stop: usize) -> bool {
+        use super::reflow::advance;
+        let (line_num, cursor) = (self.cursor_line(), self.cursor_column());
+        let line = self.get_line(line_num).unwrap_or_default();
+        let Some(before) = line.get(..cursor) else {
+            return false;
         };
+        if width == 0 || advance(0, before, tab_stop) <= width {
+            return false;
+        }
+        let prefix = super::reflow::line_prefix(&line);
+        // Where each run of blanks after the prefix starts
+        let blanks: Vec<usize> = before
+            .char_indices()
+            .filter(|&(index, ch)| index >= prefix.len() && ch.is_whitespace() && !line[..index].ends_with(char::is_whitespace))
+            .map(|(index, _)| index)
+            .collect();
+        // The last blank that fits, or failing that the first one
+        let fitting = blanks.iter().rev().find(|&&index| advance(0, &line[..index], tab_stop) <= width);
+        let Some(&start) = fitting.or(blanks.first()) else {
+            return false;
+        };
+        let end = start + line[start..].len() - line[start..].trim_start().len();
+        if end >= cursor {
+            return false;
+        }
 
-        // Record undo action for splitting the line
-        self.undo_manager
-            .add_action(super::undo::UndoAction::SplitLine {
-                line: self.cursor_line(),
-                column: self.cursor_column(),
-                text: new_line.clone(),
-            });
+        let continuation = format!("{prefix}{}", &line[end..]);
+        self.replace_line_with_undo(line_num, &line[..start]);
+        self.insert_line_with_undo(line_num + 1, &continuation);
+        self.cursor_line = line_num + 1;
+        self.cursor_column = prefix.len() + cursor - end;
+        true
+    }
+
+    /// Type `c` with 'autopairs': an opening bracket or quote brings its
+    /// closing one, and a closing one already under the cursor is stepped
+    /// over rather than typed again
+    pub fn insert_char_paired(&mut self, c: char) {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let column = self.cursor_column().min(line.len());
+        let (before, after) = (line[..column].chars().next_back(), line[column..].chars().next());
+        let closes = matches!(c, ')' | ']' | '}') || super::surround::auto_close(c) == Some(c);
+        if closes && after == Some(c) {
+            self.cursor_column += c.len_utf8();
+            return;
+        }
+
+        self.insert_char(c);
+        // Not before a word, nor a quote straight after one (don't)
+        let word = |ch: Option<char>| ch.is_some_and(char::is_alphanumeric);
+        if let Some(close) = super::surround::auto_close(c)
+            && !word(after)
+            && !(close == c && word(before))
+        {
+            self.insert_char(close);
+            self.cursor_column -= close.len_utf8();
+        }
+    }
+
+    /// Backspace with 'autopairs': between an empty pair, delete both
+    pub fn delete_char_paired(&mut self) {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let column = self.cursor_column().min(line.len());
+        let (before, after) = (line[..column].chars().next_back(), line[column..].chars().next());
+        let empty_pair = before.and_then(super::surround::auto_close).is_some_and(|close| after == Some(close));
+        self.delete_char();
+        if empty_pair {
+            self.delete_chars_forward(1);
+        }
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.record_insert_undo(self.cursor_line(), self.cursor_column(), "\n");
 
-        // Use piece table for newline insertion
         use super::text_buffer::Position;
         let pos = Position::new(self.cursor_line(), self.cursor_column());
         self.text_buffer.insert_newline(pos);
@@ -535,26 +1556,24 @@ impl Document {
         
         // Update marks: new line created at cursor_line (after increment)
         self.update_marks_line_inserted(self.cursor_line());
+
+        // 'autoindent': the new line starts as far in as the one split
+        let indent = self.auto_indent_of(self.cursor_line() - 1);
+        if !indent.is_empty() {
+            self.record_insert_undo(self.cursor_line(), 0, &indent);
+            self.text_buffer.insert(Position::new(self.cursor_line(), 0), &indent);
+            self.cursor_column = indent.len();
+        }
     }
 
     pub fn delete_char(&mut self) {
         if self.cursor_column() > 0 {
-            let pos = Position::new(self.cursor_line(), self.cursor_column() - 1);
-            let deleted_char = self.text_buffer.char_at(pos).unwrap_or(' ');
-            
-            self.record_delete_undo(self.cursor_line(), self.cursor_column() - 1, &deleted_char.to_string());
-            self.text_buffer.delete_char(pos);
-            self.cursor_column -= 1;
+            let deleted = self.delete_grapheme_before_cursor();
+            self.record_delete_undo(self.cursor_line(), self.cursor_column(), &deleted);
             self.modified = true;
         } else if self.cursor_line() > 0 {
-            let current_line = self.get_line(self.cursor_line()).unwrap_or_default();
             let previous_line_len = self.get_line_length(self.cursor_line() - 1);
-
-            self.undo_manager.add_action(super::undo::UndoAction::JoinLines {
-                line: self.cursor_line() - 1,
-                separator: String::new(),
-                second_line_text: current_line.clone(),
-            });
+            self.record_delete_undo(self.cursor_line() - 1, previous_line_len, "\n");
 
             let pos = Position::new(self.cursor_line() - 1, previous_line_len);
             self.text_buffer.delete_char(pos);
@@ -565,276 +1584,96 @@ impl Document {
         }
     }
 
-    pub fn delete_char_forward(&mut self) {
-        let pos = Position::new(self.cursor_line(), self.cursor_column());
-        let line_length = self.get_line_length(self.cursor_line());
-        
-        if self.cursor_column() < line_length {
-            if let Some(deleted_char) = self.text_buffer.char_at(pos) {
-                self.record_delete_undo(self.cursor_line(), self.cursor_column(), &deleted_char.to_string());
-                self.text_buffer.delete_char(pos);
-                self.modified = true;
-            }
-        } else if self.cursor_line() < self.line_count() - 1 {
-            let next_line = self.get_line(self.cursor_line() + 1).unwrap_or_default();
-
-            self.undo_manager.add_action(super::undo::UndoAction::JoinLines {
-                line: self.cursor_line(),
-                separator: String::new(),
-                second_line_text: next_line.clone(),
-            });
-
-            self.text_buffer.delete_char(pos);
-            self.modified = true;
-        }
-    }
-
-    pub fn delete_line(&mut self) {
-        if self.line_count() > 1 {
-            self.delete_line_at(self.cursor_line());
-            if self.cursor_line() >= self.line_count() {
-                let _ = self.set_cursor(self.line_count().saturating_sub(1), 0);
-            } else {
-                let _ = self.set_cursor(self.cursor_line(), 0);
-            }
-            self.modified = true;
-        } else {
-            // If only one line, clear it
-            self.set_line(0, "");
-            let _ = self.set_cursor(0, 0);
-            self.modified = true;
-        }
-    }
-
-    pub fn delete_to_end_of_line(&mut self) {
-        let line_length = self.get_line_length(self.cursor_line());
-        if self.cursor_column() < line_length {
-            // Delete from cursor to end of line
-            use super::text_buffer::{Position, Range};
-            let start_pos = Position::new(self.cursor_line(), self.cursor_column());
-            let end_pos = Position::new(self.cursor_line(), line_length);
-            let range = Range::new(start_pos, end_pos);
-            self.text_buffer.delete(range);
-            self.modified = true;
-            
-            // Adjust cursor if needed
-            let new_line_length = self.get_line_length(self.cursor_line());
-            if self.cursor_column() > 0 && self.cursor_column() >= new_line_length {
-                self.cursor_column = new_line_length.saturating_sub(1);
-            }
-        }
-    }
-
-    /// Generic delete operation that moves cursor and deletes text
-    fn delete_with_movement<F>(&mut self, move_fn: F)
-    where
-        F: FnOnce(&mut Self),
-    {
-        let original_line = self.cursor_line();
-        let original_column = self.cursor_column();
-
-        move_fn(self);
-
-        let start_pos = Position::new(original_line, original_column);
-        let end_pos = Position::new(self.cursor_line(), self.cursor_column());
-        let range = Range::new(start_pos, end_pos);
-        
-        self.text_buffer.delete(range);
-        
-        self.cursor_line = original_line;
-        self.cursor_column = original_column;
-        self.modified = true;
-    }
-
-    pub fn delete_word_forward(&mut self) {
-        self.delete_with_movement(Self::move_word_forward);
-    }
-
-    pub fn delete_big_word_forward(&mut self) {
-        self.delete_with_movement(Self::move_big_word_forward);
-    }
-
-    pub fn delete_char_backward(&mut self) {
-        if self.cursor_column() > 0 {
-            // Delete character before cursor using piece table
-            use super::text_buffer::Position;
-            let pos = Position::new(self.cursor_line(), self.cursor_column() - 1);
-            let deleted_char = self.text_buffer.char_at(pos).unwrap_or(' ');
-            
-            self.undo_manager
-                .add_action(super::undo::UndoAction::DeleteText {
-                    line: self.cursor_line(),
-                    column: self.cursor_column() - 1,
-                    text: deleted_char.to_string(),
-                });
-            
-            self.text_buffer.delete_char(pos);
-            self.cursor_column -= 1;
-            self.modified = true;
-        } else if self.cursor_line() > 0 {
-            // Join with previous line
-            let current_line = self.delete_line_at(self.cursor_line());
-            let previous_line_len = self.get_line_length(self.cursor_line() - 1);
-
-            // Record undo action for joining lines
-            self.undo_manager
-                .add_action(super::undo::UndoAction::JoinLines {
-                    line: self.cursor_line() - 1,
-                    separator: String::new(),
-                    second_line_text: current_line.clone(),
-                });
-
-            self.cursor_line -= 1;
-            self.cursor_column = previous_line_len;
-            // Append the current line content to the previous line using piece table
-            use super::text_buffer::Position;
-            let pos = Position::new(self.cursor_line(), previous_line_len);
-            self.text_buffer.insert(pos, &current_line);
+    /// Delete from `column` up to the cursor, recording undo. Returns the
+    /// deleted text.
+    pub fn delete_back_to(&mut self, column: usize) -> String {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let end = self.cursor_column().min(line.len());
+        let start = column.min(end);
+        if start < end {
+            self.record_delete_undo(self.cursor_line(), start, &line[start..end]);
+            self.text_buffer.delete(Range::new(Position::new(self.cursor_line(), start), Position::new(self.cursor_line(), end)));
+            self.cursor_column = start;
             self.modified = true;
         }
+        line[start..end].to_string()
     }
 
-    /// Generic delete operation that moves cursor backward and deletes text
-    fn delete_with_backward_movement<F>(&mut self, move_fn: F)
-    where
-        F: FnOnce(&mut Self),
-    {
-        let original_line = self.cursor_line();
-        let original_column = self.cursor_column();
-
-        move_fn(self);
-
-        let start_pos = Position::new(self.cursor_line(), self.cursor_column());
-        let end_pos = Position::new(original_line, original_column);
-        let range = Range::new(start_pos, end_pos);
-        self.text_buffer.delete(range);
-        self.modified = true;
-    }
-
-    pub fn delete_word_backward(&mut self) {
-        self.delete_with_backward_movement(Self::move_word_backward);
-    }
-
-    pub fn delete_big_word_backward(&mut self) {
-        self.delete_with_backward_movement(Self::move_big_word_backward);
-    }
-
-    pub fn delete_to_end_of_word(&mut self) {
-        self.delete_with_movement(Self::move_word_end);
-    }
-
-    pub fn delete_to_end_of_big_word(&mut self) {
-        self.delete_with_movement(Self::move_big_word_end);
+    /// Where the word before the cursor starts, past any blanks between:
+    /// a run of word characters or of other non-blanks, as Ctrl-W takes
+    pub fn word_start_before_cursor(&self) -> usize {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let before = line[..self.cursor_column().min(line.len())].trim_end();
+        let class = |c: char| c.is_alphanumeric() || c == '_';
+        let Some(last) = before.chars().next_back() else {
+            return 0;
+        };
+        before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| !c.is_whitespace() && class(c) == class(last))
+            .last()
+            .map_or(before.len(), |(index, _)| index)
     }
 
-    /// Generic range delete operation
-    fn delete_range(&mut self, start_line: usize, start_col: usize, end_line: usize, end_col: usize) {
-        if start_line != end_line || start_col != end_col {
-            let start_pos = Position::new(start_line, start_col);
-            let end_pos = Position::new(end_line, end_col);
-            let range = Range::new(start_pos, end_pos);
-            self.text_buffer.delete(range);
+    /// Delete up to count grapheme clusters from the cursor, stopping at the
+    /// end of the line. Returns the deleted text.
+    pub fn delete_chars_forward(&mut self, count: usize) -> String {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let start = grapheme_start(&line, self.cursor_column().min(line.len()));
+        let end = (0..count).fold(start, |column, _| next_grapheme(&line, column));
+        if end > start {
+            self.record_delete_undo(self.cursor_line(), start, &line[start..end]);
+            self.text_buffer.delete(Range::new(Position::new(self.cursor_line(), start), Position::new(self.cursor_line(), end)));
             self.modified = true;
         }
+        line[start..end].to_string()
     }
 
-    pub fn delete_to_start_of_line(&mut self) {
-        if self.cursor_column() > 0 {
-            self.delete_range(self.cursor_line(), 0, self.cursor_line(), self.cursor_column());
-            self.reset_cursor_column();
-        }
-    }
-
-    pub fn delete_to_first_non_whitespace(&mut self) {
+    /// Delete up to count grapheme clusters before the cursor, stopping at the
+    /// start of the line. Returns the deleted text.
+    pub fn delete_chars_backward(&mut self, count: usize) -> String {
         let line = self.get_line(self.cursor_line()).unwrap_or_default();
-        let first_non_ws = line.chars().position(|c| !c.is_whitespace()).unwrap_or(0);
-
-        if self.cursor_column() > first_non_ws {
-            self.delete_range(self.cursor_line(), first_non_ws, self.cursor_line(), self.cursor_column());
-            self.cursor_column = first_non_ws;
-        }
-    }
-
-    pub fn delete_to_end_of_file(&mut self) {
-        let last_line = self.line_count().saturating_sub(1);
-        let last_column = self.get_line_length(last_line);
-        self.delete_range(self.cursor_line(), self.cursor_column(), last_line, last_column);
-    }
-
-    pub fn delete_to_start_of_file(&mut self) {
-        if self.cursor_line() > 0 || self.cursor_column() > 0 {
-            self.delete_range(0, 0, self.cursor_line(), self.cursor_column());
-            self.cursor_line = 0;
-            self.reset_cursor_column();
+        let end = grapheme_start(&line, self.cursor_column().min(line.len()));
+        let start = (0..count).fold(end, |column, _| prev_grapheme(&line, column));
+        if start < end {
+            self.record_delete_undo(self.cursor_line(), start, &line[start..end]);
+            self.text_buffer.delete(Range::new(Position::new(self.cursor_line(), start), Position::new(self.cursor_line(), end)));
+            self.cursor_column = start;
+            self.modified = true;
         }
+        line[start..end].to_string()
     }
 
-    pub fn substitute_char(&mut self) {
-        // Delete current character and enter insert mode
-        self.delete_char_forward();
-    }
-
-    pub fn substitute_line(&mut self) {
-        // Clear current line and move cursor to beginning
-        self.set_line(self.cursor_line(), "");
-        self.reset_cursor_column();
-        self.modified = true;
-    }
-
-    /// Generic character-based delete operation
-    fn delete_until_char_generic(&mut self, target: char, forward: bool, include_char: bool) {
+    /// Delete the grapheme cluster before the cursor and move back over it.
+    /// Returns the deleted text.
+    fn delete_grapheme_before_cursor(&mut self) -> String {
         let line = self.get_line(self.cursor_line()).unwrap_or_default();
-        let cursor_col = self.cursor_column();
-        
-        let target_pos = if forward {
-            line[cursor_col + 1..].find(target).map(|pos| cursor_col + 1 + pos)
-        } else {
-            line[..cursor_col].rfind(target)
-        };
-
-        if let Some(pos) = target_pos {
-            let (start_col, end_col) = if forward {
-                let end = if include_char { pos + 1 } else { pos };
-                (cursor_col, end)
-            } else {
-                let start = if include_char { pos } else { pos + 1 };
-                (start, cursor_col)
-            };
-            
-            self.delete_range(self.cursor_line(), start_col, self.cursor_line(), end_col);
-            
-            if !forward {
-                self.cursor_column = if include_char { pos } else { pos + 1 };
-            }
-        }
-    }
-
-    pub fn delete_until_char(&mut self, target: char) {
-        self.delete_until_char_generic(target, true, false);
-    }
-
-    pub fn delete_until_char_backward(&mut self, target: char) {
-        self.delete_until_char_generic(target, false, false);
-    }
-
-    pub fn delete_find_char(&mut self, target: char) {
-        self.delete_until_char_generic(target, true, true);
+        let end = self.cursor_column().min(line.len());
+        let start = prev_grapheme(&line, end);
+        let range = Range::new(Position::new(self.cursor_line(), start), Position::new(self.cursor_line(), end));
+        self.text_buffer.delete(range);
+        self.cursor_column = start;
+        line[start..end].to_string()
     }
 
-    pub fn delete_find_char_backward(&mut self, target: char) {
-        self.delete_until_char_generic(target, false, true);
+    /// Delete count characters for insert mode to replace. Returns the deleted text.
+    pub fn substitute_chars(&mut self, count: usize) -> String {
+        self.delete_chars_forward(count)
     }
 
     pub fn open_line_below(&mut self) {
+        let indent = self.auto_indent_of(self.cursor_line());
         self.cursor_line += 1;
-        self.reset_cursor_column();
-        self.insert_line_at(self.cursor_line(), "");
+        self.insert_line_with_undo(self.cursor_line(), &indent);
+        self.cursor_column = indent.len();
         self.modified = true;
     }
 
     pub fn open_line_above(&mut self) {
-        self.insert_line_at(self.cursor_line(), "");
-        self.reset_cursor_column();
+        let indent = self.auto_indent_of(self.cursor_line());
+        self.insert_line_with_undo(self.cursor_line(), &indent);
+        self.cursor_column = indent.len();
         self.modified = true;
     }
 
@@ -843,46 +1682,9 @@ impl Document {
         self.expand_tab = expand;
     }
 
-    pub fn tabs_to_spaces(&mut self, tab_width: usize) -> usize {
-        let mut changed_lines = 0;
-        let spaces = " ".repeat(tab_width);
-
-        for line_idx in 0..self.line_count() {
-            if let Some(line) = self.get_line(line_idx) {
-                if line.contains('\t') {
-                    let new_line = line.replace('\t', &spaces);
-                    self.set_line(line_idx, &new_line);
-                    changed_lines += 1;
-                }
-            }
-        }
-
-        if changed_lines > 0 {
-            self.modified = true;
-        }
-
-        changed_lines
-    }
-
-    pub fn spaces_to_tabs(&mut self, tab_width: usize) -> usize {
-        let mut changed_lines = 0;
-        let spaces = " ".repeat(tab_width);
-
-        for line_idx in 0..self.line_count() {
-            if let Some(line) = self.get_line(line_idx) {
-                if line.contains(&spaces) {
-                    let new_line = line.replace(&spaces, "\t");
-                    self.set_line(line_idx, &new_line);
-                    changed_lines += 1;
-                }
-            }
-        }
-
-        if changed_lines > 0 {
-            self.modified = true;
-        }
-
-        changed_lines
+    /// Columns >> and << move lines by: 'shiftwidth', or the tabstop when it is 0
+    pub fn shift_columns(&self, tab_stop: usize) -> usize {
+        if self.shift_width == 0 { tab_stop } else { self.shift_width }
     }
 
     /// Convert Unicode characters to their ASCII equivalents
@@ -893,7 +1695,7 @@ impl Document {
             if let Some(line) = self.get_line(line_idx) {
                 let normalized = Self::normalize_to_ascii(&line);
                 if normalized != line {
-                    self.set_line(line_idx, &normalized);
+                    self.replace_line_with_undo(line_idx, &normalized);
                     changed_lines += 1;
                 }
             }
@@ -967,93 +1769,6 @@ impl Document {
         self.modified = true;
     }
 
-    pub fn indent_line(&mut self, tab_width: usize, use_spaces: bool) {
-        let indent = if use_spaces {
-            " ".repeat(tab_width)
-        } else {
-            "\t".to_string()
-        };
-
-        use super::text_buffer::Position;
-        let pos = Position::new(self.cursor_line(), 0);
-        self.text_buffer.insert(pos, &indent);
-        
-        self.cursor_column += if use_spaces { tab_width } else { 1 };
-        self.modified = true;
-    }
-
-    pub fn indent_lines(
-        &mut self,
-        start_line: usize,
-        count: usize,
-        tab_width: usize,
-        use_spaces: bool,
-    ) {
-        let line_count = self.line_count();
-        let end_line = std::cmp::min(start_line + count, line_count);
-        let indent = if use_spaces {
-            " ".repeat(tab_width)
-        } else {
-            "\t".to_string()
-        };
-
-        for line_idx in start_line..end_line {
-            use super::text_buffer::Position;
-            let pos = Position::new(line_idx, 0);
-            self.text_buffer.insert(pos, &indent);
-        }
-
-        self.modified = true;
-    }
-
-    /// Helper to calculate dedent amount
-    fn calculate_dedent_amount(line: &str, tab_width: usize) -> usize {
-        if line.starts_with('\t') {
-            1
-        } else {
-            line.chars().take(tab_width).take_while(|&ch| ch == ' ').count()
-        }
-    }
-
-    pub fn dedent_line(&mut self, tab_width: usize) {
-        if let Some(line) = self.get_line(self.cursor_line()) {
-            let chars_to_remove = Self::calculate_dedent_amount(&line, tab_width);
-            
-            if chars_to_remove > 0 {
-                let start_pos = Position::new(self.cursor_line(), 0);
-                let end_pos = Position::new(self.cursor_line(), chars_to_remove);
-                let range = Range::new(start_pos, end_pos);
-                self.text_buffer.delete(range);
-                self.cursor_column = self.cursor_column().saturating_sub(chars_to_remove);
-                self.modified = true;
-            }
-        }
-    }
-
-    pub fn dedent_lines(&mut self, start_line: usize, count: usize, tab_width: usize) {
-        let line_count = self.line_count();
-        let end_line = std::cmp::min(start_line + count, line_count);
-        let mut any_modified = false;
-
-        for line_idx in start_line..end_line {
-            if let Some(line) = self.get_line(line_idx) {
-                let chars_to_remove = Self::calculate_dedent_amount(&line, tab_width);
-                
-                if chars_to_remove > 0 {
-                    let start_pos = Position::new(line_idx, 0);
-                    let end_pos = Position::new(line_idx, chars_to_remove);
-                    let range = Range::new(start_pos, end_pos);
-                    self.text_buffer.delete(range);
-                    any_modified = true;
-                }
-            }
-        }
-
-        if any_modified {
-            self.modified = true;
-        }
-    }
-
     /// Set a local mark (a-z) for this buffer
     pub fn set_local_mark(
         &mut self,
@@ -1117,282 +1832,10 @@ impl Document {
         &self.local_marks
     }
 
-    // Yank (copy) operations - return text to be copied to registers
-
-    pub fn yank_line(&self) -> String {
-        if !self.is_empty() && self.cursor_line() < self.line_count() {
-            self.get_line(self.cursor_line()).unwrap_or_default()
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_to_end_of_line(&self) -> String {
-        if let Some(line) = self.get_line(self.cursor_line()) {
-            if self.cursor_column() <= line.len() {
-                line[self.cursor_column()..].to_string()
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_word_forward(&self) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        let (end_line, end_col) = self.calculate_word_forward_position();
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_big_word_forward(&self) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        let (end_line, end_col) = self.calculate_big_word_forward_position();
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_word_backward(&self) -> String {
-        let end_line = self.cursor_line();
-        let end_col = self.cursor_column();
-
-        let (start_line, start_col) = self.calculate_word_backward_position();
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_big_word_backward(&self) -> String {
-        let end_line = self.cursor_line();
-        let end_col = self.cursor_column();
-
-        let (start_line, start_col) = self.calculate_big_word_backward_position();
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_to_end_of_word(&self) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        let (end_line, end_col_raw) = self.calculate_word_end_position();
-        let end_col = end_col_raw + 1; // Include the character at cursor
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_to_end_of_big_word(&self) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        let (end_line, end_col_raw) = self.calculate_big_word_end_position();
-        let end_col = end_col_raw + 1; // Include the character at cursor
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_to_start_of_line(&self) -> String {
-        if let Some(line) = self.get_line(self.cursor_line()) {
-            if self.cursor_column() <= line.len() {
-                line[..self.cursor_column()].to_string()
-            } else {
-                line.to_string()
-            }
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_to_first_non_whitespace(&self) -> String {
-        if let Some(line) = self.get_line(self.cursor_line()) {
-            let first_non_ws = line.chars().position(|c| !c.is_whitespace()).unwrap_or(0);
-            if self.cursor_column() >= first_non_ws {
-                line[first_non_ws..self.cursor_column()].to_string()
-            } else {
-                line[self.cursor_column()..first_non_ws].to_string()
-            }
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_to_end_of_file(&self) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-        let end_line = self.line_count().saturating_sub(1);
-        let end_col = if !self.is_empty() {
-            self.get_line_length(end_line)
-        } else {
-            0
-        };
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_to_start_of_file(&self) -> String {
-        let start_line = 0;
-        let start_col = 0;
-        let end_line = self.cursor_line();
-        let end_col = self.cursor_column();
-
-        self.get_text_range(start_line, start_col, end_line, end_col)
-    }
-
-    pub fn yank_until_char(&self, target: char) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        if let Some((end_line, end_col)) = self.find_char_position(target, true, true) {
-            self.get_text_range(start_line, start_col, end_line, end_col)
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_until_char_backward(&self, target: char) -> String {
-        let end_line = self.cursor_line();
-        let end_col = self.cursor_column();
-
-        if let Some((start_line, start_col)) = self.find_char_position(target, false, true) {
-            self.get_text_range(start_line, start_col + 1, end_line, end_col)
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_find_char(&self, target: char) -> String {
-        let start_line = self.cursor_line();
-        let start_col = self.cursor_column();
-
-        if let Some((end_line, end_col)) = self.find_char_position(target, true, false) {
-            self.get_text_range(start_line, start_col, end_line, end_col + 1)
-        } else {
-            String::new()
-        }
-    }
-
-    pub fn yank_find_char_backward(&self, target: char) -> String {
-        let end_line = self.cursor_line();
-        let end_col = self.cursor_column();
-
-        if let Some((start_line, start_col)) = self.find_char_position(target, false, false) {
-            self.get_text_range(start_line, start_col, end_line, end_col)
-        } else {
-            String::new()
-        }
-    }
-
-    // Change (delete + insert mode) operations - return deleted text and modify document
-
-    #[allow(dead_code)]
-    pub fn change_char(&mut self) -> String {
-        let deleted = self.get_char_at_cursor();
-        self.delete_char_forward();
-        deleted
-    }
-
-    pub fn change_line(&mut self) -> String {
-        let deleted = self.get_line(self.cursor_line()).unwrap_or_default();
-        self.set_line(self.cursor_line(), "");
-        self.reset_cursor_column();
-        self.modified = true;
-        deleted
-    }
-
-    /// Generic change operation: yank then delete
-    fn change_with_operation<F, G>(&mut self, yank_fn: F, delete_fn: G) -> String
-    where
-        F: Fn(&Self) -> String,
-        G: FnOnce(&mut Self),
-    {
-        let deleted = yank_fn(self);
-        delete_fn(self);
-        deleted
-    }
-
-    pub fn change_to_end_of_line(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_end_of_line, Self::delete_to_end_of_line)
-    }
-
-    pub fn change_word_forward(&mut self) -> String {
-        self.change_with_operation(Self::yank_word_forward, Self::delete_word_forward)
-    }
-
-    pub fn change_big_word_forward(&mut self) -> String {
-        self.change_with_operation(Self::yank_big_word_forward, Self::delete_big_word_forward)
-    }
-
-    pub fn change_word_backward(&mut self) -> String {
-        self.change_with_operation(Self::yank_word_backward, Self::delete_word_backward)
-    }
-
-    pub fn change_big_word_backward(&mut self) -> String {
-        self.change_with_operation(Self::yank_big_word_backward, Self::delete_big_word_backward)
-    }
-
-    pub fn change_to_end_of_word(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_end_of_word, Self::delete_to_end_of_word)
-    }
-
-    pub fn change_to_end_of_big_word(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_end_of_big_word, Self::delete_to_end_of_big_word)
-    }
-
-    pub fn change_to_start_of_line(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_start_of_line, Self::delete_to_start_of_line)
-    }
-
-    pub fn change_to_first_non_whitespace(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_first_non_whitespace, Self::delete_to_first_non_whitespace)
-    }
-
-    pub fn change_to_end_of_file(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_end_of_file, Self::delete_to_end_of_file)
-    }
-
-    pub fn change_to_start_of_file(&mut self) -> String {
-        self.change_with_operation(Self::yank_to_start_of_file, Self::delete_to_start_of_file)
-    }
-
-    pub fn change_until_char(&mut self, target: char) -> String {
-        self.change_with_operation(|doc| doc.yank_until_char(target), |doc| doc.delete_until_char(target))
-    }
-
-    pub fn change_until_char_backward(&mut self, target: char) -> String {
-        self.change_with_operation(|doc| doc.yank_until_char_backward(target), |doc| doc.delete_until_char_backward(target))
-    }
-
-    pub fn change_find_char(&mut self, target: char) -> String {
-        self.change_with_operation(|doc| doc.yank_find_char(target), |doc| doc.delete_find_char(target))
-    }
-
-    pub fn change_find_char_backward(&mut self, target: char) -> String {
-        self.change_with_operation(|doc| doc.yank_find_char_backward(target), |doc| doc.delete_find_char_backward(target))
-    }
-
-    // Helper method to get character at cursor
-    #[allow(dead_code)]
-    fn get_char_at_cursor(&self) -> String {
-        if self.cursor_line() < self.line_count() {
-            let line = self.get_line(self.cursor_line()).unwrap_or_default();
-            if self.cursor_column() < self.get_line_length(self.cursor_line()) {
-                let chars: Vec<char> = line.chars().collect();
-                chars[self.cursor_column()].to_string()
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
-        }
-    }
-
-    /// Join the current line with the next line (vim J command)
+    /// Join the current line with the next line (vim J command), or with
+    /// `spaces` false keep the next line as it is (gJ)
     /// Returns true if lines were joined, false if at last line
-    pub fn join_lines(&mut self) -> bool {
+    pub fn join_lines(&mut self, spaces: bool) -> bool {
         let line_count = self.line_count();
         
         // Check if we can join (not at the last line)
@@ -1407,49 +1850,31 @@ impl Document {
         let current_line_text = self.get_line(current_line).unwrap_or_default();
         let next_line_text = self.get_line(next_line).unwrap_or_default();
 
-        // Remember cursor position before join for undo
+        // Where the next line's text (or the joining space) will start
         let join_position = current_line_text.len();
 
-        // Add a space between lines unless the current line ends with whitespace
-        // or the next line starts with whitespace (vim behavior)
-        let needs_space = !current_line_text.ends_with(' ')
+        // The next line's indent goes, and one space joins them unless the
+        // current line ends with whitespace or either is blank (vim behavior)
+        let needs_space = spaces
+            && !current_line_text.ends_with(' ')
             && !current_line_text.ends_with('\t')
-            && !next_line_text.starts_with(' ')
-            && !next_line_text.starts_with('\t')
             && !current_line_text.is_empty()
-            && !next_line_text.is_empty();
+            && !next_line_text.trim_start().is_empty();
 
         let mut joined_line = current_line_text;
         if needs_space {
             joined_line.push(' ');
         }
 
-        // Trim leading whitespace from the next line
-        let trimmed_next = next_line_text.trim_start();
-        joined_line.push_str(trimmed_next);
-
-        // Record undo information
-        self.undo_manager
-            .add_action(super::undo::UndoAction::JoinLines {
-                line: current_line,
-                separator: if needs_space {
-                    " ".to_string()
-                } else {
-                    String::new()
-                },
-                second_line_text: next_line_text,
-            });
+        // J drops the next line's indent; gJ keeps it
+        joined_line.push_str(if spaces { next_line_text.trim_start() } else { &next_line_text });
 
-        // Update the document using piece table operations
-        self.set_line(current_line, &joined_line);
-        self.delete_line_at(next_line);
+        self.replace_line_with_undo(current_line, &joined_line);
+        self.delete_line_with_undo(next_line);
 
-        // Position cursor at the join point
-        self.cursor_column = if needs_space {
-            join_position + 1
-        } else {
-            join_position
-        };
+        // Position cursor at the join point: on the joining space, or on
+        // the first character from the next line when none was added
+        self.cursor_column = join_position;
 
         self.modified = true;
         true
@@ -1468,12 +1893,9 @@ impl Document {
             return false;
         }
 
-        let chars: Vec<char> = line.chars().collect();
-        if self.cursor_column() >= chars.len() {
+        let Some(original_char) = line[self.cursor_column()..].chars().next() else {
             return false;
-        }
-
-        let original_char = chars[self.cursor_column()];
+        };
         let new_char = if original_char.is_uppercase() {
             original_char.to_lowercase().collect::<String>()
         } else if original_char.is_lowercase() {
@@ -1494,68 +1916,49 @@ impl Document {
                 line: self.cursor_line(),
                 column: self.cursor_column(),
                 text: new_char.clone(),
-            });
-
-        // Replace character using piece table
-        use super::text_buffer::{Position, Range};
-        let char_start = Position::new(self.cursor_line(), self.cursor_column());
-        let char_end = Position::new(self.cursor_line(), self.cursor_column() + 1);
-        let range = Range::new(char_start, char_end);
-        self.text_buffer.replace(range, &new_char);
-
-        // Move cursor forward (vim behavior)
-        let line_length = self.get_line_length(self.cursor_line());
-        if self.cursor_column() < line_length.saturating_sub(1) {
-            self.cursor_column += 1;
-        }
-
-        self.modified = true;
-        true
-    }
-
-    /// Convert current line to lowercase
-    pub fn lowercase_line(&mut self) {
-        self.transform_line(|line| line.to_lowercase());
-    }
-
-    /// Convert current line to uppercase
-    pub fn uppercase_line(&mut self) {
-        self.transform_line(|line| line.to_uppercase());
-    }
-
-    /// Helper to transform current line with a function
-    fn transform_line<F>(&mut self, transform: F)
-    where
-        F: Fn(&str) -> String,
-    {
-        if self.cursor_line() >= self.line_count() {
-            return;
-        }
+            });
 
-        if let Some(original_line) = self.get_line(self.cursor_line()) {
-            let transformed_line = transform(&original_line);
+        // Replace character using piece table
+        use super::text_buffer::{Position, Range};
+        let char_start = Position::new(self.cursor_line(), self.cursor_column());
+        let char_end = Position::new(self.cursor_line(), self.cursor_column() + original_char.len_utf8());
+        let range = Range::new(char_start, char_end);
+        self.text_buffer.replace(range, &new_char);
 
-            if original_line != transformed_line {
-                self.record_line_replace_undo(&original_line, &transformed_line);
-                self.set_line(self.cursor_line(), &transformed_line);
-                self.modified = true;
-                self.clamp_cursor_column_to_current_line();
-            }
+        // Move cursor forward (vim behavior)
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        let next = next_grapheme(&line, self.cursor_column());
+        if next < line.len() {
+            self.cursor_column = next;
         }
+
+        self.modified = true;
+        true
     }
 
     /// Helper to record undo actions for line replacement
-    fn record_line_replace_undo(&mut self, original: &str, new: &str) {
-        self.undo_manager.add_action(super::undo::UndoAction::DeleteText {
-            line: self.cursor_line(),
-            column: 0,
-            text: original.to_string(),
-        });
-        self.undo_manager.add_action(super::undo::UndoAction::InsertText {
-            line: self.cursor_line(),
-            column: 0,
-            text: new.to_string(),
-        });
+    fn record_line_replace_undo(&mut self, line: usize, original: &str, new: &str) {
+        // Only the part between what the two share at either end changed
+        let prefix = original
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(original.len().min(new.len()), |((i, _), _)| i);
+        let suffix = original[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+        let removed = &original[prefix..original.len() - suffix];
+        let added = &new[prefix..new.len() - suffix];
+        if !removed.is_empty() {
+            self.record_delete_undo(line, prefix, removed);
+        }
+        if !added.is_empty() {
+            self.record_insert_undo(line, prefix, added);
+        }
     }
 
     /// Helper to record undo actions for text insertion
@@ -1577,7 +1980,7 @@ impl Document {
     }
 
     // Helper method to get text in a range
-    fn get_text_range(
+    pub fn get_text_range(
         &self,
         start_line: usize,
         start_col: usize,
@@ -1593,323 +1996,28 @@ impl Document {
         let end_pos = Position::new(end_line, end_col);
         let range = Range::new(start_pos, end_pos);
         
-        let mut text_buffer = self.text_buffer.clone();
-        text_buffer.get_text_range(range)
-    }
-
-    // Position calculation functions for yank operations - eliminates document cloning
-    fn calculate_word_forward_position(&self) -> (usize, usize) {
-        let mut cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        let line = self.get_line(cursor_line).unwrap_or_default();
-
-        // If at end of line, move to next line
-        if cursor_column >= self.get_line_length(self.cursor_line()) {
-            if cursor_line < self.line_count() - 1 {
-                cursor_line += 1;
-                cursor_column = 0;
-                // Find first non-whitespace on new line
-                let new_line = self.get_line(cursor_line).unwrap_or_default();
-                for (i, c) in new_line.chars().enumerate() {
-                    if !c.is_whitespace() {
-                        cursor_column = i;
-                        break;
-                    }
-                }
-            }
-            return (cursor_line, cursor_column);
-        }
-
-        let chars: Vec<char> = line.chars().collect();
-        let current_char = chars[cursor_column];
-
-        // Skip whitespace first
-        if current_char.is_whitespace() {
-            while cursor_column < chars.len() && chars[cursor_column].is_whitespace() {
-                cursor_column += 1;
-            }
-        } else {
-            // Determine the type of the current character
-            let is_word_char = current_char.is_alphanumeric() || current_char == '_';
-
-            if is_word_char {
-                // Skip alphanumeric/underscore characters
-                while cursor_column < chars.len() {
-                    let c = chars[cursor_column];
-                    if !(c.is_alphanumeric() || c == '_') {
-                        break;
-                    }
-                    cursor_column += 1;
-                }
-            } else {
-                // Skip punctuation characters (non-whitespace, non-alphanumeric)
-                while cursor_column < chars.len() {
-                    let c = chars[cursor_column];
-                    if c.is_whitespace() || c.is_alphanumeric() || c == '_' {
-                        break;
-                    }
-                    cursor_column += 1;
-                }
-            }
-
-            // Skip whitespace after the word/punctuation
-            while cursor_column < chars.len() && chars[cursor_column].is_whitespace() {
-                cursor_column += 1;
-            }
-        }
-
-        // If we reached end of line, move to next line
-        if cursor_column >= chars.len() {
-            if cursor_line < self.line_count() - 1 {
-                cursor_line += 1;
-                cursor_column = 0;
-                // Find first non-whitespace on new line
-                let new_line = self.get_line(cursor_line).unwrap_or_default();
-                for (i, c) in new_line.chars().enumerate() {
-                    if !c.is_whitespace() {
-                        cursor_column = i;
-                        break;
-                    }
-                }
-            } else {
-                // At end of document, clamp to last character
-                cursor_column = if chars.is_empty() { 0 } else { chars.len() - 1 };
-            }
-        }
-
-        (cursor_line, cursor_column)
-    }
-
-    fn calculate_word_backward_position(&self) -> (usize, usize) {
-        let mut cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        // If at beginning of line, move to end of previous line
-        if cursor_column == 0 {
-            if cursor_line > 0 {
-                cursor_line -= 1;
-                let line = self.get_line(cursor_line).unwrap_or_default();
-                cursor_column = self.get_line_length(self.cursor_line());
-                // Find last non-whitespace character
-                let chars: Vec<char> = line.chars().collect();
-                while cursor_column > 0 && chars[cursor_column - 1].is_whitespace() {
-                    cursor_column -= 1;
-                }
-            }
-            return (cursor_line, cursor_column);
-        }
-
-        let line = self.get_line(cursor_line).unwrap_or_default();
-        let chars: Vec<char> = line.chars().collect();
-
-        // Move back one position first
-        cursor_column -= 1;
-
-        // Skip whitespace
-        while cursor_column > 0 && chars[cursor_column].is_whitespace() {
-            cursor_column -= 1;
-        }
-
-        // If we're now on a character, move to start of this word/punctuation group
-        if cursor_column < chars.len() && !chars[cursor_column].is_whitespace() {
-            let current_char = chars[cursor_column];
-            let is_word_char = current_char.is_alphanumeric() || current_char == '_';
-
-            if is_word_char {
-                // Move to start of alphanumeric word
-                while cursor_column > 0 {
-                    let prev_char = chars[cursor_column - 1];
-                    if !(prev_char.is_alphanumeric() || prev_char == '_') {
-                        break;
-                    }
-                    cursor_column -= 1;
-                }
-            } else {
-                // Move to start of punctuation group
-                while cursor_column > 0 {
-                    let prev_char = chars[cursor_column - 1];
-                    if prev_char.is_whitespace() || prev_char.is_alphanumeric() || prev_char == '_'
-                    {
-                        break;
-                    }
-                    cursor_column -= 1;
-                }
-            }
-        }
-
-        (cursor_line, cursor_column)
-    }
-
-    fn calculate_word_end_position(&self) -> (usize, usize) {
-        let cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        let line = self.get_line(cursor_line).unwrap_or_default();
-        if cursor_column < self.get_line_length(self.cursor_line()) {
-            let chars: Vec<char> = line.chars().collect();
-
-            // If on whitespace, move to start of next word first
-            if chars[cursor_column].is_whitespace() {
-                while cursor_column < chars.len() && chars[cursor_column].is_whitespace() {
-                    cursor_column += 1;
-                }
-            }
-
-            // Move to end of current word
-            while cursor_column < chars.len() - 1
-                && (chars[cursor_column + 1].is_alphanumeric() || chars[cursor_column + 1] == '_')
-            {
-                cursor_column += 1;
-            }
-        }
-
-        (cursor_line, cursor_column)
-    }
-
-    fn calculate_big_word_forward_position(&self) -> (usize, usize) {
-        let mut cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        loop {
-            let line = self.get_line(cursor_line).unwrap_or_default();
-            if cursor_column >= self.get_line_length(self.cursor_line()) {
-                if cursor_line < self.line_count() - 1 {
-                    cursor_line += 1;
-                    cursor_column = 0;
-                } else {
-                    break;
-                }
-            } else {
-                let chars: Vec<char> = line.chars().collect();
-                let start_col = cursor_column;
-
-                // Skip current big word (non-whitespace)
-                while cursor_column < chars.len() && !chars[cursor_column].is_whitespace() {
-                    cursor_column += 1;
-                }
-
-                // Skip whitespace
-                while cursor_column < chars.len() && chars[cursor_column].is_whitespace() {
-                    cursor_column += 1;
-                }
-
-                if cursor_column == start_col {
-                    if cursor_line < self.line_count() - 1 {
-                        cursor_line += 1;
-                        cursor_column = 0;
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
-        }
-
-        (cursor_line, cursor_column)
-    }
-
-    fn calculate_big_word_backward_position(&self) -> (usize, usize) {
-        let mut cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        loop {
-            if cursor_column == 0 {
-                if cursor_line > 0 {
-                    cursor_line -= 1;
-                    cursor_column = self.get_line_length(cursor_line);
-                } else {
-                    break;
-                }
-            } else {
-                let line = self.get_line(cursor_line).unwrap_or_default();
-                let chars: Vec<char> = line.chars().collect();
-
-                cursor_column -= 1;
-
-                // Skip whitespace
-                while cursor_column > 0 && chars[cursor_column].is_whitespace() {
-                    cursor_column -= 1;
-                }
-
-                // Move to start of big word
-                while cursor_column > 0 && !chars[cursor_column - 1].is_whitespace() {
-                    cursor_column -= 1;
-                }
-
-                break;
-            }
-        }
-
-        (cursor_line, cursor_column)
-    }
-
-    fn calculate_big_word_end_position(&self) -> (usize, usize) {
-        let cursor_line = self.cursor_line();
-        let mut cursor_column = self.cursor_column();
-
-        let line = self.get_line(cursor_line).unwrap_or_default();
-        if cursor_column < self.get_line_length(self.cursor_line()) {
-            let chars: Vec<char> = line.chars().collect();
-
-            // If on whitespace, move to start of next word first
-            if chars[cursor_column].is_whitespace() {
-                while cursor_column < chars.len() && chars[cursor_column].is_whitespace() {
-                    cursor_column += 1;
-                }
-            }
-
-            // Move to end of current big word
-            while cursor_column < chars.len() - 1 && !chars[cursor_column + 1].is_whitespace() {
-                cursor_column += 1;
-            }
-        }
-
-        (cursor_line, cursor_column)
+        self.text_buffer.get_text_range(range)
     }
 
-    fn find_char_position(
-        &self,
-        target: char,
-        forward: bool,
-        before: bool,
-    ) -> Option<(usize, usize)> {
+    /// Column of the count-th occurrence of target on the cursor line,
+    /// searching strictly after (forward) or before (backward) the cursor
+    pub fn find_char_column(&self, target: char, forward: bool, count: usize) -> Option<usize> {
         let line = self.get_line(self.cursor_line()).unwrap_or_default();
-        let chars: Vec<char> = line.chars().collect();
-        let mut cursor_column = self.cursor_column();
+        let cursor_col = self.cursor_column();
+        let nth = count.max(1) - 1;
 
         if forward {
-            let start = if before {
-                cursor_column
-            } else {
-                cursor_column + 1
-            };
-            for (i, ch) in chars.iter().enumerate().skip(start) {
-                if *ch == target {
-                    cursor_column = if before && i > 0 { i - 1 } else { i };
-                    return Some((self.cursor_line(), cursor_column));
-                }
-            }
+            line.char_indices()
+                .filter(|&(i, ch)| i > cursor_col && ch == target)
+                .nth(nth)
+                .map(|(i, _)| i)
         } else {
-            let end = if before && cursor_column < chars.len() {
-                cursor_column + 1
-            } else {
-                cursor_column
-            };
-            for i in (0..end).rev() {
-                if chars[i] == target {
-                    cursor_column = if before && i < chars.len() - 1 {
-                        i + 1
-                    } else {
-                        i
-                    };
-                    return Some((self.cursor_line(), cursor_column));
-                }
-            }
+            line.char_indices()
+                .rev()
+                .filter(|&(i, ch)| i < cursor_col && ch == target)
+                .nth(nth)
+                .map(|(i, _)| i)
         }
-
-        None
     }
 
     pub fn get_word_under_cursor(&self) -> Option<String> {
@@ -1954,25 +2062,33 @@ impl Document {
         }
     }
 
-    pub fn find_matching_bracket(&self) -> Option<(usize, usize)> {
-        let line_count = self.line_count();
-        
-        if self.cursor_line() >= line_count {
-            return None;
-        }
+    /// Position of the bracket matching the one under the cursor. The scan
+    /// gives up (None) at any line longer than `max_line_len`.
+    pub fn find_matching_bracket(&self, max_line_len: usize) -> Option<(usize, usize)> {
+        self.find_matching_bracket_at(self.cursor_line(), self.cursor_column(), max_line_len)
+    }
 
-        let line = self.get_line(self.cursor_line()).unwrap_or_default();
-        
-        if self.cursor_column() >= self.get_line_length(self.cursor_line()) {
-            return None;
+    /// Position of the bracket matching the one at `line`, `column`
+    pub fn find_matching_bracket_at(&self, line: usize, column: usize, max_line_len: usize) -> Option<(usize, usize)> {
+        match self.scan_bracket_at(line, column, max_line_len)? {
+            BracketScan::Found(position) => Some(position),
+            BracketScan::Unmatched | BracketScan::GaveUp => None,
         }
+    }
 
-        let chars: Vec<char> = line.chars().collect();
-        if chars.is_empty() || self.cursor_column() >= chars.len() {
-            return None;
+    /// Check if the bracket at the cursor position is unmatched. A scan that
+    /// gave up at a line longer than `max_line_len` doesn't count as unmatched.
+    pub fn is_unmatched_bracket(&self, max_line_len: usize) -> Option<(usize, usize)> {
+        match self.scan_bracket_at(self.cursor_line(), self.cursor_column(), max_line_len)? {
+            BracketScan::Unmatched => Some((self.cursor_line(), self.cursor_column())),
+            BracketScan::Found(_) | BracketScan::GaveUp => None,
         }
+    }
 
-        let cursor_char = chars[self.cursor_column()];
+    /// Look for the partner of the bracket at `line`, `column`. None if
+    /// there isn't a bracket there.
+    fn scan_bracket_at(&self, line: usize, column: usize, max_line_len: usize) -> Option<BracketScan> {
+        let cursor_char = self.get_line(line)?.get(column..)?.chars().next()?;
 
         // Define bracket pairs
         let bracket_pairs = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
@@ -1991,108 +2107,19 @@ impl Document {
             return None;
         };
 
-        if is_opening {
+        Some(if is_opening {
             // Search forward for closing bracket
-            self.find_closing_bracket(opening, closing, self.cursor_line(), self.cursor_column())
+            self.find_closing_bracket(opening, closing, line, column, max_line_len)
         } else {
             // Search backward for opening bracket
-            self.find_opening_bracket(opening, closing, self.cursor_line(), self.cursor_column())
-        }
-    }
-
-    /// Check if the bracket at the cursor position is unmatched
-    pub fn is_unmatched_bracket(&self) -> Option<(usize, usize)> {
-        let line_count = self.line_count();
-        
-        if self.cursor_line() >= line_count {
-            return None;
-        }
-
-        let line = self.get_line(self.cursor_line()).unwrap_or_default();
-        if self.cursor_column() >= self.get_line_length(self.cursor_line()) {
-            return None;
-        }
-
-        let chars: Vec<char> = line.chars().collect();
-        if chars.is_empty() || self.cursor_column() >= chars.len() {
-            return None;
-        }
-
-        let cursor_char = chars[self.cursor_column()];
-
-        // Define bracket pairs
-        let bracket_pairs = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
-
-        // Check if cursor is on a bracket character
-        let (opening, closing, is_opening) = if let Some((open, close)) =
-            bracket_pairs.iter().find(|(open, _)| *open == cursor_char)
-        {
-            (*open, *close, true)
-        } else if let Some((open, close)) = bracket_pairs
-            .iter()
-            .find(|(_, close)| *close == cursor_char)
-        {
-            (*open, *close, false)
-        } else {
-            return None;
-        };
-
-        // Try to find the matching bracket
-        let has_match = if is_opening {
-            self.find_closing_bracket(opening, closing, self.cursor_line(), self.cursor_column())
-                .is_some()
-        } else {
-            self.find_opening_bracket(opening, closing, self.cursor_line(), self.cursor_column())
-                .is_some()
-        };
-
-        // If no match found, this bracket is unmatched
-        if !has_match {
-            Some((self.cursor_line(), self.cursor_column()))
-        } else {
-            None
-        }
+            self.find_opening_bracket(opening, closing, line, column, max_line_len)
+        })
     }
 
     /// Find all unmatched brackets in the document
     pub fn find_all_unmatched_brackets(&self) -> Vec<(usize, usize)> {
-        let mut unmatched = Vec::new();
-        let bracket_pairs = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
-
-        // For each bracket type, track opening brackets and match them with closing ones
-        for (opening, closing) in bracket_pairs {
-            let mut stack: Vec<(usize, usize)> = Vec::new(); // Stack of opening bracket positions
-
-            // Scan through the entire document
-            let mut text_buffer = self.text_buffer.clone();
-            let lines = text_buffer.get_lines();
-            
-            for (line_idx, line) in lines.iter().enumerate() {
-                let chars: Vec<char> = line.chars().collect();
-                for (col_idx, &ch) in chars.iter().enumerate() {
-                    if ch == opening {
-                        // Found opening bracket, push to stack
-                        stack.push((line_idx, col_idx));
-                    } else if ch == closing {
-                        // Found closing bracket, try to match with most recent opening
-                        if stack.is_empty() {
-                            // Unmatched closing bracket
-                            unmatched.push((line_idx, col_idx));
-                        } else {
-                            // Matched pair, remove from stack
-                            stack.pop();
-                        }
-                    }
-                }
-            }
-
-            // Any remaining opening brackets are unmatched
-            unmatched.extend(stack);
-        }
-
-        // Sort by position for consistent ordering
-        unmatched.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
-        unmatched
+        let never = std::sync::atomic::AtomicBool::new(false);
+        super::analysis::unmatched_brackets(&self.buffer_text(), &never).unwrap_or_default()
     }
 
     fn find_closing_bracket(
@@ -2101,36 +2128,31 @@ impl Document {
         closing: char,
         start_line: usize,
         start_col: usize,
-    ) -> Option<(usize, usize)> {
+        max_line_len: usize,
+    ) -> BracketScan {
         let mut depth = 1;
-        let mut line_idx = start_line;
         let mut col_idx = start_col + 1;
 
-        let line_count = self.line_count();
-        
-        while line_idx < line_count {
+        for line_idx in start_line..self.line_count() {
             let line = self.get_line(line_idx).unwrap_or_default();
-            let chars: Vec<char> = line.chars().collect();
-
-            while col_idx < chars.len() {
-                match chars[col_idx] {
-                    ch if ch == opening => depth += 1,
-                    ch if ch == closing => {
-                        depth -= 1;
-                        if depth == 0 {
-                            return Some((line_idx, col_idx));
-                        }
+            if line.len() > max_line_len {
+                return BracketScan::GaveUp;
+            }
+
+            for (col, ch) in line.char_indices().skip_while(|&(col, _)| col < col_idx) {
+                if ch == opening {
+                    depth += 1;
+                } else if ch == closing {
+                    depth -= 1;
+                    if depth == 0 {
+                        return BracketScan::Found((line_idx, col));
                     }
-                    _ => {}
                 }
-                col_idx += 1;
             }
-
-            line_idx += 1;
             col_idx = 0;
         }
 
-        None
+        BracketScan::Unmatched
     }
 
     fn find_opening_bracket(
@@ -2139,73 +2161,31 @@ impl Document {
         closing: char,
         start_line: usize,
         start_col: usize,
-    ) -> Option<(usize, usize)> {
+        max_line_len: usize,
+    ) -> BracketScan {
         let mut depth = 1;
-        let mut line_idx = start_line;
-        let mut col_idx = if start_col > 0 {
-            start_col - 1
-        } else {
-            // If we're at the beginning of a line, we need to move to the previous line
-            if start_line == 0 {
-                return None; // Can't go back further
-            }
-            // Find the first non-empty line going backwards
-            let mut search_line = start_line - 1;
-            loop {
-                let line = self.get_line(search_line).unwrap_or_default();
-                let chars: Vec<char> = line.chars().collect();
-                if !chars.is_empty() {
-                    line_idx = search_line;
-                    break chars.len() - 1;
-                }
-                if search_line == 0 {
-                    return None; // No non-empty lines found
-                }
-                search_line -= 1;
-            }
-        };
 
-        loop {
+        for line_idx in (0..=start_line).rev() {
             let line = self.get_line(line_idx).unwrap_or_default();
-            let chars: Vec<char> = line.chars().collect();
-
-            // Search backwards through the current line
-            loop {
-                if col_idx < chars.len() {
-                    match chars[col_idx] {
-                        ch if ch == closing => depth += 1,
-                        ch if ch == opening => {
-                            depth -= 1;
-                            if depth == 0 {
-                                return Some((line_idx, col_idx));
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-
-                if col_idx == 0 {
-                    break;
-                }
-                col_idx -= 1;
+            if line.len() > max_line_len {
+                return BracketScan::GaveUp;
             }
 
-            // Move to previous line
-            if line_idx == 0 {
-                break;
+            // Search backwards through the line, from just before the cursor on its own line
+            let end = if line_idx == start_line { start_col.min(line.len()) } else { line.len() };
+            for (col, ch) in line[..end].char_indices().rev() {
+                if ch == closing {
+                    depth += 1;
+                } else if ch == opening {
+                    depth -= 1;
+                    if depth == 0 {
+                        return BracketScan::Found((line_idx, col));
+                    }
+                }
             }
-            line_idx -= 1;
-            let prev_line = self.get_line(line_idx).unwrap_or_default();
-            let prev_chars: Vec<char> = prev_line.chars().collect();
-            if prev_chars.is_empty() {
-                // Empty line - continue to next iteration to check previous line
-                col_idx = 0; // Set to 0 so the inner loop will immediately break
-            } else {
-                col_idx = prev_chars.len() - 1;
-            };
         }
 
-        None
+        BracketScan::Unmatched
     }
 }
 
@@ -2213,6 +2193,241 @@ impl Document {
 mod tests {
     use super::*;
 
+    /// Write `text` to `path` and move its modification time on, so the
+    /// change shows even on filesystems with coarse timestamps
+    fn rewrite(path: &std::path::Path, text: &str) {
+        fs::write(path, text).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+    }
+
+    #[test]
+    fn test_write_and_append_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part.txt");
+        let mut doc = Document::from_string("one\ntwo\nthree\n".to_string());
+        doc.line_ending = LineEnding::Windows;
+        assert_eq!(doc.lines_content(1, 1), "two\r\n");
+        // The empty line after "three" is the final line break
+        assert_eq!(doc.lines_content(1, 3), "two\r\nthree\r\n");
+        assert_eq!(doc.lines_content(0, 3), doc.file_content());
+
+        assert_eq!(doc.write_lines(0, 1, &path, false).unwrap(), 2);
+        assert_eq!(doc.write_lines(2, 3, &path, true).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\ntwo\r\nthree\r\n");
+        assert_eq!(doc.filename, None);
+
+        doc.set_end_of_line(false);
+        assert_eq!(doc.lines_content(2, 3), "three");
+    }
+
+    #[test]
+    fn test_reload_after_change_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        let _ = doc.set_cursor(2, 3);
+        assert!(!doc.changed_on_disk());
+
+        rewrite(&path, "one\n2\n");
+        assert!(doc.changed_on_disk());
+        doc.reload().unwrap();
+        assert!(!doc.changed_on_disk());
+        assert!(!doc.is_modified());
+        assert_eq!(doc.text_buffer.get_lines(), vec!["one", "2", ""]);
+        assert_eq!((doc.cursor_line(), doc.cursor_column()), (2, 0));
+
+        // The reload undoes as one change
+        doc.goto_undo_state(0);
+        assert_eq!(doc.text_buffer.get_lines(), vec!["one", "two", "three", ""]);
+
+        // Keeping the buffer stops the question until the file changes again
+        rewrite(&path, "other\n");
+        doc.keep_over_disk_change();
+        assert!(!doc.changed_on_disk());
+        doc.save().unwrap();
+        assert!(!doc.changed_on_disk());
+    }
+
+    #[test]
+    fn test_save_uses_fileformat_and_endofline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        assert_eq!((doc.line_ending, doc.end_of_line), (LineEnding::Windows, true));
+
+        // Lines inserted with "\n" are written with the file's line ending
+        doc.insert_line_at(1, "new");
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\nnew\r\ntwo\r\n");
+
+        doc.set_line_ending(LineEnding::Mac);
+        assert!(doc.is_modified());
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\rnew\rtwo\r");
+
+        doc.set_line_ending(LineEnding::Unix);
+        doc.set_end_of_line(false);
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\nnew\ntwo");
+
+        // A file without a final line break keeps it that way
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        assert!(!doc.end_of_line);
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\nnew\ntwo");
+        doc.set_end_of_line(true);
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\nnew\ntwo\n");
+
+        // An empty buffer writes an empty file
+        let mut doc = Document::new();
+        doc.save_as(path.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_file_encoding_kept_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, [&[0xFE, 0xFF][..], &[0, b'h', 0, 0xE9, 0, b'\n']].concat()).unwrap();
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        assert_eq!((doc.encoding, doc.bom), (Encoding::Utf16Be, true));
+        assert_eq!(doc.get_line(0).unwrap(), "hé");
+        doc.insert_text_at(0, 0, "!");
+        doc.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [&[0xFE, 0xFF][..], &[0, b'!', 0, b'h', 0, 0xE9, 0, b'\n']].concat());
+
+        // Binary files open read-only with a warning
+        fs::write(&path, b"\x00\x01\xff\x00\x02").unwrap();
+        let doc = Document::from_file(path.clone()).unwrap();
+        assert!(doc.binary && doc.read_only);
+        assert!(doc.load_warning().unwrap().contains("opened read-only"));
+    }
+
+    #[test]
+    fn test_file_loaded_in_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        let text: String = (0..2000).map(|i| format!("line {}\r\n", i)).collect();
+        fs::write(&path, &text).unwrap();
+
+        // The start is there at once, and the rest follows
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        assert_eq!(doc.get_line(0).unwrap(), "line 0");
+        assert!(doc.is_loading() && doc.line_count() < 2000);
+        while doc.poll_loading(false).is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!doc.is_loading() && !doc.is_modified());
+        assert_eq!(doc.line_count(), 2001);
+        assert_eq!(doc.get_line(1999).unwrap(), "line 1999");
+        assert_eq!(doc.line_ending, LineEnding::Windows);
+
+        // An edit waits for the rest of the file, and so does a write
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        doc.insert_text_at(0, 0, "!");
+        assert_eq!(doc.line_count(), 2001);
+        assert_eq!(doc.poll_loading(false), Some(Ok(())));
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("!{}", text));
+
+        // Bytes that aren't UTF-8 past the start make it a binary file
+        fs::write(&path, [text.as_bytes(), b"\xff\n"].concat()).unwrap();
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        assert_eq!(doc.poll_loading(true), Some(Ok(())));
+        assert!(doc.binary && doc.read_only);
+        assert!(doc.save().is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_file_mapped_into_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        let text: String = (0..2000).map(|i| format!("line {} é\n", i)).collect();
+        fs::write(&path, &text).unwrap();
+
+        // A file with "\n" line breaks is used where it is
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        assert!(doc.text_buffer.is_mapped_from(&path));
+        assert_eq!(doc.poll_loading(true), Some(Ok(())));
+        assert_eq!(doc.line_count(), 2001);
+        assert_eq!(doc.get_line(1999).unwrap(), "line 1999 é");
+
+        // Writing over the file copies the text out of it first
+        doc.insert_text_at(0, 0, "!");
+        doc.save().unwrap();
+        assert!(!doc.text_buffer.is_mapped_from(&path));
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("!{}", text));
+
+        // So does writing part of the buffer over it
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        assert_eq!(doc.poll_loading(true), Some(Ok(())));
+        assert!(doc.text_buffer.is_mapped_from(&path));
+        assert_eq!(doc.write_lines(0, 4, &path, false).unwrap(), 5);
+        assert!(!doc.text_buffer.is_mapped_from(&path));
+        assert_eq!(doc.get_line(1999).unwrap(), "line 1999 é");
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+
+        // From a carriage return or a byte that isn't UTF-8, the rest is read into memory
+        fs::write(&path, [text.as_bytes(), b"a\r\nb\xff\n"].concat()).unwrap();
+        let mut doc = Document::from_file_in_chunks(path.clone(), Some(100)).unwrap();
+        assert!(doc.text_buffer.is_mapped_from(&path));
+        assert_eq!(doc.poll_loading(true), Some(Ok(())));
+        assert!(doc.binary && doc.end_of_line);
+        assert_eq!(doc.get_line(1999).unwrap(), "line 1999 é");
+        assert_eq!(doc.get_line(2000).unwrap(), "a");
+        assert_eq!(doc.get_line(2001).unwrap(), "b\u{FFFD}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_swap_file_journals_and_recovers_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        let swap_path = SwapFile::path_for(&path);
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        doc.acquire_lock().unwrap();
+
+        // Only a modified buffer has a swap file
+        doc.sync_swap_file(true);
+        assert!(!swap_path.exists());
+        doc.insert_text_at(0, 3, "!");
+        doc.sync_swap_file(false);
+        assert!(swap_path.exists());
+
+        // Edits wait for a forced sync (or SWAP_SYNC_OPS of them)
+        doc.insert_line_at(1, "new");
+        doc.sync_swap_file(false);
+        assert_eq!(SwapFile::recover(&path).unwrap().text, "one!\ntwo\n");
+        doc.sync_swap_file(true);
+        assert_eq!(SwapFile::recover(&path).unwrap().text, "one!\nnew\ntwo\n");
+
+        // The editor "crashes": its swap file stays behind with a dead PID
+        let swap = fs::read_to_string(&swap_path).unwrap();
+        let pid = format!("pid {}\n", std::process::id());
+        fs::write(&swap_path, swap.replace(&pid, "pid 4294967295\n")).unwrap();
+        std::mem::forget(doc);
+
+        let mut doc = Document::from_file(path.clone()).unwrap();
+        assert!(matches!(doc.acquire_lock(), Err(LockConflict::Recoverable { .. })));
+        doc.read_only = true;
+        doc.recover_from_swap().unwrap();
+        assert_eq!(doc.text_buffer.get_lines(), vec!["one!", "new", "two", ""]);
+        assert!(doc.is_modified() && !doc.read_only);
+        assert_eq!(SwapFile::owner(&path), Some(std::process::id()));
+        assert!(doc.recover_from_swap().is_err());
+
+        // Saving removes the swap file
+        doc.save().unwrap();
+        assert!(!swap_path.exists());
+        assert!(doc.recover_from_swap().is_err());
+    }
+
     #[test]
     fn test_piece_table_integration() {
         let mut doc = Document::new();
@@ -2280,4 +2495,97 @@ mod tests {
         println!("✅ Document creation with piece table successful");
     }
 
+
+    #[test]
+    fn test_bracket_scan_gives_up_at_long_lines() {
+        let doc = Document::from_string(format!("(\n{}\n)", "a".repeat(50)));
+        assert_eq!(doc.find_matching_bracket(usize::MAX), Some((2, 0)));
+        assert_eq!(doc.find_matching_bracket(10), None);
+        assert_eq!(doc.is_unmatched_bracket(10), None); // Unknown, not unmatched
+
+        let mut doc = Document::from_string("a)\n(b".to_string());
+        doc.set_cursor(0, 1).unwrap();
+        assert_eq!(doc.is_unmatched_bracket(10), Some((0, 1)));
+        doc.set_cursor(1, 0).unwrap();
+        assert_eq!(doc.is_unmatched_bracket(10), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_shift_lines_by_columns() {
+        let mut doc = Document::from_string("a\n   b\n\tc\n".to_string());
+        doc.shift_width = 4;
+        doc.shift_lines_with_undo(0, 3, 1, 8);
+        assert_eq!(doc.get_piece_table_content(), "    a\n       b\n            c\n");
+
+        // Tabs where they fit without 'expandtab'; the tab counts as 8 columns
+        let mut doc = Document::from_string("\tc\n  d".to_string());
+        doc.expand_tab = false;
+        doc.shift_width = 4;
+        doc.shift_lines_with_undo(0, 1, 2, 8);
+        assert_eq!(doc.get_piece_table_content(), "\t\tc\n\t  d");
+        doc.shift_lines_with_undo(0, 1, -1, 8);
+        assert_eq!(doc.get_piece_table_content(), "\t    c\n      d");
+
+        // 'shiftround' lands on a multiple of the step
+        let mut doc = Document::from_string("   a\n     b".to_string());
+        doc.shift_width = 4;
+        doc.shift_round = true;
+        doc.shift_lines_with_undo(0, 0, 1, 8);
+        doc.shift_lines_with_undo(1, 1, -1, 8);
+        assert_eq!(doc.get_piece_table_content(), "    a\n    b");
+    }
+
+    #[test]
+    fn test_undo_keeps_only_the_changed_text() {
+        use super::super::undo::UndoAction;
+        let mut doc = Document::from_string("let x = 1;\nnext".to_string());
+        doc.undo_manager_mut().start_group((0, 0));
+        doc.replace_line_with_undo(0, "let x = 42;");
+        doc.set_cursor(1, 0).unwrap();
+        doc.delete_char(); // Joins the lines
+        doc.undo_manager_mut().end_group((0, 11));
+
+        let group = doc.undo_manager_mut().undo().unwrap();
+        let texts: Vec<&str> = group
+            .actions
+            .iter()
+            .map(|action| match action {
+                UndoAction::InsertText { text, .. } | UndoAction::DeleteText { text, .. } => text.as_str(),
+                UndoAction::InsertLine { text, .. } | UndoAction::DeleteLine { text, .. } => text.as_str(),
+            })
+            .collect();
+        assert_eq!(texts, ["1", "42", "\n"]);
+        group.apply_reverse_to_document(&mut doc);
+        assert_eq!(doc.get_piece_table_content(), "let x = 1;\nnext");
+        group.apply_to_document(&mut doc);
+        assert_eq!(doc.get_piece_table_content(), "let x = 42;next");
+    }
+
+    #[test]
+    fn test_char_deletes_snap_a_cursor_inside_a_character() {
+        let mut doc = Document::from_string("éé".to_string());
+        doc.cursor_column = 1; // Left inside é by a change elsewhere
+        assert_eq!(doc.delete_chars_forward(1), "é");
+        assert_eq!(doc.get_piece_table_content(), "é");
+        doc.cursor_column = 1;
+        assert_eq!(doc.delete_chars_backward(1), "");
+        assert_eq!(doc.get_piece_table_content(), "é");
+    }
+
+    #[test]
+    fn test_auto_wrap_with_the_cursor_past_the_line() {
+        let mut doc = Document::from_string("abcdefg".to_string());
+        doc.cursor_line = 0;
+        doc.cursor_column = 20; // Left behind by a change that shortened the line
+        assert!(!doc.auto_wrap(5, 8));
+        assert_eq!(doc.get_piece_table_content(), "abcdefg");
+    }
+
+    #[test]
+    fn test_delete_span_inside_characters() {
+        let mut doc = Document::from_string("éé\naé".to_string());
+        assert_eq!(doc.delete_span_with_undo((0, 1), (1, 2)), "éé\na");
+        assert_eq!(doc.get_piece_table_content(), "é");
+        assert_eq!(doc.cursor_column(), 0);
+    }
 }
@@ -0,0 +1,204 @@
+//! File encodings - how the bytes of a file become the buffer's text and back
+//!
+//! Buffers always hold UTF-8. A file is read as UTF-8 when it is valid UTF-8,
+//! as UTF-16 when it starts with a byte order mark or looks like UTF-16 text
+//! (every other byte zero), and as latin1 otherwise, since every byte is a
+//! latin1 character. Files with NUL bytes that aren't UTF-16 are binary: they
+//! open with the undecodable bytes replaced.
+
+/// The 'fileencoding' of a buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+/// A file's text and how it was stored
+#[derive(Debug, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: Encoding,
+    pub bom: bool,    // The file started with a byte order mark
+    pub binary: bool, // Not text: undecodable bytes were replaced with U+FFFD
+}
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+impl Encoding {
+    /// The 'fileencoding' name
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    /// Read a 'fileencoding' name, with the aliases vim accepts
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16" | "utf16" | "utf-16be" | "utf16be" | "ucs-2" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Work out how `bytes` are encoded and decode them
+    pub fn decode(bytes: &[u8]) -> Decoded {
+        let decoded = |text, encoding, bom| Decoded { text, encoding, bom, binary: false };
+        if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+            return Decoded {
+                binary: std::str::from_utf8(rest).is_err(),
+                ..decoded(String::from_utf8_lossy(rest).into_owned(), Encoding::Utf8, true)
+            };
+        }
+        for (bom, encoding) in [(UTF16LE_BOM, Encoding::Utf16Le), (UTF16BE_BOM, Encoding::Utf16Be)] {
+            if let Some(rest) = bytes.strip_prefix(bom)
+                && let Some(text) = encoding.decode_utf16(rest)
+            {
+                return decoded(text, encoding, true);
+            }
+        }
+
+        let has_nul = bytes.contains(&0);
+        if !has_nul && let Ok(text) = std::str::from_utf8(bytes) {
+            return decoded(text.to_string(), Encoding::Utf8, false);
+        }
+        if has_nul && let Some(encoding) = Self::guess_utf16(bytes)
+            && let Some(text) = encoding.decode_utf16(bytes)
+        {
+            return decoded(text, encoding, false);
+        }
+        if has_nul {
+            return Decoded { binary: true, ..decoded(String::from_utf8_lossy(bytes).into_owned(), Encoding::Utf8, false) };
+        }
+        decoded(bytes.iter().map(|&byte| byte as char).collect(), Encoding::Latin1, false)
+    }
+
+    /// The bytes of `text` in this encoding, after a byte order mark if `bom`.
+    /// Err names the first character the encoding can't hold.
+    pub fn encode(self, text: &str, bom: bool) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        match self {
+            Encoding::Utf8 => {
+                if bom {
+                    bytes.extend(UTF8_BOM);
+                }
+                bytes.extend(text.as_bytes());
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if bom {
+                    bytes.extend(if self == Encoding::Utf16Le { UTF16LE_BOM } else { UTF16BE_BOM });
+                }
+                for unit in text.encode_utf16() {
+                    bytes.extend(if self == Encoding::Utf16Le { unit.to_le_bytes() } else { unit.to_be_bytes() });
+                }
+            }
+            Encoding::Latin1 => {
+                for (line, text) in text.split('\n').enumerate() {
+                    if line > 0 {
+                        bytes.push(b'\n');
+                    }
+                    for ch in text.chars() {
+                        let byte = u8::try_from(u32::from(ch))
+                            .map_err(|_| format!("can't write '{}' in latin1 (line {})", ch, line + 1))?;
+                        bytes.push(byte);
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// UTF-16 text without a byte order mark: most of one half of each pair
+    /// of bytes is zero (ASCII characters), and little of the other half
+    fn guess_utf16(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let pairs = bytes.len() / 2;
+        let zeros = |offset: usize| bytes.iter().skip(offset).step_by(2).filter(|&&byte| byte == 0).count();
+        let (even, odd) = (zeros(0), zeros(1));
+        if odd * 2 > pairs && even * 8 < pairs {
+            Some(Encoding::Utf16Le)
+        } else if even * 2 > pairs && odd * 8 < pairs {
+            Some(Encoding::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// None if `bytes` aren't whole, well-formed UTF-16 units
+    fn decode_utf16(self, bytes: &[u8]) -> Option<String> {
+        if !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let units = bytes.chunks_exact(2).map(|pair| match self {
+            Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+            _ => u16::from_be_bytes([pair[0], pair[1]]),
+        });
+        char::decode_utf16(units).collect::<Result<String, _>>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_decode_detects_encoding() {
+        let plain = Encoding::decode("héllo\n".as_bytes());
+        assert_eq!((plain.text.as_str(), plain.encoding, plain.bom, plain.binary), ("héllo\n", Encoding::Utf8, false, false));
+
+        let bom = Encoding::decode(&[UTF8_BOM, b"hi"].concat());
+        assert_eq!((bom.text.as_str(), bom.encoding, bom.bom), ("hi", Encoding::Utf8, true));
+
+        let latin1 = Encoding::decode(b"caf\xe9\n");
+        assert_eq!((latin1.text.as_str(), latin1.encoding), ("café\n", Encoding::Latin1));
+
+        let with_bom = Encoding::decode(&[UTF16BE_BOM, &[0, b'a', 0x20, 0xAC]].concat());
+        assert_eq!((with_bom.text.as_str(), with_bom.encoding, with_bom.bom), ("a€", Encoding::Utf16Be, true));
+
+        let guessed = Encoding::decode(&utf16le("plain text\n"));
+        assert_eq!((guessed.text.as_str(), guessed.encoding, guessed.bom), ("plain text\n", Encoding::Utf16Le, false));
+
+        let binary = Encoding::decode(b"\x7fELF\x02\x01\x00\x00\xff\xfe\x00");
+        assert!(binary.binary);
+        assert_eq!(binary.encoding, Encoding::Utf8);
+        assert!(binary.text.contains('\u{FFFD}'));
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            for bom in [false, true] {
+                let bytes = encoding.encode("line one\nzwei – ✓\n", bom).unwrap();
+                let decoded = Encoding::decode(&bytes);
+                assert_eq!(decoded.text, "line one\nzwei – ✓\n");
+                assert_eq!((decoded.encoding, decoded.bom), (encoding, bom), "{}", encoding.name());
+            }
+        }
+        assert_eq!(Encoding::Latin1.encode("café", false).unwrap(), b"caf\xe9");
+        assert_eq!(Encoding::Latin1.encode("ok\nno ✓", false), Err("can't write '✓' in latin1 (line 2)".to_string()));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Encoding::from_name("UTF-8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::from_name("utf-16"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::from_name("iso-8859-1"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::from_name("koi8-r"), None);
+        assert_eq!(Encoding::Utf16Le.name(), "utf-16le");
+    }
+}
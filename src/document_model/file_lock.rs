@@ -0,0 +1,290 @@
+use super::swap_file::SwapFile;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Who else is editing a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockConflict {
+    ViRus { pid: u32, lock_path: PathBuf },
+    Vim { swap_path: PathBuf },
+    Recoverable { pid: u32, swap_path: PathBuf }, // Swap file left by a vi-rus that is no longer running
+}
+
+impl fmt::Display for LockConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockConflict::ViRus { pid, lock_path } => write!(
+                f,
+                "file is being edited by another vi-rus (PID {}, lock {})",
+                pid,
+                lock_path.display()
+            ),
+            LockConflict::Vim { swap_path } => write!(
+                f,
+                "swap file \"{}\" already exists (vim may be editing this file)",
+                swap_path.display()
+            ),
+            LockConflict::Recoverable { pid, swap_path } => write!(
+                f,
+                "swap file \"{}\" was left by a vi-rus that stopped (PID {}); it may hold unsaved changes",
+                swap_path.display(),
+                pid
+            ),
+        }
+    }
+}
+
+/// How to proceed when a file is already locked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockChoice {
+    ReadOnly, // Open without taking the lock; writes need !
+    Proceed,  // Take the lock anyway
+    Abort,    // Don't open the file
+    Recover,  // Take the lock and restore the text from the swap file
+}
+
+/// Advisory lock file held while a document is open for editing.
+/// The lock file is removed on drop if it still belongs to this process.
+#[derive(Debug)]
+pub struct FileLock {
+    lock_path: PathBuf,
+}
+
+impl FileLock {
+    /// Path of the lock file guarding `file` (`dir/.name.virus-lock`)
+    pub fn lock_path_for(file: &Path) -> PathBuf {
+        Self::sibling_path(file, "virus-lock")
+    }
+
+    /// Path of the swap file vim would create for `file` (`dir/.name.swp`)
+    pub fn vim_swap_path_for(file: &Path) -> PathBuf {
+        Self::sibling_path(file, "swp")
+    }
+
+    pub(super) fn sibling_path(file: &Path, extension: &str) -> PathBuf {
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        file.with_file_name(format!(".{}.{}", name, extension))
+    }
+
+    /// Check whether another live editor holds `file`
+    pub fn find_conflict(file: &Path) -> Option<LockConflict> {
+        let lock_path = Self::lock_path_for(file);
+        if let Some(pid) = Self::read_owner(&lock_path)
+            && pid != std::process::id()
+            && Self::process_alive(pid)
+        {
+            return Some(LockConflict::ViRus { pid, lock_path });
+        }
+
+        if let Some(pid) = SwapFile::owner(file)
+            && pid != std::process::id()
+            && !Self::process_alive(pid)
+        {
+            return Some(LockConflict::Recoverable { pid, swap_path: SwapFile::path_for(file) });
+        }
+
+        let swap_path = Self::vim_swap_path_for(file);
+        if swap_path.exists() {
+            return Some(LockConflict::Vim { swap_path });
+        }
+
+        None
+    }
+
+    /// Take the lock for `file`, failing if another editor already holds it.
+    /// The lock file is created only if it doesn't exist, so of two editors
+    /// opening the file at once just one gets it.
+    pub fn acquire(file: &Path) -> Result<Option<FileLock>, LockConflict> {
+        if let Some(conflict) = Self::find_conflict(file) {
+            return Err(conflict);
+        }
+        let lock_path = Self::lock_path_for(file);
+        match Self::create(&lock_path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if let Some(pid) = Self::read_owner(&lock_path)
+                    && pid != std::process::id()
+                    && Self::process_alive(pid)
+                {
+                    return Err(LockConflict::ViRus { pid, lock_path });
+                }
+                // Left by an editor that stopped: replace it, unless another
+                // editor does so first
+                let _ = fs::remove_file(&lock_path);
+                match Self::create(&lock_path) {
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                        let pid = Self::read_owner(&lock_path).unwrap_or_default();
+                        Err(LockConflict::ViRus { pid, lock_path })
+                    }
+                    result => Ok(result.ok()),
+                }
+            }
+            result => Ok(result.ok()),
+        }
+    }
+
+    /// Take the lock for `file` regardless of other editors.
+    /// Returns None when the lock file can't be written (e.g. read-only directory).
+    pub fn force_acquire(file: &Path) -> Option<FileLock> {
+        let lock_path = Self::lock_path_for(file);
+        fs::write(&lock_path, format!("{}\n", std::process::id())).ok()?;
+        Some(FileLock { lock_path })
+    }
+
+    /// Create the lock file with this process's PID, failing if it exists
+    fn create(lock_path: &Path) -> std::io::Result<FileLock> {
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).open(lock_path)?;
+        std::io::Write::write_all(&mut file, format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(FileLock { lock_path: lock_path.to_path_buf() })
+    }
+
+    fn read_owner(lock_path: &Path) -> Option<u32> {
+        fs::read_to_string(lock_path).ok()?.trim().parse().ok()
+    }
+
+    #[cfg(unix)]
+    pub(super) fn process_alive(pid: u32) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false; // Beyond any PID the system hands out
+        };
+        if pid <= 0 {
+            return false;
+        }
+        // Signal 0 only checks that the process exists; EPERM means it
+        // does, under another user
+        let signalled = unsafe { libc::kill(pid, 0) } == 0;
+        signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    #[cfg(not(unix))]
+    pub(super) fn process_alive(_pid: u32) -> bool {
+        // Without a portable liveness check, treat every lock as live
+        true
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Leave the file alone if another instance took the lock over
+        if Self::read_owner(&self.lock_path) == Some(std::process::id()) {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_path_naming() {
+        let file = Path::new("/some/dir/notes.txt");
+        assert_eq!(FileLock::lock_path_for(file), PathBuf::from("/some/dir/.notes.txt.virus-lock"));
+        assert_eq!(FileLock::vim_swap_path_for(file), PathBuf::from("/some/dir/.notes.txt.swp"));
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        let lock_path = FileLock::lock_path_for(&file);
+
+        let lock = FileLock::acquire(&file).unwrap().unwrap();
+        assert!(lock_path.exists());
+        // Our own lock is not a conflict
+        assert!(FileLock::find_conflict(&file).is_none());
+
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_conflict_with_live_owner() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        // PID 1 is always running
+        fs::write(FileLock::lock_path_for(&file), "1\n").unwrap();
+
+        match FileLock::acquire(&file) {
+            Err(LockConflict::ViRus { pid, .. }) => assert_eq!(pid, 1),
+            other => panic!("expected conflict, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_lock_is_ignored() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        // Above the kernel's pid_max, so never a live process
+        fs::write(FileLock::lock_path_for(&file), "4294967295\n").unwrap();
+
+        assert!(FileLock::find_conflict(&file).is_none());
+        let lock = FileLock::acquire(&file).unwrap();
+        assert!(lock.is_some());
+        assert_eq!(FileLock::read_owner(&FileLock::lock_path_for(&file)), Some(std::process::id()));
+
+        // A PID the system could hand out, but that isn't running
+        assert!(!FileLock::process_alive(1 << 30));
+    }
+
+    #[test]
+    fn test_lock_file_is_created_only_once() {
+        let dir = TempDir::new().unwrap();
+        let lock_path = FileLock::lock_path_for(&dir.path().join("a.txt"));
+
+        // The second of two editors racing for the lock finds it taken
+        let lock = FileLock::create(&lock_path).unwrap();
+        let second = FileLock::create(&lock_path).unwrap_err();
+        assert_eq!(second.kind(), std::io::ErrorKind::AlreadyExists);
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_vim_swap_conflict() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(FileLock::vim_swap_path_for(&file), "").unwrap();
+
+        assert!(matches!(FileLock::find_conflict(&file), Some(LockConflict::Vim { .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_swap_of_stopped_editor_is_recoverable() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        let swap_path = SwapFile::path_for(&file);
+        fs::write(&swap_path, "vi-rus swap 1\npid 4294967295\nending unix\nbase 0\n\n").unwrap();
+
+        match FileLock::find_conflict(&file) {
+            Some(LockConflict::Recoverable { pid, swap_path: found }) => {
+                assert_eq!(pid, 4294967295);
+                assert_eq!(found, swap_path);
+            }
+            other => panic!("expected a recoverable swap file, got {:?}", other),
+        }
+
+        // A swap file of a running editor goes with its lock
+        fs::write(&swap_path, "vi-rus swap 1\npid 1\nending unix\nbase 0\n\n").unwrap();
+        assert!(FileLock::find_conflict(&file).is_none());
+    }
+
+    #[test]
+    fn test_drop_keeps_lock_taken_over_by_other_instance() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        let lock_path = FileLock::lock_path_for(&file);
+
+        let lock = FileLock::force_acquire(&file).unwrap();
+        fs::write(&lock_path, "1\n").unwrap();
+        drop(lock);
+
+        assert!(lock_path.exists());
+    }
+}
@@ -0,0 +1,105 @@
+//! Where the cursor goes once a file is loaded - `+42`, `+` for the last
+//! line, or a `file:line[:column]` suffix as compilers and grep print them:
+//!
+//! ```text
+//! virus +42 src/main.rs
+//! virus src/main.rs:42:7
+//! :e src/main.rs +42
+//! ```
+//!
+//! Lines and columns are 1-based as typed; the column is a byte column, as
+//! in the quickfix list.
+
+use crate::document_model::Document;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilePosition {
+    pub line: Option<usize>,   // None for the last line
+    pub column: Option<usize>, // None for the first non-blank
+}
+
+impl FilePosition {
+    /// Read a `+N` or `+` argument
+    pub fn parse_plus(arg: &str) -> Option<Self> {
+        let number = arg.strip_prefix('+')?;
+        if number.is_empty() {
+            return Some(Self { line: None, column: None });
+        }
+        let line = number.parse().ok()?;
+        Some(Self { line: Some(line), column: None })
+    }
+
+    /// Split `file:line[:column]` into the file and its position. A name
+    /// that exists as it is, or has no number after a colon, is left whole.
+    /// A colon left over from `file:line:column:` is dropped.
+    pub fn split_name(name: &str) -> (&str, Option<Self>) {
+        let trimmed = name.strip_suffix(':').unwrap_or(name);
+        if Path::new(name).exists() {
+            return (name, None);
+        }
+        let numbers = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+
+        if let Some((rest, last)) = trimmed.rsplit_once(':')
+            && numbers(last)
+        {
+            if let Some((file, line)) = rest.rsplit_once(':')
+                && numbers(line)
+                && !file.is_empty()
+            {
+                let position = Self { line: line.parse().ok(), column: last.parse().ok() };
+                return (file, Some(position));
+            }
+            if !rest.is_empty() {
+                return (rest, Some(Self { line: last.parse().ok(), column: None }));
+            }
+        }
+        (name, None)
+    }
+
+    /// Put the cursor of `doc` here, kept inside the text
+    pub fn apply(&self, doc: &mut Document) {
+        match self.line {
+            Some(line) => doc.move_to_line(line.max(1)),
+            None => doc.move_document_end(),
+        }
+        match self.column {
+            Some(column) => {
+                let last = doc.get_line_length(doc.cursor_line()).saturating_sub(1);
+                doc.move_cursor_to(doc.cursor_line(), column.saturating_sub(1).min(last));
+            }
+            None => doc.move_first_non_whitespace(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let at = |line, column| Some(FilePosition { line, column });
+        assert_eq!(FilePosition::parse_plus("+42"), at(Some(42), None));
+        assert_eq!(FilePosition::parse_plus("+"), at(None, None));
+        assert_eq!(FilePosition::parse_plus("+x"), None);
+        assert_eq!(FilePosition::parse_plus("42"), None);
+
+        assert_eq!(FilePosition::split_name("no-such.rs:42:7"), ("no-such.rs", at(Some(42), Some(7))));
+        assert_eq!(FilePosition::split_name("no-such.rs:42:7:"), ("no-such.rs", at(Some(42), Some(7))));
+        assert_eq!(FilePosition::split_name("no-such.rs:42"), ("no-such.rs", at(Some(42), None)));
+        assert_eq!(FilePosition::split_name("no-such.rs"), ("no-such.rs", None));
+        assert_eq!(FilePosition::split_name("no-such:rs"), ("no-such:rs", None));
+        assert_eq!(FilePosition::split_name(":42"), (":42", None));
+
+        let mut doc = Document::from_string("one\n  two\nthree".to_string());
+        FilePosition { line: Some(2), column: None }.apply(&mut doc);
+        assert_eq!((doc.cursor_line(), doc.cursor_column()), (1, 2));
+        FilePosition { line: Some(99), column: Some(3) }.apply(&mut doc);
+        assert_eq!((doc.cursor_line(), doc.cursor_column()), (2, 2));
+        FilePosition { line: Some(1), column: Some(99) }.apply(&mut doc);
+        assert_eq!((doc.cursor_line(), doc.cursor_column()), (0, 2));
+        FilePosition { line: None, column: None }.apply(&mut doc);
+        assert_eq!(doc.cursor_line(), 2);
+    }
+}
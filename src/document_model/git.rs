@@ -0,0 +1,211 @@
+//! Git - the signs that mark lines changed since the file was staged, and
+//! the blame annotations of `:Gblame`, both from the `git` command
+//!
+//! Signs come from `git diff --no-color -U0`, the saved file against the
+//! index, so they are read when a file is loaded or written. Blame is taken
+//! from the buffer's own text, so its lines match the buffer's.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// What the sign column shows beside a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GitSign {
+    Added,    // A line the index doesn't have
+    Modified, // A line that differs from the index
+    Removed,  // Lines the index has were removed below this one
+}
+
+impl GitSign {
+    /// The sign's text, two cells wide
+    pub fn text(self) -> &'static str {
+        match self {
+            GitSign::Added => "+ ",
+            GitSign::Modified => "~ ",
+            GitSign::Removed => "_ ",
+        }
+    }
+}
+
+/// Signs by line
+pub type GitSigns = BTreeMap<usize, GitSign>;
+
+/// `git` run in the directory of `path`
+fn git(path: &Path) -> Command {
+    let mut command = Command::new("git");
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        command.arg("-C").arg(dir);
+    }
+    command.stdin(Stdio::null()).stderr(Stdio::null());
+    command
+}
+
+/// The signs for the file at `path` as it is on disk. Empty when git isn't
+/// installed, the file isn't tracked, or it matches the index.
+pub fn signs(path: &Path) -> GitSigns {
+    let Some(name) = path.file_name() else {
+        return GitSigns::new();
+    };
+    match git(path).args(["diff", "--no-color", "--no-ext-diff", "-U0", "--"]).arg(name).output() {
+        Ok(output) if output.status.success() => parse_diff(&String::from_utf8_lossy(&output.stdout)),
+        _ => GitSigns::new(),
+    }
+}
+
+/// Signs from the `@@ -old,count +new,count @@` hunk headers of a diff
+/// made with no context lines
+pub fn parse_diff(diff: &str) -> GitSigns {
+    let mut signs = GitSigns::new();
+    for header in diff.lines().filter_map(|line| line.strip_prefix("@@ -")) {
+        let mut ranges = header.split_whitespace();
+        let (Some(old), Some(new)) = (ranges.next(), ranges.next().and_then(|new| new.strip_prefix('+'))) else {
+            continue;
+        };
+        let range = |text: &str| -> Option<(usize, usize)> {
+            match text.split_once(',') {
+                Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+                None => Some((text.parse().ok()?, 1)),
+            }
+        };
+        let (Some((_, old_count)), Some((new_start, new_count))) = (range(old), range(new)) else {
+            continue;
+        };
+
+        // Lines are 1-based; a removal names the line above it, 0 at the top
+        if new_count == 0 {
+            signs.insert(new_start.saturating_sub(1), GitSign::Removed);
+            continue;
+        }
+        let sign = if old_count == 0 { GitSign::Added } else { GitSign::Modified };
+        for line in new_start..new_start + new_count {
+            signs.insert(line - 1, sign);
+        }
+    }
+    signs
+}
+
+/// :Gblame - who last changed each line of `content`, the buffer's text of
+/// the file at `path`, in which commit and when
+pub fn blame(path: &Path, content: &[u8]) -> Result<Vec<String>, String> {
+    let name = path.file_name().ok_or("No file name")?;
+    let mut child = git(path)
+        .args(["blame", "--porcelain", "--contents", "-", "--"])
+        .arg(name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Can't run git: {}", e))?;
+
+    // Feed stdin from another thread so a large buffer can't deadlock on full pipes
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let content = content.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&content));
+    let output = child.wait_with_output().map_err(|e| format!("Can't run git: {}", e))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().next().unwrap_or("git blame failed").trim_start_matches("fatal: ").to_string());
+    }
+    Ok(parse_blame(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Annotations from `git blame --porcelain`: `commit date author` for each
+/// line, in line order. Porcelain output describes a commit only the first
+/// time it appears, so what is known of each is kept.
+pub fn parse_blame(porcelain: &str) -> Vec<String> {
+    let mut commits: BTreeMap<&str, (String, Option<i64>, i64)> = BTreeMap::new(); // Author, time, zone offset
+    let mut lines: BTreeMap<usize, &str> = BTreeMap::new();
+    let mut current: Option<&str> = None;
+
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            current = None; // The line's text ends its entry
+            continue;
+        }
+        let mut words = line.split(' ');
+        let first = words.next().unwrap_or_default();
+        if current.is_none() && first.len() == 40 && first.bytes().all(|b| b.is_ascii_hexdigit()) {
+            if let Some(final_line) = words.nth(1).and_then(|number| number.parse::<usize>().ok()) {
+                lines.insert(final_line, first);
+            }
+            commits.entry(first).or_insert((String::new(), None, 0));
+            current = Some(first);
+            continue;
+        }
+        let (Some(commit), Some((key, value))) = (current, line.split_once(' ')) else {
+            continue;
+        };
+        let Some(info) = commits.get_mut(commit) else {
+            continue;
+        };
+        match key {
+            "author" => info.0 = value.to_string(),
+            "author-time" => info.1 = value.parse().ok(),
+            "author-tz" => info.2 = zone_offset(value),
+            _ => {}
+        }
+    }
+
+    lines
+        .values()
+        .map(|commit| {
+            let (author, time, offset) = &commits[commit];
+            let date = time.map_or_else(|| " ".repeat(10), |time| date(time + offset));
+            format!("{} {} {}", &commit[..8], date, author)
+        })
+        .collect()
+}
+
+/// Seconds east of UTC for a `+hhmm` zone
+fn zone_offset(zone: &str) -> i64 {
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits: i64 = zone.trim_start_matches(['+', '-']).parse().unwrap_or(0);
+    sign * (digits / 100 * 3600 + digits % 100 * 60)
+}
+
+/// `YYYY-MM-DD` for seconds since the epoch
+fn date(seconds: i64) -> String {
+    // Howard Hinnant's civil_from_days, over 400-year eras starting in March
+    let days = seconds.div_euclid(86400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diff_and_blame() {
+        let diff = "diff --git a/f b/f\n--- a/f\n+++ b/f\n@@ -2 +2 @@\n-b\n+B\n@@ -4,0 +5,2 @@\n+x\n+y\n@@ -9,2 +10,0 @@\n-p\n-q\n";
+        let signs = parse_diff(diff);
+        assert_eq!(
+            signs.into_iter().collect::<Vec<_>>(),
+            vec![(1, GitSign::Modified), (4, GitSign::Added), (5, GitSign::Added), (9, GitSign::Removed)]
+        );
+        assert_eq!(parse_diff("@@ -1 +0,0 @@\n-a\n").get(&0), Some(&GitSign::Removed));
+
+        let first = "1".repeat(40);
+        let second = "2".repeat(40);
+        let porcelain = format!(
+            "{first} 1 1 2\nauthor Ann\nauthor-time 86399\nauthor-tz +0100\nsummary one\n\tline one\n\
+             {first} 2 2\n\tline two\n\
+             {second} 3 3 1\nauthor Bo\nauthor-time 951782400\nauthor-tz -0000\n\tline three\n"
+        );
+        assert_eq!(
+            parse_blame(&porcelain),
+            vec!["11111111 1970-01-02 Ann", "11111111 1970-01-02 Ann", "22222222 2000-02-29 Bo"]
+        );
+    }
+}
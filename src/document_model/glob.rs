@@ -0,0 +1,182 @@
+//! Wildcards in file names for :e and :args - `*` and `?` within a name,
+//! `[abc]` / `[!abc]` for one character and `**` for any depth of
+//! directories. A backslash makes the next character plain.
+
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// True if `word` has a wildcard that isn't escaped
+pub fn is_pattern(word: &str) -> bool {
+    let mut chars = word.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The files and directories `pattern` names, sorted. Relative patterns
+/// give paths relative to the working directory; names starting with a
+/// dot only match a part of the pattern that starts with one too.
+pub fn expand(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (PathBuf::from("/"), rest),
+        None => (PathBuf::new(), pattern),
+    };
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let mut matches = Vec::new();
+    walk(&root, &parts, &mut matches)?;
+    matches.sort();
+    matches.dedup();
+    if matches.is_empty() {
+        return Err(format!("No match: {}", pattern));
+    }
+    Ok(matches)
+}
+
+fn walk(dir: &Path, parts: &[&str], matches: &mut Vec<PathBuf>) -> Result<(), String> {
+    let Some((&part, rest)) = parts.split_first() else {
+        matches.push(dir.to_path_buf());
+        return Ok(());
+    };
+
+    if part == "**" {
+        // Nothing for the ** to match, then one more directory down
+        walk(dir, rest, matches)?;
+        for entry in entries(dir) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with('.') && entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                walk(&dir.join(name), parts, matches)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if !is_pattern(part) {
+        let path = dir.join(unescape(part));
+        if path.exists() && (rest.is_empty() || path.is_dir()) {
+            walk(&path, rest, matches)?;
+        }
+        return Ok(());
+    }
+
+    let regex = component_regex(part)?;
+    for entry in entries(dir) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') && !part.starts_with('.') {
+            continue;
+        }
+        let path = dir.join(&name);
+        if regex.is_match(&name) && (rest.is_empty() || path.is_dir()) {
+            walk(&path, rest, matches)?;
+        }
+    }
+    Ok(())
+}
+
+/// The entries of `dir` (the working directory for an empty path),
+/// nothing if it can't be read
+fn entries(dir: &Path) -> impl Iterator<Item = fs::DirEntry> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    fs::read_dir(dir).into_iter().flatten().flatten()
+}
+
+/// A regex matching a whole file name against one part of a pattern
+fn component_regex(part: &str) -> Result<Regex, String> {
+    let mut regex = String::from("^");
+    let mut chars = part.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' => regex.push_str(&regex::escape(&chars.next().map(String::from).unwrap_or_default())),
+            '[' => {
+                let rest: String = chars.clone().collect();
+                let Some(end) = rest.find(']').filter(|&end| end > 0) else {
+                    // No closing bracket: a plain [
+                    regex.push_str(r"\[");
+                    continue;
+                };
+                let class = &rest[..end];
+                chars.nth(class.chars().count());
+                regex.push('[');
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated
+                    }
+                    None => class,
+                };
+                for c in class.chars() {
+                    if matches!(c, '\\' | '[' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            _ => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|_| format!("Invalid pattern: {}", part))
+}
+
+/// `part` with its backslash escapes taken out
+fn unescape(part: &str) -> String {
+    let mut text = String::new();
+    let mut chars = part.chars();
+    while let Some(ch) = chars.next() {
+        text.extend(if ch == '\\' { chars.next() } else { Some(ch) });
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_pattern() {
+        assert!(is_pattern("*.md"));
+        assert!(is_pattern("src/**/mod.rs"));
+        assert!(is_pattern("file[12].txt"));
+        assert!(!is_pattern("notes.md"));
+        assert!(!is_pattern(r"what\?.txt"));
+    }
+
+    #[test]
+    fn test_expand_wildcards_and_any_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in ["a.md", "b.md", "c.txt", ".hidden.md", "src/x.rs", "src/deep/y.rs", "src/deep/z.txt"] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let expand_in = |pattern: &str| -> Vec<String> {
+            expand(&format!("{}/{}", root.display(), pattern))
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(root).unwrap().display().to_string())
+                .collect()
+        };
+
+        assert_eq!(expand_in("*.md"), ["a.md", "b.md"]);
+        assert_eq!(expand_in(".*.md"), [".hidden.md"]);
+        assert_eq!(expand_in("?.*"), ["a.md", "b.md", "c.txt"]);
+        assert_eq!(expand_in("[!a].md"), ["b.md"]);
+        assert_eq!(expand_in("src/**/*.rs"), ["src/deep/y.rs", "src/x.rs"]);
+        assert_eq!(expand_in("**/z.txt"), ["src/deep/z.txt"]);
+        assert_eq!(expand_in("*/deep"), ["src/deep"]);
+
+        let missing = format!("{}/*.rs", root.display());
+        assert_eq!(expand(&missing), Err(format!("No match: {}", missing)));
+    }
+}
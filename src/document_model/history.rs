@@ -0,0 +1,153 @@
+//! Command line and search history - the lines run at the : prompt and the
+//! patterns searched for with / and ?, oldest first
+//!
+//! Up and Down at a prompt step through the entries that start with what
+//! was typed before the first Up, as in vim. `q:`, `q/` and `q?` open the
+//! entries in a buffer of their own, where they can be edited and Enter
+//! runs the line under the cursor. The info file keeps them from one
+//! session to the next.
+
+/// How many entries each history keeps
+pub const HISTORY_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    Command, // Lines run at the : prompt
+    Search,  // Patterns searched for with / or ?
+}
+
+impl HistoryKind {
+    /// The history of lines typed at `prompt` (':', '/' or '?')
+    pub fn of(prompt: char) -> Self {
+        if prompt == ':' { Self::Command } else { Self::Search }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    commands: Vec<String>,
+    searches: Vec<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self, kind: HistoryKind) -> &[String] {
+        match kind {
+            HistoryKind::Command => &self.commands,
+            HistoryKind::Search => &self.searches,
+        }
+    }
+
+    /// Add `entry` as the newest, moving it there if it was already kept and
+    /// dropping the oldest past HISTORY_SIZE. Empty entries are left out.
+    pub fn add(&mut self, kind: HistoryKind, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        let entries = match kind {
+            HistoryKind::Command => &mut self.commands,
+            HistoryKind::Search => &mut self.searches,
+        };
+        entries.retain(|kept| kept != entry);
+        entries.push(entry.to_string());
+        if entries.len() > HISTORY_SIZE {
+            entries.drain(..entries.len() - HISTORY_SIZE);
+        }
+    }
+}
+
+/// Where Up and Down have got to in a history while a prompt is open
+#[derive(Debug, Default)]
+pub struct HistoryBrowser {
+    index: Option<usize>, // Entry shown; None while the typed line is
+    typed: String,        // The line as typed before the first Up
+}
+
+impl HistoryBrowser {
+    /// Stop browsing: the next Up starts again from the newest entry
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.typed.clear();
+    }
+
+    /// Up: the next older entry that starts with the typed text, or None
+    /// at the oldest. `line` is what the prompt holds now.
+    pub fn older(&mut self, entries: &[String], line: &str) -> Option<String> {
+        let end = match self.index {
+            Some(index) => index,
+            None => {
+                self.typed = line.to_string();
+                entries.len()
+            }
+        };
+        let index = entries[..end.min(entries.len())].iter().rposition(|entry| entry.starts_with(&self.typed))?;
+        self.index = Some(index);
+        Some(entries[index].clone())
+    }
+
+    /// Down: the next newer entry that starts with the typed text, then the
+    /// typed text itself. None when not browsing.
+    pub fn newer(&mut self, entries: &[String]) -> Option<String> {
+        let start = self.index? + 1;
+        let found = entries.iter().skip(start).position(|entry| entry.starts_with(&self.typed));
+        match found {
+            Some(offset) => {
+                self.index = Some(start + offset);
+                Some(entries[start + offset].clone())
+            }
+            None => {
+                self.index = None;
+                Some(self.typed.clone())
+            }
+        }
+    }
+}
+
+/// What makes a buffer a `q:` / `q/` / `q?` history window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryWindow {
+    pub prompt: char,  // Where Enter runs the line: ':', '/' or '?'
+    pub origin: usize, // The buffer it was opened from, gone back to on Enter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_moves_duplicates_and_caps_size() {
+        let mut history = History::new();
+        history.add(HistoryKind::Command, "w");
+        history.add(HistoryKind::Command, "set nu");
+        history.add(HistoryKind::Command, "w");
+        history.add(HistoryKind::Command, "");
+        history.add(HistoryKind::Search, "foo");
+        assert_eq!(history.entries(HistoryKind::Command), ["set nu", "w"]);
+        assert_eq!(history.entries(HistoryKind::Search), ["foo"]);
+
+        for number in 0..HISTORY_SIZE {
+            history.add(HistoryKind::Command, &number.to_string());
+        }
+        let entries = history.entries(HistoryKind::Command);
+        assert_eq!((entries.len(), entries[0].as_str()), (HISTORY_SIZE, "0"));
+    }
+
+    #[test]
+    fn test_browse_by_prefix() {
+        let entries: Vec<String> = ["set nu", "w", "set list", "e foo"].iter().map(|s| s.to_string()).collect();
+        let mut browser = HistoryBrowser::default();
+        assert_eq!(browser.newer(&entries), None);
+
+        assert_eq!(browser.older(&entries, "se").as_deref(), Some("set list"));
+        assert_eq!(browser.older(&entries, "set list").as_deref(), Some("set nu"));
+        assert_eq!(browser.older(&entries, "set nu"), None);
+        assert_eq!(browser.newer(&entries).as_deref(), Some("set list"));
+        assert_eq!(browser.newer(&entries).as_deref(), Some("se"));
+
+        browser.reset();
+        assert_eq!(browser.older(&entries, "").as_deref(), Some("e foo"));
+    }
+}
@@ -0,0 +1,241 @@
+//! The info file (~/.virusinfo) - state carried from one editing session to
+//! the next, like vim's viminfo: global marks, named registers, the last
+//! search and the last command line, and the command line and search history
+//!
+//! It is plain text with one record per line after a version line. A file
+//! written by a newer version is refused rather than misread, and records
+//! this version doesn't know are skipped. Register text follows its record
+//! line, one `|`-prefixed line per line of text.
+
+use crate::document_model::history::{History, HistoryKind};
+use crate::document_model::marks::{Mark, MarkManager};
+use crate::document_model::registers::{RegisterData, RegisterManager, RegisterType};
+use crate::document_model::search_state::{SearchDirection, SearchState};
+use std::path::PathBuf;
+
+/// Format version written in the header
+const VERSION: u32 = 1;
+const HEADER: &str = "# vi-rus info file - rewritten on exit";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct InfoFile {
+    pub marks: Vec<(char, Mark)>,                  // Global marks A-Z
+    pub registers: Vec<(char, RegisterData)>,      // Named registers a-z
+    pub search: Option<(String, SearchDirection)>, // Last search pattern
+    pub command: Option<String>,                   // Last command line
+    pub history: History,                          // Command lines and searches, oldest first
+}
+
+impl InfoFile {
+    /// ~/.virusinfo, if there is a home directory
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".virusinfo"))
+    }
+
+    /// What the editor holds now that is worth keeping
+    pub fn capture(marks: &MarkManager, registers: &RegisterManager, search: &SearchState, history: &History) -> Self {
+        Self {
+            marks: marks
+                .global_marks()
+                .into_iter()
+                .filter(|(_, mark)| mark.filename.is_some())
+                .map(|(name, mark)| (name, mark.clone()))
+                .collect(),
+            registers: registers
+                .named_registers()
+                .into_iter()
+                .filter(|(_, data)| !data.content.is_empty())
+                .map(|(name, data)| (name, data.clone()))
+                .collect(),
+            search: (!search.pattern.is_empty()).then(|| (search.pattern.clone(), search.direction.clone())),
+            command: Some(registers.last_command().to_string()).filter(|command| !command.is_empty()),
+            history: history.clone(),
+        }
+    }
+
+    /// Put the saved marks, registers and history back. The search is left
+    /// to the caller, which knows the document to find matches in.
+    pub fn restore(&self, marks: &mut MarkManager, registers: &mut RegisterManager, history: &mut History) {
+        for (name, mark) in &self.marks {
+            let _ = marks.set_global_mark(*name, mark.line, mark.column, mark.filename.clone());
+        }
+        for (name, data) in &self.registers {
+            registers.restore_named_register(*name, data.clone());
+        }
+        if let Some(command) = &self.command {
+            registers.set_last_command(command);
+        }
+        for kind in [HistoryKind::Command, HistoryKind::Search] {
+            for entry in self.history.entries(kind) {
+                history.add(kind, entry);
+            }
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![HEADER.to_string(), format!("version {}", VERSION)];
+        for (name, mark) in &self.marks {
+            if let Some(filename) = &mark.filename {
+                lines.push(format!("mark {} {} {} {}", name, mark.line + 1, mark.column, filename.display()));
+            }
+        }
+        for (name, data) in &self.registers {
+            let register_type = match data.register_type {
+                RegisterType::Character => "char",
+                RegisterType::Line => "line",
+                RegisterType::Block => "block",
+            };
+            lines.push(format!("register {} {}", name, register_type));
+            lines.extend(data.content.split('\n').map(|line| format!("|{}", line)));
+        }
+        if let Some((pattern, direction)) = &self.search {
+            let prefix = if *direction == SearchDirection::Forward { '/' } else { '?' };
+            lines.push(format!("search {}{}", prefix, pattern));
+        }
+        if let Some(command) = &self.command {
+            lines.push(format!("command {}", command));
+        }
+        for (kind, prefix) in [(HistoryKind::Command, ':'), (HistoryKind::Search, '/')] {
+            lines.extend(self.history.entries(kind).iter().map(|entry| format!("history {}{}", prefix, entry)));
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// Read an info file. Err if it has no version line or a newer version.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('#')).peekable();
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("version "))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or("no version line")?;
+        if version > VERSION {
+            return Err(format!("written by a newer vi-rus (format {})", version));
+        }
+
+        let mut info = Self::default();
+        while let Some(line) = lines.next() {
+            let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match kind {
+                "mark" => {
+                    if let Some(mark) = Self::parse_mark(rest) {
+                        info.marks.push(mark);
+                    }
+                }
+                "register" => {
+                    let mut content = Vec::new();
+                    while let Some(text) = lines.next_if(|line| line.starts_with('|')) {
+                        content.push(&text[1..]);
+                    }
+                    if let Some((name, register_type)) = Self::parse_register(rest) {
+                        info.registers.push((name, RegisterData::new(content.join("\n"), register_type)));
+                    }
+                }
+                "search" => {
+                    let direction = if rest.starts_with('?') { SearchDirection::Backward } else { SearchDirection::Forward };
+                    let pattern = rest.get(1..).unwrap_or_default();
+                    info.search = (!pattern.is_empty()).then(|| (pattern.to_string(), direction));
+                }
+                "command" => info.command = Some(rest.to_string()).filter(|command| !command.is_empty()),
+                "history" => match rest.split_at_checked(1) {
+                    Some((":", entry)) => info.history.add(HistoryKind::Command, entry),
+                    Some(("/", entry)) => info.history.add(HistoryKind::Search, entry),
+                    _ => {}
+                },
+                _ => {} // A record from a later version
+            }
+        }
+        Ok(info)
+    }
+
+    /// `A 12 4 path`: name, line from 1, column from 0, file
+    fn parse_mark(record: &str) -> Option<(char, Mark)> {
+        let mut fields = record.splitn(4, ' ');
+        let name = fields.next()?.chars().next().filter(|name| name.is_ascii_uppercase())?;
+        let line = fields.next()?.parse::<usize>().ok()?.checked_sub(1)?;
+        let column = fields.next()?.parse().ok()?;
+        let filename = PathBuf::from(fields.next().filter(|path| !path.is_empty())?);
+        Some((name, Mark::new(line, column, Some(filename))))
+    }
+
+    /// `a line`: name and type
+    fn parse_register(record: &str) -> Option<(char, RegisterType)> {
+        let (name, register_type) = record.split_once(' ')?;
+        let name = name.chars().next().filter(|name| name.is_ascii_lowercase())?;
+        let register_type = match register_type {
+            "char" => RegisterType::Character,
+            "line" => RegisterType::Line,
+            "block" => RegisterType::Block,
+            _ => return None,
+        };
+        Some((name, register_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> InfoFile {
+        let mut history = History::new();
+        history.add(HistoryKind::Command, "e notes.txt");
+        history.add(HistoryKind::Command, "s/a/b/g");
+        history.add(HistoryKind::Search, "fo+ bar");
+        InfoFile {
+            marks: vec![('A', Mark::new(11, 4, Some(PathBuf::from("/tmp/some file.txt"))))],
+            registers: vec![
+                ('a', RegisterData::new("one\ntwo".to_string(), RegisterType::Line)),
+                ('b', RegisterData::new("|piped|\n".to_string(), RegisterType::Character)),
+            ],
+            search: Some(("fo+ bar".to_string(), SearchDirection::Backward)),
+            command: Some("s/a/b/g".to_string()),
+            history,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = sample().to_text();
+        assert!(text.contains("mark A 12 4 /tmp/some file.txt\n"));
+        assert!(text.contains("register a line\n|one\n|two\n"));
+        assert!(text.contains("history :e notes.txt\nhistory :s/a/b/g\nhistory /fo+ bar\n"));
+        assert_eq!(InfoFile::parse(&text).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_versions_and_unknown_records() {
+        assert!(InfoFile::parse("").is_err());
+        assert!(InfoFile::parse("version 99\ncommand q\n").is_err());
+
+        let info = InfoFile::parse("# comment\nversion 1\nhistory x\nmark 1 2 3 f\ncommand w\n").unwrap();
+        assert!(info.marks.is_empty());
+        assert_eq!(info.command.as_deref(), Some("w"));
+    }
+
+    #[test]
+    fn test_capture_and_restore() {
+        let mut marks = MarkManager::new();
+        marks.set_global_mark('B', 3, 1, Some(PathBuf::from("b.txt"))).unwrap();
+        let mut registers = RegisterManager::new();
+        registers.store_in_register(Some('c'), "text".to_string(), RegisterType::Character);
+        registers.set_last_command("wq");
+        let mut search = SearchState::new();
+        search.set_pattern("needle".to_string(), SearchDirection::Forward).unwrap();
+
+        let mut history = History::new();
+        history.add(HistoryKind::Search, "needle");
+
+        let info = InfoFile::parse(&InfoFile::capture(&marks, &registers, &search, &history).to_text()).unwrap();
+        let (mut new_marks, mut new_registers, mut new_history) = (MarkManager::new(), RegisterManager::new(), History::new());
+        info.restore(&mut new_marks, &mut new_registers, &mut new_history);
+
+        assert_eq!(new_marks.get_global_mark('B'), marks.get_global_mark('B'));
+        assert_eq!(new_registers.get_register_content(Some('c')).unwrap().content, "text");
+        assert_eq!(new_registers.get_register_content(None).unwrap().content, "");
+        assert_eq!(new_registers.last_command(), "wq");
+        assert_eq!(new_history, history);
+        assert_eq!(info.search, Some(("needle".to_string(), SearchDirection::Forward)));
+    }
+}
@@ -0,0 +1,325 @@
+//! Loading big files in the background
+//!
+//! Reading a file of hundreds of megabytes takes long enough to notice, so
+//! a big file opens with just its start read, enough to fill the screen. A
+//! thread reads the rest in chunks, and the event loop adds each one to the
+//! end of the buffer as it arrives. Only plain UTF-8 files load this way;
+//! files in other encodings are read whole, as the encoding has to be
+//! worked out from all of the bytes.
+//!
+//! With the `mmap` feature, a file with "\n" line breaks isn't copied into
+//! memory at all: it is mapped, and the thread only checks it is UTF-8, a
+//! chunk at a time, before the buffer takes in more of it. A carriage
+//! return or a byte that isn't UTF-8 ends that, and the rest of the file is
+//! read into memory as it would be without the feature.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+#[cfg(feature = "mmap")]
+use std::path::PathBuf;
+#[cfg(feature = "mmap")]
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// Files at least this big are read in the background
+pub const BACKGROUND_LOAD_SIZE: u64 = 16 * 1024 * 1024;
+/// How much the thread reads at a time, and how much is read up front
+pub const LOAD_CHUNK_SIZE: usize = 1024 * 1024;
+/// The most text one poll adds to the buffer, so the screen keeps up
+const POLL_LIMIT: usize = 8 * 1024 * 1024;
+
+/// What the thread sends
+enum Message {
+    Text { text: String, read: u64 }, // More text with "\n" line breaks, and the bytes read so far
+    #[cfg(feature = "mmap")]
+    Mapped { len: usize }, // The first `len` bytes of the mapped file are text
+    Finished(LoadEnd),
+}
+
+/// The start of a file, read at once
+pub enum LoadStart {
+    Text(Vec<u8>), // The bytes, ending at a character boundary
+    #[cfg(feature = "mmap")]
+    Mapped(Arc<MappedFile>, usize), // The file, and how much of it is checked to be text
+}
+
+/// What the thread has sent since the last look
+#[derive(Debug, Default)]
+pub struct Received {
+    #[cfg(feature = "mmap")]
+    pub mapped: Option<usize>, // The mapped file is text up to here
+    pub text: String,          // Text read into memory, after the mapped part
+    pub end: Option<LoadEnd>,  // How the load ended, if it has
+}
+
+/// A file mapped into memory, for a buffer to use its bytes as text
+/// without copying them
+///
+/// The file must not change while it is mapped: the buffer's text would
+/// change with it, and a file cut short makes reading past its new end
+/// crash the editor. Writing the buffer over its file copies the text
+/// into memory first (see `PieceTable::unmap`); other programs writing
+/// the file are the risk every memory-mapped file takes, and why the
+/// `mmap` feature is off by default.
+#[cfg(feature = "mmap")]
+pub struct MappedFile {
+    map: memmap2::Mmap,
+    path: PathBuf, // Canonical
+}
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    /// The first `len` bytes, which the loader has checked are UTF-8
+    pub fn text(&self, len: usize) -> &str {
+        // SAFETY: the loader only ever hands out lengths up to which it
+        // checked the bytes with from_utf8, and the file doesn't change
+        unsafe { std::str::from_utf8_unchecked(&self.map[..len]) }
+    }
+
+    /// True when this is the file at `path`
+    pub fn is(&self, path: &Path) -> bool {
+        std::fs::canonicalize(path).is_ok_and(|path| path == self.path)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl std::fmt::Debug for MappedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MappedFile({})", self.path.display())
+    }
+}
+
+/// How a background load ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadEnd {
+    Complete { replaced: bool, end_of_line: bool }, // Undecodable bytes were replaced; the file ends with a line break
+    Failed(String),
+}
+
+/// The thread reading the rest of a file, and what it has sent so far
+#[derive(Debug)]
+pub struct FileLoader {
+    receiver: Receiver<Message>,
+    size: u64, // The file's size when it was opened
+    read: u64, // Bytes of it taken from the thread
+}
+
+impl FileLoader {
+    /// Read the start of `path`, `chunk_size` bytes of it, and start a
+    /// thread reading the rest. Returns the start with the loader for what
+    /// follows it; None when the start isn't plain UTF-8, and the file has
+    /// to be read whole.
+    pub fn start(path: &Path, chunk_size: usize) -> io::Result<Option<(LoadStart, FileLoader)>> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        #[cfg(feature = "mmap")]
+        if let Some(started) = Self::start_mapped(&file, path, size, chunk_size) {
+            return Ok(Some(started));
+        }
+
+        let mut pending = Vec::with_capacity(chunk_size);
+        (&mut file).take(chunk_size as u64).read_to_end(&mut pending)?;
+        let plain_utf8 = !pending.starts_with(&[0xEF, 0xBB, 0xBF])
+            && !pending.contains(&0)
+            && std::str::from_utf8(&pending).map_or_else(|e| e.error_len().is_none(), |_| true);
+        if !plain_utf8 {
+            return Ok(None);
+        }
+
+        let last_byte = pending.last().copied();
+        let start = pending.drain(..pending.len() - held_back(&pending)).collect();
+        let read = (chunk_size as u64).min(size);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || read_rest(file, pending, read, last_byte, chunk_size, sender));
+        Ok(Some((LoadStart::Text(start), FileLoader { receiver, size, read })))
+    }
+
+    /// Map `file` and check its start is text the buffer can use as it is.
+    /// None when it isn't, or the file can't be mapped.
+    #[cfg(feature = "mmap")]
+    fn start_mapped(file: &File, path: &Path, size: u64, chunk_size: usize) -> Option<(LoadStart, FileLoader)> {
+        // SAFETY: see MappedFile
+        let map = unsafe { memmap2::Mmap::map(file) }.ok()?;
+        let head = &map[..chunk_size.min(map.len())];
+        let len = head.len() - held_back(head);
+        if head.starts_with(&[0xEF, 0xBB, 0xBF]) || head.contains(&0) || head.contains(&b'\r') {
+            return None;
+        }
+        std::str::from_utf8(&head[..len]).ok()?;
+
+        let mapped = Arc::new(MappedFile { map, path: std::fs::canonicalize(path).ok()? });
+        let (sender, receiver) = mpsc::channel();
+        let thread_file = Arc::clone(&mapped);
+        thread::spawn(move || check_mapped(&thread_file, len, chunk_size, sender));
+        Some((LoadStart::Mapped(mapped, len), FileLoader { receiver, size, read: len as u64 }))
+    }
+
+    /// How much of the file has been read, as a percentage
+    pub fn progress(&self) -> u8 {
+        if self.size == 0 {
+            return 100;
+        }
+        (self.read.min(self.size) * 100 / self.size) as u8
+    }
+
+    /// The size of the file being read
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// What the thread has read since the last call. With `wait`, waits
+    /// for the whole of the file; otherwise takes what is ready, up to a
+    /// limit.
+    pub fn receive(&mut self, wait: bool) -> Received {
+        let mut received = Received::default();
+        let mut taken = 0;
+        while wait || taken < POLL_LIMIT {
+            let message = if wait {
+                self.receiver.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                self.receiver.try_recv()
+            };
+            match message {
+                Ok(Message::Text { text, read }) => {
+                    taken += text.len();
+                    received.text.push_str(&text);
+                    self.read = read;
+                }
+                #[cfg(feature = "mmap")]
+                Ok(Message::Mapped { len }) => {
+                    taken += len - self.read as usize;
+                    received.mapped = Some(len);
+                    self.read = len as u64;
+                }
+                Ok(Message::Finished(end)) => {
+                    received.end = Some(end);
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    received.end = Some(LoadEnd::Failed("the reading thread stopped".to_string()));
+                    break;
+                }
+            }
+        }
+        received
+    }
+}
+
+/// Bytes at the end of `bytes` to keep for the next chunk: the start of a
+/// character cut off by the chunk's end, or a carriage return that may be
+/// the first half of "\r\n"
+fn held_back(bytes: &[u8]) -> usize {
+    let from = bytes.len().saturating_sub(3);
+    for (at, &byte) in bytes.iter().enumerate().skip(from).rev() {
+        if byte & 0xC0 != 0x80 {
+            let length = match byte {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => 1,
+            };
+            return if bytes.len() - at < length { bytes.len() - at } else { usize::from(byte == b'\r') };
+        }
+    }
+    0
+}
+
+/// Text of `bytes` with "\n" line breaks, and whether any bytes weren't UTF-8
+fn decode(bytes: &[u8]) -> (String, bool) {
+    let text = String::from_utf8_lossy(bytes);
+    let replaced = std::str::from_utf8(bytes).is_err();
+    (text.replace("\r\n", "\n").replace('\r', "\n"), replaced)
+}
+
+/// The thread for a mapped file: check the file is text after the first
+/// `len` bytes of it, a chunk at a time, until the end or until the buffer
+/// is closed. From a chunk that isn't, the rest is read as text.
+#[cfg(feature = "mmap")]
+fn check_mapped(file: &MappedFile, mut len: usize, chunk_size: usize, sender: Sender<Message>) {
+    let bytes = &file.map[..];
+    while len < bytes.len() {
+        let end = (len + chunk_size.max(4)).min(bytes.len());
+        let chunk = &bytes[len..end];
+        let complete = if end == bytes.len() { chunk } else { &chunk[..chunk.len() - held_back(chunk)] };
+        if complete.contains(&b'\r') || std::str::from_utf8(complete).is_err() {
+            let last_byte = len.checked_sub(1).map(|at| bytes[at]);
+            return read_rest(&bytes[len..], Vec::new(), len as u64, last_byte, chunk_size, sender);
+        }
+        len += complete.len();
+        if sender.send(Message::Mapped { len }).is_err() {
+            return; // The buffer was closed
+        }
+    }
+    let end_of_line = bytes.last().is_none_or(|&byte| byte == b'\n');
+    let _ = sender.send(Message::Finished(LoadEnd::Complete { replaced: false, end_of_line }));
+}
+
+/// The thread: read the file after `read` bytes of it, sending its text
+/// a chunk at a time, until the end or until the buffer is closed
+fn read_rest(
+    mut file: impl Read,
+    mut pending: Vec<u8>,
+    mut read: u64,
+    mut last_byte: Option<u8>,
+    chunk_size: usize,
+    sender: Sender<Message>,
+) {
+    let mut chunk = vec![0; chunk_size];
+    let mut replaced = false;
+    loop {
+        let count = match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                let _ = sender.send(Message::Finished(LoadEnd::Failed(e.to_string())));
+                return;
+            }
+        };
+        read += count as u64;
+        last_byte = Some(chunk[count - 1]);
+        pending.extend_from_slice(&chunk[..count]);
+        let complete: Vec<u8> = pending.drain(..pending.len() - held_back(&pending)).collect();
+        let (text, lossy) = decode(&complete);
+        replaced |= lossy;
+        if sender.send(Message::Text { text, read }).is_err() {
+            return; // The buffer was closed
+        }
+    }
+    let (text, lossy) = decode(&pending);
+    let end_of_line = last_byte.is_none_or(|byte| byte == b'\n' || byte == b'\r');
+    let _ = sender.send(Message::Text { text, read });
+    let _ = sender.send(Message::Finished(LoadEnd::Complete { replaced: replaced || lossy, end_of_line }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_split_at_character_and_line_break_boundaries() {
+        assert_eq!(held_back(b"abc"), 0);
+        assert_eq!(held_back(b"ab\r"), 1);
+        assert_eq!(held_back("ab€".as_bytes()), 0);
+        assert_eq!(held_back(&"ab€".as_bytes()[..4]), 2); // Two bytes of three
+        assert_eq!(held_back(&"ab😀".as_bytes()[..5]), 3);
+
+        let text = "one\r\ntwo €\r\nthree 😀\r\n".repeat(50);
+        let path = std::env::temp_dir().join(format!("virus_loader_{}.txt", std::process::id()));
+        std::fs::write(&path, &text).unwrap();
+        #[cfg_attr(not(feature = "mmap"), allow(irrefutable_let_patterns))]
+        let (LoadStart::Text(start), mut loader) = FileLoader::start(&path, 7).unwrap().unwrap() else {
+            panic!("a file with \\r\\n line breaks was mapped");
+        };
+        let received = loader.receive(true);
+        std::fs::remove_file(&path).unwrap();
+
+        let start = String::from_utf8(start).unwrap().replace("\r\n", "\n");
+        assert_eq!(start + &received.text, text.replace("\r\n", "\n"));
+        assert_eq!(received.end, Some(LoadEnd::Complete { replaced: false, end_of_line: true }));
+        assert_eq!(loader.progress(), 100);
+    }
+}
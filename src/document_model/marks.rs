@@ -35,10 +35,15 @@ pub struct MarkManager {
 
     // Special marks
     last_jump: Option<Mark>,   // '' mark - last jump position
-    last_change: Option<Mark>, // '. mark - last change position
     last_insert: Option<Mark>, // '^ mark - last insert position
 }
 
+impl Default for MarkManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MarkManager {
     pub fn new() -> Self {
         Self {
@@ -46,7 +51,6 @@ impl MarkManager {
             jump_list: Vec::new(),
             jump_position: 0,
             last_jump: None,
-            last_change: None,
             last_insert: None,
         }
     }
@@ -74,13 +78,19 @@ impl MarkManager {
     pub fn get_global_mark(&self, mark_char: char) -> Option<&Mark> {
         match mark_char {
             'A'..='Z' => self.global_marks.get(&mark_char),
-            '\'' => self.last_jump.as_ref(),  // '' - last jump
-            '.' => self.last_change.as_ref(), // '. - last change
+            '\'' | '`' => self.last_jump.as_ref(), // '' or `` - last jump
             '^' => self.last_insert.as_ref(), // '^ - last insert
             _ => None,
         }
     }
 
+    /// Global marks (A-Z) in name order
+    pub fn global_marks(&self) -> Vec<(char, &Mark)> {
+        let mut marks: Vec<(char, &Mark)> = self.global_marks.iter().map(|(ch, mark)| (*ch, mark)).collect();
+        marks.sort_by_key(|(ch, _)| *ch);
+        marks
+    }
+
     /// Delete a global mark (A-Z)
     pub fn delete_global_mark(&mut self, mark_char: char) -> bool {
         match mark_char {
@@ -103,10 +113,10 @@ impl MarkManager {
         }
 
         // Don't add if it's the same position as the last entry
-        if let Some(last) = self.jump_list.last() {
-            if last.line == line && last.column == column {
-                return;
-            }
+        if let Some(last) = self.jump_list.last()
+            && last.line == line && last.column == column
+        {
+            return;
         }
 
         self.jump_list.push(entry);
@@ -147,11 +157,6 @@ impl MarkManager {
         self.last_jump = Some(Mark::new(line, column, None));
     }
 
-    /// Update the last change position ('. mark)
-    pub fn set_last_change(&mut self, line: usize, column: usize) {
-        self.last_change = Some(Mark::new(line, column, None));
-    }
-
     /// Update the last insert position ('^ mark)
     pub fn set_last_insert(&mut self, line: usize, column: usize) {
         self.last_insert = Some(Mark::new(line, column, None));
@@ -161,7 +166,6 @@ impl MarkManager {
     pub fn clear_all_marks(&mut self) {
         self.clear_global_marks();
         self.last_jump = None;
-        self.last_change = None;
         self.last_insert = None;
     }
 
@@ -196,15 +200,15 @@ impl MarkManager {
 
         // Clear special marks (they're buffer-specific)
         self.last_jump = None;
-        self.last_change = None;
         self.last_insert = None;
     }
 
     /// List all marks (for :marks command)
-    /// Takes local marks from the current document as parameter
+    /// Takes local marks and the last change ('.) from the current document
     pub fn list_marks(
         &self,
         local_marks: &std::collections::HashMap<char, (usize, usize)>,
+        last_change: Option<(usize, usize)>,
     ) -> Vec<(char, usize, usize, Option<&std::path::PathBuf>)> {
         let mut marks = Vec::new();
 
@@ -222,8 +226,8 @@ impl MarkManager {
         if let Some(mark) = &self.last_jump {
             marks.push(('\'', mark.line, mark.column, mark.filename.as_ref()));
         }
-        if let Some(mark) = &self.last_change {
-            marks.push(('.', mark.line, mark.column, mark.filename.as_ref()));
+        if let Some((line, column)) = last_change {
+            marks.push(('.', line, column, None));
         }
         if let Some(mark) = &self.last_insert {
             marks.push(('^', mark.line, mark.column, mark.filename.as_ref()));
@@ -334,12 +338,6 @@ mod tests {
         assert_eq!(mark.line, 15);
         assert_eq!(mark.column, 8);
 
-        // Test last change mark
-        manager.set_last_change(25, 12);
-        let mark = manager.get_global_mark('.').unwrap();
-        assert_eq!(mark.line, 25);
-        assert_eq!(mark.column, 12);
-
         // Test last insert mark
         manager.set_last_insert(35, 16);
         let mark = manager.get_global_mark('^').unwrap();
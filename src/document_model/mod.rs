@@ -1,7 +1,7 @@
-/// Document model subsystem - Core data structures and text operations
-/// 
-/// This module contains all document-related data structures and operations,
-/// including text storage, editing operations, search state, marks, and undo/redo.
+//! Document model subsystem - Core data structures and text operations
+//!
+//! This module contains all document-related data structures and operations,
+//! including text storage, editing operations, search state, marks, and undo/redo.
 
 pub mod document;
 pub mod text_buffer;
@@ -11,10 +11,46 @@ pub mod marks;
 pub mod movement;
 pub mod registers;
 pub mod undo;
+pub mod file_lock;
+pub mod reflow;
+pub mod retab;
+pub mod sort;
+pub mod shell;
+pub mod comment;
+pub mod surround;
+pub mod stats;
+pub mod clipboard;
+pub mod directory;
+pub mod analysis;
+pub mod info_file;
+pub mod history;
+pub mod selection;
+pub mod quickfix;
+pub mod arg_list;
+pub mod glob;
+pub mod swap_file;
+pub mod encoding;
+pub mod digraph;
+pub mod file_position;
+pub mod diff;
+pub mod git;
+pub mod tags;
+pub mod diagnostics;
+pub mod loader;
 
 // Re-export main types for convenience
 pub use document::{Document, LineEnding};
-pub use text_buffer::Position;
+pub use text_buffer::LineChange;
 pub use search_state::{SearchState, SearchDirection, SearchError};
 pub use marks::MarkManager;
-pub use registers::{RegisterManager, RegisterType};
\ No newline at end of file
+pub use registers::{RegisterManager, RegisterType};
+pub use file_lock::{LockChoice, LockConflict};
+pub use quickfix::QuickfixList;
+pub use arg_list::ArgList;
+pub use history::{History, HistoryKind};
+pub use encoding::Encoding;
+pub use file_position::FilePosition;
+pub use diff::{Diff, DiffKind, DiffRow};
+pub use git::{GitSign, GitSigns};
+pub use tags::{Tag, TagFile, TagStack};
+pub use diagnostics::{Diagnostic, Severity};
\ No newline at end of file
@@ -1,6 +1,17 @@
 use super::document::Document;
 
 impl Document {
+    /// Word and character-search motions scan lines as char vectors, so they
+    /// run with the cursor column as a char index, converted back to a byte
+    /// column when they finish
+    fn with_char_column(&mut self, motion: impl FnOnce(&mut Self)) {
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        self.cursor_column = line.get(..self.cursor_column()).map_or(0, |before| before.chars().count());
+        motion(self);
+        let line = self.get_line(self.cursor_line()).unwrap_or_default();
+        self.cursor_column = line.char_indices().nth(self.cursor_column()).map_or(line.len(), |(i, _)| i);
+    }
+
     // Word movement methods - moved from document.rs to keep it focused on data
     fn move_word_forward_impl(&mut self, use_word_boundaries: bool) {
         loop {
@@ -8,14 +19,11 @@ impl Document {
             let line_count = self.line_count();
             
             // If at end of line, move to next line
-            if self.cursor_column() >= line.len() {
-                if self.cursor_line() < line_count - 1 {
-                    self.cursor_line += 1;
-                    self.reset_cursor_column();
-                } else {
-                    break;
+            if self.cursor_column() >= line.chars().count() {
+                if self.cursor_line() < line_count - 1 && !self.enter_next_line_for_word() {
+                    continue;
                 }
-                continue;
+                break;
             }
 
             let chars: Vec<char> = line.chars().collect();
@@ -71,8 +79,9 @@ impl Document {
             // If we didn't move or reached end of line, continue to next line
             if self.cursor_column() == start_col || self.cursor_column() >= chars.len() {
                 if self.cursor_line() < line_count - 1 {
-                    self.cursor_line += 1;
-                    self.reset_cursor_column();
+                    if self.enter_next_line_for_word() {
+                        break;
+                    }
                 } else {
                     // At end of document, clamp to last character
                     self.cursor_column = if chars.is_empty() { 0 } else { chars.len() - 1 };
@@ -84,8 +93,25 @@ impl Document {
         }
     }
 
+    /// Step onto the next line for w: true if the cursor lands there, on
+    /// the first word or on an empty line; a blank line is passed over
+    fn enter_next_line_for_word(&mut self) -> bool {
+        self.cursor_line += 1;
+        let chars: Vec<char> = self.get_line(self.cursor_line()).unwrap_or_default().chars().collect();
+        match chars.iter().position(|c| !c.is_whitespace()) {
+            Some(column) => {
+                self.cursor_column = column;
+                true
+            }
+            None => {
+                self.cursor_column = chars.len();
+                chars.is_empty()
+            }
+        }
+    }
+
     pub fn move_word_forward(&mut self) {
-        self.move_word_forward_impl(true);
+        self.with_char_column(|doc| doc.move_word_forward_impl(true));
     }
 
     fn move_word_backward_impl(&mut self, use_word_boundaries: bool) {
@@ -99,7 +125,7 @@ impl Document {
                     self.cursor_column = chars.len();
                     // Find last non-whitespace character
                     while self.cursor_column() > 0 && self.cursor_column() <= chars.len() {
-                        if chars.get(self.cursor_column() - 1).map_or(false, |c| !c.is_whitespace()) {
+                        if chars.get(self.cursor_column() - 1).is_some_and(|c| !c.is_whitespace()) {
                             break;
                         }
                         self.cursor_column -= 1;
@@ -179,7 +205,7 @@ impl Document {
     }
 
     pub fn move_word_backward(&mut self) {
-        self.move_word_backward_impl(true);
+        self.with_char_column(|doc| doc.move_word_backward_impl(true));
     }
 
     fn move_to_word_end(&mut self, use_word_boundaries: bool) {
@@ -319,7 +345,7 @@ impl Document {
     }
 
     pub fn move_word_end(&mut self) {
-        self.move_to_word_end(true);
+        self.with_char_column(|doc| doc.move_to_word_end(true));
     }
 
     fn get_word_type(&self, c: char) -> u8 {
@@ -336,15 +362,15 @@ impl Document {
 
     // Big word movement (space-separated)
     pub fn move_big_word_forward(&mut self) {
-        self.move_word_forward_impl(false);
+        self.with_char_column(|doc| doc.move_word_forward_impl(false));
     }
 
     pub fn move_big_word_backward(&mut self) {
-        self.move_word_backward_impl(false);
+        self.with_char_column(|doc| doc.move_word_backward_impl(false));
     }
 
     pub fn move_big_word_end(&mut self) {
-        self.move_to_word_end(false);
+        self.with_char_column(|doc| doc.move_to_word_end(false));
     }
 
     // Line movement
@@ -359,7 +385,7 @@ impl Document {
     pub fn move_first_non_whitespace(&mut self) {
         let line = self.get_line(self.cursor_line()).unwrap_or_default();
         self.reset_cursor_column();
-        for (i, c) in line.chars().enumerate() {
+        for (i, c) in line.char_indices() {
             if !c.is_whitespace() {
                 self.cursor_column = i;
                 break;
@@ -411,19 +437,6 @@ impl Document {
         self.clamp_cursor_column_to_current_line();
     }
 
-    pub fn move_half_page_up(&mut self) {
-        let half_page = 10; // Could be made configurable
-        self.cursor_line = self.cursor_line().saturating_sub(half_page);
-        self.clamp_cursor_column_to_current_line();
-    }
-
-    pub fn move_half_page_down(&mut self) {
-        let half_page = 10; // Could be made configurable
-        let line_count = self.line_count();
-        self.cursor_line = std::cmp::min(self.cursor_line() + half_page, line_count.saturating_sub(1));
-        self.clamp_cursor_column_to_current_line();
-    }
-
     pub fn move_to_line(&mut self, line: usize) {
         let line_count = self.line_count();
         self.cursor_line = std::cmp::min(line.saturating_sub(1), line_count.saturating_sub(1));
@@ -432,6 +445,10 @@ impl Document {
 
     // Character search
     pub fn find_char(&mut self, target: char, forward: bool, before: bool) {
+        self.with_char_column(|doc| doc.find_char_impl(target, forward, before));
+    }
+
+    fn find_char_impl(&mut self, target: char, forward: bool, before: bool) {
         let line = self.get_line(self.cursor_line()).unwrap_or_default();
         let chars: Vec<char> = line.chars().collect();
 
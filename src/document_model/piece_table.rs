@@ -1,4 +1,8 @@
 use std::fmt;
+#[cfg(feature = "mmap")]
+use super::loader::MappedFile;
+#[cfg(feature = "mmap")]
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BufferType {
@@ -23,61 +27,104 @@ impl Piece {
     }
 }
 
+/// Offsets where each line starts, kept up to date on every edit so line
+/// lookups never have to rescan the text
+#[derive(Debug, Clone)]
 pub struct LineIndex {
     line_starts: Vec<usize>,  // Offset positions where each line starts
-    valid: bool,              // Whether the index is current
 }
 
 impl LineIndex {
     fn new() -> Self {
         Self {
             line_starts: vec![0],  // First line always starts at 0
-            valid: false,
         }
     }
-    
-    fn invalidate(&mut self) {
-        self.valid = false;
+
+    fn from_text(text: &str) -> Self {
+        let mut index = Self::new();
+        index.inserted(0, text);
+        index
     }
-    
-    fn rebuild(&mut self, text: &str) {
-        self.line_starts.clear();
-        self.line_starts.push(0);
-        
-        let mut byte_pos = 0;
-        for ch in text.chars() {
-            if ch == '\n' {
-                byte_pos += ch.len_utf8();
-                self.line_starts.push(byte_pos);
-            } else {
-                byte_pos += ch.len_utf8();
-            }
+
+    /// Record `text` being inserted at `position`
+    fn inserted(&mut self, position: usize, text: &str) {
+        // A line starting exactly at `position` keeps its start; later ones move
+        let split = self.line_starts.partition_point(|&start| start <= position);
+        for start in &mut self.line_starts[split..] {
+            *start += text.len();
+        }
+        let new_starts = text.match_indices('\n').map(|(i, _)| position + i + 1);
+        self.line_starts.splice(split..split, new_starts);
+    }
+
+    /// Record the bytes in `start..end` being deleted
+    fn deleted(&mut self, start: usize, end: usize) {
+        // Lines starting inside (start, end] lost the newline before them
+        let first = self.line_starts.partition_point(|&line_start| line_start <= start);
+        let last = self.line_starts.partition_point(|&line_start| line_start <= end);
+        self.line_starts.drain(first..last);
+        for line_start in &mut self.line_starts[first..] {
+            *line_start -= end - start;
         }
-        self.valid = true;
     }
-    
+
     fn line_count(&self) -> usize {
         self.line_starts.len()
     }
-    
+
     fn line_start(&self, line: usize) -> Option<usize> {
         self.line_starts.get(line).copied()
     }
+
+    /// The line containing `offset` (binary search)
+    #[cfg(test)]
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset).saturating_sub(1)
+    }
 }
 
+/// The text a table was made from: a string, or with the `mmap` feature,
+/// a file mapped into memory, of which the first `len` bytes have been
+/// found to be UTF-8 text with "\n" line breaks
+#[derive(Clone)]
+enum Original {
+    Owned(String),
+    #[cfg(feature = "mmap")]
+    Mapped { file: Arc<MappedFile>, len: usize },
+}
+
+impl Original {
+    fn as_str(&self) -> &str {
+        match self {
+            Original::Owned(text) => text,
+            #[cfg(feature = "mmap")]
+            Original::Mapped { file, len } => file.text(*len),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct PieceTable {
-    original: String,
+    original: Original,
     add: String,
     pieces: Vec<Piece>,
+    piece_offsets: Vec<usize>, // Document offset where each piece starts
     total_length: usize,
     line_index: LineIndex,
 }
 
+impl Default for PieceTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PieceTable {
-    /// Safe substring that respects UTF-8 character boundaries
-    fn safe_substring(text: &str, start_byte: usize, end_byte: usize) -> String {
+    /// Slice that respects UTF-8 character boundaries
+    fn safe_slice(text: &str, start_byte: usize, end_byte: usize) -> &str {
         if start_byte >= text.len() {
-            return String::new();
+            return "";
         }
         
         let end_byte = end_byte.min(text.len());
@@ -99,36 +146,79 @@ impl PieceTable {
         };
         
         if safe_start >= safe_end {
-            return String::new();
+            return "";
         }
         
-        text[safe_start..safe_end].to_string()
+        &text[safe_start..safe_end]
+    }
+
+    /// The text of bytes `from..to` within `piece`, borrowed from its buffer
+    fn piece_text(&self, piece: &Piece, from: usize, to: usize) -> &str {
+        let buffer = match piece.buffer {
+            BufferType::Original => self.original.as_str(),
+            BufferType::Add => &self.add,
+        };
+        if piece.start > buffer.len() {
+            eprintln!("Warning: Invalid piece start in {:?} buffer - start: {}, buffer len: {}",
+                    piece.buffer, piece.start, buffer.len());
+            return "";
+        }
+        Self::safe_slice(buffer, piece.start + from, piece.start + to.min(piece.length))
+    }
+
+    /// Index of the piece holding `offset` (binary search)
+    fn piece_at(&self, offset: usize) -> usize {
+        self.piece_offsets.partition_point(|&start| start <= offset).saturating_sub(1)
+    }
+
+    fn update_piece_offsets(&mut self) {
+        self.piece_offsets.clear();
+        let mut offset = 0;
+        for piece in &self.pieces {
+            self.piece_offsets.push(offset);
+            offset += piece.length;
+        }
     }
 
     pub fn new() -> Self {
         Self {
-            original: String::new(),
+            original: Original::Owned(String::new()),
             add: String::new(),
             pieces: Vec::new(),
+            piece_offsets: Vec::new(),
             total_length: 0,
             line_index: LineIndex::new(),
         }
     }
 
     pub fn from_string(text: String) -> Self {
+        Self::from_original(Original::Owned(text))
+    }
+
+    /// A table of the first `len` bytes of a mapped file, which must be
+    /// UTF-8 text with "\n" line breaks
+    #[cfg(feature = "mmap")]
+    pub fn from_mapped(file: Arc<MappedFile>, len: usize) -> Self {
+        Self::from_original(Original::Mapped { file, len })
+    }
+
+    fn from_original(original: Original) -> Self {
+        let text = original.as_str();
         let length = text.len();
+        let line_index = LineIndex::from_text(text);
         let mut table = Self {
-            original: text,
+            original,
             add: String::new(),
             pieces: if length > 0 { 
                 vec![Piece::new(BufferType::Original, 0, length)] 
             } else { 
                 Vec::new() 
             },
+            piece_offsets: Vec::new(),
             total_length: length,
-            line_index: LineIndex::new(),
+            line_index,
         };
-        table.rebuild_line_index();
+        table.update_piece_offsets();
         table
     }
 
@@ -141,15 +231,17 @@ impl PieceTable {
         self.add.push_str(text);
         
         let insert_piece = Piece::new(BufferType::Add, add_start, text.len());
+        let position = position.min(self.total_length);
         
-        if position >= self.total_length {
+        if position == self.total_length {
             // Insert at end
             self.pieces.push(insert_piece);
         } else {
             // Find the piece and position to split
-            let mut current_offset = 0;
+            let first = self.piece_at(position);
+            let mut current_offset = self.piece_offsets[first];
             
-            for i in 0..self.pieces.len() {
+            for i in first..self.pieces.len() {
                 let piece = &self.pieces[i];
                 
                 if current_offset + piece.length > position {
@@ -183,7 +275,80 @@ impl PieceTable {
         }
         
         self.total_length += text.len();
-        self.line_index.invalidate();
+        self.line_index.inserted(position, text);
+        self.update_piece_offsets();
+    }
+
+    /// Add `text` to the end of the document as more of the file the
+    /// table was made from, as a file loading in the background arrives.
+    /// Consecutive appends grow one piece. A mapped file can't grow, so
+    /// the text goes in the add buffer then.
+    pub fn append_original(&mut self, text: &str) {
+        let (buffer, start) = match &mut self.original {
+            Original::Owned(original) => {
+                original.push_str(text);
+                (BufferType::Original, original.len() - text.len())
+            }
+            #[cfg(feature = "mmap")]
+            Original::Mapped { .. } => {
+                self.add.push_str(text);
+                (BufferType::Add, self.add.len() - text.len())
+            }
+        };
+        self.append_piece(buffer, start, text);
+    }
+
+    /// Take in more of the mapped file: its first `len` bytes, which must
+    /// be UTF-8 text with "\n" line breaks
+    #[cfg(feature = "mmap")]
+    pub fn extend_mapped(&mut self, len: usize) {
+        let Original::Mapped { file, len: mapped } = &mut self.original else {
+            return;
+        };
+        let (file, start) = (Arc::clone(file), std::mem::replace(mapped, len));
+        self.append_piece(BufferType::Original, start, &file.text(len)[start..]);
+    }
+
+    /// Put `text`, which is at `start` in `buffer`, at the end of the document
+    fn append_piece(&mut self, buffer: BufferType, start: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.pieces.last_mut() {
+            Some(piece) if piece.buffer == buffer && piece.start + piece.length == start => {
+                piece.length += text.len();
+            }
+            _ => {
+                self.pieces.push(Piece::new(buffer, start, text.len()));
+                self.piece_offsets.push(self.total_length);
+            }
+        }
+        self.line_index.inserted(self.total_length, text);
+        self.total_length += text.len();
+    }
+
+    /// Make room for `additional` more bytes of the original file
+    pub fn reserve_original(&mut self, additional: usize) {
+        match &mut self.original {
+            Original::Owned(original) => original.reserve(additional),
+            #[cfg(feature = "mmap")]
+            Original::Mapped { .. } => {}
+        }
+    }
+
+    /// True when the text comes from `path` mapped into memory
+    #[cfg(feature = "mmap")]
+    pub fn is_mapped_from(&self, path: &std::path::Path) -> bool {
+        matches!(&self.original, Original::Mapped { file, .. } if file.is(path))
+    }
+
+    /// Copy the text of a mapped file into memory, so the file can be
+    /// written over
+    #[cfg(feature = "mmap")]
+    pub fn unmap(&mut self) {
+        if let Original::Mapped { .. } = self.original {
+            self.original = Original::Owned(self.original.as_str().to_string());
+        }
     }
 
     pub fn delete(&mut self, start: usize, length: usize) {
@@ -193,105 +358,41 @@ impl PieceTable {
 
         let end = (start + length).min(self.total_length);
         let mut current_offset = 0;
-        let mut pieces_to_remove = Vec::new();
-        let mut pieces_to_add = Vec::new();
+        let mut pieces = Vec::with_capacity(self.pieces.len() + 1);
 
-        for (i, piece) in self.pieces.iter().enumerate() {
+        for piece in &self.pieces {
             let piece_start = current_offset;
             let piece_end = current_offset + piece.length;
+            current_offset = piece_end;
 
             if piece_end <= start || piece_start >= end {
                 // Piece is outside deletion range
-                current_offset = piece_end;
+                pieces.push(piece.clone());
                 continue;
             }
 
-            if piece_start >= start && piece_end <= end {
-                // Piece is completely within deletion range
-                pieces_to_remove.push(i);
-            } else if piece_start < start && piece_end > end {
-                // Piece spans the entire deletion range - split into two
-                let left_length = start - piece_start;
-                let right_start = piece.start + (end - piece_start);
-                let right_length = piece_end - end;
-
-                let left_piece = Piece::new(piece.buffer, piece.start, left_length);
-                let right_piece = Piece::new(piece.buffer, right_start, right_length);
-
-                pieces_to_add.push((i, vec![left_piece, right_piece]));
-                pieces_to_remove.push(i);
-            } else if piece_start < start {
-                // Piece starts before deletion, ends within
-                let new_length = start - piece_start;
-                let new_piece = Piece::new(piece.buffer, piece.start, new_length);
-                pieces_to_add.push((i, vec![new_piece]));
-                pieces_to_remove.push(i);
-            } else {
-                // Piece starts within deletion, ends after
-                let new_start = piece.start + (end - piece_start);
-                let new_length = piece_end - end;
-                let new_piece = Piece::new(piece.buffer, new_start, new_length);
-                pieces_to_add.push((i, vec![new_piece]));
-                pieces_to_remove.push(i);
+            // Keep whatever sticks out on either side of the deletion; a piece
+            // spanning the whole range leaves both a left and a right part
+            if piece_start < start {
+                pieces.push(Piece::new(piece.buffer, piece.start, start - piece_start));
             }
-
-            current_offset = piece_end;
-        }
-
-        // Apply changes in reverse order to maintain indices
-        // First, collect the insert operations and sort them
-        let mut sorted_adds: Vec<_> = pieces_to_add.into_iter().collect();
-        sorted_adds.sort_by_key(|(i, _)| *i);
-        
-        // Remove pieces in reverse order 
-        for &i in pieces_to_remove.iter().rev() {
-            self.pieces.remove(i);
-        }
-
-        // Adjust insert indices based on how many pieces were removed before each position
-        for (original_i, new_pieces) in sorted_adds.into_iter() {
-            // Count how many pieces were removed before this position
-            let removed_before = pieces_to_remove.iter().filter(|&&removed_i| removed_i < original_i).count();
-            let adjusted_i = original_i - removed_before;
-            
-            // Insert the new pieces at the adjusted position
-            for (j, piece) in new_pieces.into_iter().enumerate() {
-                let insert_pos = (adjusted_i + j).min(self.pieces.len());
-                self.pieces.insert(insert_pos, piece);
+            if piece_end > end {
+                let right_start = piece.start + (end - piece_start);
+                pieces.push(Piece::new(piece.buffer, right_start, piece_end - end));
             }
         }
 
+        self.pieces = pieces;
         self.total_length = self.total_length.saturating_sub(end - start);
-        self.line_index.invalidate();
+        self.line_index.deleted(start, end);
+        self.update_piece_offsets();
     }
 
     pub fn get_text(&self) -> String {
         let mut result = String::with_capacity(self.total_length);
-        
         for piece in &self.pieces {
-            let text = match piece.buffer {
-                BufferType::Original => {
-                    if piece.start <= self.original.len() {
-                        Self::safe_substring(&self.original, piece.start, piece.start + piece.length)
-                    } else {
-                        eprintln!("Warning: Invalid piece start in original buffer - start: {}, buffer len: {}", 
-                                piece.start, self.original.len());
-                        String::new()
-                    }
-                },
-                BufferType::Add => {
-                    if piece.start <= self.add.len() {
-                        Self::safe_substring(&self.add, piece.start, piece.start + piece.length)
-                    } else {
-                        eprintln!("Warning: Invalid piece start in add buffer - start: {}, buffer len: {}", 
-                                piece.start, self.add.len());
-                        String::new()
-                    }
-                },
-            };
-            result.push_str(&text);
+            result.push_str(self.piece_text(piece, 0, piece.length));
         }
-        
         result
     }
 
@@ -301,146 +402,41 @@ impl PieceTable {
         }
 
         let end = end.min(self.total_length);
-        let mut result = String::new();
-        let mut current_offset = 0;
+        let mut result = String::with_capacity(end - start);
+        let first = self.piece_at(start);
 
-        for piece in &self.pieces {
-            let piece_end = current_offset + piece.length;
-
-            if current_offset >= end {
+        for (piece, &piece_offset) in self.pieces[first..].iter().zip(&self.piece_offsets[first..]) {
+            if piece_offset >= end {
                 break;
             }
-            if piece_end <= start {
-                current_offset = piece_end;
-                continue;
-            }
-
-            let piece_start_in_range = start.saturating_sub(current_offset);
-            let piece_end_in_range = (end - current_offset).min(piece.length);
-
-            let text = match piece.buffer {
-                BufferType::Original => {
-                    if piece.start <= self.original.len() {
-                        Self::safe_substring(&self.original, piece.start, piece.start + piece.length)
-                    } else {
-                        eprintln!("Warning: Invalid piece start in original buffer (get_range) - start: {}, buffer len: {}", 
-                                piece.start, self.original.len());
-                        String::new()
-                    }
-                },
-                BufferType::Add => {
-                    if piece.start <= self.add.len() {
-                        Self::safe_substring(&self.add, piece.start, piece.start + piece.length)
-                    } else {
-                        eprintln!("Warning: Invalid piece start in add buffer (get_range) - start: {}, buffer len: {}", 
-                                piece.start, self.add.len());
-                        String::new()
-                    }
-                },
-            };
-
-            // Ensure we slice at UTF-8 character boundaries
-            let safe_text = Self::safe_substring(&text, piece_start_in_range, piece_end_in_range);
-            result.push_str(&safe_text);
-            current_offset = piece_end;
+            let from = start.saturating_sub(piece_offset);
+            let to = end - piece_offset;
+            result.push_str(self.piece_text(piece, from, to));
         }
 
         result
     }
 
-    pub fn char_at(&self, position: usize) -> Option<char> {
-        if position >= self.total_length {
-            return None;
-        }
-
-        let mut current_offset = 0;
-        
-        for piece in &self.pieces {
-            if current_offset + piece.length > position {
-                let char_pos = position - current_offset;
-                let text = match piece.buffer {
-                    BufferType::Original => {
-                        if piece.start <= self.original.len() {
-                            Self::safe_substring(&self.original, piece.start, piece.start + piece.length)
-                        } else {
-                            eprintln!("Warning: Invalid piece start in original buffer (char_at) - start: {}, buffer len: {}", 
-                                    piece.start, self.original.len());
-                            String::new()
-                        }
-                    },
-                    BufferType::Add => {
-                        if piece.start <= self.add.len() {
-                            Self::safe_substring(&self.add, piece.start, piece.start + piece.length)
-                        } else {
-                            eprintln!("Warning: Invalid piece start in add buffer (char_at) - start: {}, buffer len: {}", 
-                                    piece.start, self.add.len());
-                            String::new()
-                        }
-                    },
-                };
-                return text.chars().nth(char_pos);
-            }
-            current_offset += piece.length;
-        }
-        
-        None
-    }
-
-    #[cfg(test)]
     pub fn is_empty(&self) -> bool {
         self.total_length == 0
     }
 
-    #[cfg(test)]
     pub fn len(&self) -> usize {
         self.total_length
     }
 
     #[cfg(test)]
-    pub fn offset_to_position(&mut self, offset: usize) -> (usize, usize) {
-        if !self.line_index.valid {
-            self.rebuild_line_index();
-        }
-
+    pub fn offset_to_position(&self, offset: usize) -> (usize, usize) {
         let offset = offset.min(self.total_length);
-        
-        // Binary search to find the line
-        let mut left = 0;
-        let mut right = self.line_index.line_starts.len();
-        
-        while left < right {
-            let mid = (left + right) / 2;
-            if self.line_index.line_starts[mid] <= offset {
-                left = mid + 1;
-            } else {
-                right = mid;
-            }
-        }
-        
-        let line = left.saturating_sub(1);
-        let line_start = self.line_index.line_starts[line];
-        let column = offset - line_start;
-        
-        (line, column)
-    }
-
-    fn rebuild_line_index(&mut self) {
-        let text = self.get_text();
-        self.line_index.rebuild(&text);
+        let line = self.line_index.line_of(offset);
+        (line, offset - self.line_index.line_starts[line])
     }
 
-    pub fn line_count(&mut self) -> usize {
-        if !self.line_index.valid {
-            self.rebuild_line_index();
-        }
+    pub fn line_count(&self) -> usize {
         self.line_index.line_count()
     }
 
-    pub fn get_line_fast(&mut self, line_number: usize) -> Option<String> {
-        if !self.line_index.valid {
-            self.rebuild_line_index();
-        }
-
+    pub fn get_line_fast(&self, line_number: usize) -> Option<String> {
         let line_start = self.line_index.line_start(line_number)?;
         let line_end = self.line_index.line_start(line_number + 1)
             .unwrap_or(self.total_length);
@@ -457,35 +453,24 @@ impl PieceTable {
         }
     }
 
-
-    pub fn position_to_offset(&mut self, line: usize, column: usize) -> usize {
-        if !self.line_index.valid {
-            self.rebuild_line_index();
+    /// Length in bytes of a line, not counting its newline
+    pub fn line_length(&self, line_number: usize) -> usize {
+        let Some(line_start) = self.line_index.line_start(line_number) else {
+            return 0;
+        };
+        match self.line_index.line_start(line_number + 1) {
+            Some(next_start) => next_start - 1 - line_start,
+            None => self.total_length - line_start,
         }
+    }
 
+    pub fn position_to_offset(&self, line: usize, column: usize) -> usize {
         if let Some(line_start) = self.line_index.line_start(line) {
             (line_start + column).min(self.total_length)
         } else {
             self.total_length
         }
     }
-
-}
-
-impl Clone for PieceTable {
-    fn clone(&self) -> Self {
-        let mut cloned = Self {
-            original: self.original.clone(),
-            add: self.add.clone(),
-            pieces: self.pieces.clone(),
-            total_length: self.total_length,
-            line_index: LineIndex::new(),
-        };
-        if self.line_index.valid {
-            cloned.rebuild_line_index();
-        }
-        cloned
-    }
 }
 
 impl fmt::Display for PieceTable {
@@ -581,7 +566,7 @@ mod tests {
 
     #[test]
     fn test_get_lines() {
-        let mut table = PieceTable::from_string("Line 1\nLine 2\nLine 3".to_string());
+        let table = PieceTable::from_string("Line 1\nLine 2\nLine 3".to_string());
         let line_count = table.line_count();
         let lines: Vec<String> = (0..line_count)
             .map(|i| table.get_line_fast(i).unwrap_or_default())
@@ -591,4 +576,75 @@ mod tests {
         assert_eq!(lines[1], "Line 2");
         assert_eq!(lines[2], "Line 3");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_line_index_follows_edits() {
+        let mut table = PieceTable::from_string("one\ntwo\nthree\n".to_string());
+        table.insert(4, "new\nlines\n");
+        table.insert(0, "\n");
+        table.delete(2, 6); // Joins lines across a newline
+        table.insert(table.len(), "tail\nend");
+        table.delete(table.len() - 4, 4);
+        table.insert(3, "é\n");
+
+        let text = table.get_text();
+        assert_eq!(table.line_index.line_starts, LineIndex::from_text(&text).line_starts);
+        let lines: Vec<String> = (0..table.line_count()).map(|i| table.get_line_fast(i).unwrap()).collect();
+        assert_eq!(lines, text.split('\n').collect::<Vec<_>>());
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(table.line_length(i), line.len());
+        }
+        assert_eq!(table.offset_to_position(text.len()), (lines.len() - 1, lines.last().unwrap().len()));
+    }
+
+    #[test]
+    fn test_append_original_grows_the_last_piece() {
+        let mut table = PieceTable::from_string("one\ntw".to_string());
+        table.append_original("o\nthr");
+        table.append_original("ee\n");
+        assert_eq!(table.pieces.len(), 1);
+        table.insert(table.len(), "fo");
+        table.append_original("ur\nfive");
+        assert_eq!(table.pieces.len(), 3); // Text typed at the end comes before what follows
+        table.insert(0, ">");
+
+        let text = table.get_text();
+        assert_eq!(text, ">one\ntwo\nthree\nfour\nfive");
+        assert_eq!(table.line_index.line_starts, LineIndex::from_text(&text).line_starts);
+        assert_eq!(table.get_line_fast(3).unwrap(), "four");
+        assert_eq!(table.position_to_offset(4, 2), text.len() - 2);
+    }
+
+    /// Line lookups on a large, heavily edited buffer. Run with
+    /// `cargo test --release -- --ignored bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_line_lookup_is_logarithmic() {
+        use std::time::Instant;
+
+        fn nanos_per_lookup(lines: usize) -> f64 {
+            let text: String = (0..lines).map(|i| format!("line number {i}\n")).collect();
+            let mut table = PieceTable::from_string(text);
+            // Scatter edits through the file so lookups cross many pieces
+            for i in 0..1000 {
+                let offset = table.position_to_offset(i * lines / 1000, 5);
+                table.insert(offset, "x");
+            }
+
+            let lookups = 200_000;
+            let start = Instant::now();
+            let mut total = 0;
+            for i in 0..lookups {
+                total += table.get_line_fast((i * 7919) % lines).unwrap().len();
+            }
+            assert!(total > 0);
+            start.elapsed().as_nanos() as f64 / lookups as f64
+        }
+
+        let small = nanos_per_lookup(10_000);
+        let large = nanos_per_lookup(500_000);
+        println!("10k lines: {small:.0}ns/lookup, 500k lines: {large:.0}ns/lookup");
+        // 50x the lines; a linear lookup would be ~50x slower
+        assert!(large < small * 5.0, "lookups grew from {small:.0}ns to {large:.0}ns");
+    }
+}
@@ -0,0 +1,125 @@
+//! The quickfix list - places found by :grep, stepped through with :cnext
+//! and :cprevious or picked from the :copen list
+
+use regex::Regex;
+use std::path::PathBuf;
+
+/// One place in the list: a file, 0-based line, byte column and the line's text
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickfixEntry {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct QuickfixList {
+    entries: Vec<QuickfixEntry>,
+    current: usize, // Entry last jumped to
+}
+
+impl QuickfixList {
+    pub fn new(entries: Vec<QuickfixEntry>) -> Self {
+        Self { entries, current: 0 }
+    }
+
+    /// Read `grep -n -H` output, one `file:line:text` per match. `pattern`
+    /// places the column on the match; without it (or if it doesn't match
+    /// the text) the column is 0. Lines of another shape are skipped.
+    pub fn from_grep_output(output: &str, pattern: Option<&Regex>) -> Self {
+        let entries = output
+            .lines()
+            .filter_map(|record| {
+                let mut fields = record.splitn(3, ':');
+                let path = fields.next().filter(|path| !path.is_empty())?;
+                let line = fields.next()?.parse::<usize>().ok()?.checked_sub(1)?;
+                let text = fields.next()?.to_string();
+                let column = pattern.and_then(|regex| regex.find(&text)).map_or(0, |found| found.start());
+                Some(QuickfixEntry { path: PathBuf::from(path), line, column, text })
+            })
+            .collect();
+        Self::new(entries)
+    }
+
+    pub fn entries(&self) -> &[QuickfixEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Make entry `index` current (:cc, the :copen list)
+    pub fn select(&mut self, index: usize) -> Option<&QuickfixEntry> {
+        let entry = self.entries.get(index)?;
+        self.current = index;
+        Some(entry)
+    }
+
+    /// Move `count` entries forward (:cnext) or back (:cprevious), stopping
+    /// at the last or first. Err if the list is empty or already there.
+    pub fn step(&mut self, count: usize, forward: bool) -> Result<usize, String> {
+        if self.entries.is_empty() {
+            return Err("Quickfix list is empty".to_string());
+        }
+        let last = self.entries.len() - 1;
+        if (forward && self.current == last) || (!forward && self.current == 0) {
+            return Err("No more items".to_string());
+        }
+        self.current = if forward { (self.current + count).min(last) } else { self.current.saturating_sub(count) };
+        Ok(self.current)
+    }
+
+    /// `file:line:column: text` for each entry, as the :copen list shows them
+    pub fn rows(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                format!("{}:{}:{}: {}", entry.path.display(), entry.line + 1, entry.column + 1, entry.text.trim())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_grep_output() {
+        let output = "src/a.rs:3:    let x = foo();\nBinary file b matches\nc.txt:10:foo: bar\n";
+        let regex = Regex::new("foo").unwrap();
+        let list = QuickfixList::from_grep_output(output, Some(&regex));
+
+        assert_eq!(list.entries().len(), 2);
+        assert_eq!(list.entries()[0].path, PathBuf::from("src/a.rs"));
+        assert_eq!((list.entries()[0].line, list.entries()[0].column), (2, 12));
+        assert_eq!(list.entries()[1].text, "foo: bar");
+        assert_eq!(list.rows()[0], "src/a.rs:3:13: let x = foo();");
+
+        let list = QuickfixList::from_grep_output(output, None);
+        assert_eq!(list.entries()[0].column, 0);
+    }
+
+    #[test]
+    fn test_step_and_select() {
+        let mut list = QuickfixList::default();
+        assert_eq!(list.step(1, true), Err("Quickfix list is empty".to_string()));
+
+        list = QuickfixList::from_grep_output("a:1:x\na:2:x\na:3:x\nb:1:x", None);
+        assert_eq!(list.step(1, false), Err("No more items".to_string()));
+        assert_eq!(list.step(2, true), Ok(2));
+        assert_eq!(list.step(5, true), Ok(3));
+        assert_eq!(list.step(1, true), Err("No more items".to_string()));
+        assert_eq!(list.step(1, false), Ok(2));
+
+        assert_eq!(list.select(0).map(|entry| entry.line), Some(0));
+        assert!(list.select(4).is_none());
+        assert_eq!(list.current_index(), 0);
+    }
+}
@@ -0,0 +1,202 @@
+//! Hard-wrap engine shared by :Wrap, wrapped paste and gq
+//!
+//! Lines longer than the text width are broken at whitespace. Continuation
+//! lines keep the original line's indentation; shorter lines are left alone.
+//! gq goes further and refills paragraphs: the lines of a paragraph are
+//! joined and broken again, each starting with the paragraph's indent and
+//! comment leader (`//`, `#`, `>`).
+//!
+//! Widths are display columns, with tabs laid out by the tabstop. Breaking a
+//! line drops the blanks at the break; the spacing between words on the
+//! same line is kept as it was.
+
+use unicode_width::UnicodeWidthChar;
+
+/// Width used when 'textwidth' is off (0), matching vim's gq fallback
+pub const DEFAULT_WRAP_WIDTH: usize = 79;
+
+/// The display column `text` ends at when it starts at `column`, a tab
+/// going on to the next multiple of `tab_stop`
+pub fn advance(column: usize, text: &str, tab_stop: usize) -> usize {
+    let tab_stop = tab_stop.max(1);
+    text.chars().fold(column, |column, ch| match ch {
+        '\t' => (column / tab_stop + 1) * tab_stop,
+        _ => column + ch.width().unwrap_or(0),
+    })
+}
+
+/// The words of `text`, each with the blanks before it
+fn words(text: &str) -> Vec<(&str, &str)> {
+    let mut words = Vec::new();
+    let mut rest = text;
+    loop {
+        let gap = rest.len() - rest.trim_start().len();
+        let word_end = rest[gap..].find(char::is_whitespace).map_or(rest.len(), |end| gap + end);
+        if word_end == gap {
+            return words;
+        }
+        words.push((&rest[..gap], &rest[gap..word_end]));
+        rest = &rest[word_end..];
+    }
+}
+
+/// Break `line` into lines of at most `width` display columns.
+/// Words wider than the available width are kept whole on their own line.
+pub fn wrap_line(line: &str, width: usize, tab_stop: usize) -> Vec<String> {
+    if width == 0 || advance(0, line, tab_stop) <= width {
+        return vec![line.to_string()];
+    }
+
+    let indent = &line[..line.len() - line.trim_start().len()];
+    fill(indent, &words(&line[indent.len()..]), width, tab_stop)
+}
+
+/// Wrap every line of `lines`, returning the resulting lines
+pub fn wrap_lines<S: AsRef<str>>(lines: &[S], width: usize, tab_stop: usize) -> Vec<String> {
+    lines
+        .iter()
+        .flat_map(|line| wrap_line(line.as_ref(), width, tab_stop))
+        .collect()
+}
+
+/// Comment leaders gq carries over to the lines it makes
+const COMMENT_LEADERS: [&str; 3] = ["//", "#", ">"];
+
+/// The indent and comment leader `line` starts with, along with the space
+/// after the leader
+pub fn line_prefix(line: &str) -> &str {
+    let body = line.trim_start();
+    let Some(leader) = COMMENT_LEADERS.iter().find(|leader| body.starts_with(*leader)) else {
+        return &line[..line.len() - body.len()];
+    };
+    let after = &body[leader.len()..];
+    &line[..line.len() - after.trim_start().len()]
+}
+
+/// Refill `lines` to `width` columns for gq. Lines sharing an indent and
+/// comment leader form a paragraph, ended by a blank line (or a leader
+/// with nothing after it), and the words of each are laid out again,
+/// those that were on one line keeping the spacing between them.
+pub fn format_lines<S: AsRef<str>>(lines: &[S], width: usize, tab_stop: usize) -> Vec<String> {
+    let mut formatted = Vec::new();
+    let mut paragraph: Option<(&str, Vec<(&str, &str)>)> = None; // Prefix and words
+
+    for line in lines.iter().map(AsRef::as_ref) {
+        let prefix = line_prefix(line);
+        let body = &line[prefix.len()..];
+        let same = paragraph.as_ref().is_some_and(|(current, _)| current.trim_end() == prefix.trim_end());
+        if !same || body.trim().is_empty() {
+            formatted.extend(paragraph.take().map(|(prefix, words)| fill(prefix, &words, width, tab_stop)).unwrap_or_default());
+        }
+        if body.trim().is_empty() {
+            formatted.push(line.trim_end().to_string());
+            continue;
+        }
+        // Lines are joined with a single space
+        let line_words = words(body).into_iter().enumerate().map(|(i, (gap, word))| (if i == 0 { " " } else { gap }, word));
+        paragraph.get_or_insert((prefix, Vec::new())).1.extend(line_words);
+    }
+    formatted.extend(paragraph.map(|(prefix, words)| fill(prefix, &words, width, tab_stop)).unwrap_or_default());
+    formatted
+}
+
+/// Lay `words` out in lines of at most `width` columns, each after
+/// `prefix`. A word keeps the blanks before it unless it starts a line.
+fn fill(prefix: &str, words: &[(&str, &str)], width: usize, tab_stop: usize) -> Vec<String> {
+    let prefix_width = advance(0, prefix, tab_stop);
+    let mut lines = Vec::new();
+    let mut current = String::from(prefix);
+    let mut column = prefix_width;
+    let mut has_word = false;
+
+    for &(gap, word) in words {
+        if has_word {
+            let end = advance(advance(column, gap, tab_stop), word, tab_stop);
+            if end <= width {
+                current.push_str(gap);
+                current.push_str(word);
+                column = end;
+                continue;
+            }
+            lines.push(current);
+            current = String::from(prefix);
+            column = prefix_width;
+        }
+        current.push_str(word);
+        column = advance(column, word, tab_stop);
+        has_word = true;
+    }
+    lines.push(current);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_line_untouched() {
+        assert_eq!(wrap_line("hello  world", 20, 8), vec!["hello  world"]);
+        assert_eq!(wrap_line("", 20, 8), vec![""]);
+    }
+
+    #[test]
+    fn test_wraps_at_whitespace() {
+        assert_eq!(
+            wrap_line("the quick brown fox jumps over", 10, 8),
+            vec!["the quick", "brown fox", "jumps over"]
+        );
+    }
+
+    #[test]
+    fn test_keeps_indent_on_continuation_lines() {
+        assert_eq!(
+            wrap_line("    alpha beta gamma delta", 15, 8),
+            vec!["    alpha beta", "    gamma delta"]
+        );
+    }
+
+    #[test]
+    fn test_long_word_stays_whole() {
+        assert_eq!(
+            wrap_line("a supercalifragilistic b", 8, 8),
+            vec!["a", "supercalifragilistic", "b"]
+        );
+    }
+
+    #[test]
+    fn test_wrap_keeps_spacing_and_counts_tabs() {
+        assert_eq!(wrap_line("a  b   c dd", 8, 8), vec!["a  b   c", "dd"]);
+        // Two tabs take 16 columns, leaving no room for a second word at 20
+        assert_eq!(wrap_line("\t\taaa bbb ccc", 20, 8), vec!["\t\taaa", "\t\tbbb", "\t\tccc"]);
+        assert_eq!(wrap_line("\t\taaa bbb ccc", 20, 2), vec!["\t\taaa bbb ccc"]);
+        assert_eq!(advance(3, "\tx", 4), 5);
+    }
+
+    #[test]
+    fn test_wrap_lines_uses_display_width() {
+        // Each CJK character is two columns wide
+        let lines = ["中文 中文 中文", "ok"];
+        assert_eq!(wrap_lines(&lines, 9, 8), vec!["中文 中文", "中文", "ok"]);
+    }
+
+    #[test]
+    fn test_format_refills_paragraphs() {
+        let lines = ["one two", "three four five", "", "six seven eight nine"];
+        assert_eq!(format_lines(&lines, 12, 8), vec!["one two", "three four", "five", "", "six seven", "eight nine"]);
+        // Spacing within a line is kept, lines are joined with one space
+        assert_eq!(format_lines(&["a  b", "c   d"], 12, 8), vec!["a  b c   d"]);
+        assert_eq!(format_lines(&["\teee fff"], 12, 8), vec!["\teee", "\tfff"]);
+    }
+
+    #[test]
+    fn test_format_keeps_indent_and_comment_leaders() {
+        assert_eq!(line_prefix("    // text"), "    // ");
+        assert_eq!(line_prefix("  plain"), "  ");
+        let lines = ["  // alpha beta", "  // gamma", "  //", "# delta epsilon", "> quoted words here"];
+        assert_eq!(
+            format_lines(&lines, 16, 8),
+            vec!["  // alpha beta", "  // gamma", "  //", "# delta epsilon", "> quoted words", "> here"]
+        );
+    }
+}
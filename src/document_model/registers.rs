@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use crate::document_model::clipboard::{ClipboardBackend, SystemClipboard};
+use std::collections::{HashMap, VecDeque};
+
+/// How many recent yanks and deletes Ctrl-P can cycle back through
+const HISTORY_SIZE: usize = 10;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RegisterType {
@@ -7,7 +11,7 @@ pub enum RegisterType {
     Block,     // Rectangular block (visual block mode)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RegisterData {
     pub content: String,
     pub register_type: RegisterType,
@@ -20,6 +24,19 @@ impl RegisterData {
             register_type,
         }
     }
+
+    /// This register with `more` appended ("Ayy). Text joins straight on
+    /// when both are characterwise; otherwise the new text starts a line of
+    /// its own and the register becomes linewise, as in vim.
+    fn appended(self, more: RegisterData) -> Self {
+        match (&self.register_type, &more.register_type) {
+            (RegisterType::Character, RegisterType::Character) => {
+                RegisterData::new(self.content + &more.content, RegisterType::Character)
+            }
+            (RegisterType::Block, _) => RegisterData::new(format!("{}\n{}", self.content, more.content), RegisterType::Block),
+            _ => RegisterData::new(format!("{}\n{}", self.content, more.content), RegisterType::Line),
+        }
+    }
 }
 
 pub struct RegisterManager {
@@ -32,9 +49,28 @@ pub struct RegisterManager {
     // Numbered registers (0-9) for delete history
     numbered_registers: [RegisterData; 10],
 
-    // System clipboard register (*)
-    #[allow(dead_code)]
-    clipboard_register: Option<RegisterData>,
+    // Last delete within a line (-)
+    small_delete_register: RegisterData,
+
+    // Last executed command line (:), read-only
+    last_command: RegisterData,
+
+    // Current and alternate file names (% and #), read-only
+    current_file: RegisterData,
+    alternate_file: RegisterData,
+
+    // Recent yanks and deletes, newest first, for cycling a paste with Ctrl-P
+    history: VecDeque<RegisterData>,
+
+    // System clipboard registers (+ and *), kept in step with the OS clipboard
+    clipboard_register: RegisterData,
+    clipboard: Box<dyn ClipboardBackend>,
+}
+
+impl Default for RegisterManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegisterManager {
@@ -45,7 +81,47 @@ impl RegisterManager {
             numbered_registers: std::array::from_fn(|_| {
                 RegisterData::new(String::new(), RegisterType::Character)
             }),
-            clipboard_register: None,
+            small_delete_register: RegisterData::new(String::new(), RegisterType::Character),
+            last_command: RegisterData::new(String::new(), RegisterType::Character),
+            current_file: RegisterData::new(String::new(), RegisterType::Character),
+            alternate_file: RegisterData::new(String::new(), RegisterType::Character),
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            clipboard_register: RegisterData::new(String::new(), RegisterType::Character),
+            clipboard: Box::new(SystemClipboard::default()),
+        }
+    }
+
+    /// Remember an executed command line for the : register
+    pub fn set_last_command(&mut self, command: &str) {
+        self.last_command = RegisterData::new(command.to_string(), RegisterType::Character);
+    }
+
+    /// The last executed command line (the : register)
+    pub fn last_command(&self) -> &str {
+        &self.last_command.content
+    }
+
+    /// Keep the % and # registers naming the current and alternate files
+    pub fn set_file_names(&mut self, current: Option<&std::path::Path>, alternate: Option<&std::path::Path>) {
+        let name = |path: Option<&std::path::Path>| {
+            RegisterData::new(path.map(|path| path.display().to_string()).unwrap_or_default(), RegisterType::Character)
+        };
+        self.current_file = name(current);
+        self.alternate_file = name(alternate);
+    }
+
+    /// Named registers (a-z) that hold something, in name order
+    pub fn named_registers(&self) -> Vec<(char, &RegisterData)> {
+        let mut registers: Vec<(char, &RegisterData)> = self.named_registers.iter().map(|(name, data)| (*name, data)).collect();
+        registers.sort_by_key(|(name, _)| *name);
+        registers
+    }
+
+    /// Put saved text back in a named register, leaving the unnamed register
+    /// and the yank history alone
+    pub fn restore_named_register(&mut self, name: char, data: RegisterData) {
+        if name.is_ascii_lowercase() {
+            self.named_registers.insert(name, data);
         }
     }
 
@@ -58,6 +134,8 @@ impl RegisterManager {
     ) {
         let data = RegisterData::new(content.clone(), register_type.clone());
 
+        // What the unnamed register ends up holding: all of an appended register
+        let mut stored = data.clone();
         match register_name {
             Some(name) => {
                 match name {
@@ -67,18 +145,22 @@ impl RegisterManager {
                     }
                     'A'..='Z' => {
                         // Uppercase: append to register content
-                        if let Some(lowercase) = name.to_lowercase().next() {
-                            if let Some(existing) = self.named_registers.get_mut(&lowercase) {
-                                existing.content.push_str(&content);
-                            } else {
-                                self.named_registers.insert(lowercase, data);
-                            }
-                        }
+                        let lowercase = name.to_ascii_lowercase();
+                        stored = match self.named_registers.remove(&lowercase) {
+                            Some(existing) => existing.appended(data),
+                            None => data,
+                        };
+                        self.named_registers.insert(lowercase, stored.clone());
                     }
                     '"' => {
                         // Explicit unnamed register
                         self.unnamed_register = data;
                     }
+                    '+' | '*' => {
+                        // System clipboard; the copy kept here covers a missing clipboard
+                        self.clipboard.set_text(&Self::clipboard_text(&data));
+                        self.clipboard_register = data;
+                    }
                     _ => {
                         // Invalid register, use unnamed
                         self.unnamed_register = data;
@@ -93,12 +175,103 @@ impl RegisterManager {
 
         // Always update unnamed register with the content (vi behavior)
         if register_name != Some('"') {
-            self.unnamed_register = RegisterData::new(content, register_type);
+            self.unnamed_register = stored;
         }
+        self.remember(RegisterData::new(content, register_type));
+    }
+
+    /// Add a yank or delete to the front of the history
+    fn remember(&mut self, data: RegisterData) {
+        if data.content.is_empty()
+            || self.history.front().is_some_and(|newest| newest.content == data.content)
+        {
+            return;
+        }
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_back();
+        }
+        self.history.push_front(data);
+    }
+
+    /// A recent yank or delete: 0 is the newest
+    pub fn history_entry(&self, index: usize) -> Option<&RegisterData> {
+        self.history.get(index)
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Where text sits in the history, if it is there
+    pub fn history_position(&self, content: &str) -> Option<usize> {
+        self.history.iter().position(|data| data.content == content)
+    }
+
+    /// Store yanked text. Without a register name it also fills "0, which
+    /// keeps the last yank while deletes pass through the unnamed register.
+    pub fn store_yank(&mut self, register_name: Option<char>, content: String, register_type: RegisterType) {
+        if register_name.is_none() {
+            self.numbered_registers[0] = RegisterData::new(content.clone(), register_type.clone());
+        }
+        self.store_in_register(register_name, content, register_type);
+    }
+
+    /// Store deleted text. Without a register name, a delete within one line
+    /// fills the small delete register (-); a bigger one goes to "1, moving
+    /// the older deletes along to "2 through "9.
+    pub fn store_delete(&mut self, register_name: Option<char>, content: String, register_type: RegisterType) {
+        if register_name.is_none() {
+            let data = RegisterData::new(content.clone(), register_type.clone());
+            if register_type == RegisterType::Character && !content.contains('\n') {
+                self.small_delete_register = data;
+            } else {
+                self.numbered_registers[1..].rotate_right(1);
+                self.numbered_registers[1] = data;
+            }
+        }
+        self.store_in_register(register_name, content, register_type);
+    }
+
+    /// Text as other programs should see it: linewise text ends in a newline
+    fn clipboard_text(data: &RegisterData) -> String {
+        match data.register_type {
+            RegisterType::Line => format!("{}\n", data.content),
+            RegisterType::Character | RegisterType::Block => data.content.clone(),
+        }
+    }
+
+    /// Pick up text copied by other programs since the clipboard was last written
+    fn sync_clipboard(&mut self) {
+        let Some(text) = self.clipboard.get_text() else {
+            return;
+        };
+        if text == Self::clipboard_text(&self.clipboard_register) {
+            return; // Unchanged - keep the register type it was yanked with
+        }
+        self.clipboard_register = match text.strip_suffix('\n') {
+            Some(lines) => RegisterData::new(lines.to_string(), RegisterType::Line),
+            None => RegisterData::new(text, RegisterType::Character),
+        };
+    }
+
+    /// Every register that holds something, in the order :registers shows
+    /// them: unnamed, numbered, named, small delete, last command, file
+    /// names, clipboard
+    pub fn list(&mut self) -> Vec<(char, RegisterData)> {
+        let names = std::iter::once('"')
+            .chain('0'..='9')
+            .chain('a'..='z')
+            .chain(['-', ':', '%', '#', '+']);
+        names
+            .filter_map(|name| {
+                let data = self.get_register_content(Some(name))?;
+                (!data.content.is_empty()).then(|| (name, data.clone()))
+            })
+            .collect()
     }
 
     /// Get content from a register
-    pub fn get_register_content(&self, register_name: Option<char>) -> Option<&RegisterData> {
+    pub fn get_register_content(&mut self, register_name: Option<char>) -> Option<&RegisterData> {
         match register_name {
             Some(name) => {
                 match name {
@@ -108,6 +281,14 @@ impl RegisterManager {
                             .and_then(|lowercase| self.named_registers.get(&lowercase))
                     }
                     '"' => Some(&self.unnamed_register),
+                    ':' => Some(&self.last_command),
+                    '%' => Some(&self.current_file),
+                    '#' => Some(&self.alternate_file),
+                    '-' => Some(&self.small_delete_register),
+                    '+' | '*' => {
+                        self.sync_clipboard();
+                        Some(&self.clipboard_register).filter(|data| !data.content.is_empty())
+                    }
                     '0'..='9' => {
                         name.to_digit(10)
                             .map(|digit| digit as usize)
@@ -124,6 +305,20 @@ impl RegisterManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A system without a clipboard
+    struct NoClipboard;
+
+    impl ClipboardBackend for NoClipboard {
+        fn get_text(&mut self) -> Option<String> {
+            None
+        }
+        fn set_text(&mut self, _text: &str) -> bool {
+            false
+        }
+    }
 
     #[test]
     fn test_unnamed_register() {
@@ -156,11 +351,34 @@ mod tests {
 
         let content = manager.get_register_content(Some('a')).unwrap();
         assert_eq!(content.content, "hello world");
+        // The unnamed register holds the whole register, ready for p
+        assert_eq!(manager.get_register_content(None).unwrap().content, "hello world");
+
+        // Appending lines makes the register linewise, each piece on its own line
+        manager.store_in_register(Some('A'), "line".to_string(), RegisterType::Line);
+        let content = manager.get_register_content(Some('a')).unwrap();
+        assert_eq!(content.content, "hello world\nline");
+        assert_eq!(content.register_type, RegisterType::Line);
+
+        // Appending to an empty register just fills it
+        manager.store_in_register(Some('B'), "b".to_string(), RegisterType::Character);
+        assert_eq!(manager.get_register_content(Some('b')).unwrap().content, "b");
+    }
+
+    #[test]
+    fn test_list_skips_empty_registers() {
+        let mut manager = RegisterManager { clipboard: Box::new(NoClipboard), ..RegisterManager::new() };
+        manager.store_in_register(Some('c'), "c".to_string(), RegisterType::Character);
+        manager.store_delete(None, "x".to_string(), RegisterType::Character);
+        manager.set_last_command("w");
+
+        let names: Vec<char> = manager.list().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ['"', 'c', '-', ':']);
     }
 
     #[test]
     fn test_numbered_registers_access() {
-        let manager = RegisterManager::new();
+        let mut manager = RegisterManager::new();
 
         // Test that numbered registers can be accessed (even if empty)
         let reg0 = manager.get_register_content(Some('0')).unwrap();
@@ -169,4 +387,111 @@ mod tests {
         let reg1 = manager.get_register_content(Some('1')).unwrap();
         assert_eq!(reg1.content, "");
     }
+
+    #[test]
+    fn test_command_register_is_read_only() {
+        let mut manager = RegisterManager::new();
+
+        manager.set_last_command("s/a/b/g");
+        manager.store_in_register(Some(':'), "yanked".to_string(), RegisterType::Character);
+
+        assert_eq!(manager.get_register_content(Some(':')).unwrap().content, "s/a/b/g");
+        assert_eq!(manager.get_register_content(None).unwrap().content, "yanked");
+    }
+
+    #[test]
+    fn test_small_delete_register() {
+        let mut manager = RegisterManager::new();
+
+        manager.store_delete(None, "abc".to_string(), RegisterType::Character);
+        manager.store_delete(None, "line".to_string(), RegisterType::Line);
+        manager.store_delete(Some('a'), "xyz".to_string(), RegisterType::Character);
+
+        assert_eq!(manager.get_register_content(Some('-')).unwrap().content, "abc");
+        assert_eq!(manager.get_register_content(Some('a')).unwrap().content, "xyz");
+        assert_eq!(manager.get_register_content(None).unwrap().content, "xyz");
+        // Only the line delete reached the numbered registers
+        assert_eq!(manager.get_register_content(Some('1')).unwrap().content, "line");
+        assert_eq!(manager.get_register_content(Some('2')).unwrap().content, "");
+    }
+
+    #[test]
+    fn test_numbered_registers_rotate() {
+        let mut manager = RegisterManager::new();
+
+        for i in 1..=10 {
+            manager.store_delete(None, format!("line {i}"), RegisterType::Line);
+        }
+        manager.store_yank(None, "yanked".to_string(), RegisterType::Character);
+        manager.store_yank(Some('b'), "named".to_string(), RegisterType::Character);
+
+        assert_eq!(manager.get_register_content(Some('1')).unwrap().content, "line 10");
+        assert_eq!(manager.get_register_content(Some('9')).unwrap().content, "line 2");
+        assert_eq!(manager.get_register_content(Some('0')).unwrap().content, "yanked");
+        // A multi-line characterwise delete counts as big
+        manager.store_delete(None, "a\nb".to_string(), RegisterType::Character);
+        assert_eq!(manager.get_register_content(Some('1')).unwrap().content, "a\nb");
+        assert_eq!(manager.get_register_content(Some('2')).unwrap().content, "line 10");
+    }
+
+    #[test]
+    fn test_history_keeps_recent_yanks_newest_first() {
+        let mut manager = RegisterManager::new();
+
+        for i in 0..=HISTORY_SIZE {
+            manager.store_in_register(None, format!("yank {i}"), RegisterType::Character);
+        }
+        manager.store_delete(None, "line".to_string(), RegisterType::Line);
+        manager.store_in_register(None, "line".to_string(), RegisterType::Line);
+
+        assert_eq!(manager.history_len(), HISTORY_SIZE);
+        assert_eq!(manager.history_entry(0).unwrap().register_type, RegisterType::Line);
+        assert_eq!(manager.history_entry(1).unwrap().content, format!("yank {HISTORY_SIZE}"));
+        assert_eq!(manager.history_position("yank 2"), Some(HISTORY_SIZE - 1));
+        assert_eq!(manager.history_position("yank 1"), None);
+    }
+
+    /// Stands in for the OS clipboard
+    struct MemoryClipboard(Rc<RefCell<Option<String>>>);
+
+    impl ClipboardBackend for MemoryClipboard {
+        fn get_text(&mut self) -> Option<String> {
+            self.0.borrow().clone()
+        }
+
+        fn set_text(&mut self, text: &str) -> bool {
+            *self.0.borrow_mut() = Some(text.to_string());
+            true
+        }
+    }
+
+    #[test]
+    fn test_clipboard_registers_sync_with_system_clipboard() {
+        let system = Rc::new(RefCell::new(None));
+        let mut manager = RegisterManager {
+            clipboard: Box::new(MemoryClipboard(system.clone())),
+            ..RegisterManager::new()
+        };
+
+        // "+yy exports a full line; reading it back keeps the linewise type
+        manager.store_in_register(Some('+'), "first".to_string(), RegisterType::Line);
+        assert_eq!(system.borrow().as_deref(), Some("first\n"));
+        let data = manager.get_register_content(Some('*')).unwrap();
+        assert_eq!((data.content.as_str(), &data.register_type), ("first", &RegisterType::Line));
+
+        // Text copied by another program replaces it
+        *system.borrow_mut() = Some("from elsewhere".to_string());
+        let data = manager.get_register_content(Some('+')).unwrap();
+        assert_eq!((data.content.as_str(), &data.register_type), ("from elsewhere", &RegisterType::Character));
+    }
+
+    #[test]
+    fn test_clipboard_register_without_system_clipboard() {
+        let mut manager = RegisterManager { clipboard: Box::new(NoClipboard), ..RegisterManager::new() };
+        assert!(manager.get_register_content(Some('+')).is_none());
+
+        manager.store_in_register(Some('+'), "kept".to_string(), RegisterType::Character);
+        assert_eq!(manager.get_register_content(Some('+')).unwrap().content, "kept");
+        assert_eq!(manager.get_register_content(None).unwrap().content, "kept");
+    }
 }
@@ -0,0 +1,110 @@
+//! Whitespace conversion behind :retab and :detab
+//!
+//! Whitespace is measured with the tab stop the text was written for, so
+//! mixed tabs and spaces keep their on-screen width, then laid out again
+//! for the new tab stop. Only the indentation is touched unless asked
+//! otherwise, so tabs inside strings and comments survive.
+
+use unicode_width::UnicodeWidthChar;
+
+/// How :retab rewrites whitespace
+#[derive(Debug, Clone, Copy)]
+pub struct Retab {
+    pub tab_stop: usize,     // Tab stop the text was written for
+    pub new_tab_stop: usize, // Tab stop the result is laid out for
+    pub use_tabs: bool,      // Tabs where they fit (:retab) or spaces only (:detab)
+    pub whole_line: bool,    // Whitespace after the indentation too (:retab!)
+}
+
+impl Retab {
+    /// `line` with its whitespace rewritten
+    pub fn apply(&self, line: &str) -> String {
+        let tab_stop = self.tab_stop.max(1);
+        let mut result = String::with_capacity(line.len());
+        let mut column = 0;
+        let mut rest = line;
+
+        while !rest.is_empty() {
+            let run_len = rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+            if run_len == 0 {
+                let ch = rest.chars().next().expect("rest is not empty");
+                result.push(ch);
+                column += ch.width().unwrap_or(1);
+                rest = &rest[ch.len_utf8()..];
+                continue;
+            }
+
+            let (run, after) = rest.split_at(run_len);
+            let start = column;
+            for ch in run.chars() {
+                column = if ch == '\t' { (column / tab_stop + 1) * tab_stop } else { column + 1 };
+            }
+            let is_indent = start == 0 && result.is_empty();
+            // A lone space between words stays a space
+            if (is_indent || self.whole_line) && run != " " {
+                result.push_str(&self.layout(start, column));
+            } else {
+                result.push_str(run);
+            }
+            rest = after;
+        }
+
+        result
+    }
+
+    /// Whitespace filling display columns `from..to`
+    fn layout(&self, from: usize, to: usize) -> String {
+        let tab_stop = self.new_tab_stop.max(1);
+        let mut whitespace = String::new();
+        let mut column = from;
+        if self.use_tabs {
+            while (column / tab_stop + 1) * tab_stop <= to {
+                whitespace.push('\t');
+                column = (column / tab_stop + 1) * tab_stop;
+            }
+        }
+        whitespace.push_str(&" ".repeat(to - column));
+        whitespace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retab(tab_stop: usize, new_tab_stop: usize, use_tabs: bool, whole_line: bool) -> Retab {
+        Retab { tab_stop, new_tab_stop, use_tabs, whole_line }
+    }
+
+    #[test]
+    fn test_indentation_to_tabs_keeps_its_width() {
+        let to_tabs = retab(4, 4, true, false);
+        assert_eq!(to_tabs.apply("        x"), "\t\tx");
+        assert_eq!(to_tabs.apply("  \t  x"), "\t  x");
+        assert_eq!(to_tabs.apply("      x = \"a    b\""), "\t  x = \"a    b\"");
+        assert_eq!(to_tabs.apply("no indent"), "no indent");
+        assert_eq!(to_tabs.apply(""), "");
+    }
+
+    #[test]
+    fn test_tabs_to_spaces_leaves_tabs_in_strings() {
+        let to_spaces = retab(4, 4, false, false);
+        assert_eq!(to_spaces.apply("\t\tcall(\"a\tb\")"), "        call(\"a\tb\")");
+        assert_eq!(to_spaces.apply("  \tx"), "    x");
+    }
+
+    #[test]
+    fn test_new_tab_stop() {
+        // Indentation 8 columns wide at tabstop 8 becomes four tabs at tabstop 2
+        assert_eq!(retab(8, 2, true, false).apply("\tx"), "\t\t\t\tx");
+        assert_eq!(retab(8, 3, false, false).apply("\tx"), "        x");
+    }
+
+    #[test]
+    fn test_whole_line() {
+        let all = retab(4, 4, true, true);
+        assert_eq!(all.apply("ab      c d"), "ab\t\tc d");
+        let to_spaces = retab(4, 4, false, true);
+        assert_eq!(to_spaces.apply("a\tb"), "a   b");
+    }
+}
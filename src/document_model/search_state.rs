@@ -1,4 +1,5 @@
 use super::document::Document;
+use super::selection::Selection;
 use regex::Regex;
 use std::fmt;
 
@@ -12,12 +13,118 @@ pub enum SearchDirection {
 pub struct SearchMatch {
     pub line: usize,
     pub start_col: usize,
-    #[allow(dead_code)] // Will be used for search highlighting
+    pub end_line: usize, // Differs from `line` when the pattern spans a line break
     pub end_col: usize,
     #[allow(dead_code)] // Will be used for search highlighting
     pub text: String,
 }
 
+impl SearchMatch {
+    /// Whether the character at (line, col) is part of the match
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        (line, col) >= (self.line, self.start_col) && (line, col) < (self.end_line, self.end_col)
+    }
+}
+
+/// Start offsets of each line in text joined with "\n", for mapping
+/// byte offsets from a buffer-wide match back to (line, column)
+pub struct LineOffsets(Vec<usize>);
+
+impl LineOffsets {
+    pub fn new(text: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(idx, _)| idx + 1));
+        Self(starts)
+    }
+
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.0.partition_point(|&start| start <= offset) - 1;
+        (line, offset - self.0[line])
+    }
+
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.0.get(line).copied()
+    }
+
+    /// Offset just past the last character of `line` (before its line break)
+    pub fn line_end(&self, line: usize, text_len: usize) -> usize {
+        self.0.get(line + 1).map_or(text_len, |next| next - 1)
+    }
+}
+
+/// Build the regex for a search pattern. Text matches literally apart from
+/// `^` at the start and `$` at the end, which anchor to the start and end of
+/// a line, `\<` and `\>`, which anchor to the start and end of a word, and
+/// `\n`, which matches a line break. `\\`, `\^` and `\$` match a
+/// literal backslash, caret and dollar. `\%V`, and the `\c` / `\C` that
+/// `matches_case` reads, match nothing here; the matches a `\%V` pattern
+/// finds are kept to the visual area afterwards. (:s patterns are regular
+/// expressions; see the substitute module.)
+pub fn pattern_to_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, SearchError> {
+    let mut regex_str = String::from(if case_sensitive { "(?m)" } else { "(?mi)" });
+    let body = match pattern.strip_prefix('^') {
+        Some(rest) => {
+            regex_str.push('^');
+            rest
+        }
+        None => pattern,
+    };
+
+    let mut chars = body.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('n') => regex_str.push_str("\\n"),
+                Some('%') if chars.peek() == Some(&'V') => {
+                    chars.next();
+                }
+                Some('c' | 'C') => {}
+                Some('<') => regex_str.push_str("\\b{start}"),
+                Some('>') => regex_str.push_str("\\b{end}"),
+                Some(escaped @ ('\\' | '^' | '$')) => regex_str.push_str(&regex::escape(&escaped.to_string())),
+                Some(other) => regex_str.push_str(&regex::escape(&format!("\\{}", other))),
+                None => regex_str.push_str("\\\\"),
+            },
+            '$' if chars.peek().is_none() => regex_str.push('$'),
+            _ => regex_str.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+
+    Regex::new(&regex_str).map_err(|e| SearchError::InvalidPattern(e.to_string()))
+}
+
+/// Whether `pattern` matches case. A `\c` in it ignores case and a `\C`
+/// matches it, whatever the options; otherwise case is ignored with
+/// `ignore_case` ('ignorecase') unless `smart_case` is on and the pattern
+/// has a capital letter.
+pub fn matches_case(pattern: &str, ignore_case: bool, smart_case: bool) -> bool {
+    let mut has_capital = false;
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('c') => return false,
+                Some('C') => return true,
+                _ => {} // \S, \W and the like aren't capitals
+            },
+            _ => has_capital |= ch.is_uppercase(),
+        }
+    }
+    !ignore_case || (smart_case && has_capital)
+}
+
+/// Whether a pattern holds `\%V`, which limits / and :s to the buffer's last
+/// visual selection
+pub fn uses_visual_area(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' && chars.next() == Some('%') && chars.as_str().starts_with('V') {
+            return true;
+        }
+    }
+    false
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchState {
     pub pattern: String,
@@ -25,8 +132,23 @@ pub struct SearchState {
     pub matches: Vec<SearchMatch>,
     pub current_match: Option<usize>,
     pub regex: Option<Regex>,
-    pub case_sensitive: bool,
+    pub ignore_case: bool, // 'ignorecase'
+    pub smart_case: bool,  // 'smartcase': a capital letter in the pattern matches case after all
     pub wrap_search: bool,
+    pub hlsearch: bool,          // Highlight all matches of the last search
+    pub incsearch: bool,         // Show matches while the pattern is typed
+    pub highlight_suspended: bool, // :noh - hide highlights until the next search
+    pub preview_matches: Option<Vec<SearchMatch>>, // Matches of the pattern being typed
+    pub preview_current: Option<usize>,
+    pub region: Option<Selection>, // Set by / or ? in visual mode: only matches inside the selection count
+    pub shortmess: String,         // 'shortmess': S leaves the [3/17] match count out of search messages
+    matches_for: Option<u64>,      // Text revision the matches were found in
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SearchState {
@@ -37,11 +159,130 @@ impl SearchState {
             matches: Vec::new(),
             current_match: None,
             regex: None,
-            case_sensitive: false, // Default to case insensitive like vim
+            ignore_case: false,
+            smart_case: false,
             wrap_search: true,     // Default to wrap search like vim
+            hlsearch: true,
+            incsearch: true,
+            highlight_suspended: false,
+            preview_matches: None,
+            preview_current: None,
+            region: None,
+            shortmess: String::new(),
+            matches_for: None,
+        }
+    }
+
+    fn compile(&self, pattern: &str) -> Result<Regex, SearchError> {
+        pattern_to_regex(pattern, matches_case(pattern, self.ignore_case, self.smart_case))
+    }
+
+    // Search the whole buffer at once so patterns can span line breaks.
+    // With `in_visual_area` (\%V) only matches inside the buffer's last
+    // visual selection count, and there are none without one.
+    fn collect_matches(regex: &Regex, document: &Document, region: Option<&Selection>, in_visual_area: bool) -> Vec<SearchMatch> {
+        let visual_area = document.last_selection.as_ref().filter(|_| in_visual_area);
+        if in_visual_area && visual_area.is_none() {
+            return Vec::new();
+        }
+        let text = document.buffer_text();
+        let offsets = LineOffsets::new(&text);
+        regex
+            .find_iter(&text)
+            .map(|mat| {
+                let (line, start_col) = offsets.position(mat.start());
+                let (end_line, end_col) = offsets.position(mat.end());
+                SearchMatch {
+                    line,
+                    start_col,
+                    end_line,
+                    end_col,
+                    text: mat.as_str().to_string(),
+                }
+            })
+            .filter(|m| {
+                [region, visual_area]
+                    .into_iter()
+                    .flatten()
+                    .all(|area| area.contains_span((m.line, m.start_col), (m.end_line, m.end_col)))
+            })
+            .collect()
+    }
+
+    /// Index of the first match after (forward) or before (backward) a position
+    fn match_index_from(
+        matches: &[SearchMatch],
+        from_line: usize,
+        from_col: usize,
+        forward: bool,
+        wrap: bool,
+    ) -> Option<usize> {
+        if matches.is_empty() {
+            return None;
+        }
+        let found = if forward {
+            matches
+                .iter()
+                .position(|m| m.line > from_line || (m.line == from_line && m.start_col > from_col))
+        } else {
+            matches
+                .iter()
+                .rposition(|m| m.line < from_line || (m.line == from_line && m.start_col < from_col))
+        };
+        match found {
+            Some(idx) => Some(idx),
+            None if wrap => Some(if forward { 0 } else { matches.len() - 1 }),
+            None => None,
+        }
+    }
+
+    /// Incremental search: match a partially typed pattern without touching
+    /// the committed search, within `region` when searching a selection.
+    /// Returns the match the cursor should jump to.
+    pub fn preview(
+        &mut self,
+        pattern: &str,
+        direction: &SearchDirection,
+        document: &Document,
+        from: (usize, usize),
+        region: Option<&Selection>,
+    ) -> Option<&SearchMatch> {
+        let matches = match self.compile(pattern) {
+            Ok(regex) if !pattern.is_empty() => Self::collect_matches(&regex, document, region, uses_visual_area(pattern)),
+            _ => Vec::new(),
+        };
+        let forward = *direction == SearchDirection::Forward;
+        self.preview_current = Self::match_index_from(&matches, from.0, from.1, forward, self.wrap_search);
+        let matches = self.preview_matches.insert(matches);
+        self.preview_current.and_then(|idx| matches.get(idx))
+    }
+
+    pub fn clear_preview(&mut self) {
+        self.preview_matches = None;
+        self.preview_current = None;
+    }
+
+    /// Matches the renderer should highlight: the pattern being typed, or the
+    /// last search's matches when 'hlsearch' is on and not suspended by :noh
+    pub fn highlighted_matches(&self) -> &[SearchMatch] {
+        if let Some(preview) = &self.preview_matches {
+            preview
+        } else if self.hlsearch && !self.highlight_suspended {
+            &self.matches
+        } else {
+            &[]
         }
     }
 
+    /// The highlighted matches touching `line`, and the index of the first.
+    /// Matches are sorted and never overlap, so they form one contiguous run.
+    pub fn highlighted_matches_on_line(&self, line: usize) -> (usize, &[SearchMatch]) {
+        let matches = self.highlighted_matches();
+        let start = matches.partition_point(|m| m.end_line < line);
+        let end = matches.partition_point(|m| m.line <= line).max(start);
+        (start, &matches[start..end])
+    }
+
     pub fn set_pattern(
         &mut self,
         pattern: String,
@@ -58,46 +299,21 @@ impl SearchState {
             return Err(SearchError::NoPattern);
         }
 
-        // Escape special regex characters for literal search
-        let escaped_pattern = regex::escape(&pattern);
-
-        // Create regex with case sensitivity setting
-        let regex_str = if self.case_sensitive {
-            escaped_pattern
-        } else {
-            format!("(?i){}", escaped_pattern)
-        };
-
-        match Regex::new(&regex_str) {
-            Ok(regex) => {
-                self.regex = Some(regex);
-                Ok(())
-            }
-            Err(e) => Err(SearchError::InvalidPattern(e.to_string())),
-        }
+        self.regex = Some(self.compile(&pattern)?);
+        self.highlight_suspended = false;
+        Ok(())
     }
 
     pub fn search_document(&mut self, document: &Document) -> Result<(), SearchError> {
         self.matches.clear();
         self.current_match = None;
+        self.matches_for = Some(document.revision());
 
         let Some(regex) = &self.regex else {
             return Ok(());
         };
 
-        let line_count = document.line_count();
-        for line_idx in 0..line_count {
-            if let Some(line_text) = document.get_line(line_idx) {
-                for mat in regex.find_iter(&line_text) {
-                    self.matches.push(SearchMatch {
-                        line: line_idx,
-                        start_col: mat.start(),
-                        end_col: mat.end(),
-                        text: mat.as_str().to_string(),
-                    });
-                }
-            }
-        }
+        self.matches = Self::collect_matches(regex, document, self.region.as_ref(), uses_visual_area(&self.pattern));
 
         if !self.matches.is_empty() {
             self.current_match = Some(0);
@@ -106,63 +322,34 @@ impl SearchState {
         Ok(())
     }
 
-    pub fn find_next_match(&mut self, from_line: usize, from_col: usize) -> Option<&SearchMatch> {
-        if self.matches.is_empty() {
-            return None;
+    /// Find the matches again when `document` isn't the text they were
+    /// found in (it was edited, or is another buffer), so highlights and
+    /// match counts follow the text
+    pub fn sync(&mut self, document: &Document) {
+        if self.regex.is_some() && self.matches_for != Some(document.revision()) {
+            let _ = self.search_document(document);
         }
+    }
 
-        // Find the first match after the current position
-        let start_idx = self
-            .matches
-            .iter()
-            .position(|m| m.line > from_line || (m.line == from_line && m.start_col > from_col));
-
-        match start_idx {
-            Some(idx) => {
-                self.current_match = Some(idx);
-                self.matches.get(idx)
-            }
-            None => {
-                // No match found after cursor
-                if self.wrap_search && !self.matches.is_empty() {
-                    // Wrap to beginning
-                    self.current_match = Some(0);
-                    self.matches.first()
-                } else {
-                    None
-                }
-            }
-        }
+    /// "[3/17]": which of the matches the cursor went to, or None when
+    /// there is no current match or 'shortmess' has S
+    pub fn match_count(&self) -> Option<String> {
+        let current = self.current_match.filter(|_| !self.matches.is_empty() && !self.shortmess.contains('S'))?;
+        Some(format!("[{}/{}]", current + 1, self.matches.len()))
     }
 
-    pub fn find_prev_match(&mut self, from_line: usize, from_col: usize) -> Option<&SearchMatch> {
-        if self.matches.is_empty() {
-            return None;
-        }
+    pub fn find_next_match(&mut self, from_line: usize, from_col: usize) -> Option<&SearchMatch> {
+        // First match after the position, wrapping to the beginning
+        let idx = Self::match_index_from(&self.matches, from_line, from_col, true, self.wrap_search)?;
+        self.current_match = Some(idx);
+        self.matches.get(idx)
+    }
 
-        // Find the last match before the current position
-        let start_idx = self
-            .matches
-            .iter()
-            .rposition(|m| m.line < from_line || (m.line == from_line && m.start_col < from_col));
-
-        match start_idx {
-            Some(idx) => {
-                self.current_match = Some(idx);
-                self.matches.get(idx)
-            }
-            None => {
-                // No match found before cursor
-                if self.wrap_search && !self.matches.is_empty() {
-                    // Wrap to end
-                    let last_idx = self.matches.len() - 1;
-                    self.current_match = Some(last_idx);
-                    self.matches.last()
-                } else {
-                    None
-                }
-            }
-        }
+    pub fn find_prev_match(&mut self, from_line: usize, from_col: usize) -> Option<&SearchMatch> {
+        // Last match before the position, wrapping to the end
+        let idx = Self::match_index_from(&self.matches, from_line, from_col, false, self.wrap_search)?;
+        self.current_match = Some(idx);
+        self.matches.get(idx)
     }
 
     pub fn repeat_last_search(
@@ -170,6 +357,7 @@ impl SearchState {
         from_line: usize,
         from_col: usize,
     ) -> Option<&SearchMatch> {
+        self.highlight_suspended = false;
         self.find_next_match(from_line, from_col)
     }
 
@@ -178,6 +366,7 @@ impl SearchState {
         from_line: usize,
         from_col: usize,
     ) -> Option<&SearchMatch> {
+        self.highlight_suspended = false;
         self.find_prev_match(from_line, from_col)
     }
 
@@ -199,4 +388,158 @@ impl fmt::Display for SearchError {
             SearchError::NoPattern => write!(f, "No search pattern"),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc() -> Document {
+        Document::from_string("foo bar\nbaz foo\nfood".to_string())
+    }
+
+    #[test]
+    fn test_preview_does_not_replace_committed_search() {
+        let document = doc();
+        let mut search = SearchState::new();
+        search.set_pattern("bar".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+
+        let first = search.preview("fo", &SearchDirection::Forward, &document, (0, 0), None).cloned();
+        assert_eq!(first.map(|m| (m.line, m.start_col)), Some((1, 4)));
+        assert_eq!(search.highlighted_matches().len(), 3);
+        assert_eq!(search.pattern, "bar");
+
+        search.clear_preview();
+        assert_eq!(search.highlighted_matches().len(), 1);
+    }
+
+    #[test]
+    fn test_preview_backward_wraps() {
+        let document = doc();
+        let mut search = SearchState::new();
+        let found = search.preview("bar", &SearchDirection::Backward, &document, (0, 0), None).cloned();
+        assert_eq!(found.map(|m| (m.line, m.start_col)), Some((0, 4)));
+        assert!(search.preview("xyz", &SearchDirection::Forward, &document, (0, 0), None).is_none());
+        assert!(search.highlighted_matches().is_empty());
+    }
+
+    #[test]
+    fn test_noh_suspends_until_next_search() {
+        let document = doc();
+        let mut search = SearchState::new();
+        search.set_pattern("foo".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+
+        search.highlight_suspended = true;
+        assert!(search.highlighted_matches().is_empty());
+        search.repeat_last_search(0, 0);
+        assert_eq!(search.highlighted_matches().len(), 3);
+
+        search.hlsearch = false;
+        assert!(search.highlighted_matches().is_empty());
+    }
+
+    #[test]
+    fn test_region_limits_matches() {
+        let document = doc();
+        let mut search = SearchState::new();
+        let mut region = Selection::new(0, 4, super::super::selection::VisualMode::Char);
+        region.update_end(1, 6);
+        search.region = Some(region.clone());
+        search.set_pattern("foo".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+        assert_eq!(search.matches.iter().map(|m| (m.line, m.start_col)).collect::<Vec<_>>(), [(1, 4)]);
+
+        let found = search.preview("ba", &SearchDirection::Forward, &document, (0, 0), Some(&region)).cloned();
+        assert_eq!(found.map(|m| (m.line, m.start_col)), Some((0, 4)));
+        assert_eq!(search.highlighted_matches().len(), 2);
+    }
+
+    #[test]
+    fn test_visual_area_limits_matches() {
+        let mut document = doc();
+        let mut search = SearchState::new();
+        search.set_pattern("\\%Vfoo".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+        assert!(search.matches.is_empty(), "no selection, no matches");
+
+        let mut selection = Selection::new(1, 0, super::super::selection::VisualMode::Line);
+        selection.update_end(2, 0);
+        document.last_selection = Some(selection);
+        search.search_document(&document).unwrap();
+        assert_eq!(search.matches.iter().map(|m| (m.line, m.start_col)).collect::<Vec<_>>(), [(1, 4), (2, 0)]);
+    }
+
+    #[test]
+    fn test_pattern_spans_line_break() {
+        let document = doc();
+        let mut search = SearchState::new();
+        search.set_pattern("bar\\nbaz".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+
+        let found = &search.matches[0];
+        assert_eq!(search.matches.len(), 1);
+        assert_eq!((found.line, found.start_col, found.end_line, found.end_col), (0, 4, 1, 3));
+        assert!(found.contains(0, 6) && found.contains(1, 0));
+        assert!(!found.contains(1, 3));
+        assert_eq!(search.highlighted_matches_on_line(1).1.len(), 1);
+        assert!(search.highlighted_matches_on_line(2).1.is_empty());
+    }
+
+    #[test]
+    fn test_line_anchors_and_literal_text() {
+        let find = |pattern: &str, text: &str| -> Vec<usize> {
+            let regex = pattern_to_regex(pattern, true).unwrap();
+            regex.find_iter(text).map(|m| m.start()).collect()
+        };
+        assert_eq!(find("^foo", "foo foo\nfoo"), vec![0, 8]);
+        assert_eq!(find("foo$", "foo foo\nfoo"), vec![4, 8]);
+        assert_eq!(find("a.b$x", "a.b$x axb"), vec![0]);
+        assert_eq!(find("\\$5", "costs $5"), vec![6]);
+        assert_eq!(find("a\\\\b", "a\\b"), vec![0]);
+        assert_eq!(find("\\%Vx", "a x"), vec![2]);
+        assert_eq!(find("\\<foo\\>", "foo food xfoo foo_ foo."), vec![0, 19]);
+        assert!(uses_visual_area("\\%Vx") && !uses_visual_area("\\\\%Vx"));
+    }
+
+    #[test]
+    fn test_ignorecase_smartcase_and_overrides() {
+        assert!(!matches_case("foo", true, true));
+        assert!(matches_case("Foo", true, true));
+        assert!(!matches_case("Foo", true, false));
+        assert!(matches_case("foo", false, false));
+        assert!(!matches_case("Foo\\c", false, true));
+        assert!(matches_case("foo\\C", true, false));
+        assert!(!matches_case("a\\S", true, true));
+
+        let document = Document::from_string("Foo foo".to_string());
+        let mut search = SearchState::new();
+        search.ignore_case = true;
+        search.smart_case = true;
+        for (pattern, count) in [("foo", 2), ("Foo", 1), ("Foo\\c", 2)] {
+            search.set_pattern(pattern.to_string(), SearchDirection::Forward).unwrap();
+            search.search_document(&document).unwrap();
+            assert_eq!(search.matches.len(), count, "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_sync_finds_matches_again_after_edits() {
+        let mut document = doc();
+        let mut search = SearchState::new();
+        search.set_pattern("foo".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&document).unwrap();
+        search.find_next_match(0, 0);
+        assert_eq!(search.match_count().as_deref(), Some("[2/3]"));
+
+        search.sync(&document);
+        assert_eq!(search.match_count().as_deref(), Some("[2/3]"), "unchanged text isn't searched again");
+
+        document.insert_text_at(0, 0, "foo ");
+        search.sync(&document);
+        assert_eq!(search.matches.len(), 4);
+
+        search.shortmess = "S".to_string();
+        assert_eq!(search.match_count(), None);
+    }
+}
@@ -0,0 +1,179 @@
+//! Visual selections - the text a visual mode has selected, kept by each
+//! buffer once visual mode ends for gv, the '< and '> marks and \%V
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VisualMode {
+    Char,
+    Line,
+    Block,
+}
+
+/// Right column of a block selection extended with $: every row runs to its own line end
+pub const LINE_END: usize = usize::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub mode: VisualMode,
+    pub to_line_end: bool, // $ pressed in block mode; cleared by horizontal movement
+}
+
+impl Selection {
+    pub fn new(line: usize, column: usize, mode: VisualMode) -> Self {
+        Self {
+            start_line: line,
+            start_column: column,
+            end_line: line,
+            end_column: column,
+            mode,
+            to_line_end: false,
+        }
+    }
+
+    pub fn update_end(&mut self, line: usize, column: usize) {
+        self.end_line = line;
+        self.end_column = column;
+    }
+
+    /// o: the cursor goes to the other end of the selection. Returns where.
+    pub fn swap_ends(&mut self) -> (usize, usize) {
+        std::mem::swap(&mut self.start_line, &mut self.end_line);
+        std::mem::swap(&mut self.start_column, &mut self.end_column);
+        (self.end_line, self.end_column)
+    }
+
+    /// O: in block mode the cursor goes to the other corner of its row;
+    /// elsewhere this is o. Returns where the cursor goes.
+    pub fn swap_corners(&mut self) -> (usize, usize) {
+        if self.mode != VisualMode::Block {
+            return self.swap_ends();
+        }
+        std::mem::swap(&mut self.start_column, &mut self.end_column);
+        (self.end_line, self.end_column)
+    }
+
+    pub fn get_ordered_bounds(&self) -> (usize, usize, usize, usize) {
+        let (start_line, start_col, end_line, end_col) = if self.start_line < self.end_line
+            || (self.start_line == self.end_line && self.start_column <= self.end_column)
+        {
+            (
+                self.start_line,
+                self.start_column,
+                self.end_line,
+                self.end_column,
+            )
+        } else {
+            (
+                self.end_line,
+                self.end_column,
+                self.start_line,
+                self.start_column,
+            )
+        };
+
+        match self.mode {
+            VisualMode::Line => {
+                // Line mode always selects entire lines
+                (start_line, 0, end_line, usize::MAX)
+            }
+            VisualMode::Char => {
+                // Character mode uses exact positions
+                (start_line, start_col, end_line, end_col)
+            }
+            VisualMode::Block => {
+                // Block mode maintains column boundaries
+                let left_col = start_col.min(end_col);
+                let right_col = if self.to_line_end {
+                    LINE_END
+                } else {
+                    start_col.max(end_col)
+                };
+                (start_line, left_col, end_line, right_col)
+            }
+        }
+    }
+
+    pub fn is_line_in_selection(&self, line: usize) -> bool {
+        let (start_line, _, end_line, _) = self.get_ordered_bounds();
+        line >= start_line && line <= end_line
+    }
+
+    /// Whether text from `start` to `end` (exclusive) lies inside the
+    /// selection. In block mode it must also sit on a single line.
+    pub fn contains_span(&self, start: (usize, usize), end: (usize, usize)) -> bool {
+        let (start_line, start_col, end_line, end_col) = self.get_ordered_bounds();
+        match self.mode {
+            VisualMode::Line => start.0 >= start_line && end.0 <= end_line,
+            VisualMode::Char => {
+                start >= (start_line, start_col) && end <= (end_line, end_col.saturating_add(1))
+            }
+            VisualMode::Block => {
+                start.0 == end.0
+                    && (start_line..=end_line).contains(&start.0)
+                    && start.1 >= start_col
+                    && end.1 <= end_col.saturating_add(1)
+            }
+        }
+    }
+
+    #[allow(dead_code)] // Will be used for visual selection highlighting
+    pub fn get_selected_range_for_line(
+        &self,
+        line: usize,
+        line_len: usize,
+    ) -> Option<(usize, usize)> {
+        if !self.is_line_in_selection(line) {
+            return None;
+        }
+
+        let (start_line, start_col, end_line, end_col) = self.get_ordered_bounds();
+
+        match self.mode {
+            VisualMode::Line => {
+                // Entire line is selected
+                Some((0, line_len))
+            }
+            VisualMode::Char => {
+                let left = if line == start_line { start_col } else { 0 };
+                let right = if line == end_line {
+                    end_col.min(line_len)
+                } else {
+                    line_len
+                };
+                Some((left, right))
+            }
+            VisualMode::Block => {
+                // Block selection uses column boundaries
+                let left = start_col.min(line_len);
+                let right = end_col.min(line_len);
+                if left <= right {
+                    Some((left, right))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swapping_ends_and_corners() {
+        let mut selection = Selection::new(1, 2, VisualMode::Block);
+        selection.update_end(3, 5);
+        assert_eq!(selection.swap_corners(), (3, 2));
+        assert_eq!(selection.get_ordered_bounds(), (1, 2, 3, 5));
+        assert_eq!(selection.swap_ends(), (1, 5));
+        assert_eq!(selection.get_ordered_bounds(), (1, 2, 3, 5));
+
+        let mut selection = Selection::new(1, 2, VisualMode::Char);
+        selection.update_end(3, 5);
+        assert_eq!(selection.swap_corners(), (1, 2));
+    }
+}
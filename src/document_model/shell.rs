@@ -0,0 +1,38 @@
+//! Text piped through shell commands, for 'writeprg' and :{range}!
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run `command` with `sh -c`, feed it `input` and collect what it prints
+pub fn filter(command: &str, input: Vec<u8>) -> std::io::Result<Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin from another thread so a large buffer can't deadlock on full pipes
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    let _ = writer.join(); // A filter may exit without reading everything
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_passes_input_through() {
+        let output = filter("tr a-z A-Z", b"abc\n".to_vec()).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"ABC\n");
+
+        let output = filter("echo oops >&2; exit 3", Vec::new()).unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stderr, b"oops\n");
+    }
+}
@@ -0,0 +1,115 @@
+//! Line reordering behind :sort, :uniq and :reverse
+//!
+//! Sorting is stable, so lines that compare equal keep their order. With
+//! numeric sorting a line sorts by the first number in it, and lines without
+//! a number come first, as in vim.
+
+use std::cmp::Ordering;
+
+/// How :sort and :uniq compare lines
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineSort {
+    pub reverse: bool,     // Largest first (:sort!)
+    pub unique: bool,      // Keep only the first of lines that compare equal (u)
+    pub numeric: bool,     // Compare the first number in each line (n)
+    pub ignore_case: bool, // Compare without regard to case (i)
+}
+
+impl LineSort {
+    /// Options from the flags after :sort, e.g. "nu"; Err holds a flag that isn't one
+    pub fn from_flags(flags: &str, reverse: bool) -> Result<Self, char> {
+        let mut sort = Self { reverse, ..Self::default() };
+        for flag in flags.chars().filter(|c| !c.is_whitespace()) {
+            match flag {
+                'u' => sort.unique = true,
+                'n' => sort.numeric = true,
+                'i' => sort.ignore_case = true,
+                other => return Err(other),
+            }
+        }
+        Ok(sort)
+    }
+
+    /// `lines` sorted, with duplicates dropped when `unique` is set
+    pub fn sort(&self, mut lines: Vec<String>) -> Vec<String> {
+        lines.sort_by(|a, b| {
+            let order = self.compare(a, b);
+            if self.reverse { order.reverse() } else { order }
+        });
+        if self.unique {
+            lines.dedup_by(|later, earlier| self.compare(earlier, later) == Ordering::Equal);
+        }
+        lines
+    }
+
+    /// `lines` with runs of lines that compare equal cut to their first line
+    pub fn uniq(&self, mut lines: Vec<String>) -> Vec<String> {
+        lines.dedup_by(|later, earlier| self.compare(earlier, later) == Ordering::Equal);
+        lines
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        if self.numeric {
+            first_number(a).cmp(&first_number(b))
+        } else if self.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    }
+}
+
+/// The first decimal number in a line, with its sign; None sorts first
+fn first_number(line: &str) -> Option<i128> {
+    let digits_start = line.find(|c: char| c.is_ascii_digit())?;
+    let digits_end = line[digits_start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(line.len(), |offset| digits_start + offset);
+    let negative = line[..digits_start].ends_with('-');
+    let magnitude = line[digits_start..digits_end].parse::<i128>().unwrap_or(i128::MAX);
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort_and_reverse_sort() {
+        let plain = LineSort::default();
+        assert_eq!(plain.sort(lines(&["b", "C", "a"])), lines(&["C", "a", "b"]));
+        let reverse = LineSort { reverse: true, ..LineSort::default() };
+        assert_eq!(reverse.sort(lines(&["b", "C", "a"])), lines(&["b", "a", "C"]));
+        let ignore_case = LineSort { ignore_case: true, ..LineSort::default() };
+        assert_eq!(ignore_case.sort(lines(&["b", "C", "a"])), lines(&["a", "b", "C"]));
+    }
+
+    #[test]
+    fn test_numeric_sort_puts_lines_without_numbers_first() {
+        let numeric = LineSort::from_flags("n", false).unwrap();
+        assert_eq!(
+            numeric.sort(lines(&["x10", "none", "x9", "x-2", "also none"])),
+            lines(&["none", "also none", "x-2", "x9", "x10"])
+        );
+    }
+
+    #[test]
+    fn test_unique() {
+        let unique = LineSort::from_flags("u", false).unwrap();
+        assert_eq!(unique.sort(lines(&["b", "a", "b", "a"])), lines(&["a", "b"]));
+        let numeric_unique = LineSort::from_flags("nu", false).unwrap();
+        assert_eq!(numeric_unique.sort(lines(&["b2", "a2", "c1"])), lines(&["c1", "b2"]));
+        // uniq only drops repeats that are next to each other
+        let uniq = LineSort::from_flags("i", false).unwrap();
+        assert_eq!(uniq.uniq(lines(&["a", "A", "b", "a"])), lines(&["a", "b", "a"]));
+    }
+
+    #[test]
+    fn test_unknown_flag() {
+        assert_eq!(LineSort::from_flags("x", false).unwrap_err(), 'x');
+    }
+}
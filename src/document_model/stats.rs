@@ -0,0 +1,60 @@
+//! Per-buffer search and substitute counts for the session, shown by :stats
+
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BufferStats {
+    pub searches: usize,            // /, ?, n, N, * and #
+    pub substitute_commands: usize, // :s runs
+    pub lines_substituted: usize,   // Lines changed by :s
+}
+
+impl BufferStats {
+    pub fn record_search(&mut self) {
+        self.searches += 1;
+    }
+
+    pub fn record_substitute(&mut self, lines_changed: usize) {
+        self.substitute_commands += 1;
+        self.lines_substituted += lines_changed;
+    }
+
+    /// One-line summary, e.g. "3 searches, 1 :s, 12 lines changed"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} search{}, {} :s, {} line{} changed",
+            self.searches,
+            if self.searches == 1 { "" } else { "es" },
+            self.substitute_commands,
+            self.lines_substituted,
+            if self.lines_substituted == 1 { "" } else { "s" },
+        )
+    }
+}
+
+impl AddAssign for BufferStats {
+    fn add_assign(&mut self, other: Self) {
+        self.searches += other.searches;
+        self.substitute_commands += other.substitute_commands;
+        self.lines_substituted += other.lines_substituted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_total() {
+        let mut first = BufferStats::default();
+        first.record_search();
+        first.record_substitute(3);
+
+        let mut second = BufferStats::default();
+        second.record_substitute(1);
+        second += first;
+
+        assert_eq!(first.summary(), "1 search, 1 :s, 3 lines changed");
+        assert_eq!(second.summary(), "1 search, 2 :s, 4 lines changed");
+    }
+}
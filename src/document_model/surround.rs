@@ -0,0 +1,173 @@
+//! Pairs of delimiters - the brackets and quotes autopairs closes as they
+//! are typed, and the surroundings ys, cs and ds add, change and delete
+//!
+//! A surround target names its pair by either character: `ds(` and `ds)`
+//! both delete the parentheses around the cursor, and `b`, `B`, `r` and `a`
+//! stand for (), {}, [] and <>. As in vim-surround, the opening character
+//! also takes the space just inside the pair: `ys` with `(` adds `( x )`,
+//! `ds(` on `( x )` leaves `x`. Any other character is its own pair.
+
+/// How many lines either side of the cursor are searched for a pair
+pub const SEARCH_LINES: usize = 200;
+
+/// The opening and closing characters a surround target names
+pub fn pair_of(target: char) -> (char, char) {
+    match target {
+        '(' | ')' | 'b' => ('(', ')'),
+        '[' | ']' | 'r' => ('[', ']'),
+        '{' | '}' | 'B' => ('{', '}'),
+        '<' | '>' | 'a' => ('<', '>'),
+        _ => (target, target),
+    }
+}
+
+/// Whether the target names its pair by the opening bracket, which takes
+/// the space inside the pair too
+fn is_padded(target: char) -> bool {
+    matches!(target, '(' | '[' | '{')
+}
+
+/// The text ys and cs put before and after for `target`
+pub fn delimiters(target: char) -> (String, String) {
+    let (open, close) = pair_of(target);
+    if is_padded(target) { (format!("{open} "), format!(" {close}")) } else { (open.to_string(), close.to_string()) }
+}
+
+/// The character autopairs types after `c`, if it opens a pair
+pub fn auto_close(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '"' | '\'' | '`' => Some(c),
+        _ => None,
+    }
+}
+
+/// The delimiters found around the cursor, as byte ranges: (line, start, end)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surrounding {
+    pub open: (usize, usize, usize),
+    pub close: (usize, usize, usize),
+}
+
+/// The pair `target` names around `cursor` in `lines`, whose first line is
+/// line `first` of the document. Brackets may span lines and nest; quotes
+/// pair up within the cursor's line.
+pub fn find(lines: &[String], first: usize, cursor: (usize, usize), target: char) -> Option<Surrounding> {
+    let (open, close) = pair_of(target);
+    let row = cursor.0.checked_sub(first)?;
+    let line = lines.get(row)?;
+    let (open_at, close_at) = if open == close {
+        find_quotes(line, cursor.1, open).map(|(start, end)| ((row, start), (row, end)))?
+    } else {
+        find_brackets(lines, (row, cursor.1), open, close)?
+    };
+
+    let mut surrounding = Surrounding {
+        open: (open_at.0, open_at.1, open_at.1 + open.len_utf8()),
+        close: (close_at.0, close_at.1, close_at.1 + close.len_utf8()),
+    };
+    if is_padded(target) {
+        let after_open = &lines[open_at.0][surrounding.open.2..];
+        surrounding.open.2 += after_open.len() - after_open.trim_start_matches(' ').len();
+        let before_close = &lines[close_at.0][..surrounding.close.1];
+        surrounding.close.1 -= before_close.len() - before_close.trim_end_matches(' ').len();
+        if surrounding.open.0 == surrounding.close.0 && surrounding.close.1 < surrounding.open.2 {
+            surrounding.close.1 = surrounding.open.2; // Only spaces between
+        }
+    }
+    surrounding.open.0 += first;
+    surrounding.close.0 += first;
+    Some(surrounding)
+}
+
+/// The quotes around `column` on `line`: quotes pair up from the start of
+/// the line, and a cursor on a quote belongs to the pair that quote is in
+fn find_quotes(line: &str, column: usize, quote: char) -> Option<(usize, usize)> {
+    let quotes: Vec<usize> = line.match_indices(quote).map(|(index, _)| index).collect();
+    quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(start, end)| start <= column && column <= end)
+}
+
+/// The innermost open and close brackets around `cursor`; a cursor on
+/// either bracket means that pair
+fn find_brackets(lines: &[String], cursor: (usize, usize), open: char, close: char) -> Option<((usize, usize), (usize, usize))> {
+    let at_cursor = lines[cursor.0][cursor.1.min(lines[cursor.0].len())..].chars().next();
+
+    // Back to the unmatched open bracket
+    let start = if at_cursor == Some(open) {
+        cursor
+    } else {
+        let mut depth = 0;
+        let mut found = None;
+        'lines: for row in (0..=cursor.0).rev() {
+            let line = &lines[row];
+            let end = if row == cursor.0 { cursor.1.min(line.len()) } else { line.len() };
+            for (index, ch) in line[..end].char_indices().rev() {
+                if ch == close {
+                    depth += 1;
+                } else if ch == open {
+                    if depth == 0 {
+                        found = Some((row, index));
+                        break 'lines;
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+        found?
+    };
+
+    // Forward to the bracket that closes it
+    let mut depth = 0;
+    for (row, line) in lines.iter().enumerate().skip(start.0) {
+        let from = if row == start.0 { start.1 + open.len_utf8() } else { 0 };
+        for (index, ch) in line[from..].char_indices() {
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                if depth == 0 {
+                    return Some((start, (row, from + index)));
+                }
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_find_brackets_and_quotes() {
+        let text = lines("f(a, (b),\n  c)");
+        let found = find(&text, 0, (1, 2), ')').unwrap();
+        assert_eq!((found.open, found.close), ((0, 1, 2), (1, 3, 4)));
+        let inner = find(&text, 0, (0, 6), 'b').unwrap();
+        assert_eq!((inner.open, inner.close), ((0, 5, 6), (0, 7, 8)));
+        assert_eq!(find(&text, 0, (0, 0), '['), None);
+
+        let text = lines(r#"say "hi" and "bye""#);
+        let found = find(&text, 0, (0, 15), '"').unwrap();
+        assert_eq!((found.open, found.close), ((0, 13, 14), (0, 17, 18)));
+        assert_eq!(find(&text, 0, (0, 10), '\''), None);
+    }
+
+    #[test]
+    fn test_opening_bracket_takes_the_space_inside() {
+        let text = lines("x = ( 1 + 2 )");
+        let found = find(&text, 4, (4, 7), '(').unwrap(); // The text is line 4 on
+        assert_eq!((found.open, found.close), ((4, 4, 6), (4, 11, 13)));
+        assert_eq!(delimiters('('), ("( ".to_string(), " )".to_string()));
+        assert_eq!(delimiters('B'), ("{".to_string(), "}".to_string()));
+    }
+}
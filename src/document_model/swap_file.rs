@@ -0,0 +1,213 @@
+//! Swap files - a journal of a modified buffer's edits kept next to its file
+//! (`dir/.name.virus-swap`), so the text can be recovered after a crash
+//!
+//! A swap file starts with a snapshot of the buffer's text, followed by the
+//! piece table edits made since, as byte offsets into that text:
+//!
+//! ```text
+//! vi-rus swap 1
+//! pid 4242
+//! ending unix
+//! base 11
+//! hello world
+//! i 5 1
+//! ,
+//! d 0 1
+//! ```
+//!
+//! Text follows its record line, its length counted in bytes, then a newline.
+//! Replaying the edits on the snapshot gives the text at the last write; a
+//! record cut short by the crash is dropped. The file is removed when the
+//! buffer is saved or closed, but kept if the editor panics.
+
+use super::document::LineEnding;
+use super::file_lock::FileLock;
+use super::piece_table::PieceTable;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Format version written in the header
+const VERSION: u32 = 1;
+
+/// One edit to the piece table, as recorded in the journal
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalOp {
+    Insert { offset: usize, text: String },
+    Delete { offset: usize, length: usize },
+}
+
+/// What a swap file holds
+#[derive(Debug, PartialEq)]
+pub struct Recovered {
+    pub pid: u32,
+    pub line_ending: LineEnding,
+    pub text: String, // "\n" line breaks, as the text buffer keeps them
+}
+
+/// The swap file of one buffer, removed when the last clone of its document drops
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+}
+
+impl SwapFile {
+    /// Path of the swap file for `file` (`dir/.name.virus-swap`)
+    pub fn path_for(file: &Path) -> PathBuf {
+        FileLock::sibling_path(file, "virus-swap")
+    }
+
+    /// Start (or restart) the swap file for `file` with a snapshot of `text`
+    pub fn create(file: &Path, text: &str, line_ending: LineEnding) -> io::Result<Self> {
+        let path = Self::path_for(file);
+        let mut content =
+            format!("vi-rus swap {}\npid {}\nending {}\n", VERSION, std::process::id(), line_ending.name()).into_bytes();
+        content.extend(format!("base {}\n{}\n", text.len(), text).into_bytes());
+        fs::write(&path, content)?;
+        Ok(Self { path })
+    }
+
+    /// Add edits to the end of the journal
+    pub fn append(&self, ops: &[JournalOp]) -> io::Result<()> {
+        let mut records = Vec::new();
+        for op in ops {
+            match op {
+                JournalOp::Insert { offset, text } => {
+                    records.extend(format!("i {} {}\n{}\n", offset, text.len(), text).into_bytes())
+                }
+                JournalOp::Delete { offset, length } => records.extend(format!("d {} {}\n", offset, length).into_bytes()),
+            }
+        }
+        let mut file = fs::OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(&records)?;
+        file.sync_data()
+    }
+
+    /// Read the swap file of `file` and replay its journal
+    pub fn recover(file: &Path) -> io::Result<Recovered> {
+        let path = Self::path_for(file);
+        let bytes = fs::read(&path)?;
+        Self::parse(&bytes).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("\"{}\" is not a vi-rus swap file", path.display())))
+    }
+
+    /// The process that wrote `file`'s swap file, if there is a readable one
+    pub fn owner(file: &Path) -> Option<u32> {
+        let bytes = fs::read(Self::path_for(file)).ok()?;
+        let mut reader = Reader { bytes: &bytes, position: 0 };
+        reader.line().filter(|line| line.starts_with("vi-rus swap "))?;
+        reader.line()?.strip_prefix("pid ")?.parse().ok()
+    }
+
+    fn parse(bytes: &[u8]) -> Option<Recovered> {
+        let mut reader = Reader { bytes, position: 0 };
+        let version: u32 = reader.line()?.strip_prefix("vi-rus swap ")?.parse().ok()?;
+        if version > VERSION {
+            return None;
+        }
+        let pid = reader.line()?.strip_prefix("pid ")?.parse().ok()?;
+        let line_ending = LineEnding::from_name(reader.line()?.strip_prefix("ending ")?).unwrap_or(LineEnding::Unix);
+        let base_length = reader.line()?.strip_prefix("base ")?.parse().ok()?;
+        let mut table = PieceTable::from_string(reader.text(base_length)?);
+
+        // Replay until the end, or a record the crash cut short
+        while let Some(record) = reader.line() {
+            let mut fields = record.split(' ');
+            let (kind, offset, length) = (fields.next(), fields.next(), fields.next());
+            let (Some(offset), Some(length)) = (offset.and_then(|o| o.parse().ok()), length.and_then(|l| l.parse().ok())) else {
+                break;
+            };
+            match kind {
+                Some("i") => match reader.text(length) {
+                    Some(text) => table.insert(offset, &text),
+                    None => break,
+                },
+                Some("d") => table.delete(offset, length),
+                _ => break,
+            }
+        }
+        Some(Recovered { pid, line_ending, text: table.get_text() })
+    }
+}
+
+impl Drop for SwapFile {
+    fn drop(&mut self) {
+        // After a panic the journal is what's left of the edits
+        if !std::thread::panicking() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Walks the records of a swap file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// The next line, without its newline. None at the end or on a partial line.
+    fn line(&mut self) -> Option<&str> {
+        let rest = &self.bytes[self.position..];
+        let end = rest.iter().position(|&byte| byte == b'\n')?;
+        self.position += end + 1;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+
+    /// `length` bytes of text and the newline after them
+    fn text(&mut self, length: usize) -> Option<String> {
+        let end = self.position.checked_add(length)?;
+        if self.bytes.get(end) != Some(&b'\n') {
+            return None;
+        }
+        let text = String::from_utf8(self.bytes[self.position..end].to_vec()).ok()?;
+        self.position = end + 1;
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_journal_replays_on_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("notes.txt");
+        let swap = SwapFile::create(&file, "hello world\nline\n", LineEnding::Windows).unwrap();
+        assert_eq!(SwapFile::path_for(&file), dir.path().join(".notes.txt.virus-swap"));
+        swap.append(&[
+            JournalOp::Insert { offset: 5, text: ",\nnew".to_string() },
+            JournalOp::Delete { offset: 0, length: 1 },
+        ])
+        .unwrap();
+        swap.append(&[JournalOp::Insert { offset: 0, text: "H".to_string() }]).unwrap();
+
+        let recovered = SwapFile::recover(&file).unwrap();
+        assert_eq!(recovered.text, "Hello,\nnew world\nline\n");
+        assert_eq!(recovered.line_ending, LineEnding::Windows);
+        assert_eq!(recovered.pid, std::process::id());
+        assert_eq!(SwapFile::owner(&file), Some(std::process::id()));
+
+        drop(swap);
+        assert!(!SwapFile::path_for(&file).exists());
+        assert!(SwapFile::recover(&file).is_err());
+    }
+
+    #[test]
+    fn test_cut_short_record_is_dropped() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        let swap = SwapFile::create(&file, "abc", LineEnding::Unix).unwrap();
+        swap.append(&[JournalOp::Insert { offset: 3, text: "d".to_string() }]).unwrap();
+        let path = SwapFile::path_for(&file);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend(b"i 0 10\nparti");
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(SwapFile::recover(&file).unwrap().text, "abcd");
+        fs::write(&path, "not a swap file\n").unwrap();
+        assert!(SwapFile::recover(&file).is_err());
+        std::mem::forget(swap);
+    }
+}
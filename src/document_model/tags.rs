@@ -0,0 +1,228 @@
+//! Tags - the definitions a `tags` file made by ctags lists, looked up by
+//! Ctrl-], :tag and :tselect, and the stack of places they jumped from that
+//! Ctrl-T goes back through
+//!
+//! Each line of a tags file is `name<Tab>file<Tab>address`, optionally
+//! followed by `;"` and extra fields, the first of them the kind of the
+//! definition. The address is a line number or a search pattern:
+//!
+//! ```text
+//! !_TAG_FILE_SORTED    1    /0=unsorted, 1=sorted, 2=foldcase/
+//! main    src/main.rs    /^fn main() {$/;"    f
+//! MAX    src/lib.rs    12;"    c
+//! ```
+//!
+//! Files ctags marks as sorted are searched by binary search.
+
+use crate::document_model::Document;
+use std::path::{Path, PathBuf};
+
+/// How deep the tag stack goes before the oldest entries are dropped
+const MAX_TAG_STACK: usize = 20;
+
+/// Where in its file a tag is defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagAddress {
+    Line(usize),     // 0-based
+    Pattern(String), // The text of the line, as `/^...$/` gives it
+}
+
+/// One definition from a tags file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub path: PathBuf, // Relative to where the editor runs
+    pub address: TagAddress,
+    pub kind: String, // ctags' one-letter kind (f, s, c...), empty if not given
+}
+
+impl Tag {
+    /// Read one line of a tags file, with paths in it relative to `dir`
+    fn parse(line: &str, dir: &Path) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let name = fields.next().filter(|name| !name.is_empty())?;
+        let file = fields.next()?;
+        let rest = fields.next()?;
+        let (address, extra) = rest.split_once(";\"").unwrap_or((rest, ""));
+        let address = if let Ok(line) = address.parse::<usize>() {
+            TagAddress::Line(line.saturating_sub(1))
+        } else {
+            TagAddress::Pattern(Self::pattern_text(address)?)
+        };
+        let kind = extra.split('\t').find(|field| !field.is_empty()).unwrap_or_default();
+        let kind = kind.strip_prefix("kind:").unwrap_or(kind).to_string();
+        let path = if dir.as_os_str().is_empty() || dir == Path::new(".") { PathBuf::from(file) } else { dir.join(file) };
+        Some(Self { name: name.to_string(), path, address, kind })
+    }
+
+    /// The line text a `/^...$/` or `?^...$?` address searches for. ctags
+    /// escapes only the delimiter and backslashes, so the rest is literal.
+    fn pattern_text(address: &str) -> Option<String> {
+        let delimiter = address.chars().next().filter(|&c| c == '/' || c == '?')?;
+        let body = address[1..].strip_suffix(delimiter)?;
+        let body = body.strip_prefix('^').unwrap_or(body);
+        let mut text = String::new();
+        let mut chars = body.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => text.extend(chars.next()),
+                _ => text.push(ch),
+            }
+        }
+        // A trailing $ anchors the end, unless it was escaped
+        if body.ends_with('$') && !body.ends_with("\\$") {
+            text.pop();
+        }
+        Some(text)
+    }
+
+    /// The line of `doc` the tag points to: the first line the pattern
+    /// matches whole, or else the first that starts with it, as the file
+    /// may have changed since ctags ran
+    pub fn line_in(&self, doc: &Document) -> Option<usize> {
+        match &self.address {
+            TagAddress::Line(line) => Some((*line).min(doc.line_count().saturating_sub(1))),
+            TagAddress::Pattern(text) => {
+                let lines: Vec<String> = (0..doc.line_count()).filter_map(|line| doc.get_line(line)).collect();
+                lines
+                    .iter()
+                    .position(|line| line == text)
+                    .or_else(|| lines.iter().position(|line| line.starts_with(text.as_str())))
+            }
+        }
+    }
+}
+
+/// The lines of a tags file
+#[derive(Debug, Default)]
+pub struct TagFile {
+    lines: Vec<String>, // Without the !_TAG_ header lines
+    dir: PathBuf,       // Where the file is, which its paths are relative to
+    sorted: bool,
+}
+
+impl TagFile {
+    /// The tags file the editor uses for `current`, the current buffer's
+    /// file: the nearest `tags` in its directory or one above it, else the
+    /// one in the working directory
+    pub fn find(current: Option<&Path>) -> Option<PathBuf> {
+        let dirs = current.and_then(Path::parent).into_iter().flat_map(Path::ancestors);
+        dirs.chain(std::iter::once(Path::new("")))
+            .map(|dir| dir.join("tags"))
+            .find(|candidate| candidate.is_file())
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read(path)?;
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(Self::parse(&String::from_utf8_lossy(&text), dir))
+    }
+
+    pub fn parse(text: &str, dir: PathBuf) -> Self {
+        let sorted = text.lines().any(|line| line.starts_with("!_TAG_FILE_SORTED\t1"));
+        let lines = text.lines().filter(|line| !line.starts_with("!_TAG_")).map(str::to_string).collect();
+        Self { lines, dir, sorted }
+    }
+
+    /// The definitions of `name`, in the order the file lists them
+    pub fn lookup(&self, name: &str) -> Vec<Tag> {
+        fn name_of(line: &str) -> &str {
+            line.split('\t').next().unwrap_or_default()
+        }
+        let matches: &[String] = if self.sorted {
+            // Sorted by byte value of the names, so the matches are together
+            let start = self.lines.partition_point(|line| name_of(line) < name);
+            let count = self.lines[start..].iter().take_while(|line| name_of(line) == name).count();
+            &self.lines[start..start + count]
+        } else {
+            &self.lines
+        };
+        matches
+            .iter()
+            .filter(|line| name_of(line) == name)
+            .filter_map(|line| Tag::parse(line, &self.dir))
+            .collect()
+    }
+}
+
+/// One jump made by a tag command: the tag, and where the cursor was
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagStackEntry {
+    pub name: String,
+    pub path: Option<PathBuf>, // None for a buffer with no file
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The places tag jumps left, newest last
+#[derive(Debug, Default)]
+pub struct TagStack {
+    entries: Vec<TagStackEntry>,
+}
+
+impl TagStack {
+    pub fn push(&mut self, entry: TagStackEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_TAG_STACK {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Take the newest `count` entries off the stack, giving the oldest of
+    /// them: where Ctrl-T with a count goes back to
+    pub fn pop(&mut self, count: usize) -> Result<TagStackEntry, String> {
+        if self.entries.is_empty() {
+            return Err("At bottom of tag stack".to_string());
+        }
+        let keep = self.entries.len().saturating_sub(count.max(1));
+        Ok(self.entries.drain(keep..).next().expect("at least one entry is drained"))
+    }
+
+    /// The name of the newest tag jumped to
+    pub fn last_name(&self) -> Option<&str> {
+        self.entries.last().map(|entry| entry.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_addresses() {
+        let text = "!_TAG_FILE_SORTED\t1\t/0=unsorted/\n\
+                    Alpha\ta.rs\t3;\"\ts\n\
+                    main\tsrc/main.rs\t/^fn main() {$/;\"\tf\n\
+                    main\tsrc/other.rs\t/^pub fn main(a\\/b: \\\\x)$/;\"\tkind:function\n\
+                    zed\tz.rs\t?^let zed$?\n";
+        let tags = TagFile::parse(text, PathBuf::from("proj"));
+        let found = tags.lookup("main");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].path, PathBuf::from("proj/src/main.rs"));
+        assert_eq!(found[0].address, TagAddress::Pattern("fn main() {".to_string()));
+        assert_eq!(found[1].address, TagAddress::Pattern("pub fn main(a/b: \\x)".to_string()));
+        assert_eq!((found[0].kind.as_str(), found[1].kind.as_str()), ("f", "function"));
+        assert_eq!(tags.lookup("Alpha")[0].address, TagAddress::Line(2));
+        assert_eq!(tags.lookup("zed")[0].kind, "");
+        assert!(tags.lookup("mai").is_empty());
+
+        let doc = Document::from_string("use x;\nfn main() {}\nfn main() {\n}".to_string());
+        assert_eq!(found[0].line_in(&doc), Some(2));
+        let moved = Tag { address: TagAddress::Pattern("fn main(".to_string()), ..found[0].clone() };
+        assert_eq!(moved.line_in(&doc), Some(1));
+        assert_eq!(found[1].line_in(&doc), None);
+    }
+
+    #[test]
+    fn test_tag_stack() {
+        let mut stack = TagStack::default();
+        assert!(stack.pop(1).is_err());
+        for line in 0..3 {
+            stack.push(TagStackEntry { name: format!("t{}", line), path: None, line, column: 0 });
+        }
+        assert_eq!(stack.last_name(), Some("t2"));
+        assert_eq!(stack.pop(2).map(|entry| entry.line), Ok(1));
+        assert_eq!(stack.pop(5).map(|entry| entry.line), Ok(0));
+        assert_eq!(stack.last_name(), None);
+    }
+}
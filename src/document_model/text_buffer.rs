@@ -1,5 +1,19 @@
 use super::document::LineEnding;
+use super::loader::{FileLoader, LoadEnd};
 use super::piece_table::PieceTable;
+use super::swap_file::JournalOp;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Revision numbers are drawn from one counter for every buffer, so two
+/// different contents never share a revision
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Position {
@@ -25,9 +39,112 @@ impl Range {
     }
 }
 
+// Columns are byte offsets into a line. Cursor movement and single-character
+// edits step over whole grapheme clusters, so the cursor never lands inside
+// a multi-byte character, a letter with combining accents or an emoji sequence.
+
+/// Start of the grapheme cluster containing byte `column` of `line`, or
+/// the line length for columns at or past its end
+pub fn grapheme_start(line: &str, column: usize) -> usize {
+    if column >= line.len() {
+        return line.len();
+    }
+    line.grapheme_indices(true)
+        .map(|(start, _)| start)
+        .take_while(|&start| start <= column)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Start of the cluster after the one at `column` (the line length at its end)
+pub fn next_grapheme(line: &str, column: usize) -> usize {
+    line.grapheme_indices(true)
+        .map(|(start, grapheme)| start + grapheme.len())
+        .find(|&end| end > column)
+        .unwrap_or(line.len())
+}
+
+/// Start of the cluster before the one at `column` (0 at the line start)
+pub fn prev_grapheme(line: &str, column: usize) -> usize {
+    line.grapheme_indices(true)
+        .map(|(start, _)| start)
+        .take_while(|&start| start < column)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Terminal cells a grapheme cluster takes: 2 for wide characters (CJK,
+/// emoji), otherwise 1. Tabs are left to the caller.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().clamp(1, 2)
+}
+
+/// Terminal cells a grapheme starting at screen column `at` takes: a tab
+/// reaches the next multiple of `tab_stop`
+pub fn cells_at(grapheme: &str, at: usize, tab_stop: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_stop = tab_stop.max(1);
+        tab_stop - at % tab_stop
+    } else {
+        grapheme_width(grapheme)
+    }
+}
+
+/// Screen column where byte `column` of `line` is drawn
+pub fn display_column(line: &str, column: usize, tab_stop: usize) -> usize {
+    line.grapheme_indices(true)
+        .take_while(|&(start, _)| start < column)
+        .fold(0, |at, (_, grapheme)| at + cells_at(grapheme, at, tab_stop))
+}
+
+/// The grapheme drawn over screen column `target`: its byte column and
+/// the screen column it starts at, which is before `target` for a tab or
+/// wide character that straddles it. Past the end of the line: the line
+/// length and the line's width.
+pub fn grapheme_at_display_column(line: &str, target: usize, tab_stop: usize) -> (usize, usize) {
+    let mut at = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        let next = at + cells_at(grapheme, at, tab_stop);
+        if next > target {
+            return (start, at);
+        }
+        at = next;
+    }
+    (line.len(), at)
+}
+
+/// The lines one edit touched, numbered as they were before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineChange {
+    pub first: usize,
+    pub last: usize,
+    pub shifted: bool, // Lines were added or removed, so every line after `first` moved
+}
+
+impl LineChange {
+    /// Whether line `line`, numbered as it is now, may read differently
+    pub fn touches(&self, line: usize) -> bool {
+        line >= self.first && (self.shifted || line <= self.last)
+    }
+}
+
+/// How many edits the buffer remembers the lines of, for redrawing
+const EDIT_LOG_LEN: usize = 64;
+
 pub struct TextBuffer {
     piece_table: PieceTable,
     line_ending: LineEnding,
+    revision: u64, // Changes whenever the text does
+    journal: Option<Vec<JournalOp>>, // Edits not yet written to the swap file, while one is kept
+    edit_log: VecDeque<(u64, LineChange)>, // The revision each recent edit started from, and its lines
+    loader: Option<FileLoader>, // Reads the rest of a file opened before all of it was read
+    load_end: Option<LoadEnd>,  // How that load ended, until the document takes it
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TextBuffer {
@@ -35,6 +152,11 @@ impl TextBuffer {
         Self {
             piece_table: PieceTable::new(),
             line_ending: LineEnding::system_default(),
+            revision: next_revision(),
+            journal: None,
+            edit_log: VecDeque::new(),
+            loader: None,
+            load_end: None,
         }
     }
 
@@ -45,31 +167,49 @@ impl TextBuffer {
         Self {
             piece_table: PieceTable::from_string(normalized),
             line_ending,
+            revision: next_revision(),
+            journal: None,
+            edit_log: VecDeque::new(),
+            loader: None,
+            load_end: None,
         }
     }
 
 
     pub fn insert(&mut self, pos: Position, text: &str) {
+        self.receive_loaded(true);
         let offset = self.piece_table.position_to_offset(pos.line, pos.column);
         self.piece_table.insert(offset, text);
+        self.record(JournalOp::Insert { offset, text: text.to_string() });
+        let breaks = text.matches('\n').count();
+        self.new_revision(LineChange { first: pos.line, last: pos.line + breaks, shifted: breaks > 0 });
     }
 
     pub fn delete(&mut self, range: Range) {
+        self.receive_loaded(true);
         let start_offset = self.piece_table.position_to_offset(range.start.line, range.start.column);
         let end_offset = self.piece_table.position_to_offset(range.end.line, range.end.column);
         let length = end_offset - start_offset;
         self.piece_table.delete(start_offset, length);
+        self.record(JournalOp::Delete { offset: start_offset, length });
+        let (first, last) = (range.start.line, range.end.line.max(range.start.line));
+        self.new_revision(LineChange { first, last, shifted: last > first });
     }
 
     pub fn delete_char(&mut self, pos: Position) {
+        self.receive_loaded(true);
         let offset = self.piece_table.position_to_offset(pos.line, pos.column);
+        let line_break = self.piece_table.substring(offset, offset + 1) == "\n";
         self.piece_table.delete(offset, 1);
+        self.record(JournalOp::Delete { offset, length: 1 });
+        self.new_revision(LineChange { first: pos.line, last: pos.line + usize::from(line_break), shifted: line_break });
     }
 
     pub fn insert_newline(&mut self, pos: Position) {
         self.insert(pos, "\n");
     }
 
+    #[cfg(test)]
     pub fn get_text(&self) -> String {
         let text = self.piece_table.get_text();
         match self.line_ending {
@@ -79,29 +219,34 @@ impl TextBuffer {
         }
     }
 
-    pub fn get_text_range(&mut self, range: Range) -> String {
+    /// Buffer contents with "\n" line breaks whatever the file's line ending
+    pub fn get_text_normalized(&self) -> String {
+        self.piece_table.get_text()
+    }
+
+    pub fn get_text_range(&self, range: Range) -> String {
         let start_offset = self.piece_table.position_to_offset(range.start.line, range.start.column);
         let end_offset = self.piece_table.position_to_offset(range.end.line, range.end.column);
         self.piece_table.substring(start_offset, end_offset)
     }
 
-    pub fn get_line(&mut self, line_number: usize) -> Option<String> {
+    pub fn get_line(&self, line_number: usize) -> Option<String> {
         self.piece_table.get_line_fast(line_number)
     }
 
-    pub fn get_lines(&mut self) -> Vec<String> {
+    pub fn get_lines(&self) -> Vec<String> {
         (0..self.line_count())
             .filter_map(|i| self.get_line(i))
             .collect()
     }
 
 
-    pub fn line_count(&mut self) -> usize {
+    pub fn line_count(&self) -> usize {
         self.piece_table.line_count()
     }
 
-    pub fn line_length(&mut self, line_number: usize) -> usize {
-        self.get_line(line_number).map_or(0, |line| line.len())
+    pub fn line_length(&self, line_number: usize) -> usize {
+        self.piece_table.line_length(line_number)
     }
 
     #[cfg(test)]
@@ -109,13 +254,11 @@ impl TextBuffer {
         self.piece_table.is_empty()
     }
 
-
-    pub fn char_at(&mut self, pos: Position) -> Option<char> {
-        let offset = self.piece_table.position_to_offset(pos.line, pos.column);
-        self.piece_table.char_at(offset)
+    /// Identifies the current text: any edit gives a new revision
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
-
     pub fn set_line_ending(&mut self, line_ending: LineEnding) {
         self.line_ending = line_ending;
     }
@@ -127,24 +270,145 @@ impl TextBuffer {
     }
 
     #[cfg(test)]
-    pub fn offset_to_position(&mut self, offset: usize) -> Position {
+    pub fn offset_to_position(&self, offset: usize) -> Position {
         let (line, column) = self.piece_table.offset_to_position(offset);
         Position::new(line, column)
     }
 
-    pub fn position_to_offset(&mut self, pos: Position) -> usize {
+    pub fn position_to_offset(&self, pos: Position) -> usize {
         self.piece_table.position_to_offset(pos.line, pos.column)
     }
 
 
 
     pub fn replace(&mut self, range: Range, replacement: &str) {
+        self.receive_loaded(true);
         let start_offset = self.position_to_offset(range.start);
         let end_offset = self.position_to_offset(range.end);
         let length = end_offset - start_offset;
         
         self.piece_table.delete(start_offset, length);
         self.piece_table.insert(start_offset, replacement);
+        self.record(JournalOp::Delete { offset: start_offset, length });
+        self.record(JournalOp::Insert { offset: start_offset, text: replacement.to_string() });
+        let (first, removed) = (range.start.line, range.end.line.saturating_sub(range.start.line));
+        let added = replacement.matches('\n').count();
+        self.new_revision(LineChange { first, last: first + removed.max(added), shifted: removed != added });
+    }
+
+    /// Go on to fill the buffer from `loader` as it reads the rest of the file
+    pub fn load_in_background(&mut self, loader: FileLoader) {
+        self.piece_table.reserve_original(usize::try_from(loader.size()).unwrap_or(0));
+        self.loader = Some(loader);
+    }
+
+    /// How much of the file still loading has been read, as a percentage
+    pub fn load_progress(&self) -> Option<u8> {
+        self.loader.as_ref().map(FileLoader::progress)
+    }
+
+    /// Add what the loader has read to the end of the buffer; with `wait`,
+    /// all of the file. Returns how the load ended, once, when it has.
+    pub fn take_loaded(&mut self, wait: bool) -> Option<LoadEnd> {
+        self.receive_loaded(wait);
+        self.load_end.take()
+    }
+
+    /// Edits wait for the rest of a file still loading, so the text read
+    /// later can't end up anywhere but the end of the file
+    fn receive_loaded(&mut self, wait: bool) {
+        let Some(loader) = &mut self.loader else {
+            return;
+        };
+        let received = loader.receive(wait);
+        if received.end.is_some() {
+            self.loader = None;
+            self.load_end = received.end;
+        }
+        let (length, last) = (self.piece_table.len(), self.line_count() - 1);
+        #[cfg(feature = "mmap")]
+        if let Some(len) = received.mapped {
+            self.piece_table.extend_mapped(len);
+        }
+        self.piece_table.append_original(&received.text);
+        if self.piece_table.len() != length {
+            let breaks = self.line_count() - 1 - last;
+            self.new_revision(LineChange { first: last, last: last + breaks, shifted: breaks > 0 });
+        }
+    }
+
+    /// With the `mmap` feature, a buffer of the first `len` bytes of a
+    /// mapped file, more of which arrives through `load_in_background`
+    #[cfg(feature = "mmap")]
+    pub fn from_mapped(file: std::sync::Arc<super::loader::MappedFile>, len: usize) -> Self {
+        Self { piece_table: PieceTable::from_mapped(file, len), line_ending: LineEnding::Unix, ..Self::new() }
+    }
+
+    /// Copy the text into memory if it is `path` mapped, before `path` is
+    /// written over
+    #[cfg(feature = "mmap")]
+    pub fn unmap_from(&mut self, path: &std::path::Path) {
+        if self.is_mapped_from(path) {
+            self.receive_loaded(true);
+            self.piece_table.unmap();
+        }
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub fn unmap_from(&mut self, _path: &std::path::Path) {}
+
+    /// True when the text is `path` mapped into memory
+    #[cfg(feature = "mmap")]
+    pub fn is_mapped_from(&self, path: &std::path::Path) -> bool {
+        self.piece_table.is_mapped_from(path)
+    }
+
+    /// Move to a new revision after an edit to `change`'s lines
+    fn new_revision(&mut self, change: LineChange) {
+        if self.edit_log.len() == EDIT_LOG_LEN {
+            self.edit_log.pop_front();
+        }
+        self.edit_log.push_back((self.revision, change));
+        self.revision = next_revision();
+    }
+
+    /// The lines each edit since `revision` touched, oldest first; None
+    /// when the buffer can't tell (`revision` is too old, or another text's)
+    pub fn changes_since(&self, revision: u64) -> Option<Vec<LineChange>> {
+        if revision == self.revision {
+            return Some(Vec::new());
+        }
+        let start = self.edit_log.iter().position(|&(before, _)| before == revision)?;
+        Some(self.edit_log.iter().skip(start).map(|&(_, change)| change).collect())
+    }
+
+    /// Start recording edits for the swap file, dropping any not yet taken
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    pub fn stop_journal(&mut self) {
+        self.journal = None;
+    }
+
+    pub fn is_journaling(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Edits recorded since the last take
+    pub fn pending_journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Hand over the recorded edits, leaving the journal running
+    pub fn take_journal(&mut self) -> Vec<JournalOp> {
+        self.journal.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn record(&mut self, op: JournalOp) {
+        if let Some(journal) = &mut self.journal {
+            journal.push(op);
+        }
     }
 
 }
@@ -154,6 +418,11 @@ impl Clone for TextBuffer {
         Self {
             piece_table: self.piece_table.clone(),
             line_ending: self.line_ending,
+            revision: self.revision,
+            journal: None, // A copy has no swap file of its own
+            edit_log: self.edit_log.clone(),
+            loader: None, // Nor the rest of a file still loading: that goes to the original
+            load_end: None,
         }
     }
 }
@@ -162,9 +431,22 @@ impl Clone for TextBuffer {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_display_columns() {
+        let line = "a\tb世c";
+        assert_eq!(display_column(line, 1, 4), 1);
+        assert_eq!(display_column(line, 2, 4), 4); // After the tab
+        assert_eq!(display_column(line, 6, 4), 7); // After the wide character
+        assert_eq!(display_column(line, line.len(), 4), 8);
+        assert_eq!(grapheme_at_display_column(line, 2, 4), (1, 1)); // Inside the tab
+        assert_eq!(grapheme_at_display_column(line, 6, 4), (3, 5)); // Second cell of 世
+        assert_eq!(grapheme_at_display_column(line, 7, 4), (6, 7));
+        assert_eq!(grapheme_at_display_column(line, 20, 4), (7, 8));
+    }
+
     #[test]
     fn test_new_buffer() {
-        let mut buffer = TextBuffer::new();
+        let buffer = TextBuffer::new();
         assert!(buffer.is_empty());
         assert_eq!(buffer.line_count(), 1);
     }
@@ -193,9 +475,28 @@ mod tests {
         assert_eq!(buffer.line_count(), 4);
     }
 
+    #[test]
+    fn test_changes_since() {
+        let mut buffer = TextBuffer::from_string("a\nb\nc\nd".to_string());
+        let start = buffer.revision();
+        assert_eq!(buffer.changes_since(start), Some(vec![]));
+
+        buffer.insert(Position::new(1, 1), "x");
+        buffer.delete_char(Position::new(2, 1)); // The line break after c
+        let changes = buffer.changes_since(start).unwrap();
+        assert_eq!(changes, [
+            LineChange { first: 1, last: 1, shifted: false },
+            LineChange { first: 2, last: 3, shifted: true },
+        ]);
+        assert!(!changes.iter().any(|change| change.touches(0)));
+        assert!(changes.iter().any(|change| change.touches(7)));
+        assert_eq!(buffer.changes_since(0), None);
+        assert_eq!(TextBuffer::new().changes_since(start), None);
+    }
+
     #[test]
     fn test_position_conversions() {
-        let mut buffer = TextBuffer::from_string("Hello\nWorld\nTest".to_string());
+        let buffer = TextBuffer::from_string("Hello\nWorld\nTest".to_string());
         
         let pos = Position::new(1, 3);
         let offset = buffer.position_to_offset(pos);
@@ -209,7 +510,7 @@ mod tests {
     #[test]
     fn test_from_lines_compatibility() {
         let lines = vec!["Hello".to_string(), "World".to_string()];
-        let mut buffer = TextBuffer::from_lines(lines.clone());
+        let buffer = TextBuffer::from_lines(lines.clone());
         
         assert_eq!(buffer.line_count(), 2);
         assert_eq!(buffer.get_line(0), Some("Hello".to_string()));
@@ -218,4 +519,63 @@ mod tests {
         let back_to_lines = buffer.get_lines();
         assert_eq!(back_to_lines, lines);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_revision_changes_with_text() {
+        let mut buffer = TextBuffer::from_string("abc".to_string());
+        let first = buffer.revision();
+        assert_eq!(buffer.clone().revision(), first);
+
+        buffer.insert(Position::new(0, 1), "x");
+        let second = buffer.revision();
+        assert_ne!(second, first);
+        buffer.delete_char(Position::new(0, 1));
+        assert_ne!(buffer.revision(), second);
+        assert_ne!(TextBuffer::from_string("abc".to_string()).revision(), first);
+    }
+
+    #[test]
+    fn test_journal_records_edits() {
+        let mut buffer = TextBuffer::from_string("abc\ndef".to_string());
+        buffer.insert(Position::new(0, 1), "x");
+        assert_eq!(buffer.pending_journal_len(), 0);
+
+        buffer.start_journal();
+        buffer.insert(Position::new(1, 0), "y");
+        buffer.delete_char(Position::new(0, 0));
+        buffer.replace(Range::new(Position::new(0, 0), Position::new(0, 2)), "Z");
+        assert_eq!(
+            buffer.take_journal(),
+            vec![
+                JournalOp::Insert { offset: 5, text: "y".to_string() },
+                JournalOp::Delete { offset: 0, length: 1 },
+                JournalOp::Delete { offset: 0, length: 2 },
+                JournalOp::Insert { offset: 0, text: "Z".to_string() },
+            ]
+        );
+        assert!(buffer.is_journaling());
+        assert_eq!(buffer.pending_journal_len(), 0);
+        assert!(!buffer.clone().is_journaling());
+    }
+
+    #[test]
+    fn test_grapheme_steps() {
+        // "e" + combining acute, a wide CJK character, then a ZWJ family emoji
+        let line = "ae\u{301}漢👨\u{200d}👩\u{200d}👧z";
+        let family_end = line.len() - 1;
+        assert_eq!(next_grapheme(line, 0), 1);
+        assert_eq!(next_grapheme(line, 1), 4);
+        assert_eq!(next_grapheme(line, 4), 7);
+        assert_eq!(next_grapheme(line, 7), family_end);
+        assert_eq!(next_grapheme(line, family_end), line.len());
+        assert_eq!(prev_grapheme(line, family_end), 7);
+        assert_eq!(prev_grapheme(line, 7), 4);
+        assert_eq!(prev_grapheme(line, 0), 0);
+        assert_eq!(grapheme_start(line, 2), 1);
+        assert_eq!(grapheme_start(line, 9), 7);
+
+        assert_eq!(grapheme_width("e\u{301}"), 1);
+        assert_eq!(grapheme_width("漢"), 2);
+        assert_eq!(grapheme_width(&line[7..family_end]), 2);
+    }
+}
@@ -1,3 +1,8 @@
+use std::time::{Duration, Instant};
+
+/// One edit, kept as the text it inserted or deleted at a position: undo
+/// applies its reverse. Text may run over lines, so splitting a line is an
+/// insert of "\n" and joining two is its delete.
 #[derive(Debug, Clone)]
 pub enum UndoAction {
     InsertText {
@@ -18,16 +23,6 @@ pub enum UndoAction {
         line: usize,
         text: String,
     },
-    SplitLine {
-        line: usize,
-        column: usize,
-        text: String, // The text that was moved to the new line
-    },
-    JoinLines {
-        line: usize,
-        separator: String,        // What was used to join (space, nothing, etc.)
-        second_line_text: String, // The text from the second line
-    },
 }
 
 impl UndoAction {
@@ -35,11 +30,17 @@ impl UndoAction {
     pub fn apply_to_document(&self, document: &mut super::document::Document) {
         match self {
             UndoAction::InsertText { line, column, text } => {
-                // Make sure the line exists
+                // Make sure the line exists; line breaks in the text split it
                 if *line < document.line_count() {
                     document.insert_text_at(*line, *column, text);
                 }
             }
+            UndoAction::DeleteText { line, column, text } if text.contains('\n') => {
+                // A split line or multi-line insert being undone, or a join redone
+                if *line < document.line_count() {
+                    document.delete_text_spanning(*line, *column, text);
+                }
+            }
             UndoAction::DeleteText { line, column, text } => {
                 // Make sure the line exists and has enough content
                 if *line < document.line_count() {
@@ -60,17 +61,15 @@ impl UndoAction {
                     document.delete_line_at(*line);
                 }
             }
-            UndoAction::SplitLine { line, column, text } => {
-                if *line < document.line_count() {
-                    document.split_line_at(*line, *column, text);
-                }
-            }
-            UndoAction::JoinLines { line, separator, .. } => {
-                // Join the line at `line` with the line at `line + 1`
-                if *line < document.line_count().saturating_sub(1) {
-                    document.join_lines_at(*line, separator);
-                }
-            }
+        }
+    }
+
+    /// Where in the text the action took place
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            UndoAction::InsertText { line, column, .. }
+            | UndoAction::DeleteText { line, column, .. } => (*line, *column),
+            UndoAction::InsertLine { line, .. } | UndoAction::DeleteLine { line, .. } => (*line, 0),
         }
     }
 
@@ -94,24 +93,6 @@ impl UndoAction {
                 line: *line,
                 text: text.clone(),
             },
-            UndoAction::SplitLine {
-                line,
-                column: _,
-                text,
-            } => UndoAction::JoinLines {
-                line: *line,
-                separator: String::new(),
-                second_line_text: text.clone(),
-            },
-            UndoAction::JoinLines {
-                line,
-                separator: _,
-                second_line_text,
-            } => UndoAction::SplitLine {
-                line: *line,
-                column: 0, // Will be recalculated based on the line content
-                text: second_line_text.clone(),
-            },
         }
     }
 }
@@ -166,33 +147,101 @@ impl UndoGroup {
     }
 }
 
+/// One state in the undo tree: the change that produced it from its parent
+#[derive(Debug, Clone)]
+struct UndoNode {
+    group: UndoGroup, // Empty for the root (the original text)
+    number: usize,    // The state's number, kept when older states are dropped
+    parent: usize,
+    redo_child: Option<usize>, // Branch that redo follows: the most recently visited
+    time: Instant,
+}
+
+/// A step taken while moving between undo states
+#[derive(Debug, Clone)]
+pub enum UndoStep {
+    Undo(UndoGroup),
+    Redo(UndoGroup),
+}
+
+/// The tip of an undo branch, as listed by :undolist
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoLeaf {
+    pub state: usize,
+    pub changes: usize, // Changes between the original text and this state
+    pub age: Duration,
+}
+
+/// Most positions the change list keeps, as in vim
+const CHANGE_LIST_SIZE: usize = 100;
+
+/// Changes kept for undo when 'undolevels' isn't set, as in vim
+pub const DEFAULT_UNDO_LEVELS: usize = 1000;
+
+/// Undo history kept as a tree: undoing and then making a new change starts
+/// a new branch instead of discarding the undone one. States are numbered in
+/// the order they were created; state 0 is the original text.
+///
+/// Each new change also records where it happened in the change list that
+/// g; and g, step through; the newest entry is the '. mark.
 #[derive(Clone)]
 pub struct UndoManager {
-    undo_stack: Vec<UndoGroup>,
-    redo_stack: Vec<UndoGroup>,
+    nodes: Vec<UndoNode>,
+    current: usize, // State the document is in
     current_group: Option<UndoGroup>,
-    max_undo_levels: usize,
+    batch_depth: usize, // While > 0, start_group/end_group are folded into the batch's group
+    changes: Vec<(usize, usize)>, // Change positions, oldest first
+    change_index: usize,          // Entry g; and g, stepped to; changes.len() when not stepping
 }
 
 impl UndoManager {
     pub fn new() -> Self {
         Self {
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            nodes: vec![UndoNode {
+                group: UndoGroup::new((0, 0)),
+                number: 0,
+                parent: 0,
+                redo_child: None,
+                time: Instant::now(),
+            }],
+            current: 0,
             current_group: None,
-            max_undo_levels: 1000,
+            batch_depth: 0,
+            changes: Vec::new(),
+            change_index: 0,
+        }
+    }
+
+    /// Begin a compound change (e.g. :g) that undoes as a single step.
+    /// Groups started by the individual commands inside the batch are merged into it.
+    pub fn begin_batch(&mut self, cursor_pos: (usize, usize)) {
+        if self.batch_depth == 0 {
+            self.start_group(cursor_pos);
+        }
+        self.batch_depth += 1;
+    }
+
+    pub fn end_batch(&mut self, cursor_pos: (usize, usize)) {
+        self.batch_depth = self.batch_depth.saturating_sub(1);
+        if self.batch_depth == 0 {
+            self.end_group(cursor_pos);
         }
     }
 
     pub fn start_group(&mut self, cursor_pos: (usize, usize)) {
-        if let Some(group) = self.current_group.take() {
-            if !group.is_empty() {
-                self.push_undo_group(group);
-            }
+        if self.batch_depth > 0 {
+            return;
         }
+        self.flush_current_group();
         self.current_group = Some(UndoGroup::new(cursor_pos));
     }
 
+    /// Where the cursor was when the open group started: for an insert,
+    /// where the typing began
+    pub fn group_start(&self) -> Option<(usize, usize)> {
+        self.current_group.as_ref().map(|group| group.cursor_before)
+    }
+
     pub fn add_action(&mut self, action: UndoAction) {
         if let Some(ref mut group) = self.current_group {
             group.add_action(action);
@@ -205,66 +254,268 @@ impl UndoManager {
     }
 
     pub fn end_group(&mut self, cursor_pos: (usize, usize)) {
-        if let Some(mut group) = self.current_group.take() {
-            if !group.is_empty() {
-                group.set_cursor_after(cursor_pos);
-                self.push_undo_group(group);
-            }
+        if self.batch_depth > 0 {
+            return;
+        }
+        if let Some(mut group) = self.current_group.take()
+            && !group.is_empty()
+        {
+            group.set_cursor_after(cursor_pos);
+            self.push_undo_group(group, Instant::now());
         }
     }
 
-    fn push_undo_group(&mut self, group: UndoGroup) {
-        self.undo_stack.push(group);
-
-        // Limit the undo stack size
-        if self.undo_stack.len() > self.max_undo_levels {
-            self.undo_stack.remove(0);
+    fn flush_current_group(&mut self) {
+        if let Some(group) = self.current_group.take()
+            && !group.is_empty()
+        {
+            self.push_undo_group(group, Instant::now());
         }
+    }
 
-        // Clear redo stack when new actions are performed
-        self.redo_stack.clear();
+    /// Add a new state as a child of the current one
+    fn push_undo_group(&mut self, group: UndoGroup, time: Instant) {
+        if let Some(action) = group.actions.last() {
+            self.record_change(action.position());
+        }
+        let id = self.nodes.len();
+        self.nodes.push(UndoNode {
+            group,
+            number: self.last_state() + 1,
+            parent: self.current,
+            redo_child: None,
+            time,
+        });
+        self.nodes[self.current].redo_child = Some(id);
+        self.current = id;
     }
 
     #[allow(dead_code)]
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+        self.current != 0
     }
 
     #[allow(dead_code)]
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.nodes[self.current].redo_child.is_some()
     }
 
     pub fn undo(&mut self) -> Option<UndoGroup> {
         // First, finish any current group
-        if let Some(group) = self.current_group.take() {
-            if !group.is_empty() {
-                self.push_undo_group(group);
+        self.flush_current_group();
+
+        if self.current == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.current];
+        let (group, parent) = (node.group.clone(), node.parent);
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(group)
+    }
+
+    pub fn redo(&mut self) -> Option<UndoGroup> {
+        let child = self.nodes[self.current].redo_child?;
+        self.current = child;
+        Some(self.nodes[child].group.clone())
+    }
+
+    /// The state the document is in (0 = original text)
+    pub fn current_state(&self) -> usize {
+        self.nodes[self.current].number
+    }
+
+    /// The most recently created state
+    pub fn last_state(&self) -> usize {
+        self.nodes[self.nodes.len() - 1].number
+    }
+
+    /// The newest state created at least `age` before the current one (:earlier 10s)
+    pub fn state_before(&self, age: Duration) -> usize {
+        let current_time = self.nodes[self.current].time;
+        let index = current_time
+            .checked_sub(age)
+            .and_then(|limit| self.nodes.iter().rposition(|node| node.time <= limit))
+            .unwrap_or(0);
+        self.nodes[index].number
+    }
+
+    /// The newest state created no more than `age` after the current one (:later 10s)
+    pub fn state_after(&self, age: Duration) -> usize {
+        let limit = self.nodes[self.current].time + age;
+        let index = self.nodes.iter().rposition(|node| node.time <= limit).unwrap_or(0);
+        self.nodes[index.max(self.current)].number
+    }
+
+    /// Move to state `target`, undoing up to the common ancestor and redoing
+    /// down the target's branch; a state that was dropped goes to the newest
+    /// kept before it. Returns the steps to apply, in order.
+    pub fn travel_to(&mut self, target: usize) -> Vec<UndoStep> {
+        self.flush_current_group();
+        let target = self.nodes.iter().rposition(|node| node.number <= target).unwrap_or(0);
+
+        let ancestors = |mut id: usize| {
+            let mut path = vec![id];
+            while id != 0 {
+                id = self.nodes[id].parent;
+                path.push(id);
             }
+            path
+        };
+        let from_path = ancestors(self.current);
+        let to_path = ancestors(target);
+        let common = *from_path
+            .iter()
+            .find(|id| to_path.contains(id))
+            .expect("every state descends from the root");
+
+        let mut steps: Vec<UndoStep> = from_path
+            .iter()
+            .take_while(|id| **id != common)
+            .map(|id| UndoStep::Undo(self.nodes[*id].group.clone()))
+            .collect();
+        let down: Vec<usize> = to_path.iter().take_while(|id| **id != common).copied().collect();
+        for id in down.iter().rev() {
+            let parent = self.nodes[*id].parent;
+            self.nodes[parent].redo_child = Some(*id);
+            steps.push(UndoStep::Redo(self.nodes[*id].group.clone()));
         }
 
-        if let Some(group) = self.undo_stack.pop() {
-            self.redo_stack.push(group.clone());
-            Some(group)
-        } else {
-            None
+        self.current = target;
+        steps
+    }
+
+    /// The tip of every branch, oldest first
+    pub fn leaves(&self) -> Vec<UndoLeaf> {
+        let now = Instant::now();
+        let mut has_children = vec![false; self.nodes.len()];
+        for node in self.nodes.iter().skip(1) {
+            has_children[node.parent] = true;
         }
+        (1..self.nodes.len())
+            .filter(|id| !has_children[*id])
+            .map(|id| {
+                let mut changes = 0;
+                let mut node = id;
+                while node != 0 {
+                    changes += 1;
+                    node = self.nodes[node].parent;
+                }
+                UndoLeaf {
+                    state: self.nodes[id].number,
+                    changes,
+                    age: now.saturating_duration_since(self.nodes[id].time),
+                }
+            })
+            .collect()
     }
 
-    pub fn redo(&mut self) -> Option<UndoGroup> {
-        if let Some(group) = self.redo_stack.pop() {
-            self.undo_stack.push(group.clone());
-            Some(group)
-        } else {
-            None
+    /// 'undolevels': drop the oldest states until at most `levels` changes
+    /// are kept. A dropped state on the way to the current one leaves the
+    /// next one as the text undo goes back to, and the branches that grew
+    /// from it go too. An open group isn't touched.
+    pub fn limit(&mut self, levels: usize) {
+        if self.nodes.len() - 1 <= levels {
+            return;
+        }
+        let count = self.nodes.len();
+        let mut on_path = vec![false; count];
+        let mut id = self.current;
+        while id != 0 {
+            on_path[id] = true;
+            id = self.nodes[id].parent;
+        }
+
+        // Oldest first: each state still kept is a child of the root, as
+        // everything older is gone or is the root
+        let mut kept = vec![true; count];
+        let mut root = 0;
+        let mut changes = count - 1;
+        for oldest in 1..count {
+            if changes <= levels {
+                break;
+            }
+            if !kept[oldest] {
+                continue;
+            }
+            let dropped = if on_path[oldest] {
+                let old_root = root;
+                root = oldest;
+                old_root
+            } else {
+                oldest
+            };
+            kept[dropped] = false;
+            changes -= 1;
+            // With the old root go its other branches, with a dead branch
+            // everything grown from it
+            for later in oldest + 1..count {
+                if kept[later] && !kept[self.nodes[later].parent] {
+                    kept[later] = false;
+                    changes -= 1;
+                }
+            }
+        }
+
+        let mut new_index = vec![0; count];
+        let mut nodes = Vec::with_capacity(changes + 1);
+        for (id, node) in std::mem::take(&mut self.nodes).into_iter().enumerate() {
+            if kept[id] {
+                new_index[id] = nodes.len();
+                nodes.push(node);
+            }
         }
+        for node in &mut nodes {
+            node.parent = new_index[node.parent];
+            node.redo_child = node.redo_child.filter(|&child| kept[child]).map(|child| new_index[child]);
+        }
+        nodes[0].group = UndoGroup::new((0, 0));
+        nodes[0].parent = 0;
+        self.nodes = nodes;
+        self.current = new_index[self.current];
+    }
+
+    /// A change on the line of the newest entry replaces it, so typing along
+    /// a line leaves one entry. Stepping starts again from the newest.
+    fn record_change(&mut self, position: (usize, usize)) {
+        if self.changes.last().is_some_and(|last| last.0 == position.0) {
+            self.changes.pop();
+        } else if self.changes.len() == CHANGE_LIST_SIZE {
+            self.changes.remove(0);
+        }
+        self.changes.push(position);
+        self.change_index = self.changes.len();
+    }
+
+    /// Where the last change happened (the '. mark)
+    pub fn last_change(&self) -> Option<(usize, usize)> {
+        self.changes.last().copied()
+    }
+
+    /// Move `count` entries through the change list, to older changes (g;)
+    /// or newer ones (g,), stopping at either end. Err if already there.
+    pub fn step_change_list(&mut self, count: usize, older: bool) -> Result<(usize, usize), String> {
+        if self.changes.is_empty() {
+            return Err("Change list is empty".to_string());
+        }
+        let index = if older {
+            if self.change_index == 0 {
+                return Err("At start of change list".to_string());
+            }
+            self.change_index.saturating_sub(count)
+        } else {
+            if self.change_index + 1 >= self.changes.len() {
+                return Err("At end of change list".to_string());
+            }
+            (self.change_index + count).min(self.changes.len() - 1)
+        };
+        self.change_index = index;
+        Ok(self.changes[index])
     }
 
     #[allow(dead_code)]
     pub fn clear(&mut self) {
-        self.undo_stack.clear();
-        self.redo_stack.clear();
-        self.current_group = None;
+        *self = Self::new();
     }
 }
 
@@ -273,3 +524,116 @@ impl Default for UndoManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(text: &str) -> UndoGroup {
+        let mut group = UndoGroup::new((0, 0));
+        group.add_action(UndoAction::InsertLine { line: 0, text: text.to_string() });
+        group
+    }
+
+    fn line_text(step: &UndoStep) -> (bool, String) {
+        let (redo, group) = match step {
+            UndoStep::Undo(group) => (false, group),
+            UndoStep::Redo(group) => (true, group),
+        };
+        match &group.actions[0] {
+            UndoAction::InsertLine { text, .. } => (redo, text.clone()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_new_change_after_undo_keeps_old_branch() {
+        let mut undo = UndoManager::new();
+        let start = Instant::now();
+        undo.push_undo_group(group("a"), start);
+        undo.push_undo_group(group("b"), start);
+        undo.undo();
+        undo.push_undo_group(group("c"), start); // State 3 branches off state 1
+
+        assert!(!undo.can_redo());
+        let leaves: Vec<usize> = undo.leaves().iter().map(|leaf| leaf.state).collect();
+        assert_eq!(leaves, vec![2, 3]);
+
+        // Back to state 2: undo c, redo b
+        let steps: Vec<(bool, String)> = undo.travel_to(2).iter().map(line_text).collect();
+        assert_eq!(steps, vec![(false, "c".to_string()), (true, "b".to_string())]);
+        assert_eq!(undo.current_state(), 2);
+
+        // Plain undo/redo now follow the branch last visited
+        undo.undo();
+        assert_eq!(undo.redo().map(|g| g.actions.len()), Some(1));
+        assert_eq!(undo.current_state(), 2);
+    }
+
+    #[test]
+    fn test_limit_drops_oldest_states() {
+        let mut undo = UndoManager::new();
+        let start = Instant::now();
+        undo.push_undo_group(group("a"), start);
+        undo.push_undo_group(group("b"), start);
+        undo.undo();
+        undo.push_undo_group(group("c"), start); // State 3 branches off state 1
+        undo.push_undo_group(group("d"), start);
+        undo.limit(10);
+        assert_eq!(undo.leaves().len(), 2);
+
+        // State 1 is the oldest and on the way to the current state: it
+        // becomes the text undo stops at, and b's branch stays
+        undo.limit(3);
+        let leaves: Vec<(usize, usize)> = undo.leaves().iter().map(|leaf| (leaf.state, leaf.changes)).collect();
+        assert_eq!(leaves, vec![(2, 1), (4, 2)]);
+
+        // Then b's branch is the oldest, and goes without touching the text
+        undo.limit(2);
+        let leaves: Vec<(usize, usize)> = undo.leaves().iter().map(|leaf| (leaf.state, leaf.changes)).collect();
+        assert_eq!(leaves, vec![(4, 2)]);
+        assert_eq!((undo.current_state(), undo.last_state()), (4, 4));
+
+        // Numbers stay put; undo stops at state 1
+        let steps: Vec<(bool, String)> = undo.travel_to(0).iter().map(line_text).collect();
+        assert_eq!(steps, vec![(false, "d".to_string()), (false, "c".to_string())]);
+        assert_eq!(undo.current_state(), 1);
+        assert!(undo.undo().is_none());
+        assert_eq!(undo.redo().map(|g| g.actions.len()), Some(1));
+        assert_eq!(undo.current_state(), 3);
+    }
+
+    #[test]
+    fn test_change_list() {
+        let mut undo = UndoManager::new();
+        assert!(undo.step_change_list(1, true).is_err());
+        for (line, column) in [(0, 0), (0, 5), (3, 1), (7, 2)] {
+            let mut group = UndoGroup::new((0, 0));
+            group.add_action(UndoAction::InsertText { line, column, text: "x".to_string() });
+            undo.push_undo_group(group, Instant::now());
+        }
+
+        // The two changes on line 0 share one entry
+        assert_eq!(undo.last_change(), Some((7, 2)));
+        assert_eq!(undo.step_change_list(1, true), Ok((7, 2)));
+        assert_eq!(undo.step_change_list(5, true), Ok((0, 5)));
+        assert!(undo.step_change_list(1, true).is_err());
+        assert_eq!(undo.step_change_list(1, false), Ok((3, 1)));
+        assert_eq!(undo.step_change_list(9, false), Ok((7, 2)));
+        assert!(undo.step_change_list(1, false).is_err());
+    }
+
+    #[test]
+    fn test_time_based_states() {
+        let mut undo = UndoManager::new();
+        let start = undo.nodes[0].time;
+        for (i, text) in ["a", "b", "c"].iter().enumerate() {
+            undo.push_undo_group(group(text), start + Duration::from_secs(10 * (i as u64 + 1)));
+        }
+
+        assert_eq!(undo.state_before(Duration::from_secs(15)), 1);
+        assert_eq!(undo.state_before(Duration::from_secs(100)), 0);
+        undo.travel_to(0);
+        assert_eq!(undo.state_after(Duration::from_secs(25)), 2);
+    }
+}
@@ -0,0 +1,32 @@
+//! vi-rus as a library - the document model, the editor controller and the
+//! view behind the `vi-rus` binary, for programs that embed the editor and
+//! for tests that drive it without a terminal
+//!
+//! ```no_run
+//! use vi_rus::EditorController;
+//!
+//! let mut editor = EditorController::new();
+//! editor.shared_state_mut().view.set_terminal_size(Some((80, 24)));
+//! editor.shared_state_mut().view.set_output(Box::new(std::io::sink()));
+//! editor.feed_keys("ihello<Esc>").unwrap();
+//! editor.render().unwrap();
+//! ```
+
+pub mod view;
+
+// Subsystems
+pub mod controller;
+pub mod config;
+pub mod document_model;
+pub mod lsp;
+
+#[cfg(test)]
+mod test_support;
+
+// Re-export public interface
+pub use controller::{EditorController, Mode, SharedEditorState};
+pub use controller::command::CommandController;
+pub use document_model::Document;
+pub use document_model::text_buffer::TextBuffer;
+pub use view::View;
+pub use view::renderer::Frame;
@@ -0,0 +1,557 @@
+//! One language server, run as a child process and spoken to over its
+//! stdin and stdout
+//!
+//! A thread reads the server's messages into a channel, so the event loop
+//! only ever takes what has already arrived. Requests are numbered and
+//! remembered until their response comes; what the server sends back is
+//! handed out by `poll` as `LspEvent`s. Until the server has answered
+//! `initialize`, everything else waits in a queue, as the protocol wants.
+
+use super::json::Json;
+use super::rpc;
+use crate::document_model::Severity;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How long a server has to exit after `exit` before it is killed
+const EXIT_WAIT: Duration = Duration::from_millis(100);
+
+/// A place in a document as the server counts it: the column is in the
+/// server's `PositionEncoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// Where a definition is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub position: Position,
+}
+
+/// A diagnostic as the server reported it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerDiagnostic {
+    pub start: Position,
+    pub end: Position,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// What a server's columns count: bytes of UTF-8, or UTF-16 code units,
+/// which is what servers use unless they agree to something else
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// The byte column of `character` in `line`
+    pub fn byte_column(self, line: &str, character: usize) -> usize {
+        match self {
+            PositionEncoding::Utf8 => {
+                let mut column = character.min(line.len());
+                while !line.is_char_boundary(column) {
+                    column -= 1;
+                }
+                column
+            }
+            PositionEncoding::Utf16 => {
+                let mut units = 0;
+                for (column, ch) in line.char_indices() {
+                    if units >= character {
+                        return column;
+                    }
+                    units += ch.len_utf16();
+                }
+                line.len()
+            }
+        }
+    }
+
+    /// The server's column for the byte column `column` of `line`
+    pub fn character(self, line: &str, column: usize) -> usize {
+        let before = line.get(..column).unwrap_or(line);
+        match self {
+            PositionEncoding::Utf8 => before.len(),
+            PositionEncoding::Utf16 => before.encode_utf16().count(),
+        }
+    }
+}
+
+/// Something a server said, for the editor to act on
+#[derive(Debug, Clone, PartialEq)]
+pub enum LspEvent {
+    Diagnostics { path: PathBuf, diagnostics: Vec<ServerDiagnostic> }, // The whole set for one file
+    Definition(Vec<Location>), // Empty when the server found none
+    Hover(String),             // Empty when there is nothing to show
+    Message(String),           // An error, or something the server wants the user to see
+    Exited,                    // The server closed its output
+}
+
+/// The requests waiting for a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Request {
+    Initialize,
+    Definition,
+    Hover,
+    Shutdown,
+}
+
+pub struct LanguageServer {
+    writer: Box<dyn Write + Send>,
+    messages: Receiver<Json>,
+    child: Option<Child>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    queued: Vec<Json>, // Sent once the server is initialized
+    initialized: bool,
+    encoding: PositionEncoding,
+    exited: bool,
+}
+
+impl LanguageServer {
+    /// Start `command` with the shell in `root`, the root of the workspace
+    pub fn spawn(command: &str, root: &Path) -> std::io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let writer = child.stdin.take().expect("stdin is piped");
+        let reader = child.stdout.take().expect("stdout is piped");
+        let mut server = Self::new(Box::new(writer), reader, root);
+        server.child = Some(child);
+        Ok(server)
+    }
+
+    /// A server reached through `writer` and `reader`. Sends `initialize`.
+    pub fn new(writer: Box<dyn Write + Send>, reader: impl Read + Send + 'static, root: &Path) -> Self {
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = rpc::read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut server = Self {
+            writer,
+            messages,
+            child: None,
+            next_id: 1,
+            pending: HashMap::new(),
+            queued: Vec::new(),
+            initialized: false,
+            encoding: PositionEncoding::Utf16,
+            exited: false,
+        };
+
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+        let capabilities = Json::object([
+            ("general", Json::object([("positionEncodings", vec!["utf-8".into(), "utf-16".into()].into())])),
+            (
+                "textDocument",
+                Json::object([
+                    ("synchronization", Json::object([])),
+                    ("publishDiagnostics", Json::object([])),
+                    ("definition", Json::object([("linkSupport", true.into())])),
+                    ("hover", Json::object([("contentFormat", vec!["plaintext".into(), "markdown".into()].into())])),
+                ]),
+            ),
+        ]);
+        let params = Json::object([
+            ("processId", (std::process::id() as usize).into()),
+            ("clientInfo", Json::object([("name", "vi-rus".into())])),
+            ("rootUri", path_to_uri(&root).into()),
+            ("capabilities", capabilities),
+        ]);
+        let message = server.request_message("initialize", params, Request::Initialize);
+        server.write(&message);
+        server
+    }
+
+    /// False once the server has gone
+    pub fn is_running(&self) -> bool {
+        !self.exited
+    }
+
+    /// What the server's columns count
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    pub fn did_open(&mut self, path: &Path, language_id: &str, version: u64, text: &str) {
+        let document = Json::object([
+            ("uri", path_to_uri(path).into()),
+            ("languageId", language_id.into()),
+            ("version", version.into()),
+            ("text", text.into()),
+        ]);
+        self.notify("textDocument/didOpen", Json::object([("textDocument", document)]));
+    }
+
+    /// The buffer's text changed: send all of it, which every server takes
+    pub fn did_change(&mut self, path: &Path, version: u64, text: &str) {
+        let document = Json::object([("uri", path_to_uri(path).into()), ("version", version.into())]);
+        let changes = vec![Json::object([("text", text.into())])];
+        self.notify("textDocument/didChange", Json::object([("textDocument", document), ("contentChanges", changes.into())]));
+    }
+
+    pub fn did_close(&mut self, path: &Path) {
+        let document = Json::object([("uri", path_to_uri(path).into())]);
+        self.notify("textDocument/didClose", Json::object([("textDocument", document)]));
+    }
+
+    /// Ask where the symbol at `position` is defined. The answer comes
+    /// from `poll` as `LspEvent::Definition`.
+    pub fn definition(&mut self, path: &Path, position: Position) {
+        let message = self.request_message("textDocument/definition", Self::position_params(path, position), Request::Definition);
+        self.send(message);
+    }
+
+    /// Ask about the symbol at `position`. The answer comes from `poll` as
+    /// `LspEvent::Hover`.
+    pub fn hover(&mut self, path: &Path, position: Position) {
+        let message = self.request_message("textDocument/hover", Self::position_params(path, position), Request::Hover);
+        self.send(message);
+    }
+
+    /// Everything the server has said since the last call, without waiting
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => self.receive(message, &mut events),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        events.push(LspEvent::Exited);
+                    }
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    fn position_params(path: &Path, position: Position) -> Json {
+        Json::object([
+            ("textDocument", Json::object([("uri", path_to_uri(path).into())])),
+            ("position", Json::object([("line", position.line.into()), ("character", position.character.into())])),
+        ])
+    }
+
+    fn request_message(&mut self, method: &str, params: Json, request: Request) -> Json {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, request);
+        let mut message = Json::object([("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into())]);
+        if let Json::Object(fields) = &mut message
+            && !params.is_null()
+        {
+            fields.push(("params".to_string(), params));
+        }
+        message
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        let mut message = Json::object([("jsonrpc", "2.0".into()), ("method", method.into())]);
+        if let Json::Object(fields) = &mut message
+            && !params.is_null()
+        {
+            fields.push(("params".to_string(), params));
+        }
+        self.send(message);
+    }
+
+    /// Write `message`, or keep it until the server is initialized
+    fn send(&mut self, message: Json) {
+        if self.initialized {
+            self.write(&message);
+        } else {
+            self.queued.push(message);
+        }
+    }
+
+    fn write(&mut self, message: &Json) {
+        // A server that has gone is noticed when its output closes
+        let _ = rpc::write_message(&mut *self.writer, message);
+    }
+
+    fn receive(&mut self, message: Json, events: &mut Vec<LspEvent>) {
+        if let Some(method) = message.get("method").and_then(Json::as_str) {
+            if let Some(id) = message.get("id") {
+                // A request from the server. None of them need more than an
+                // empty answer, but workspace/configuration wants one per item.
+                let result = match method {
+                    "workspace/configuration" => {
+                        let items = message.at(&["params", "items"]).and_then(Json::as_array).map_or(0, <[Json]>::len);
+                        Json::Array(vec![Json::Null; items])
+                    }
+                    _ => Json::Null,
+                };
+                let reply = Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]);
+                self.write(&reply);
+                return;
+            }
+            let params = message.get("params").unwrap_or(&Json::Null);
+            match method {
+                "textDocument/publishDiagnostics" => {
+                    if let Some(path) = params.get("uri").and_then(Json::as_str).and_then(uri_to_path) {
+                        let items = params.get("diagnostics").and_then(Json::as_array).unwrap_or_default();
+                        let diagnostics = items.iter().filter_map(diagnostic).collect();
+                        events.push(LspEvent::Diagnostics { path, diagnostics });
+                    }
+                }
+                "window/showMessage" => {
+                    if let Some(text) = params.get("message").and_then(Json::as_str) {
+                        events.push(LspEvent::Message(text.lines().next().unwrap_or_default().to_string()));
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        let Some(request) = message.get("id").and_then(Json::as_u64).and_then(|id| self.pending.remove(&id)) else {
+            return;
+        };
+        if let Some(error) = message.get("error") {
+            let text = error.get("message").and_then(Json::as_str).unwrap_or("request failed");
+            events.push(LspEvent::Message(format!("Language server: {}", text)));
+            return;
+        }
+        let result = message.get("result").unwrap_or(&Json::Null);
+        match request {
+            Request::Initialize => {
+                if result.at(&["capabilities", "positionEncoding"]).and_then(Json::as_str) == Some("utf-8") {
+                    self.encoding = PositionEncoding::Utf8;
+                }
+                self.initialized = true;
+                self.notify("initialized", Json::object([]));
+                for message in std::mem::take(&mut self.queued) {
+                    self.write(&message);
+                }
+            }
+            Request::Definition => events.push(LspEvent::Definition(locations(result))),
+            Request::Hover => events.push(LspEvent::Hover(hover_text(result))),
+            Request::Shutdown => {}
+        }
+    }
+}
+
+impl Drop for LanguageServer {
+    /// Ask the server to shut down and exit, and kill it if it doesn't
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        if self.initialized && !self.exited {
+            let message = self.request_message("shutdown", Json::Null, Request::Shutdown);
+            self.write(&message);
+            self.notify("exit", Json::Null);
+        }
+        let step = Duration::from_millis(10);
+        for _ in 0..EXIT_WAIT.as_millis() / step.as_millis() {
+            if matches!(child.try_wait(), Ok(Some(_))) {
+                return;
+            }
+            thread::sleep(step);
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+fn position(value: &Json) -> Option<Position> {
+    let line = value.get("line")?.as_u64()? as usize;
+    let character = value.get("character")?.as_u64()? as usize;
+    Some(Position { line, character })
+}
+
+fn diagnostic(value: &Json) -> Option<ServerDiagnostic> {
+    let range = value.get("range")?;
+    Some(ServerDiagnostic {
+        start: position(range.get("start")?)?,
+        end: position(range.get("end")?)?,
+        severity: Severity::from_lsp(value.get("severity").and_then(Json::as_u64)),
+        message: value.get("message")?.as_str()?.to_string(),
+    })
+}
+
+/// The places a definition result names: one Location, a list of them, or
+/// a list of LocationLinks
+fn locations(result: &Json) -> Vec<Location> {
+    let items = match result {
+        Json::Array(items) => items.as_slice(),
+        Json::Null => &[],
+        single => std::slice::from_ref(single),
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let uri = item.get("uri").or_else(|| item.get("targetUri"))?.as_str()?;
+            let range = item.get("range").or_else(|| item.get("targetSelectionRange"))?;
+            Some(Location { path: uri_to_path(uri)?, position: position(range.get("start")?)? })
+        })
+        .collect()
+}
+
+/// The text of a hover result, which may be markup, a string or a list of
+/// either, without the fences of markdown code blocks
+fn hover_text(result: &Json) -> String {
+    fn text_of(contents: &Json) -> String {
+        match contents {
+            Json::String(text) => text.clone(),
+            Json::Array(items) => items.iter().map(text_of).filter(|text| !text.is_empty()).collect::<Vec<_>>().join("\n\n"),
+            _ => contents.get("value").and_then(Json::as_str).unwrap_or_default().to_string(),
+        }
+    }
+    let text = result.get("contents").map(text_of).unwrap_or_default();
+    let lines: Vec<&str> = text.lines().filter(|line| !line.trim_start().starts_with("```")).collect();
+    lines.join("\n").trim().to_string()
+}
+
+/// The `file://` URI of `path`, which should be absolute
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        let escaped = (encoded[index] == b'%')
+            .then(|| encoded.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                index += 3;
+            }
+            None => {
+                bytes.push(encoded[index]);
+                index += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, PipeReader, PipeWriter};
+    use std::time::Instant;
+
+    fn next(from_client: &mut impl BufRead) -> Json {
+        rpc::read_message(from_client).unwrap().unwrap()
+    }
+
+    fn wait_for(server: &mut LanguageServer) -> Vec<LspEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            let events = server.poll();
+            if !events.is_empty() {
+                return events;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        Vec::new()
+    }
+
+    fn connect() -> (LanguageServer, BufReader<PipeReader>, PipeWriter) {
+        let (client_reader, to_client) = std::io::pipe().unwrap();
+        let (from_client, client_writer) = std::io::pipe().unwrap();
+        let server = LanguageServer::new(Box::new(client_writer), client_reader, Path::new("/work"));
+        (server, BufReader::new(from_client), to_client)
+    }
+
+    #[test]
+    fn test_requests_wait_for_initialize_and_answers_become_events() {
+        let (mut server, mut from_client, mut to_client) = connect();
+        let initialize = next(&mut from_client);
+        assert_eq!(initialize.get("method").and_then(Json::as_str), Some("initialize"));
+        assert_eq!(initialize.at(&["params", "rootUri"]).and_then(Json::as_str), Some("file:///work"));
+
+        let path = Path::new("/work/a b.rs");
+        server.did_open(path, "rust", 1, "fn é() {}");
+        server.hover(path, Position { line: 0, character: 3 });
+        let reply = r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"positionEncoding":"utf-16"}}}"#;
+        rpc::write_message(&mut to_client, &Json::parse(reply).unwrap()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server.initialized && Instant::now() < deadline {
+            assert!(server.poll().is_empty());
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(server.encoding(), PositionEncoding::Utf16);
+
+        let methods: Vec<Json> = (0..3).map(|_| next(&mut from_client)).collect();
+        let method = |index: usize| methods[index].get("method").and_then(Json::as_str);
+        assert_eq!((method(0), method(1), method(2)), (Some("initialized"), Some("textDocument/didOpen"), Some("textDocument/hover")));
+        assert_eq!(methods[1].at(&["params", "textDocument", "uri"]).and_then(Json::as_str), Some("file:///work/a%20b.rs"));
+
+        let messages = [
+            r#"{"jsonrpc":"2.0","id":7,"method":"workspace/configuration","params":{"items":[{},{}]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"contents":{"kind":"markdown","value":"```rust\nfn é()\n```"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///work/a%20b.rs",
+                "diagnostics":[{"range":{"start":{"line":0,"character":3},"end":{"line":0,"character":4}},"severity":2,"message":"unused"}]}}"#,
+        ];
+        for message in messages {
+            rpc::write_message(&mut to_client, &Json::parse(message).unwrap()).unwrap();
+        }
+        let mut events = wait_for(&mut server);
+        while events.len() < 2 {
+            events.extend(wait_for(&mut server));
+        }
+        assert_eq!(events[0], LspEvent::Hover("fn é()".to_string()));
+        let start = Position { line: 0, character: 3 };
+        let diagnostic = ServerDiagnostic { start, end: Position { line: 0, character: 4 }, severity: Severity::Warning, message: "unused".to_string() };
+        assert_eq!(events[1], LspEvent::Diagnostics { path: path.to_path_buf(), diagnostics: vec![diagnostic] });
+        let answer = next(&mut from_client);
+        assert_eq!(answer.get("id").and_then(Json::as_u64), Some(7));
+        assert_eq!(answer.get("result"), Some(&Json::Array(vec![Json::Null, Json::Null])));
+
+        drop(to_client);
+        assert_eq!(wait_for(&mut server), [LspEvent::Exited]);
+        assert!(!server.is_running());
+    }
+
+    #[test]
+    fn test_locations_and_columns() {
+        let link = Json::parse(r#"[{"targetUri":"file:///x.rs","targetSelectionRange":{"start":{"line":4,"character":2}}}]"#).unwrap();
+        assert_eq!(locations(&link), [Location { path: PathBuf::from("/x.rs"), position: Position { line: 4, character: 2 } }]);
+        assert!(locations(&Json::Null).is_empty());
+
+        let line = "aé😀b";
+        assert_eq!(PositionEncoding::Utf16.byte_column(line, 4), 7);
+        assert_eq!(PositionEncoding::Utf16.character(line, 7), 4);
+        assert_eq!(PositionEncoding::Utf8.byte_column(line, 2), 1);
+        assert_eq!(PositionEncoding::Utf8.character(line, 7), 7);
+        assert_eq!(uri_to_path(&path_to_uri(Path::new("/a b/ü.rs"))), Some(PathBuf::from("/a b/ü.rs")));
+    }
+}
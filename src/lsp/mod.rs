@@ -0,0 +1,12 @@
+//! Language server protocol - the client the editor uses to get
+//! diagnostics, definitions and hover text from a language server such as
+//! rust-analyzer or clangd
+//!
+//! `json` and `rpc` are the wire format; `client` runs one server.
+
+pub mod client;
+pub mod json;
+pub mod rpc;
+
+pub use client::{LanguageServer, Location, LspEvent, Position, PositionEncoding, ServerDiagnostic};
+pub use json::Json;
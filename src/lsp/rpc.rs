@@ -0,0 +1,73 @@
+//! JSON-RPC framing - each message is a JSON text after a
+//! `Content-Length` header and a blank line:
+//!
+//! ```text
+//! Content-Length: 33\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","method":"exit"}
+//! ```
+
+use super::json::Json;
+use std::io::{self, BufRead, Write};
+
+/// Write one message and flush it, so the server sees it at once
+pub fn write_message(writer: &mut dyn Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Read the next message. None at the end of the stream; an error when
+/// the stream doesn't hold a message where one should be.
+pub fn read_message(reader: &mut dyn BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let line = header.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue; // Blank lines before a header
+        }
+        // Content-Type is the only other header, and is always UTF-8 JSON
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    Json::parse(&String::from_utf8_lossy(&body))
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let first = Json::object([("jsonrpc", "2.0".into()), ("method", "exit".into())]);
+        let second = Json::object([("id", 1usize.into()), ("result", "é".into())]);
+        let mut stream = Vec::new();
+        write_message(&mut stream, &first).unwrap();
+        write_message(&mut stream, &second).unwrap();
+        assert!(stream.starts_with(b"Content-Length: 33\r\n\r\n{\"jsonrpc\""));
+
+        let mut reader = io::Cursor::new(stream);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        let mut broken = io::Cursor::new(b"Content-Length: 4\r\n\r\n{no}".to_vec());
+        assert!(read_message(&mut broken).is_err());
+    }
+}
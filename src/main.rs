@@ -1,31 +1,229 @@
-mod view;
+use vi_rus::EditorController;
+use vi_rus::controller::session_script::DEFAULT_SESSION_FILE;
+use vi_rus::config::RcLoader;
+use vi_rus::document_model::info_file::InfoFile;
+use vi_rus::document_model::{FilePosition, LockChoice, LockConflict};
+use std::env;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-// Subsystems
-mod controller;
-mod config;
-mod document_model;
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let Args { commands, batch, session, recover, stdin, diff, filenames, positions } = split_args(env::args().skip(1))?;
 
-use controller::EditorController;
-use config::RcLoader;
-use std::env;
-use std::path::PathBuf;
+    // virus - reads stdin to its end, then takes keys from the terminal
+    let piped = if stdin {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        if !batch {
+            reattach_tty().map_err(|e| format!("can't read keys from the terminal: {e}"))?;
+        }
+        Some(bytes)
+    } else {
+        None
+    };
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    // -r on its own lists what could be recovered here
+    if recover && filenames.is_empty() {
+        list_swap_files();
+        return Ok(ExitCode::SUCCESS);
+    }
 
     // Load RC configuration
     let config = RcLoader::load_config();
 
     // Use the new modular EditorController for testing
-    let mut controller = if args.len() > 1 {
-        let filenames: Vec<PathBuf> = args[1..].iter().map(PathBuf::from).collect();
-        EditorController::new_with_files(filenames)?
+    let mut controller = if !filenames.is_empty() {
+        EditorController::new_with_files(filenames, |path, conflict| match conflict {
+            LockConflict::Recoverable { .. } if recover => LockChoice::Recover,
+            _ => prompt_lock_conflict(path, conflict),
+        })?
     } else {
         EditorController::new()
     };
 
+    if let Some(bytes) = &piped {
+        controller.open_stdin(bytes);
+    }
+
     // Apply RC configuration to the controller
     controller.apply_config(&config);
 
-    controller.run()
+    // Marks, registers and the last search from the previous session
+    if let Some(path) = InfoFile::path() {
+        controller.load_info(&path);
+    }
+
+    // -S loads a session before anything else runs; a bad line only leaves
+    // its message on the status line
+    if let Some(session) = session {
+        let _ = controller.run_commands(&[format!("source {}", session.display())]);
+    }
+
+    // +N and file:line[:column] put the cursor where they say
+    for (index, position) in positions {
+        controller.go_to_position(index + usize::from(stdin), position);
+    }
+
+    // -d compares the two files side by side (stdin is the first when read)
+    if diff {
+        controller.diff_buffers(0, 1);
+    }
+
+    // With --cmd or -es, run the commands and exit without opening the
+    // terminal. A failure prints "vi-rus: error: cmd N: message" and exits with 1.
+    if batch {
+        return Ok(match controller.run_commands(&commands) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err((index, message)) => {
+                eprintln!("vi-rus: error: cmd {}: {}: {}", index + 1, commands[index], message);
+                ExitCode::FAILURE
+            }
+        });
+    }
+
+    // -c and +cmd run before the first screen is drawn; a failure only leaves
+    // its message on the status line, and a command that quits ends it there
+    if controller.run_startup_commands(&commands)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    controller.run()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// What the command line asks for
+struct Args {
+    commands: Vec<String>,    // --cmd, -c and +cmd, in order
+    batch: bool,              // --cmd or -es: run the commands without the terminal
+    session: Option<PathBuf>, // -S, loaded before editing starts
+    recover: bool,            // -r, restore files from their swap files
+    stdin: bool,              // -, edit what is piped in
+    diff: bool,               // -d, compare two files in diff mode
+    filenames: Vec<PathBuf>,
+    positions: Vec<(usize, FilePosition)>, // Where the cursor goes in the file at each index
+}
+
+/// Separate `--cmd <command>`, `-c <command>`, `+command`, `-es`,
+/// `-S [session]`, `-r`, `-`, `-d` and `+N` options from file names. `+N` is for
+/// the first file, as in vim; a name may carry its own position as
+/// `file:line[:column]`.
+fn split_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        commands: Vec::new(),
+        batch: false,
+        session: None,
+        recover: false,
+        stdin: false,
+        diff: false,
+        filenames: Vec::new(),
+        positions: Vec::new(),
+    };
+    let mut first_file = None;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cmd" | "-c" => {
+                parsed.commands.push(args.next().ok_or(format!("{arg} needs a command"))?);
+                parsed.batch |= arg == "--cmd";
+            }
+            // Silent ex mode: the -c and +cmd commands run without the terminal
+            "-es" => parsed.batch = true,
+            // -S without a file name loads Session.vim, as in vim
+            "-S" => {
+                let name = args.next_if(|next| !next.starts_with('-'));
+                parsed.session = Some(PathBuf::from(name.as_deref().unwrap_or(DEFAULT_SESSION_FILE)));
+            }
+            "-r" => parsed.recover = true,
+            "-" => parsed.stdin = true,
+            "-d" => parsed.diff = true,
+            _ if arg.starts_with('+') => match FilePosition::parse_plus(&arg) {
+                Some(position) => first_file = Some(position),
+                None => parsed.commands.push(arg[1..].to_string()),
+            },
+            _ => {
+                let (name, position) = FilePosition::split_name(&arg);
+                if let Some(position) = position {
+                    parsed.positions.push((parsed.filenames.len(), position));
+                }
+                parsed.filenames.push(PathBuf::from(name));
+            }
+        }
+    }
+    if let Some(position) = first_file
+        && !parsed.filenames.is_empty()
+    {
+        parsed.positions.retain(|(index, _)| *index != 0);
+        parsed.positions.push((0, position));
+    }
+    if parsed.diff && parsed.filenames.len() + usize::from(parsed.stdin) != 2 {
+        return Err("-d compares two files".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Once stdin has been read, point it at the terminal, so keys come from
+/// there and so do the shell commands the editor runs
+#[cfg(unix)]
+fn reattach_tty() -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    // Descriptor 0 keeps the terminal open after `tty` is closed
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Keys are read from the console whatever stdin is
+#[cfg(not(unix))]
+fn reattach_tty() -> io::Result<()> {
+    Ok(())
+}
+
+/// Ask on the terminal what to do with a file another editor is already editing.
+/// Runs before raw mode is enabled, so plain line input works.
+fn prompt_lock_conflict(path: &Path, conflict: &LockConflict) -> LockChoice {
+    eprintln!("ATTENTION: \"{}\": {}", path.display(), conflict);
+    let recoverable = matches!(conflict, LockConflict::Recoverable { .. });
+    if recoverable {
+        eprint!("(R)ecover, [O]pen read-only, (E)dit anyway, (A)bort: ");
+    } else {
+        eprint!("[O]pen read-only, (E)dit anyway, (A)bort: ");
+    }
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return LockChoice::ReadOnly;
+    }
+    match answer.trim().chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('e') => LockChoice::Proceed,
+        Some('r') if recoverable => LockChoice::Recover,
+        Some('a') | Some('q') => LockChoice::Abort,
+        _ => LockChoice::ReadOnly,
+    }
+}
+
+/// -r without files: name the files in the current directory that have a
+/// swap file to recover
+fn list_swap_files() {
+    let mut names: Vec<String> = std::fs::read_dir(".")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            Some(name.strip_prefix('.')?.strip_suffix(".virus-swap")?.to_string())
+        })
+        .collect();
+    names.sort();
+    if names.is_empty() {
+        println!("No swap files found in the current directory");
+        return;
+    }
+    println!("Swap files found (recover with virus -r <file>):");
+    for name in names {
+        println!("  {}", name);
+    }
 }
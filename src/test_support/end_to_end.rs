@@ -0,0 +1,1765 @@
+//! End-to-end scenarios: key scripts checked against the screen and buffer
+
+use super::TestEditor;
+use crate::controller::Mode;
+use crate::document_model::HistoryKind;
+use std::time::Duration;
+
+#[test]
+fn test_first_frame_shows_header_text_and_status() {
+    let mut editor = TestEditor::new("hello world\nsecond line");
+    editor.assert_screen(&[
+        "Buffer 1/1: \"[No Name]\"",
+        "hello world",
+        "second line",
+        "",
+        "",
+        "",
+        "",
+        "-- NORMAL --",
+    ]);
+    assert_eq!(editor.screen_cursor(), (1, 0));
+}
+
+#[test]
+fn test_insert_then_undo_and_redo() {
+    let mut editor = TestEditor::new("world");
+    editor.keys("ihello <Esc>");
+    editor.assert_lines(&["hello world"]);
+    assert_eq!(editor.mode(), Mode::Normal);
+    editor.keys("u");
+    editor.assert_lines(&["world"]);
+    editor.keys("<C-r>");
+    editor.assert_lines(&["hello world"]);
+}
+
+#[test]
+fn test_insert_mode_shows_in_status_line() {
+    let mut editor = TestEditor::new("abc");
+    editor.keys("A");
+    assert_eq!(editor.status_line(), "-- INSERT --");
+    editor.keys("def<BS>");
+    assert_eq!(editor.screen()[1], "abcde");
+    assert_eq!(editor.screen_cursor(), (1, 5));
+}
+
+#[test]
+fn test_open_line_below_and_above() {
+    let mut editor = TestEditor::new("middle");
+    editor.keys("obelow<Esc>kOabove<Esc>");
+    editor.assert_lines(&["above", "middle", "below"]);
+}
+
+#[test]
+fn test_counted_motions_and_deletes() {
+    let mut editor = TestEditor::new("one two three four\nline 2\nline 3\nline 4");
+    editor.keys("2w");
+    assert_eq!(editor.cursor(), (0, 8));
+    editor.keys("x");
+    editor.assert_lines(&["one two hree four", "line 2", "line 3", "line 4"]);
+    editor.keys("j2dd");
+    editor.assert_lines(&["one two hree four", "line 4"]);
+
+    // The count of f/t is used up by the search, leaving none for x
+    let mut editor = TestEditor::new("a-b-c-d-e");
+    editor.keys("3f-");
+    assert_eq!(editor.cursor(), (0, 5));
+    editor.keys("x2F-");
+    editor.assert_lines(&["a-b-cd-e"]);
+    assert_eq!(editor.cursor(), (0, 1));
+}
+
+#[test]
+fn test_counted_x_pastes_back() {
+    let mut editor = TestEditor::new("abcdef");
+    editor.keys("3xp");
+    editor.assert_lines(&["dabcef"]);
+    editor.keys("0ll2X");
+    editor.assert_lines(&["bcef"]);
+    editor.keys("0P");
+    editor.assert_lines(&["dabcef"]);
+}
+
+#[test]
+fn test_yank_and_put_lines() {
+    let mut editor = TestEditor::new("first\nsecond");
+    editor.keys("yyjp");
+    editor.assert_lines(&["first", "second", "first"]);
+    editor.keys("ggP");
+    editor.assert_lines(&["first", "first", "second", "first"]);
+}
+
+#[test]
+fn test_at_colon_repeats_last_command_line() {
+    let mut editor = TestEditor::new("a a\na a\na a");
+    editor.keys(":s/a/b/<CR>j@:j2@:");
+    editor.assert_lines(&["b a", "b a", "b b"]);
+}
+
+#[test]
+fn test_search_moves_to_next_match() {
+    let mut editor = TestEditor::new("alpha\nbeta alpha\ngamma alpha");
+    editor.keys("/alpha<CR>");
+    assert_eq!(editor.cursor(), (1, 5));
+    editor.keys("n");
+    assert_eq!(editor.cursor(), (2, 6));
+    editor.keys("N");
+    assert_eq!(editor.cursor(), (1, 5));
+}
+
+#[test]
+fn test_search_prompt_is_drawn_while_typing() {
+    let mut editor = TestEditor::new("text");
+    editor.keys("/te");
+    assert_eq!(editor.status_line(), "/te");
+    assert_eq!(editor.screen_cursor(), (7, 3));
+}
+
+#[test]
+fn test_substitute_whole_buffer() {
+    let mut editor = TestEditor::new("cat and cat\ndog\ncat");
+    editor.keys(":%s/cat/bird/g<CR>");
+    editor.assert_lines(&["bird and bird", "dog", "bird"]);
+    assert_eq!(editor.mode(), Mode::Normal);
+    assert_eq!(editor.cursor(), (2, 0)); // The last line substituted
+
+    // The cursor ends up on a character of a line that still exists
+    let mut editor = TestEditor::new("日本語");
+    editor.keys("l:s/^/  /<CR>x");
+    editor.assert_lines(&["  本語"]);
+    editor.keys("l:s/本/a/<CR>x");
+    editor.assert_lines(&["  語"]);
+    let mut editor = TestEditor::new("a\nb\nc");
+    editor.keys("G:%s/\\n//<CR>");
+    editor.assert_lines(&["abc"]);
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys("ggl:s/b/x/c<CR>y");
+    assert_eq!(editor.cursor(), (0, 0));
+}
+
+#[test]
+fn test_unknown_command_reports_error() {
+    let mut editor = TestEditor::new("");
+    editor.keys(":frobnicate<CR>");
+    assert!(editor.status_line().contains("frobnicate"), "{}", editor.status_line());
+}
+
+#[test]
+fn test_marks_jump_back() {
+    let mut editor = TestEditor::new("one\ntwo\nthree");
+    editor.keys("jmaG'a");
+    assert_eq!(editor.cursor().0, 1);
+}
+
+#[test]
+fn test_paste_undoes_in_one_step() {
+    let mut editor = TestEditor::new("ab\ncd");
+    editor.keys("yj3p");
+    assert_eq!(editor.lines().len(), 8);
+    editor.keys("u");
+    editor.assert_lines(&["ab", "cd"]);
+    editor.keys("xp");
+    editor.assert_lines(&["ba", "cd"]);
+    editor.keys("u");
+    editor.assert_lines(&["b", "cd"]);
+}
+
+#[test]
+fn test_ctrl_p_cycles_paste_through_yank_history() {
+    let mut editor = TestEditor::new("a\nb\nc");
+    editor.keys("yyjyyjyyp");
+    editor.assert_lines(&["a", "b", "c", "c"]);
+    editor.keys("<C-p>");
+    editor.assert_lines(&["a", "b", "c", "b"]);
+    assert_eq!(editor.status_line(), "Yank history 2 of 3");
+    editor.keys("<C-p><C-p>");
+    editor.assert_lines(&["a", "b", "c", "c"]);
+    editor.keys("2<C-p>");
+    editor.assert_lines(&["a", "b", "c", "a"]);
+    editor.keys("u");
+    editor.assert_lines(&["a", "b", "c"]);
+
+    editor.keys("<C-p>");
+    editor.assert_lines(&["a", "b", "c"]);
+    assert_eq!(editor.status_line(), "Ctrl-P only works straight after p or P");
+}
+
+#[test]
+fn test_delete_to_mark_line_and_undo() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys("jmaGd'a");
+    editor.assert_lines(&["one"]);
+    editor.keys("u");
+    editor.assert_lines(&["one", "two", "three", "four"]);
+    editor.keys("ggjmaGd'ap");
+    editor.assert_lines(&["one", "two", "three", "four"]);
+}
+
+#[test]
+fn test_delete_and_yank_to_mark_position() {
+    let mut editor = TestEditor::new("abc def ghi");
+    editor.keys("wmawd`a");
+    editor.assert_lines(&["abc ghi"]);
+    editor.keys("0P");
+    editor.assert_lines(&["def abc ghi"]);
+
+    let mut editor = TestEditor::new("one\ntwo\nthree");
+    editor.keys("jmaG\"qy'a");
+    assert_eq!(editor.cursor().0, 1);
+    editor.keys("G\"qp");
+    editor.assert_lines(&["one", "two", "three", "two", "three"]);
+}
+
+#[test]
+fn test_change_to_mark_is_one_undo_step() {
+    let mut editor = TestEditor::new("a\nb\nc");
+    editor.keys("majc'aX<Esc>");
+    editor.assert_lines(&["X", "c"]);
+    editor.keys("u");
+    editor.assert_lines(&["a", "b", "c"]);
+}
+
+#[test]
+fn test_uppercase_register_appends() {
+    let mut editor = TestEditor::new("one\ntwo\nthree");
+    editor.keys("\"ayyj\"AyyG\"ap");
+    editor.assert_lines(&["one", "two", "three", "one", "two"]);
+}
+
+#[test]
+fn test_numbered_and_small_delete_registers() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys("yyjddddx\"2p\"0p\"-P");
+    editor.assert_lines(&["one", "our", "two", "fone"]);
+}
+
+#[test]
+fn test_change_list() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour\nfive");
+    editor.keys("xjjllxGg;");
+    assert_eq!(editor.cursor(), (2, 2));
+    editor.keys("g;");
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys("g;");
+    assert_eq!(editor.status_line(), "At start of change list");
+    editor.keys("g,G`.");
+    assert_eq!(editor.cursor(), (2, 2));
+    editor.keys("gg'.");
+    assert_eq!(editor.cursor(), (2, 0));
+}
+
+#[test]
+fn test_operator_to_unset_mark() {
+    let mut editor = TestEditor::new("a\nb");
+    editor.keys("jd'z");
+    editor.assert_lines(&["a", "b"]);
+    assert_eq!(editor.status_line(), "Mark not set");
+}
+
+#[test]
+fn test_visual_selection_extends_to_mark() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys("jmaGV'a");
+    assert_eq!(&editor.screen()[1..5], [" one", ">two", ">three", ">four"]);
+    editor.keys("<Esc>gg2lmbGv`b");
+    assert_eq!(editor.cursor(), (0, 2));
+}
+
+#[test]
+fn test_operators_on_visual_selections() {
+    let mut editor = TestEditor::new("one two\nthree\nfour");
+    editor.keys("vwU");
+    editor.assert_lines(&["ONE Two", "three", "four"]);
+    assert_eq!(editor.mode(), Mode::Normal);
+    editor.keys("jVjdp");
+    editor.assert_lines(&["ONE Two", "three", "four"]);
+    editor.keys("Vj>");
+    editor.assert_lines(&["ONE Two", "    three", "    four"]);
+    editor.keys("gg<C-v>jlcX<Esc>");
+    editor.assert_lines(&["XE Two", "X  three", "    four"]);
+}
+
+#[test]
+fn test_colon_in_visual_mode_sorts_the_selection() {
+    let mut editor = TestEditor::new("c\nb\na\n0");
+    editor.keys("Vj:");
+    assert_eq!(editor.status_line(), ":'<,'>");
+    editor.keys("sort<CR>");
+    editor.assert_lines(&["b", "c", "a", "0"]);
+    editor.keys("G:'<,'>sort!<CR>");
+    editor.assert_lines(&["c", "b", "a", "0"]);
+}
+
+#[test]
+fn test_visual_line_selection_is_marked() {
+    let mut editor = TestEditor::new("one\ntwo\nthree");
+    editor.keys("Vj");
+    assert_eq!(editor.status_line(), "-- VISUAL LINE --");
+    assert_eq!(&editor.screen()[1..4], [">one", ">two", " three"]);
+    editor.keys("<Esc>");
+    assert_eq!(editor.mode(), Mode::Normal);
+}
+
+#[test]
+fn test_line_numbers_take_a_gutter() {
+    let mut editor = TestEditor::new("a\nb");
+    editor.keys(":set nu<CR>");
+    assert_eq!(&editor.screen()[1..3], ["  1 a", "  2 b"]);
+    assert_eq!(editor.screen_cursor(), (1, 4));
+}
+
+#[test]
+fn test_scrolling_keeps_cursor_on_screen() {
+    let text: Vec<String> = (1..=20).map(|i| format!("line {i}")).collect();
+    let mut editor = TestEditor::new(&text.join("\n"));
+    editor.keys("G");
+    let screen = editor.screen();
+    assert_eq!(screen[6], "line 20");
+    assert_eq!(screen[1], "line 15");
+    assert_eq!(editor.screen_cursor(), (6, 0));
+}
+
+#[test]
+fn test_buffer_list_popup() {
+    let mut editor = TestEditor::new("text");
+    editor.keys(":ls<CR>");
+    let screen = editor.screen();
+    assert_eq!(screen[5], " Buffers (1)");
+    assert!(screen[6].starts_with("  1 %"), "{}", screen[6]);
+    editor.keys("<Esc>");
+    assert_eq!(editor.mode(), Mode::Normal);
+    assert_eq!(editor.screen()[6], "");
+}
+
+#[test]
+fn test_split_window_draws_both_panes() {
+    let mut editor = TestEditor::new("shared");
+    editor.keys(":split<CR>");
+    let screen = editor.screen();
+    assert_eq!(screen.iter().filter(|row| *row == "shared").count(), 2);
+    assert_eq!(screen.iter().filter(|row| row.trim() == "[No Name]").count(), 2);
+}
+
+#[test]
+fn test_resize_relayouts_screen() {
+    let mut editor = TestEditor::new("abc");
+    editor.resize(20, 4);
+    assert_eq!(editor.screen().len(), 4);
+}
+
+#[test]
+fn test_write_and_quit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "draft\n").unwrap();
+
+    let mut editor = TestEditor::open(&path);
+    assert!(editor.screen()[0].contains("notes.txt"));
+    editor.keys("A final<Esc>:wq<CR>");
+    assert!(editor.has_quit());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft final\n");
+}
+
+#[test]
+fn test_zz_writes_and_zq_discards() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "draft\n").unwrap();
+
+    let mut editor = TestEditor::open(&path);
+    editor.keys("A final<Esc>ZZ");
+    assert!(editor.has_quit());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft final\n");
+
+    let mut editor = TestEditor::open(&path);
+    editor.keys("ddZQ");
+    assert!(editor.has_quit());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft final\n");
+
+    // ZZ asks about other unsaved buffers, as :q does
+    let mut editor = TestEditor::open(&path);
+    let other = dir.path().join("other.txt");
+    editor.keys(&format!(":e {}<CR>inew<Esc>:b1<CR>ZZ", other.display()));
+    assert!(!editor.has_quit());
+    assert_eq!(editor.mode(), Mode::Command);
+    assert!(editor.status_line().contains("Unsaved changes in \"other.txt\""), "{}", editor.status_line());
+    editor.keys("q");
+    assert!(editor.has_quit());
+}
+
+#[test]
+fn test_quit_refuses_with_unsaved_changes() {
+    let mut editor = TestEditor::new("");
+    editor.keys("ichanged<Esc>:q<CR>");
+    assert!(!editor.has_quit());
+    assert!(editor.status_line().starts_with("No write since last change"), "{}", editor.status_line());
+    editor.keys(":q!<CR>");
+    assert!(editor.has_quit());
+}
+
+#[test]
+fn test_cursor_steps_over_whole_graphemes() {
+    // Combining accent, a wide CJK character and a ZWJ emoji sequence
+    let mut editor = TestEditor::new("ae\u{301}漢👨\u{200d}👩\u{200d}👧z");
+    editor.keys("l");
+    assert_eq!(editor.cursor(), (0, 1));
+    assert_eq!(editor.screen_cursor(), (1, 1));
+    editor.keys("l");
+    assert_eq!(editor.cursor(), (0, 4));
+    assert_eq!(editor.screen_cursor(), (1, 2));
+    editor.keys("ll");
+    assert_eq!(editor.screen_cursor(), (1, 6));
+    editor.keys("hh");
+    assert_eq!(editor.cursor(), (0, 4));
+
+    editor.keys("x");
+    editor.assert_lines(&["ae\u{301}👨\u{200d}👩\u{200d}👧z"]);
+    editor.keys("X");
+    editor.assert_lines(&["a👨\u{200d}👩\u{200d}👧z"]);
+    editor.keys("u");
+    editor.assert_lines(&["ae\u{301}👨\u{200d}👩\u{200d}👧z"]);
+}
+
+#[test]
+fn test_insert_and_backspace_multibyte() {
+    let mut editor = TestEditor::new("");
+    editor.keys("içé漢<BS>x<Esc>");
+    editor.assert_lines(&["çéx"]);
+    editor.keys("0l");
+    assert_eq!(editor.cursor(), (0, 2));
+    assert_eq!(editor.screen_cursor(), (1, 1));
+}
+
+#[test]
+fn test_wide_characters_take_two_cells() {
+    let mut editor = TestEditor::with_size("漢字かな\n\tx", (7, 5));
+    // A wide character cut by the edge shows as a truncation marker
+    assert_eq!(editor.screen()[1], "漢字か>");
+    assert_eq!(editor.screen()[2], "    x");
+
+    // Scrolling right never splits a wide character
+    editor.keys("lll");
+    assert_eq!(editor.screen()[1], "<字かな");
+    assert_eq!(editor.screen_cursor(), (1, 5));
+}
+
+#[test]
+fn test_word_motions_and_find_on_multibyte_lines() {
+    let mut editor = TestEditor::new("héllo wörld ñ");
+    editor.keys("w");
+    assert_eq!(editor.cursor(), (0, 7));
+    editor.keys("fñ");
+    assert_eq!(editor.cursor(), (0, 14));
+    editor.keys("b");
+    assert_eq!(editor.cursor(), (0, 7));
+    editor.keys("0e");
+    assert_eq!(editor.cursor(), (0, 5));
+}
+
+#[test]
+fn test_word_motion_stops_on_the_next_line() {
+    let mut editor = TestEditor::new("one two\n  three\n\nfour");
+    editor.keys("ww");
+    assert_eq!(editor.cursor(), (1, 2));
+    editor.keys("w");
+    assert_eq!(editor.cursor(), (2, 0));
+    editor.keys("ggwdw");
+    editor.assert_lines(&["one ", "  three", "", "four"]);
+}
+
+#[test]
+fn test_read_file_by_full_and_short_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("insert.txt");
+    std::fs::write(&path, "inserted").unwrap();
+    let path = path.display();
+
+    let mut editor = TestEditor::new("one\ntwo");
+    editor.keys(&format!(":0read {path}<CR>"));
+    editor.assert_lines(&["inserted", "one", "two"]);
+    editor.keys(&format!(":$r {path}<CR>"));
+    editor.assert_lines(&["inserted", "one", "two", "inserted"]);
+    editor.keys(":r !echo shell<CR>");
+    assert!(editor.lines().contains(&"shell".to_string()));
+}
+
+#[test]
+fn test_shell_filters_and_reads() {
+    let mut editor = TestEditor::new("c\nb\na\nz");
+    editor.keys("Vjj:!sort<CR>");
+    editor.assert_lines(&["a", "b", "c", "z"]);
+    assert_eq!(editor.status_line(), "3 lines filtered");
+    editor.keys("u");
+    editor.assert_lines(&["c", "b", "a", "z"]);
+
+    editor.keys(":2r !echo out<CR>");
+    editor.assert_lines(&["c", "b", "out", "a", "z"]);
+    editor.keys(":%!false<CR>");
+    editor.assert_lines(&["c", "b", "out", "a", "z"]);
+
+    editor.keys(":!echo hi<CR>");
+    assert_eq!(editor.shared().pending_shell_command.as_deref(), Some("echo hi"));
+}
+
+#[test]
+fn test_ctrl_z_and_stop_ask_to_suspend() {
+    // The editor suspends once it can hand the terminal back
+    let mut editor = TestEditor::new("abc");
+    editor.keys("<C-z>");
+    assert!(editor.shared().pending_suspend);
+
+    let mut editor = TestEditor::new("abc");
+    editor.keys(":st<CR>");
+    assert!(editor.shared().pending_suspend);
+    assert_eq!(editor.mode(), Mode::Normal);
+}
+
+#[test]
+fn test_vertical_scroll_commands() {
+    let text: Vec<String> = (1..=30).map(|n| n.to_string()).collect();
+    let mut editor = TestEditor::with_size(&text.join("\n"), (10, 8)); // Six text rows
+
+    // Ctrl-E and Ctrl-Y move the view; the cursor only to stay on screen
+    editor.keys("<C-e>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("2", (1, 0)));
+    editor.keys("3<C-e>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("5", (4, 0)));
+    editor.keys("<C-y>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("4", (4, 0)));
+    assert_eq!(editor.screen_cursor(), (2, 0));
+
+    // Ctrl-D and Ctrl-U move both half a window, keeping the cursor's row
+    editor.keys("<C-d>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("7", (7, 0)));
+    editor.keys("<C-u>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("4", (4, 0)));
+    assert_eq!(editor.screen_cursor(), (2, 0));
+
+    // At the end the view stops with the last line at the bottom
+    editor.keys("25G<C-d>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("23", (27, 0)));
+    editor.keys("<C-d>");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("25", (29, 0)));
+    editor.keys("<C-d>");
+    assert_eq!(editor.cursor(), (29, 0));
+
+    // zt, zz and zb scroll the cursor's line into place
+    editor.keys("15Gzt");
+    assert_eq!((editor.screen()[1].as_str(), editor.screen_cursor()), ("15", (1, 0)));
+    editor.keys("zz");
+    assert_eq!((editor.screen()[1].as_str(), editor.screen_cursor()), ("12", (4, 0)));
+    editor.keys("zb");
+    assert_eq!((editor.screen()[1].as_str(), editor.screen_cursor()), ("10", (6, 0)));
+    editor.keys("ggzb");
+    assert_eq!((editor.screen()[1].as_str(), editor.cursor()), ("1", (0, 0)));
+}
+
+#[test]
+fn test_horizontal_scroll_commands_and_markers() {
+    let mut editor = TestEditor::with_size("0123456789abcdefghij\nshort", (10, 5));
+    assert_eq!(editor.screen()[1], "012345678>");
+
+    // zl drags the cursor along once it would leave the screen
+    editor.keys("3zl");
+    assert_eq!(editor.screen()[1], "<456789ab>");
+    assert_eq!(editor.screen()[2], "<t");
+    assert_eq!(editor.cursor(), (0, 3));
+    assert_eq!(editor.screen_cursor(), (1, 0));
+
+    editor.keys("zL");
+    assert_eq!(editor.screen()[1], "<9abcdefg>");
+    assert_eq!(editor.cursor(), (0, 8));
+
+    // Never further right than the last character of the cursor line
+    editor.keys("20zl");
+    assert_eq!(editor.screen()[1], "<");
+    assert_eq!(editor.cursor(), (0, 19));
+
+    editor.keys("zH");
+    assert_eq!(editor.screen()[1], "<fghij");
+    assert_eq!(editor.cursor(), (0, 19));
+    editor.keys("20zh");
+    assert_eq!(editor.screen()[1], "012345678>");
+    assert_eq!(editor.cursor(), (0, 9));
+}
+
+#[test]
+fn test_mksession_and_source_restore_windows_and_cursors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    std::fs::write(&a, "a1\na2\na3\n").unwrap();
+    std::fs::write(&b, "b1\nb2 word\n").unwrap();
+    let session = dir.path().join("s.vim");
+
+    let mut editor = TestEditor::open(&a);
+    editor.keys(":set nu<CR>:setl tw=20<CR>jj");
+    editor.keys(&format!(":vsplit {}<CR>jw", b.display()));
+    editor.keys(&format!(":mksession {}<CR>", session.display()));
+    assert!(editor.shared().status_message.starts_with("Session saved"));
+    // An existing session file needs !
+    editor.keys(&format!(":mksession {}<CR>", session.display()));
+    assert!(editor.shared().status_message.ends_with("exists (add ! to override)"));
+
+    let mut restored = TestEditor::new("");
+    restored.keys(&format!(":source {}<CR>", session.display()));
+    assert!(restored.shared().status_message.ends_with("sourced"), "{}", restored.shared().status_message);
+    let shared = restored.shared();
+    assert_eq!(shared.session_controller.buffer_count(), 2);
+    assert!(shared.view.get_line_numbers());
+    assert_eq!(shared.view.windows().count(), 2);
+    assert_eq!(restored.document().filename.as_deref(), Some(b.canonicalize().unwrap().as_path()));
+    assert_eq!(restored.cursor(), (1, 3));
+
+    // The other window shows a.txt at its own cursor
+    restored.keys("<C-w>w");
+    assert_eq!(restored.cursor(), (2, 0));
+    assert_eq!(restored.document().text_width, 20);
+}
+
+#[test]
+fn test_source_reports_the_first_failing_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.vim");
+    std::fs::write(&script, "\" comment\nset nu\nbogus\ncall cursor(2, 2)\n").unwrap();
+
+    let mut editor = TestEditor::new("one\ntwo");
+    editor.keys(&format!(":so {}<CR>", script.display()));
+    assert!(editor.shared().status_message.ends_with("line 3: Unknown command: bogus"), "{}", editor.shared().status_message);
+    // Lines after the failure still ran
+    assert_eq!(editor.cursor(), (1, 1));
+    assert!(editor.shared().view.get_line_numbers());
+}
+
+#[test]
+fn test_key_mappings() {
+    let mut editor = TestEditor::new("one\ntwo\nthree");
+    editor.keys(":nnoremap ,d dd<CR>:inoremap jk <lt>Esc><CR>:nmap Q ,d<CR>");
+    editor.keys(",d");
+    editor.assert_lines(&["two", "three"]);
+    editor.keys("Q");
+    editor.assert_lines(&["three"]);
+
+    // jk leaves insert mode; a j on its own is typed once the next key shows it isn't jk
+    editor.keys("Ajanjk");
+    assert_eq!(editor.mode(), Mode::Normal);
+    editor.assert_lines(&["threejan"]);
+
+    editor.keys(":nmap<CR>");
+    assert!(editor.shared().status_message.contains(",d         *dd"), "{}", editor.shared().status_message);
+    editor.keys(":nunmap ,d<CR>,d");
+    editor.assert_lines(&["threejan"]);
+}
+
+#[test]
+fn test_shiftwidth_sets_indent_step() {
+    let mut editor = TestEditor::new("a\nb");
+    editor.keys(":set sw=2<CR>>>j>>>>");
+    editor.assert_lines(&["  a", "    b"]);
+    editor.keys(":set sw=0<CR><<");
+    editor.assert_lines(&["  a", "b"]);
+}
+
+#[test]
+fn test_shift_counts_shiftround_and_autoindent() {
+    let mut editor = TestEditor::new("a\nb\nc");
+    editor.keys(":set sw=2<CR>2>>");
+    editor.assert_lines(&["  a", "  b", "c"]);
+    editor.keys("Vj3>");
+    editor.assert_lines(&["        a", "        b", "c"]);
+    editor.keys("u");
+    editor.assert_lines(&["  a", "  b", "c"]);
+
+    editor.keys("Gi <Esc>:set sr<CR>>>");
+    editor.assert_lines(&["  a", "  b", "  c"]);
+
+    editor.keys(":set ai<CR>A<CR>d<Esc>od<Esc>");
+    editor.assert_lines(&["  a", "  b", "  c", "  d", "  d"]);
+}
+
+#[test]
+fn test_set_takes_several_options() {
+    let mut editor = TestEditor::new("a\n b");
+    editor.keys(":set sw=2 sr<CR>j>>");
+    editor.assert_lines(&["a", "  b"]);
+
+    // An unknown option stops the line there
+    editor.keys(":set sw=4 bogus sw=6<CR>");
+    assert_eq!(editor.status_line(), "Unknown option: bogus");
+    assert_eq!(editor.document().shift_width, 4);
+}
+
+#[test]
+fn test_gq_formats_to_textwidth() {
+    let mut editor = TestEditor::new("// one two\n// three four five\n\nsix seven eight");
+    editor.keys(":set tw=14<CR>gqj");
+    editor.assert_lines(&["// one two", "// three four", "// five", "", "six seven eight"]);
+    assert_eq!(editor.cursor(), (2, 0));
+    editor.keys("uGgqq");
+    editor.assert_lines(&["// one two", "// three four five", "", "six seven", "eight"]);
+    editor.keys("ggVjgq");
+    editor.assert_lines(&["// one two", "// three four", "// five", "", "six seven", "eight"]);
+
+    // Typing past the margin breaks the line
+    let mut editor = TestEditor::new("    x");
+    editor.keys(":set tw=10<CR>A abc def<Esc>");
+    editor.assert_lines(&["    x abc", "    def"]);
+    editor.keys("u");
+    editor.assert_lines(&["    x"]);
+
+    // Tab indentation takes its columns, and spacing within a line is kept
+    let mut editor = TestEditor::new("\t\taaa bbb ccc ddd\nx  y   z");
+    editor.keys(":set tw=12<CR>:set tabstop=8<CR>gqq");
+    editor.assert_lines(&["\t\taaa", "\t\tbbb", "\t\tccc", "\t\tddd", "x  y   z"]);
+    editor.keys("u:set tw=16<CR>:set tabstop=4<CR>gqq");
+    editor.assert_lines(&["\t\taaa bbb", "\t\tccc ddd", "x  y   z"]);
+    editor.keys("uGgqq");
+    editor.assert_lines(&["\t\taaa bbb ccc ddd", "x  y   z"]);
+    let mut editor = TestEditor::new("\tx");
+    editor.keys(":set tw=12<CR>:set tabstop=8<CR>A abc<Esc>");
+    editor.assert_lines(&["\tx", "\tabc"]);
+}
+
+#[test]
+fn test_autopairs_and_surround() {
+    let mut editor = TestEditor::new("");
+    editor.keys(":set autopairs<CR>if(x<Esc>");
+    editor.assert_lines(&["f(x)"]);
+    editor.keys("i)<BS><BS>[\"a\"] don't<Esc>");
+    editor.assert_lines(&["f([\"a\"] don't"]);
+    editor.keys("a<BS>[<BS>y<Esc>");
+    editor.assert_lines(&["f([\"a\"] don'y"]);
+
+    let mut editor = TestEditor::new("call(say \"hi\", there)");
+    editor.keys("fhcs\"'");
+    editor.assert_lines(&["call(say 'hi', there)"]);
+    editor.keys("ds)");
+    editor.assert_lines(&["callsay 'hi', there"]);
+    editor.keys("0ysw(");
+    editor.assert_lines(&["( callsay ) 'hi', there"]);
+    editor.keys("ds(yssB");
+    editor.assert_lines(&["{callsay 'hi', there}"]);
+    editor.keys("uu");
+    editor.assert_lines(&["( callsay ) 'hi', there"]);
+}
+
+#[test]
+fn test_insert_mode_control_keys() {
+    let mut editor = TestEditor::new("keep");
+    editor.keys("A foo.bar baz<C-w><C-w>qux<Esc>");
+    editor.assert_lines(&["keep foo.qux"]);
+    editor.keys("u");
+    editor.assert_lines(&["keep"]); // One undo step for the whole insert
+    editor.keys("<C-r>");
+    editor.assert_lines(&["keep foo.qux"]);
+    editor.keys("A more<C-u>!<Esc>");
+    editor.assert_lines(&["keep foo.qux!"]);
+    editor.keys("ox<C-u><C-u>y<Esc>");
+    editor.assert_lines(&["keep foo.qux!y"]);
+
+    editor.keys(":set sw=2<CR>o<C-t>a<C-t>b<C-d>c<Esc>");
+    editor.assert_lines(&["keep foo.qux!y", "  abc"]);
+
+    editor.keys("k0\"ayw$a<C-r>a<C-r>q<Esc>");
+    editor.assert_lines(&["keep foo.qux!ykeep ", "  abc"]);
+    assert!(editor.shared().status_message.contains("empty"), "{}", editor.shared().status_message);
+}
+
+#[test]
+fn test_insert_literal_keys_codes_and_digraphs() {
+    let mut editor = TestEditor::new("");
+    editor.keys(":set et<CR>i<C-v><Tab>x<C-v>u00e9<C-k>a:<C-v>u41<Esc>");
+    editor.assert_lines(&["\txéäA"]);
+    assert_eq!(editor.mode(), Mode::Normal);
+
+    // One undo step, like the rest of the insert
+    editor.keys("u");
+    editor.assert_lines(&[""]);
+}
+
+#[test]
+fn test_showmatch_flashes_the_opened_bracket() {
+    let mut editor = TestEditor::new("");
+    editor.keys(":set sm<CR>:set mat=2<CR>if(a[b]");
+    assert_eq!(editor.shared().show_match.flash, Some((0, 3)));
+    editor.keys("c");
+    assert_eq!(editor.shared().show_match.flash, None); // The next key ends it
+    editor.keys(")");
+    assert_eq!(editor.shared().show_match.flash, Some((0, 1)));
+    editor.idle_for(Duration::from_millis(300));
+    assert_eq!(editor.shared().show_match.flash, None);
+
+    // Off the screen, the line is shown instead
+    editor.keys("{<CR>").keys(&"x<CR>".repeat(10));
+    editor.screen();
+    editor.keys("}");
+    assert_eq!(editor.shared().show_match.flash, None);
+    assert_eq!(editor.shared().status_message, "1: f(a[b]c){");
+}
+
+#[test]
+fn test_showcmd_and_timeout_for_commands_typed_in_part() {
+    let mut editor = TestEditor::with_size("one two three four five six seven\neight", (40, 5));
+    editor.keys(":set showcmd<CR>\"a2d3");
+    assert_eq!(editor.status_line(), "Commands typed in part are s \"a2d3");
+    editor.keys("w");
+    editor.assert_lines(&["seven", "eight"]);
+    assert!(!editor.status_line().ends_with("\"a2d3"));
+
+    // Without 'timeout' a pending operator waits however long typing pauses
+    editor.keys("u0d");
+    editor.idle_for(Duration::from_secs(5));
+    editor.keys("w");
+    editor.assert_lines(&["two three four five six seven", "eight"]);
+
+    // With it, the key after the pause starts a command of its own
+    editor.keys(":set timeout<CR>:set tm=200<CR>d");
+    assert!(editor.status_line().ends_with(" d"));
+    editor.idle_for(Duration::from_millis(100));
+    editor.keys("2");
+    editor.idle_for(Duration::from_millis(300));
+    editor.keys("w");
+    editor.assert_lines(&["two three four five six seven", "eight"]);
+    assert_eq!(editor.cursor(), (0, 4));
+    assert_eq!(editor.status_line(), "timeoutlen=200"); // Nothing left pending
+}
+
+#[test]
+fn test_autosave_on_leaving_buffer_and_losing_focus() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    std::fs::write(&first, "one\n").unwrap();
+    std::fs::write(&second, "two\n").unwrap();
+    let mut editor = TestEditor::open(&first);
+    editor.keys(&format!(":e {}<CR>", second.display()));
+    editor.keys(":bp<CR>x:bn<CR>");
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "one\n"); // Off
+
+    editor.keys(":set autosave<CR>:bp<CR>:bn<CR>");
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "ne\n");
+    assert!(editor.shared().status_message.ends_with("autosaved)"), "{}", editor.shared().status_message);
+
+    editor.keys(":bp<CR>x");
+    editor.lose_focus();
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "e\n");
+    assert!(!editor.shared().session_controller.current_document().is_modified());
+}
+
+#[test]
+fn test_timers_run_while_idle() {
+    // A key that starts a mapping stands for itself once typing pauses
+    let mut editor = TestEditor::new("one two");
+    editor.keys(":nnoremap dwx dd<CR>dw");
+    editor.idle_for(Duration::from_millis(100));
+    editor.assert_lines(&["one two"]); // Still waiting for the x
+    editor.idle_for(Duration::from_secs(2));
+    editor.assert_lines(&["two"]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "one\n").unwrap();
+    let mut editor = TestEditor::open(&path);
+    editor.keys(":set autosave=10<CR>x");
+    editor.idle_for(Duration::from_secs(5));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+    editor.idle_for(Duration::from_secs(11));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "ne\n");
+    assert!(editor.shared().status_message.ends_with("autosaved"), "{}", editor.shared().status_message);
+}
+
+#[test]
+fn test_bracketed_paste_is_one_insert() {
+    let mut editor = TestEditor::new("start end");
+    editor.keys("wi").paste("one\r\ntwo\nthree ");
+    assert_eq!(editor.mode(), Mode::Insert);
+    editor.keys("x<Esc>");
+    editor.assert_lines(&["start one", "two", "three xend"]);
+    editor.keys("u");
+    editor.assert_lines(&["start end"]); // The paste and the typing are one insert
+
+    // In normal mode it goes before the cursor, as its own change
+    editor.keys("0").paste("a\nb");
+    assert_eq!(editor.mode(), Mode::Normal);
+    editor.assert_lines(&["a", "bstart end"]);
+    assert_eq!(editor.cursor(), (1, 0));
+    editor.keys("u");
+    editor.assert_lines(&["start end"]);
+
+    // The command line takes it as one line
+    editor.keys(":").paste("s/end/\nfinish\n");
+    editor.keys("/<CR>");
+    editor.assert_lines(&["start  finish"]);
+}
+
+#[test]
+fn test_history_prompts_and_windows() {
+    let mut editor = TestEditor::new("one two\nthree two\none");
+    editor.keys(":s/one/1/<CR>:set nu<CR>jj:s/one/ONE/<CR>");
+    editor.assert_lines(&["1 two", "three two", "ONE"]);
+
+    // Up finds older lines starting with what was typed, Down comes back
+    editor.keys("gg:s/<Up><Up><CR>");
+    editor.assert_lines(&["1 two", "three two", "ONE"]); // :s/one/1/ finds nothing on line 1
+    editor.keys(":<Up><Up><Down><Esc>");
+    assert_eq!(editor.mode(), Mode::Normal);
+    assert_eq!(editor.shared().history.entries(HistoryKind::Command), ["set nu", "s/one/ONE/", "s/one/1/"]);
+
+    editor.keys("/two<CR>/three<CR>gg/<Up><Up><CR>");
+    assert_eq!(editor.cursor(), (0, 2));
+
+    // q: lists the history to edit and run from
+    editor.keys("q:");
+    editor.assert_lines(&["set nu", "s/one/ONE/", "s/one/1/", ""]);
+    assert_eq!(editor.cursor(), (3, 0));
+    editor.keys("kccs/1/first/<Esc><CR>");
+    editor.assert_lines(&["first two", "three two", "ONE"]);
+    assert_eq!(editor.shared().session_controller.buffer_count(), 1);
+    assert_eq!(editor.shared().history.entries(HistoryKind::Command).last().unwrap(), "s/1/first/");
+
+    editor.keys("gg0q/kk<CR>"); // Searches: three, two
+    editor.assert_lines(&["first two", "three two", "ONE"]);
+    assert_eq!(editor.cursor(), (1, 0));
+    editor.keys("q:<CR>"); // The empty line: nothing to run
+    assert_eq!(editor.shared().session_controller.buffer_count(), 1);
+}
+
+#[test]
+fn test_normal_command_types_keys() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys(":%norm A;<CR>");
+    editor.assert_lines(&["one;", "two;", "three;", "four;"]);
+    editor.keys("u");
+    editor.assert_lines(&["one", "two", "three", "four"]); // One undo step
+
+    // Without a range, at the cursor; special keys in <> notation
+    editor.keys("ll:normal i[<lt>Esc>la]<CR>");
+    editor.assert_lines(&["on[e]", "two", "three", "four"]);
+    assert_eq!(editor.mode(), Mode::Normal);
+
+    // Lines deleted by the keys don't throw the later lines off
+    editor.keys(":g/t/norm dd<CR>");
+    editor.assert_lines(&["on[e]", "four"]);
+
+    // A selection's lines; an unfinished count or operator is dropped
+    editor.keys("ggVj:norm 2d<CR>");
+    editor.assert_lines(&["on[e]", "four"]);
+    editor.keys("ggVj:norm I-<lt>Space><CR>");
+    editor.assert_lines(&["- on[e]", "- four"]);
+    assert_eq!(editor.cursor().0, 1);
+}
+
+#[test]
+fn test_normal_applies_mappings_unless_banged() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys(":nnoremap x dd<CR>:inoremap jk <lt>Esc><CR>");
+    editor.keys(":normal x<CR>");
+    editor.assert_lines(&["two", "three", "four"]);
+    editor.keys(":normal! x<CR>");
+    editor.assert_lines(&["wo", "three", "four"]);
+    editor.keys(":2norm AjkX<CR>");
+    editor.assert_lines(&["wo", "thre", "four"]);
+    editor.keys(":2norm! AjkX<CR>");
+    editor.assert_lines(&["wo", "threjkX", "four"]);
+}
+
+#[test]
+fn test_paste_follows_register_type_and_count() {
+    // Whole lines go below or above wherever the cursor is
+    let mut editor = TestEditor::new("  one\ntwo");
+    editor.keys("yyj$2p");
+    editor.assert_lines(&["  one", "two", "  one", "  one"]);
+    assert_eq!(editor.cursor(), (2, 2));
+    editor.keys("u0P");
+    editor.assert_lines(&["  one", "  one", "two"]);
+
+    // Text within a line goes after the cursor, count times
+    let mut editor = TestEditor::new("ab");
+    editor.keys("yl3p");
+    editor.assert_lines(&["aaaab"]);
+
+    // A block goes in as a column, padding short lines and adding new ones
+    let mut editor = TestEditor::new("abc\ndef\ngh");
+    editor.keys("l<C-v>jly");
+    editor.keys("G$p");
+    editor.assert_lines(&["abc", "def", "ghbc", "  ef"]);
+    editor.keys("u");
+    editor.assert_lines(&["abc", "def", "gh"]);
+    editor.keys("gg02P");
+    editor.assert_lines(&["bcbcabc", "efefdef", "gh"]);
+    assert_eq!(editor.cursor(), (0, 0));
+
+    let mut editor = TestEditor::new("x\nlong line");
+    editor.keys("A12<Esc>0<C-v>jly"); // Rows "x1" and "lo"
+    editor.keys("ggP");
+    editor.assert_lines(&["x1x12", "lolong line"]);
+}
+
+#[test]
+fn test_block_yank_and_paste_line_up_on_screen() {
+    // A block covers screen columns, whatever bytes or cells the characters take
+    let mut editor = TestEditor::new("éab\nxab\na世b\nxyzw");
+    editor.keys("0l<C-v>jy0P");
+    editor.assert_lines(&["abéab", "abxab", "a世b", "xyzw"]);
+    editor.keys("ujj0l<C-v>jd");
+    editor.assert_lines(&["éab", "xab", "ab", "xw"]);
+
+    // A tab the paste column falls inside is split into spaces
+    let mut editor = TestEditor::new("ab\ncd\nxy\n\tz");
+    editor.keys("<C-v>jly");
+    editor.keys("jj0p");
+    editor.assert_lines(&["ab", "cd", "xaby", " cd   z"]);
+    assert_eq!(editor.cursor(), (2, 1));
+
+    // Rows go in at the same screen column below a wide character
+    let mut editor = TestEditor::new("世x\nab\ncd");
+    editor.keys("j<C-v>jy");
+    editor.keys("ggl");
+    editor.keys("P");
+    editor.assert_lines(&["世ax", "abc", "cd"]);
+
+    // Repeated copies pad each row out to the block's screen width
+    let mut editor = TestEditor::new("世\na\n|\n|");
+    editor.keys("<C-v>j$y");
+    editor.keys("jj2P");
+    editor.assert_lines(&["世", "a", "世世|", "a a |"]);
+}
+
+#[test]
+fn test_list_draws_listchars_without_moving_the_cursor() {
+    let mut editor = TestEditor::new("\tx y  \na\u{a0}b\n");
+    editor.keys(":set list<CR>");
+    let screen = editor.screen();
+    assert_eq!(&screen[1..5], ["»···x y··$", "a␣b$", "$", ""]);
+    editor.keys("0fy");
+    assert_eq!(editor.screen_cursor(), (1, 6));
+
+    editor.keys(":set lcs=tab:>-,space:.<CR>");
+    assert_eq!(editor.screen()[1], ">---x.y..");
+    editor.keys(":set listchars=tab:>");
+    editor.keys("<CR>");
+    assert!(editor.status_line().contains("Invalid listchars item"));
+    editor.keys(":set nolist<CR>");
+    assert_eq!(editor.screen()[1], "    x y");
+}
+
+#[test]
+fn test_trim_trailing_whitespace() {
+    let mut editor = TestEditor::new("one  \ntwo\t\nthree \t \nfour ");
+    editor.keys(":2,3trim<CR>");
+    editor.assert_lines(&["one  ", "two", "three", "four "]);
+    assert_eq!(editor.status_line().trim_end(), "2 lines trimmed");
+    editor.keys(":trim<CR>");
+    editor.assert_lines(&["one", "two", "three", "four"]);
+    editor.keys("u");
+    editor.assert_lines(&["one  ", "two", "three", "four "]); // One undo step
+
+    // With trimtrailing, writing strips every line as one undo step
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "keep\n").unwrap();
+    let mut editor = TestEditor::open(&path);
+    editor.keys(":set trimtrailing<CR>");
+    editor.keys("A  <Esc>oend\t<Esc>:w<CR>");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep\nend\n");
+    editor.keys("u");
+    editor.assert_lines(&["keep  ", "end\t", ""]);
+}
+
+#[test]
+fn test_join_with_a_count() {
+    let mut editor = TestEditor::new("one\n    two\nthree\nfour");
+    editor.keys("3J");
+    editor.assert_lines(&["one two three", "four"]);
+    assert_eq!(editor.cursor(), (0, 3));
+    editor.keys("u");
+    editor.assert_lines(&["one", "    two", "three", "four"]);
+
+    editor.keys("3gJ");
+    editor.assert_lines(&["one    twothree", "four"]);
+    assert_eq!(editor.cursor(), (0, 3));
+
+    // A count past the end joins what is there
+    editor.keys("9J");
+    editor.assert_lines(&["one    twothree four"]);
+    editor.keys("J");
+    assert_eq!(editor.status_line(), "Cannot join: at last line");
+}
+
+#[test]
+fn test_visual_replace_join_and_put() {
+    let mut editor = TestEditor::new("hello world\none\n  two\nthree");
+    editor.keys("vllr*");
+    editor.assert_lines(&["***lo world", "one", "  two", "three"]);
+    editor.keys("jVjJ");
+    editor.assert_lines(&["***lo world", "one two", "three"]);
+    editor.keys("u");
+    editor.assert_lines(&["***lo world", "one", "  two", "three"]);
+
+    // p swaps the selection for the register and keeps what it replaced; P doesn't
+    let mut editor = TestEditor::new("foo bar baz");
+    editor.keys("yewvep");
+    editor.assert_lines(&["foo foo baz"]);
+    editor.keys("wveP");
+    editor.assert_lines(&["foo foo bar"]);
+    editor.keys("0vep");
+    editor.assert_lines(&["bar foo bar"]);
+    editor.keys("u");
+    editor.assert_lines(&["foo foo bar"]); // One undo step
+    editor.keys("0\"aylyw0wwlv\"ap");
+    editor.assert_lines(&["foo foo bfr"]);
+
+    // Lines replace lines; lines put over text go between its halves
+    let mut editor = TestEditor::new("a\nb\nc d");
+    editor.keys("yyjVp");
+    editor.assert_lines(&["a", "a", "c d"]);
+    editor.keys("Gllvp");
+    editor.assert_lines(&["a", "a", "c ", "b", ""]);
+    editor.keys("ggVGp");
+    editor.assert_lines(&["d"]);
+
+    // A line of text goes on every row of a block
+    let mut editor = TestEditor::new("x1\nx2\nx3");
+    editor.keys("lyl0<C-v>jjp");
+    editor.assert_lines(&["11", "12", "13"]);
+}
+
+#[test]
+fn test_reselect_and_swap_selection_ends() {
+    let mut editor = TestEditor::new("one two\nthree four\nfive");
+    editor.keys("wvjy");
+    editor.keys("Ggv");
+    assert_eq!((editor.mode(), editor.cursor()), (Mode::VisualChar, (1, 4)));
+    editor.keys("o");
+    assert_eq!(editor.cursor(), (0, 4));
+    editor.keys("d");
+    editor.assert_lines(&["one  four", "five"]);
+
+    // O goes to the other corner of a block's row; gv in visual mode swaps back
+    let mut editor = TestEditor::new("abcd\nefgh\nijkl");
+    editor.keys("l<C-v>jlO");
+    assert_eq!(editor.cursor(), (1, 1));
+    editor.keys("<Esc>Vgv");
+    assert_eq!((editor.mode(), editor.cursor()), (Mode::VisualBlock, (1, 1)));
+    editor.keys("gv");
+    assert_eq!(editor.mode(), Mode::VisualLine);
+    editor.keys("<Esc>gvd"); // Esc kept the line selection
+    editor.assert_lines(&["abcd", "ijkl"]);
+
+    // Each buffer keeps its own
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    std::fs::write(&first, "one\n").unwrap();
+    std::fs::write(&second, "two\n").unwrap();
+    let mut editor = TestEditor::open(&first);
+    editor.keys("vly");
+    editor.keys(&format!(":e {}<CR>gv", second.display()));
+    assert_eq!(editor.mode(), Mode::Normal);
+    // \%V in a search is the buffer's own selection too
+    editor.keys("/\\%Vw<CR>");
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys(":bp<CR>/\\%Vn<CR>");
+    assert_eq!(editor.cursor(), (0, 1));
+    editor.keys("0/\\%Ve<CR>");
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys("gvd");
+    editor.assert_lines(&["e", ""]);
+
+    // A selection the text has shrunk under is put back onto characters
+    let mut editor = TestEditor::new("aé\naé");
+    editor.keys("lvj<Esc>ggxjxgvd");
+    editor.assert_lines(&[""]);
+    for (keys, lines) in [("gvd", &["", ""][..]), ("gvJ", &["é é"]), ("gv~", &["É", "É"])] {
+        let mut editor = TestEditor::new("aé\naé");
+        editor.keys("l<C-v>j<Esc>ggxjx");
+        editor.keys(keys);
+        editor.assert_lines(lines);
+    }
+}
+
+#[test]
+fn test_substitute_and_search_within_selection() {
+    let mut editor = TestEditor::new("foo x foo\nfoo x foo");
+    editor.keys("wvj:s/foo/F/g<CR>");
+    editor.assert_lines(&["foo x F", "F x foo"]);
+    editor.keys("u:%s/foo/F/g<CR>");
+    editor.assert_lines(&["F x F", "F x F"]);
+
+    // / in visual mode finds only what the selection holds, and n keeps to it
+    let mut editor = TestEditor::new("a foo foo\nfoo");
+    editor.keys("wwve/foo<CR>");
+    assert_eq!((editor.mode(), editor.cursor()), (Mode::Normal, (0, 6)));
+    editor.keys("n");
+    assert_eq!(editor.cursor(), (0, 6));
+    editor.keys("gg/foo<CR>n");
+    assert_eq!(editor.cursor(), (0, 6));
+    editor.keys("n");
+    assert_eq!(editor.cursor(), (1, 0));
+}
+
+#[test]
+fn test_substitute_groups_case_and_delimiters() {
+    let mut editor = TestEditor::new("width=10\nheight=20\npath a/b");
+    editor.keys(":%s/^(\\w+)=(\\d+)$/\\U\\1\\E: $2 (&)/<CR>");
+    editor.assert_lines(&["WIDTH: 10 (width=10)", "HEIGHT: 20 (height=20)", "path a/b"]);
+    editor.keys("G:s#a/b#\\u&/c#<CR>");
+    editor.assert_lines(&["WIDTH: 10 (width=10)", "HEIGHT: 20 (height=20)", "path A/b/c"]);
+    editor.keys(":s/[/x/<CR>");
+    assert!(editor.shared().status_message.starts_with("Invalid substitute pattern"), "{}", editor.shared().status_message);
+}
+
+#[test]
+fn test_ignorecase_and_smartcase() {
+    let mut editor = TestEditor::new("x Foo foo FOO");
+    editor.keys("/foo<CR>");
+    assert_eq!(editor.cursor(), (0, 6)); // Case matters by default
+    editor.keys(":set ic<CR>0/foo<CR>");
+    assert_eq!(editor.cursor(), (0, 2));
+    editor.keys(":set noic<CR>0/FOO\\c<CR>");
+    assert_eq!(editor.cursor(), (0, 2));
+
+    editor.keys(":set ic<CR>:set scs<CR>0/FOO<CR>");
+    assert_eq!(editor.cursor(), (0, 10));
+    editor.keys(":s/Foo/bar/g<CR>");
+    editor.assert_lines(&["x bar foo FOO"]);
+    editor.keys(":s/fo\\C/x/<CR>:s/FOO/y/I<CR>");
+    editor.assert_lines(&["x bar xo y"]);
+}
+
+#[test]
+fn test_star_searches_whole_words() {
+    let mut editor = TestEditor::new("foo food xfoo foo\nfoo");
+    editor.keys("*");
+    assert_eq!(editor.cursor(), (0, 14));
+    assert_eq!(editor.shared().search_state.pattern, "\\<foo\\>");
+    assert_eq!(editor.shared().search_state.highlighted_matches().len(), 3);
+    editor.keys("n");
+    assert_eq!(editor.cursor(), (1, 0));
+
+    // g* finds the word inside others too; # goes back from the word's start
+    editor.keys("ggg*");
+    assert_eq!(editor.cursor(), (0, 4));
+    editor.keys("Gl#");
+    assert_eq!(editor.cursor(), (0, 14));
+    editor.keys("g#");
+    assert_eq!(editor.cursor(), (0, 10));
+    assert_eq!(editor.shared().history.entries(HistoryKind::Search), ["\\<foo\\>", "foo"]);
+}
+
+
+#[test]
+fn test_edit_places_the_cursor_at_a_position() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "fn main() {\n    let x = 1;\n}\n").unwrap();
+    let mut editor = TestEditor::new("");
+    editor.keys(&format!(":e {} +2<CR>", path.display()));
+    assert_eq!(editor.cursor(), (1, 4));
+    editor.keys(&format!(":e +1 {}<CR>", path.display()));
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys(&format!(":e {}:2:9:<CR>", path.display()));
+    assert_eq!(editor.cursor(), (1, 8));
+    assert_eq!(editor.shared().session_controller.buffers.len(), 2);
+}
+
+#[test]
+fn test_scripted_session_through_the_event_loop() {
+    let mut editor = TestEditor::new("first\nsecond");
+    editor.keys("ddp");
+    editor.assert_lines(&["second", "first"]);
+    assert_eq!(editor.register('"').as_deref(), Some("first")); // Linewise, so no line break
+    assert_eq!(editor.register('1').as_deref(), Some("first"));
+
+    let drawn = editor.run("ggihello <Esc>0\"ayw:q!<CR>");
+    assert!(editor.has_quit());
+    assert!(drawn.contains("hello second"));
+    editor.assert_lines(&["hello second", "first"]);
+    assert_eq!(editor.register('a').as_deref(), Some("hello "));
+}
+
+#[test]
+fn test_diff_mode_jumps_between_and_copies_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("new.txt");
+    std::fs::write(&path, "a\nx\nc\nd\ne").unwrap();
+    let mut editor = TestEditor::with_size("a\nb\nc\nd", (41, 8));
+    editor.keys(&format!(":diffsplit {}<CR>", path.display()));
+    assert_eq!(editor.status_line(), "2 changes");
+
+    // The old text on the left, with filler where only the new one has a line
+    let screen = editor.screen();
+    assert_eq!(screen[2], format!("{:<20}│x", "b"));
+    assert_eq!(screen[5], format!("{}│e", "-".repeat(20)));
+
+    editor.keys("]c");
+    assert_eq!(editor.cursor(), (1, 0));
+    editor.keys("do]c");
+    assert_eq!(editor.cursor(), (3, 0));
+    editor.keys("do");
+    editor.assert_lines(&["a", "x", "c", "d", "e"]);
+    editor.keys("gg]c");
+    assert_eq!(editor.status_line(), "No more changes");
+
+    // Putting goes the other way
+    editor.keys("ggcwz<Esc>dp");
+    assert_eq!(editor.shared().session_controller.buffers[1].get_line(0).as_deref(), Some("z"));
+    editor.keys(":diffoff<CR>]c");
+    assert_eq!(editor.status_line(), "Not in diff mode");
+}
+
+#[test]
+fn test_git_signs_refresh_on_write_and_gblame_lines_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(["-c", "user.name=Ann", "-c", "user.email=ann@example.com"])
+            .args(args)
+            .current_dir(dir.path())
+            .output()
+            .is_ok_and(|output| output.status.success())
+    };
+    if !git(&["init", "-q"]) {
+        return; // No git to test with
+    }
+    let path = dir.path().join("file.txt");
+    std::fs::write(&path, "a\nb\nc\n").unwrap();
+    assert!(git(&["add", "file.txt"]) && git(&["commit", "-qm", "first"]));
+
+    let mut editor = TestEditor::open(&path);
+    assert!(editor.shared().session_controller.current_document().git_signs.is_empty());
+    editor.keys("ggcwA<Esc>3Gox<Esc>:w<CR>");
+    let screen = editor.screen();
+    assert_eq!(screen[1], "~ A");
+    assert_eq!(screen[2], "  b");
+    assert_eq!(screen[4], "+ x");
+
+    // Blame goes by the buffer, so the unsaved line is there too
+    editor.keys("oy<Esc>:Gblame<CR>");
+    let blame: Vec<String> = (0..5).filter_map(|line| editor.shared().session_controller.current_document().get_line(line)).collect();
+    assert!(blame[1].ends_with(" Ann") && blame[1].len() == 23, "{:?}", blame);
+    assert!(blame[4].starts_with("00000000 "), "{:?}", blame);
+    assert_eq!(editor.cursor(), (4, 0));
+    assert!(editor.shared().view.windows().windows().iter().all(|window| window.scroll_bind));
+}
+
+#[test]
+fn test_tags_jump_to_definitions_and_back() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    let main = dir.path().join("src/main.rs");
+    std::fs::write(&main, "fn main() {\n    helper();\n}").unwrap();
+    std::fs::write(dir.path().join("src/util.rs"), "// util\n  fn helper() {}\nfn helper() {}").unwrap();
+    std::fs::write(
+        dir.path().join("tags"),
+        "!_TAG_FILE_SORTED\t1\t//\nhelper\tsrc/util.rs\t/^  fn helper() {}$/;\"\tf\nhelper\tsrc/util.rs\t3;\"\tf\nmain\tsrc/main.rs\t/^fn main() {$/;\"\tf\n",
+    )
+    .unwrap();
+    let name = |editor: &TestEditor| {
+        let doc = editor.shared().session_controller.current_document();
+        doc.filename.as_ref().unwrap().file_name().unwrap().to_string_lossy().to_string()
+    };
+
+    // Ctrl-] goes to the first definition, Ctrl-T comes back
+    let mut editor = TestEditor::open(&main);
+    editor.keys("jw<C-]>");
+    assert_eq!((name(&editor), editor.cursor()), ("util.rs".to_string(), (1, 2)));
+    assert_eq!(editor.status_line(), "tag 1 of 2, :tselect to choose");
+    editor.keys("<C-t>");
+    assert_eq!((name(&editor), editor.cursor()), ("main.rs".to_string(), (1, 4)));
+    editor.keys("<C-t>");
+    assert_eq!(editor.status_line(), "At bottom of tag stack");
+
+    // :tselect picks another, :tag goes by name
+    editor.keys(":ts helper<CR>j<CR>");
+    assert_eq!((name(&editor), editor.cursor()), ("util.rs".to_string(), (2, 0)));
+    editor.keys(":tag main<CR>:tag nothing<CR>");
+    assert_eq!(editor.status_line(), "tag not found: nothing");
+    assert_eq!((name(&editor), editor.cursor()), ("main.rs".to_string(), (0, 0)));
+    editor.keys(":pop 2<CR>");
+    assert_eq!((name(&editor), editor.cursor()), ("main.rs".to_string(), (1, 4)));
+}
+
+#[test]
+fn test_language_server_diagnostics_hover_and_gd() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "fn main() {\n    let x = y;\n    x\n}").unwrap();
+    let uri = crate::lsp::client::path_to_uri(&std::path::absolute(&path).unwrap());
+    let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let diagnostic = r#"{"range":{"start":{"line":1,"character":12},"end":{"line":1,"character":13}},"severity":1,"message":"cannot find value `y`"}"#;
+    let replies = frame(r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{}}}"#.to_string())
+        + &frame(format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{{"uri":"{}","diagnostics":[{}]}}}}"#,
+            uri, diagnostic
+        ));
+    let server = dir.path().join("server");
+    std::fs::write(&server, replies).unwrap();
+
+    // A server that says all it has to say at once, then exits
+    let mut editor = TestEditor::open(&path);
+    editor.keys(&format!(":lsp rust cat {}<CR>", server.display()));
+    for _ in 0..100 {
+        if editor.status_line().contains("exited") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        editor.idle_for(Duration::from_millis(60));
+    }
+    assert_eq!(editor.status_line(), "Language server for rust exited");
+    assert_eq!(editor.document().diagnostics[0].start, (1, 12));
+    assert!(editor.screen().iter().any(|row| row == "E>    let x = y;"));
+
+    // K shows the diagnostics on the line until the next key
+    editor.keys("jK");
+    assert!(editor.screen().iter().any(|row| row.contains("E> cannot find value `y`")));
+    editor.keys("l");
+    assert!(!editor.screen().iter().any(|row| row.contains("cannot find value")));
+
+    // Without a running server gd goes to the first use of the word
+    editor.keys("jwgd");
+    assert_eq!(editor.cursor(), (1, 8));
+}
+
+#[test]
+fn test_each_command_undoes_in_one_step() {
+    let text = "  alpha beta\n\tdelta (épsilon)\neta";
+    for script in [
+        "snew<Esc>", "3snew<Esc>", "onew<Esc>", "Onew<Esc>", "o<Esc>", "2onew<Esc>", "Go<Esc>",
+        ":set ai<CR>onew<Esc>", ":ascii<CR>", ":r !echo hi<CR>", ":0r !echo hi<CR>", "dw", "3J", "cwx<Esc>",
+        "ahi<CR>there<Esc>", "i<BS><BS><Esc>", ":%d<CR>", ":1,3d<CR>", ":2d<CR>", ":g/./d<CR>", "ggdG", "Vjjd",
+    ] {
+        let mut editor = TestEditor::new(text);
+        editor.keys("jw");
+        let before = editor.lines();
+        editor.keys(script);
+        let after = editor.lines();
+        assert_ne!(after, before, "{script}");
+        editor.keys("u");
+        assert_eq!(editor.lines(), before, "{script}");
+        editor.keys("<C-r>");
+        assert_eq!(editor.lines(), after, "{script}");
+    }
+}
+
+#[test]
+fn test_help_buffer_topics_and_links() {
+    let mut editor = TestEditor::with_size("text", (80, 10));
+    let cursor_line = |editor: &TestEditor| editor.lines()[editor.cursor().0].clone();
+    editor.keys(":help registers<CR>");
+    assert!(cursor_line(&editor).starts_with("NAMED REGISTERS:"));
+    assert!(editor.screen()[1].ends_with(" *registers*")); // The topic is at the top
+    editor.keys(":h :s<CR>");
+    assert!(cursor_line(&editor).starts_with("  :s/old/new/ - "));
+    assert_eq!(editor.shared().session_controller.buffer_count(), 2); // Still the one help buffer
+
+    // Ctrl-] follows the link under the cursor, Ctrl-o comes back
+    editor.keys("gg/TOPICS<CR>jw<C-]>");
+    assert!(cursor_line(&editor).starts_with("MOVEMENT (Normal Mode):"));
+    editor.keys("<C-o>");
+    assert!(cursor_line(&editor).starts_with("  |movement|"));
+
+    editor.keys(":help no-such-topic<CR>");
+    assert_eq!(editor.status_line(), "Sorry, no help for no-such-topic");
+
+    // q closes the help, and is an ordinary key elsewhere
+    editor.keys("q");
+    editor.assert_lines(&["text"]);
+    assert_eq!(editor.shared().session_controller.buffer_count(), 1);
+}
+
+#[test]
+fn test_help_buffer_refuses_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join("help.txt");
+    let mut editor = TestEditor::new("text");
+    editor.keys(":help<CR>");
+    let help = editor.lines();
+
+    for keys in ["dd", "x", "ihello<Esc>", "o", "p", "J", "~", ">>", "Vd", ":%s/a/b/g<CR>", ":1d<CR>", ":normal x<CR>"] {
+        editor.keys(keys);
+        assert_eq!(editor.lines(), help, "{keys}");
+        assert_eq!(editor.mode(), Mode::Normal, "{keys}");
+        assert_eq!(editor.shared().status_message, "Cannot make changes, the help buffer is read-only", "{keys}");
+    }
+    editor.keys(":g/^/d<CR>");
+    editor.paste("pasted");
+    assert_eq!(editor.lines(), help);
+    assert!(!editor.document().is_modified());
+
+    // Yanking and writing a copy still work, and the copy is the help as it is
+    editor.keys(&format!("yy:w {}<CR>", copy.display()));
+    assert_eq!(std::fs::read_to_string(&copy).unwrap().lines().count(), help.len());
+}
+
+#[test]
+fn test_leader_mappings_at_runtime() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys(":nnoremap <lt>leader>d dd<CR>\\d");
+    editor.assert_lines(&["two", "three", "four"]);
+
+    // A new leader applies to mappings made after it
+    editor.keys(":let mapleader = \",\"<CR>:nnoremap <lt>leader>d x<CR>:nnoremap <lt>leader>dd ddp<CR>\\d");
+    editor.assert_lines(&["three", "four"]);
+
+    // ,d waits to see whether ,dd follows; a pause settles it
+    editor.keys(",d");
+    editor.assert_lines(&["three", "four"]);
+    editor.idle_for(Duration::from_millis(1100));
+    editor.assert_lines(&["hree", "four"]);
+    editor.keys(",dd");
+    editor.assert_lines(&["four", "hree"]);
+
+    editor.keys(":let mapleader<CR>");
+    assert_eq!(editor.shared().status_message, "mapleader ,");
+}
+
+#[test]
+fn test_search_match_count_follows_edits() {
+    let mut editor = TestEditor::new("foo bar\nfoo\nbaz foo");
+    editor.keys("/foo<CR>");
+    assert_eq!(editor.status_line(), "/foo/  [2/3]");
+    editor.keys("n");
+    assert_eq!(editor.status_line(), "/foo/  [3/3]");
+    editor.keys("N");
+    assert_eq!(editor.status_line(), "?foo?  [2/3]");
+
+    // An edit adds a match; n counts it, and it is highlighted
+    editor.keys("Ofoo<Esc>gg");
+    editor.keys("n");
+    assert_eq!(editor.cursor(), (1, 0));
+    assert_eq!(editor.status_line(), "/foo/  [2/4]");
+    assert_eq!(editor.shared().search_state.matches.len(), 4);
+    editor.keys("ddu");
+    editor.screen();
+    assert_eq!(editor.shared().search_state.matches.len(), 4);
+
+    editor.keys(":set shm=S<CR>n");
+    assert_eq!(editor.status_line(), "/foo/");
+}
+
+#[test]
+fn test_argument_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|name| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, name).unwrap();
+        path.display().to_string()
+    });
+    let mut editor = TestEditor::open(std::path::Path::new(&a));
+    editor.keys(":args<CR>");
+    assert_eq!(editor.shared().status_message, format!("[{a}]"));
+
+    editor.keys(&format!(":args {a} {b} {c}<CR>:n<CR>"));
+    editor.assert_lines(&["b.txt"]);
+    assert_eq!(editor.shared().status_message, format!("\"{b}\" (file 2 of 3)"));
+    editor.keys(":n<CR>:n<CR>");
+    editor.assert_lines(&["c.txt"]);
+    assert_eq!(editor.shared().status_message, "Cannot go beyond last file");
+    editor.keys(":N<CR>:prev<CR>");
+    editor.assert_lines(&["a.txt"]);
+    editor.keys(":last<CR>");
+    editor.assert_lines(&["c.txt"]);
+    editor.keys(":first<CR>:next 2<CR>");
+    editor.assert_lines(&["c.txt"]);
+
+    // Closing a buffer or editing another file leaves the list alone
+    editor.keys(":bd<CR>:e other.txt<CR>:args<CR>");
+    assert_eq!(editor.shared().status_message, format!("{a} {b} [{c}]"));
+
+    // :argadd puts files after the current one; a new file opens empty
+    let new = dir.path().join("new.txt").display().to_string();
+    editor.keys(&format!(":prev<CR>:argadd {new}<CR>"));
+    assert_eq!(editor.shared().status_message, format!("{a} [{b}] {new} {c}"));
+    editor.keys(":n<CR>");
+    editor.assert_lines(&[""]);
+    assert_eq!(editor.shared().status_message, format!("\"{new}\" (file 3 of 4)"));
+}
+
+#[test]
+fn test_wildcards_and_alternate_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().display().to_string();
+    for name in ["a.md", "b.md", "notes.txt", "src/lib.rs", "src/deep/mod.rs"] {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, name).unwrap();
+    }
+    let mut editor = TestEditor::new("");
+
+    // A wildcard opens every file it matches
+    editor.keys(&format!(":e {root}/src/**/*.rs<CR>"));
+    assert_eq!(editor.shared().session_controller.buffer_count(), 3);
+    editor.keys(&format!(":args {root}/*.md<CR>"));
+    editor.assert_lines(&["a.md"]);
+    editor.keys(":args<CR>");
+    assert_eq!(editor.shared().status_message, format!("[{root}/a.md] {root}/b.md"));
+    editor.keys(&format!(":e {root}/*.doc<CR>"));
+    assert_eq!(editor.shared().status_message, format!("No match: {root}/*.doc"));
+
+    // # is the file edited before, % this one; Ctrl-^ goes back and forth
+    editor.keys(":e #<CR>");
+    editor.assert_lines(&["src/deep/mod.rs"]);
+    editor.keys("<C-^>");
+    editor.assert_lines(&["a.md"]);
+    editor.keys("<C-6>");
+    editor.assert_lines(&["src/deep/mod.rs"]);
+    editor.keys(":e %:h:h/../notes.txt<CR>");
+    editor.assert_lines(&["notes.txt"]);
+    editor.keys(":r #<CR>");
+    editor.assert_lines(&["notes.txt", "src/deep/mod.rs"]);
+    editor.keys(r":e \#<CR>");
+    editor.assert_lines(&[""]);
+    assert_eq!(editor.document().filename, Some(std::path::PathBuf::from("#")));
+}
+
+#[test]
+fn test_alternate_file_per_window() {
+    let dir = tempfile::tempdir().unwrap();
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|name| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, name).unwrap();
+        path.display().to_string()
+    });
+    let mut editor = TestEditor::open(std::path::Path::new(&a));
+    editor.keys(&format!(":e {b}<CR>"));
+
+    // The new window starts with the same alternate, then each keeps its own
+    editor.keys(&format!(":split<CR>:e {c}<CR><C-^>"));
+    editor.assert_lines(&["b.txt"]);
+    editor.keys("<C-w>w<C-^>");
+    editor.assert_lines(&["a.txt"]);
+    editor.keys("<C-w>w<C-^>");
+    editor.assert_lines(&["c.txt"]);
+
+    // :ls marks the alternate buffer #, and the # register names it
+    editor.keys(":ls<CR>");
+    assert_eq!(editor.shared().status_message, "  1: \"a.txt\"  | # 2: \"b.txt\"  | % 3: \"c.txt\" ");
+    editor.keys("<Esc>\"#p");
+    assert_eq!(editor.lines(), [format!("c{b}.txt")]);
+
+    // A count picks the buffer by number
+    editor.keys("u1<C-6>");
+    editor.assert_lines(&["a.txt"]);
+    editor.keys("<C-6>");
+    editor.assert_lines(&["c.txt"]);
+}
+
+#[test]
+fn test_write_ranges_append_and_pipe() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+    let snippet = dir.path().join("snippet.txt").display().to_string();
+    let log = dir.path().join("log.txt").display().to_string();
+    let mut editor = TestEditor::open(&path);
+
+    editor.keys(&format!(":2,3w {snippet}<CR>"));
+    assert_eq!(editor.shared().status_message, format!("\"{snippet}\" 2 lines written"));
+    assert_eq!(std::fs::read_to_string(&snippet).unwrap(), "two\nthree\n");
+
+    // A visual selection appends to a file, which has to exist without !
+    editor.keys(&format!("GkVk:w >> {log}<CR>"));
+    assert_eq!(editor.shared().status_message, format!("\"{log}\" doesn't exist (add ! to create it)"));
+    editor.keys(&format!(":'<,'>w! >> {log}<CR>:1w >> {log}<CR>"));
+    assert_eq!(editor.shared().status_message, format!("\"{log}\" 1 line appended"));
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "three\nfour\none\n");
+
+    // Part of the buffer doesn't go over its own file without !
+    editor.keys(":1,2w<CR>");
+    assert_eq!(editor.shared().status_message, "Use ! to write partial buffer");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\nfour\n");
+
+    editor.keys(":w !wc -l<CR>");
+    assert_eq!(editor.shared().status_message.trim(), "4");
+    editor.keys(":2,3w !tr a-z A-Z<CR>");
+    assert_eq!(editor.shared().status_message, "2 lines of output in preview buffer");
+    editor.assert_lines(&["TWO", "THREE"]);
+}
+
+#[test]
+fn test_confirm_asks_instead_of_failing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    let other = dir.path().join("other.txt");
+    std::fs::write(&path, "draft\n").unwrap();
+    std::fs::write(&other, "other\n").unwrap();
+    let mut editor = TestEditor::open(&path);
+    editor.keys(":set confirm<CR>A one<Esc>");
+
+    // :e over a modified buffer: c stays put, y saves it and goes on
+    editor.keys(&format!(":e {}<CR>", other.display()));
+    assert_eq!(editor.mode(), Mode::Command);
+    assert!(editor.status_line().contains("Save changes to \"notes.txt\"?"), "{}", editor.status_line());
+    editor.keys("c");
+    editor.assert_lines(&["draft one", ""]);
+    editor.keys(&format!(":e {}<CR>y", other.display()));
+    editor.assert_lines(&["other", ""]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft one\n");
+
+    // :w name over an existing file: n leaves it alone, y writes it
+    editor.keys(&format!(":w {}<CR>", path.display()));
+    assert!(editor.status_line().contains("Overwrite existing file"), "{}", editor.status_line());
+    editor.keys("n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft one\n");
+    editor.keys(&format!(":w {}<CR>y", path.display()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "other\n");
+
+    // :q with unsaved changes: n quits without them
+    editor.keys("A two<Esc>:q<CR>");
+    assert!(!editor.has_quit());
+    editor.keys("n");
+    assert!(editor.has_quit());
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), "other\n");
+}
+
+#[test]
+fn test_undolevels_drops_oldest_changes() {
+    let mut editor = TestEditor::new("one\ntwo\nthree\nfour");
+    editor.keys(":set ul=2<CR>dddddd");
+    editor.assert_lines(&["four"]);
+    editor.keys("uuu");
+    editor.assert_lines(&["two", "three", "four"]);
+
+    // Making the limit smaller drops what is over it at once
+    editor.keys("<C-r>:set undolevels=1<CR>uu");
+    editor.assert_lines(&["three", "four"]);
+}
+
+#[test]
+fn test_wrap_counts_tabs_and_keeps_spacing() {
+    let mut editor = TestEditor::new("\taaa  bbb ccc ddd");
+    editor.keys(":set tabstop=4<CR>:Wrap 14<CR>");
+    editor.assert_lines(&["\taaa  bbb", "\tccc ddd"]);
+}
+
+#[test]
+fn test_operator_motions_that_fail_change_nothing() {
+    let mut editor = TestEditor::new("abc\nline 2");
+    editor.keys("d%");
+    editor.assert_lines(&["abc", "line 2"]);
+    editor.keys("dk");
+    editor.assert_lines(&["abc", "line 2"]);
+    editor.keys("jdj");
+    editor.assert_lines(&["abc", "line 2"]);
+    editor.keys("dk");
+    editor.assert_lines(&[""]);
+}
+
+#[test]
+fn test_page_motions_land_on_a_character() {
+    let mut editor = TestEditor::new("abc\néé");
+    editor.keys("l<C-f>x");
+    editor.assert_lines(&["abc", "é"]);
+    let mut editor = TestEditor::new("éé\nabc");
+    editor.keys("Gl<C-b>x");
+    editor.assert_lines(&["é", "abc"]);
+}
+
+#[test]
+fn test_ex_delete_leaves_the_cursor_on_a_character() {
+    let mut editor = TestEditor::new("abc\nxyz\néé");
+    editor.keys("jjl:1d<CR>");
+    assert_eq!(editor.cursor(), (0, 0));
+    editor.keys("jl:1d<CR>ix<Esc>");
+    editor.assert_lines(&["xéé"]);
+}
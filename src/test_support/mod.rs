@@ -0,0 +1,215 @@
+//! Headless end-to-end testing - drive an `EditorController` with scripted
+//! keys and check the screen it would draw, the text it edits and its
+//! registers. `keys` hands events straight to the editor; `run` sends them
+//! through the event loop itself, from a `ScriptedEvents` source, with the
+//! screen drawn to memory.
+//!
+//! Key scripts use vim's notation, as key mappings do: see
+//! `crate::controller::key_map`.
+
+mod end_to_end;
+
+use crate::controller::event_source::ScriptedEvents;
+use crate::controller::key_map::parse_keys;
+use crate::controller::{EditorController, Mode, SharedEditorState};
+use crate::document_model::{Document, LockChoice};
+use crossterm::event::Event;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// An editor with a fixed-size in-memory screen
+pub struct TestEditor {
+    editor: EditorController,
+    quit: bool,
+}
+
+impl TestEditor {
+    /// Screen size used unless a test asks for another
+    pub const DEFAULT_SIZE: (u16, u16) = (40, 8);
+
+    /// An editor with one unnamed buffer holding `text`
+    pub fn new(text: &str) -> Self {
+        Self::with_size(text, Self::DEFAULT_SIZE)
+    }
+
+    pub fn with_size(text: &str, size: (u16, u16)) -> Self {
+        let mut editor = EditorController::new();
+        editor.shared_state_mut().session_controller.buffers[0] = Document::from_string(text.to_string());
+        Self::wrap(editor, size)
+    }
+
+    /// An editor editing the file at `path`
+    pub fn open(path: &Path) -> Self {
+        let editor = EditorController::new_with_files(vec![path.to_path_buf()], |_, _| LockChoice::Proceed)
+            .expect("failed to open file");
+        Self::wrap(editor, Self::DEFAULT_SIZE)
+    }
+
+    fn wrap(mut editor: EditorController, size: (u16, u16)) -> Self {
+        editor.shared_state_mut().view.set_terminal_size(Some(size));
+        Self { editor, quit: false }
+    }
+
+    /// Type a key script. Panics if a key arrives after the editor quit.
+    pub fn keys(&mut self, script: &str) -> &mut Self {
+        for key in parse_keys(script) {
+            assert!(!self.quit, "key {key:?} sent after the editor quit");
+            self.quit = self.editor.feed_event(Event::Key(key)).expect("event handling failed");
+        }
+        self
+    }
+
+    /// Resize the terminal, as a resize event would
+    pub fn resize(&mut self, width: u16, height: u16) -> &mut Self {
+        self.editor.feed_event(Event::Resize(width, height)).expect("event handling failed");
+        self
+    }
+
+    /// Let time pass without input, running the timers that come due
+    pub fn idle_for(&mut self, idle: Duration) -> &mut Self {
+        assert!(!self.quit, "idle after the editor quit");
+        self.quit = self.editor.idle_for(idle).expect("timer failed");
+        self
+    }
+
+    /// Paste text into the terminal, as a bracketed paste would
+    pub fn paste(&mut self, text: &str) -> &mut Self {
+        self.quit = self.editor.feed_event(Event::Paste(text.to_string())).expect("event handling failed");
+        self
+    }
+
+    /// The terminal loses focus, as a focus event would
+    pub fn lose_focus(&mut self) -> &mut Self {
+        self.editor.feed_event(Event::FocusLost).expect("event handling failed");
+        self
+    }
+
+    /// Run `script` through the editor's whole event loop, as keys read
+    /// from the terminal, with the screen drawn to memory. The script must
+    /// make the editor quit. Returns what was drawn, escapes and all.
+    pub fn run(&mut self, script: &str) -> String {
+        assert!(!self.quit, "run after the editor quit");
+        let screen = MemoryScreen::default();
+        let shared = self.editor.shared_state_mut();
+        shared.view.set_output(Box::new(screen.clone()));
+        self.editor.set_event_source(Box::new(ScriptedEvents::keys(script)));
+        self.editor.run_events().expect("the script didn't make the editor quit");
+        self.quit = true;
+        let drawn = screen.0.borrow();
+        String::from_utf8_lossy(&drawn).into_owned()
+    }
+
+    /// True once a key made the editor quit
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// The whole screen as plain text, one string per terminal row, with
+    /// colours removed and trailing spaces trimmed
+    pub fn screen(&mut self) -> Vec<String> {
+        let frame = self.editor.frame();
+        let layout = frame.layout;
+        let mut screen: Vec<String> = frame.buffer_info.into_iter().chain(frame.rows).collect();
+        screen.truncate(layout.status_row());
+        screen.resize(layout.status_row(), String::new());
+        screen.push(frame.status);
+        screen.iter().map(|row| strip_escapes(row).trim_end().to_string()).collect()
+    }
+
+    pub fn assert_screen(&mut self, expected: &[&str]) {
+        assert_eq!(self.screen(), expected, "screen differs");
+    }
+
+    /// The status line as drawn
+    pub fn status_line(&mut self) -> String {
+        self.screen().pop().unwrap_or_default()
+    }
+
+    /// Where the terminal cursor would be: screen row and column
+    pub fn screen_cursor(&mut self) -> (usize, usize) {
+        self.editor.frame().cursor
+    }
+
+    /// Cursor position in the current document: line and column
+    pub fn cursor(&self) -> (usize, usize) {
+        let doc = self.document();
+        (doc.cursor_line(), doc.cursor_column())
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let doc = self.document();
+        (0..doc.line_count()).filter_map(|i| doc.get_line(i)).collect()
+    }
+
+    pub fn assert_lines(&self, expected: &[&str]) {
+        assert_eq!(self.lines(), expected, "buffer differs");
+    }
+
+    /// The text held in register `name`
+    pub fn register(&mut self, name: char) -> Option<String> {
+        let registers = &mut self.editor.shared_state_mut().register_manager;
+        registers.get_register_content(Some(name)).map(|data| data.content.clone())
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.editor.mode()
+    }
+
+    pub fn document(&self) -> &Document {
+        self.shared().session_controller.current_document()
+    }
+
+    pub fn shared(&self) -> &SharedEditorState {
+        self.editor.shared_state()
+    }
+}
+
+/// A screen drawn into memory
+#[derive(Clone, Default)]
+struct MemoryScreen(Rc<RefCell<Vec<u8>>>);
+
+impl Write for MemoryScreen {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remove terminal escape sequences (colours, attributes) from a row
+fn strip_escapes(row: &str) -> String {
+    let mut result = String::with_capacity(row.len());
+    let mut chars = row.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            // CSI sequences end with a byte in '@'..='~'
+            if chars.next() == Some('[') {
+                for ch in chars.by_ref() {
+                    if ('@'..='~').contains(&ch) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        result.push(ch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_escapes() {
+        let row = format!("a{}b{}c", crossterm::style::SetBackgroundColor(crossterm::style::Color::Red), crossterm::style::ResetColor);
+        assert_eq!(strip_escapes(&row), "abc");
+    }
+}
@@ -0,0 +1,162 @@
+//! Damage tracking - which rows of the text area must be composed again for
+//! the next frame
+//!
+//! The renderer keeps the rows it composed last time, by screen row. Before
+//! a frame, the rows showing lines an edit touched (from the text buffer's
+//! edit log) are dropped, along with the lines the cursor or a bracket
+//! highlight was on or has moved to, and the rows a scroll brought into
+//! view; the rest shift with the scroll and are used again. Anything that
+//! changes every row - the layout, an option, search highlights, a
+//! selection, another buffer - drops them all, as does :redraw.
+
+use crate::document_model::LineChange;
+
+/// One composed row of the text area
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedRow {
+    pub text: String,
+    pub clipped: bool, // The line was too long to highlight in full
+}
+
+/// What every row of a frame depends on
+pub struct FrameInputs {
+    pub key: u64,          // Hash of the settings and highlights that affect every row
+    pub revision: u64,     // The text's revision
+    pub scroll_offset: usize,
+    pub rows: usize,
+    pub marked_lines: Vec<usize>, // Lines drawn differently for the cursor or a bracket highlight
+}
+
+#[derive(Debug, Default)]
+pub struct RowCache {
+    rows: Vec<Option<CachedRow>>,
+    revision: Option<u64>,
+    key: u64,
+    scroll_offset: usize,
+    marked_lines: Vec<usize>,
+}
+
+impl RowCache {
+    /// Forget every row, so the next frame composes them all
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Get ready for a frame by dropping the rows it can't use. `changes`
+    /// gives the lines edited since a revision, or None when that's not
+    /// known. Returns how many rows the kept text moved up the screen
+    /// (negative: down), for the terminal to scroll instead of redrawing.
+    pub fn prepare(&mut self, inputs: FrameInputs, changes: impl FnOnce(u64) -> Option<Vec<LineChange>>) -> isize {
+        let reusable = self.key == inputs.key && self.rows.len() == inputs.rows;
+        let changes = self.revision.filter(|_| reusable).and_then(changes);
+        let scrolled = inputs.scroll_offset as isize - self.scroll_offset as isize;
+        let kept = match changes {
+            Some(changes) if scrolled.unsigned_abs() < inputs.rows => {
+                self.shift(scrolled);
+                for (row, cached) in self.rows.iter_mut().enumerate() {
+                    let line = inputs.scroll_offset + row;
+                    let marked = self.marked_lines.contains(&line) || inputs.marked_lines.contains(&line);
+                    if marked || changes.iter().any(|change| change.touches(line)) {
+                        *cached = None;
+                    }
+                }
+                true
+            }
+            _ => {
+                self.rows = vec![None; inputs.rows];
+                false
+            }
+        };
+
+        self.revision = Some(inputs.revision);
+        self.key = inputs.key;
+        self.scroll_offset = inputs.scroll_offset;
+        self.marked_lines = inputs.marked_lines;
+        if kept { scrolled } else { 0 }
+    }
+
+    /// Move the rows up by `rows` (down when negative), leaving gaps at the
+    /// edge the text scrolled in from
+    fn shift(&mut self, rows: isize) {
+        let count = rows.unsigned_abs();
+        if rows > 0 {
+            self.rows.drain(..count);
+            self.rows.extend(std::iter::repeat_n(None, count));
+        } else if rows < 0 {
+            self.rows.truncate(self.rows.len() - count);
+            self.rows.splice(0..0, std::iter::repeat_n(None, count));
+        }
+    }
+
+    pub fn get(&self, row: usize) -> Option<&CachedRow> {
+        self.rows.get(row)?.as_ref()
+    }
+
+    pub fn store(&mut self, row: usize, cached: CachedRow) {
+        if let Some(slot) = self.rows.get_mut(row) {
+            *slot = Some(cached);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(revision: u64, scroll_offset: usize, marked_lines: Vec<usize>) -> FrameInputs {
+        FrameInputs { key: 7, revision, scroll_offset, rows: 4, marked_lines }
+    }
+
+    fn fill(cache: &mut RowCache, scroll_offset: usize) {
+        for row in 0..4 {
+            cache.store(row, CachedRow { text: format!("line {}", scroll_offset + row), clipped: false });
+        }
+    }
+
+    fn kept(cache: &RowCache) -> Vec<Option<&str>> {
+        (0..4).map(|row| cache.get(row).map(|cached| cached.text.as_str())).collect()
+    }
+
+    #[test]
+    fn test_edits_and_cursor_moves_drop_rows() {
+        let mut cache = RowCache::default();
+        assert_eq!(cache.prepare(inputs(1, 0, vec![0]), |_| panic!("nothing cached yet")), 0);
+        fill(&mut cache, 0);
+
+        // Line 2 edited, the cursor moved from line 0 to line 1
+        let edit = LineChange { first: 2, last: 2, shifted: false };
+        cache.prepare(inputs(2, 0, vec![1]), |revision| (revision == 1).then(|| vec![edit]));
+        assert_eq!(kept(&cache), [None, None, None, Some("line 3")]);
+
+        // A line break added moves every line after it
+        fill(&mut cache, 0);
+        let split = LineChange { first: 2, last: 3, shifted: true };
+        cache.prepare(inputs(3, 0, vec![1]), |_| Some(vec![split]));
+        assert_eq!(kept(&cache), [Some("line 0"), None, None, None]);
+
+        // Another buffer's text, or another key, starts over
+        fill(&mut cache, 0);
+        cache.prepare(inputs(9, 0, vec![1]), |_| None);
+        assert_eq!(kept(&cache), [None; 4]);
+        fill(&mut cache, 0);
+        cache.prepare(FrameInputs { key: 8, ..inputs(9, 0, vec![1]) }, |_| Some(vec![]));
+        assert_eq!(kept(&cache), [None; 4]);
+    }
+
+    #[test]
+    fn test_scrolling_shifts_rows() {
+        let mut cache = RowCache::default();
+        cache.prepare(inputs(1, 10, vec![]), |_| None);
+        fill(&mut cache, 10);
+
+        assert_eq!(cache.prepare(inputs(1, 11, vec![]), |_| Some(vec![])), 1);
+        assert_eq!(kept(&cache), [Some("line 11"), Some("line 12"), Some("line 13"), None]);
+
+        assert_eq!(cache.prepare(inputs(1, 9, vec![]), |_| Some(vec![])), -2);
+        assert_eq!(kept(&cache), [None, None, Some("line 11"), Some("line 12")]);
+
+        // Further than a screen: nothing to keep
+        assert_eq!(cache.prepare(inputs(1, 20, vec![]), |_| Some(vec![])), 0);
+        assert_eq!(kept(&cache), [None; 4]);
+    }
+}
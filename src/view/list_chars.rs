@@ -0,0 +1,111 @@
+//! 'listchars' - what 'list' draws in place of whitespace, and at the ends
+//! of lines:
+//!
+//! ```text
+//! set listchars=tab:»·,trail:·,nbsp:␣,eol:$
+//! ```
+//!
+//! `tab:` takes two characters: the first starts the tab and the second
+//! fills the rest of its cells. Every replacement takes one cell, as the
+//! space or tab cell it stands for does, so cursor columns are unchanged.
+
+use std::fmt;
+use unicode_width::UnicodeWidthChar;
+
+/// 'listchars' when it hasn't been set
+pub const DEFAULT_LIST_CHARS: &str = "tab:»·,trail:·,nbsp:␣,eol:$";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListChars {
+    pub tab: Option<(char, char)>, // First cell of a tab, then the rest; a tab is blank without it
+    pub space: Option<char>,       // Every space
+    pub trail: Option<char>,       // Spaces at the end of a line, over `space`
+    pub nbsp: Option<char>,        // Non-breaking spaces
+    pub eol: Option<char>,         // Drawn after the last character
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self::parse(DEFAULT_LIST_CHARS).expect("default listchars parse")
+    }
+}
+
+impl ListChars {
+    /// Read `tab:xy,trail:c,...`; an empty value shows nothing
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut list_chars = Self { tab: None, space: None, trail: None, nbsp: None, eol: None };
+        for item in value.split(',').filter(|item| !item.is_empty()) {
+            let (name, chars) = item.split_once(':').ok_or_else(|| format!("Invalid listchars item: {item}"))?;
+            let chars: Vec<char> = chars.chars().collect();
+            if chars.iter().any(|c| c.width() != Some(1)) {
+                return Err(format!("listchars must be one cell wide: {item}"));
+            }
+            match (name, chars.as_slice()) {
+                ("tab", &[first, fill]) => list_chars.tab = Some((first, fill)),
+                ("space", &[c]) => list_chars.space = Some(c),
+                ("trail", &[c]) => list_chars.trail = Some(c),
+                ("nbsp", &[c]) => list_chars.nbsp = Some(c),
+                ("eol", &[c]) => list_chars.eol = Some(c),
+                _ => return Err(format!("Invalid listchars item: {item}")),
+            }
+        }
+        Ok(list_chars)
+    }
+
+    /// What a whitespace grapheme taking `width` cells is drawn as, or None
+    /// when it is drawn as it is. `trailing` is true at the end of a line.
+    pub fn whitespace(&self, grapheme: &str, width: usize, trailing: bool) -> Option<String> {
+        match grapheme {
+            "\t" => Some(match self.tab {
+                Some((first, fill)) => std::iter::once(first).chain(std::iter::repeat_n(fill, width - 1)).collect(),
+                None => " ".repeat(width),
+            }),
+            " " if trailing && self.trail.is_some() => self.trail.map(String::from),
+            " " => self.space.map(String::from),
+            "\u{a0}" | "\u{202f}" => self.nbsp.map(String::from),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ListChars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: Vec<String> = [
+            self.tab.map(|(first, fill)| format!("tab:{first}{fill}")),
+            self.space.map(|c| format!("space:{c}")),
+            self.trail.map(|c| format!("trail:{c}")),
+            self.nbsp.map(|c| format!("nbsp:{c}")),
+            self.eol.map(|c| format!("eol:{c}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_draw() {
+        let list_chars = ListChars::default();
+        assert_eq!(list_chars.to_string(), DEFAULT_LIST_CHARS);
+        assert_eq!(list_chars.whitespace("\t", 4, false).as_deref(), Some("»···"));
+        assert_eq!(list_chars.whitespace(" ", 1, false), None);
+        assert_eq!(list_chars.whitespace(" ", 1, true).as_deref(), Some("·"));
+        assert_eq!(list_chars.whitespace("\u{a0}", 1, false).as_deref(), Some("␣"));
+        assert_eq!(list_chars.whitespace("a", 1, true), None);
+
+        let spaces = ListChars::parse("space:.,tab:>-").unwrap();
+        assert_eq!((spaces.eol, spaces.to_string().as_str()), (None, "tab:>-,space:."));
+        assert_eq!(spaces.whitespace(" ", 1, true).as_deref(), Some("."));
+        assert_eq!(ListChars::parse("").unwrap().whitespace("\t", 2, false).as_deref(), Some("  "));
+
+        assert!(ListChars::parse("tab:>").is_err());
+        assert!(ListChars::parse("eol").is_err());
+        assert!(ListChars::parse("eol:世").is_err());
+        assert!(ListChars::parse("lead:x").is_err());
+    }
+}
@@ -1,11 +1,18 @@
-/// View subsystem - Independent rendering and display management
-/// 
-/// This module provides a clean abstraction layer for all visual rendering,
-/// completely independent of document internals through the ViewModel trait.
+//! View subsystem - Independent rendering and display management
+//!
+//! This module provides a clean abstraction layer for all visual rendering,
+//! completely independent of document internals through the ViewModel trait.
 
 pub mod view_model;
 pub mod renderer;
+pub mod damage;
+pub mod window;
+pub mod theme;
+pub mod list_chars;
 
 // Re-export public interface
 pub use view_model::{DocumentViewModel, BracketHighlight};
-pub use renderer::{View, RenderParams};
\ No newline at end of file
+pub use renderer::{View, RenderParams, WindowPane, ListPopup};
+pub use window::{LayoutStep, SplitDirection};
+pub use theme::Theme;
+pub use list_chars::ListChars;
\ No newline at end of file
@@ -1,13 +1,21 @@
 use crate::controller::Mode;
-use crate::document_model::SearchState;
+use crate::document_model::{DiffKind, DiffRow, GitSign, SearchState, Severity};
+use crate::document_model::diagnostics;
+use crate::document_model::search_state::SearchMatch;
+use crate::document_model::text_buffer::{grapheme_start, grapheme_width};
 use crate::controller::Selection;
+use super::damage::{CachedRow, FrameInputs, RowCache};
+use super::list_chars::ListChars;
+use super::theme::{Style, Theme};
 use super::view_model::{ViewModel, BracketHighlight};
+use super::window::{Rect, Window, WindowId, WindowManager};
 use crossterm::{
-    cursor, execute,
-    style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{Clear, ClearType, size},
+    cursor, queue,
+    terminal::{Clear, ClearType, ScrollDown, ScrollUp, size},
 };
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{self, Write, stdout};
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthChar;
 
 #[derive(Clone)]
@@ -19,6 +27,58 @@ pub struct RenderParams<'a> {
     pub visual_selection: Option<&'a Selection>,
     pub search_state: Option<&'a SearchState>,
     pub bracket_highlights: Option<&'a BracketHighlight>,
+    pub window_panes: &'a [WindowPane<'a>], // Content of each split window; empty when not split
+    pub popup: Option<&'a ListPopup>,        // List drawn over the text, e.g. the :ls picker
+    pub pending_keys: &'a str,               // A command typed in part, for 'showcmd'
+}
+
+/// A list drawn over the bottom rows of the text area
+#[derive(Clone)]
+pub struct ListPopup {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize, // Past the last item when there is nothing to pick, as in K's hover text
+}
+
+/// Content of one split window for rendering
+pub struct WindowPane<'a> {
+    pub id: WindowId,
+    pub view_model: Box<dyn ViewModel + 'a>,
+    pub name: String,
+    pub modified: bool,
+    pub diff: Option<&'a [DiffRow]>, // Rows of this window's side in diff mode
+}
+
+/// Environment variable that pins the terminal size, e.g. `VIRUS_TERM_SIZE=80x24`
+pub const TERM_SIZE_ENV: &str = "VIRUS_TERM_SIZE";
+
+/// Screen geometry for one frame, derived from the terminal size
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub struct ScreenLayout {
+    pub width: u16,
+    pub height: u16,
+    pub start_line: usize,     // First screen row used for text
+    pub max_lines: usize,      // Number of text rows
+    pub sign_width: usize,     // Columns taken by the sign column, left of the line numbers
+    pub line_num_width: usize, // Columns taken by the line number gutter
+    pub text_width: usize,     // Columns available for text
+}
+
+impl ScreenLayout {
+    /// Screen row of the status/command line
+    pub fn status_row(&self) -> usize {
+        self.height.saturating_sub(1) as usize
+    }
+}
+
+/// The content of one frame: header, text rows, status line and cursor
+pub struct Frame {
+    pub layout: ScreenLayout,
+    pub buffer_info: Option<String>, // Header row, when shown
+    pub rows: Vec<String>,           // Text area rows, which may carry colour escapes
+    pub status: String,
+    pub cursor: (usize, usize),      // Screen row and column
+    pub scrolled: isize,             // Rows the text moved up since the last frame (down if negative)
 }
 
 pub struct View {
@@ -29,6 +89,8 @@ pub struct View {
     last_command_buffer: String,
     last_cursor_pos: (usize, usize),
     last_terminal_size: (u16, u16),
+    terminal_size: Option<(u16, u16)>,
+    header_lines: usize,
     scroll_offset: usize,
     horizontal_scroll: usize,
     needs_full_redraw: bool,
@@ -36,6 +98,38 @@ pub struct View {
     show_line_numbers: bool,
     tab_stop: usize,
     show_whitespace: bool,
+    list_chars: ListChars,       // 'listchars': what 'list' draws for whitespace
+    show_trailing: bool,         // 'hltrailing': highlight whitespace at the ends of lines
+    cursor_line: bool,           // 'cursorline': highlight the cursor's line
+    show_command: bool,          // 'showcmd': show a command typed in part on the status line
+    color_columns: Vec<usize>,   // 'colorcolumn': screen columns (from 1) marked on every line
+    max_highlight_column: usize, // 'synmaxcol': no highlighting past this column; 0 = no limit
+    windows: WindowManager,
+    theme: Theme,
+    row_cache: RowCache, // Text rows of the last frame, for the next to reuse
+    output: Box<dyn Write>, // Where frames are drawn: the terminal unless set_output gives another
+}
+
+/// Columns at the right of the status line 'showcmd' uses, as in vim
+const SHOW_COMMAND_WIDTH: usize = 10;
+
+/// Default 'synmaxcol', as in vim
+pub const DEFAULT_MAX_HIGHLIGHT_COLUMN: usize = 3000;
+
+/// Read a 'colorcolumn' value: columns from 1, separated by commas. Empty
+/// marks none.
+pub fn parse_color_columns(value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse::<usize>().ok().filter(|&column| column > 0).ok_or_else(|| format!("Invalid colorcolumn: {item}")))
+        .collect()
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl View {
@@ -48,6 +142,10 @@ impl View {
             last_command_buffer: String::new(),
             last_cursor_pos: (0, 0),
             last_terminal_size: (0, 0),
+            terminal_size: std::env::var(TERM_SIZE_ENV)
+                .ok()
+                .and_then(|value| Self::parse_terminal_size(&value)),
+            header_lines: 1,
             scroll_offset: 0,
             horizontal_scroll: 0,
             needs_full_redraw: true,
@@ -55,149 +153,466 @@ impl View {
             show_line_numbers: false,
             tab_stop: 4, // default to 4 spaces
             show_whitespace: false,
+            list_chars: ListChars::default(),
+            show_trailing: false,
+            cursor_line: false,
+            show_command: false,
+            color_columns: Vec::new(),
+            max_highlight_column: DEFAULT_MAX_HIGHLIGHT_COLUMN,
+            windows: WindowManager::new(),
+            theme: Theme::default(),
+            row_cache: RowCache::default(),
+            output: Box::new(stdout()),
+        }
+    }
+
+    /// Draw frames into `output` instead of the terminal, as escape
+    /// sequences; the next frame is drawn whole
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+        self.needs_full_redraw = true;
+    }
+
+    pub fn windows(&self) -> &WindowManager {
+        &self.windows
+    }
+
+    pub fn windows_mut(&mut self) -> &mut WindowManager {
+        self.needs_full_redraw = true;
+        &mut self.windows
+    }
+
+    pub fn get_horizontal_scroll(&self) -> usize {
+        self.horizontal_scroll
+    }
+
+    /// Restore a saved scroll position (e.g. when focus moves to another window)
+    pub fn set_scroll_position(&mut self, scroll_offset: usize, horizontal_scroll: usize) {
+        self.scroll_offset = scroll_offset;
+        self.horizontal_scroll = horizontal_scroll;
+        self.needs_full_redraw = true;
+    }
+
+    /// Parse a `COLSxROWS` size string such as "80x24"
+    fn parse_terminal_size(value: &str) -> Option<(u16, u16)> {
+        let (cols, rows) = value.trim().split_once(['x', 'X'])?;
+        Some((cols.trim().parse().ok()?, rows.trim().parse().ok()?))
+    }
+
+    /// Current terminal size: the injected/last reported size if known,
+    /// otherwise queried from the terminal
+    pub fn terminal_size(&self) -> (u16, u16) {
+        self.terminal_size.unwrap_or_else(|| size().unwrap_or((80, 24)))
+    }
+
+    /// Inject a fixed terminal size (None to query the terminal again)
+    pub fn set_terminal_size(&mut self, size: Option<(u16, u16)>) {
+        if self.terminal_size != size {
+            self.terminal_size = size;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    /// Record a size reported by a terminal resize event
+    pub fn handle_resize(&mut self, width: u16, height: u16) {
+        self.set_terminal_size(Some((width, height)));
+    }
+
+    /// Work out the screen geometry for the current terminal size and
+    /// scroll so the cursor stays visible within it
+    pub(crate) fn update_layout(&mut self, view_model: &dyn ViewModel, has_buffer_info: bool) -> ScreenLayout {
+        let (width, height) = self.terminal_size();
+        let start_line = if has_buffer_info { 1 } else { 0 };
+        self.header_lines = start_line;
+
+        // Rows left after the header and status line
+        let max_lines = (height as usize).saturating_sub(1 + start_line);
+
+        let sign_width = Self::sign_width(view_model);
+        let line_num_width = self.gutter_width(view_model.get_line_count());
+
+        // Adjust available width for text (minimum width 1)
+        let text_width = (width as usize).saturating_sub(sign_width + line_num_width).max(1);
+
+        // Adjust scrolling to keep cursor visible
+        self.adjust_scroll_to_cursor(view_model, max_lines, text_width);
+
+        ScreenLayout {
+            width,
+            height,
+            start_line,
+            max_lines,
+            sign_width,
+            line_num_width,
+            text_width,
+        }
+    }
+
+    /// Columns the line numbers take for a buffer of `line_count` lines
+    fn gutter_width(&self, line_count: usize) -> usize {
+        if self.show_line_numbers {
+            (line_count.to_string().len() + 1).max(4)
+        } else {
+            0
+        }
+    }
+
+    /// Columns the sign column takes: two while the buffer has signs or
+    /// diagnostics, none otherwise
+    fn sign_width(view_model: &dyn ViewModel) -> usize {
+        if view_model.signs().is_empty() && view_model.diagnostics().is_empty() { 0 } else { 2 }
+    }
+
+    /// The sign column's cell for `line`: its worst diagnostic, or else
+    /// its git sign
+    fn sign_cell(&self, view_model: &dyn ViewModel, line: usize) -> String {
+        if let Some(diagnostic) = diagnostics::worst_on(view_model.diagnostics(), line) {
+            let style = Style { underline: false, ..self.diagnostic_style(diagnostic.severity) };
+            return style.paint(diagnostic.severity.sign());
+        }
+        match view_model.signs().get(&line) {
+            Some(&sign) => {
+                let style = match sign {
+                    GitSign::Added => self.theme.diff_add,
+                    GitSign::Modified => self.theme.diff_change,
+                    GitSign::Removed => self.theme.diff_delete,
+                };
+                style.paint(sign.text())
+            }
+            None => "  ".to_string(),
+        }
+    }
+
+    fn diagnostic_style(&self, severity: Severity) -> Style {
+        match severity {
+            Severity::Error => self.theme.diagnostic_error,
+            _ => self.theme.diagnostic_warning,
+        }
+    }
+
+    /// Screen position of the document cursor for the given layout
+    pub(crate) fn cursor_screen_position(&self, view_model: &dyn ViewModel, layout: &ScreenLayout) -> (usize, usize) {
+        let cursor_pos = view_model.get_cursor_position();
+        let screen_line = cursor_pos.line.saturating_sub(self.scroll_offset) + layout.start_line;
+
+        // Convert logical cursor position to display column position
+        let line_content = view_model.get_line(cursor_pos.line).unwrap_or_default();
+        let display_column = self.calculate_display_column(&line_content, cursor_pos.column);
+        let screen_column = display_column.saturating_sub(self.horizontal_scroll) + layout.sign_width + layout.line_num_width;
+
+        (screen_line, screen_column)
+    }
+
+    /// Clip `text` to at most `width` columns without splitting a character
+    /// `status` with the keys of a command typed in part at the right, where
+    /// vim's 'showcmd' puts them: the last of them when there are too many
+    fn with_pending_keys(status: &str, keys: &str, width: usize) -> String {
+        let column = width.saturating_sub(SHOW_COMMAND_WIDTH + 1);
+        let skip = keys.chars().count().saturating_sub(SHOW_COMMAND_WIDTH);
+        let keys: String = keys.chars().skip(skip).collect();
+        let status = Self::clip_to_width(status, column.saturating_sub(1));
+        format!("{status:<column$}{keys}")
+    }
+
+    fn clip_to_width(text: &str, width: usize) -> &str {
+        match text.char_indices().nth(width) {
+            Some((idx, _)) => &text[..idx],
+            None => text,
         }
     }
 
-    fn clear_screen(&self) -> io::Result<()> {
-        execute!(stdout(), Clear(ClearType::All))
+    fn clear_screen(&mut self) -> io::Result<()> {
+        queue!(self.output, Clear(ClearType::All))
     }
 
-    fn move_cursor(&self, line: usize, column: usize) -> io::Result<()> {
-        execute!(stdout(), cursor::MoveTo(column as u16, line as u16))
+    fn move_cursor(&mut self, line: usize, column: usize) -> io::Result<()> {
+        queue!(self.output, cursor::MoveTo(column as u16, line as u16))
     }
 
-    fn apply_highlighting(
+    /// The part of `line` inside the horizontal scroll window, with tabs
+    /// expanded (drawn as 'listchars' with 'list'), wide characters taking
+    /// two cells, and search and bracket highlighting applied, as well as
+    /// trailing whitespace with 'hltrailing' and the underlines of the
+    /// buffer's diagnostics.
+    /// A '<' in the first column and a '>' in the last mark text that
+    /// continues off screen, including a wide character cut by the edge.
+    /// Past 'synmaxcol' the line is drawn plain so huge lines stay cheap.
+    fn visible_text(
         &self,
-        text: &str,
+        line: &str,
         line_idx: usize,
-        cursor_line: usize,
-        cursor_col: usize,
-        horizontal_scroll: usize,
-        search_state: Option<&SearchState>,
-        bracket_highlights: Option<&BracketHighlight>,
+        view_model: &dyn ViewModel,
+        text_width: usize,
+        max_column: usize,
+        params: &RenderParams,
     ) -> String {
+        let cursor = view_model.get_cursor_position();
+        let cursor = (cursor.line, cursor.column);
+        let diagnostics = view_model.diagnostics();
+        let (left, right) = (self.horizontal_scroll, self.horizontal_scroll + text_width);
+        let (first_match, line_matches) = params.search_state
+            .map_or((0, &[][..]), |search| search.highlighted_matches_on_line(line_idx));
+        let trailing = line.trim_end_matches([' ', '\t']).len();
+        let trailing_from = if self.show_trailing { trailing } else { line.len() };
+        let cursor_row = self.cursor_line && line_idx == cursor.0;
         let mut result = String::new();
-        let chars: Vec<char> = text.chars().collect();
-
-        for (i, ch) in chars.iter().enumerate() {
-            let actual_col = horizontal_scroll + i;
-            let mut highlighted = false;
-
-            // Search highlighting
-            if let Some(search) = search_state {
-                if !search.matches.is_empty() {
-                    for search_match in &search.matches {
-                        if search_match.line == line_idx
-                            && actual_col >= search_match.start_col
-                            && actual_col < search_match.end_col
-                        {
-                            if actual_col == search_match.start_col {
-                                // Start highlight
-                                result.push_str(&format!(
-                                    "{}{}",
-                                    SetBackgroundColor(Color::Yellow),
-                                    SetForegroundColor(Color::Black)
-                                ));
-                            }
-                            result.push(*ch);
-                            if actual_col == search_match.end_col - 1 {
-                                // End highlight
-                                result.push_str(&format!("{ResetColor}"));
-                            }
-                            highlighted = true;
-                            break;
-                        }
-                    }
-                }
+        let mut column = 0;
+
+        for (byte_col, grapheme) in line.grapheme_indices(true) {
+            let start = column;
+            column += self.cell_width(grapheme, start);
+            if column <= left {
+                continue;
+            }
+            if start >= right {
+                break;
             }
 
-            // Bracket highlighting
-            if !highlighted {
-                let is_cursor_bracket = line_idx == cursor_line
-                    && actual_col == cursor_col
-                    && matches!(*ch, '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>');
-
-                let is_matching_bracket = if let Some(highlights) = bracket_highlights {
-                    if let Some((match_line, match_col)) = highlights.matching {
-                        line_idx == match_line
-                            && actual_col == match_col
-                            && matches!(*ch, '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>')
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
+            let cut_left = left > 0 && start <= left;
+            let cut_right = column > right || (column == right && byte_col + grapheme.len() < line.len());
+            if cut_left || cut_right {
+                // Truncation markers get no highlighting but the row's own
+                let blank = " ".repeat(column.min(right) - start.max(left) - 1);
+                let marker = if cut_left { format!("<{blank}") } else { format!("{blank}>") };
+                result.push_str(&self.row_background(start.max(left)..column.min(right), cursor_row).paint(&marker));
+                continue;
+            }
 
-                // Check if this position is an unmatched bracket (cursor-specific)
-                let is_cursor_unmatched_bracket = if let Some(highlights) = bracket_highlights {
-                    if let Some((unmatch_line, unmatch_col)) = highlights.unmatched_at_cursor {
-                        line_idx == unmatch_line
-                            && actual_col == unmatch_col
-                            && matches!(*ch, '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>')
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
+            let cell = self.whitespace_cell(grapheme, column - start, byte_col >= trailing)
+                .unwrap_or_else(|| grapheme.to_string());
 
-                // Check if this position is in the list of all unmatched brackets
-                let is_all_unmatched_bracket = if let Some(highlights) = bracket_highlights {
-                    highlights.all_unmatched.iter().any(|(unmatch_line, unmatch_col)| {
-                        line_idx == *unmatch_line
-                            && actual_col == *unmatch_col
-                            && matches!(*ch, '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>')
+            let style = if byte_col >= max_column {
+                None
+            } else {
+                self.highlight_style(line_idx, byte_col, grapheme, cursor, (first_match, line_matches), params)
+                    .or_else(|| {
+                        let diagnostic = diagnostics.iter().filter(|d| d.covers(line_idx, byte_col)).min_by_key(|d| d.severity);
+                        diagnostic.map(|diagnostic| self.diagnostic_style(diagnostic.severity))
                     })
-                } else {
-                    false
-                };
-
-                if is_cursor_unmatched_bracket || is_all_unmatched_bracket {
-                    // Highlight unmatched brackets with red background
-                    result.push_str(&format!(
-                        "{}{}{}{}",
-                        SetBackgroundColor(Color::Red),
-                        SetForegroundColor(Color::White),
-                        ch,
-                        ResetColor
-                    ));
-                    highlighted = true;
-                } else if is_cursor_bracket || is_matching_bracket {
-                    // Highlight matched brackets with cyan background
-                    result.push_str(&format!(
-                        "{}{}{}{}",
-                        SetBackgroundColor(Color::Cyan),
-                        SetForegroundColor(Color::Black),
-                        ch,
-                        ResetColor
-                    ));
-                    highlighted = true;
+                    .or_else(|| (byte_col >= trailing_from).then_some(self.theme.trailing_whitespace))
+            };
+            let style = style.unwrap_or(Style::PLAIN);
+            if cell.chars().count() > 1 && cell.chars().count() == column - start {
+                // A tab is drawn a character a cell, so a colour column can fall inside it
+                for (offset, c) in cell.chars().enumerate() {
+                    let under = self.row_background(start + offset..start + offset + 1, cursor_row);
+                    result.push_str(&style.over(under).paint(&c.to_string()));
                 }
+            } else {
+                result.push_str(&style.over(self.row_background(start..column, cursor_row)).paint(&cell));
             }
+        }
 
-            if !highlighted {
-                result.push(*ch);
-            }
+        // The end of line mark takes the cell after the last character
+        let mut end = column.max(left);
+        if let Some(eol) = self.end_of_line_mark()
+            && (left..right).contains(&column)
+        {
+            result.push_str(&self.row_background(column..column + 1, cursor_row).paint(&eol.to_string()));
+            end += 1;
         }
 
+        // The cursor line and colour columns go on past the text
+        let mut backgrounds: Vec<Style> = (end..right).map(|c| self.row_background(c..c + 1, cursor_row)).collect();
+        while backgrounds.last() == Some(&Style::PLAIN) {
+            backgrounds.pop();
+        }
+        for run in backgrounds.chunk_by(|a, b| a == b) {
+            result.push_str(&run[0].paint(&" ".repeat(run.len())));
+        }
         result
     }
 
-    pub fn render<'a>(&mut self, view_model: &dyn ViewModel, params: &RenderParams<'a>) -> io::Result<()> {
-        let (width, height) = size()?;
-        let start_line = if params.buffer_info.is_some() {
-            1usize
+    /// What is drawn under the text of display columns `columns`: a
+    /// 'colorcolumn' among them, or the cursor's line with 'cursorline'
+    fn row_background(&self, columns: std::ops::Range<usize>, cursor_row: bool) -> Style {
+        if self.color_columns.iter().any(|&number| columns.contains(&(number - 1))) {
+            self.theme.color_column
+        } else if cursor_row {
+            self.theme.cursor_line
+        } else {
+            Style::PLAIN
+        }
+    }
+
+    /// How 'list' draws a whitespace grapheme of `width` cells, or None to
+    /// draw it as it is
+    fn whitespace_cell(&self, grapheme: &str, width: usize, trailing: bool) -> Option<String> {
+        if self.show_whitespace {
+            self.list_chars.whitespace(grapheme, width, trailing)
         } else {
-            0usize
+            (grapheme == "\t").then(|| " ".repeat(width))
+        }
+    }
+
+    fn end_of_line_mark(&self) -> Option<char> {
+        self.list_chars.eol.filter(|_| self.show_whitespace)
+    }
+
+    /// The theme's style for the grapheme at `byte_col`, if it is part of a
+    /// search match or a highlighted bracket
+    fn highlight_style(
+        &self,
+        line_idx: usize,
+        byte_col: usize,
+        grapheme: &str,
+        cursor: (usize, usize),
+        (first_match, line_matches): (usize, &[SearchMatch]),
+        params: &RenderParams,
+    ) -> Option<Style> {
+        // Search highlighting, per character so matches spanning lines, or
+        // starting left of the horizontal scroll, still show
+        if let Some(search) = params.search_state
+            && let Some(offset) = line_matches.iter().position(|m| m.contains(line_idx, byte_col))
+        {
+            // The incremental search target stands out
+            let current = search.preview_matches.is_some() && search.preview_current == Some(first_match + offset);
+            return Some(if current { self.theme.search_current } else { self.theme.search });
+        }
+
+        // Bracket highlighting
+        if !matches!(grapheme, "(" | ")" | "[" | "]" | "{" | "}" | "<" | ">") {
+            return None;
+        }
+        let position = (line_idx, byte_col);
+        let highlights = params.bracket_highlights;
+        let unmatched = highlights.is_some_and(|h| {
+            h.unmatched_at_cursor == Some(position) || h.all_unmatched.contains(&position)
+        });
+        if unmatched {
+            Some(self.theme.bracket_unmatched)
+        } else if position == cursor || highlights.is_some_and(|h| h.matching == Some(position)) {
+            Some(self.theme.bracket_match)
+        } else {
+            None
+        }
+    }
+
+    /// Cells `grapheme` takes when it starts at display column `column`
+    fn cell_width(&self, grapheme: &str, column: usize) -> usize {
+        if grapheme == "\t" {
+            self.tab_stop - column % self.tab_stop
+        } else {
+            grapheme_width(grapheme)
+        }
+    }
+
+    /// Everything one frame puts on screen, worked out without touching the
+    /// terminal. render() draws it; tests inspect it directly.
+    pub(crate) fn compose_frame(&mut self, view_model: &dyn ViewModel, params: &RenderParams) -> Frame {
+        let layout = self.update_layout(view_model, params.buffer_info.is_some());
+
+        // Get visible lines with scrolling applied
+        let mut long_line_clipped = false;
+        let mut scrolled = 0;
+        if self.needs_full_redraw {
+            self.row_cache.clear();
+        }
+        let (mut rows, text_cursor) = if params.window_panes.len() > 1 {
+            self.row_cache.clear();
+            self.compose_split_rows(params.window_panes, &layout)
+        } else {
+            let inputs = FrameInputs {
+                key: self.frame_key(&layout, view_model, params),
+                revision: view_model.revision(),
+                scroll_offset: self.scroll_offset,
+                rows: layout.max_lines,
+                marked_lines: Self::marked_lines(view_model, params),
+            };
+            let mut cache = std::mem::take(&mut self.row_cache);
+            scrolled = cache.prepare(inputs, |revision| view_model.changes_since(revision));
+            let (rows, clipped) = self.compose_rows(view_model, params, &layout, &mut cache);
+            self.row_cache = cache;
+            long_line_clipped = clipped;
+            (rows, self.cursor_screen_position(view_model, &layout))
         };
+        if let Some(popup) = params.popup {
+            Self::overlay_popup(&mut rows, popup, layout.width as usize, &self.theme);
+        }
 
-        self.render_count += 1;
+        let status = match *params.mode {
+            Mode::Normal => {
+                if !params.status_message.is_empty() {
+                    params.status_message.to_string()
+                } else if long_line_clipped {
+                    format!(
+                        "-- NORMAL -- (long line: highlighting stops at column {}, see :set synmaxcol)",
+                        self.max_highlight_column
+                    )
+                } else {
+                    "-- NORMAL --".to_string()
+                }
+            }
+            Mode::Insert => "-- INSERT --".to_string(),
+            Mode::Command => format!(":{}", params.command_buffer),
+            Mode::Search => format!("/{}", params.command_buffer),
+            Mode::SearchBackward => format!("?{}", params.command_buffer),
+            Mode::VisualChar => "-- VISUAL --".to_string(),
+            Mode::VisualLine => "-- VISUAL LINE --".to_string(),
+            Mode::VisualBlock => "-- VISUAL BLOCK --".to_string(),
+        };
+        let status = if self.show_command && !params.pending_keys.is_empty() {
+            Self::with_pending_keys(&status, params.pending_keys, layout.width as usize)
+        } else {
+            status
+        };
 
-        // Force full redraw every 50 renders to prevent state drift
-        if self.render_count % 50 == 0 {
-            self.needs_full_redraw = true;
+        // Cursor position, adjusted for scrolling and line numbers
+        let cursor = match *params.mode {
+            Mode::Normal
+            | Mode::Insert
+            | Mode::VisualChar
+            | Mode::VisualLine
+            | Mode::VisualBlock => text_cursor,
+            Mode::Command | Mode::Search | Mode::SearchBackward => {
+                (layout.status_row(), params.command_buffer.len() + 1)
+            }
+        };
+
+        Frame {
+            layout,
+            buffer_info: params.buffer_info.map(|info| Self::clip_to_width(info, layout.width as usize).to_string()),
+            rows,
+            status: self.theme.status_line.paint(Self::clip_to_width(&status, layout.width as usize)),
+            cursor,
+            scrolled,
+        }
+    }
+
+    /// Hash of everything that changes how every text row looks, so the
+    /// rows of the last frame are only used again while it stays the same
+    fn frame_key(&self, layout: &ScreenLayout, view_model: &dyn ViewModel, params: &RenderParams) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (layout, view_model.signs(), view_model.diagnostics()).hash(&mut hasher);
+        (self.horizontal_scroll, self.show_line_numbers, self.tab_stop, self.show_whitespace, self.show_trailing).hash(&mut hasher);
+        (self.list_chars, self.cursor_line, &self.color_columns).hash(&mut hasher);
+        (self.max_highlight_column, &self.theme.name, params.buffer_info).hash(&mut hasher);
+        params.visual_selection.map(|selection| format!("{:?}", selection)).hash(&mut hasher);
+        if let Some(search) = params.search_state {
+            for m in search.highlighted_matches() {
+                (m.line, m.start_col, m.end_line, m.end_col).hash(&mut hasher);
+            }
+            (search.preview_matches.is_some(), search.preview_current).hash(&mut hasher);
         }
+        params.bracket_highlights.map(|highlights| &highlights.all_unmatched).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Lines drawn differently because of where the cursor is: its own,
+    /// for a bracket under it, and those with the bracket highlights
+    fn marked_lines(view_model: &dyn ViewModel, params: &RenderParams) -> Vec<usize> {
+        let mut lines = vec![view_model.get_cursor_position().line];
+        if let Some(highlights) = params.bracket_highlights {
+            lines.extend(highlights.matching.iter().chain(&highlights.unmatched_at_cursor).map(|&(line, _)| line));
+        }
+        lines
+    }
+
+    pub fn render<'a>(&mut self, view_model: &dyn ViewModel, params: &RenderParams<'a>) -> io::Result<()> {
+        let frame = self.compose_frame(view_model, params);
+        let ScreenLayout { width, height, start_line, .. } = frame.layout;
+
+        self.render_count += 1;
 
         // Check if terminal size changed
         let current_size = (width, height);
@@ -209,12 +624,6 @@ impl View {
             self.last_buffer_info = None;
             self.last_status.clear();
             self.last_cursor_pos = (0, 0);
-            // Scrolling will be adjusted below to keep cursor visible
-        }
-
-        // Force full redraw on mode changes to ensure clean state
-        if self.last_mode != *params.mode {
-            self.needs_full_redraw = true;
         }
 
         // Check if we need a full redraw
@@ -228,198 +637,366 @@ impl View {
             self.last_cursor_pos = (0, 0);
         }
 
+        // Scroll the text rows on the terminal rather than drawing them all again
+        if frame.scrolled != 0 && self.last_lines.len() == frame.rows.len() {
+            self.scroll_text_area(frame.scrolled, start_line, frame.rows.len())?;
+        }
+
         // Update buffer info if changed
-        if self.last_buffer_info.as_deref() != params.buffer_info {
+        if self.last_buffer_info != frame.buffer_info {
             self.move_cursor(0, 0)?;
-            execute!(stdout(), Clear(ClearType::CurrentLine))?;
-            if let Some(ref info) = params.buffer_info {
-                let clipped_info = if info.len() > width as usize {
-                    &info[..width as usize]
-                } else {
-                    info
-                };
-                print!("{clipped_info}");
+            queue!(self.output, Clear(ClearType::CurrentLine))?;
+            if let Some(info) = &frame.buffer_info {
+                write!(self.output, "{info}")?;
             }
-            self.last_buffer_info = params.buffer_info.map(|s| s.to_string());
+            self.last_buffer_info = frame.buffer_info;
         }
 
-        // Calculate visible area dimensions
-        let max_lines = if height > (1 + start_line as u16) {
-            (height - 1 - start_line as u16) as usize
-        } else {
-            0
-        };
+        if self.last_lines != frame.rows {
 
-        // Calculate line number width and text offset
-        let line_num_width = if self.show_line_numbers {
-            // Calculate width needed for line numbers (based on total lines)
-            let total_lines = view_model.get_line_count();
-            if total_lines == 0 {
-                4
-            } else {
-                (total_lines.to_string().len() + 1).max(4)
+            // Only redraw changed lines
+            for (i, line) in frame.rows.iter().enumerate() {
+                if i >= self.last_lines.len() || self.last_lines[i] != *line {
+                    self.move_cursor(i + start_line, 0)?;
+                    queue!(self.output, Clear(ClearType::CurrentLine))?;
+                    write!(self.output, "{line}")?;
+                }
             }
-        } else {
-            0
-        };
 
-        // Adjust available width for text
-        let text_width = if width as usize > line_num_width {
-            width as usize - line_num_width
-        } else {
-            1 // Minimum width
-        };
+            // Clear any extra lines if the new content is shorter
+            if frame.rows.len() < self.last_lines.len() {
+                for i in frame.rows.len()..self.last_lines.len() {
+                    if i + start_line < frame.layout.status_row() {
+                        self.move_cursor(i + start_line, 0)?;
+                        queue!(self.output, Clear(ClearType::CurrentLine))?;
+                    }
+                }
+            }
 
-        // Adjust scrolling to keep cursor visible
-        self.adjust_scroll_to_cursor(view_model, max_lines, text_width);
+            self.last_lines = frame.rows;
+        }
 
-        // Get visible lines with scrolling applied
-        let visible_lines: Vec<String> = (0..max_lines)
+        // Update status line if changed
+        if self.last_status != frame.status
+            || self.last_mode != *params.mode
+            || self.last_command_buffer != params.command_buffer
+        {
+            self.move_cursor(frame.layout.status_row(), 0)?;
+            queue!(self.output, Clear(ClearType::CurrentLine))?;
+            write!(self.output, "{}", frame.status)?;
+            self.last_status = frame.status;
+            self.last_mode = *params.mode;
+            self.last_command_buffer = params.command_buffer.to_string();
+        }
+
+        if self.last_cursor_pos != frame.cursor {
+            self.move_cursor(frame.cursor.0, frame.cursor.1)?;
+            self.last_cursor_pos = frame.cursor;
+        }
+
+        self.output.flush()
+    }
+
+    /// Move the `rows` text rows starting at screen row `top` up by
+    /// `scrolled` (down if negative) within a scroll region, so only the
+    /// rows scrolled into view need drawing. The rows kept for comparison
+    /// move the same way.
+    fn scroll_text_area(&mut self, scrolled: isize, top: usize, rows: usize) -> io::Result<()> {
+        let count = scrolled.unsigned_abs();
+        write!(self.output, "\x1b[{};{}r", top + 1, top + rows)?;
+        if scrolled > 0 {
+            queue!(self.output, ScrollUp(count as u16))?;
+            self.last_lines.drain(..count);
+            self.last_lines.extend(std::iter::repeat_n(String::new(), count));
+        } else {
+            queue!(self.output, ScrollDown(count as u16))?;
+            self.last_lines.truncate(rows - count);
+            self.last_lines.splice(0..0, std::iter::repeat_n(String::new(), count));
+        }
+        // Setting the region homes the cursor
+        write!(self.output, "\x1b[r")?;
+        self.last_cursor_pos = (usize::MAX, usize::MAX);
+        Ok(())
+    }
+
+    /// Rows of the text area for a single (unsplit) window, and whether any
+    /// of them was too long to highlight in full. Rows still in `cache` are
+    /// used as they are; the rest are composed and stored there.
+    fn compose_rows(
+        &self,
+        view_model: &dyn ViewModel,
+        params: &RenderParams,
+        layout: &ScreenLayout,
+        cache: &mut RowCache,
+    ) -> (Vec<String>, bool) {
+        let ScreenLayout { max_lines, sign_width, line_num_width, text_width, .. } = *layout;
+        let max_column = self.highlight_line_limit();
+        let mut clipped = false;
+        let rows = (0..max_lines)
             .map(|i| {
+                if let Some(cached) = cache.get(i) {
+                    clipped |= cached.clipped;
+                    return cached.text.clone();
+                }
                 let actual_line_num = self.scroll_offset + i + 1;
                 let doc_line_idx = self.scroll_offset + i;
                 let line_num_str = if self.show_line_numbers {
-                    format!("{:>width$} ", actual_line_num, width = line_num_width - 1)
+                    self.theme.line_number.paint(&format!("{:>width$} ", actual_line_num, width = line_num_width - 1))
                 } else {
                     String::new()
                 };
 
                 // Get the line from document
-                let line = if doc_line_idx < view_model.get_line_count() {
+                let in_buffer = doc_line_idx < view_model.get_line_count();
+                let line = if in_buffer {
                     view_model.get_line(doc_line_idx).unwrap_or_default()
                 } else {
                     String::new()
                 };
 
-                // Apply horizontal scrolling to the text part
-                let line_start = std::cmp::min(self.horizontal_scroll, line.len());
-                let line_end = std::cmp::min(line_start + text_width, line.len());
-                let mut text_part = if line_start < line.len() {
-                    line[line_start..line_end].to_string()
+                // Cut out the visible columns and highlight them; rows past
+                // the end of the buffer stay blank, without an end of line mark
+                clipped |= line.len() > max_column;
+                let text_part = if in_buffer {
+                    self.visible_text(&line, doc_line_idx, view_model, text_width, max_column, params)
                 } else {
                     String::new()
                 };
 
-                // Show whitespace if enabled (before highlighting)
-                if self.show_whitespace {
-                    text_part = text_part
-                        .replace('\t', &format!(">{}", "─".repeat(self.tab_stop - 1)))
-                        .replace(' ', "·");
-                }
-
-                // Apply search and bracket highlighting
-                text_part = self.apply_highlighting(
-                    &text_part,
-                    doc_line_idx,
-                    view_model.get_cursor_position().line,
-                    view_model.get_cursor_position().column,
-                    self.horizontal_scroll,
-                    params.search_state,
-                    params.bracket_highlights,
-                );
-
                 // Add visual selection indicator only when in visual mode
                 let line_marker = if let Some(selection) = params.visual_selection {
                     if selection.is_line_in_selection(doc_line_idx) {
-                        ">" // Simple indicator for selected lines
+                        self.theme.selection.paint(">") // Simple indicator for selected lines
                     } else {
-                        " " // Space to maintain alignment when in visual mode
+                        " ".to_string() // Space to maintain alignment when in visual mode
                     }
                 } else {
-                    "" // No marker when not in visual mode
+                    String::new() // No marker when not in visual mode
+                };
+
+                let sign = if sign_width > 0 && in_buffer {
+                    self.sign_cell(view_model, doc_line_idx)
+                } else {
+                    " ".repeat(sign_width)
                 };
 
-                format!("{line_marker}{line_num_str}{text_part}")
+                let text = format!("{line_marker}{sign}{line_num_str}{text_part}");
+                cache.store(i, CachedRow { text: text.clone(), clipped: line.len() > max_column });
+                text
             })
             .collect();
+        (rows, clipped)
+    }
 
-        if self.last_lines != visible_lines {
-            // Only redraw changed lines
-            for (i, line) in visible_lines.iter().enumerate() {
-                if i >= self.last_lines.len() || self.last_lines[i] != *line {
-                    self.move_cursor(i + start_line, 0)?;
-                    execute!(stdout(), Clear(ClearType::CurrentLine))?;
-                    print!("{line}");
-                }
+    /// Rows of the text area when the screen is split into several windows.
+    /// Each window gets its own scroll position and a status line; vertical
+    /// neighbours are divided by a separator column. Highlighting and visual
+    /// selection markers are only drawn in an unsplit window.
+    /// Returns the rows and the screen position of the active window's cursor.
+    fn compose_split_rows(&mut self, panes: &[WindowPane], layout: &ScreenLayout) -> (Vec<String>, (usize, usize)) {
+        let area = Rect {
+            x: 0,
+            y: layout.start_line,
+            width: layout.width as usize,
+            height: layout.max_lines,
+        };
+        let active = self.windows.active_id();
+
+        // The live scroll position belongs to the active window
+        let window = self.windows.active_mut();
+        window.scroll_offset = self.scroll_offset;
+        window.horizontal_scroll = self.horizontal_scroll;
+
+        let mut segments: Vec<Vec<(usize, usize, String)>> = vec![Vec::new(); area.height];
+        let mut text_cursor = (layout.start_line, 0);
+        let rects = self.windows.layout(area);
+
+        // Windows in diff mode, and those :Gblame binds, scroll together,
+        // lined up row for row with the active one
+        let bound = |pane: &WindowPane, window: &Window| pane.diff.is_some() || window.scroll_bind;
+        let bound_scroll = panes.iter().find(|pane| pane.id == active).and_then(|pane| {
+            if !bound(pane, self.windows.active()) {
+                return None;
             }
+            let (_, rect) = rects.iter().find(|(id, _)| *id == active)?;
+            let line = pane.view_model.get_cursor_position().line;
+            let cursor_row = pane.diff.map_or(line, |rows| diff_row_of(rows, line));
+            Some(scroll_into_view(self.scroll_offset, cursor_row, rect.height.saturating_sub(1)))
+        });
 
-            // Clear any extra lines if the new content is shorter
-            if visible_lines.len() < self.last_lines.len() {
-                for i in visible_lines.len()..self.last_lines.len() {
-                    if i + start_line < (height - 1) as usize {
-                        self.move_cursor(i + start_line, 0)?;
-                        execute!(stdout(), Clear(ClearType::CurrentLine))?;
+        for (id, rect) in rects {
+            let Some(pane) = panes.iter().find(|p| p.id == id) else {
+                continue;
+            };
+            let view_model = pane.view_model.as_ref();
+            let text_rows = rect.height.saturating_sub(1);
+            let line_count = view_model.get_line_count();
+            let sign_width = Self::sign_width(view_model);
+            let number_width = self.gutter_width(line_count);
+            let gutter = sign_width + number_width;
+            let text_width = rect.width.saturating_sub(gutter).max(1);
+
+            // Scroll this window so its cursor stays visible. In diff mode
+            // the scroll counts rows, filler included, rather than lines.
+            let cursor = view_model.get_cursor_position();
+            let cursor_row = pane.diff.map_or(cursor.line, |rows| diff_row_of(rows, cursor.line));
+            let cursor_line_text = view_model.get_line(cursor.line).unwrap_or_default();
+            let display_column = self.calculate_display_column(&cursor_line_text, cursor.column);
+            let Some(window) = self.windows.get_mut(id) else {
+                continue;
+            };
+            window.scroll_offset = match bound_scroll {
+                Some(scroll) if bound(pane, window) => scroll,
+                _ => scroll_into_view(window.scroll_offset, cursor_row, text_rows),
+            };
+            window.horizontal_scroll = scroll_into_view(window.horizontal_scroll, display_column, text_width);
+            let (scroll, hscroll) = (window.scroll_offset, window.horizontal_scroll);
+
+            for row in 0..text_rows {
+                let (line, kind) = match pane.diff {
+                    Some(rows) => rows.get(scroll + row).map_or((None, DiffKind::Same), |r| (r.line, r.kind)),
+                    None => (Some(scroll + row).filter(|&line| line < line_count), DiffKind::Same),
+                };
+                let content = match line {
+                    Some(line_idx) => {
+                        let line_num_str = if self.show_line_numbers {
+                            format!("{:>width$} ", line_idx + 1, width = number_width - 1)
+                        } else {
+                            String::new()
+                        };
+                        let text = self.expand_for_display(&view_model.get_line(line_idx).unwrap_or_default());
+                        format!("{}{}", line_num_str, Self::skip_columns(&text, hscroll))
                     }
-                }
+                    // Filler stands in for lines only the other side of a diff has
+                    None if kind == DiffKind::Filler => format!("{}{}", " ".repeat(number_width), "-".repeat(text_width)),
+                    None => String::new(),
+                };
+                let content = Self::fit_to_width(&content, rect.width.saturating_sub(sign_width));
+                // The gutter is ASCII, so its columns are its bytes
+                let (number, text) = content.split_at(number_width.min(content.len()));
+                let number = if self.show_line_numbers && line.is_some() {
+                    self.theme.line_number.paint(number)
+                } else {
+                    number.to_string()
+                };
+                let text = match kind {
+                    DiffKind::Same => text.to_string(),
+                    DiffKind::Changed => self.theme.diff_change.paint(text),
+                    DiffKind::Added => self.theme.diff_add.paint(text),
+                    DiffKind::Filler => self.theme.diff_delete.paint(text),
+                };
+                let sign = match line {
+                    Some(line_idx) if sign_width > 0 => self.sign_cell(view_model, line_idx),
+                    _ => " ".repeat(sign_width),
+                };
+                segments[rect.y - area.y + row].push((rect.x, rect.width, format!("{}{}{}", sign, number, text)));
+            }
+
+            // Status line at the bottom of each window; the active one is shown reversed
+            if rect.height > 0 {
+                let label = format!(" {}{}", pane.name, if pane.modified { " [+]" } else { "" });
+                let label = Self::fit_to_width(&label, rect.width);
+                let status = if id == active {
+                    self.theme.window_status.paint(&label)
+                } else {
+                    label
+                };
+                segments[rect.y - area.y + text_rows].push((rect.x, rect.width, status));
             }
 
-            self.last_lines = visible_lines;
+            if id == active {
+                self.scroll_offset = scroll;
+                self.horizontal_scroll = hscroll;
+                text_cursor = (
+                    rect.y + cursor_row.saturating_sub(scroll),
+                    rect.x + gutter + display_column.saturating_sub(hscroll),
+                );
+            }
         }
 
-        // Update status line if changed
-        let current_status = match *params.mode {
-            Mode::Normal => {
-                if !params.status_message.is_empty() {
-                    params.status_message.to_string()
-                } else {
-                    "-- NORMAL --".to_string()
+        // Join each row's window segments, filling the gaps with separators
+        let rows = segments
+            .into_iter()
+            .map(|mut row_segments| {
+                row_segments.sort_by_key(|(x, _, _)| *x);
+                let mut row = String::new();
+                let mut column = 0;
+                for (x, width, text) in row_segments {
+                    row.push_str(&"│".repeat(x.saturating_sub(column)));
+                    row.push_str(&text);
+                    column = x + width;
                 }
+                row
+            })
+            .collect();
+
+        (rows, text_cursor)
+    }
+
+    /// Expand tabs to spaces (and mark whitespace when 'list' is on)
+    fn expand_for_display(&self, line: &str) -> String {
+        let trailing = line.trim_end_matches([' ', '\t']).len();
+        let mut result = String::new();
+        let mut column = 0;
+        for (byte_col, grapheme) in line.grapheme_indices(true) {
+            let width = self.cell_width(grapheme, column);
+            match self.whitespace_cell(grapheme, width, byte_col >= trailing) {
+                Some(cell) => result.push_str(&cell),
+                None => result.push_str(grapheme),
             }
-            Mode::Insert => "-- INSERT --".to_string(),
-            Mode::Command => format!(":{}", params.command_buffer),
-            Mode::Search => format!("/{}", params.command_buffer),
-            Mode::SearchBackward => format!("?{}", params.command_buffer),
-            Mode::VisualChar => "-- VISUAL --".to_string(),
-            Mode::VisualLine => "-- VISUAL LINE --".to_string(),
-            Mode::VisualBlock => "-- VISUAL BLOCK --".to_string(),
-        };
+            column += width;
+        }
+        result.extend(self.end_of_line_mark());
+        result
+    }
 
-        if self.last_status != current_status
-            || self.last_mode != *params.mode
-            || self.last_command_buffer != params.command_buffer
-        {
-            self.move_cursor((height - 1) as usize, 0)?;
-            execute!(stdout(), Clear(ClearType::CurrentLine))?;
-            let clipped_status = if current_status.len() > width as usize {
-                &current_status[..width as usize]
+    /// Drop the first `columns` display columns of `text`
+    fn skip_columns(text: &str, columns: usize) -> &str {
+        let mut column = 0;
+        for (idx, ch) in text.char_indices() {
+            if column >= columns {
+                return &text[idx..];
+            }
+            column += ch.width().unwrap_or(1);
+        }
+        ""
+    }
+
+    /// Draw `popup` over the last of `rows`: a reversed title line, then as
+    /// many items as fit, scrolled so the selected one shows
+    fn overlay_popup(rows: &mut [String], popup: &ListPopup, width: usize, theme: &Theme) {
+        let height = (popup.items.len() + 1).min(rows.len());
+        if height == 0 {
+            return;
+        }
+        let top = rows.len() - height;
+        let first = if popup.selected < popup.items.len() { (popup.selected + 1).saturating_sub(height - 1) } else { 0 };
+
+        rows[top] = theme.popup_title.paint(&Self::fit_to_width(&popup.title, width));
+        for (row, index) in (top + 1..rows.len()).zip(first..) {
+            let item = Self::fit_to_width(&popup.items[index], width);
+            rows[row] = if index == popup.selected {
+                theme.popup_selected.paint(&item)
             } else {
-                &current_status
+                item
             };
-            print!("{clipped_status}");
-            self.last_status = current_status;
-            self.last_mode = *params.mode;
-            self.last_command_buffer = params.command_buffer.to_string();
         }
+    }
 
-        // Update cursor position if changed (adjusted for scrolling and line numbers)
-        let new_cursor_pos = match &self.last_mode {
-            Mode::Normal
-            | Mode::Insert
-            | Mode::VisualChar
-            | Mode::VisualLine
-            | Mode::VisualBlock => {
-                let cursor_pos = view_model.get_cursor_position();
-                let screen_line = cursor_pos.line.saturating_sub(self.scroll_offset) + start_line;
-                
-                // Convert logical cursor position to display column position
-                let line_content = view_model.get_line(cursor_pos.line).unwrap_or_default();
-                let display_column = self.calculate_display_column(&line_content, cursor_pos.column);
-                let screen_column = display_column.saturating_sub(self.horizontal_scroll) + line_num_width;
-                
-                (screen_line, screen_column)
+    /// Clip or pad `text` to exactly `width` display columns
+    fn fit_to_width(text: &str, width: usize) -> String {
+        let mut result = String::new();
+        let mut used = 0;
+        for ch in text.chars() {
+            let ch_width = ch.width().unwrap_or(1);
+            if used + ch_width > width {
+                break;
             }
-            Mode::Command => ((height - 1) as usize, self.last_command_buffer.len() + 1),
-            Mode::Search => ((height - 1) as usize, self.last_command_buffer.len() + 1),
-            Mode::SearchBackward => ((height - 1) as usize, self.last_command_buffer.len() + 1),
-        };
-
-        if self.last_cursor_pos != new_cursor_pos {
-            self.move_cursor(new_cursor_pos.0, new_cursor_pos.1)?;
-            self.last_cursor_pos = new_cursor_pos;
+            result.push(ch);
+            used += ch_width;
         }
-
-        stdout().flush()?;
-        Ok(())
+        result.push_str(&" ".repeat(width - used));
+        result
     }
 
     pub fn force_redraw(&mut self) {
@@ -453,78 +1030,258 @@ impl View {
         }
     }
 
+    pub fn set_list_chars(&mut self, list_chars: ListChars) {
+        if self.list_chars != list_chars {
+            self.list_chars = list_chars;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    pub fn get_list_chars(&self) -> ListChars {
+        self.list_chars
+    }
+
+    pub fn set_cursor_line(&mut self, show: bool) {
+        if self.cursor_line != show {
+            self.cursor_line = show;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    pub fn get_cursor_line(&self) -> bool {
+        self.cursor_line
+    }
+
+    pub fn set_show_command(&mut self, show: bool) {
+        self.show_command = show;
+    }
+
+    pub fn get_show_command(&self) -> bool {
+        self.show_command
+    }
+
+    /// Columns counted from 1, as parse_color_columns gives them
+    pub fn set_color_columns(&mut self, columns: Vec<usize>) {
+        if self.color_columns != columns {
+            self.color_columns = columns;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    pub fn get_color_columns(&self) -> &[usize] {
+        &self.color_columns
+    }
+
+    pub fn set_show_trailing(&mut self, show: bool) {
+        if self.show_trailing != show {
+            self.show_trailing = show;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Draw with `theme` from the next frame on
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.needs_full_redraw = true;
+    }
+
     pub fn get_tab_stop(&self) -> usize {
         self.tab_stop
     }
 
+    pub fn get_line_numbers(&self) -> bool {
+        self.show_line_numbers
+    }
+
+    pub fn get_show_whitespace(&self) -> bool {
+        self.show_whitespace
+    }
+
+    pub fn get_show_trailing(&self) -> bool {
+        self.show_trailing
+    }
+
+    pub fn get_max_highlight_column(&self) -> usize {
+        self.max_highlight_column
+    }
+
+    pub fn set_max_highlight_column(&mut self, column: usize) {
+        if self.max_highlight_column != column {
+            self.max_highlight_column = column;
+            self.needs_full_redraw = true;
+        }
+    }
+
+    /// Longest line that still gets search and bracket highlighting
+    pub fn highlight_line_limit(&self) -> usize {
+        match self.max_highlight_column {
+            0 => usize::MAX,
+            column => column,
+        }
+    }
+
     pub fn get_scroll_offset(&self) -> usize {
         self.scroll_offset
     }
 
-    pub fn get_visible_lines_count(&self) -> usize {
-        // Calculate visible lines based on terminal height
-        let (_, height) = crossterm::terminal::size().unwrap_or((80, 24));
-        let start_line = if self.show_line_numbers { 1 } else { 0 };
-        if height > (1 + start_line as u16) {
-            (height - 1 - start_line as u16) as usize
+    /// Screen area shared by the windows: everything but the header and status line
+    pub fn window_area(&self) -> Rect {
+        let (width, height) = self.terminal_size();
+        Rect {
+            x: 0,
+            y: self.header_lines,
+            width: width as usize,
+            height: (height as usize).saturating_sub(1 + self.header_lines),
+        }
+    }
+
+    /// Columns of text the active window shows for the buffer of `view_model`
+    pub fn text_columns(&self, view_model: &dyn ViewModel) -> usize {
+        let area = self.window_area();
+        let width = if self.windows.is_split() {
+            self.windows
+                .layout(area)
+                .into_iter()
+                .find(|(id, _)| *id == self.windows.active_id())
+                .map_or(0, |(_, rect)| rect.width)
         } else {
-            0
+            area.width
+        };
+        let gutter = Self::sign_width(view_model) + self.gutter_width(view_model.get_line_count());
+        width.saturating_sub(gutter).max(1)
+    }
+
+    /// Scroll `lines` rows down the buffer (negative scrolls up), no further
+    /// than leaves the last of `line_count` lines at the top. Returns the
+    /// new top line; the caller keeps the cursor on screen.
+    pub fn scroll_vertically(&mut self, lines: isize, line_count: usize) -> usize {
+        self.scroll_offset = self.scroll_offset.saturating_add_signed(lines).min(line_count.saturating_sub(1));
+        self.scroll_offset
+    }
+
+    /// Scroll sideways by `columns` display columns (negative scrolls left),
+    /// but not past the start of the last character of `line`, the cursor
+    /// line. Returns the byte column the cursor has to move to so it stays
+    /// on screen.
+    pub fn scroll_horizontally(&mut self, line: &str, cursor_column: usize, columns: isize, view_model: &dyn ViewModel) -> usize {
+        // Byte column and first and last+1 display columns of each grapheme
+        let mut cells = Vec::new();
+        let mut display_column = 0;
+        for (byte_col, grapheme) in line.grapheme_indices(true) {
+            let width = self.cell_width(grapheme, display_column);
+            cells.push((byte_col, display_column, display_column + width));
+            display_column += width;
+        }
+
+        let last_start = cells.last().map_or(0, |&(_, start, _)| start);
+        let left = self.horizontal_scroll.saturating_add_signed(columns).min(last_start);
+        if left != self.horizontal_scroll {
+            self.horizontal_scroll = left;
+            self.needs_full_redraw = true;
+        }
+        let right = left + self.text_columns(view_model);
+
+        let cursor_column = grapheme_start(line, cursor_column);
+        let (start, end) = cells
+            .iter()
+            .find(|&&(byte_col, _, _)| byte_col == cursor_column)
+            .map_or((display_column, display_column + 1), |&(_, start, end)| (start, end));
+        if start < left {
+            cells.iter().find(|&&(_, start, _)| start >= left).map_or(cursor_column, |&(byte_col, _, _)| byte_col)
+        } else if end > right {
+            cells.iter().rev().find(|&&(_, _, end)| end <= right).map_or(cursor_column, |&(byte_col, _, _)| byte_col)
+        } else {
+            cursor_column
         }
     }
 
+    pub fn get_visible_lines_count(&self) -> usize {
+        // Same rows render() uses: everything but the header and status line
+        let area = self.window_area();
+        if !self.windows.is_split() {
+            return area.height;
+        }
+
+        // Split windows each lose a row to their own status line
+        self.windows
+            .layout(area)
+            .into_iter()
+            .find(|(id, _)| *id == self.windows.active_id())
+            .map_or(0, |(_, rect)| rect.height.saturating_sub(1))
+    }
+
     fn adjust_scroll_to_cursor(&mut self, view_model: &dyn ViewModel, visible_lines: usize, width: usize) {
         let cursor_pos = view_model.get_cursor_position();
         let cursor_line = cursor_pos.line;
-        let cursor_column = cursor_pos.column;
+
+        // Horizontal scrolling works in display columns; a wide character
+        // under the cursor must fit on screen as a whole
+        let line = view_model.get_line(cursor_line).unwrap_or_default();
+        let cursor_column = self.calculate_display_column(&line, cursor_pos.column);
+        let cursor_width = line
+            .get(cursor_pos.column..)
+            .and_then(|rest| rest.graphemes(true).next())
+            .filter(|grapheme| *grapheme != "\t")
+            .map_or(1, grapheme_width);
 
         // Adjust vertical scrolling
-        if cursor_line < self.scroll_offset {
+        if visible_lines == 0 {
+            // No room for text (terminal too small); pin the cursor line to the top
+            self.scroll_offset = cursor_line;
+        } else if cursor_line < self.scroll_offset {
             // Cursor is above visible area - scroll up
             self.scroll_offset = cursor_line;
-            self.needs_full_redraw = true;
         } else if cursor_line >= self.scroll_offset + visible_lines {
             // Cursor is below visible area - scroll down
             self.scroll_offset = cursor_line - visible_lines + 1;
-            self.needs_full_redraw = true;
         }
 
-        // Adjust horizontal scrolling
+        // Adjust horizontal scrolling; the rows are composed again, but only
+        // those that differ are drawn
         if cursor_column < self.horizontal_scroll {
             // Cursor is left of visible area - scroll left
             self.horizontal_scroll = cursor_column;
-            self.needs_full_redraw = true;
-        } else if cursor_column >= self.horizontal_scroll + width {
+        } else if cursor_column + cursor_width > self.horizontal_scroll + width {
             // Cursor is right of visible area - scroll right
-            self.horizontal_scroll = cursor_column - width + 1;
-            self.needs_full_redraw = true;
+            self.horizontal_scroll = (cursor_column + cursor_width).saturating_sub(width);
         }
     }
 
-    /// Convert logical character position to display column position
-    /// Accounts for tab expansion and Unicode character widths
-    fn calculate_display_column(&self, text: &str, logical_pos: usize) -> usize {
-        let chars: Vec<char> = text.chars().collect();
-        let mut display_col = 0;
-        
-        for i in 0..logical_pos.min(chars.len()) {
-            match chars[i] {
-                '\t' => {
-                    // Move to next tab stop
-                    display_col = ((display_col / self.tab_stop) + 1) * self.tab_stop;
-                }
-                c => {
-                    // Use unicode-width crate for proper Unicode handling
-                    display_col += c.width().unwrap_or(1);
-                }
-            }
-        }
-        display_col
+    /// Convert a byte column to the display column it is drawn at.
+    /// Accounts for tab expansion and wide characters; a column inside a
+    /// grapheme cluster counts as the start of that cluster.
+    fn calculate_display_column(&self, text: &str, column: usize) -> usize {
+        let end = grapheme_start(text, column.min(text.len()));
+        text[..end]
+            .graphemes(true)
+            .fold(0, |display_col, grapheme| display_col + self.cell_width(grapheme, display_col))
+    }
+}
+
+/// Scroll offset that keeps `position` inside a window of `visible` rows/columns
+/// The row of a diff window that shows `line`
+fn diff_row_of(rows: &[DiffRow], line: usize) -> usize {
+    rows.iter().position(|row| row.line.is_some_and(|l| l >= line)).unwrap_or(rows.len().saturating_sub(1))
+}
+
+fn scroll_into_view(offset: usize, position: usize, visible: usize) -> usize {
+    if visible == 0 || position < offset {
+        position
+    } else if position >= offset + visible {
+        position + 1 - visible
+    } else {
+        offset
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crossterm::style::{Attribute, Color, SetAttribute, SetBackgroundColor, SetForegroundColor};
 
     #[test]
     fn test_calculate_display_column() {
@@ -547,14 +1304,298 @@ mod tests {
         assert_eq!(view.calculate_display_column("abc\t", 4), 4); // tab from 3 goes to 4
         assert_eq!(view.calculate_display_column("abcd\t", 5), 8); // tab from 4 goes to 8
         
-        // Test Unicode characters
+        // Test Unicode characters (columns are byte offsets)
         assert_eq!(view.calculate_display_column("a中b", 0), 0); // 'a'
         assert_eq!(view.calculate_display_column("a中b", 1), 1); // '中' starts at 1
-        assert_eq!(view.calculate_display_column("a中b", 2), 3); // 'b' at 3 (中 is 2 wide)
+        assert_eq!(view.calculate_display_column("a中b", 2), 1); // Inside '中'
+        assert_eq!(view.calculate_display_column("a中b", 4), 3); // 'b' at 3 (中 is 2 wide)
         
         // Test emojis (wide characters)
         assert_eq!(view.calculate_display_column("a😀b", 0), 0); // 'a'
         assert_eq!(view.calculate_display_column("a😀b", 1), 1); // '😀' starts at 1
-        assert_eq!(view.calculate_display_column("a😀b", 2), 3); // 'b' at 3 (😀 is 2 wide)
+        assert_eq!(view.calculate_display_column("a😀b", 5), 3); // 'b' at 3 (😀 is 2 wide)
+
+        // Combining marks share their base character's cell
+        assert_eq!(view.calculate_display_column("e\u{301}x", 3), 1);
+    }
+
+    fn numbered_doc(lines: usize) -> crate::document_model::Document {
+        let content: Vec<String> = (0..lines).map(|i| format!("line {}", i)).collect();
+        crate::document_model::Document::from_string(content.join("\n"))
+    }
+
+    #[test]
+    fn test_parse_terminal_size() {
+        assert_eq!(View::parse_terminal_size("80x24"), Some((80, 24)));
+        assert_eq!(View::parse_terminal_size(" 120X40 "), Some((120, 40)));
+        assert_eq!(View::parse_terminal_size("80"), None);
+        assert_eq!(View::parse_terminal_size("axb"), None);
+    }
+
+    #[test]
+    fn test_injected_size_drives_layout() {
+        let mut view = View::new();
+        view.set_terminal_size(Some((40, 10)));
+        let doc = numbered_doc(100);
+        let layout = view.update_layout(&crate::view::DocumentViewModel::new(&doc), true);
+
+        assert_eq!((layout.width, layout.height), (40, 10));
+        assert_eq!(layout.max_lines, 8); // minus header and status line
+        assert_eq!(layout.status_row(), 9);
+        assert_eq!(view.get_visible_lines_count(), 8);
+    }
+
+    #[test]
+    fn test_degenerate_sizes_do_not_underflow() {
+        let mut view = View::new();
+        let mut doc = numbered_doc(50);
+        doc.set_cursor(30, 3).unwrap();
+
+        for size in [(0, 0), (1, 1), (0, 2), (5, 3), (1, 0)] {
+            view.set_terminal_size(Some(size));
+            let vm = crate::view::DocumentViewModel::new(&doc);
+            let layout = view.update_layout(&vm, true);
+            assert!(layout.text_width >= 1);
+            assert_eq!(layout.status_row(), size.1.saturating_sub(1) as usize);
+            assert!(view.get_scroll_offset() <= 30);
+        }
+    }
+
+    #[test]
+    fn test_shrinking_keeps_cursor_on_screen() {
+        let mut view = View::new();
+        let mut doc = numbered_doc(200);
+        view.set_terminal_size(Some((80, 50)));
+        doc.set_cursor(45, 0).unwrap();
+        view.update_layout(&crate::view::DocumentViewModel::new(&doc), true);
+        assert_eq!(view.get_scroll_offset(), 0);
+
+        // Shrinking below the cursor line scrolls just enough to keep it visible
+        view.handle_resize(80, 12);
+        let vm = crate::view::DocumentViewModel::new(&doc);
+        let layout = view.update_layout(&vm, true);
+        let (row, _) = view.cursor_screen_position(&vm, &layout);
+        assert_eq!(layout.max_lines, 10);
+        assert_eq!(view.get_scroll_offset(), 36);
+        assert_eq!(row, layout.start_line + layout.max_lines - 1);
+    }
+
+    #[test]
+    fn test_split_rows_have_separator_and_status_lines() {
+        let mut view = View::new();
+        view.set_terminal_size(Some((31, 6)));
+        let left_doc = numbered_doc(10);
+        let mut right_doc = crate::document_model::Document::from_string("a\tb".to_string());
+        right_doc.set_cursor(0, 2).unwrap();
+
+        let left = view.windows_mut().split(super::super::SplitDirection::Vertical);
+        let panes = [
+            WindowPane {
+                id: left,
+                view_model: Box::new(crate::view::DocumentViewModel::with_cursor(&left_doc, (7, 0))),
+                name: "left.txt".to_string(),
+                modified: true,
+                diff: None,
+            },
+            WindowPane {
+                id: 0,
+                view_model: Box::new(crate::view::DocumentViewModel::new(&right_doc)),
+                name: "right.txt".to_string(),
+                modified: false,
+                diff: None,
+            },
+        ];
+
+        let layout = view.update_layout(panes[0].view_model.as_ref(), true);
+        let (rows, cursor) = view.compose_split_rows(&panes, &layout);
+
+        // 4 rows: 3 text rows per window plus their status lines
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], format!("{:<15}│{:<15}", "line 5", "a   b"));
+        assert_eq!(rows[2], format!("{:<15}│{:<15}", "line 7", ""));
+        assert!(rows[3].contains(" left.txt [+]"));
+        assert!(rows[3].ends_with(&format!("│{:<15}", " right.txt")));
+        // Active (left) window scrolled so its cursor line 7 is on the last text row
+        assert_eq!(cursor, (3, 0));
+        assert_eq!(view.get_visible_lines_count(), 3);
+    }
+
+    #[test]
+    fn test_long_lines_are_highlighted_up_to_synmaxcol() {
+        use crate::document_model::SearchDirection;
+
+        let mut view = View::new();
+        view.set_terminal_size(Some((80, 6)));
+        view.set_max_highlight_column(10);
+        let doc = crate::document_model::Document::from_string(format!("{}\nxx", "x".repeat(40)));
+        let mut search = SearchState::new();
+        search.set_pattern("x".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&doc).unwrap();
+
+        let vm = crate::view::DocumentViewModel::new(&doc);
+        let params = RenderParams {
+            mode: &Mode::Normal,
+            command_buffer: "",
+            status_message: "",
+            buffer_info: None,
+            visual_selection: None,
+            search_state: Some(&search),
+            bracket_highlights: None,
+            window_panes: &[],
+            popup: None,
+            pending_keys: "",
+        };
+        let layout = view.update_layout(&vm, true);
+        let highlight = format!("{}", SetBackgroundColor(Color::Yellow));
+
+        let (rows, clipped) = view.compose_rows(&vm, &params, &layout, &mut RowCache::default());
+        assert!(clipped);
+        assert_eq!(rows[0].matches(&highlight).count(), 10);
+        assert_eq!(rows[1].matches(&highlight).count(), 2);
+
+        // 0 lifts the limit
+        view.set_max_highlight_column(0);
+        let (rows, clipped) = view.compose_rows(&vm, &params, &layout, &mut RowCache::default());
+        assert!(!clipped);
+        assert_eq!(rows[0].matches(&highlight).count(), 40);
+    }
+
+    #[test]
+    fn test_theme_colours_the_frame() {
+        use crate::document_model::SearchDirection;
+
+        let mut view = View::new();
+        view.set_terminal_size(Some((40, 6)));
+        view.set_line_numbers(true);
+        view.set_theme(Theme::builtin("dark").unwrap());
+        let doc = crate::document_model::Document::from_string("find me\n".to_string());
+        let mut search = SearchState::new();
+        search.set_pattern("me".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&doc).unwrap();
+
+        let vm = crate::view::DocumentViewModel::new(&doc);
+        let params = RenderParams {
+            mode: &Mode::Normal,
+            command_buffer: "",
+            status_message: "",
+            buffer_info: None,
+            visual_selection: None,
+            search_state: Some(&search),
+            bracket_highlights: None,
+            window_panes: &[],
+            popup: None,
+            pending_keys: "",
+        };
+        let frame = view.compose_frame(&vm, &params);
+        let row = &frame.rows[0];
+        assert!(row.starts_with(&format!("{}", SetForegroundColor(Color::DarkGrey))));
+        assert_eq!(row.matches(&format!("{}", SetBackgroundColor(Color::DarkYellow))).count(), 2);
+        assert!(!row.contains(&format!("{}", SetBackgroundColor(Color::Yellow))));
+        assert!(frame.status.starts_with(&format!("{}", SetBackgroundColor(Color::DarkBlue))));
+        assert!(frame.status.contains("-- NORMAL --"));
+    }
+
+    #[test]
+    fn test_cursor_line_and_color_columns_under_highlights() {
+        use crate::document_model::SearchDirection;
+
+        let mut view = View::new();
+        view.set_terminal_size(Some((40, 6)));
+        view.set_cursor_line(true);
+        view.set_color_columns(parse_color_columns("2,10").unwrap());
+        let doc = crate::document_model::Document::from_string("find me\n\tx".to_string());
+        let mut search = SearchState::new();
+        search.set_pattern("me".to_string(), SearchDirection::Forward).unwrap();
+        search.search_document(&doc).unwrap();
+
+        let vm = crate::view::DocumentViewModel::new(&doc);
+        let params = RenderParams {
+            mode: &Mode::Normal,
+            command_buffer: "",
+            status_message: "",
+            buffer_info: None,
+            visual_selection: None,
+            search_state: Some(&search),
+            bracket_highlights: None,
+            window_panes: &[],
+            popup: None,
+            pending_keys: "",
+        };
+        let frame = view.compose_frame(&vm, &params);
+        let background = |row: &str, color| row.matches(&format!("{}", SetBackgroundColor(color))).count();
+
+        // The cursor's line: each character, then the rest of the row in
+        // two runs either side of column 10; the match keeps its colour
+        let row = &frame.rows[0];
+        assert_eq!(background(row, Color::DarkGrey), 6);
+        assert_eq!(background(row, Color::DarkRed), 2);
+        assert_eq!(background(row, Color::Yellow), 2);
+
+        // Another line: column 2 inside the tab and column 10 past the text
+        let row = &frame.rows[1];
+        assert_eq!(background(row, Color::DarkGrey), 0);
+        assert_eq!(background(row, Color::DarkRed), 2);
+        assert_eq!(frame.rows[2], "");
+
+        assert_eq!(parse_color_columns(""), Ok(vec![]));
+        assert!(parse_color_columns("80,x").is_err());
+        assert!(parse_color_columns("0").is_err());
+    }
+
+    #[test]
+    fn test_frames_reuse_rows_and_scroll() {
+        let mut view = View::new();
+        view.set_terminal_size(Some((20, 5))); // Three text rows
+        let mut doc = crate::document_model::Document::from_string("a\nb\nc\nd\ne".to_string());
+        let params = RenderParams {
+            mode: &Mode::Normal,
+            command_buffer: "",
+            status_message: "",
+            buffer_info: Some("header"),
+            visual_selection: None,
+            search_state: None,
+            bracket_highlights: None,
+            window_panes: &[],
+            popup: None,
+            pending_keys: "",
+        };
+        let frame = view.compose_frame(&crate::view::DocumentViewModel::new(&doc), &params);
+        assert_eq!(frame.rows, ["a", "b", "c"]);
+        assert_eq!(frame.scrolled, 0);
+        view.needs_full_redraw = false; // As render() does once the screen is cleared
+
+        // An edit on another line than the cursor's shows up
+        doc.replace_line_with_undo(2, "changed");
+        let frame = view.compose_frame(&crate::view::DocumentViewModel::new(&doc), &params);
+        assert_eq!(frame.rows, ["a", "b", "changed"]);
+
+        // Moving past the bottom scrolls the kept rows up
+        doc.move_cursor_to(4, 0);
+        let frame = view.compose_frame(&crate::view::DocumentViewModel::new(&doc), &params);
+        assert_eq!(frame.rows, ["changed", "d", "e"]);
+        assert_eq!(frame.scrolled, 2);
+
+        // Another buffer starts over
+        let other = crate::document_model::Document::from_string("x".to_string());
+        let frame = view.compose_frame(&crate::view::DocumentViewModel::new(&other), &params);
+        assert_eq!(frame.rows, ["x", "", ""]);
+        assert_eq!(frame.scrolled, 0);
+    }
+
+    #[test]
+    fn test_popup_scrolls_to_selected_item() {
+        let mut rows = vec!["text".to_string(); 4];
+        let popup = ListPopup {
+            title: "Buffers".to_string(),
+            items: (1..=5).map(|n| format!("item {n}")).collect(),
+            selected: 3,
+        };
+        View::overlay_popup(&mut rows, &popup, 8, &Theme::default());
+
+        // Title plus three items, ending at the selection
+        assert_eq!(rows[0], format!("{}Buffers {}", SetAttribute(Attribute::Reverse), SetAttribute(Attribute::Reset)));
+        assert_eq!(rows[1], "item 2  ");
+        assert_eq!(rows[2], "item 3  ");
+        assert!(rows[3].contains("item 4") && rows[3].contains(&format!("{}", SetBackgroundColor(Color::Cyan))));
     }
 }
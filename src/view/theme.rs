@@ -0,0 +1,429 @@
+//! Colour themes - the colours the renderer draws with, chosen with
+//! `:colorscheme name`
+//!
+//! A few themes are built in. Others are read from
+//! `~/.virus/themes/<name>.toml`, a small TOML file with one table per
+//! highlight group:
+//!
+//! ```toml
+//! # Start from a built-in theme (default if left out)
+//! base = "dark"
+//!
+//! [search]
+//! fg = "black"
+//! bg = "#ffd700"
+//!
+//! [status_line]
+//! bg = "dark_blue"
+//! reverse = false
+//! underline = false
+//! ```
+//!
+//! Colours are crossterm names (`red`, `dark_red`, `grey`, `dark_grey`, ...),
+//! `#rrggbb`, a 256-colour index, or `none` for the terminal's own colour.
+//! Groups and keys left out keep the base theme's values.
+
+use crossterm::style::{Attribute, Color, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use std::path::PathBuf;
+
+/// How one kind of text is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub reverse: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Drawn in the terminal's own colours
+    pub const PLAIN: Style = Style { foreground: None, background: None, reverse: false, underline: false };
+    /// Foreground and background swapped, as vim draws its status lines
+    pub const REVERSE: Style = Style { foreground: None, background: None, reverse: true, underline: false };
+
+    pub const fn colors(background: Color, foreground: Color) -> Self {
+        Style { foreground: Some(foreground), background: Some(background), reverse: false, underline: false }
+    }
+
+    const fn foreground(foreground: Color) -> Self {
+        Style { foreground: Some(foreground), background: None, reverse: false, underline: false }
+    }
+
+    const fn background(background: Color) -> Self {
+        Style { foreground: None, background: Some(background), reverse: false, underline: false }
+    }
+
+    /// This style with the text underlined as well
+    const fn underlined(self) -> Self {
+        Style { underline: true, ..self }
+    }
+
+    /// This style drawn on top of `under`: colours this one leaves to the
+    /// terminal come from `under`
+    pub fn over(self, under: Style) -> Style {
+        Style {
+            foreground: self.foreground.or(under.foreground),
+            background: self.background.or(under.background),
+            reverse: self.reverse || under.reverse,
+            underline: self.underline || under.underline,
+        }
+    }
+
+    /// `text` wrapped in the escapes that draw it in this style
+    pub fn paint(&self, text: &str) -> String {
+        if *self == Style::PLAIN {
+            return text.to_string();
+        }
+        let mut result = String::new();
+        if self.reverse {
+            result.push_str(&SetAttribute(Attribute::Reverse).to_string());
+        }
+        if self.underline {
+            result.push_str(&SetAttribute(Attribute::Underlined).to_string());
+        }
+        if let Some(background) = self.background {
+            result.push_str(&SetBackgroundColor(background).to_string());
+        }
+        if let Some(foreground) = self.foreground {
+            result.push_str(&SetForegroundColor(foreground).to_string());
+        }
+        result.push_str(text);
+        if self.background.is_some() || self.foreground.is_some() {
+            result.push_str(&ResetColor.to_string());
+        }
+        if self.reverse || self.underline {
+            result.push_str(&SetAttribute(Attribute::Reset).to_string());
+        }
+        result
+    }
+}
+
+/// The colours of everything the renderer highlights
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub status_line: Style,         // Mode and messages at the bottom of the screen
+    pub line_number: Style,         // The 'number' gutter
+    pub selection: Style,           // Marker of lines in the visual selection
+    pub search: Style,              // Matches of the last search
+    pub search_current: Style,      // The match incremental search will jump to
+    pub bracket_match: Style,       // Bracket under the cursor and its partner
+    pub bracket_unmatched: Style,   // Brackets without a partner
+    pub popup_title: Style,         // Title line of lists like the :ls picker
+    pub popup_selected: Style,      // Selected item of those lists
+    pub window_status: Style,       // Status line of the active split window
+    pub cursor_line: Style,         // The cursor's line, with 'cursorline'
+    pub trailing_whitespace: Style, // Spaces and tabs ending a line, with 'hltrailing'
+    pub color_column: Style,        // The columns 'colorcolumn' marks
+    pub diff_add: Style,            // Lines only one side of a diff has
+    pub diff_change: Style,         // Lines both sides of a diff have, changed
+    pub diff_delete: Style,         // Filler where the other side of a diff has lines
+    pub diagnostic_error: Style,    // Errors a language server reports, and their sign
+    pub diagnostic_warning: Style,  // Its warnings, notes and hints
+}
+
+/// Highlight groups, as named in theme files
+const GROUPS: &[&str] = &[
+    "status_line",
+    "line_number",
+    "selection",
+    "search",
+    "search_current",
+    "bracket_match",
+    "bracket_unmatched",
+    "popup_title",
+    "popup_selected",
+    "window_status",
+    "cursor_line",
+    "trailing_whitespace",
+    "color_column",
+    "diff_add",
+    "diff_change",
+    "diff_delete",
+    "diagnostic_error",
+    "diagnostic_warning",
+];
+
+/// Names of the themes that need no file
+pub const BUILTIN_THEMES: &[&str] = &["default", "dark", "light", "solarized", "mono"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            status_line: Style::PLAIN,
+            line_number: Style::PLAIN,
+            selection: Style::PLAIN,
+            search: Style::colors(Color::Yellow, Color::Black),
+            search_current: Style::colors(Color::Magenta, Color::Black),
+            bracket_match: Style::colors(Color::Cyan, Color::Black),
+            bracket_unmatched: Style::colors(Color::Red, Color::White),
+            popup_title: Style::REVERSE,
+            popup_selected: Style::colors(Color::Cyan, Color::Black),
+            window_status: Style::REVERSE,
+            cursor_line: Style::background(Color::DarkGrey),
+            trailing_whitespace: Style::colors(Color::Red, Color::White),
+            color_column: Style::background(Color::DarkRed),
+            diff_add: Style::background(Color::DarkGreen),
+            diff_change: Style::background(Color::DarkBlue),
+            diff_delete: Style::foreground(Color::DarkRed),
+            diagnostic_error: Style::foreground(Color::Red).underlined(),
+            diagnostic_warning: Style::foreground(Color::Yellow).underlined(),
+        }
+    }
+}
+
+impl Theme {
+    /// A built-in theme
+    pub fn builtin(name: &str) -> Option<Self> {
+        let rgb = |r, g, b| Color::Rgb { r, g, b };
+        let theme = match name {
+            "default" => Theme::default(),
+            "dark" => Theme {
+                status_line: Style::colors(Color::DarkBlue, Color::White),
+                line_number: Style::foreground(Color::DarkGrey),
+                selection: Style::foreground(Color::Yellow),
+                search: Style::colors(Color::DarkYellow, Color::Black),
+                bracket_match: Style::colors(Color::DarkCyan, Color::White),
+                bracket_unmatched: Style::colors(Color::DarkRed, Color::White),
+                popup_title: Style::colors(Color::DarkBlue, Color::White),
+                popup_selected: Style::colors(Color::DarkCyan, Color::Black),
+                window_status: Style::colors(Color::DarkBlue, Color::White),
+                cursor_line: Style::background(rgb(0x30, 0x30, 0x30)),
+                color_column: Style::background(rgb(0x3a, 0x1c, 0x1c)),
+                diff_add: Style::background(rgb(0x1c, 0x3a, 0x1c)),
+                diff_change: Style::background(rgb(0x1c, 0x24, 0x3a)),
+                ..Theme::default()
+            },
+            "light" => Theme {
+                status_line: Style::colors(Color::Grey, Color::Black),
+                line_number: Style::foreground(Color::DarkGrey),
+                selection: Style::foreground(Color::DarkBlue),
+                search_current: Style::colors(Color::DarkMagenta, Color::White),
+                popup_selected: Style::colors(Color::DarkCyan, Color::White),
+                window_status: Style::colors(Color::Grey, Color::Black),
+                cursor_line: Style::background(rgb(0xee, 0xee, 0xee)),
+                color_column: Style::background(rgb(0xff, 0xd7, 0xd7)),
+                diff_add: Style::background(rgb(0xd7, 0xff, 0xd7)),
+                diff_change: Style::background(rgb(0xd7, 0xe7, 0xff)),
+                diff_delete: Style::foreground(rgb(0xd7, 0x87, 0x87)),
+                diagnostic_warning: Style::foreground(Color::DarkYellow).underlined(),
+                ..Theme::default()
+            },
+            "solarized" => {
+                let (base02, base01, base1) = (rgb(0x07, 0x36, 0x42), rgb(0x58, 0x6e, 0x75), rgb(0x93, 0xa1, 0xa1));
+                Theme {
+                    status_line: Style::colors(base02, base1),
+                    line_number: Style::foreground(base01),
+                    selection: Style::foreground(rgb(0x26, 0x8b, 0xd2)),
+                    search: Style::colors(rgb(0xb5, 0x89, 0x00), base02),
+                    search_current: Style::colors(rgb(0xcb, 0x4b, 0x16), base02),
+                    bracket_match: Style::colors(rgb(0x2a, 0xa1, 0x98), base02),
+                    bracket_unmatched: Style::colors(rgb(0xdc, 0x32, 0x2f), base1),
+                    popup_title: Style::colors(base01, base02),
+                    popup_selected: Style::colors(rgb(0x2a, 0xa1, 0x98), base02),
+                    window_status: Style::colors(base02, base1),
+                    cursor_line: Style::background(base02),
+                    trailing_whitespace: Style::colors(rgb(0xdc, 0x32, 0x2f), base1),
+                    color_column: Style::background(base02),
+                    diff_add: Style::colors(base02, rgb(0x85, 0x99, 0x00)),
+                    diff_change: Style::colors(base02, rgb(0xb5, 0x89, 0x00)),
+                    diff_delete: Style::foreground(rgb(0xdc, 0x32, 0x2f)),
+                    diagnostic_error: Style::foreground(rgb(0xdc, 0x32, 0x2f)).underlined(),
+                    diagnostic_warning: Style::foreground(rgb(0xb5, 0x89, 0x00)).underlined(),
+                    ..Theme::default()
+                }
+            }
+            // No colours at all, for terminals that have none
+            "mono" => Theme {
+                search: Style::REVERSE,
+                search_current: Style::REVERSE,
+                bracket_match: Style::REVERSE,
+                bracket_unmatched: Style::REVERSE,
+                popup_selected: Style::REVERSE,
+                cursor_line: Style::PLAIN,
+                trailing_whitespace: Style::REVERSE,
+                color_column: Style::REVERSE,
+                diff_add: Style::REVERSE,
+                diff_change: Style::REVERSE,
+                diff_delete: Style::PLAIN,
+                diagnostic_error: Style::PLAIN.underlined(),
+                diagnostic_warning: Style::PLAIN.underlined(),
+                ..Theme::default()
+            },
+            _ => return None,
+        };
+        Some(Theme { name: name.to_string(), ..theme })
+    }
+
+    /// Where user themes live: `~/.virus/themes`
+    pub fn user_dir() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".virus").join("themes"))
+    }
+
+    /// The theme called `name`: a user theme file of that name, or else a
+    /// built-in theme
+    pub fn load(name: &str) -> Result<Self, String> {
+        let file = Self::user_dir().map(|dir| dir.join(format!("{}.toml", name))).filter(|path| path.is_file());
+        match file {
+            Some(path) => {
+                let content = std::fs::read_to_string(&path).map_err(|e| format!("Error reading \"{}\": {}", path.display(), e))?;
+                Self::parse(name, &content).map_err(|e| format!("\"{}\" {}", path.display(), e))
+            }
+            None => Self::builtin(name).ok_or_else(|| format!("Cannot find color scheme '{}'", name)),
+        }
+    }
+
+    /// Read a theme file. Err says which line is wrong and why.
+    pub fn parse(name: &str, content: &str) -> Result<Self, String> {
+        let mut theme = Theme::default();
+        let mut group: Option<&str> = None;
+        for (number, line) in content.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let header = header.trim();
+                group = Some(GROUPS.iter().find(|&&g| g == header).ok_or_else(|| error(format!("unknown group [{}]", header)))?);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected key = value, found \"{}\"", line)));
+            };
+            let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+
+            let Some(group) = group else {
+                if key != "base" {
+                    return Err(error(format!("unknown key \"{}\"", key)));
+                }
+                let base = Self::builtin(value).ok_or_else(|| error(format!("unknown base theme \"{}\"", value)))?;
+                theme = base;
+                continue;
+            };
+            let style = theme.style_mut(group);
+            match key {
+                "fg" | "foreground" => style.foreground = parse_color(value).map_err(error)?,
+                "bg" | "background" => style.background = parse_color(value).map_err(error)?,
+                "reverse" | "underline" => {
+                    let on = match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(error(format!("{} must be true or false, not \"{}\"", key, value))),
+                    };
+                    if key == "reverse" { style.reverse = on } else { style.underline = on }
+                }
+                _ => return Err(error(format!("unknown key \"{}\" in [{}]", key, group))),
+            }
+        }
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+
+    fn style_mut(&mut self, group: &str) -> &mut Style {
+        match group {
+            "status_line" => &mut self.status_line,
+            "line_number" => &mut self.line_number,
+            "selection" => &mut self.selection,
+            "search" => &mut self.search,
+            "search_current" => &mut self.search_current,
+            "bracket_match" => &mut self.bracket_match,
+            "bracket_unmatched" => &mut self.bracket_unmatched,
+            "popup_title" => &mut self.popup_title,
+            "popup_selected" => &mut self.popup_selected,
+            "cursor_line" => &mut self.cursor_line,
+            "trailing_whitespace" => &mut self.trailing_whitespace,
+            "color_column" => &mut self.color_column,
+            "diff_add" => &mut self.diff_add,
+            "diff_change" => &mut self.diff_change,
+            "diff_delete" => &mut self.diff_delete,
+            "diagnostic_error" => &mut self.diagnostic_error,
+            "diagnostic_warning" => &mut self.diagnostic_warning,
+            _ => &mut self.window_status,
+        }
+    }
+}
+
+/// `line` up to a `#` that isn't inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// A colour name, `#rrggbb`, a 0-255 colour index, or `none`
+fn parse_color(value: &str) -> Result<Option<Color>, String> {
+    let lower = value.to_ascii_lowercase();
+    if lower == "none" {
+        return Ok(None);
+    }
+    if let Some(hex) = lower.strip_prefix('#')
+        && hex.len() == 6
+        && let Ok(rgb) = u32::from_str_radix(hex, 16)
+    {
+        return Ok(Some(Color::Rgb { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8 }));
+    }
+    if let Ok(index) = lower.parse::<u8>() {
+        return Ok(Some(Color::AnsiValue(index)));
+    }
+    Color::try_from(lower.as_str()).map(Some).map_err(|_| format!("unknown colour \"{}\"", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(Style::PLAIN.paint("x"), "x");
+        assert_eq!(
+            Style::colors(Color::Yellow, Color::Black).paint("x"),
+            format!("{}{}x{}", SetBackgroundColor(Color::Yellow), SetForegroundColor(Color::Black), ResetColor)
+        );
+        assert_eq!(
+            Style::REVERSE.paint("x"),
+            format!("{}x{}", SetAttribute(Attribute::Reverse), SetAttribute(Attribute::Reset))
+        );
+
+        // A search match on the cursor line keeps its own background
+        let search = Style::colors(Color::Yellow, Color::Black);
+        assert_eq!(search.over(Style::background(Color::DarkGrey)), search);
+        assert_eq!(Style::PLAIN.over(Style::background(Color::DarkGrey)), Style::background(Color::DarkGrey));
+        assert_eq!(Style::foreground(Color::Red).over(Style::background(Color::Blue)), Style::colors(Color::Blue, Color::Red));
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        for name in BUILTIN_THEMES {
+            assert_eq!(Theme::builtin(name).unwrap().name, *name);
+        }
+        assert_eq!(Theme::builtin("default"), Some(Theme::default()));
+        assert_eq!(Theme::builtin("nope"), None);
+    }
+
+    #[test]
+    fn test_parse_theme_file() {
+        let theme = Theme::parse(
+            "mine",
+            "# comment\nbase = \"dark\"\n\n[search]\nfg = \"black\"  # inline\nbg = \"#ffd700\"\n\n[status_line]\nbg = none\nreverse = true\n[line_number]\nfg = 244\n",
+        )
+        .unwrap();
+        let dark = Theme::builtin("dark").unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.search, Style::colors(Color::Rgb { r: 0xff, g: 0xd7, b: 0 }, Color::Black));
+        assert_eq!(theme.status_line, Style { background: None, reverse: true, ..dark.status_line });
+        assert_eq!(theme.line_number.foreground, Some(Color::AnsiValue(244)));
+        assert_eq!(theme.bracket_match, dark.bracket_match);
+
+        assert_eq!(Theme::parse("x", "[search]\nfg = \"purple\"\n"), Err("line 2: unknown colour \"purple\"".to_string()));
+        assert_eq!(Theme::parse("x", "[cursor]\n"), Err("line 1: unknown group [cursor]".to_string()));
+        assert_eq!(Theme::parse("x", "fg = \"red\"\n"), Err("line 1: unknown key \"fg\"".to_string()));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::document_model::{Diagnostic, GitSigns, LineChange};
+
 /// View Model - Abstracts document data for the view layer
 /// This ensures the view has no direct dependencies on Document internals
 
@@ -25,25 +27,43 @@ pub trait ViewModel {
     
     /// Get a specific line by number
     fn get_line(&self, line_number: usize) -> Option<String>;
+
+    /// Identifies the current text: any edit changes it
+    fn revision(&self) -> u64;
+
+    /// The lines each edit since `revision` touched; None when unknown
+    fn changes_since(&self, revision: u64) -> Option<Vec<LineChange>>;
+
+    /// Signs for the sign column, by line; the column is shown while there are any
+    fn signs(&self) -> &GitSigns;
+
+    /// Diagnostics from a language server, signed and underlined
+    fn diagnostics(&self) -> &[Diagnostic];
 }
 
 /// Concrete implementation that adapts Document to ViewModel
 pub struct DocumentViewModel<'a> {
     document: &'a crate::document_model::Document,
+    cursor: Option<(usize, usize)>, // Overrides the document cursor (inactive split windows)
 }
 
 impl<'a> DocumentViewModel<'a> {
     pub fn new(document: &'a crate::document_model::Document) -> Self {
-        Self { document }
+        Self { document, cursor: None }
+    }
+
+    /// View a document with a cursor other than its own
+    pub fn with_cursor(document: &'a crate::document_model::Document, cursor: (usize, usize)) -> Self {
+        Self { document, cursor: Some(cursor) }
     }
 }
 
 impl<'a> ViewModel for DocumentViewModel<'a> {
     fn get_cursor_position(&self) -> CursorPosition {
-        CursorPosition {
-            line: self.document.cursor_line(),
-            column: self.document.cursor_column(),
-        }
+        let (line, column) = self
+            .cursor
+            .unwrap_or((self.document.cursor_line(), self.document.cursor_column()));
+        CursorPosition { line, column }
     }
     
     fn get_line_count(&self) -> usize {
@@ -53,4 +73,20 @@ impl<'a> ViewModel for DocumentViewModel<'a> {
     fn get_line(&self, line_number: usize) -> Option<String> {
         self.document.get_line(line_number)
     }
+
+    fn revision(&self) -> u64 {
+        self.document.revision()
+    }
+
+    fn changes_since(&self, revision: u64) -> Option<Vec<LineChange>> {
+        self.document.changes_since(revision)
+    }
+
+    fn signs(&self) -> &GitSigns {
+        &self.document.git_signs
+    }
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.document.diagnostics
+    }
 }
\ No newline at end of file
@@ -0,0 +1,479 @@
+//! Window layout - split windows sharing the screen
+//!
+//! Windows are arranged in a tree of horizontal and vertical splits. Each
+//! window remembers which buffer it shows plus its own cursor and scroll
+//! position; the active window's live state is kept in the document and
+//! view, and is saved back here when focus moves.
+
+pub type WindowId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitDirection {
+    Horizontal, // Windows stacked top to bottom (:split)
+    Vertical,   // Windows side by side (:vsplit)
+}
+
+/// Screen area occupied by a window, including its status line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub id: WindowId,
+    pub buffer_index: usize,
+    pub cursor: (usize, usize),
+    pub scroll_offset: usize,
+    pub horizontal_scroll: usize,
+    pub scroll_bind: bool, // Scrolls with the other bound windows, as :Gblame's pair do
+    pub alternate: Option<std::path::PathBuf>, // File edited here before the current one (Ctrl-^)
+}
+
+/// One step of rebuilding a layout from a single window, as a session does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutStep {
+    Split(SplitDirection), // Split the active window
+    Next,                  // Move to the next window in screen order
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum LayoutNode {
+    Leaf(WindowId),
+    Split {
+        direction: SplitDirection,
+        children: Vec<LayoutNode>,
+    },
+}
+
+impl LayoutNode {
+    fn leaves(&self, out: &mut Vec<WindowId>) {
+        match self {
+            LayoutNode::Leaf(id) => out.push(*id),
+            LayoutNode::Split { children, .. } => {
+                for child in children {
+                    child.leaves(out);
+                }
+            }
+        }
+    }
+
+    /// Put `new_id` before `target` in the tree. Returns true once inserted.
+    fn insert_before(&mut self, target: WindowId, new_id: WindowId, direction: SplitDirection) -> bool {
+        match self {
+            LayoutNode::Leaf(id) if *id == target => {
+                *self = LayoutNode::Split {
+                    direction,
+                    children: vec![LayoutNode::Leaf(new_id), LayoutNode::Leaf(target)],
+                };
+                true
+            }
+            LayoutNode::Leaf(_) => false,
+            LayoutNode::Split { direction: split_direction, children } => {
+                // Same direction as the parent: add a sibling instead of nesting
+                if *split_direction == direction
+                    && let Some(pos) = children.iter().position(|c| *c == LayoutNode::Leaf(target))
+                {
+                    children.insert(pos, LayoutNode::Leaf(new_id));
+                    return true;
+                }
+                children
+                    .iter_mut()
+                    .any(|child| child.insert_before(target, new_id, direction))
+            }
+        }
+    }
+
+    /// Remove the leaf `target`, collapsing splits left with one child
+    fn remove(&mut self, target: WindowId) -> bool {
+        let LayoutNode::Split { children, .. } = self else {
+            return false;
+        };
+        let removed = if let Some(pos) = children.iter().position(|c| *c == LayoutNode::Leaf(target)) {
+            children.remove(pos);
+            true
+        } else {
+            children.iter_mut().any(|child| child.remove(target))
+        };
+        if children.len() == 1 {
+            *self = children.remove(0);
+        }
+        removed
+    }
+
+    /// Steps that turn the active window into this node, leaving its last
+    /// window active. Splitting n-1 times makes n windows with the first one
+    /// active; each child is then built in turn, stepping on to the next.
+    fn steps(&self, out: &mut Vec<LayoutStep>) {
+        let LayoutNode::Split { direction, children } = self else {
+            return;
+        };
+        out.extend(std::iter::repeat_n(LayoutStep::Split(*direction), children.len() - 1));
+        for (i, child) in children.iter().enumerate() {
+            if i > 0 {
+                out.push(LayoutStep::Next);
+            }
+            child.steps(out);
+        }
+    }
+
+    fn layout(&self, area: Rect, out: &mut Vec<(WindowId, Rect)>) {
+        match self {
+            LayoutNode::Leaf(id) => out.push((*id, area)),
+            LayoutNode::Split { direction, children } => {
+                let count = children.len();
+                match direction {
+                    SplitDirection::Horizontal => {
+                        let sizes = Self::share(area.height, count);
+                        let mut y = area.y;
+                        for (child, height) in children.iter().zip(sizes) {
+                            child.layout(Rect { y, height, ..area }, out);
+                            y += height;
+                        }
+                    }
+                    SplitDirection::Vertical => {
+                        // One column between neighbours for the separator
+                        let separators = count - 1;
+                        let sizes = Self::share(area.width.saturating_sub(separators), count);
+                        let mut x = area.x;
+                        for (child, width) in children.iter().zip(sizes) {
+                            child.layout(Rect { x, width, ..area }, out);
+                            x += width + 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Split `total` into `parts` near-equal sizes, earlier parts getting the remainder
+    fn share(total: usize, parts: usize) -> Vec<usize> {
+        (0..parts)
+            .map(|i| total / parts + usize::from(i < total % parts))
+            .collect()
+    }
+}
+
+/// Tracks all windows and how they split the screen
+#[derive(Debug, Clone)]
+pub struct WindowManager {
+    windows: Vec<Window>,
+    root: LayoutNode,
+    active: WindowId,
+    next_id: WindowId,
+}
+
+impl Default for WindowManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindowManager {
+    pub fn new() -> Self {
+        Self {
+            windows: vec![Window {
+                id: 0,
+                buffer_index: 0,
+                cursor: (0, 0),
+                scroll_offset: 0,
+                horizontal_scroll: 0,
+                scroll_bind: false,
+                alternate: None,
+            }],
+            root: LayoutNode::Leaf(0),
+            active: 0,
+            next_id: 1,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.windows.len() > 1
+    }
+
+    pub fn active_id(&self) -> WindowId {
+        self.active
+    }
+
+    pub fn active(&self) -> &Window {
+        self.get(self.active).expect("active window always exists")
+    }
+
+    pub fn active_mut(&mut self) -> &mut Window {
+        let active = self.active;
+        self.windows
+            .iter_mut()
+            .find(|w| w.id == active)
+            .expect("active window always exists")
+    }
+
+    pub fn get(&self, id: WindowId) -> Option<&Window> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    /// Windows in screen order (top-left to bottom-right)
+    pub fn windows(&self) -> Vec<&Window> {
+        self.order().into_iter().filter_map(|id| self.get(id)).collect()
+    }
+
+    fn order(&self) -> Vec<WindowId> {
+        let mut ids = Vec::new();
+        self.root.leaves(&mut ids);
+        ids
+    }
+
+    /// Split the active window. The new window copies the active one's
+    /// state, goes above/left of it and becomes active.
+    pub fn split(&mut self, direction: SplitDirection) -> WindowId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut window = self.active().clone();
+        window.id = id;
+        self.windows.push(window);
+        self.root.insert_before(self.active, id, direction);
+        self.active = id;
+        id
+    }
+
+    /// Close a window. The last window can't be closed.
+    pub fn close(&mut self, id: WindowId) -> bool {
+        if !self.is_split() || self.get(id).is_none() {
+            return false;
+        }
+
+        // Focus moves to the window that followed the closed one (or preceded it)
+        let order = self.order();
+        let pos = order.iter().position(|w| *w == id).unwrap_or(0);
+        let successor = order.get(pos + 1).or_else(|| order.get(pos.wrapping_sub(1))).copied();
+
+        self.root.remove(id);
+        self.windows.retain(|w| w.id != id);
+        if self.active == id {
+            self.active = successor.unwrap_or(self.windows[0].id);
+        }
+        true
+    }
+
+    /// Close every window except the active one
+    pub fn only(&mut self) -> usize {
+        let closed = self.windows.len() - 1;
+        self.windows.retain(|w| w.id == self.active);
+        self.root = LayoutNode::Leaf(self.active);
+        closed
+    }
+
+    pub fn set_active(&mut self, id: WindowId) -> bool {
+        if self.get(id).is_some() {
+            self.active = id;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The window after (or before) the active one in screen order, wrapping around
+    pub fn cycle(&self, forward: bool) -> WindowId {
+        let order = self.order();
+        let pos = order.iter().position(|w| *w == self.active).unwrap_or(0);
+        let next = if forward {
+            (pos + 1) % order.len()
+        } else {
+            (pos + order.len() - 1) % order.len()
+        };
+        order[next]
+    }
+
+    /// Rectangles of every window within `area`, in screen order
+    pub fn layout(&self, area: Rect) -> Vec<(WindowId, Rect)> {
+        let mut rects = Vec::new();
+        self.root.layout(area, &mut rects);
+        rects
+    }
+
+    /// Steps that rebuild this layout from one window, ending on the last
+    /// window in screen order
+    pub fn layout_steps(&self) -> Vec<LayoutStep> {
+        let mut steps = Vec::new();
+        self.root.steps(&mut steps);
+        steps
+    }
+
+    /// Position of the active window in screen order
+    pub fn active_position(&self) -> usize {
+        self.order().iter().position(|id| *id == self.active).unwrap_or(0)
+    }
+
+    /// The window next to the active one in direction h/j/k/l, if any
+    pub fn neighbor(&self, area: Rect, direction: char) -> Option<WindowId> {
+        let rects = self.layout(area);
+        let (_, current) = *rects.iter().find(|(id, _)| *id == self.active)?;
+
+        let overlaps = |a_start: usize, a_len: usize, b_start: usize, b_len: usize| {
+            a_start < b_start + b_len && b_start < a_start + a_len
+        };
+
+        rects
+            .iter()
+            .filter(|(id, _)| *id != self.active)
+            .filter_map(|(id, rect)| {
+                // Distance to the candidate, if it lies in the requested direction
+                let distance = match direction {
+                    'h' if rect.x + rect.width <= current.x
+                        && overlaps(rect.y, rect.height, current.y, current.height) =>
+                    {
+                        current.x - (rect.x + rect.width)
+                    }
+                    'l' if rect.x >= current.x + current.width
+                        && overlaps(rect.y, rect.height, current.y, current.height) =>
+                    {
+                        rect.x - (current.x + current.width)
+                    }
+                    'k' if rect.y + rect.height <= current.y
+                        && overlaps(rect.x, rect.width, current.x, current.width) =>
+                    {
+                        current.y - (rect.y + rect.height)
+                    }
+                    'j' if rect.y >= current.y + current.height
+                        && overlaps(rect.x, rect.width, current.x, current.width) =>
+                    {
+                        rect.y - (current.y + current.height)
+                    }
+                    _ => return None,
+                };
+                Some((distance, rect.y, rect.x, *id))
+            })
+            .min()
+            .map(|(_, _, _, id)| id)
+    }
+
+    /// Keep buffer indices valid after buffer `removed` was deleted
+    pub fn buffer_removed(&mut self, removed: usize, buffer_count: usize) {
+        for window in &mut self.windows {
+            if window.buffer_index > removed {
+                window.buffer_index -= 1;
+            }
+            window.buffer_index = window.buffer_index.min(buffer_count.saturating_sub(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Rect = Rect { x: 0, y: 1, width: 81, height: 22 };
+
+    #[test]
+    fn test_split_shares_screen() {
+        let mut windows = WindowManager::new();
+        let top = windows.split(SplitDirection::Horizontal);
+        assert_eq!(windows.active_id(), top);
+
+        let rects = windows.layout(SCREEN);
+        assert_eq!(rects[0], (top, Rect { x: 0, y: 1, width: 81, height: 11 }));
+        assert_eq!(rects[1], (0, Rect { x: 0, y: 12, width: 81, height: 11 }));
+
+        // Vertical split of the top window leaves a separator column
+        let left = windows.split(SplitDirection::Vertical);
+        let rects = windows.layout(SCREEN);
+        assert_eq!(rects[0], (left, Rect { x: 0, y: 1, width: 40, height: 11 }));
+        assert_eq!(rects[1], (top, Rect { x: 41, y: 1, width: 40, height: 11 }));
+        assert_eq!(rects[2].0, 0);
+    }
+
+    #[test]
+    fn test_same_direction_splits_are_siblings() {
+        let mut windows = WindowManager::new();
+        windows.split(SplitDirection::Vertical);
+        windows.split(SplitDirection::Vertical);
+        let widths: Vec<usize> = windows.layout(SCREEN).iter().map(|(_, r)| r.width).collect();
+        assert_eq!(widths, vec![27, 26, 26]);
+    }
+
+    #[test]
+    fn test_close_collapses_and_moves_focus() {
+        let mut windows = WindowManager::new();
+        let top = windows.split(SplitDirection::Horizontal);
+        windows.split(SplitDirection::Vertical);
+
+        assert!(windows.close(windows.active_id()));
+        assert_eq!(windows.active_id(), top);
+        assert_eq!(windows.count(), 2);
+
+        assert!(windows.close(top));
+        assert_eq!(windows.active_id(), 0);
+        assert!(!windows.close(0)); // Last window stays
+        assert_eq!(windows.layout(SCREEN), vec![(0, SCREEN)]);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut windows = WindowManager::new();
+        let top = windows.split(SplitDirection::Horizontal);
+        let left = windows.split(SplitDirection::Vertical);
+
+        assert_eq!(windows.neighbor(SCREEN, 'l'), Some(top));
+        assert_eq!(windows.neighbor(SCREEN, 'j'), Some(0));
+        assert_eq!(windows.neighbor(SCREEN, 'h'), None);
+        assert_eq!(windows.cycle(true), top);
+        assert_eq!(windows.cycle(false), 0);
+
+        windows.set_active(0);
+        assert_eq!(windows.neighbor(SCREEN, 'k'), Some(left));
+    }
+
+    #[test]
+    fn test_layout_steps_rebuild_the_layout() {
+        let mut windows = WindowManager::new();
+        windows.split(SplitDirection::Vertical);
+        windows.split(SplitDirection::Horizontal);
+        let steps = windows.layout_steps();
+        assert_eq!(steps, vec![
+            LayoutStep::Split(SplitDirection::Vertical),
+            LayoutStep::Split(SplitDirection::Horizontal),
+            LayoutStep::Next,
+            LayoutStep::Next,
+        ]);
+
+        let mut rebuilt = WindowManager::new();
+        for step in steps {
+            match step {
+                LayoutStep::Split(direction) => {
+                    rebuilt.split(direction);
+                }
+                LayoutStep::Next => {
+                    rebuilt.set_active(rebuilt.cycle(true));
+                }
+            }
+        }
+        let rects = |windows: &WindowManager| -> Vec<Rect> {
+            windows.layout(SCREEN).into_iter().map(|(_, rect)| rect).collect()
+        };
+        assert_eq!(rects(&rebuilt), rects(&windows));
+        assert_eq!(rebuilt.active_position(), 2);
+    }
+
+    #[test]
+    fn test_buffer_removed_shifts_indices() {
+        let mut windows = WindowManager::new();
+        windows.active_mut().buffer_index = 2;
+        windows.split(SplitDirection::Horizontal);
+        windows.active_mut().buffer_index = 0;
+
+        windows.buffer_removed(1, 2);
+        let indices: Vec<usize> = windows.windows().iter().map(|w| w.buffer_index).collect();
+        assert_eq!(indices, vec![0, 1]);
+    }
+}
@@ -0,0 +1,63 @@
+//! The editor driven through the library, as an embedding program would
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use vi_rus::{Document, EditorController, Mode};
+
+/// A render target the test can read back
+#[derive(Clone, Default)]
+struct Screen(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Screen {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_keys_edit_and_render_without_a_terminal() {
+    let screen = Screen::default();
+    let mut editor = EditorController::new();
+    let shared = editor.shared_state_mut();
+    shared.session_controller.buffers[0] = Document::from_string("world".to_string());
+    shared.view.set_terminal_size(Some((40, 6)));
+    shared.view.set_output(Box::new(screen.clone()));
+
+    assert!(!editor.feed_keys("ihello <Esc>").unwrap());
+    assert_eq!(editor.mode(), Mode::Normal);
+    let doc = editor.shared_state().session_controller.current_document();
+    assert_eq!(doc.get_line(0).as_deref(), Some("hello world"));
+
+    editor.render().unwrap();
+    assert!(String::from_utf8_lossy(&screen.0.borrow()).contains("hello world"));
+    assert_eq!(editor.frame().rows[0], "hello world");
+
+    assert_eq!(editor.run_commands(&["s/world/there/".to_string()]), Ok(false));
+    assert_eq!(editor.frame().rows[0], "hello there");
+    assert!(editor.feed_keys(":q!<CR>").unwrap());
+}
+
+#[test]
+fn test_startup_commands_can_ask_the_user() {
+    let mut editor = EditorController::new();
+    let shared = editor.shared_state_mut();
+    shared.session_controller.buffers[0] = Document::from_string("one\ntwo".to_string());
+    shared.view.set_terminal_size(Some((40, 6)));
+
+    // A command that fails doesn't stop the ones after it
+    let commands = ["frob", "%s/o/0/gc", "s/t/T/"].map(str::to_string);
+    assert!(!editor.run_startup_commands(&commands).unwrap());
+    assert_eq!(editor.mode(), Mode::Command);
+    assert!(!editor.feed_keys("yn").unwrap());
+    assert_eq!(editor.mode(), Mode::Normal);
+    let doc = editor.shared_state().session_controller.current_document();
+    assert_eq!((doc.get_line(0).as_deref(), doc.get_line(1).as_deref()), (Some("0ne"), Some("two")));
+
+    assert!(editor.run_startup_commands(&["q!".to_string()]).unwrap());
+}